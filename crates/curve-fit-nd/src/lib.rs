@@ -18,9 +18,16 @@ pub fn curve_fit_cubic_to_points_db(
 */
 
 use bitflags::bitflags;
-use std::{ptr, slice};
+use std::{
+    ptr, slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 bitflags! {
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
     pub struct CalcFlags: u32 {
         const HIGH_QUALITY = curve_fit_nd_sys::CURVE_FIT_CALC_HIGH_QUALIY as u32;
         const CYCLIC = curve_fit_nd_sys::CURVE_FIT_CALC_CYCLIC as u32;
@@ -108,3 +115,288 @@ pub fn curve_fit_cubic_to_points_f64(
         })
     }
 }
+
+/// Cancellation flag for `curve_fit_cubic_to_points_chunked`.
+///
+/// Clones share the same flag, so the token can be cancelled from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Requests cancellation. Fitting stops after the chunk in progress.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Error returned by `curve_fit_cubic_to_points_chunked`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ChunkedFitError {
+    /// Fitting was cancelled with the cancellation token.
+    Cancelled,
+    /// Fitting of a chunk failed with the specified error code.
+    Fit(u32),
+}
+
+/// Corners closer than this fraction of the chunk size to the nominal end of a chunk are used as the seam.
+const SEAM_SNAP_FRACTION: usize = 4;
+
+/// Chooses the end point index of each chunk.
+///
+/// Seams are snapped to nearby corners when possible, since there the tangents don't need to be continuous.
+fn chunk_ends(point_count: usize, chunk_size: usize, corners: &[u32]) -> Vec<usize> {
+    let mut ends = vec![];
+    let mut start = 0;
+    let snap = chunk_size / SEAM_SNAP_FRACTION;
+    while start + chunk_size < point_count - 1 {
+        let nominal = start + chunk_size;
+        let end = corners
+            .iter()
+            .map(|&c| c as usize)
+            .filter(|&c| c > start + 1 && c + snap >= nominal && c <= nominal + snap && c < point_count - 1)
+            .min_by_key(|&c| c.abs_diff(nominal))
+            .unwrap_or(nominal);
+        ends.push(end);
+        start = end;
+    }
+    ends.push(point_count - 1);
+    ends
+}
+
+/// Makes the handles of a knot collinear, keeping their lengths.
+///
+/// `knot` is the knot data: in-handle, knot, out-handle, with `dims` coordinates each.
+fn align_handles(knot: &mut [f64], dims: usize) {
+    let (h_in, rest) = knot.split_at_mut(dims);
+    let (k, h_out) = rest.split_at_mut(dims);
+    let len_in = (0..dims).map(|i| (k[i] - h_in[i]).powi(2)).sum::<f64>().sqrt();
+    let len_out = (0..dims).map(|i| (h_out[i] - k[i]).powi(2)).sum::<f64>().sqrt();
+    if len_in == 0.0 || len_out == 0.0 {
+        return;
+    }
+    let mut dir: Vec<f64> = (0..dims)
+        .map(|i| (k[i] - h_in[i]) / len_in + (h_out[i] - k[i]) / len_out)
+        .collect();
+    let len = dir.iter().map(|d| d * d).sum::<f64>().sqrt();
+    if len == 0.0 {
+        // cusp
+        return;
+    }
+    dir.iter_mut().for_each(|d| *d /= len);
+    for i in 0..dims {
+        h_in[i] = k[i] - dir[i] * len_in;
+        h_out[i] = k[i] + dir[i] * len_out;
+    }
+}
+
+/// Same as `curve_fit_cubic_to_points_f64`, but fits the points in chunks of `chunk_size` points.
+///
+/// Between chunks, the function checks the cancellation token and reports progress via `progress`,
+/// called with the number of points processed so far and the total number of points.
+///
+/// Consecutive chunks share their seam point, so the resulting curve is continuous. At seams that
+/// are not corners, the tangents are made continuous by aligning the handles of the seam knot.
+/// Seams are moved to nearby corners when possible. The result has the same layout as the result
+/// of `curve_fit_cubic_to_points_f64`, with corner and original indices relative to the whole input.
+///
+/// Cyclic curves are not split, and are fitted in one call.
+#[allow(clippy::too_many_arguments)]
+pub fn curve_fit_cubic_to_points_chunked(
+    points: &[f64],
+    dims: usize,
+    error_threshold: f64,
+    calc_flag: CalcFlags,
+    corners: Option<&[u32]>,
+    chunk_size: usize,
+    cancel: Option<&CancellationToken>,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<CurveFitCubicResult, ChunkedFitError> {
+    assert!(dims > 0);
+    assert!(points.len().is_multiple_of(dims));
+    assert!(chunk_size >= 2, "chunks must have at least two points");
+
+    let point_count = points.len() / dims;
+    let is_cancelled = || cancel.is_some_and(|c| c.is_cancelled());
+    if is_cancelled() {
+        return Err(ChunkedFitError::Cancelled);
+    }
+
+    let input_corners = corners.unwrap_or(&[]);
+    if point_count <= chunk_size || calc_flag.contains(CalcFlags::CYCLIC) {
+        let result = curve_fit_cubic_to_points_f64(points, dims, error_threshold, calc_flag, corners)
+            .map_err(ChunkedFitError::Fit)?;
+        progress(point_count, point_count);
+        return Ok(result);
+    }
+
+    let ends = chunk_ends(point_count, chunk_size, input_corners);
+    let mut cubic_array: Vec<f64> = vec![];
+    let mut corner_index_array = vec![];
+    let mut cubic_orig_index = Some(vec![]);
+    let knot_len = 3 * dims;
+
+    let mut start = 0;
+    for (chunk_index, &end) in ends.iter().enumerate() {
+        if is_cancelled() {
+            return Err(ChunkedFitError::Cancelled);
+        }
+        let first = chunk_index == 0;
+        let last = chunk_index == ends.len() - 1;
+
+        // The fitter only fits the spans between the corners it is given, so the chunk endpoints must be
+        // passed as corners along with the input corners inside the chunk.
+        let mut chunk_corners = vec![0];
+        chunk_corners.extend(
+            input_corners
+                .iter()
+                .map(|&c| c as usize)
+                .filter(|&c| c > start && c < end)
+                .map(|c| (c - start) as u32),
+        );
+        chunk_corners.push((end - start) as u32);
+        let chunk = curve_fit_cubic_to_points_f64(
+            &points[start * dims..(end + 1) * dims],
+            dims,
+            error_threshold,
+            calc_flag,
+            Some(&chunk_corners),
+        )
+        .map_err(ChunkedFitError::Fit)?;
+        let knot_count = chunk.cubic_array.len() / knot_len;
+
+        // index of the first knot of this chunk in the merged array
+        let knot_offset;
+        if first {
+            knot_offset = 0;
+            cubic_array.extend_from_slice(&chunk.cubic_array);
+        } else {
+            // The first knot of the chunk is the last knot of the previous one (the seam): take the
+            // out-handle from this chunk, then append the other knots.
+            knot_offset = cubic_array.len() / knot_len - 1;
+            let seam = knot_offset * knot_len;
+            cubic_array[seam + 2 * dims..seam + 3 * dims].copy_from_slice(&chunk.cubic_array[2 * dims..3 * dims]);
+            if !input_corners.contains(&(start as u32)) {
+                align_handles(&mut cubic_array[seam..seam + knot_len], dims);
+            }
+            cubic_array.extend_from_slice(&chunk.cubic_array[knot_len..]);
+        }
+
+        // the seams are reported as corners only if they are corners of the input
+        for c in chunk.corner_index_array.into_iter().flatten() {
+            let c = c as usize;
+            let at_seam = (c == 0 && !first) || (c == knot_count - 1 && !last);
+            let is_input_corner = c == 0 && input_corners.contains(&(start as u32));
+            if !at_seam || is_input_corner {
+                let c = (knot_offset + c) as u32;
+                if corner_index_array.last() != Some(&c) {
+                    corner_index_array.push(c);
+                }
+            }
+        }
+        cubic_orig_index = match (cubic_orig_index, chunk.cubic_orig_index) {
+            (Some(mut merged), Some(orig)) => {
+                let skip = if first { 0 } else { 1 };
+                merged.extend(orig.iter().skip(skip).map(|&i| i + start as u32));
+                Some(merged)
+            }
+            _ => None,
+        };
+
+        progress(end + 1, point_count);
+        start = end;
+    }
+
+    Ok(CurveFitCubicResult {
+        cubic_array,
+        corner_index_array: Some(corner_index_array),
+        cubic_orig_index,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{curve_fit_cubic_to_points_chunked, curve_fit_cubic_to_points_f64, CalcFlags, CurveFitCubicResult};
+
+    const DIMS: usize = 2;
+
+    fn point(points: &[f64], i: usize) -> &[f64] {
+        &points[i * DIMS..(i + 1) * DIMS]
+    }
+
+    /// Returns the (in-handle, knot, out-handle) of knot `i`.
+    fn knot(result: &CurveFitCubicResult, i: usize) -> (&[f64], &[f64], &[f64]) {
+        let k = &result.cubic_array[i * 3 * DIMS..(i + 1) * 3 * DIMS];
+        (&k[..DIMS], &k[DIMS..2 * DIMS], &k[2 * DIMS..])
+    }
+
+    /// Input indices of the corners of the fitted curve.
+    fn corner_points(result: &CurveFitCubicResult) -> Vec<u32> {
+        let orig = result.cubic_orig_index.as_ref().unwrap();
+        result.corner_index_array.as_ref().unwrap().iter().map(|&c| orig[c as usize]).collect()
+    }
+
+    #[test]
+    fn chunked_fit_matches_single_fit() {
+        let point_count = 161;
+        let points: Vec<f64> = (0..point_count)
+            .flat_map(|i| {
+                let t = i as f64 * 0.15;
+                [i as f64, 10.0 * t.sin()]
+            })
+            .collect();
+        // chunks of 50 points are split at 50, 100 and 150, none of which are corners:
+        // the second chunk contains a single interior corner and the third none
+        let corners = [0, 37, 70, 160];
+        let seams = [50, 100, 150];
+
+        let single = curve_fit_cubic_to_points_f64(&points, DIMS, 0.01, CalcFlags::empty(), Some(&corners)).unwrap();
+        let chunked = curve_fit_cubic_to_points_chunked(
+            &points,
+            DIMS,
+            0.01,
+            CalcFlags::empty(),
+            Some(&corners),
+            50,
+            None,
+            |_, _| {},
+        )
+        .unwrap();
+
+        let knot_count = chunked.cubic_array.len() / (3 * DIMS);
+        assert_eq!(knot(&chunked, 0).1, point(&points, 0));
+        assert_eq!(knot(&chunked, knot_count - 1).1, point(&points, point_count - 1));
+
+        // every knot lies on its input point, and the knots cover the input without gaps or overlaps
+        let orig = chunked.cubic_orig_index.as_ref().unwrap();
+        assert_eq!(orig.len(), knot_count);
+        assert_eq!(orig.first(), Some(&0));
+        assert_eq!(orig.last(), Some(&(point_count as u32 - 1)));
+        assert!(orig.windows(2).all(|w| w[0] < w[1]));
+        for (i, &o) in orig.iter().enumerate() {
+            assert_eq!(knot(&chunked, i).1, point(&points, o as usize));
+        }
+
+        // the seams are knots with continuous tangents
+        for seam in seams {
+            let i = orig.iter().position(|&o| o == seam).expect("seam is not a knot");
+            let (h_in, k, h_out) = knot(&chunked, i);
+            let d_in = [k[0] - h_in[0], k[1] - h_in[1]];
+            let d_out = [h_out[0] - k[0], h_out[1] - k[1]];
+            let cross = d_in[0] * d_out[1] - d_in[1] * d_out[0];
+            let dot = d_in[0] * d_out[0] + d_in[1] * d_out[1];
+            assert!(cross.abs() < 1e-9 * dot, "tangents are not continuous at seam {seam}");
+        }
+
+        // corners are preserved, and the seams are not reported as corners
+        assert_eq!(corner_points(&single), corners);
+        assert_eq!(corner_points(&chunked), corners);
+    }
+}
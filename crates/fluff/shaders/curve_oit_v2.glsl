@@ -385,7 +385,8 @@ void main() {
 #endif
 
     // stochastic transparency test
-    if (.5+.5*hash(ivec2(coord.xy) + ivec2(48*(frame+i_curveIndex))) > frag.color.a) {
+    // seeded by the curve ID so that the noise of a curve doesn't change when curves are reordered
+    if (.5+.5*hash(ivec2(coord.xy) + ivec2(48*(frame+int(curves[i_curveIndex].curveId)))) > frag.color.a) {
         discard;
     }
 
@@ -0,0 +1,151 @@
+#version 460 core
+#include "bindless.inc.glsl"
+#include "shared.inc.glsl"
+
+#extension GL_EXT_mesh_shader : require
+#extension GL_EXT_scalar_block_layout : require
+
+// Display of the meshes (e.g. scalp meshes) and point clouds of the scene.
+//
+// Each mesh shader workgroup draws SUBGROUP_SIZE triangles, or SUBGROUP_SIZE point sprites if POINTS is defined.
+
+layout(scalar, push_constant) uniform PushConstants {
+    DrawGeometryPushConstants u;
+};
+
+struct TaskData {
+    uint baseIndex;
+};
+
+vec4 project(vec3 pos)
+{
+    vec4 p = u.sceneParams.d.viewProj * vec4(pos, 1.0);
+    p.y = -p.y;
+    return p;
+}
+
+//////////////////////////////////////////////////////////
+
+#ifdef __TASK__
+
+layout(local_size_x=1) in;
+
+taskPayloadSharedEXT TaskData taskData;
+
+void main() {
+    taskData.baseIndex = gl_WorkGroupID.x * SUBGROUP_SIZE;
+    EmitMeshTasksEXT(1, 1, 1);
+}
+
+#endif
+
+//////////////////////////////////////////////////////////
+
+#ifdef __MESH__
+
+taskPayloadSharedEXT TaskData taskData;
+
+layout(local_size_x=SUBGROUP_SIZE) in;
+
+#ifdef POINTS
+// One quad per point
+layout(triangles, max_vertices=4*SUBGROUP_SIZE, max_primitives=2*SUBGROUP_SIZE) out;
+#else
+layout(triangles, max_vertices=3*SUBGROUP_SIZE, max_primitives=SUBGROUP_SIZE) out;
+#endif
+
+layout(location=0) out vec3 o_position[];
+layout(location=1) out vec3 o_normal[];
+layout(location=2) out vec3 o_color[];
+// Position in the point sprite, in [-1,1]
+layout(location=3) out vec2 o_uv[];
+
+void main() {
+    uint i = gl_LocalInvocationID.x;
+    uint index = taskData.baseIndex + i;
+    uint count = min(u.count - taskData.baseIndex, SUBGROUP_SIZE);
+
+#ifdef POINTS
+    if (i == 0) {
+        SetMeshOutputsEXT(4 * count, 2 * count);
+    }
+    if (i >= count) {
+        return;
+    }
+
+    ControlPoint point = u.points.d[u.baseIndex + index];
+    vec4 p = project(point.pos);
+    vec2 viewportSize = vec2(u.sceneParams.d.viewportSize);
+    // sprite radius in pixels
+    float radius = u.pointSize;
+    if (u.sizeAttenuation != 0) {
+        radius = u.pointSize * u.sceneParams.d.proj[1][1] * 0.5 * viewportSize.y / p.w;
+    }
+    radius = max(radius, 1.0);
+    vec2 extent = radius * 2.0 / viewportSize * p.w;
+
+    const vec2 corners[4] = vec2[4](vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(-1.0, 1.0), vec2(1.0, 1.0));
+    for (uint c = 0; c < 4; ++c) {
+        uint v = 4 * i + c;
+        gl_MeshVerticesEXT[v].gl_Position = vec4(p.xy + corners[c] * extent, p.zw);
+        o_position[v] = point.pos;
+        o_normal[v] = vec3(0.0);
+        o_color[v] = point.color;
+        o_uv[v] = corners[c];
+    }
+    gl_PrimitiveTriangleIndicesEXT[2 * i] = uvec3(4 * i, 4 * i + 1, 4 * i + 2);
+    gl_PrimitiveTriangleIndicesEXT[2 * i + 1] = uvec3(4 * i + 2, 4 * i + 1, 4 * i + 3);
+#else
+    if (i == 0) {
+        SetMeshOutputsEXT(3 * count, count);
+    }
+    if (i >= count) {
+        return;
+    }
+
+    for (uint c = 0; c < 3; ++c) {
+        uint v = 3 * i + c;
+        MeshVertex vertex = u.meshVertices.d[u.baseIndex + 3 * index + c];
+        gl_MeshVerticesEXT[v].gl_Position = project(vertex.pos);
+        o_position[v] = vertex.pos;
+        o_normal[v] = vertex.normal;
+        o_color[v] = vertex.color;
+        o_uv[v] = vec2(0.0);
+    }
+    gl_PrimitiveTriangleIndicesEXT[i] = uvec3(3 * i, 3 * i + 1, 3 * i + 2);
+#endif
+}
+
+#endif
+
+//////////////////////////////////////////////////////////
+
+#ifdef __FRAGMENT__
+
+layout(location=0) in vec3 i_position;
+layout(location=1) in vec3 i_normal;
+layout(location=2) in vec3 i_color;
+layout(location=3) in vec2 i_uv;
+layout(location=0) out vec4 o_color;
+
+void main() {
+#ifdef POINTS
+    // round sprites
+    if (dot(i_uv, i_uv) > 1.0) {
+        discard;
+    }
+    o_color = vec4(i_color, 1.0);
+#else
+    vec3 n = normalize(i_normal);
+    if (u.colorByNormals != 0) {
+        o_color = vec4(n * 0.5 + 0.5, 1.0);
+    } else {
+        // headlight, two-sided
+        vec3 viewDir = normalize(u.sceneParams.d.eye - i_position);
+        float shade = 0.2 + 0.8 * abs(dot(n, viewDir));
+        o_color = vec4(i_color * shade, 1.0);
+    }
+#endif
+}
+
+#endif
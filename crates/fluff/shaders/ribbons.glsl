@@ -0,0 +1,274 @@
+#version 460 core
+#include "bindless.inc.glsl"
+#include "shared.inc.glsl"
+#include "common.inc.glsl"
+#include "bezier.inc.glsl"
+
+#extension GL_EXT_mesh_shader : require
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_KHR_shader_subgroup_ballot : require
+#extension GL_KHR_shader_subgroup_arithmetic : require
+#extension GL_EXT_scalar_block_layout : require
+#extension GL_EXT_shader_explicit_arithmetic_types : require
+
+// GPU tessellation of bezier curve segments into camera-facing ribbons.
+//
+// The task shader processes SUBGROUP_SIZE curve segments at once: it culls segments outside the view,
+// or hidden behind the occluders of the depth pre-pass if there's one, and chooses the number of samples of each segment from its screen-space curvature (Wang's formula).
+// Each surviving segment is then expanded into a ribbon by one mesh shader workgroup, one sample per invocation.
+
+layout(scalar, push_constant) uniform PushConstants {
+    DrawRibbonsPushConstants u;
+};
+
+struct TaskData {
+    uint baseCurveID;
+    // (relative curve ID, sample count) for each segment to expand
+    u8vec2 curves[SUBGROUP_SIZE];
+};
+
+vec4 project(vec3 pos)
+{
+    vec4 p = u.sceneParams.d.viewProj * vec4(pos, 1.0);
+    p.y = -p.y;
+    return p;
+}
+
+// Clip space to window coordinates (pixels).
+vec2 clipToWindow(vec4 p) {
+    return (p.xy / p.w * 0.5 + 0.5) * vec2(u.sceneParams.d.viewportSize);
+}
+
+CubicBezier3D loadSegment(CurveDesc curve) {
+    return CubicBezier3D(
+        u.controlPoints.d[curve.start].pos,
+        u.controlPoints.d[curve.start + 1].pos,
+        u.controlPoints.d[curve.start + 2].pos,
+        u.controlPoints.d[curve.start + 3].pos);
+}
+
+//////////////////////////////////////////////////////////
+
+#ifdef __TASK__
+
+// Tests the screen-space bounds of a segment against the depth pyramid of the occluders.
+// The control points must be in front of the camera.
+bool isOccluded(vec4 c0, vec4 c1, vec4 c2, vec4 c3) {
+    vec2 w0 = clipToWindow(c0);
+    vec2 w1 = clipToWindow(c1);
+    vec2 w2 = clipToWindow(c2);
+    vec2 w3 = clipToWindow(c3);
+    // the ribbon extends on both sides of the curve
+    float margin = 0.5 * u.width + u.filterWidth;
+    vec2 maxCoord = vec2(u.sceneParams.d.viewportSize) - 1.0;
+    vec2 bmin = clamp(min(min(w0, w1), min(w2, w3)) - margin, vec2(0.0), maxCoord);
+    vec2 bmax = clamp(max(max(w0, w1), max(w2, w3)) + margin, vec2(0.0), maxCoord);
+    // The pyramid holds view-space depth, which is the W coordinate in clip space.
+    float nearest = min(min(c0.w, c1.w), min(c2.w, c3.w));
+
+    // level at which the bounds span at most 2x2 texels
+    vec2 extent = bmax - bmin;
+    uint level = min(uint(ceil(log2(max(max(extent.x, extent.y), 1.0)))), u.depthPyramidLevels - 1);
+    image2DHandle hiz = u.depthPyramid.d[level];
+    ivec2 p0 = ivec2(bmin) >> level;
+    ivec2 p1 = ivec2(bmax) >> level;
+    float farthest = max(
+        max(imageLoad(hiz, p0).r, imageLoad(hiz, ivec2(p1.x, p0.y)).r),
+        max(imageLoad(hiz, ivec2(p0.x, p1.y)).r, imageLoad(hiz, p1).r));
+    return nearest > farthest;
+}
+
+layout(local_size_x=SUBGROUP_SIZE) in;
+
+taskPayloadSharedEXT TaskData taskData;
+
+void main() {
+    uint curveIdx = gl_GlobalInvocationID.x;
+    bool visible = false;
+    uint sampleCount = 0;
+
+    if (curveIdx < u.curveCount) {
+        CurveDesc curve = u.curves.d[u.baseCurveIndex + curveIdx];
+        CubicBezier3D seg = loadSegment(curve);
+        vec4 c0 = project(seg.p0);
+        vec4 c1 = project(seg.p1);
+        vec4 c2 = project(seg.p2);
+        vec4 c3 = project(seg.p3);
+
+        // The curve is contained in the convex hull of its control points: cull it if they are all
+        // on the outer side of the same clip plane.
+        bvec3 allLess = lessThan(vec3(max(max(c0.x + c0.w, c1.x + c1.w), max(c2.x + c2.w, c3.x + c3.w)),
+                                      max(max(c0.y + c0.w, c1.y + c1.w), max(c2.y + c2.w, c3.y + c3.w)),
+                                      max(max(c0.w, c1.w), max(c2.w, c3.w))), vec3(0.0));
+        bvec2 allGreater = greaterThan(vec2(min(min(c0.x - c0.w, c1.x - c1.w), min(c2.x - c2.w, c3.x - c3.w)),
+                                            min(min(c0.y - c0.w, c1.y - c1.w), min(c2.y - c2.w, c3.y - c3.w))), vec2(0.0));
+        bool inFrustum = !any(allLess) && !any(allGreater);
+        visible = inFrustum;
+
+        // distance culling
+        bool distanceCulled = false;
+        if (visible && u.cullDistance > 0.0) {
+            vec3 center = 0.25 * (seg.p0 + seg.p1 + seg.p2 + seg.p3);
+            distanceCulled = distance(center, u.sceneParams.d.eye) > u.cullDistance;
+            visible = !distanceCulled;
+        }
+
+        // occlusion culling
+        bool occluded = false;
+        if (visible && u.depthPyramidLevels > 0 && min(min(c0.w, c1.w), min(c2.w, c3.w)) > 0.0) {
+            occluded = isOccluded(c0, c1, c2, c3);
+            visible = !occluded;
+        }
+
+        if (u.collectStats != 0) {
+            uint tested = subgroupBallotBitCount(subgroupBallot(true));
+            uint frustumCulled = subgroupBallotBitCount(subgroupBallot(!inFrustum));
+            uint distanceCulledCount = subgroupBallotBitCount(subgroupBallot(distanceCulled));
+            uint occlusionCulled = subgroupBallotBitCount(subgroupBallot(occluded));
+            if (subgroupElect()) {
+                atomicAdd(u.stats.d[0].segmentsTested, tested);
+                atomicAdd(u.stats.d[0].frustumCulled, frustumCulled);
+                atomicAdd(u.stats.d[0].distanceCulled, distanceCulledCount);
+                atomicAdd(u.stats.d[0].occlusionCulled, occlusionCulled);
+            }
+        }
+
+        if (visible) {
+            // Wang's formula: number of line segments needed so that the polyline stays within
+            // `tolerance` pixels of the curve. Control points behind the camera can't be projected
+            // meaningfully, in that case use the maximum sample count.
+            uint segmentCount = SUBGROUP_SIZE - 1;
+            if (min(min(c0.w, c1.w), min(c2.w, c3.w)) > 0.0) {
+                vec2 w0 = clipToWindow(c0);
+                vec2 w1 = clipToWindow(c1);
+                vec2 w2 = clipToWindow(c2);
+                vec2 w3 = clipToWindow(c3);
+                float dd = max(length(w0 - 2.0 * w1 + w2), length(w1 - 2.0 * w2 + w3));
+                segmentCount = uint(ceil(sqrt(0.75 * dd / max(u.tolerance, 0.01))));
+            }
+            sampleCount = clamp(segmentCount + 1, 2, SUBGROUP_SIZE);
+        }
+    }
+
+    // compact visible segments at the start of the payload
+    uvec4 vote = subgroupBallot(visible);
+    uint outIdx = subgroupBallotExclusiveBitCount(vote);
+    if (visible) {
+        taskData.curves[outIdx] = u8vec2(gl_SubgroupInvocationID, sampleCount);
+    }
+    if (gl_SubgroupInvocationID == 0) {
+        taskData.baseCurveID = gl_WorkGroupID.x * SUBGROUP_SIZE;
+    }
+    if (u.collectStats != 0) {
+        uint lineCount = subgroupAdd(visible ? sampleCount - 1 : 0);
+        if (subgroupElect()) {
+            atomicAdd(u.stats.d[0].segmentsRasterized, lineCount);
+        }
+    }
+    EmitMeshTasksEXT(subgroupBallotBitCount(vote), 1, 1);
+}
+
+#endif
+
+//////////////////////////////////////////////////////////
+
+#ifdef __MESH__
+
+taskPayloadSharedEXT TaskData taskData;
+
+layout(local_size_x=SUBGROUP_SIZE) in;
+
+// Two vertices per sample, two triangles between consecutive samples.
+layout(triangles, max_vertices=2*SUBGROUP_SIZE, max_primitives=2*(SUBGROUP_SIZE-1)) out;
+
+// Signed distance to the centerline, in pixels
+layout(location=0) out float o_offset[];
+// Half-width of the ribbon at the vertex, in pixels
+layout(location=1) out float o_halfWidth[];
+layout(location=2) out vec4 o_color[];
+
+void main() {
+    uint curveID = u.baseCurveIndex + taskData.baseCurveID + taskData.curves[gl_WorkGroupID.x].x;
+    uint sampleCount = taskData.curves[gl_WorkGroupID.x].y;
+    uint i = gl_SubgroupInvocationID;
+
+    if (i == 0) {
+        SetMeshOutputsEXT(2 * sampleCount, 2 * (sampleCount - 1));
+    }
+    if (i >= sampleCount) {
+        return;
+    }
+
+    CurveDesc curve = u.curves.d[curveID];
+    CubicBezier3D seg = loadSegment(curve);
+    float t = float(i) / float(sampleCount - 1);
+    vec3 pos = evalCubicBezier3D(seg, t);
+    vec3 tangent = evalCubicBezier3DTangent(seg, t);
+    if (dot(tangent, tangent) < 1e-12) {
+        // degenerate tangent (coincident control points), use the chord instead
+        tangent = seg.p3 - seg.p0;
+    }
+
+    // width & opacity profiles are polynomials in the curve parameter
+    float s = remap(t, 0., 1., curve.paramRange.x, curve.paramRange.y);
+    vec4 powers = vec4(1.0, s, s * s, s * s * s);
+    float width = u.width * max(dot(curve.widthProfile, powers), 0.0);
+    float opacity = clamp(dot(curve.opacityProfile, powers), 0.0, 1.0);
+
+    // Expand perpendicularly to the screen-space tangent, so that the ribbon always faces the camera.
+    vec4 p = project(pos);
+    vec4 pt = project(pos + 0.01 * tangent);
+    vec2 pxSize = vec2(2.0) / vec2(u.sceneParams.d.viewportSize);
+    vec2 dir = (pt.xy / pt.w - p.xy / p.w) / pxSize;
+    dir = dot(dir, dir) > 0.0 ? normalize(dir) : vec2(1.0, 0.0);
+    vec2 normal = vec2(-dir.y, dir.x);
+
+    // half-width + anti-aliasing margin (thin ribbons are faded out instead of getting thinner)
+    float hw = max(width, 1.0) * 0.5;
+    float hwAA = hw + u.filterWidth;
+    vec2 offset = hwAA * normal * pxSize * p.w;
+
+    vec3 color = mix(u.controlPoints.d[curve.start].color, u.controlPoints.d[curve.start + 3].color, t);
+    color = mix(color, u.tint.rgb, u.tint.a);
+    vec4 rgba = vec4(color, opacity * min(width, 1.0) * u.opacity);
+
+    uint v = 2 * i;
+    gl_MeshVerticesEXT[v].gl_Position = vec4(p.xy - offset, p.zw);
+    gl_MeshVerticesEXT[v + 1].gl_Position = vec4(p.xy + offset, p.zw);
+    o_offset[v] = -hwAA;
+    o_offset[v + 1] = hwAA;
+    o_halfWidth[v] = hw;
+    o_halfWidth[v + 1] = hw;
+    o_color[v] = rgba;
+    o_color[v + 1] = rgba;
+
+    if (i < sampleCount - 1) {
+        gl_PrimitiveTriangleIndicesEXT[v] = uvec3(v, v + 2, v + 1);
+        gl_PrimitiveTriangleIndicesEXT[v + 1] = uvec3(v + 2, v + 3, v + 1);
+    }
+}
+
+#endif
+
+//////////////////////////////////////////////////////////
+
+#ifdef __FRAGMENT__
+
+layout(location=0) in float i_offset;
+layout(location=1) in float i_halfWidth;
+layout(location=2) in vec4 i_color;
+layout(location=0) out vec4 o_color;
+
+void main() {
+    if (u.collectStats != 0) {
+        atomicAdd(u.stats.d[0].fragments, 1);
+    }
+
+    // box-filtered coverage of the ribbon cross-section
+    float halfFilterWidth = u.filterWidth * 0.5;
+    float d = abs(i_offset);
+    float coverage = clamp((i_halfWidth - d + halfFilterWidth) / max(u.filterWidth, 1e-3), 0.0, 1.0);
+    o_color = i_color * vec4(1.0, 1.0, 1.0, coverage);
+}
+
+#endif
@@ -50,6 +50,7 @@ struct SceneParams {
     uvec2 viewportSize;
     vec2 cursorPos;
     float time;
+    uint seed;
 };
 
 layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer SceneParamsPtr {SceneParams d;};
@@ -80,6 +81,8 @@ struct CurveDesc {
     uint count;
     vec2 paramRange;
     uint brushIndex;
+    uint curveId;
+    float seed;
 };
 
 layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer CurveDescPtr {CurveDesc d;};
@@ -111,6 +114,8 @@ struct Stroke {
     uint vertexCount;
     uint8_t brush;
     float arcLength;
+    uint curveId;
+    float seed;
 };
 
 layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer StrokePtr {Stroke d;};
@@ -249,3 +254,190 @@ struct DrawStrokesPushConstants {
 
 
 
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer CullingStatsPtr;
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer CullingStatsSlice;
+
+//  Culling statistics, accumulated by the GPU ribbons pipeline.
+struct CullingStats {
+    uint segmentsTested;
+    uint frustumCulled;
+    uint distanceCulled;
+    uint occlusionCulled;
+    uint segmentsRasterized;
+    uint fragments;
+};
+
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer CullingStatsPtr {CullingStats d;};
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer CullingStatsSlice {CullingStats[] d;};
+
+
+
+//  Push constants of the GPU ribbon tessellation pipeline (`ribbons.glsl`).
+struct DrawRibbonsPushConstants {
+    ControlPointSlice controlPoints;
+    CurveDescSlice curves;
+    SceneParamsPtr sceneParams;
+    uint baseCurveIndex;
+    uint curveCount;
+    float width;
+    float filterWidth;
+    float tolerance;
+    float cullDistance;
+    uint collectStats;
+    CullingStatsSlice stats;
+    vec4 tint;
+    float opacity;
+    image2DHandleSlice depthPyramid;
+    uint depthPyramidLevels;
+};
+
+
+
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer SphereColliderPtr;
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer SphereColliderSlice;
+
+//  Sphere collider of the strand dynamics solver.
+struct SphereCollider {
+    vec3 center;
+    float radius;
+};
+
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer SphereColliderPtr {SphereCollider d;};
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer SphereColliderSlice {SphereCollider[] d;};
+
+
+
+//  Push constants of the strand dynamics solver (`strand_dynamics.comp`).
+struct StrandDynamicsParams {
+    ControlPointSlice controlPoints;
+    CurveDescSlice curves;
+    vec4Slice positions;
+    vec4Slice prevPositions;
+    ControlPointSlice simulatedPoints;
+    SphereColliderSlice colliders;
+    uint baseCurveIndex;
+    uint curveCount;
+    uint colliderCount;
+    uint iterations;
+    vec3 gravity;
+    float dt;
+    float damping;
+    float stiffness;
+    uint reset;
+};
+
+
+
+const uint STRAND_DYNAMICS_WORKGROUP_SIZE = 64;
+
+
+
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer MeshVertexPtr;
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer MeshVertexSlice;
+
+//  Vertex of the meshes of the scene (e.g. scalp meshes).
+struct MeshVertex {
+    vec3 pos;
+    vec3 normal;
+    vec3 color;
+};
+
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer MeshVertexPtr {MeshVertex d;};
+layout(buffer_reference, scalar, buffer_reference_align=8) coherent buffer MeshVertexSlice {MeshVertex[] d;};
+
+
+
+//  Push constants of the mesh and point cloud pipelines (`geometry.glsl`).
+struct DrawGeometryPushConstants {
+    MeshVertexSlice meshVertices;
+    ControlPointSlice points;
+    SceneParamsPtr sceneParams;
+    uint baseIndex;
+    uint count;
+    float pointSize;
+    uint sizeAttenuation;
+    uint colorByNormals;
+};
+
+
+
+//  Push constants of the display transform pass (`display_transform.comp`).
+struct DisplayTransformParams {
+    uvec2 viewportSize;
+    image2DHandle inputImage;
+    image2DHandle outputImage;
+    mat3 workingToRec709;
+    float exposure;
+    uint view;
+    vec3Slice lut;
+    uint lutSize;
+    vec3 lutDomainMin;
+    vec3 lutDomainMax;
+};
+
+
+
+const uint DISPLAY_TRANSFORM_WORKGROUP_SIZE = 16;
+
+
+
+//  Push constants of the stylization pass (`stylize.comp`).
+struct StylizeParams {
+    uvec2 viewportSize;
+    image2DHandle inputImage;
+    image2DHandle outputImage;
+    floatSlice depth;
+    float nearPlane;
+    float farPlane;
+    vec4 outlineColor;
+    float outlineWidth;
+    float depthThreshold;
+    float colorThreshold;
+    float paperStrength;
+    float paperScale;
+    uint paperPeriod;
+    vec2 paperOffset;
+    uint seed;
+};
+
+
+
+const uint STYLIZE_WORKGROUP_SIZE = 16;
+
+
+
+//  Push constants of the stock blur pass (`blur.comp`), one direction of a separable gaussian blur.
+struct BlurParams {
+    uvec2 imageSize;
+    image2DHandle inputImage;
+    image2DHandle outputImage;
+    uint horizontal;
+    uint radius;
+    float sigma;
+};
+
+
+
+//  Push constants of the stock copy and downsampling passes (`copy_image.comp`, `downsample.comp`), and of
+//  the depth pyramid builder (`hiz_downsample.comp`).
+struct ResampleParams {
+    uvec2 inputSize;
+    uvec2 outputSize;
+    image2DHandle inputImage;
+    image2DHandle outputImage;
+};
+
+
+
+//  Push constants of the stock depth resolve pass (`resolve_depth.comp`).
+struct ResolveDepthParams {
+    uvec2 imageSize;
+    floatSlice depth;
+    image2DHandle outputImage;
+    float nearPlane;
+    float farPlane;
+};
+
+
+
+const uint STOCK_PASS_WORKGROUP_SIZE = 16;
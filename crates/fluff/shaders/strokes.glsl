@@ -461,6 +461,8 @@ layout(location=8) in perprimitiveEXT flat vec2 i_radii;
 layout(location=9) in perprimitiveEXT flat float i_startArcLength;
 layout(location=10) in perprimitiveEXT flat float i_segmentLength;
 layout(location=0) out vec4 o_color;
+// index of the stroke in the frame plus one, 0 where there is no stroke (used for selection)
+layout(location=1) out uint o_strokeID;
 
 /*
 // 2D Distance from a point to a segment.
@@ -497,6 +499,7 @@ void main() {
     #endif
 
     Stroke stroke = u.strokes.d[i_strokeID];
+    o_strokeID = uint(i_strokeID) + 1;
 
     float width1 = max(width, 1.0);
     float h = width1 * 0.5;
@@ -603,7 +606,7 @@ void main() {
     #endif
 
     #ifdef DITHER
-    o_color.rgb += vec3(0.03 * noise(gl_FragCoord.xy + 10000.0 * u.sceneParams.d.time));
+    o_color.rgb += vec3(0.03 * noise(gl_FragCoord.xy + float(u.sceneParams.d.seed & 0xFFFFu)));
     #endif
 
     #ifdef SHOW_STROKE_SKELETON
@@ -45,6 +45,14 @@ impl AABB {
         0.5 * (self.min + self.max)
     }
 
+    /// Returns the bounding box of the points, or `None` if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = glam::Vec3>) -> Option<AABB> {
+        let mut points = points.into_iter().map(glam::Vec3A::from);
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), p| (min.min(p), max.max(p)));
+        Some(AABB { min, max })
+    }
+
     /// Returns the union of this bounding box with another.
     pub fn union(&self, other: &AABB) -> AABB {
         AABB {
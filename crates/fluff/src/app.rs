@@ -6,16 +6,14 @@ use std::{
     collections::BTreeMap,
     fs, mem,
     path::{Path, PathBuf},
-    ptr,
+    rc::Rc,
 };
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use egui::ImageData::Color;
 use tracing::{error, info, trace, warn};
 
 use houdinio::Geo;
 use rand::{random, thread_rng, Rng};
-use uniform_cubic_splines::{spline, spline_inverse};
-use uniform_cubic_splines::basis::CatmullRom;
 //use splines::Spline;
 use winit::{
     event::{MouseButton, TouchPhase},
@@ -24,7 +22,9 @@ use winit::{
 
 use crate::{
     camera_control::CameraControl,
-    engine::{ComputePipelineDesc, Engine, Error, MeshRenderPipelineDesc},
+    engine::{
+        color_attachment, depth_stencil_attachment, ComputePipelineDesc, Engine, Error, LoadHint, MeshRenderPipelineDesc, StockPass,
+    },
     overlay::{CubicBezierSegment, OverlayRenderParams, OverlayRenderer},
     shaders,
     shaders::shared::{
@@ -33,11 +33,37 @@ use crate::{
     },
     util::resolve_file_sequence,
 };
-use crate::util::AppendBuffer;
-use crate::shaders::shared::{DrawStrokesPushConstants, Stroke, StrokeVertex, SUBGROUP_SIZE};
-use crate::scene::{Scene, load_stroke_animation_data};
-use crate::ui::{curve_editor_button, icon_button};
+use crate::util::{AppendBuffer, CommandStreamUploadExt};
+use crate::shaders::shared::{DrawRibbonsPushConstants, DrawStrokesPushConstants, Stroke, StrokeVertex, SUBGROUP_SIZE};
+use crate::scene::{DrawnStroke, Scene, SceneObjectKind, load_stroke_animation_data};
+use crate::brush::{fit_stroke, tessellate, BrushInput, BrushSample, BrushSettings};
+use crate::ui::{icon_button, keyframe_curve_editor};
+use crate::keyframe::AnimatedParams;
+use crate::audio::{AudioPlayer, AudioTrack};
+use crate::ui::timeline_waveform;
 use crate::util::lagrange_interpolate_4;
+use crate::selection::{Falloff, PendingSelection, Selection, SelectionOp, SelectionShape};
+use crate::plugin::{plugin_directory, PluginRegistry, RenderPassContext};
+use crate::script::{ConsoleLine, ScriptEngine, ScriptHost};
+use crate::dynamics::StrandDynamics;
+use crate::geometry::GeometryDisplay;
+use crate::stats::CullingStatsCollector;
+use crate::jobs::{JobHandle, JobStatus, JobSystem};
+use crate::asset_browser::AssetBrowser;
+use crate::color::{ColorManagement, ColorSettings};
+use crate::onion_skin::OnionSkin;
+use crate::stylize::{Stylize, StylizeSettings};
+use crate::dynamic_resolution::DynamicResolution;
+use crate::svg_export::SvgExport;
+use crate::workspace::{default_layouts, Workspace, WorkspaceLayout};
+use crate::eco_mode::{EcoMode, ECO_RIBBON_TOLERANCE_SCALE};
+use crate::review_session::{ReviewSession, SessionUpdate};
+use crate::usd;
+use crate::curve_id::curve_seed;
+use crate::determinism::SeedSettings;
+use crate::live_link::LiveLink;
+use crate::import_transform::ImportTransform;
+use crate::gpu_memory::{self, image_byte_size, MemoryCategory, MemoryPanel, MemoryTag};
 
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -67,18 +93,11 @@ fn load_brush_texture(cmd: &mut CommandStream, path: impl AsRef<Path>, usage: Im
         samples: 1,
     });
 
-    let byte_size = width as u64 * height as u64;
-
-    // create a staging buffer
-    let staging_buffer = device.create_buffer(BufferUsage::TRANSFER_SRC, MemoryLocation::CpuToGpu, byte_size);
-
-    // read image data
-    unsafe {
-        ptr::copy_nonoverlapping(gray_image.as_raw().as_ptr(), staging_buffer.as_mut_ptr(), byte_size as usize);
-    }
+    // upload image data to a staging buffer
+    let staging_buffer = cmd.upload_slice(BufferUsage::TRANSFER_SRC, MemoryLocation::CpuToGpu, gray_image.as_raw());
     cmd.copy_buffer_to_image(
         ImageCopyBuffer {
-            buffer: &staging_buffer,
+            buffer: &staging_buffer.untyped,
             layout: ImageDataLayout {
                 offset: 0,
                 row_length: Some(width),
@@ -106,13 +125,57 @@ struct GeoFileData {
     geometry: Geo,
 }
 
+/// Loads a Houdini geometry file (JSON or binary), printing errors and warnings.
+fn load_houdini_geo(file_path: &Path) -> Option<Geo> {
+    let options = houdinio::ParseOptions {
+        lenient: true,
+        // drop broken primitives instead of indexing out of bounds when building GPU buffers
+        validation: houdinio::Validation::Repair,
+        recenter: false,
+    };
+    match Geo::load_with_options(file_path, &options) {
+        Ok((geometry, warnings)) => {
+            eprintln!("Loaded `{}`", file_path.display());
+            for warning in warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            Some(geometry)
+        }
+        Err(err) => {
+            eprintln!("Error loading `{}`: {}", file_path.display(), err);
+            None
+        }
+    }
+}
+
+/// Loads a USD file, with one frame per time code of the layer.
+fn load_usd_geo(file_path: &Path) -> Vec<GeoFileData> {
+    match usd::load_usd(file_path) {
+        Ok((frames, warnings)) => {
+            eprintln!("Loaded `{}` ({} frames)", file_path.display(), frames.len());
+            for warning in warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            frames
+                .into_iter()
+                .enumerate()
+                .map(|(index, geometry)| GeoFileData { index, geometry })
+                .collect()
+        }
+        Err(err) => {
+            eprintln!("Error loading `{}`: {}", file_path.display(), err);
+            vec![]
+        }
+    }
+}
+
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 fn create_depth_buffer(device: &Device, width: u32, height: u32) -> Image {
     let image = device.create_image(&ImageCreateInfo {
         memory_location: MemoryLocation::GpuOnly,
         type_: ImageType::Image2D,
-        usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT,
+        usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSFER_SRC,
         format: Format::D32_SFLOAT,
         width,
         height,
@@ -125,6 +188,98 @@ fn create_depth_buffer(device: &Device, width: u32, height: u32) -> Image {
     image
 }
 
+/// Creates the image receiving the index of the stroke drawn on each pixel, for selection.
+fn create_stroke_id_image(device: &Device, width: u32, height: u32) -> Image {
+    let image = device.create_image(&ImageCreateInfo {
+        memory_location: MemoryLocation::GpuOnly,
+        type_: ImageType::Image2D,
+        usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+        format: Format::R32_UINT,
+        width,
+        height,
+        depth: 1,
+        mip_levels: 1,
+        array_layers: 1,
+        samples: 1,
+    });
+    image.set_name("stroke ID image");
+    image
+}
+
+/// Size in bytes of the frame-sized images of the app (depth buffer, frame image, temporal average and
+/// stroke IDs).
+fn render_target_byte_size(width: u32, height: u32) -> u64 {
+    image_byte_size(Format::D32_SFLOAT, width, height, 1)
+        + 2 * image_byte_size(Format::R16G16B16A16_SFLOAT, width, height, 1)
+        + image_byte_size(Format::R32_UINT, width, height, 1)
+}
+
+/// Color and depth images at the window resolution, when the scene is rendered at a lower resolution.
+///
+/// The scene is upscaled to them, and the overlay is drawn over it.
+struct UpscaleTargets {
+    color: Image,
+    depth: Image,
+    depth_view: ImageView,
+}
+
+impl UpscaleTargets {
+    fn new(device: &Device, width: u32, height: u32) -> UpscaleTargets {
+        let color = device.create_image(&ImageCreateInfo {
+            memory_location: MemoryLocation::GpuOnly,
+            type_: ImageType::Image2D,
+            usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::COLOR_ATTACHMENT,
+            format: Format::R16G16B16A16_SFLOAT,
+            width,
+            height,
+            depth: 1,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: 1,
+        });
+        color.set_name("upscaled frame image");
+        let depth = device.create_image(&ImageCreateInfo {
+            memory_location: MemoryLocation::GpuOnly,
+            type_: ImageType::Image2D,
+            usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSFER_DST,
+            format: Format::D32_SFLOAT,
+            width,
+            height,
+            depth: 1,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: 1,
+        });
+        depth.set_name("upscaled depth buffer");
+        let depth_view = depth.create_top_level_view();
+        UpscaleTargets { color, depth, depth_view }
+    }
+
+    fn byte_size(&self) -> u64 {
+        image_byte_size(Format::D32_SFLOAT, self.color.width(), self.color.height(), 1)
+            + image_byte_size(Format::R16G16B16A16_SFLOAT, self.color.width(), self.color.height(), 1)
+    }
+}
+
+/// Scales the top mip level of `src` to cover `dst`.
+fn blit_scaled(cmd: &mut CommandStream, src: &Image, dst: &Image, aspect_mask: vk::ImageAspectFlags, filter: vk::Filter) {
+    let subresource = || ImageSubresourceLayers {
+        aspect_mask,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    let rect = |image: &Image| Rect3D {
+        min: Point3D { x: 0, y: 0, z: 0 },
+        max: Point3D {
+            x: image.width() as i32,
+            y: image.height() as i32,
+            z: 1,
+        },
+    };
+    cmd.blit_image(src, subresource(), rect(src), dst, subresource(), rect(dst), filter);
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Default)]
@@ -142,6 +297,20 @@ enum RenderMode {
     BinRasterization = 0,
     CurvesOIT = 1,
     CurvesOITv2 = 2,
+    GpuRibbons = 3,
+}
+
+impl RenderMode {
+    /// Looks up a render mode by its short name: `bin`, `oit`, `oit2` or `ribbons`.
+    fn from_name(name: &str) -> Option<RenderMode> {
+        match name {
+            "bin" => Some(RenderMode::BinRasterization),
+            "oit" => Some(RenderMode::CurvesOIT),
+            "oit2" => Some(RenderMode::CurvesOITv2),
+            "ribbons" => Some(RenderMode::GpuRibbons),
+            _ => None,
+        }
+    }
 }
 
 struct BrushTexture {
@@ -151,13 +320,8 @@ struct BrushTexture {
     sat_image: Image,
     sat_image_view: ImageView,
     id: u32,
-}
-
-#[derive(Copy, Clone)]
-struct PenSample {
-    position: DVec2,
-    pressure: f64,
-    arc_length: f64,
+    /// Accounts for the memory of both images while the texture is loaded.
+    _memory: MemoryTag,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -168,33 +332,41 @@ struct Tweak {
     autofocus: bool,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
-struct CubicCurve {
-    knots: Vec<f64>,
-    values: Vec<f64>,
-}
-
-impl CubicCurve {
-    pub fn sample(&self, t: f64) -> f64 {
-        let t = spline_inverse::<CatmullRom, _>(t, &self.knots, None, None).unwrap_or_default();
-        spline::<CatmullRom, _, _>(t, &self.values)
-    }
-}
-
-impl Default for CubicCurve {
-    fn default() -> Self {
-        Self {
-            knots: vec![0.0, 0.0, 0.33, 0.66, 1.0, 1.0],
-            values: vec![0.0, 0.0, 0.33, 0.66, 1.0, 1.0],
-        }
-    }
-}
-
 #[derive(serde::Serialize, serde::Deserialize)]
 struct SavedSettings {
     tweaks: Vec<Tweak>,
     last_geom_file: Option<PathBuf>,
-    pressure_response_curve: CubicCurve,
+    /// Brush used to draw strokes in the viewport.
+    #[serde(default)]
+    brush: BrushSettings,
+    #[serde(default)]
+    animated_params: AnimatedParams,
+    #[serde(default)]
+    audio_track: Option<PathBuf>,
+    /// Most recently loaded geometry files, most recent first.
+    #[serde(default)]
+    recent_files: Vec<PathBuf>,
+    /// Directories scanned by the asset browser.
+    #[serde(default)]
+    asset_directories: Vec<PathBuf>,
+    #[serde(default)]
+    color: ColorSettings,
+    #[serde(default)]
+    stylize: StylizeSettings,
+    /// Unit and axis conversion of each imported asset, by path.
+    #[serde(default)]
+    import_transforms: BTreeMap<PathBuf, ImportTransform>,
+    #[serde(default = "default_layouts")]
+    workspace_layouts: Vec<WorkspaceLayout>,
+    /// Random seeds of the stochastic effects.
+    #[serde(default)]
+    seeds: SeedSettings,
+    /// Directory watched by the live link.
+    #[serde(default)]
+    live_link_directory: Option<PathBuf>,
+    /// Maximum size of the transient resources of a frame, in MiB.
+    #[serde(default)]
+    transient_budget_mib: Option<u64>,
 }
 
 impl Default for SavedSettings {
@@ -204,7 +376,18 @@ impl Default for SavedSettings {
         Self {
             tweaks: vec![],
             last_geom_file: None,
-            pressure_response_curve: Default::default(),
+            brush: Default::default(),
+            animated_params: Default::default(),
+            audio_track: None,
+            recent_files: vec![],
+            asset_directories: vec![],
+            color: Default::default(),
+            stylize: Default::default(),
+            import_transforms: Default::default(),
+            workspace_layouts: default_layouts(),
+            seeds: Default::default(),
+            live_link_directory: None,
+            transient_budget_mib: None,
         }
     }
 }
@@ -226,6 +409,8 @@ pub struct App {
     device: Device,
     depth_buffer: Image,
     depth_buffer_view: ImageView,
+    /// Index of the stroke drawn on each pixel of the scene, written by the stroke pass.
+    stroke_id_image: Image,
     color_target_format: Format,
     camera_control: CameraControl,
     overlay: OverlayRenderer,
@@ -237,8 +422,11 @@ pub struct App {
     mode: RenderMode,
     temporal_average: bool,
     temporal_average_alpha: f32,
+    /// Image receiving the rendered scene, at the resolution given by `dynamic_resolution`.
     frame_image: Image,
     temporal_avg_image: Image,
+    /// Native resolution targets, when the scene is rendered at a lower resolution.
+    upscale_targets: Option<UpscaleTargets>,
     debug_tile_line_overflow: bool,
     start_time: Instant,
     frame_start_time: Instant,
@@ -247,6 +435,41 @@ pub struct App {
     bin_rast_stroke_width: f32,
     current_frame: usize,
 
+    // Timeline playback
+    playing: bool,
+    /// Instant and frame at which playback was started.
+    playback_start: (Instant, usize),
+    fps: f64,
+    /// Audio output, `None` if no output device could be opened.
+    audio: Option<AudioPlayer>,
+
+    // GPU ribbons
+    /// Maximum screen-space distance (in pixels) between the curves and their tessellation.
+    ribbon_tolerance: f32,
+    /// Curve segments farther than this from the camera are culled (0 = no distance culling).
+    cull_distance: f32,
+    /// Draw the meshes in a depth pre-pass, and cull the curve segments they hide (GPU ribbons only).
+    occlusion_culling: bool,
+    culling_stats: CullingStatsCollector,
+    /// GPU memory usage by subsystem.
+    memory_panel: MemoryPanel,
+    /// Memory of the depth buffer, frame image and temporal average image.
+    render_target_memory: MemoryTag,
+    /// Ghosted display of the adjacent frames.
+    onion_skin: OnionSkin,
+
+    /// Display transform applied to the final frame.
+    color: ColorManagement,
+    /// Outlines and paper grain, applied before the overlays.
+    stylize: Stylize,
+    /// Reduced redraw rate and quality while the window is unfocused.
+    eco_mode: EcoMode,
+    dynamic_resolution: DynamicResolution,
+    svg_export: SvgExport,
+    workspace: Workspace,
+    /// Camera, timeline and annotations shared with remote participants.
+    review: ReviewSession,
+
     // Curves OIT
     oit_stroke_width: f32,
     oit_max_fragments_per_pixel: u32,
@@ -261,17 +484,14 @@ pub struct App {
     overlay_filter_width: f32,
 
     // UI
-    /// Curve drawing mode active
-    is_drawing: bool,
-    last_pos: DVec2,
-    pen_points: Vec<PenSample>,
+    /// Stroke being drawn with the pen.
+    brush_input: Option<BrushInput>,
     drawn_curves: AppendBuffer<CurveDesc>,
     drawn_control_points: AppendBuffer<ControlPoint>,
     settings: SavedSettings,
     tweaks_changed: bool,
     engine: Engine,
     draw_origin: glam::Vec2,
-    fit_tolerance: f64,
     curve_embedding_factor: f64,
     stroke_bleed_exp: f32,
     stroke_color: Color32,
@@ -280,10 +500,64 @@ pub struct App {
     width_profile: glam::Vec4,
     opacity_profile_pos: glam::Vec4,
     opacity_profile: glam::Vec4,
-    opacity_response_curve: CubicCurve,
-
+    /// Parameter shown in the keyframe editor.
+    keyframe_editor_param: &'static str,
+
+    // Selection
+    selection: Selection,
+    /// Selection gesture in progress (right mouse button drag).
+    selection_gesture: Option<(SelectionOp, SelectionShape)>,
+    /// Selection gesture that ended, to resolve with the stroke IDs of the next frame.
+    finished_selection_gesture: Option<(SelectionOp, SelectionShape)>,
+    /// Selection waiting for the stroke IDs to be read back.
+    pending_selection: Option<PendingSelection>,
+    cursor_pos: DVec2,
+    shift_down: bool,
+    ctrl_down: bool,
+    alt_down: bool,
+    /// Name entered in the "Save set" field.
+    selection_set_name: String,
+    /// Translation applied by the "Move" button of the selection window.
+    selection_offset: Vec3,
+
+    /// Render passes, importers and panels provided by plug-ins.
+    plugins: PluginRegistry,
+
+    /// Strand dynamics preview.
+    dynamics: StrandDynamics,
+    jobs: JobSystem,
+    /// Geometry being loaded in the background, and the path it was loaded from.
+    pending_geo_load: Option<(PathBuf, JobHandle<Vec<GeoFileData>>)>,
+    /// SVG export running in the background, with the output path.
+    pending_svg_export: Option<(PathBuf, JobHandle<Result<(), String>>)>,
+    /// Whether the geometry being loaded replaces a new version of the current scene, from the live link.
+    live_reload: bool,
+    live_link: LiveLink,
+    /// Asset waiting for confirmation in the import dialog, and the conversion being edited.
+    import_dialog: Option<(PathBuf, ImportTransform)>,
+    /// Display of meshes and point clouds.
+    geometry: GeometryDisplay,
+    asset_browser: AssetBrowser,
+
+    // Scripting
+    scripts: Rc<ScriptEngine>,
+    /// Command being typed in the console window.
+    console_input: String,
 }
 
+/// Number of entries in the "Open Recent" menu.
+const MAX_RECENT_FILES: usize = 10;
+
+/// Names of the parameters that can be keyframed.
+const ANIMATABLE_PARAMS: &[&str] = &[
+    "stroke_width",
+    "stroke_bleed_exp",
+    "temporal_average_alpha",
+    "overlay_line_width",
+    "overlay_filter_width",
+    "camera_fov",
+];
+
 impl App {
     fn compute_sats(&mut self, cmd: &mut CommandStream) -> Result<(), Error> {
         let sat_shader = PathBuf::from("crates/fluff/shaders/sat.glsl");
@@ -372,9 +646,16 @@ impl App {
         let curve_count = anim_frame.curve_range.count;
         let base_curve_index = anim_frame.curve_range.start;
         let frame = self.current_frame as u32;
-        let stroke_width = self.bin_rast_stroke_width;
+        // widths are in pixels of the scene, which may be rendered at a lower resolution
+        let stroke_width = self.bin_rast_stroke_width * self.dynamic_resolution.scale();
         let viewport_size = [width, height];
         let temporal_average_falloff = self.temporal_average_alpha;
+        let temporal_average = self.temporal_average_enabled();
+        let ribbon_tolerance = if self.eco_mode.is_active() {
+            self.ribbon_tolerance * ECO_RIBBON_TOLERANCE_SCALE
+        } else {
+            self.ribbon_tolerance
+        };
         let debug_tile_line_overflow = self.debug_tile_line_overflow;
 
         let tile_count_x = width.div_ceil(BINNING_TILE_SIZE);
@@ -399,6 +680,7 @@ impl App {
             viewport_size: viewport_size.into(),
             cursor_pos: Default::default(),
             time,
+            seed: self.settings.seeds.frame_seed(self.current_frame),
         };
 
 
@@ -406,21 +688,33 @@ impl App {
         let scene_params_buf = self.device.upload(BufferUsage::STORAGE_BUFFER, &scene_params);
         cmd.reference_resource(&scene_params_buf);
 
+        let culling_stats = self.culling_stats.begin_frame(width, height);
+
+        ////////////////////////////////////////////////////////////
+        // Strand dynamics
+        let control_points = self
+            .dynamics
+            .step(cmd, engine, animation, self.current_frame)?
+            .unwrap_or(animation.position_buffer.device_address());
+
         // FIXME: importing image sets will mean finding a contiguous range of free image handles
         // or we can pass the image view handles in an array, at the cost of another indirection
         //let brush_textures = rg.import_image_set(self.brush_textures.iter().map(|b| b.image.clone()));
 
         ////////////////////////////////////////////////////////////
         // Curve binning
+        let tile_count = tile_count_x as usize * tile_count_y as usize;
+        engine.use_transient("curve binning", "tile line counts", (tile_count * size_of::<u32>()) as u64)?;
+        engine.use_transient("curve binning", "tile data", (tile_count * size_of::<TileData>()) as u64)?;
         let tile_line_count_buffer = self.device.create_array_buffer::<u32>(
             BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
             MemoryLocation::GpuOnly,
-            tile_count_x as usize * tile_count_y as usize,
+            tile_count,
         );
         let tile_buffer = self.device.create_array_buffer::<TileData>(
             BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
             MemoryLocation::GpuOnly,
-            tile_count_x as usize * tile_count_y as usize,
+            tile_count,
         );
 
 
@@ -434,6 +728,7 @@ impl App {
         let color_target_view = color_target.create_top_level_view();
         let depth_target_view = self.depth_buffer.create_top_level_view();
         let temporal_avg_view = self.temporal_avg_image.create_top_level_view();
+        let stroke_id_view = self.stroke_id_image.create_top_level_view();
 
         // pipelines
         let curve_binning_pipeline = engine.create_mesh_render_pipeline(
@@ -482,6 +777,35 @@ impl App {
                 mesh_shader: PathBuf::from("crates/fluff/shaders/strokes.glsl"),
                 fragment_shader: PathBuf::from("crates/fluff/shaders/strokes.glsl"),
                 defines: Default::default(),
+                color_targets: vec![
+                    ColorTargetState {
+                        format: Format::R16G16B16A16_SFLOAT,
+                        blend_equation: Some(ColorBlendEquation::ALPHA_BLENDING),
+                        ..Default::default()
+                    },
+                    ColorTargetState {
+                        format: Format::R32_UINT,
+                        ..Default::default()
+                    },
+                ],
+                rasterization_state: Default::default(),
+                depth_stencil_state: Some(DepthStencilState {
+                    format: Format::D32_SFLOAT,
+                    depth_write_enable: false,
+                    depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                    stencil_state: StencilState::default(),
+                }),
+                multisample_state: Default::default(),
+            },
+        )?;
+
+        let draw_ribbons_pipeline = engine.create_mesh_render_pipeline(
+            "draw_ribbons",
+            MeshRenderPipelineDesc {
+                task_shader: PathBuf::from("crates/fluff/shaders/ribbons.glsl"),
+                mesh_shader: PathBuf::from("crates/fluff/shaders/ribbons.glsl"),
+                fragment_shader: PathBuf::from("crates/fluff/shaders/ribbons.glsl"),
+                defines: Default::default(),
                 color_targets: vec![ColorTargetState {
                     format: Format::R16G16B16A16_SFLOAT,
                     blend_equation: Some(ColorBlendEquation::ALPHA_BLENDING),
@@ -501,6 +825,12 @@ impl App {
         //////////////////////////////////////////
         cmd.reference_resource(&brush_textures);
 
+        // Whether the meshes were already drawn by the depth pre-pass.
+        let mut geometry_rendered = false;
+        if !self.occlusion_culling || self.mode != RenderMode::GpuRibbons {
+            engine.release_depth_pyramid();
+        }
+
         match self.mode {
             RenderMode::BinRasterization => {
                 cmd.fill_buffer(&tile_line_count_buffer.untyped.byte_range(..), 0);
@@ -510,15 +840,8 @@ impl App {
 
                 // curve binning
                 let mut encoder = cmd.begin_rendering(RenderPassInfo {
-                    color_attachments: &[ColorAttachment {
-                        image_view: &color_target_view,
-                        clear_value: Some([0.0, 0.0, 0.0, 1.0]),
-                    }],
-                    depth_stencil_attachment: Some(DepthStencilAttachment {
-                        image_view: &depth_target_view,
-                        depth_clear_value: Some(1.0),
-                        stencil_clear_value: None,
-                    }),
+                    color_attachments: &[color_attachment(&color_target_view, LoadHint::Clear([0.0, 0.0, 0.0, 1.0]))],
+                    depth_stencil_attachment: Some(depth_stencil_attachment(&depth_target_view, LoadHint::Clear(1.0), LoadHint::Load)),
                 });
 
                 let vp_width = width as f32 / BINNING_TILE_SIZE as f32;
@@ -536,7 +859,7 @@ impl App {
                     tile_count_x,
                     tile_count_y,
                     frame,
-                    control_points: animation.position_buffer.device_address(),
+                    control_points,
                     curves: animation.curve_buffer.device_address(),
                     tile_line_count: tile_line_count_buffer.device_address(),
                     tile_data: tile_buffer.device_address(),
@@ -549,7 +872,7 @@ impl App {
                 let mut encoder = cmd.begin_compute();
                 encoder.bind_compute_pipeline(&draw_curves_pipeline);
                 encoder.push_constants(&DrawCurvesPushConstants {
-                    control_points: animation.position_buffer.device_address(),
+                    control_points,
                     curves: animation.curve_buffer.device_address(),
                     //view_proj,
                     scene_params: scene_params_buf.device_address(),
@@ -571,15 +894,14 @@ impl App {
             RenderMode::CurvesOIT => {
                 let clear_color = self.background_color.to_normalized_gamma_f32();
                 let mut encoder = cmd.begin_rendering(RenderPassInfo {
-                    color_attachments: &[ColorAttachment {
-                        image_view: &color_target_view,
-                        clear_value: Some([clear_color[0] as f64, clear_color[1] as f64, clear_color[2] as f64, clear_color[3] as f64]),
-                    }],
-                    depth_stencil_attachment: Some(DepthStencilAttachment {
-                        image_view: &depth_target_view,
-                        depth_clear_value: Some(1.0),
-                        stencil_clear_value: None,
-                    }),
+                    color_attachments: &[
+                        color_attachment(
+                            &color_target_view,
+                            LoadHint::Clear([clear_color[0] as f64, clear_color[1] as f64, clear_color[2] as f64, clear_color[3] as f64]),
+                        ),
+                        color_attachment(&stroke_id_view, LoadHint::Clear([0.0; 4])),
+                    ],
+                    depth_stencil_attachment: Some(depth_stencil_attachment(&depth_target_view, LoadHint::Clear(1.0), LoadHint::Load)),
                 });
                 encoder.bind_graphics_pipeline(&draw_strokes_pipeline);
                 encoder.push_constants(&DrawStrokesPushConstants {
@@ -595,11 +917,116 @@ impl App {
                 encoder.draw_mesh_tasks(anim_frame.stroke_count.div_ceil(SUBGROUP_SIZE), 1, 1);
                 encoder.finish();
             }
+            RenderMode::GpuRibbons => {
+                let clear_color = self.background_color.to_normalized_gamma_f32();
+                let mut color_load =
+                    LoadHint::Clear([clear_color[0] as f64, clear_color[1] as f64, clear_color[2] as f64, clear_color[3] as f64]);
+                let mut depth_load = LoadHint::Clear(1.0);
+                // The handles are not read when there are no levels, but the address must be valid.
+                let mut depth_pyramid = brush_textures.device_address();
+                let mut depth_pyramid_levels = 0;
+
+                if self.occlusion_culling {
+                    // Depth pre-pass: draw the meshes first, then build the depth pyramid of the occluders
+                    // for the task shader.
+                    let encoder = cmd.begin_rendering(RenderPassInfo {
+                        color_attachments: &[color_attachment(&color_target_view, color_load)],
+                        depth_stencil_attachment: Some(depth_stencil_attachment(&depth_target_view, depth_load, LoadHint::Load)),
+                    });
+                    encoder.finish();
+                    self.geometry.render(
+                        cmd,
+                        engine,
+                        animation,
+                        self.current_frame,
+                        scene_params_buf.device_address(),
+                        &color_target_view,
+                        &depth_target_view,
+                    )?;
+                    geometry_rendered = true;
+                    let pyramid = engine.build_depth_pyramid(
+                        cmd,
+                        &self.depth_buffer,
+                        camera.frustum.near_plane,
+                        camera.frustum.far_plane,
+                    )?;
+                    cmd.barrier(pyramid.barrier(Barrier::new()));
+                    depth_pyramid = pyramid.upload_level_handles(cmd).device_address();
+                    depth_pyramid_levels = pyramid.level_count();
+                    color_load = LoadHint::Load;
+                    depth_load = LoadHint::Load;
+                }
+
+                let mut encoder = cmd.begin_rendering(RenderPassInfo {
+                    color_attachments: &[color_attachment(&color_target_view, color_load)],
+                    depth_stencil_attachment: Some(depth_stencil_attachment(&depth_target_view, depth_load, LoadHint::Load)),
+                });
+                encoder.bind_graphics_pipeline(&draw_ribbons_pipeline);
+                // Onion skins: adjacent frames, drawn under the current one. They show the animated
+                // positions, not the simulated ones.
+                let ghosts = if self.eco_mode.is_active() {
+                    vec![]
+                } else {
+                    self.onion_skin.ghost_frames(self.current_frame, animation.frames.len())
+                };
+                for ghost in ghosts {
+                    let range = animation.frames[ghost.frame].curve_range;
+                    encoder.push_constants(&DrawRibbonsPushConstants {
+                        control_points: animation.position_buffer.device_address(),
+                        curves: animation.curve_buffer.device_address(),
+                        scene_params: scene_params_buf.device_address(),
+                        base_curve_index: range.start,
+                        curve_count: range.count,
+                        width: stroke_width,
+                        filter_width: self.overlay_filter_width,
+                        tolerance: ribbon_tolerance,
+                        cull_distance: self.cull_distance,
+                        collect_stats: 0,
+                        stats: culling_stats,
+                        tint: ghost.tint,
+                        opacity: ghost.opacity,
+                        depth_pyramid,
+                        depth_pyramid_levels,
+                    });
+                    encoder.draw_mesh_tasks(range.count.div_ceil(SUBGROUP_SIZE), 1, 1);
+                }
+                encoder.push_constants(&DrawRibbonsPushConstants {
+                    control_points,
+                    curves: animation.curve_buffer.device_address(),
+                    scene_params: scene_params_buf.device_address(),
+                    base_curve_index,
+                    curve_count,
+                    width: stroke_width,
+                    filter_width: self.overlay_filter_width,
+                    tolerance: ribbon_tolerance,
+                    cull_distance: self.cull_distance,
+                    collect_stats: self.culling_stats.enabled as u32,
+                    stats: culling_stats,
+                    tint: glam::Vec4::ZERO,
+                    opacity: 1.0,
+                    depth_pyramid,
+                    depth_pyramid_levels,
+                });
+                encoder.draw_mesh_tasks(curve_count.div_ceil(SUBGROUP_SIZE), 1, 1);
+                encoder.finish();
+            }
             _ => {}
         }
 
+        // Meshes and point clouds
+        if !geometry_rendered {
+            self.geometry.render(
+                cmd,
+                engine,
+                animation,
+                self.current_frame,
+                scene_params_buf.device_address(),
+                &color_target_view,
+                &depth_target_view,
+            )?;
+        }
 
-        if self.temporal_average {
+        if temporal_average {
             cmd.reference_resource(&temporal_avg_view);
             cmd.barrier(
                 Barrier::new()
@@ -659,6 +1086,12 @@ impl App {
             });
             let image_view = image.create_top_level_view();
             let sat_image_view = sat_image.create_top_level_view();
+            let (width, height) = (image.width(), image.height());
+            let memory = MemoryTag::new(
+                MemoryCategory::Textures,
+                image_byte_size(Format::R8_SRGB, width, height, graal::mip_level_count(width, height))
+                    + image_byte_size(Format::R32_SFLOAT, width, height, 1),
+            );
             self.brush_textures.push(BrushTexture {
                 name,
                 image,
@@ -666,12 +1099,19 @@ impl App {
                 image_view,
                 sat_image_view,
                 id: self.brush_textures.len() as u32,
+                _memory: memory,
             });
         }
         let _ = self.compute_sats(cmd);
     }
 
     fn load_geo_file(&mut self, path: &Path) {
+        if usd::is_usd_file(path) {
+            // a single USD file holds all the frames
+            let geo_files = load_usd_geo(path);
+            self.finish_geo_load(path, geo_files);
+            return;
+        }
         let file_sequence = match resolve_file_sequence(path) {
             Ok(seq) => seq,
             Err(err) => {
@@ -682,25 +1122,217 @@ impl App {
 
         let mut geo_files = vec![];
         for (frame_index, file_path) in file_sequence {
-            eprint!("Loading: `{}`...", file_path.display());
-            match Geo::load_json(file_path) {
-                Ok(geometry) => {
+            if let Some(importer) = self.plugins.importer_for(&file_path) {
+                match importer.import(&file_path) {
+                    Ok(geometry) => {
+                        geo_files.push(GeoFileData {
+                            index: frame_index,
+                            geometry,
+                        });
+                        eprintln!("Loaded `{}` ({})", file_path.display(), importer.format_name());
+                    }
+                    Err(err) => {
+                        eprintln!("Error loading `{}`: {}", file_path.display(), err);
+                    }
+                }
+                continue;
+            }
+            if let Some(geometry) = load_houdini_geo(&file_path) {
+                geo_files.push(GeoFileData {
+                    index: frame_index,
+                    geometry,
+                });
+            }
+        }
+        self.finish_geo_load(path, geo_files);
+    }
+
+    /// Loads a geometry file, or file sequence, in a background job.
+    ///
+    /// Falls back to loading on the main thread if some files need an importer plug-in,
+    /// since plug-ins are not thread-safe.
+    fn load_geo_file_in_background(&mut self, path: &Path) {
+        if usd::is_usd_file(path) {
+            let name = format!("Loading {}", path.file_name().unwrap_or_default().to_string_lossy());
+            let usd_path = path.to_path_buf();
+            let job = self.jobs.spawn(name, move |_| load_usd_geo(&usd_path));
+            self.pending_geo_load = Some((path.to_path_buf(), job));
+            return;
+        }
+        let file_sequence = match resolve_file_sequence(path) {
+            Ok(seq) => seq,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                return;
+            }
+        };
+        if file_sequence.iter().any(|(_, file_path)| self.plugins.importer_for(file_path).is_some()) {
+            self.load_geo_file(path);
+            return;
+        }
+
+        let name = format!("Loading {}", path.file_name().unwrap_or_default().to_string_lossy());
+        let job = self.jobs.spawn(name, move |ctx| {
+            let mut geo_files = vec![];
+            let file_count = file_sequence.len();
+            for (i, (frame_index, file_path)) in file_sequence.into_iter().enumerate() {
+                if ctx.is_cancelled() {
+                    break;
+                }
+                if let Some(geometry) = load_houdini_geo(&file_path) {
                     geo_files.push(GeoFileData {
                         index: frame_index,
                         geometry,
                     });
-                    eprintln!("OK")
-                }
-                Err(err) => {
-                    eprintln!("Error: {}", err);
                 }
+                ctx.set_progress((i + 1) as f32 / file_count as f32);
+            }
+            geo_files
+        });
+        // replaces (and cancels) the previous load if there's one
+        self.pending_geo_load = Some((path.to_path_buf(), job));
+    }
+
+    /// Checks whether the background geometry load has finished.
+    fn poll_geo_load(&mut self) {
+        let Some((_, job)) = self.pending_geo_load.as_mut() else {
+            return;
+        };
+        match job.poll() {
+            JobStatus::Running => {}
+            JobStatus::Done(geo_files) => {
+                let (path, _) = self.pending_geo_load.take().unwrap();
+                self.finish_geo_load(&path, geo_files);
+            }
+            JobStatus::Cancelled => {
+                eprintln!("Geometry loading cancelled");
+                self.pending_geo_load = None;
+                self.live_reload = false;
+            }
+            JobStatus::Failed(err) => {
+                eprintln!("Error: {}", err);
+                self.pending_geo_load = None;
+                self.live_reload = false;
+            }
+        }
+    }
+
+    /// Reloads the scene in the background when the live link reports that its files were rewritten.
+    ///
+    /// Loads the last written file if there's no scene.
+    fn poll_live_link(&mut self) {
+        let plugins = &self.plugins;
+        let written = self.live_link.poll(|path| {
+            houdinio::is_geo_file(path) || usd::is_usd_file(path) || plugins.importer_for(path).is_some()
+        });
+        let Some(last_written) = written.last() else { return };
+        let scene_path = match self.settings.last_geom_file {
+            Some(ref path) if self.animation.is_some() => path.clone(),
+            _ => {
+                self.load_geo_file_in_background(last_written);
+                return;
+            }
+        };
+
+        let same_file = |a: &Path, b: &Path| {
+            a == b || fs::canonicalize(a).is_ok_and(|a| fs::canonicalize(b).is_ok_and(|b| a == b))
+        };
+        let scene_files = resolve_file_sequence(&scene_path).unwrap_or_default();
+        if written.iter().any(|path| scene_files.iter().any(|(_, file)| same_file(path, file))) {
+            info!("live link: reloading `{}`", scene_path.display());
+            self.live_reload = true;
+            self.load_geo_file_in_background(&scene_path);
+        }
+    }
+
+    /// Opens the import dialog for the specified asset, with the conversion last used for it.
+    fn open_import_dialog(&mut self, path: &Path) {
+        let transform = self.settings.import_transforms.get(path).copied().unwrap_or_default();
+        self.import_dialog = Some((path.to_path_buf(), transform));
+    }
+
+    /// Uploads loaded geometry and makes it the current scene.
+    ///
+    /// The unit and axis conversion saved for `path` is applied to the geometry first.
+    fn finish_geo_load(&mut self, path: &Path, mut geo_files: Vec<GeoFileData>) {
+        if let Some(transform) = self.settings.import_transforms.get(path) {
+            for geo_file in geo_files.iter_mut() {
+                transform.apply(&mut geo_file.geometry);
             }
         }
         self.settings.last_geom_file = Some(path.to_path_buf());
+        self.settings.recent_files.retain(|p| p != path);
+        self.settings.recent_files.insert(0, path.to_path_buf());
+        self.settings.recent_files.truncate(MAX_RECENT_FILES);
         self.settings.save();
         let geoms: Vec<_> = geo_files.into_iter().map(|g| g.geometry).collect();
-        self.animation = Some(load_stroke_animation_data(&self.device, &geoms));
-        self.current_frame = 0;
+        let mut scene = load_stroke_animation_data(&self.device, &geoms);
+        if mem::take(&mut self.live_reload) {
+            // new version of the same scene: stay on the same frame and keep the drawn strokes
+            // (the visibility of the objects is kept by name in `geometry`)
+            if let Some(ref previous) = self.animation {
+                scene.copy_drawn_strokes(previous);
+            }
+            self.current_frame = self.current_frame.min(scene.frames.len().saturating_sub(1));
+        } else {
+            self.current_frame = 0;
+        }
+        self.animation = Some(scene);
+        self.dynamics.clear();
+        // stroke indices refer to the previous scene
+        self.selection = Selection::default();
+    }
+}
+
+impl ScriptHost for App {
+    fn param_names(&self) -> Vec<String> {
+        ANIMATABLE_PARAMS.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn param(&self, name: &str) -> Option<f64> {
+        self.param_value(name)
+    }
+
+    fn set_param(&mut self, name: &str, value: f64) {
+        self.set_param_value(name, value);
+    }
+
+    fn frame(&self) -> usize {
+        self.current_frame
+    }
+
+    fn set_frame(&mut self, frame: usize) {
+        self.scrub_to(frame);
+    }
+
+    fn frame_count(&self) -> usize {
+        App::frame_count(self)
+    }
+
+    fn set_playing(&mut self, playing: bool) {
+        App::set_playing(self, playing);
+    }
+
+    fn load_geometry(&mut self, path: &Path) {
+        self.load_geo_file(path);
+    }
+
+    fn stroke_count(&self) -> usize {
+        self.animation
+            .as_ref()
+            .map_or(0, |anim| anim.frames[self.current_frame].stroke_count as usize)
+    }
+
+    fn selection(&self) -> Vec<u32> {
+        self.selection.selected.iter().copied().collect()
+    }
+
+    fn select(&mut self, op: SelectionOp, strokes: Vec<u32>) {
+        self.selection.apply(op, strokes);
+    }
+
+    fn define(&mut self, name: &str, value: &str) {
+        self.engine.define_global(name, value);
     }
 }
 
@@ -733,8 +1365,35 @@ impl App {
     ///
     /// * `color_target_format` format of the swap chain images
     pub fn new(device: &Device, width: u32, height: u32, color_target_format: Format) -> App {
+        let settings = SavedSettings::load().unwrap_or_default();
+        let plugins = PluginRegistry::load(&plugin_directory());
+        Self::with_settings(device, width, height, color_target_format, settings, plugins)
+    }
+
+    /// Initializes the application with the default settings and without plug-ins, so that it
+    /// renders the same on every machine (see `golden`).
+    pub fn new_headless(device: &Device, width: u32, height: u32, color_target_format: Format) -> App {
+        Self::with_settings(
+            device,
+            width,
+            height,
+            color_target_format,
+            SavedSettings::default(),
+            PluginRegistry::default(),
+        )
+    }
+
+    fn with_settings(
+        device: &Device,
+        width: u32,
+        height: u32,
+        color_target_format: Format,
+        settings: SavedSettings,
+        plugins: PluginRegistry,
+    ) -> App {
         let depth_buffer = create_depth_buffer(device, width, height);
         let depth_buffer_view = depth_buffer.create_top_level_view();
+        let stroke_id_image = create_stroke_id_image(device, 1, 1);
         let camera_control = CameraControl::new(width, height);
         let overlay_renderer = OverlayRenderer::new(device, color_target_format, depth_buffer.format());
         let frame_image = device.create_image(&ImageCreateInfo {
@@ -762,11 +1421,12 @@ impl App {
             samples: 1,
         });
 
-        let drawn_curves = AppendBuffer::new(device, BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu);
-        let drawn_control_points = AppendBuffer::new(device, BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu);
+        let mut drawn_curves = AppendBuffer::new(device, BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu);
+        drawn_curves.set_memory_category(MemoryCategory::Curves);
+        let mut drawn_control_points = AppendBuffer::new(device, BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu);
+        drawn_control_points.set_memory_category(MemoryCategory::Curves);
 
         // load tweaks
-        let settings = SavedSettings::load().unwrap_or_default();
         let mut engine = Engine::new(device.clone());
         let tweaks = settings
             .tweaks
@@ -775,18 +1435,52 @@ impl App {
             .map(|tweak| (tweak.name.clone(), tweak.value.clone()))
             .collect();
         engine.set_global_defines(tweaks);
+        engine.set_transient_budget(settings.transient_budget_mib.map(|mib| mib << 20));
+
+        let mut audio = match AudioPlayer::new() {
+            Ok(audio) => Some(audio),
+            Err(err) => {
+                warn!("failed to open audio output: {err}");
+                None
+            }
+        };
+        if let (Some(audio), Some(path)) = (audio.as_mut(), settings.audio_track.as_ref()) {
+            match AudioTrack::load(path) {
+                Ok(track) => audio.set_track(Some(track)),
+                Err(err) => warn!("failed to load audio track `{}`: {err}", path.display()),
+            }
+        }
 
         let mut app = App {
             device: device.clone(),
             animation: None,
             depth_buffer,
             depth_buffer_view,
+            stroke_id_image,
             color_target_format,
             camera_control,
             overlay: overlay_renderer,
             pipelines: Default::default(),
             bin_rast_stroke_width: 1.0,
             current_frame: 0,
+            playing: false,
+            playback_start: (Instant::now(), 0),
+            fps: 24.0,
+            audio,
+            ribbon_tolerance: 0.25,
+            cull_distance: 0.0,
+            occlusion_culling: false,
+            culling_stats: CullingStatsCollector::new(&device),
+            memory_panel: MemoryPanel::default(),
+            render_target_memory: MemoryTag::new(MemoryCategory::RenderTargets, render_target_byte_size(width, height)),
+            onion_skin: OnionSkin::default(),
+            color: ColorManagement::new(&device, settings.color.clone()),
+            stylize: Stylize::new(settings.stylize.clone()),
+            eco_mode: EcoMode::default(),
+            dynamic_resolution: DynamicResolution::default(),
+            svg_export: SvgExport::default(),
+            workspace: Workspace::new(settings.workspace_layouts.clone()),
+            review: ReviewSession::default(),
             oit_stroke_width: 0.0,
             oit_max_fragments_per_pixel: 0,
             reload_brush_textures: true,
@@ -794,13 +1488,12 @@ impl App {
             selected_brush: 0,
             overlay_line_width: 1.0,
             overlay_filter_width: 1.0,
-            is_drawing: false,
-            last_pos: Default::default(),
-            pen_points: vec![],
+            brush_input: None,
             drawn_curves,
             mode: RenderMode::BinRasterization,
             temporal_average: false,
             temporal_avg_image,
+            upscale_targets: None,
             frame: 0,
             frame_image,
             temporal_average_alpha: 0.25,
@@ -811,7 +1504,6 @@ impl App {
             start_time: Instant::now(),
             tweaks_changed: false,
             draw_origin: Default::default(),
-            fit_tolerance: 1.0,
             curve_embedding_factor: 1.0,
             stroke_bleed_exp: 1.0,
             stroke_color: Color32::from_rgb(129, 212, 250),
@@ -820,26 +1512,119 @@ impl App {
             width_profile: vec4(0.0, 0.8, 0.5, 0.3),
             opacity_profile_pos: vec4(0.0, 0.333, 0.666, 1.0),
             opacity_profile: vec4(1.0, 1.0, 0.7, 0.),
-            opacity_response_curve: Default::default(),
             frame_start_time: Instant::now(),
+            keyframe_editor_param: ANIMATABLE_PARAMS[0],
+            selection: Default::default(),
+            selection_gesture: None,
+            finished_selection_gesture: None,
+            pending_selection: None,
+            cursor_pos: Default::default(),
+            shift_down: false,
+            ctrl_down: false,
+            alt_down: false,
+            selection_set_name: String::new(),
+            selection_offset: Vec3::ZERO,
+            plugins,
+            dynamics: StrandDynamics::new(),
+            jobs: JobSystem::new(2),
+            pending_geo_load: None,
+            pending_svg_export: None,
+            live_reload: false,
+            live_link: LiveLink::new(settings.live_link_directory.clone()),
+            import_dialog: None,
+            geometry: GeometryDisplay::default(),
+            asset_browser: AssetBrowser::new(),
+            scripts: Rc::new(ScriptEngine::new()),
+            console_input: String::new(),
         };
         app.reload_shaders();
+        app.asset_browser.rescan(&mut app.jobs, &app.settings.asset_directories);
         app
     }
 
+    /// Selects the curve rendering mode by name (see `RenderMode::from_name`). Returns false if the name is unknown.
+    pub fn set_render_mode(&mut self, name: &str) -> bool {
+        match RenderMode::from_name(name) {
+            Some(mode) => {
+                self.mode = mode;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the errors of the pipelines that failed to build.
+    pub fn pipeline_errors(&self) -> Vec<Error> {
+        self.engine.pipeline_errors()
+    }
+
+    /// Runs a chain of stock passes (see `StockPass::from_name`) on `image`, and returns the output of the last one.
+    ///
+    /// `image` must have the `STORAGE` usage. Intermediate images have the same format.
+    pub fn run_stock_passes(&mut self, cmd: &mut CommandStream, image: Image, passes: &[String]) -> Result<Image, Error> {
+        let mut input = image;
+        for name in passes {
+            let pass = StockPass::from_name(name).ok_or_else(|| Error::ResourceNotFound(format!("stock pass `{name}`")))?;
+            let (width, height) = match pass {
+                StockPass::Downsample => (input.width().div_ceil(2), input.height().div_ceil(2)),
+                _ => (input.width(), input.height()),
+            };
+            let bytes = image_byte_size(input.format(), width, height, 1);
+            self.engine.use_transient(name, "output image", bytes)?;
+            let output = cmd.device().create_image(&ImageCreateInfo {
+                memory_location: MemoryLocation::GpuOnly,
+                type_: ImageType::Image2D,
+                usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+                format: input.format(),
+                width,
+                height,
+                depth: 1,
+                mip_levels: 1,
+                array_layers: 1,
+                samples: 1,
+            });
+            output.set_name(name);
+            self.engine.run_pass(cmd, pass, &input, &output)?;
+            input = output;
+        }
+        Ok(input)
+    }
+
+    /// Called when the main window gains or loses focus.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.eco_mode.set_focused(focused);
+    }
+
+    /// Returns when the main window should be redrawn next, or `None` to redraw it right away.
+    pub fn next_redraw(&self) -> Option<Instant> {
+        self.eco_mode.next_redraw()
+    }
+
+    /// Whether the temporal average pass runs this frame. It is skipped in eco mode.
+    fn temporal_average_enabled(&self) -> bool {
+        self.temporal_average && !self.eco_mode.is_active()
+    }
+
     /// Called when the main window is resized.
     pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
-        // reallocate the depth buffer
         self.camera_control.resize(width, height);
-        self.depth_buffer = create_depth_buffer(device, width, height);
+        self.allocate_render_targets(device, width, height);
+    }
+
+    /// (Re)allocates the frame-sized images for a window of the specified size, at the resolution
+    /// given by the dynamic resolution scale.
+    fn allocate_render_targets(&mut self, device: &Device, width: u32, height: u32) {
+        let (scene_width, scene_height) = self.dynamic_resolution.scene_size(width, height);
+        self.depth_buffer = create_depth_buffer(device, scene_width, scene_height);
         self.depth_buffer_view = self.depth_buffer.create_top_level_view();
+        self.stroke_id_image = create_stroke_id_image(device, scene_width, scene_height);
         self.temporal_avg_image = device.create_image(&ImageCreateInfo {
             memory_location: MemoryLocation::GpuOnly,
             type_: ImageType::Image2D,
             usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::COLOR_ATTACHMENT,
             format: Format::R16G16B16A16_SFLOAT,
-            width,
-            height,
+            width: scene_width,
+            height: scene_height,
             depth: 1,
             mip_levels: 1,
             array_layers: 1,
@@ -851,143 +1636,458 @@ impl App {
             type_: ImageType::Image2D,
             usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::COLOR_ATTACHMENT,
             format: Format::R16G16B16A16_SFLOAT,
-            width,
-            height,
+            width: scene_width,
+            height: scene_height,
             depth: 1,
             mip_levels: 1,
             array_layers: 1,
             samples: 1,
         });
         self.frame_image.set_name("frame_image");
+        self.upscale_targets =
+            ((scene_width, scene_height) != (width, height)).then(|| UpscaleTargets::new(device, width, height));
+        let upscale_bytes = self.upscale_targets.as_ref().map_or(0, UpscaleTargets::byte_size);
+        self.render_target_memory
+            .set_bytes(render_target_byte_size(scene_width, scene_height) + upscale_bytes);
     }
 
-    pub fn mouse_input(&mut self, button: MouseButton, _pos: DVec2, pressed: bool) {
-        self.camera_control.mouse_input(button, pressed);
+    /// Runs a Lua script (see `script`). Output and errors are shown in the console window.
+    pub fn run_script_file(&mut self, path: &Path) {
+        let scripts = self.scripts.clone();
+        scripts.run_file(path, self);
     }
 
-    pub fn cursor_moved(&mut self, pos: DVec2) {
-        self.camera_control.cursor_moved(pos);
+    /// Returns the current value of an animatable parameter.
+    fn param_value(&self, name: &str) -> Option<f64> {
+        match name {
+            "stroke_width" => Some(self.bin_rast_stroke_width as f64),
+            "stroke_bleed_exp" => Some(self.stroke_bleed_exp as f64),
+            "temporal_average_alpha" => Some(self.temporal_average_alpha as f64),
+            "overlay_line_width" => Some(self.overlay_line_width as f64),
+            "overlay_filter_width" => Some(self.overlay_filter_width as f64),
+            "camera_fov" => Some(self.camera_control.fov_y_radians().to_degrees()),
+            _ => None,
+        }
     }
 
-    pub fn key_input(&mut self, key: &winit::keyboard::Key, pressed: bool) {
-        if *key == winit::keyboard::Key::Named(NamedKey::F5) && pressed {
-            self.reload_shaders();
+    /// Sets the value of an animatable parameter.
+    fn set_param_value(&mut self, name: &str, value: f64) {
+        match name {
+            "stroke_width" => self.bin_rast_stroke_width = value as f32,
+            "stroke_bleed_exp" => self.stroke_bleed_exp = value as f32,
+            "temporal_average_alpha" => self.temporal_average_alpha = value as f32,
+            "overlay_line_width" => self.overlay_line_width = value as f32,
+            "overlay_filter_width" => self.overlay_filter_width = value as f32,
+            "camera_fov" => self.camera_control.set_fov_y_radians(value.to_radians()),
+            _ => warn!("unknown animated parameter: {name}"),
         }
     }
 
-    pub fn add_stroke(&mut self) {
-        let camera = self.camera_control.camera();
+    /// Returns the timeline position of the given frame.
+    fn frame_time(&self, frame: usize) -> Duration {
+        Duration::from_secs_f64(frame as f64 / self.fps)
+    }
 
-        // project points on screen-aligned plane
-        let Some(first_point) = self.pen_points.first().cloned() else { return };
-        let (eye, dir) = camera.screen_to_world_ray(first_point.position);
-        let ground_plane = Plane::new(dvec3(0.0, 1.0, 0.0), dvec3(0.0, 0.0, 0.0));
+    fn frame_count(&self) -> usize {
+        self.animation.as_ref().map_or(0, |a| a.frames.len())
+    }
 
-        if let Some(ground_pos) = ground_plane.intersect(eye, dir) {
-            let plane = Plane::new(-dir, ground_pos);
-            let proj_points = self
-                .pen_points
-                .iter()
-                .filter_map(|p| {
-                    let (eye, pos) = camera.screen_to_world_ray(p.position);
-                    if let Some(pos) = plane.intersect(eye, pos) {
-                        Some((pos, p.pressure, p.arc_length))
-                    } else {
-                        None
+    fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+        self.playback_start = (Instant::now(), self.current_frame);
+        let time = self.frame_time(self.current_frame);
+        if let Some(ref mut audio) = self.audio {
+            if playing {
+                audio.play(time);
+            } else {
+                audio.pause();
+            }
+        }
+    }
+
+    /// Moves the timeline to the given frame, and plays a short bit of the audio track
+    /// if the timeline is paused.
+    fn scrub_to(&mut self, frame: usize) {
+        self.current_frame = frame.min(self.frame_count().saturating_sub(1));
+        self.playback_start = (Instant::now(), self.current_frame);
+        // scripts that export frames read and render the parameters right after changing frames
+        self.apply_keyframes();
+        // don't carry the velocity of the strands across jumps in the timeline
+        self.dynamics.reset();
+        let time = self.frame_time(self.current_frame);
+        let grain = Duration::from_secs_f64(1.0 / self.fps);
+        if let Some(ref mut audio) = self.audio {
+            if self.playing {
+                audio.play(time);
+            } else {
+                audio.scrub(time, grain);
+            }
+        }
+    }
+
+    /// Advances the timeline during playback.
+    fn update_playback(&mut self) {
+        let frame_count = self.frame_count();
+        if !self.playing || frame_count == 0 {
+            return;
+        }
+        let (start_instant, start_frame) = self.playback_start;
+        let elapsed_frames = (start_instant.elapsed().as_secs_f64() * self.fps) as usize;
+        let frame = start_frame + elapsed_frames;
+        if frame >= frame_count {
+            // loop
+            self.current_frame = 0;
+            self.playback_start = (Instant::now(), 0);
+        } else {
+            self.current_frame = frame;
+        }
+        let time = self.frame_time(self.current_frame);
+        if let Some(ref mut audio) = self.audio {
+            audio.sync(time);
+        }
+    }
+
+    /// Applies the changes from the presenter of the review session, and shares the local camera and
+    /// timeline if presenting.
+    fn sync_review_session(&mut self) {
+        for update in self.review.poll() {
+            match update {
+                SessionUpdate::Camera { eye, center, up, fov_y } => {
+                    self.camera_control.set_look_at(eye, center, up);
+                    self.camera_control.set_fov_y_radians(fov_y);
+                }
+                SessionUpdate::Timeline { frame, playing } => {
+                    if playing != self.playing {
+                        self.set_playing(playing);
+                    }
+                    // during playback, only resync when drifting, to avoid restarting the audio every frame
+                    if (!playing && frame != self.current_frame) || frame.abs_diff(self.current_frame) > 2 {
+                        self.scrub_to(frame);
                     }
-                })
-                .collect::<Vec<_>>();
-
-            /*let width_profile = DVec4::from(lagrange_interpolate_4(
-                [self.width_profile_pos.x as f64, self.width_profile.x as f64],
-                [self.width_profile_pos.y as f64, self.width_profile.y as f64],
-                [self.width_profile_pos.z as f64, self.width_profile.z as f64],
-                [self.width_profile_pos.w as f64, self.width_profile.w as f64],
-            )).as_vec4();
-            let opacity_profile = DVec4::from(lagrange_interpolate_4(
-                [self.opacity_profile_pos.x as f64, self.opacity_profile.x as f64],
-                [self.opacity_profile_pos.y as f64, self.opacity_profile.y as f64],
-                [self.opacity_profile_pos.z as f64, self.opacity_profile.z as f64],
-                [self.opacity_profile_pos.w as f64, self.opacity_profile.w as f64],
-            )).as_vec4();*/
-            let color = *egui::Rgba::from(self.stroke_color).to_array().first_chunk::<4>().unwrap();
-
-            //trace!("projected points: {:#?}", proj_points);
-
-            if let Some(ref mut anim) = self.animation.as_mut() {
-                let base_vertex = anim.stroke_vertex_buffer.len() as u32;
-                let mut arc_length = 0.0;
-                let mut prev_pt = proj_points.first().unwrap().0;
-                for (point, pressure, screen_space_arc_length) in proj_points.iter() {
-                    arc_length += prev_pt.distance(*point) as f32;
-                    let mapped_pressure = self.settings.pressure_response_curve.sample(*pressure);
-                    let mapped_opacity = self.opacity_response_curve.sample(*pressure);
-                    anim.stroke_vertex_buffer.push(StrokeVertex {
-                        pos: point.as_vec3().to_array(),
-                        s: *screen_space_arc_length as f32,
-                        color: [(color[0] * 255.) as u8, (color[1] * 255.) as u8, (color[2] * 255.) as u8, (color[3] * 255.) as u8],
-                        width: (mapped_pressure * 255.) as u8,
-                        opacity: (mapped_opacity * 255.) as u8,
-                    });
-                    prev_pt = *point;
                 }
-                anim.stroke_buffer.push(Stroke {
-                    base_vertex,
-                    vertex_count: proj_points.len() as u32,
-                    brush: self.selected_brush as u8,
-                    arc_length,
-                });
-                anim.frames[0].stroke_count += 1;
             }
         }
+        self.review.share_camera(&self.camera_control);
+        self.review.share_timeline(self.current_frame, self.playing);
     }
 
-    pub fn touch_event(&mut self, touch_event: &winit::event::Touch) {
-        let (x, y): (f64, f64) = touch_event.location.into();
-        if touch_event.phase == TouchPhase::Started {
-            self.pen_points.clear();
-            self.is_drawing = true;
-            self.last_pos = dvec2(x, y);
+    fn load_audio_track(&mut self, path: &Path) {
+        let Some(ref mut audio) = self.audio else {
+            warn!("no audio output available");
+            return;
+        };
+        match AudioTrack::load(path) {
+            Ok(track) => {
+                audio.set_track(Some(track));
+                self.settings.audio_track = Some(path.to_path_buf());
+                self.settings.save();
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+            }
         }
+    }
 
-        if self.is_drawing {
-            let mut pressure = if let Some(force) = touch_event.force {
-                force.normalized()
+    /// Evaluates keyframed parameters at the current frame.
+    fn apply_keyframes(&mut self) {
+        let frame = self.current_frame as f64;
+        let values: Vec<_> = self
+            .settings
+            .animated_params
+            .evaluate(frame)
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        for (name, value) in values {
+            self.set_param_value(&name, value);
+        }
+    }
+
+    pub fn mouse_input(&mut self, button: MouseButton, pos: DVec2, pressed: bool) {
+        if button == MouseButton::Left && self.review.is_annotating() {
+            if pressed {
+                self.review.begin_stroke(pos, self.camera_control.camera().screen_size);
             } else {
-                1.0
-            };
+                self.review.end_stroke();
+            }
+            return;
+        }
+        if button == MouseButton::Right {
+            if pressed {
+                self.begin_selection_gesture();
+            } else {
+                self.end_selection_gesture();
+            }
+        }
+        self.camera_control.mouse_input(button, pressed);
+    }
 
+    pub fn cursor_moved(&mut self, pos: DVec2) {
+        self.cursor_pos = pos;
+        match self.selection_gesture {
+            Some((_, SelectionShape::Marquee(_, ref mut corner))) => *corner = pos,
+            Some((_, SelectionShape::Lasso(ref mut points))) => {
+                if !points.last().is_some_and(|last| last.distance(pos) <= 2.0) {
+                    points.push(pos);
+                }
+            }
+            None => {}
+        }
+        self.review.extend_stroke(pos, self.camera_control.camera().screen_size);
+        self.camera_control.cursor_moved(pos);
+    }
 
-            const PEN_SPACING_THRESHOLD: f64 = 40.0;
+    pub fn key_input(&mut self, key: &winit::keyboard::Key, pressed: bool) {
+        use winit::keyboard::Key;
+        match key {
+            Key::Named(NamedKey::F5) if pressed => self.reload_shaders(),
+            Key::Named(NamedKey::Shift) => self.shift_down = pressed,
+            Key::Named(NamedKey::Control) => self.ctrl_down = pressed,
+            Key::Named(NamedKey::Alt) => self.alt_down = pressed,
+            _ => {}
+        }
+    }
 
-            let pos = dvec2(x, y);
-            let delta = self.last_pos.distance(pos);
-            trace!(
-                "Touch event: {:?} at ({}, {}) with pressure {}; delta = {}",
-                touch_event.phase,
-                x,
-                y,
-                pressure,
-                delta
-            );
+    /// Starts a marquee selection (or a lasso if Alt is held).
+    ///
+    /// Shift adds to the current selection, Ctrl removes from it.
+    fn begin_selection_gesture(&mut self) {
+        let op = if self.shift_down {
+            SelectionOp::Add
+        } else if self.ctrl_down {
+            SelectionOp::Subtract
+        } else {
+            SelectionOp::Replace
+        };
+        let shape = if self.alt_down {
+            SelectionShape::Lasso(vec![self.cursor_pos])
+        } else {
+            SelectionShape::Marquee(self.cursor_pos, self.cursor_pos)
+        };
+        self.selection_gesture = Some((op, shape));
+    }
 
-            if delta >= PEN_SPACING_THRESHOLD + 1.0 {
-                // stabilization
-                let new_pos = dvec2(x, y).lerp(self.last_pos, PEN_SPACING_THRESHOLD / delta);
-                if touch_event.phase != TouchPhase::Ended {
-                    let prev_arc_length = self.pen_points.last().map(|p| p.arc_length).unwrap_or(0.0);
-                    self.pen_points.push(PenSample {
-                        position: new_pos,
-                        pressure,
-                        arc_length: prev_arc_length + new_pos.distance(self.last_pos),
-                    });
+    /// Ends the selection gesture. The strokes under the region are read back from the stroke ID image of the
+    /// next frame (see `resolve_selection`).
+    fn end_selection_gesture(&mut self) {
+        self.finished_selection_gesture = self.selection_gesture.take();
+    }
+
+    /// Copies the stroke IDs under the finished selection gesture, and applies the selection once they have
+    /// been read back.
+    ///
+    /// `width` is the width of the window, in the screen coordinates of the gesture.
+    fn resolve_selection(&mut self, cmd: &mut CommandStream, width: u32) {
+        if let Some((op, shape)) = self.finished_selection_gesture.take() {
+            // only the stroke pass writes stroke IDs: nothing is selected in the other modes
+            if self.mode == RenderMode::CurvesOIT && self.animation.is_some() {
+                // the scene may be rendered at a lower resolution than the window
+                let scale = self.stroke_id_image.width() as f64 / width.max(1) as f64;
+                self.pending_selection = PendingSelection::new(
+                    &self.device,
+                    cmd,
+                    &self.stroke_id_image,
+                    op,
+                    shape,
+                    self.current_frame,
+                    scale,
+                );
+            }
+            if self.pending_selection.is_none() {
+                self.selection.apply(op, []);
+            }
+        }
+
+        let Some(strokes) = self.pending_selection.as_mut().and_then(PendingSelection::poll) else { return };
+        let pending = self.pending_selection.take().unwrap();
+        // the IDs are indices of strokes in the frame that was rendered
+        if pending.frame == self.current_frame {
+            self.selection.apply(pending.op, strokes);
+        }
+    }
+
+    /// Waits until the GPU is done with the frames in flight.
+    ///
+    /// Stroke data is modified in place in host-visible memory, which the frames in flight may still be reading.
+    fn wait_for_frames_in_flight(&self) {
+        // SAFETY: the device is only used from this thread
+        if let Err(err) = unsafe { self.device.raw().device_wait_idle() } {
+            error!("failed to wait for the device: {err}");
+        }
+    }
+
+    /// Returns the strokes of the current frame along with their selection weights.
+    fn selection_weights(&self) -> Vec<(Stroke, f32)> {
+        let Some(ref anim) = self.animation else { return vec![] };
+        let weights = self.selection.weights(&anim.stroke_centers(self.current_frame));
+        anim.frame_strokes(self.current_frame).iter().copied().zip(weights).collect()
+    }
+
+    /// Moves the selected strokes, weighted by the soft selection.
+    fn translate_selection(&mut self, offset: Vec3) {
+        let strokes = self.selection_weights();
+        self.wait_for_frames_in_flight();
+        let Some(ref mut anim) = self.animation else { return };
+        for (stroke, weight) in strokes.iter().filter(|(_, w)| *w > 0.0) {
+            for v in anim.stroke_vertices_mut(stroke) {
+                v.pos = (Vec3::from(v.pos) + *weight * offset).to_array();
+            }
+        }
+    }
+
+    /// Paints the selected strokes with the current stroke color, weighted by the soft selection.
+    fn paint_selection(&mut self) {
+        let strokes = self.selection_weights();
+        let color = self.stroke_color.to_array().map(|c| c as f32);
+        self.wait_for_frames_in_flight();
+        let Some(ref mut anim) = self.animation else { return };
+        for (stroke, weight) in strokes.iter().filter(|(_, w)| *w > 0.0) {
+            for v in anim.stroke_vertices_mut(stroke) {
+                for (c, target) in v.color.iter_mut().zip(color) {
+                    *c = (*c as f32 + weight * (target - *c as f32)).round() as u8;
                 }
-                self.last_pos = new_pos;
             }
+        }
+    }
+
+    /// Adds the stroke drawn with the pen to the current frame.
+    ///
+    /// The samples are projected on a plane facing the camera, through the point of the ground plane under
+    /// the first sample, then fitted with curves and tessellated with the current brush.
+    fn add_stroke(&mut self, samples: &[BrushSample]) {
+        let camera = self.camera_control.camera();
+        let Some(first_point) = samples.first() else { return };
+        let (eye, dir) = camera.screen_to_world_ray(first_point.position);
+        let ground_plane = Plane::new(dvec3(0.0, 1.0, 0.0), dvec3(0.0, 0.0, 0.0));
+        let Some(ground_pos) = ground_plane.intersect(eye, dir) else { return };
+        let plane = Plane::new(-dir, ground_pos);
+        let project = |p: DVec2| {
+            let (eye, dir) = camera.screen_to_world_ray(p);
+            plane.intersect(eye, dir)
+        };
+        let points: Vec<DVec4> = samples
+            .iter()
+            .filter_map(|s| project(s.position).map(|pos| pos.extend(s.pressure)))
+            .collect();
+        if points.is_empty() {
+            return;
+        }
+
+        // smoothing tolerance in world units, from the size of a pixel on the plane
+        let pixel_size = project(first_point.position + dvec2(1.0, 0.0)).map_or(0.0, |p| p.distance(ground_pos));
+        let control_points = fit_stroke(&points, self.settings.brush.smoothing * pixel_size);
+        let color = egui::Rgba::from(self.stroke_color).to_array().map(|c| (c * 255.0) as u8);
+        let (vertices, arc_length) = tessellate(&control_points, &self.settings.brush, color);
+
+        let Some(ref mut anim) = self.animation else { return };
+        // drawn strokes are identified by their index
+        let curve_id = anim.stroke_buffer.len() as u32;
+        let stroke = Stroke {
+            base_vertex: 0,
+            vertex_count: 0,
+            brush: self.selected_brush as u8,
+            arc_length,
+            curve_id,
+            seed: curve_seed(curve_id),
+        };
+        let Some(stroke_index) = anim.push_stroke(self.current_frame, &vertices, stroke) else {
+            warn!("strokes can only be drawn on the last frame");
+            return;
+        };
+        anim.drawn_strokes.push(DrawnStroke {
+            stroke_index,
+            control_points,
+            brush: self.settings.brush.clone(),
+            color,
+        });
+    }
+
+    /// Exports the strokes of the current frame to SVG, as seen from the viewport camera.
+    fn export_svg(&mut self) {
+        if self.animation.is_none() {
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new().add_filter("SVG", &["svg"]).save_file() else {
+            return;
+        };
+        // the UI may have changed the frame or the keys since the last render
+        self.apply_keyframes();
+        let Some(ref anim) = self.animation else { return };
+        let settings = self.svg_export;
+        let strokes = settings.collect_strokes(anim, self.current_frame, &self.selection.selected);
+        let camera = self.camera_control.camera();
+        let brush_names: Vec<String> = self.brush_textures.iter().map(|brush| brush.name.clone()).collect();
+        let name = format!("Exporting {}", path.file_name().unwrap_or_default().to_string_lossy());
+        let job = self.jobs.spawn(name, move |_| {
+            settings.export(&path, &strokes, &camera, &brush_names).map_err(|err| err.to_string())
+        });
+        self.pending_svg_export = Some((path, job));
+    }
+
+    /// Checks whether the background SVG export has finished.
+    fn poll_svg_export(&mut self) {
+        let Some((path, job)) = self.pending_svg_export.as_mut() else {
+            return;
+        };
+        match job.poll() {
+            JobStatus::Running => return,
+            JobStatus::Done(Ok(())) => info!("exported strokes to {}", path.display()),
+            JobStatus::Done(Err(err)) | JobStatus::Failed(err) => {
+                error!("failed to export SVG to {}: {err}", path.display())
+            }
+            JobStatus::Cancelled => info!("SVG export to {} cancelled", path.display()),
+        }
+        self.pending_svg_export = None;
+    }
 
-            if touch_event.phase == TouchPhase::Ended {
-                self.is_drawing = false;
-                self.add_stroke();
+    /// Tessellates the selected drawn strokes again with the current brush settings.
+    fn apply_brush_to_selection(&mut self) {
+        self.wait_for_frames_in_flight();
+        let Some(ref mut anim) = self.animation else { return };
+        let frame = &anim.frames[self.current_frame];
+        let frame_strokes = frame.stroke_offset..frame.stroke_offset + frame.stroke_count;
+        let mut drawn_strokes = mem::take(&mut anim.drawn_strokes);
+        for drawn in drawn_strokes.iter_mut() {
+            // the selection holds indices of strokes in the frame
+            if !frame_strokes.contains(&drawn.stroke_index)
+                || !self.selection.selected.contains(&(drawn.stroke_index - frame_strokes.start))
+            {
+                continue;
+            }
+            drawn.brush = self.settings.brush.clone();
+            let (vertices, arc_length) = tessellate(&drawn.control_points, &drawn.brush, drawn.color);
+            anim.replace_stroke_vertices(drawn.stroke_index, &vertices, arc_length);
+        }
+        anim.drawn_strokes = drawn_strokes;
+    }
+
+    pub fn touch_event(&mut self, touch_event: &winit::event::Touch) {
+        let (x, y): (f64, f64) = touch_event.location.into();
+        let pos = dvec2(x, y);
+        let pressure = touch_event.force.map_or(1.0, |force| force.normalized());
+        trace!("Touch event: {:?} at ({x}, {y}) with pressure {pressure}", touch_event.phase);
+        match touch_event.phase {
+            TouchPhase::Started => {
+                // the jitter depends on the frame and the number of strokes already drawn on it
+                let stroke_count = self
+                    .animation
+                    .as_ref()
+                    .map_or(0, |anim| anim.frame_strokes(self.current_frame).len());
+                let rng = self.settings.seeds.rng(self.current_frame, stroke_count as u32);
+                self.brush_input = Some(BrushInput::new(pos, pressure, rng));
+            }
+            TouchPhase::Moved => {
+                if let Some(ref mut input) = self.brush_input {
+                    input.add(&self.settings.brush, pos, pressure);
+                }
+            }
+            TouchPhase::Ended => {
+                if let Some(mut input) = self.brush_input.take() {
+                    input.add(&self.settings.brush, pos, pressure);
+                    self.add_stroke(&input.finish());
+                }
+            }
+            TouchPhase::Cancelled => {
+                self.brush_input = None;
             }
         }
     }
@@ -1010,8 +2110,41 @@ impl App {
         self.overlay.cone(vec3(0.0, 0.0, 0.95), vec3(0.0, 0.0, 1.0), 0.02, blue, blue);
 
         let camera = self.camera_control.camera();
-        let pen_line = self.pen_points.iter().map(|p| p.position).collect::<Vec<_>>();
-        self.overlay.screen_polyline(&camera, pen_line.as_slice(), [255, 128, 0, 255]);
+        if let Some(ref input) = self.brush_input {
+            let pen_line = input.samples().iter().map(|p| p.position).collect::<Vec<_>>();
+            self.overlay.screen_polyline(&camera, pen_line.as_slice(), [255, 128, 0, 255]);
+        }
+    }
+
+    /// Draws the selection gesture and highlights the selected strokes.
+    fn draw_selection(&mut self) {
+        let camera = self.camera_control.camera();
+        if let Some((_, ref shape)) = self.selection_gesture {
+            self.overlay.screen_polyline(&camera, &shape.outline(), [255, 255, 255, 255]);
+        }
+        if self.selection.is_empty() {
+            return;
+        }
+        let strokes = self.selection_weights();
+        let Some(ref anim) = self.animation else { return };
+        for (stroke, weight) in strokes.iter().filter(|(_, w)| *w > 0.0) {
+            // full selection in orange, soft selection fading to blue
+            let color = [255, (128.0 + 127.0 * (1.0 - weight)) as u8, ((1.0 - weight) * 255.0) as u8, 255];
+            for w in anim.stroke_vertices(stroke).windows(2) {
+                let (a, b) = (Vec3::from(w[0].pos).as_dvec3(), Vec3::from(w[1].pos).as_dvec3());
+                self.overlay.line(a, b, color, color);
+            }
+        }
+    }
+
+    /// Draws the bounding boxes of the volumes in the current frame.
+    fn draw_volume_bounds(&mut self) {
+        let Some(anim_frame) = self.animation.as_ref().and_then(|a| a.frames.get(self.current_frame)) else {
+            return;
+        };
+        for bounds in anim_frame.volume_bounds.iter() {
+            self.overlay.aabb(bounds, [80, 160, 255, 255]);
+        }
     }
 
     pub fn render(&mut self, cmd: &mut CommandStream, image: &Image) {
@@ -1031,16 +2164,67 @@ impl App {
 
         let width = image.width();
         let height = image.height();
+        // the scene is rendered at a lower resolution under load
+        let (scene_width, scene_height) = self.dynamic_resolution.scene_size(width, height);
+        if (self.frame_image.width(), self.frame_image.height()) != (scene_width, scene_height) {
+            let device = self.device.clone();
+            self.allocate_render_targets(&device, width, height);
+        }
 
-        self.setup(cmd, self.frame_image.clone(), width, height);
+        self.update_playback();
+        self.sync_review_session();
+        self.apply_keyframes();
+        self.engine.begin_frame();
+        // pipeline and transient budget errors are shown in the UI
+        let _ = self.setup(cmd, self.frame_image.clone(), scene_width, scene_height);
+        self.resolve_selection(cmd, width);
+        // thumbnail errors are shown in the asset browser
+        let _ = self.asset_browser.render_thumbnails(cmd, &mut self.engine);
 
         let color_target_view = self.frame_image.create_top_level_view();
+
+        // Custom render passes
+        let pass_ctx = RenderPassContext {
+            camera: self.camera_control.camera(),
+            color_target: &self.frame_image,
+            color_target_view: &color_target_view,
+            depth_target: &self.depth_buffer_view,
+            width: scene_width,
+            height: scene_height,
+            frame: self.current_frame,
+        };
+        for pass in self.plugins.render_passes.iter_mut() {
+            let name = pass.name().to_string();
+            cmd.debug_group(&name, |cmd| pass.record(cmd, &mut self.engine, &pass_ctx));
+        }
+
+        if !self.eco_mode.is_active() {
+            cmd.debug_group("Stylize", |cmd| {
+                // pipeline errors are shown in the UI
+                let _ = self.stylize.apply(
+                    cmd,
+                    &mut self.engine,
+                    &self.camera_control.camera(),
+                    &self.frame_image,
+                    &self.depth_buffer,
+                    self.settings.seeds.base_seed(),
+                );
+            });
+        }
+
         self.draw_axes();
+        self.draw_volume_bounds();
+        self.draw_selection();
+        if let Some(ref animation) = self.animation {
+            self.geometry.draw_normals(&mut self.overlay, animation, self.current_frame);
+            self.geometry.draw_bounds(&mut self.overlay, animation, self.current_frame);
+        }
 
         let camera = self.camera_control.camera();
-        if self.is_drawing {
+        self.review.draw(&mut self.overlay, &camera);
+        if let Some(ref input) = self.brush_input {
             // draw a cross at the touch point
-            let (x, y) = (self.last_pos.x, self.last_pos.y);
+            let DVec2 { x, y } = input.last_position();
             self.overlay.screen_line(
                 &camera,
                 dvec2(x - 50.0, y),
@@ -1057,28 +2241,53 @@ impl App {
             );
         }
 
+        // Upscale the scene to the window resolution, so that the overlay is drawn at native resolution.
+        // The temporal average, if any, was already copied to the frame image.
+        let upscaled = self.upscale_targets.as_ref().map(|targets| {
+            cmd.debug_group("Upscale", |cmd| {
+                blit_scaled(cmd, &self.frame_image, &targets.color, vk::ImageAspectFlags::COLOR, vk::Filter::LINEAR);
+                // depth can't be filtered
+                blit_scaled(cmd, &self.depth_buffer, &targets.depth, vk::ImageAspectFlags::DEPTH, vk::Filter::NEAREST);
+            });
+            (targets.color.clone(), targets.color.create_top_level_view(), &targets.depth_view)
+        });
+        let (overlay_color_target, overlay_depth_target) = match upscaled {
+            Some((_, ref color_view, depth_view)) => (color_view, depth_view),
+            None => (&color_target_view, &self.depth_buffer_view),
+        };
+
         // Draw overlay
         cmd.debug_group("Overlay", |cmd| {
             self.overlay.render(
                 cmd,
                 OverlayRenderParams {
                     camera: self.camera_control.camera(),
-                    color_target: &color_target_view,
-                    depth_target: &self.depth_buffer_view,
+                    color_target: overlay_color_target,
+                    depth_target: overlay_depth_target,
                     line_width: self.overlay_line_width,
                     filter_width: self.overlay_filter_width,
                 },
             );
         });
 
+        let final_image = if let Some((color, _, _)) = upscaled {
+            color
+        } else if self.temporal_average_enabled() {
+            self.temporal_avg_image.clone()
+        } else {
+            self.frame_image.clone()
+        };
+        let mut display_image = None;
+        cmd.debug_group("Display transform", |cmd| {
+            // pipeline errors are shown in the UI
+            display_image = self.color.apply(cmd, &mut self.engine, &final_image).ok().flatten();
+        });
+        let display_image = display_image.unwrap_or(final_image);
+
         // blit next frame to screen
         cmd.debug_group("blit final frame", |cmd| {
             cmd.blit_image(
-                if self.temporal_average {
-                    &self.temporal_avg_image
-                } else {
-                    &self.frame_image
-                },
+                &display_image,
                 ImageSubresourceLayers {
                     aspect_mask: vk::ImageAspectFlags::COLOR,
                     mip_level: 0,
@@ -1113,17 +2322,24 @@ impl App {
         });
 
         self.frame += 1;
+        self.eco_mode.frame_rendered();
+        self.dynamic_resolution.frame_rendered(self.eco_mode.is_active());
     }
 
     pub fn egui(&mut self, ctx: &egui::Context) {
         // why does `egui::Context` need Send+Sync?
         let dt = ctx.input(|input| input.unstable_dt);
+        self.jobs.poll();
+        self.poll_geo_load();
+        self.poll_svg_export();
+        self.poll_live_link();
+        self.workspace.handle_shortcuts(ctx);
 
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             let reload_shortcut = egui::KeyboardShortcut::new(Modifiers::CTRL | Modifiers::SHIFT, Key::O);
             if ui.input_mut(|input| input.consume_shortcut(&reload_shortcut)) {
                 if let Some(path) = self.settings.last_geom_file.clone() {
-                    self.load_geo_file(&path);
+                    self.load_geo_file_in_background(&path);
                 }
             }
 
@@ -1131,21 +2347,78 @@ impl App {
                 ui.menu_button("File", |ui| {
                     if ui.button("Load .geo...").clicked() {
                         use rfd::FileDialog;
-                        let file = FileDialog::new().add_filter("Houdini JSON geometry", &["geo"]).pick_file();
+                        let mut dialog = FileDialog::new()
+                            .add_filter("Houdini geometry", &["geo", "bgeo", "gz", "sc"])
+                            .add_filter("USD (text)", usd::USD_EXTENSIONS);
+                        for importer in self.plugins.importers.iter() {
+                            dialog = dialog.add_filter(importer.format_name(), importer.extensions());
+                        }
+                        let file = dialog.pick_file();
                         if let Some(ref file) = file {
-                            self.load_geo_file(file);
+                            self.open_import_dialog(file);
                         }
                     }
+                    ui.add_enabled_ui(!self.settings.recent_files.is_empty(), |ui| {
+                        ui.menu_button("Open Recent", |ui| {
+                            for path in self.settings.recent_files.clone() {
+                                if ui.button(path.display().to_string()).clicked() {
+                                    self.load_geo_file_in_background(&path);
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    });
+                    if ui.button("Load audio track...").clicked() {
+                        use rfd::FileDialog;
+                        let file = FileDialog::new().add_filter("Audio", &["wav", "flac"]).pick_file();
+                        if let Some(ref file) = file {
+                            self.load_audio_track(file);
+                        }
+                    }
+                    ui.add_enabled_ui(self.animation.is_some(), |ui| {
+                        ui.menu_button("Export SVG", |ui| {
+                            self.svg_export.ui(ui);
+                            if ui.button("Export...").clicked() {
+                                self.export_svg();
+                                ui.close_menu();
+                            }
+                        });
+                    });
+                    ui.menu_button("Live Link", |ui| {
+                        if self.live_link.ui(ui) {
+                            self.settings.live_link_directory = self.live_link.directory.clone();
+                            self.settings.save();
+                        }
+                    });
                     if egui::Button::new("Reload last geometry")
                         .shortcut_text(ui.ctx().format_shortcut(&reload_shortcut))
                         .ui(ui)
                         .clicked()
                     {
                         if let Some(path) = self.settings.last_geom_file.clone() {
-                            self.load_geo_file(&path);
+                            self.load_geo_file_in_background(&path);
                         }
                     }
-                })
+                });
+                ui.menu_button("Workspace", |ui| {
+                    if self.workspace.menu_ui(ui) {
+                        self.settings.workspace_layouts = self.workspace.layouts.clone();
+                        self.settings.save();
+                    }
+                });
+                ui.menu_button("Plug-ins", |ui| {
+                    if self.plugins.plugins().is_empty() {
+                        ui.label("No plug-ins loaded");
+                    }
+                    for plugin in self.plugins.plugins() {
+                        ui.label(format!("{} ({:?})", plugin.name, plugin.capabilities)).on_hover_text(
+                            plugin
+                                .library
+                                .as_ref()
+                                .map_or("built-in".to_string(), |path| path.display().to_string()),
+                        );
+                    }
+                });
             });
         });
 
@@ -1159,7 +2432,7 @@ impl App {
             .collapsible(false)
             .title_bar(false)
             .anchor(Align2::RIGHT_TOP, egui::Vec2::new(-5., 5.))
-            .fixed_size(egui::Vec2::new(200., 40.)) // https://github.com/emilk/egui/issues/498 🤡
+            .fixed_size(egui::Vec2::new(260., 110.)) // https://github.com/emilk/egui/issues/498 🤡
             .show(ctx, |ui| {
                 ui.set_width(ui.available_width());
                 ui.set_height(ui.available_height());
@@ -1172,10 +2445,344 @@ impl App {
                     //ui.label(format!("{} points", point_count));
                     ui.label(format!("{} curves (current frame), {} points (all frames)", curve_count, point_count));
                     ui.label(format!("{} strokes (current frame), {} stroke vertices (all frames)", stroke_count, stroke_vertex_count));
+
+                    let frame = &anim.frames[self.current_frame];
+                    let (mut meshes, mut triangles, mut clouds, mut points) = (0, 0, 0, 0);
+                    for object in frame.objects.iter() {
+                        match object.kind {
+                            SceneObjectKind::Mesh => {
+                                meshes += 1;
+                                triangles += object.count;
+                            }
+                            SceneObjectKind::Points => {
+                                clouds += 1;
+                                points += object.count;
+                            }
+                        }
+                    }
+                    ui.label(format!("{meshes} meshes ({triangles} triangles), {clouds} point clouds ({points} points)"));
+                    if let Some(bounds) = frame.bounds() {
+                        let size = bounds.size();
+                        ui.label(format!("Bounds: {:.3} x {:.3} x {:.3}", size.x, size.y, size.z));
+                    }
+                    let usage = gpu_memory::usage();
+                    let scene_bytes = usage[MemoryCategory::Curves as usize].bytes + usage[MemoryCategory::Geometry as usize].bytes;
+                    ui.label(format!("Scene memory: {}", gpu_memory::format_bytes(scene_bytes)));
+                }
+            });
+
+        // Shader error overlay. Pipelines that failed to build are retried when their sources change,
+        // so the overlay disappears by itself once the errors are fixed.
+        let pipeline_errors = self.engine.pipeline_errors();
+        if !pipeline_errors.is_empty() {
+            egui::Area::new(egui::Id::new("shader_errors"))
+                .anchor(Align2::LEFT_BOTTOM, egui::Vec2::new(5., -5.))
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style())
+                        .fill(egui::Color32::from_black_alpha(220))
+                        .show(ui, |ui| {
+                            ui.set_max_width(ctx.screen_rect().width() * 0.6);
+                            egui::ScrollArea::vertical().max_height(ctx.screen_rect().height() * 0.5).show(ui, |ui| {
+                                for err in pipeline_errors.iter() {
+                                    match err {
+                                        Error::Pipeline { pipeline, stage, .. } => {
+                                            ui.strong(format!("{pipeline} ({stage})"));
+                                        }
+                                        _ => {
+                                            ui.strong("Pipeline");
+                                        }
+                                    }
+                                    let diagnostics = err.diagnostics();
+                                    if diagnostics.is_empty() {
+                                        ui.label(err.to_string());
+                                    }
+                                    for d in diagnostics {
+                                        let color = if d.is_error { egui::Color32::LIGHT_RED } else { egui::Color32::YELLOW };
+                                        ui.colored_label(color, d.to_string());
+                                        for (n, text) in d.excerpt.iter() {
+                                            let line = format!("{n:>5} | {text}");
+                                            if Some(*n) == d.line {
+                                                ui.label(egui::RichText::new(line).monospace().color(color));
+                                            } else {
+                                                ui.label(egui::RichText::new(line).monospace().weak());
+                                            }
+                                        }
+                                    }
+                                    ui.separator();
+                                }
+                            });
+                            if ui.button("Retry").clicked() {
+                                self.engine.clear_pipeline_cache();
+                            }
+                        });
+                });
+        }
+
+        if self.jobs.is_busy() {
+            egui::Window::new("Background Jobs")
+                .anchor(Align2::RIGHT_BOTTOM, [-8.0, -8.0])
+                .resizable(false)
+                .show(ctx, |ui| {
+                    self.jobs.ui(ui);
+                });
+        }
+
+        let mut open = self.workspace.is_visible("Assets");
+        let response = self.workspace.window("Assets", &mut open).default_open(false).show(ctx, |ui| {
+            let plugins = &self.plugins;
+            let directory_count = self.settings.asset_directories.len();
+            let to_load = self.asset_browser.ui(ui, &mut self.jobs, &mut self.settings.asset_directories, |path| {
+                houdinio::is_geo_file(path)
+                    || usd::is_usd_file(path)
+                    || plugins.importer_for(path).is_some()
+            });
+            if self.settings.asset_directories.len() != directory_count {
+                self.settings.save();
+            }
+            if let Some(path) = to_load {
+                self.open_import_dialog(&path);
+            }
+        });
+        self.workspace.panel_shown("Assets", open, response);
+
+        if let Some((path, mut transform)) = self.import_dialog.take() {
+            let mut open = true;
+            let mut import = false;
+            egui::Window::new("Import")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label(path.display().to_string());
+                    transform.ui(ui);
+                    ui.horizontal(|ui| {
+                        import = ui.button("Import").clicked();
+                        if ui.button("Cancel").clicked() {
+                            open = false;
+                        }
+                    });
+                });
+            if import {
+                if transform.is_identity() {
+                    self.settings.import_transforms.remove(&path);
+                } else {
+                    self.settings.import_transforms.insert(path.clone(), transform);
+                }
+                self.settings.save();
+                self.load_geo_file_in_background(&path);
+            } else if open {
+                self.import_dialog = Some((path, transform));
+            }
+        }
+
+        let mut open = self.workspace.is_visible("Color Management");
+        let response = self.workspace.window("Color Management", &mut open).default_open(false).show(ctx, |ui| {
+            if self.color.ui(ui, &self.device) {
+                self.settings.color = self.color.settings.clone();
+                self.settings.save();
+            }
+        });
+        self.workspace.panel_shown("Color Management", open, response);
+
+        let mut open = self.workspace.is_visible("Stylization");
+        let response = self.workspace.window("Stylization", &mut open).default_open(false).show(ctx, |ui| {
+            if self.stylize.ui(ui) {
+                self.settings.stylize = self.stylize.settings.clone();
+                self.settings.save();
+            }
+        });
+        self.workspace.panel_shown("Stylization", open, response);
+
+        let mut open = self.workspace.is_visible("Review Session");
+        let response = self.workspace.window("Review Session", &mut open).default_open(false).show(ctx, |ui| {
+            self.review.ui(ui);
+        });
+        self.workspace.panel_shown("Review Session", open, response);
+
+        let mut open = self.workspace.is_visible("Culling");
+        let response = self.workspace.window("Culling", &mut open).default_open(false).show(ctx, |ui| {
+            self.culling_stats.ui(ui);
+        });
+        self.workspace.panel_shown("Culling", open, response);
+
+        self.memory_panel.update();
+        let mut open = self.workspace.is_visible("GPU Memory");
+        let response = self.workspace.window("GPU Memory", &mut open).default_open(false).show(ctx, |ui| {
+            self.memory_panel.ui(ui);
+            ui.separator();
+            let budget = &mut self.settings.transient_budget_mib;
+            if self.memory_panel.transient_ui(ui, self.engine.transient_memory_mut(), budget) {
+                self.engine.set_transient_budget(budget.map(|mib| mib << 20));
+                self.settings.save();
+            }
+        });
+        self.workspace.panel_shown("GPU Memory", open, response);
+
+        let mut open = self.workspace.is_visible("Console");
+        let response = self.workspace.window("Console", &mut open).default_open(false).show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in self.scripts.output().iter() {
+                        match line {
+                            ConsoleLine::Input(cmd) => ui.monospace(format!("> {cmd}")),
+                            ConsoleLine::Output(msg) => ui.monospace(msg),
+                            ConsoleLine::Error(msg) => ui.colored_label(egui::Color32::LIGHT_RED, msg),
+                        };
+                    }
+                });
+            ui.horizontal(|ui| {
+                let response = ui.add(egui::TextEdit::singleline(&mut self.console_input).code_editor().hint_text("Lua"));
+                if response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter)) && !self.console_input.is_empty() {
+                    let command = mem::take(&mut self.console_input);
+                    let scripts = self.scripts.clone();
+                    scripts.run_command(&command, self);
+                    response.request_focus();
+                }
+                if ui.button("Run script...").clicked() {
+                    if let Some(file) = rfd::FileDialog::new().add_filter("Lua script", &["lua"]).pick_file() {
+                        self.run_script_file(&file);
+                    }
+                }
+                if ui.button("Clear").clicked() {
+                    self.scripts.clear_output();
                 }
             });
+        });
+        self.workspace.panel_shown("Console", open, response);
 
-        egui::Window::new("Settings").show(ctx, |ui| {
+        for panel in self.plugins.panels.iter_mut() {
+            egui::Window::new(panel.title()).default_open(false).show(ctx, |ui| panel.ui(ui));
+        }
+
+        let mut open = self.workspace.is_visible("Keyframes");
+        let response = self.workspace.window("Keyframes", &mut open).default_open(false).show(ctx, |ui| {
+            let current_frame = self.current_frame as f64;
+            let frame_range = (0.0, self.animation.as_ref().map_or(0, |a| a.frames.len().saturating_sub(1)) as f64);
+
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Parameter")
+                    .selected_text(self.keyframe_editor_param)
+                    .show_ui(ui, |ui| {
+                        for &name in ANIMATABLE_PARAMS {
+                            let label = if self.settings.animated_params.is_animated(name) {
+                                format!("{name} (animated)")
+                            } else {
+                                name.to_string()
+                            };
+                            ui.selectable_value(&mut self.keyframe_editor_param, name, label);
+                        }
+                    });
+
+                let name = self.keyframe_editor_param;
+                if ui.button("Set key").on_hover_text("Key the current value at the current frame").clicked() {
+                    if let Some(value) = self.param_value(name) {
+                        self.settings
+                            .animated_params
+                            .curves
+                            .entry(name.to_string())
+                            .or_default()
+                            .set_key(current_frame, value);
+                    }
+                }
+                if ui.button("Delete key").clicked() {
+                    if let Some(curve) = self.settings.animated_params.curves.get_mut(name) {
+                        curve.remove_key_at(current_frame);
+                        if curve.keys.is_empty() {
+                            self.settings.animated_params.curves.remove(name);
+                        }
+                    }
+                }
+            });
+
+            let name = self.keyframe_editor_param;
+            if let Some(curve) = self.settings.animated_params.curves.get_mut(name) {
+                keyframe_curve_editor(ui, curve, frame_range, current_frame);
+            } else {
+                ui.label("No keys on this parameter.");
+            }
+        });
+        self.workspace.panel_shown("Keyframes", open, response);
+
+        let mut open = self.workspace.is_visible("Selection");
+        let response = self.workspace.window("Selection", &mut open).default_open(false).show(ctx, |ui| {
+            ui.label(format!("{} strokes selected", self.selection.selected.len()))
+                .on_hover_text("Right-drag: marquee, Alt+right-drag: lasso, Shift: add, Ctrl: remove");
+            if ui.button("Clear").clicked() {
+                self.selection.clear();
+            }
+
+            ui.separator();
+            ui.heading("Selection sets");
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.selection_set_name);
+                if ui.add_enabled(!self.selection_set_name.is_empty(), egui::Button::new("Save set")).clicked() {
+                    self.selection.save_set(&self.selection_set_name);
+                }
+            });
+            let mut action = None;
+            for (name, set) in self.selection.sets.iter() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{name} ({})", set.len()));
+                    if ui.small_button("Select").clicked() {
+                        action = Some((name.clone(), Some(SelectionOp::Replace)));
+                    }
+                    if ui.small_button("+").on_hover_text("Add to selection").clicked() {
+                        action = Some((name.clone(), Some(SelectionOp::Add)));
+                    }
+                    if ui.small_button("-").on_hover_text("Remove from selection").clicked() {
+                        action = Some((name.clone(), Some(SelectionOp::Subtract)));
+                    }
+                    if ui.small_button("Delete").clicked() {
+                        action = Some((name.clone(), None));
+                    }
+                });
+            }
+            match action {
+                Some((name, Some(op))) => self.selection.select_set(&name, op),
+                Some((name, None)) => {
+                    self.selection.sets.remove(&name);
+                }
+                None => {}
+            }
+
+            ui.separator();
+            ui.heading("Soft selection");
+            ui.add(Slider::new(&mut self.selection.soft_radius, 0.0..=1.0).text("Radius"));
+            egui::ComboBox::from_label("Falloff")
+                .selected_text(self.selection.falloff.name())
+                .show_ui(ui, |ui| {
+                    for falloff in Falloff::ALL {
+                        ui.selectable_value(&mut self.selection.falloff, falloff, falloff.name());
+                    }
+                });
+
+            ui.separator();
+            ui.heading("Edit");
+            ui.horizontal(|ui| {
+                ui.add(DragValue::new(&mut self.selection_offset.x).speed(0.001).prefix("x: "));
+                ui.add(DragValue::new(&mut self.selection_offset.y).speed(0.001).prefix("y: "));
+                ui.add(DragValue::new(&mut self.selection_offset.z).speed(0.001).prefix("z: "));
+                if ui.button("Move").clicked() {
+                    self.translate_selection(self.selection_offset);
+                }
+            });
+            if ui.button("Paint with stroke color").clicked() {
+                self.paint_selection();
+            }
+            if ui
+                .button("Apply brush")
+                .on_hover_text("Tessellate the selected drawn strokes again with the current brush settings")
+                .clicked()
+            {
+                self.apply_brush_to_selection();
+            }
+        });
+        self.workspace.panel_shown("Selection", open, response);
+
+        let mut open = self.workspace.is_visible("Settings");
+        let response = self.workspace.window("Settings", &mut open).show(ctx, |ui| {
             ui.heading("Temporal average");
             //  ui.checkbox(&mut self.is_drawing, "Drawing mode");
             ui.checkbox(&mut self.temporal_average, "Enable Temporal Average");
@@ -1189,6 +2796,7 @@ impl App {
             ui.radio_value(&mut self.mode, RenderMode::BinRasterization, "Bin Rasterization");
             ui.radio_value(&mut self.mode, RenderMode::CurvesOIT, "Curves OIT");
             ui.radio_value(&mut self.mode, RenderMode::CurvesOITv2, "Curves OIT v2");
+            ui.radio_value(&mut self.mode, RenderMode::GpuRibbons, "GPU Ribbons");
             ui.checkbox(&mut self.debug_tile_line_overflow, "Debug overflowing tiles")
                 .on_hover_text("Show tiles which exceeded the maximum number of lines per tile");
 
@@ -1196,9 +2804,34 @@ impl App {
 
             ui.add(egui::Slider::new(&mut self.bin_rast_stroke_width, 0.1..=256.0).text("Stroke Width"));
             ui.add(egui::Slider::new(&mut self.oit_stroke_width, 0.1..=256.0).text("OIT Stroke Width"));
+            ui.add(egui::Slider::new(&mut self.ribbon_tolerance, 0.05..=4.0).logarithmic(true).text("Ribbon Tolerance (px)"))
+                .on_hover_text("Maximum distance between the curves and the GPU-tessellated ribbons");
+            ui.add(egui::Slider::new(&mut self.cull_distance, 0.0..=100.0).text("Cull Distance"))
+                .on_hover_text("Curve segments farther than this from the camera are not drawn (0: disabled)");
+            ui.checkbox(&mut self.occlusion_culling, "Occlusion Culling")
+                .on_hover_text("Don't draw the curve segments hidden behind meshes (GPU Ribbons only)");
             ui.add(egui::Slider::new(&mut self.overlay_line_width, 0.1..=40.0).text("Overlay Line Width"));
             ui.add(egui::Slider::new(&mut self.overlay_filter_width, 0.01..=10.0).text("Overlay Filter Width"));
 
+            ui.separator();
+            ui.heading("Eco Mode");
+            self.eco_mode.ui(ui);
+
+            ui.separator();
+            ui.heading("Dynamic Resolution");
+            let screen_size = self.camera_control.camera().screen_size;
+            self.dynamic_resolution.ui(ui, screen_size.x as u32, screen_size.y as u32);
+
+            ui.separator();
+            ui.heading("Onion Skinning");
+            self.onion_skin.ui(ui);
+
+            ui.separator();
+            ui.heading("Random Seed");
+            if self.settings.seeds.ui(ui) {
+                self.settings.save();
+            }
+
             ui.separator();
 
             let current_brush = if self.selected_brush < self.brush_textures.len() {
@@ -1216,13 +2849,58 @@ impl App {
 
             ui.separator();
 
+            ui.heading("Strand Dynamics");
+            self.dynamics.ui(ui);
+
+            ui.separator();
+
+            ui.heading("Scene Objects");
+            self.geometry.ui(ui, self.animation.as_ref(), self.current_frame);
+
+            ui.separator();
+
             ui.heading("Animation");
 
             if let Some(ref animation) = self.animation {
-                egui::DragValue::new(&mut self.current_frame)
-                    .clamp_range(0..=(animation.frames.len() - 1))
-                    .custom_formatter(|n, _| format!("Frame {} of {}", n, animation.frames.len()))
-                    .ui(ui);
+                let frame_count = animation.frames.len();
+                let mut frame = self.current_frame;
+                let mut playing = self.playing;
+                ui.horizontal(|ui| {
+                    let icon = if playing { egui_phosphor::fill::PAUSE } else { egui_phosphor::fill::PLAY };
+                    if icon_button(ui, icon, egui::Color32::WHITE).clicked() {
+                        playing = !playing;
+                    }
+                    egui::DragValue::new(&mut frame)
+                        .clamp_range(0..=(frame_count - 1))
+                        .custom_formatter(|n, _| format!("Frame {} of {}", n, frame_count))
+                        .ui(ui);
+                    ui.add(DragValue::new(&mut self.fps).clamp_range(1.0..=120.0).suffix(" fps"));
+                });
+
+                if let Some(track) = self.audio.as_ref().and_then(|audio| audio.track()) {
+                    let duration = self.frame_time(frame_count);
+                    if let Some(time) = timeline_waveform(ui, track, duration, self.frame_time(frame)) {
+                        frame = (time.as_secs_f64() * self.fps) as usize;
+                    }
+                }
+
+                if playing != self.playing {
+                    self.set_playing(playing);
+                }
+                if frame != self.current_frame {
+                    self.scrub_to(frame);
+                }
+            }
+
+            if let Some(ref mut audio) = self.audio {
+                let mut latency_ms = audio.latency.as_millis() as u64;
+                if ui
+                    .add(DragValue::new(&mut latency_ms).clamp_range(0..=500).suffix(" ms"))
+                    .on_hover_text("Audio latency compensation")
+                    .changed()
+                {
+                    audio.latency = Duration::from_millis(latency_ms);
+                }
             }
 
             ui.heading("Global settings");
@@ -1309,7 +2987,6 @@ impl App {
                 self.tweaks_changed = false;
             }
 
-            ui.add(Slider::new(&mut self.fit_tolerance, 1.0..=40.0).text("Curve fit tolerance"));
             ui.add(Slider::new(&mut self.curve_embedding_factor, 1.0..=40.0).text("Curve embedding factor"));
             ui.add(Slider::new(&mut self.stroke_bleed_exp, 1.0..=40.0).text("Stroke bleeding exponent"));
             ui.horizontal(|ui| {
@@ -1347,26 +3024,15 @@ impl App {
                 ui.add(DragValue::new(&mut self.opacity_profile.w).speed(0.1).clamp_range(0.0..=1.0));
             });
 
-            ui.horizontal(|ui| {
-                ui.label("Width response");
-                curve_editor_button(ui, &mut self.settings.pressure_response_curve.knots, &mut self.settings.pressure_response_curve.values);
-                if ui.button("Load default").clicked() {
-                    self.settings.pressure_response_curve = Default::default();
-                }
-            });
-
-            ui.horizontal(|ui| {
-                ui.label("Opacity response");
-                curve_editor_button(ui, &mut self.opacity_response_curve.knots, &mut self.opacity_response_curve.values);
-                if ui.button("Load default").clicked() {
-                    self.opacity_response_curve = Default::default();
-                }
-            });
+            ui.separator();
+            ui.heading("Brush");
+            self.settings.brush.ui(ui);
 
             //ui.add(Slider::new(&mut self.oit_stroke_width, 0.1..=40.0).text("OIT Stroke Width"));
             //ui.add(Slider::new(&mut self.overlay_line_width, 0.1..=40.0).text("Overlay Line Width"));
             //ui.add(Slider::new(&mut self.overlay_filter_width, 0.01..=10.0).text("Overlay Filter Width"));
         });
+        self.workspace.panel_shown("Settings", open, response);
     }
 
     pub fn on_exit(&mut self) {
@@ -0,0 +1,772 @@
+//! Asset browser: geometry files found in the project directories, with thumbnails and metadata.
+//!
+//! Directories are scanned in a background job. The first frame of each asset is loaded in a
+//! background job, and its points are rendered offscreen on the GPU as a thumbnail, seen from a
+//! fixed angle. Thumbnails are cached on disk in the user's cache directory, along with the
+//! metadata, keyed by path and modification time.
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    env, fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use egui::{Align2, Color32, ColorImage, FontId, Sense, TextureHandle, TextureOptions};
+use glam::{Mat3, Mat4, UVec2, Vec3};
+use graal::{prelude::*, util::DeviceExt, vk, Buffer, ImageCopyBuffer, ImageCopyView, ImageDataLayout, RenderPassInfo};
+use houdinio::Geo;
+use regex::Regex;
+
+use crate::{
+    engine::{color_attachment, depth_stencil_attachment, Engine, Error, LoadHint, MeshRenderPipelineDesc},
+    jobs::{JobHandle, JobStatus, JobSystem},
+    scene::SceneFile,
+    shaders::shared::{ControlPoint, DrawGeometryPushConstants, MeshVertex, SceneParams, SUBGROUP_SIZE},
+    usd,
+};
+
+/// Size of the thumbnails, in pixels.
+const THUMBNAIL_SIZE: usize = 96;
+/// Subdirectories deeper than this are not scanned.
+const MAX_SCAN_DEPTH: usize = 4;
+/// Maximum number of points drawn in a thumbnail.
+const MAX_THUMBNAIL_POINTS: usize = 200_000;
+/// Maximum number of thumbnails rendered in a frame.
+const MAX_THUMBNAILS_PER_FRAME: usize = 4;
+/// Number of frames to wait before reading back a thumbnail. Must be larger than the number of frames in flight.
+const READBACK_LATENCY: usize = 4;
+/// Background of the thumbnails (sRGB 24, in linear space).
+const THUMBNAIL_BACKGROUND: f64 = 0.0091;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AssetKind {
+    /// Houdini geometry (`.geo`, `.bgeo`, compressed or not)
+    HoudiniGeo,
+    /// Alembic archive (`.abc`)
+    Alembic,
+    /// Scene produced by `fluff import` (`.fluff.json`)
+    Scene,
+    /// USD text layer (`.usda`), holding all the frames of an animation
+    Usd,
+}
+
+impl AssetKind {
+    fn from_path(path: &Path) -> Option<AssetKind> {
+        let name = path.file_name()?.to_str()?;
+        if name.ends_with(".fluff.json") {
+            Some(AssetKind::Scene)
+        } else if houdinio::is_geo_file(path) {
+            Some(AssetKind::HoudiniGeo)
+        } else if name.ends_with(".abc") {
+            Some(AssetKind::Alembic)
+        } else if usd::is_usd_file(path) {
+            Some(AssetKind::Usd)
+        } else {
+            None
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            AssetKind::HoudiniGeo => "geo",
+            AssetKind::Alembic => "abc",
+            AssetKind::Scene => "fluff",
+            AssetKind::Usd => "usd",
+        }
+    }
+}
+
+/// Metadata of an asset, computed along with the thumbnail.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct AssetInfo {
+    pub point_count: usize,
+    pub curve_count: usize,
+}
+
+/// A file, or file sequence, found in the project directories.
+#[derive(Clone, Debug)]
+struct ScannedAsset {
+    /// First file of the sequence.
+    path: PathBuf,
+    /// File name, with the frame number replaced by `#` for sequences.
+    name: String,
+    kind: AssetKind,
+    /// First and last frame numbers of a file sequence.
+    frame_range: Option<(usize, usize)>,
+    modified: Option<SystemTime>,
+}
+
+/// Thumbnail of a preview job.
+enum PreviewImage {
+    /// Thumbnail loaded from the cache.
+    Cached(ColorImage),
+    /// Points of the first frame, to render the thumbnail from.
+    Points(Vec<ControlPoint>),
+}
+
+/// Result of a preview job.
+struct Preview {
+    info: AssetInfo,
+    image: PreviewImage,
+}
+
+/// Thumbnail being copied back from the GPU.
+struct ThumbnailReadback {
+    buffer: Buffer<[u8]>,
+    frames_left: usize,
+}
+
+struct Asset {
+    scanned: ScannedAsset,
+    info: Option<AssetInfo>,
+    thumbnail: Option<TextureHandle>,
+    error: Option<String>,
+    preview_job: Option<JobHandle<Result<Preview, String>>>,
+    /// Points waiting for the thumbnail to be rendered.
+    points: Option<Vec<ControlPoint>>,
+    readback: Option<ThumbnailReadback>,
+    /// Rendered thumbnail, waiting to be uploaded to egui and cached.
+    rendered: Option<ColorImage>,
+}
+
+/// Collects the asset files in `dir` and its subdirectories.
+fn scan_directory(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth < MAX_SCAN_DEPTH {
+                scan_directory(&path, depth + 1, files);
+            }
+        } else if AssetKind::from_path(&path).is_some() {
+            files.push(path);
+        }
+    }
+}
+
+/// Finds the assets in the specified directories, grouping numbered files into sequences.
+fn scan_assets(directories: &[PathBuf]) -> Vec<ScannedAsset> {
+    let mut files = vec![];
+    for dir in directories {
+        scan_directory(dir, 0, &mut files);
+    }
+
+    // same pattern as `resolve_file_sequence`
+    let re = Regex::new(r"(\D*)(\d+)\.(\w*)").unwrap();
+    let mut assets: BTreeMap<(PathBuf, String), ScannedAsset> = BTreeMap::new();
+    for path in files {
+        let kind = AssetKind::from_path(&path).unwrap();
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        let parent = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let sequence = (!matches!(kind, AssetKind::Scene | AssetKind::Usd))
+            .then(|| re.captures(&file_name))
+            .flatten()
+            .and_then(|c| {
+                let frame = c.get(2).unwrap().as_str().parse::<usize>().ok()?;
+                let name = format!("{}#.{}", c.get(1).unwrap().as_str(), c.get(3).unwrap().as_str());
+                Some((name, frame))
+            });
+        match sequence {
+            Some((name, frame)) => {
+                let asset = assets.entry((parent, name.clone())).or_insert_with(|| ScannedAsset {
+                    path: path.clone(),
+                    name,
+                    kind,
+                    frame_range: Some((frame, frame)),
+                    modified,
+                });
+                let (first, last) = asset.frame_range.unwrap();
+                if frame < first {
+                    asset.path = path.clone();
+                }
+                asset.frame_range = Some((first.min(frame), last.max(frame)));
+                asset.modified = asset.modified.max(modified);
+            }
+            None => {
+                assets.insert(
+                    (parent, file_name.clone()),
+                    ScannedAsset {
+                        path,
+                        name: file_name,
+                        kind,
+                        frame_range: None,
+                        modified,
+                    },
+                );
+            }
+        }
+    }
+    assets.into_values().collect()
+}
+
+/// Returns the directory where thumbnails and metadata are cached, in the user's cache directory.
+fn thumbnail_cache_dir() -> PathBuf {
+    let env_dir = |var: &str| env::var_os(var).filter(|dir| !dir.is_empty()).map(PathBuf::from);
+    let cache_dir = if cfg!(windows) {
+        env_dir("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library/Caches"))
+    } else {
+        env_dir("XDG_CACHE_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".cache")))
+    };
+    cache_dir.unwrap_or_else(env::temp_dir).join("fluff").join("thumbnails")
+}
+
+/// Returns the path of the cached thumbnail (.png) and metadata (.json) of an asset, without extension.
+fn cache_path(asset: &ScannedAsset) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    asset.path.hash(&mut hasher);
+    asset.modified.hash(&mut hasher);
+    thumbnail_cache_dir().join(format!("{:016x}", hasher.finish()))
+}
+
+/// Writes a thumbnail and the metadata of an asset to the cache. Caching is best-effort.
+fn cache_preview(asset: &ScannedAsset, info: &AssetInfo, thumbnail: &ColorImage) {
+    let cache = cache_path(asset);
+    if fs::create_dir_all(thumbnail_cache_dir()).is_ok() {
+        let rgba: Vec<u8> = thumbnail.pixels.iter().flat_map(|c| c.to_array()).collect();
+        let _ = image::save_buffer(
+            cache.with_extension("png"),
+            &rgba,
+            THUMBNAIL_SIZE as u32,
+            THUMBNAIL_SIZE as u32,
+            image::ColorType::Rgba8,
+        );
+        let _ = fs::write(cache.with_extension("json"), serde_json::to_string(info).unwrap());
+    }
+}
+
+/// Returns the camera of a thumbnail: a three-quarter view of the points, with an orthographic projection.
+fn thumbnail_camera(points: &[ControlPoint]) -> SceneParams {
+    let rotation = Mat3::from_rotation_x(-0.35) * Mat3::from_rotation_y(0.6);
+    let (min, max) = points
+        .iter()
+        .map(|p| rotation * Vec3::from(p.pos))
+        .fold((Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)), |(min, max), p| {
+            (min.min(p), max.max(p))
+        });
+    let center = 0.5 * (min + max);
+    // leave a margin around the points
+    let half_extent = 0.5 * (max - min).truncate().max_element().max(1e-6) / 0.9;
+    // the camera looks down -Z: keep all the points between the near and far planes
+    let depth_margin = 1e-3 * (max.z - min.z).max(1e-6);
+    let view = Mat4::from_mat3(rotation);
+    let proj = Mat4::orthographic_rh(
+        center.x - half_extent,
+        center.x + half_extent,
+        center.y - half_extent,
+        center.y + half_extent,
+        -max.z - depth_margin,
+        -min.z + depth_margin,
+    );
+    SceneParams {
+        view,
+        proj,
+        view_proj: proj * view,
+        eye: rotation.transpose() * Vec3::new(center.x, center.y, max.z + 1.0),
+        near_clip: -max.z - depth_margin,
+        far_clip: -min.z + depth_margin,
+        left: 0.0,
+        right: 0.0,
+        top: 0.0,
+        bottom: 0.0,
+        viewport_size: UVec2::splat(THUMBNAIL_SIZE as u32),
+        cursor_pos: Default::default(),
+        time: 0.0,
+        seed: 0,
+    }
+}
+
+/// Renders the points of an asset offscreen, and starts copying the result to host memory.
+fn render_thumbnail(
+    cmd: &mut CommandStream,
+    engine: &mut Engine,
+    points: &[ControlPoint],
+) -> Result<ThumbnailReadback, Error> {
+    let device = cmd.device().clone();
+    let size = THUMBNAIL_SIZE as u32;
+    let pipeline = engine.create_mesh_render_pipeline(
+        "thumbnail_points",
+        MeshRenderPipelineDesc {
+            task_shader: PathBuf::from("crates/fluff/shaders/geometry.glsl"),
+            mesh_shader: PathBuf::from("crates/fluff/shaders/geometry.glsl"),
+            fragment_shader: PathBuf::from("crates/fluff/shaders/geometry.glsl"),
+            defines: [("POINTS".to_string(), "1".to_string())].into(),
+            color_targets: vec![ColorTargetState {
+                format: Format::R8G8B8A8_SRGB,
+                ..Default::default()
+            }],
+            rasterization_state: Default::default(),
+            depth_stencil_state: Some(DepthStencilState {
+                format: Format::D32_SFLOAT,
+                depth_write_enable: true,
+                depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                stencil_state: StencilState::default(),
+            }),
+            multisample_state: Default::default(),
+        },
+    )?;
+
+    let create_target = |format, usage| {
+        device.create_image(&ImageCreateInfo {
+            memory_location: MemoryLocation::GpuOnly,
+            type_: ImageType::Image2D,
+            usage,
+            format,
+            width: size,
+            height: size,
+            depth: 1,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: 1,
+        })
+    };
+    let color = create_target(
+        Format::R8G8B8A8_SRGB,
+        ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+    );
+    color.set_name("thumbnail");
+    let depth = create_target(Format::D32_SFLOAT, ImageUsage::DEPTH_STENCIL_ATTACHMENT);
+    depth.set_name("thumbnail depth");
+
+    let point_buffer = device.upload_array_buffer(BufferUsage::STORAGE_BUFFER, points);
+    let scene_params = device.upload(BufferUsage::STORAGE_BUFFER, &thumbnail_camera(points));
+    // not read when drawing points, but the address must be valid
+    let mesh_vertices =
+        device.create_array_buffer::<MeshVertex>(BufferUsage::STORAGE_BUFFER, MemoryLocation::GpuOnly, 1);
+    cmd.reference_resource(&point_buffer);
+    cmd.reference_resource(&scene_params);
+    cmd.reference_resource(&mesh_vertices);
+
+    let color_view = color.create_top_level_view();
+    let depth_view = depth.create_top_level_view();
+    let mut encoder = cmd.begin_rendering(RenderPassInfo {
+        color_attachments: &[color_attachment(
+            &color_view,
+            LoadHint::Clear([THUMBNAIL_BACKGROUND, THUMBNAIL_BACKGROUND, THUMBNAIL_BACKGROUND, 1.0]),
+        )],
+        depth_stencil_attachment: Some(depth_stencil_attachment(
+            &depth_view,
+            LoadHint::Clear(1.0),
+            LoadHint::Clear(0),
+        )),
+    });
+    encoder.bind_graphics_pipeline(&pipeline);
+    encoder.set_viewport(0.0, 0.0, size as f32, size as f32, 0.0, 1.0);
+    encoder.set_scissor(0, 0, size, size);
+    encoder.push_constants(&DrawGeometryPushConstants {
+        mesh_vertices: mesh_vertices.device_address(),
+        points: point_buffer.device_address(),
+        scene_params: scene_params.device_address(),
+        base_index: 0,
+        count: points.len() as u32,
+        point_size: 1.0,
+        size_attenuation: 0,
+        color_by_normals: 0,
+    });
+    encoder.draw_mesh_tasks((points.len() as u32).div_ceil(SUBGROUP_SIZE), 1, 1);
+    encoder.finish();
+
+    let byte_size = THUMBNAIL_SIZE * THUMBNAIL_SIZE * 4;
+    let buffer = device.create_array_buffer::<u8>(BufferUsage::TRANSFER_DST, MemoryLocation::CpuToGpu, byte_size);
+    buffer.set_name("thumbnail readback");
+    cmd.copy_image_to_buffer(
+        ImageCopyView {
+            image: &color,
+            mip_level: 0,
+            origin: vk::Offset3D { x: 0, y: 0, z: 0 },
+            aspect: vk::ImageAspectFlags::COLOR,
+        },
+        ImageCopyBuffer {
+            buffer: &buffer.untyped,
+            layout: ImageDataLayout {
+                offset: 0,
+                row_length: Some(size),
+                image_height: Some(size),
+            },
+        },
+        vk::Extent3D {
+            width: size,
+            height: size,
+            depth: 1,
+        },
+    );
+    Ok(ThumbnailReadback {
+        buffer,
+        frames_left: READBACK_LATENCY,
+    })
+}
+
+impl ThumbnailReadback {
+    /// Call once per frame. Returns the thumbnail once the GPU has finished the copy.
+    fn poll(&mut self) -> Option<ColorImage> {
+        if self.frames_left > 0 {
+            self.frames_left -= 1;
+            return None;
+        }
+        // SAFETY: the buffer is host-visible, and the GPU has finished the frame that wrote to it
+        // since there are fewer than READBACK_LATENCY frames in flight.
+        let pixels = unsafe { std::slice::from_raw_parts(self.buffer.as_mut_ptr(), THUMBNAIL_SIZE * THUMBNAIL_SIZE * 4) };
+        Some(ColorImage::from_rgba_unmultiplied(
+            [THUMBNAIL_SIZE, THUMBNAIL_SIZE],
+            pixels,
+        ))
+    }
+}
+
+/// Loads the first frame of an asset, and computes its metadata and thumbnail.
+fn preview_asset(asset: &ScannedAsset) -> Result<Preview, String> {
+    let cache = cache_path(asset);
+    let cached_image = cache.with_extension("png");
+    let cached_info = cache.with_extension("json");
+    if let (Ok(image), Ok(info)) = (image::open(&cached_image), fs::read_to_string(&cached_info)) {
+        if let Ok(info) = serde_json::from_str::<AssetInfo>(&info) {
+            let image = image.to_rgba8();
+            let size = [image.width() as usize, image.height() as usize];
+            return Ok(Preview {
+                info,
+                image: PreviewImage::Cached(ColorImage::from_rgba_unmultiplied(size, image.as_raw())),
+            });
+        }
+    }
+
+    let mut info = AssetInfo::default();
+    let mut points = vec![];
+    match asset.kind {
+        AssetKind::HoudiniGeo | AssetKind::Usd => {
+            let geo = if asset.kind == AssetKind::Usd {
+                // thumbnail of the first frame
+                let (frames, _warnings) = usd::load_usd(&asset.path).map_err(|err| err.to_string())?;
+                frames.into_iter().next().unwrap_or_default()
+            } else {
+                let options = houdinio::ParseOptions {
+                    lenient: true,
+                    validation: houdinio::Validation::Repair,
+                    recenter: false,
+                };
+                let (geo, _warnings) = Geo::load_with_options(&asset.path, &options).map_err(|err| err.to_string())?;
+                geo
+            };
+            let colors = geo.color();
+            info.point_count = geo.point_count;
+            for prim in geo.primitives.iter() {
+                if let houdinio::Primitive::BezierRun(run) = prim {
+                    info.curve_count += run.count;
+                }
+            }
+            points = geo
+                .positions()
+                .iter()
+                .enumerate()
+                .map(|(i, p)| ControlPoint {
+                    pos: *p,
+                    color: colors.and_then(|c| c.get(i).copied()).unwrap_or([0.8; 3]),
+                })
+                .collect();
+        }
+        AssetKind::Scene => {
+            let json = fs::read_to_string(&asset.path).map_err(|err| err.to_string())?;
+            let scene: SceneFile = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+            if let Some(frame) = scene.frames.first() {
+                info.curve_count = frame.curves.len();
+                for curve in frame.curves.iter() {
+                    info.point_count += curve.points.len();
+                    for (i, p) in curve.points.iter().enumerate() {
+                        let color = curve
+                            .colors
+                            .as_ref()
+                            .and_then(|c| c.get(i).copied())
+                            .unwrap_or([0.8; 3]);
+                        points.push(ControlPoint { pos: *p, color });
+                    }
+                }
+            }
+        }
+        AssetKind::Alembic => return Err("alembic files are not supported yet".to_string()),
+    }
+
+    if points.len() > MAX_THUMBNAIL_POINTS {
+        let step = points.len().div_ceil(MAX_THUMBNAIL_POINTS);
+        points = points.into_iter().step_by(step).collect();
+    }
+    Ok(Preview {
+        info,
+        image: PreviewImage::Points(points),
+    })
+}
+
+/// Asset browser panel.
+pub struct AssetBrowser {
+    assets: Vec<Asset>,
+    scan_job: Option<JobHandle<Vec<ScannedAsset>>>,
+    /// Filters assets by name.
+    filter: String,
+    /// Asset being dragged to the viewport.
+    dragged: Option<PathBuf>,
+}
+
+impl AssetBrowser {
+    pub fn new() -> AssetBrowser {
+        AssetBrowser {
+            assets: vec![],
+            scan_job: None,
+            filter: String::new(),
+            dragged: None,
+        }
+    }
+
+    /// Rescans the specified directories for assets.
+    pub fn rescan(&mut self, jobs: &mut JobSystem, directories: &[PathBuf]) {
+        let directories = directories.to_vec();
+        self.scan_job = Some(jobs.spawn("Scanning assets", move |_| scan_assets(&directories)));
+    }
+
+    /// Collects the results of the scan and preview jobs.
+    fn poll_jobs(&mut self, ctx: &egui::Context, jobs: &mut JobSystem) {
+        if let Some(scan_job) = self.scan_job.as_mut() {
+            match scan_job.poll() {
+                JobStatus::Running => {}
+                JobStatus::Done(scanned) => {
+                    self.scan_job = None;
+                    self.assets = scanned
+                        .into_iter()
+                        .map(|scanned| {
+                            let job_asset = scanned.clone();
+                            let name = format!("Preview {}", scanned.name);
+                            Asset {
+                                scanned,
+                                info: None,
+                                thumbnail: None,
+                                error: None,
+                                preview_job: Some(jobs.spawn(name, move |_| preview_asset(&job_asset))),
+                                points: None,
+                                readback: None,
+                                rendered: None,
+                            }
+                        })
+                        .collect();
+                }
+                JobStatus::Cancelled | JobStatus::Failed(_) => self.scan_job = None,
+            }
+        }
+
+        for asset in self.assets.iter_mut() {
+            let Some(job) = asset.preview_job.as_mut() else {
+                continue;
+            };
+            match job.poll() {
+                JobStatus::Running => {}
+                JobStatus::Done(Ok(preview)) => {
+                    asset.info = Some(preview.info);
+                    match preview.image {
+                        PreviewImage::Cached(image) => {
+                            asset.thumbnail = Some(ctx.load_texture(
+                                asset.scanned.path.to_string_lossy(),
+                                image,
+                                TextureOptions::LINEAR,
+                            ));
+                        }
+                        // an empty asset has no thumbnail
+                        PreviewImage::Points(points) if !points.is_empty() => asset.points = Some(points),
+                        PreviewImage::Points(_) => {}
+                    }
+                    asset.preview_job = None;
+                }
+                JobStatus::Done(Err(err)) | JobStatus::Failed(err) => {
+                    asset.error = Some(err);
+                    asset.preview_job = None;
+                }
+                JobStatus::Cancelled => asset.preview_job = None,
+            }
+        }
+
+        for asset in self.assets.iter_mut() {
+            let Some(image) = asset.rendered.take() else {
+                continue;
+            };
+            if let Some(ref info) = asset.info {
+                cache_preview(&asset.scanned, info, &image);
+            }
+            asset.thumbnail =
+                Some(ctx.load_texture(asset.scanned.path.to_string_lossy(), image, TextureOptions::LINEAR));
+        }
+    }
+
+    /// Renders the thumbnails of the assets whose points have been loaded, and reads back the
+    /// thumbnails rendered in previous frames. Call once per frame.
+    pub fn render_thumbnails(&mut self, cmd: &mut CommandStream, engine: &mut Engine) -> Result<(), Error> {
+        for asset in self.assets.iter_mut() {
+            let Some(readback) = asset.readback.as_mut() else {
+                continue;
+            };
+            if let Some(image) = readback.poll() {
+                asset.rendered = Some(image);
+                asset.readback = None;
+            }
+        }
+
+        for asset in self
+            .assets
+            .iter_mut()
+            .filter(|asset| asset.points.is_some())
+            .take(MAX_THUMBNAILS_PER_FRAME)
+        {
+            let points = asset.points.take().unwrap();
+            match render_thumbnail(cmd, engine, &points) {
+                Ok(readback) => asset.readback = Some(readback),
+                Err(err) => {
+                    asset.error = Some(err.to_string());
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows the asset browser. Returns the path of the asset to load, if one was opened
+    /// (double-click) or dropped on the viewport.
+    ///
+    /// `directories` is the list of project directories, edited by the panel.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        jobs: &mut JobSystem,
+        directories: &mut Vec<PathBuf>,
+        can_load: impl Fn(&Path) -> bool,
+    ) -> Option<PathBuf> {
+        let ctx = ui.ctx().clone();
+        self.poll_jobs(&ctx, jobs);
+
+        let mut rescan = false;
+        ui.collapsing("Project directories", |ui| {
+            let mut removed = None;
+            for (i, dir) in directories.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui
+                        .small_button(egui_phosphor::regular::X)
+                        .on_hover_text("Remove")
+                        .clicked()
+                    {
+                        removed = Some(i);
+                    }
+                    ui.label(dir.display().to_string());
+                });
+            }
+            if let Some(i) = removed {
+                directories.remove(i);
+                rescan = true;
+            }
+            if ui.button("Add directory...").clicked() {
+                if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                    directories.push(dir);
+                    rescan = true;
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.filter)
+                    .hint_text("Filter")
+                    .desired_width(140.0),
+            );
+            if ui.button("Rescan").clicked() {
+                rescan = true;
+            }
+            if self.scan_job.is_some() {
+                ui.spinner();
+            }
+        });
+        if rescan {
+            self.rescan(jobs, directories);
+        }
+
+        let mut open = None;
+        let filter = self.filter.to_lowercase();
+        egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+            if self.assets.is_empty() && self.scan_job.is_none() {
+                ui.label("No assets found");
+            }
+            for asset in self.assets.iter() {
+                if !filter.is_empty() && !asset.scanned.name.to_lowercase().contains(&filter) {
+                    continue;
+                }
+                ui.horizontal(|ui| {
+                    let size = egui::vec2(THUMBNAIL_SIZE as f32, THUMBNAIL_SIZE as f32);
+                    let (rect, response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+                    let painter = ui.painter_at(rect);
+                    painter.rect_filled(rect, 2.0, Color32::from_gray(24));
+                    if let Some(ref thumbnail) = asset.thumbnail {
+                        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                        painter.image(thumbnail.id(), rect, uv, Color32::WHITE);
+                    } else if asset.preview_job.is_some() || asset.points.is_some() || asset.readback.is_some() {
+                        painter.text(
+                            rect.center(),
+                            Align2::CENTER_CENTER,
+                            "...",
+                            FontId::default(),
+                            Color32::GRAY,
+                        );
+                    }
+
+                    let loadable = can_load(&asset.scanned.path);
+                    if loadable {
+                        if response.double_clicked() {
+                            open = Some(asset.scanned.path.clone());
+                        }
+                        if response.drag_started() {
+                            self.dragged = Some(asset.scanned.path.clone());
+                        }
+                    }
+
+                    ui.vertical(|ui| {
+                        ui.strong(&asset.scanned.name);
+                        ui.label(format!(
+                            "{} · {}",
+                            asset.scanned.kind.label(),
+                            asset.scanned.path.parent().unwrap_or(Path::new("")).display()
+                        ));
+                        if let Some((first, last)) = asset.scanned.frame_range {
+                            ui.label(format!("frames {first}–{last}"));
+                        }
+                        if let Some(ref info) = asset.info {
+                            ui.label(format!("{} points, {} curves", info.point_count, info.curve_count));
+                        }
+                        if let Some(ref err) = asset.error {
+                            ui.colored_label(Color32::from_rgb(230, 120, 100), err);
+                        }
+                        if loadable && ui.small_button("Open").clicked() {
+                            open = Some(asset.scanned.path.clone());
+                        }
+                    });
+                });
+                ui.separator();
+            }
+        });
+
+        // drag to the viewport
+        if let Some(ref dragged) = self.dragged {
+            let pointer = ctx.pointer_interact_pos();
+            if ctx.input(|input| input.pointer.any_released()) {
+                // dropped outside of any egui window: that's the viewport
+                if !ctx.is_pointer_over_area() {
+                    open = Some(dragged.clone());
+                }
+                self.dragged = None;
+            } else if let Some(pos) = pointer {
+                let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Tooltip, egui::Id::new("asset_drag")));
+                let name = dragged.file_name().unwrap_or_default().to_string_lossy();
+                painter.text(
+                    pos + egui::vec2(12.0, 12.0),
+                    Align2::LEFT_TOP,
+                    format!("{} {}", egui_phosphor::regular::FILE, name),
+                    FontId::proportional(12.0),
+                    Color32::WHITE,
+                );
+            }
+        }
+
+        open
+    }
+}
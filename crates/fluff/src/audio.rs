@@ -0,0 +1,212 @@
+//! Audio track playback, synchronized with the timeline.
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
+use tracing::warn;
+
+/// Number of waveform buckets per second of audio.
+const WAVEFORM_BUCKETS_PER_SECOND: usize = 100;
+
+/// If the audio drifts from the timeline by more than this, playback is restarted at the timeline position.
+const MAX_DRIFT: Duration = Duration::from_millis(80);
+
+/// A decoded audio track (WAV or FLAC).
+pub struct AudioTrack {
+    pub path: PathBuf,
+    samples: Arc<[i16]>,
+    channels: u16,
+    sample_rate: u32,
+    /// Peak amplitude (0..1) per waveform bucket.
+    peaks: Vec<f32>,
+}
+
+impl AudioTrack {
+    /// Loads and decodes an audio file.
+    pub fn load(path: &Path) -> Result<AudioTrack, anyhow::Error> {
+        let decoder = rodio::Decoder::new(BufReader::new(File::open(path)?))?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples: Arc<[i16]> = decoder.collect();
+
+        let bucket_len = (sample_rate as usize / WAVEFORM_BUCKETS_PER_SECOND).max(1) * channels as usize;
+        let peaks = samples
+            .chunks(bucket_len)
+            .map(|bucket| bucket.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0) as f32 / i16::MAX as f32)
+            .collect();
+
+        Ok(AudioTrack {
+            path: path.to_path_buf(),
+            samples,
+            channels,
+            sample_rate,
+            peaks,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        let frames = self.samples.len() / self.channels as usize;
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+
+    /// Returns the peak amplitude of the waveform between the two given times.
+    pub fn peak(&self, from: Duration, to: Duration) -> f32 {
+        let a = (from.as_secs_f64() * WAVEFORM_BUCKETS_PER_SECOND as f64) as usize;
+        let b = ((to.as_secs_f64() * WAVEFORM_BUCKETS_PER_SECOND as f64) as usize).max(a + 1);
+        self.peaks
+            .get(a.min(self.peaks.len())..b.min(self.peaks.len()))
+            .map(|peaks| peaks.iter().copied().fold(0.0, f32::max))
+            .unwrap_or(0.0)
+    }
+
+    /// Returns a source that plays the track from `position`, optionally stopping after `length`.
+    fn source(&self, position: Duration, length: Option<Duration>) -> TrackSource {
+        let to_sample = |d: Duration| (d.as_secs_f64() * self.sample_rate as f64) as usize * self.channels as usize;
+        let start = to_sample(position).min(self.samples.len());
+        let end = length.map_or(self.samples.len(), |l| (start + to_sample(l)).min(self.samples.len()));
+        TrackSource {
+            samples: self.samples.clone(),
+            pos: start,
+            end,
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+        }
+    }
+}
+
+/// Source playing a range of samples of an `AudioTrack`.
+struct TrackSource {
+    samples: Arc<[i16]>,
+    pos: usize,
+    end: usize,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl Iterator for TrackSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.pos >= self.end {
+            return None;
+        }
+        let s = self.samples[self.pos];
+        self.pos += 1;
+        Some(s)
+    }
+}
+
+impl Source for TrackSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.end - self.pos)
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Plays an audio track following the timeline.
+///
+/// The timeline is the master clock: the player is told where the timeline is (`play`, `sync`, `scrub`)
+/// and restarts the audio when it drifts too much.
+pub struct AudioPlayer {
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+    sink: Option<Sink>,
+    track: Option<AudioTrack>,
+    /// Output latency compensation: audio is started this much ahead of the timeline.
+    pub latency: Duration,
+    /// Timeline position and instant at which the current playback was started.
+    started: Option<(Duration, Instant)>,
+}
+
+impl AudioPlayer {
+    /// Opens the default audio output device.
+    pub fn new() -> Result<AudioPlayer, anyhow::Error> {
+        let (stream, handle) = OutputStream::try_default()?;
+        Ok(AudioPlayer {
+            _stream: stream,
+            handle,
+            sink: None,
+            track: None,
+            latency: Duration::from_millis(50),
+            started: None,
+        })
+    }
+
+    pub fn track(&self) -> Option<&AudioTrack> {
+        self.track.as_ref()
+    }
+
+    pub fn set_track(&mut self, track: Option<AudioTrack>) {
+        self.stop();
+        self.track = track;
+    }
+
+    fn stop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        self.started = None;
+    }
+
+    fn start(&mut self, time: Duration, length: Option<Duration>) {
+        self.stop();
+        let Some(ref track) = self.track else { return };
+        let sink = match Sink::try_new(&self.handle) {
+            Ok(sink) => sink,
+            Err(err) => {
+                warn!("failed to create audio sink: {err}");
+                return;
+            }
+        };
+        sink.append(track.source(time + self.latency, length));
+        self.sink = Some(sink);
+        if length.is_none() {
+            self.started = Some((time, Instant::now()));
+        }
+    }
+
+    /// Starts playback at the given timeline position.
+    pub fn play(&mut self, time: Duration) {
+        self.start(time, None);
+    }
+
+    pub fn pause(&mut self) {
+        self.stop();
+    }
+
+    /// Call every frame during playback with the current timeline position.
+    pub fn sync(&mut self, time: Duration) {
+        let Some((start_time, start_instant)) = self.started else {
+            self.play(time);
+            return;
+        };
+        let audio_time = start_time + start_instant.elapsed();
+        let drift = if audio_time > time { audio_time - time } else { time - audio_time };
+        if drift > MAX_DRIFT {
+            self.play(time);
+        }
+    }
+
+    /// Plays a short grain of audio at the given position, when the timeline is scrubbed.
+    pub fn scrub(&mut self, time: Duration, grain: Duration) {
+        self.start(time, Some(grain));
+    }
+}
@@ -0,0 +1,271 @@
+//! Brush engine: turns pen input into editable strokes.
+//!
+//! Pen samples are resampled at a fixed spacing along the path of the pen, optionally jittered, then
+//! projected on the drawing plane and fitted with cubic Bézier curves (with curve-fit-nd). The pressure is
+//! fitted as a fourth dimension, so that it's smoothed along with the position. The curves are kept in the
+//! scene (see `DrawnStroke`), and tessellated into polyline strokes whose width and opacity are given by the
+//! pressure transfer curves of the brush. A drawn stroke can be tessellated again with other settings.
+use curve_fit_nd::{curve_fit_cubic_to_points_f64, CalcFlags};
+use glam::{DVec2, DVec3, DVec4, Vec4};
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use uniform_cubic_splines::basis::CatmullRom;
+use uniform_cubic_splines::{spline, spline_inverse};
+
+use crate::shaders::shared::StrokeVertex;
+use crate::ui::curve_editor_button;
+
+/// Pressure error tolerated by the curve fit, relative to the position tolerance.
+const PRESSURE_TOLERANCE: f64 = 0.05;
+/// Number of vertices per Bézier segment when tessellating strokes.
+const SEGMENT_SUBDIVISIONS: usize = 16;
+
+/// A response curve mapping `[0, 1]` to `[0, 1]`, edited with `curve_editor_button`.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct CubicCurve {
+    pub knots: Vec<f64>,
+    pub values: Vec<f64>,
+}
+
+impl CubicCurve {
+    pub fn sample(&self, t: f64) -> f64 {
+        let t = spline_inverse::<CatmullRom, _>(t, &self.knots, None, None).unwrap_or_default();
+        spline::<CatmullRom, _, _>(t, &self.values)
+    }
+}
+
+impl Default for CubicCurve {
+    fn default() -> Self {
+        Self {
+            knots: vec![0.0, 0.0, 0.33, 0.66, 1.0, 1.0],
+            values: vec![0.0, 0.0, 0.33, 0.66, 1.0, 1.0],
+        }
+    }
+}
+
+/// Parameters of the brush used to draw strokes.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BrushSettings {
+    /// Stroke width (as a fraction of the maximum width) as a function of the pressure.
+    pub width_curve: CubicCurve,
+    /// Stroke opacity as a function of the pressure.
+    pub opacity_curve: CubicCurve,
+    /// Distance between samples along the path of the pen, in pixels.
+    pub spacing: f64,
+    /// Maximum random offset of the samples, in pixels.
+    pub jitter: f64,
+    /// Maximum distance between the fitted curves and the samples, in pixels. Higher values smooth more.
+    pub smoothing: f64,
+}
+
+impl Default for BrushSettings {
+    fn default() -> Self {
+        BrushSettings {
+            width_curve: CubicCurve::default(),
+            opacity_curve: CubicCurve::default(),
+            spacing: 4.0,
+            jitter: 0.0,
+            smoothing: 2.0,
+        }
+    }
+}
+
+impl BrushSettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.spacing, 1.0..=40.0).text("Spacing (px)"));
+        ui.add(egui::Slider::new(&mut self.jitter, 0.0..=20.0).text("Jitter (px)"));
+        ui.add(egui::Slider::new(&mut self.smoothing, 0.1..=40.0).text("Smoothing (px)"))
+            .on_hover_text("Maximum distance between the fitted curve and the pen samples");
+        ui.horizontal(|ui| {
+            ui.label("Width response");
+            curve_editor_button(ui, &mut self.width_curve.knots, &mut self.width_curve.values);
+            if ui.button("Load default").clicked() {
+                self.width_curve = Default::default();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Opacity response");
+            curve_editor_button(ui, &mut self.opacity_curve.knots, &mut self.opacity_curve.values);
+            if ui.button("Load default").clicked() {
+                self.opacity_curve = Default::default();
+            }
+        });
+    }
+}
+
+/// A sample of the pen, in screen space.
+#[derive(Copy, Clone, Debug)]
+pub struct BrushSample {
+    pub position: DVec2,
+    /// Normalized pressure.
+    pub pressure: f64,
+}
+
+/// Pen input of a stroke being drawn.
+pub struct BrushInput {
+    /// Resampled (and jittered) samples.
+    samples: Vec<BrushSample>,
+    /// Last raw sample of the pen.
+    last: BrushSample,
+    /// Distance travelled by the pen since the last resampled sample.
+    travelled: f64,
+    /// Random number generator of the jitter.
+    rng: ChaCha8Rng,
+}
+
+impl BrushInput {
+    /// Starts a stroke. `rng` generates the jitter: seed it deterministically (see `SeedSettings::rng`)
+    /// so that the same pen input gives the same stroke.
+    pub fn new(position: DVec2, pressure: f64, rng: ChaCha8Rng) -> BrushInput {
+        let first = BrushSample { position, pressure };
+        BrushInput {
+            samples: vec![first],
+            last: first,
+            travelled: 0.0,
+            rng,
+        }
+    }
+
+    pub fn samples(&self) -> &[BrushSample] {
+        &self.samples
+    }
+
+    /// Returns the last position of the pen.
+    pub fn last_position(&self) -> DVec2 {
+        self.last.position
+    }
+
+    fn push_sample(&mut self, settings: &BrushSettings, mut sample: BrushSample) {
+        if settings.jitter > 0.0 {
+            let rng = &mut self.rng;
+            sample.position += DVec2::new(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0)) * settings.jitter;
+        }
+        self.samples.push(sample);
+    }
+
+    /// Adds a sample of the pen. Samples are emitted every `settings.spacing` pixels along the path, with
+    /// the pressure interpolated between pen samples.
+    pub fn add(&mut self, settings: &BrushSettings, position: DVec2, pressure: f64) {
+        let spacing = settings.spacing.max(0.5);
+        let from = self.last;
+        let length = from.position.distance(position);
+        let mut d = spacing - self.travelled;
+        while d <= length {
+            let t = d / length;
+            self.push_sample(
+                settings,
+                BrushSample {
+                    position: from.position.lerp(position, t),
+                    pressure: from.pressure + (pressure - from.pressure) * t,
+                },
+            );
+            d += spacing;
+        }
+        self.travelled = length - (d - spacing);
+        self.last = BrushSample { position, pressure };
+    }
+
+    /// Ends the stroke at the last position of the pen, and returns the samples.
+    pub fn finish(mut self) -> Vec<BrushSample> {
+        if self.travelled > 0.0 {
+            self.samples.push(self.last);
+        }
+        self.samples
+    }
+}
+
+/// Fits cubic Bézier curves to points with pressure (in `w`).
+///
+/// `tolerance` is the maximum distance between the curves and the points. Returns the control points,
+/// as a sequence of cubic Bézier segments sharing their end points. The points are joined by straight
+/// segments, without smoothing, if `tolerance` isn't positive (e.g. no smoothing, or the size of a pixel
+/// couldn't be computed).
+pub fn fit_stroke(points: &[DVec4], tolerance: f64) -> Vec<Vec4> {
+    match points {
+        [] => return vec![],
+        // a dot
+        [p] => return vec![p.as_vec4(); 4],
+        _ => {}
+    }
+    if tolerance.is_nan() || tolerance <= 0.0 {
+        return linear_segments(points);
+    }
+    // scale the pressure so that the tolerance applies to both
+    let pressure_scale = tolerance / PRESSURE_TOLERANCE;
+    let flat: Vec<f64> = points
+        .iter()
+        .flat_map(|p| [p.x, p.y, p.z, p.w * pressure_scale])
+        .collect();
+    let cubics = match curve_fit_cubic_to_points_f64(&flat, 4, tolerance, CalcFlags::default(), None) {
+        Ok(result) => result.cubic_array,
+        Err(err) => {
+            tracing::warn!("stroke curve fit failed (error {err}), using the samples as control points");
+            return linear_segments(points);
+        }
+    };
+    // knots as [handle_in, knot, handle_out]
+    let knot_point = |i: usize| {
+        let c = &cubics[i * 4..i * 4 + 4];
+        DVec4::new(c[0], c[1], c[2], c[3] / pressure_scale).as_vec4()
+    };
+    let knot_count = cubics.len() / 12;
+    if knot_count < 2 {
+        return linear_segments(points);
+    }
+    let mut control_points = vec![knot_point(1)];
+    for k in 0..knot_count - 1 {
+        control_points.push(knot_point(k * 3 + 2));
+        control_points.push(knot_point((k + 1) * 3));
+        control_points.push(knot_point((k + 1) * 3 + 1));
+    }
+    control_points
+}
+
+/// Straight Bézier segments between consecutive points.
+fn linear_segments(points: &[DVec4]) -> Vec<Vec4> {
+    let mut control_points = vec![points[0].as_vec4()];
+    for w in points.windows(2) {
+        let (a, b) = (w[0].as_vec4(), w[1].as_vec4());
+        control_points.extend([a.lerp(b, 1.0 / 3.0), a.lerp(b, 2.0 / 3.0), b]);
+    }
+    control_points
+}
+
+fn eval_cubic(p: &[Vec4], t: f32) -> Vec4 {
+    let u = 1.0 - t;
+    p[0] * (u * u * u) + p[1] * (3.0 * u * u * t) + p[2] * (3.0 * u * t * t) + p[3] * (t * t * t)
+}
+
+/// Tessellates the curves of a drawn stroke into stroke vertices.
+///
+/// Returns the vertices and the arc length of the stroke.
+pub fn tessellate(control_points: &[Vec4], settings: &BrushSettings, color: [u8; 4]) -> (Vec<StrokeVertex>, f32) {
+    let mut vertices = vec![];
+    let mut arc_length = 0.0;
+    let mut prev: Option<DVec3> = None;
+    let mut emit = |p: Vec4| {
+        let pos = p.truncate().as_dvec3();
+        if let Some(prev) = prev {
+            arc_length += prev.distance(pos) as f32;
+        }
+        prev = Some(pos);
+        let pressure = (p.w as f64).clamp(0.0, 1.0);
+        vertices.push(StrokeVertex {
+            pos: p.truncate().to_array(),
+            s: arc_length,
+            color,
+            width: (settings.width_curve.sample(pressure).clamp(0.0, 1.0) * 255.0) as u8,
+            opacity: (settings.opacity_curve.sample(pressure).clamp(0.0, 1.0) * 255.0) as u8,
+        });
+    };
+    if let Some(&first) = control_points.first() {
+        emit(first);
+    }
+    for segment in control_points.windows(4).step_by(3) {
+        for i in 1..=SEGMENT_SUBDIVISIONS {
+            emit(eval_cubic(segment, i as f32 / SEGMENT_SUBDIVISIONS as f32));
+        }
+    }
+    (vertices, arc_length)
+}
@@ -195,6 +195,29 @@ impl CameraControl {
         self.frame.eye
     }
 
+    /// Returns the vertical field of view, in radians.
+    pub fn fov_y_radians(&self) -> f64 {
+        self.fov_y_radians
+    }
+
+    /// Sets the vertical field of view, in radians.
+    pub fn set_fov_y_radians(&mut self, fov_y_radians: f64) {
+        self.fov_y_radians = fov_y_radians;
+        self.last_cam.set(None);
+    }
+
+    /// Returns the eye position, the target and the up vector.
+    pub fn look_at(&self) -> (DVec3, DVec3, DVec3) {
+        (self.frame.eye, self.frame.center, self.frame.up)
+    }
+
+    /// Sets the eye position, the target and the up vector.
+    pub fn set_look_at(&mut self, eye: DVec3, center: DVec3, up: DVec3) {
+        self.frame = CameraFrame { eye, up, center };
+        self.input_mode = CameraInputMode::None;
+        self.last_cam.set(None);
+    }
+
     fn handle_pan(&mut self, orig: &CameraFrame, delta_screen: glam::DVec2) {
         let delta = delta_screen / self.screen_size;
         let dir = orig.center - orig.eye;
@@ -0,0 +1,362 @@
+//! Color management of the viewport.
+//!
+//! The frame is rendered in a linear working space. A final pass (`display_transform.comp`) converts
+//! it to the display: exposure, conversion to linear Rec.709 primaries, then a view transform (the
+//! sRGB transfer function, or a 3D LUT loaded from a `.cube` file, e.g. the one used in comp).
+use std::{
+    fs, mem,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+use glam::{uvec2, Mat3, Vec3};
+use graal::{prelude::*, util::DeviceExt, Barrier, Buffer};
+
+use crate::{
+    engine::{ComputePipelineDesc, Engine, Error},
+    gpu_memory::{image_byte_size, MemoryCategory, MemoryTag},
+    shaders::shared::{DisplayTransformParams, DISPLAY_TRANSFORM_WORKGROUP_SIZE},
+};
+
+/// Color space in which the frame is rendered.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum WorkingSpace {
+    /// Linear, Rec.709 primaries.
+    #[default]
+    LinearRec709,
+    /// Linear, ACES AP1 primaries.
+    AcesCg,
+}
+
+impl WorkingSpace {
+    fn label(&self) -> &'static str {
+        match self {
+            WorkingSpace::LinearRec709 => "Linear Rec.709",
+            WorkingSpace::AcesCg => "ACEScg",
+        }
+    }
+
+    /// Matrix converting colors in this space to linear Rec.709.
+    fn to_rec709(&self) -> Mat3 {
+        match self {
+            WorkingSpace::LinearRec709 => Mat3::IDENTITY,
+            // AP1 to Rec.709, with Bradford adaptation from D60 to D65. Rows of the matrix.
+            WorkingSpace::AcesCg => Mat3::from_cols_array(&[
+                1.70505, -0.62179, -0.08326, //
+                -0.13026, 1.14080, -0.01055, //
+                -0.02400, -0.12897, 1.15297,
+            ])
+            .transpose(),
+        }
+    }
+}
+
+/// Transform from linear Rec.709 to display values.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ViewTransform {
+    /// Values are sent to the display as is.
+    #[default]
+    Raw,
+    /// sRGB transfer function.
+    Srgb,
+    /// 3D LUT.
+    Lut,
+}
+
+impl ViewTransform {
+    fn label(&self) -> &'static str {
+        match self {
+            ViewTransform::Raw => "Raw",
+            ViewTransform::Srgb => "sRGB",
+            ViewTransform::Lut => "3D LUT",
+        }
+    }
+}
+
+/// Color management settings, saved with the app settings.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ColorSettings {
+    pub working_space: WorkingSpace,
+    pub view: ViewTransform,
+    /// Exposure adjustment, in stops.
+    pub exposure: f32,
+    /// `.cube` file used by the LUT view transform.
+    pub lut_path: Option<PathBuf>,
+}
+
+impl Default for ColorSettings {
+    fn default() -> Self {
+        ColorSettings {
+            working_space: WorkingSpace::default(),
+            view: ViewTransform::default(),
+            exposure: 0.0,
+            lut_path: None,
+        }
+    }
+}
+
+impl ColorSettings {
+    /// Whether the display transform doesn't change the frame.
+    fn is_identity(&self) -> bool {
+        self.working_space == WorkingSpace::LinearRec709 && self.view == ViewTransform::Raw && self.exposure == 0.0
+    }
+}
+
+/// 3D lookup table.
+pub struct Lut3D {
+    /// Number of entries along each axis.
+    pub size: u32,
+    pub domain_min: Vec3,
+    pub domain_max: Vec3,
+    /// Entries, with red varying fastest, then green, then blue.
+    pub data: Vec<Vec3>,
+}
+
+impl Lut3D {
+    /// Loads a 3D LUT in the Resolve/Adobe `.cube` format.
+    pub fn load_cube(path: &Path) -> Result<Lut3D, anyhow::Error> {
+        let text = fs::read_to_string(path).with_context(|| format!("could not read `{}`", path.display()))?;
+        let mut size = 0;
+        let mut domain_min = Vec3::ZERO;
+        let mut domain_max = Vec3::ONE;
+        let mut data = vec![];
+
+        let parse_vec3 = |values: &[&str], line: usize| -> Result<Vec3, anyhow::Error> {
+            let v: Vec<f32> = values
+                .iter()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| anyhow!("line {line}: invalid number"))?;
+            match v[..] {
+                [r, g, b] => Ok(Vec3::new(r, g, b)),
+                _ => bail!("line {line}: expected 3 values, got {}", v.len()),
+            }
+        };
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[0] {
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    size = words
+                        .get(1)
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(|| anyhow!("line {line_number}: invalid LUT size"))?;
+                }
+                "LUT_1D_SIZE" => bail!("1D LUTs are not supported"),
+                "DOMAIN_MIN" => domain_min = parse_vec3(&words[1..], line_number)?,
+                "DOMAIN_MAX" => domain_max = parse_vec3(&words[1..], line_number)?,
+                _ => data.push(parse_vec3(&words, line_number)?),
+            }
+        }
+
+        if size < 2 {
+            bail!("missing or invalid LUT_3D_SIZE");
+        }
+        let expected = (size * size * size) as usize;
+        if data.len() != expected {
+            bail!("expected {expected} LUT entries for size {size}, got {}", data.len());
+        }
+        Ok(Lut3D {
+            size,
+            domain_min,
+            domain_max,
+            data,
+        })
+    }
+}
+
+/// Applies the display transform to the final frame.
+pub struct ColorManagement {
+    pub settings: ColorSettings,
+    lut: Option<Lut3D>,
+    /// Error encountered when loading the LUT.
+    lut_error: Option<String>,
+    /// LUT entries on the GPU. Contains a single entry if no LUT is loaded.
+    lut_buffer: Buffer<[Vec3]>,
+    /// Output of the display transform, reallocated when the frame size changes.
+    display_image: Option<Image>,
+    lut_memory: MemoryTag,
+    display_image_memory: MemoryTag,
+}
+
+impl ColorManagement {
+    pub fn new(device: &Device, settings: ColorSettings) -> ColorManagement {
+        let mut color = ColorManagement {
+            settings,
+            lut: None,
+            lut_error: None,
+            lut_buffer: device.upload_array_buffer(BufferUsage::STORAGE_BUFFER, &[Vec3::ZERO]),
+            display_image: None,
+            lut_memory: MemoryTag::new(MemoryCategory::Textures, mem::size_of::<Vec3>() as u64),
+            display_image_memory: MemoryTag::new(MemoryCategory::RenderTargets, 0),
+        };
+        if let Some(path) = color.settings.lut_path.clone() {
+            color.load_lut(device, &path);
+        }
+        color
+    }
+
+    /// Loads the LUT used by the `Lut` view transform.
+    pub fn load_lut(&mut self, device: &Device, path: &Path) {
+        self.settings.lut_path = Some(path.to_path_buf());
+        match Lut3D::load_cube(path) {
+            Ok(lut) => {
+                self.lut_buffer = device.upload_array_buffer(BufferUsage::STORAGE_BUFFER, &lut.data);
+                self.lut_buffer.set_name("display LUT");
+                self.lut_memory.set_bytes(mem::size_of_val(lut.data.as_slice()) as u64);
+                self.lut = Some(lut);
+                self.lut_error = None;
+            }
+            Err(err) => {
+                self.lut = None;
+                self.lut_error = Some(format!("{err:#}"));
+            }
+        }
+    }
+
+    fn display_image(&mut self, device: &Device, width: u32, height: u32) -> Image {
+        if let Some(ref image) = self.display_image {
+            if image.width() == width && image.height() == height {
+                return image.clone();
+            }
+        }
+        let image = device.create_image(&ImageCreateInfo {
+            memory_location: MemoryLocation::GpuOnly,
+            type_: ImageType::Image2D,
+            usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+            format: Format::R16G16B16A16_SFLOAT,
+            width,
+            height,
+            depth: 1,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: 1,
+        });
+        image.set_name("display_image");
+        self.display_image_memory
+            .set_bytes(image_byte_size(Format::R16G16B16A16_SFLOAT, width, height, 1));
+        self.display_image = Some(image.clone());
+        image
+    }
+
+    /// Applies the display transform to `frame`.
+    ///
+    /// Returns the image to present, or `None` if the transform doesn't change the frame.
+    pub fn apply(
+        &mut self,
+        cmd: &mut CommandStream,
+        engine: &mut Engine,
+        frame: &Image,
+    ) -> Result<Option<Image>, Error> {
+        if self.settings.is_identity() {
+            return Ok(None);
+        }
+        let pipeline = engine.create_compute_pipeline(
+            "display_transform",
+            ComputePipelineDesc {
+                shader: "crates/fluff/shaders/display_transform.comp".into(),
+                defines: Default::default(),
+            },
+        )?;
+
+        let (width, height) = (frame.width(), frame.height());
+        let device = cmd.device().clone();
+        let output = self.display_image(&device, width, height);
+        let input_view = frame.create_top_level_view();
+        let output_view = output.create_top_level_view();
+        cmd.reference_resource(&input_view);
+        cmd.reference_resource(&output_view);
+        cmd.reference_resource(&self.lut_buffer);
+
+        // fall back to the sRGB transfer function if the LUT couldn't be loaded
+        let view = match (self.settings.view, &self.lut) {
+            (ViewTransform::Lut, None) => ViewTransform::Srgb,
+            (view, _) => view,
+        };
+        let (lut_size, domain_min, domain_max) = match self.lut {
+            Some(ref lut) => (lut.size, lut.domain_min, lut.domain_max),
+            None => (1, Vec3::ZERO, Vec3::ONE),
+        };
+
+        cmd.barrier(Barrier::new().shader_read_image(frame).shader_write_image(&output));
+        let mut encoder = cmd.begin_compute();
+        encoder.bind_compute_pipeline(&pipeline);
+        encoder.push_constants(&DisplayTransformParams {
+            viewport_size: uvec2(width, height),
+            input_image: input_view.device_image_handle(),
+            output_image: output_view.device_image_handle(),
+            working_to_rec709: self.settings.working_space.to_rec709(),
+            exposure: self.settings.exposure.exp2(),
+            view: view as u32,
+            lut: self.lut_buffer.device_address(),
+            lut_size,
+            lut_domain_min: domain_min,
+            lut_domain_max: domain_max,
+        });
+        encoder.dispatch(
+            width.div_ceil(DISPLAY_TRANSFORM_WORKGROUP_SIZE),
+            height.div_ceil(DISPLAY_TRANSFORM_WORKGROUP_SIZE),
+            1,
+        );
+        encoder.finish();
+        Ok(Some(output))
+    }
+
+    /// Color management settings panel. Returns true if the settings changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui, device: &Device) -> bool {
+        let before = self.settings.clone();
+        egui::ComboBox::from_label("Working space")
+            .selected_text(self.settings.working_space.label())
+            .show_ui(ui, |ui| {
+                for space in [WorkingSpace::LinearRec709, WorkingSpace::AcesCg] {
+                    ui.selectable_value(&mut self.settings.working_space, space, space.label());
+                }
+            });
+        egui::ComboBox::from_label("View")
+            .selected_text(self.settings.view.label())
+            .show_ui(ui, |ui| {
+                for view in [ViewTransform::Raw, ViewTransform::Srgb, ViewTransform::Lut] {
+                    ui.selectable_value(&mut self.settings.view, view, view.label());
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.settings.exposure, -8.0..=8.0).text("Exposure (stops)"));
+
+        ui.horizontal(|ui| {
+            if ui.button("Load LUT...").clicked() {
+                if let Some(path) = rfd::FileDialog::new().add_filter("Cube LUT", &["cube"]).pick_file() {
+                    self.load_lut(device, &path);
+                    self.settings.view = ViewTransform::Lut;
+                }
+            }
+            match (&self.lut, &self.settings.lut_path) {
+                (Some(lut), Some(path)) => {
+                    ui.label(format!(
+                        "{} ({}³)",
+                        path.file_name().unwrap_or_default().to_string_lossy(),
+                        lut.size
+                    ));
+                }
+                _ => {
+                    ui.label("No LUT loaded");
+                }
+            }
+        });
+        if let Some(ref err) = self.lut_error {
+            ui.colored_label(egui::Color32::LIGHT_RED, err);
+        }
+        if self.settings.view == ViewTransform::Lut && self.lut.is_none() {
+            ui.label("Using the sRGB transfer function until a LUT is loaded");
+        }
+
+        self.settings.working_space != before.working_space
+            || self.settings.view != before.view
+            || self.settings.exposure != before.exposure
+            || self.settings.lut_path != before.lut_path
+    }
+}
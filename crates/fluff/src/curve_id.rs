@@ -0,0 +1,102 @@
+//! Stable per-curve IDs and random seeds.
+//!
+//! Stylization effects that vary per curve (e.g. stochastic transparency) need an identifier that
+//! doesn't change when the curves are reordered, or when the file is exported again. The `id`
+//! primitive attribute is used when the file has one. Otherwise IDs are generated from a hash of the
+//! quantized root position of the curve, and of the number of curves with the same root before it.
+use std::collections::HashMap;
+
+use houdinio::{AttributeStorage, Geo, Primitive};
+
+/// Root positions are quantized to this fraction of a scene unit before hashing,
+/// so that roundoff differences between exports don't change the IDs.
+const ROOT_QUANTIZATION: f32 = 1.0e-4;
+
+/// Finalizer of MurmurHash3: mixes the bits of `x`.
+fn mix32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2_ae35);
+    x ^= x >> 16;
+    x
+}
+
+fn quantize(root: [f32; 3]) -> [i32; 3] {
+    root.map(|c| (c / ROOT_QUANTIZATION).round() as i32)
+}
+
+/// Hashes the quantized root position of a curve. `occurrence` disambiguates curves with the same root.
+fn hash_root(root: [i32; 3], occurrence: u32) -> u32 {
+    let mut h = 0x9e37_79b9;
+    for c in root {
+        h = mix32(h ^ c as u32);
+    }
+    mix32(h ^ occurrence)
+}
+
+/// Returns the random seed of a curve, in `[0, 1)`, derived from its ID.
+pub fn curve_seed(id: u32) -> f32 {
+    // 24 bits so that the result is exactly representable
+    (mix32(id ^ 0x5bd1_e995) >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Returns the value of an integer (or integral float) primitive attribute with one element per primitive.
+fn primitive_id(storage: &AttributeStorage, index: usize) -> Option<u32> {
+    match storage {
+        AttributeStorage::Int32(v) => v.get(index).map(|&x| x as u32),
+        AttributeStorage::Int64(v) => v.get(index).map(|&x| x as u32),
+        AttributeStorage::FpReal32(v) => v.get(index).map(|&x| x as i64 as u32),
+        AttributeStorage::FpReal64(v) => v.get(index).map(|&x| x as i64 as u32),
+    }
+}
+
+/// Returns the ID of each bezier curve of the geometry, in the order of `BezierRun::iter`.
+///
+/// `previous` are the IDs of the curves of the previous frame of an animation: if the frame has the
+/// same number of curves, and no `id` attribute, the curves are assumed to be the same and keep their
+/// IDs, even if their roots moved.
+pub fn curve_ids(geo: &Geo, previous: Option<&[u32]>) -> Vec<u32> {
+    let id_attribute = geo.primitive_attributes.iter().find(|a| a.name == "id" && a.size == 1);
+
+    let mut ids = vec![];
+    let mut roots = vec![];
+    let mut primitive_index = 0;
+    for prim in geo.primitives.iter() {
+        match prim {
+            Primitive::BezierRun(run) => {
+                for (i, curve) in run.iter().enumerate() {
+                    if let Some(id) = id_attribute.and_then(|a| primitive_id(&a.storage, primitive_index + i)) {
+                        ids.push(id);
+                    }
+                    let root = curve.vertices.first().map_or([0.0; 3], |&v| geo.vertex_position(v));
+                    roots.push(root);
+                }
+                primitive_index += run.count;
+            }
+            Primitive::PolygonRun(run) => primitive_index += run.count,
+            Primitive::Volume(_) => primitive_index += 1,
+        }
+    }
+
+    if ids.len() == roots.len() {
+        return ids;
+    }
+    if let Some(previous) = previous {
+        if previous.len() == roots.len() {
+            return previous.to_vec();
+        }
+    }
+
+    let mut occurrences: HashMap<[i32; 3], u32> = HashMap::new();
+    roots
+        .iter()
+        .map(|root| {
+            let quantized = quantize(*root);
+            let occurrence = occurrences.entry(quantized).or_insert(0);
+            let id = hash_root(quantized, *occurrence);
+            *occurrence += 1;
+            id
+        })
+        .collect()
+}
@@ -0,0 +1,70 @@
+//! Deterministic random seeds for stochastic effects.
+//!
+//! Brush jitter and dithering derive their randomness from the seed of the frame, computed from the seed
+//! saved in the settings and the frame number. Rendering a frame, or drawing the same strokes on it, gives
+//! the same result on every machine. Locking the seed uses the same seed for all frames, so that the noise
+//! doesn't change during playback. The paper grain always uses the base seed, since the paper doesn't
+//! change between frames.
+//!
+//! CPU effects use `ChaCha8Rng`, whose output is specified, unlike `StdRng` whose algorithm may change
+//! between versions of `rand`.
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// Seed settings, saved with the application settings.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SeedSettings {
+    /// Seed from which the seeds of the frames are derived.
+    pub seed: u32,
+    /// Use the same seed for all frames.
+    pub lock: bool,
+}
+
+/// Integer hash (from "Hash Functions for GPU Rendering", Jarzynski & Olano).
+pub fn pcg_hash(x: u32) -> u32 {
+    let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+impl SeedSettings {
+    /// Returns the seed of the effects that don't change between frames, whether or not the seed is locked.
+    pub fn base_seed(&self) -> u32 {
+        pcg_hash(self.seed)
+    }
+
+    /// Returns the seed of a frame of the animation.
+    pub fn frame_seed(&self, frame: usize) -> u32 {
+        if self.lock {
+            self.base_seed()
+        } else {
+            pcg_hash(self.seed ^ pcg_hash(frame as u32))
+        }
+    }
+
+    /// Returns a random number generator for an effect computed on the CPU.
+    ///
+    /// `stream` distinguishes the uses of the generator on the same frame (e.g. the index of a stroke),
+    /// so that they don't get the same random numbers.
+    pub fn rng(&self, frame: usize, stream: u32) -> ChaCha8Rng {
+        let seed = ((self.frame_seed(frame) as u64) << 32) | pcg_hash(stream) as u64;
+        ChaCha8Rng::seed_from_u64(seed)
+    }
+
+    /// Returns whether the settings changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            changed |= ui.add(egui::DragValue::new(&mut self.seed).prefix("Seed: ")).changed();
+            if ui.button("New seed").clicked() {
+                self.seed = rand::random();
+                changed = true;
+            }
+        });
+        changed |= ui
+            .checkbox(&mut self.lock, "Lock seed")
+            .on_hover_text("Use the same seed on all frames, so that noise doesn't change during playback")
+            .changed();
+        changed
+    }
+}
@@ -0,0 +1,165 @@
+//! Dynamic resolution: the scene is rendered at a reduced resolution when frames take too long.
+//!
+//! The frame time is measured between consecutive frames. Since frames are throttled by the swapchain,
+//! the time of a GPU-bound frame is the time the GPU took to render it. When the average frame time goes
+//! over the budget, the resolution scale is lowered in proportion; when frames fit in the budget again,
+//! the scale is raised back step by step. With vsync, frames never go faster than the refresh rate, so
+//! there's no way to know in advance whether a higher scale would fit: it is tried after a while, and if
+//! it goes over budget, the next try waits twice as long.
+//!
+//! The scene (curves, meshes, stylization) is rendered at the scaled resolution and upscaled to the
+//! window size with a bilinear filter. The overlay and the UI are drawn afterwards, at native resolution.
+use std::time::Instant;
+
+/// Weight of the last frame in the average frame time.
+const AVERAGE_WEIGHT: f32 = 0.1;
+/// The scale is lowered when the average frame time is over the budget by this factor.
+const OVER_BUDGET: f32 = 1.1;
+/// Frames at or under the budget by this factor leave room for a higher scale.
+const WITHIN_BUDGET: f32 = 1.02;
+/// Maximum decrease of the scale at once.
+const MAX_SCALE_DECREASE: f32 = 0.75;
+/// Increase of the scale when there's headroom.
+const SCALE_STEP: f32 = 0.05;
+/// Frames to wait after a change of scale, so that the average frame time settles.
+const SETTLE_FRAMES: u32 = 20;
+/// Frames within budget before trying a higher scale.
+const PROBE_FRAMES: u32 = 60;
+/// Maximum backoff exponent of `PROBE_FRAMES`, after higher scales repeatedly went over budget.
+const MAX_BACKOFF: u32 = 5;
+/// Frame intervals longer than this are pauses (window minimized, file loading, etc.), not load.
+const MAX_FRAME_INTERVAL: f32 = 0.5;
+
+pub struct DynamicResolution {
+    pub enabled: bool,
+    /// Target frame rate.
+    pub target_fps: f32,
+    /// Lowest resolution scale.
+    pub min_scale: f32,
+    /// Current resolution scale, in (0, 1].
+    scale: f32,
+    last_frame: Option<Instant>,
+    /// Moving average of the frame time, in seconds. Zero if unknown.
+    average_frame_time: f32,
+    /// Frames since the last change of scale.
+    frames_since_change: u32,
+    /// Consecutive frames within budget.
+    frames_within_budget: u32,
+    /// Whether the last change of scale was an increase.
+    probing: bool,
+    backoff: u32,
+}
+
+impl Default for DynamicResolution {
+    fn default() -> Self {
+        DynamicResolution {
+            enabled: false,
+            target_fps: 60.0,
+            min_scale: 0.5,
+            scale: 1.0,
+            last_frame: None,
+            average_frame_time: 0.0,
+            frames_since_change: 0,
+            frames_within_budget: 0,
+            probing: false,
+            backoff: 0,
+        }
+    }
+}
+
+impl DynamicResolution {
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Returns the size at which the scene should be rendered, for a window of the specified size.
+    pub fn scene_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = |size: u32| ((size as f32 * self.scale).round() as u32).clamp(1, size.max(1));
+        (scale(width), scale(height))
+    }
+
+    fn set_scale(&mut self, scale: f32) {
+        // Quantized, so that small changes don't reallocate the render targets.
+        let scale = ((scale.clamp(self.min_scale, 1.0) / SCALE_STEP).round() * SCALE_STEP).clamp(self.min_scale, 1.0);
+        if scale != self.scale {
+            self.probing = scale > self.scale;
+            self.scale = scale;
+            self.frames_since_change = 0;
+            self.frames_within_budget = 0;
+        }
+    }
+
+    /// Must be called once per rendered frame.
+    ///
+    /// `throttled` is whether the redraws were deliberately delayed (eco mode): the time between
+    /// those frames says nothing about the load.
+    pub fn frame_rendered(&mut self, throttled: bool) {
+        let now = Instant::now();
+        let interval = self.last_frame.replace(now).map(|last| (now - last).as_secs_f32());
+        if !self.enabled {
+            self.scale = 1.0;
+            self.average_frame_time = 0.0;
+            return;
+        }
+        let Some(interval) = interval.filter(|&interval| !throttled && interval <= MAX_FRAME_INTERVAL) else {
+            return;
+        };
+
+        self.average_frame_time = if self.average_frame_time == 0.0 {
+            interval
+        } else {
+            self.average_frame_time + (interval - self.average_frame_time) * AVERAGE_WEIGHT
+        };
+        self.frames_since_change = self.frames_since_change.saturating_add(1);
+        if self.frames_since_change < SETTLE_FRAMES {
+            return;
+        }
+
+        let budget = 1.0 / self.target_fps.max(1.0);
+        if self.average_frame_time > budget * OVER_BUDGET {
+            if self.probing {
+                // the higher scale didn't fit, wait longer before trying again
+                self.backoff = (self.backoff + 1).min(MAX_BACKOFF);
+            }
+            // the cost of a frame is roughly proportional to the number of pixels, i.e. the square of the scale
+            let ratio = (budget / self.average_frame_time).sqrt().max(MAX_SCALE_DECREASE);
+            self.set_scale(self.scale * ratio);
+            // don't count it as a failed probe if it happens again
+            self.probing = false;
+        } else if self.average_frame_time <= budget * WITHIN_BUDGET {
+            if self.probing && self.frames_since_change >= PROBE_FRAMES {
+                // the higher scale fits
+                self.probing = false;
+                self.backoff = self.backoff.saturating_sub(1);
+            }
+            self.frames_within_budget += 1;
+            if self.scale < 1.0 && self.frames_within_budget >= PROBE_FRAMES << self.backoff {
+                self.set_scale(self.scale + SCALE_STEP);
+            }
+        } else {
+            self.frames_within_budget = 0;
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, width: u32, height: u32) {
+        ui.checkbox(&mut self.enabled, "Dynamic resolution")
+            .on_hover_text("Render the scene at a lower resolution when the frame rate drops below the target");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.add(egui::Slider::new(&mut self.target_fps, 15.0..=144.0).text("Target FPS"));
+            if ui
+                .add(egui::Slider::new(&mut self.min_scale, 0.25..=1.0).text("Minimum scale"))
+                .changed()
+            {
+                self.set_scale(self.scale);
+            }
+        });
+        if self.enabled {
+            let (scene_width, scene_height) = self.scene_size(width, height);
+            ui.label(format!(
+                "Scale: {:.0}% ({scene_width}x{scene_height}), frame time {:.1} ms",
+                self.scale * 100.0,
+                self.average_frame_time * 1000.0
+            ));
+        }
+    }
+}
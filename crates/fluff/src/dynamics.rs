@@ -0,0 +1,233 @@
+//! Strand dynamics preview.
+//!
+//! A lightweight position-based solver (`strand_dynamics.comp`) that lets strands sway under gravity and
+//! collide with the volumes of the scene, to preview motion without going back to Houdini.
+//! The animated curves act as the rest pose: roots follow the animation, and the rest of the strand is
+//! pulled back towards its animated shape.
+use std::{path::PathBuf, time::Instant};
+
+use glam::vec3;
+use graal::{util::DeviceExt, Barrier, Buffer, BufferUsage, CommandStream, Device, DeviceAddress, MemoryLocation};
+
+use crate::{
+    engine::{ComputePipelineDesc, Engine, Error},
+    scene::Scene,
+    shaders::shared::{ControlPoint, SphereCollider, StrandDynamicsParams, STRAND_DYNAMICS_WORKGROUP_SIZE},
+};
+
+/// Longest time step of the solver. Longer frames are simulated in slow motion, which is preferable to
+/// the solver exploding.
+const MAX_TIME_STEP: f32 = 1.0 / 30.0;
+
+/// Solver parameters.
+pub struct DynamicsSettings {
+    pub enabled: bool,
+    /// Gravity acceleration, in scene units per second squared (towards -Y).
+    pub gravity: f32,
+    /// Fraction of the velocity lost at each step.
+    pub damping: f32,
+    /// How strongly the strands are pulled back to their animated shape.
+    pub stiffness: f32,
+    pub iterations: u32,
+    /// Use the volumes of the scene as colliders.
+    pub collide_with_volumes: bool,
+    /// Added to the radius of the colliders.
+    pub collider_margin: f32,
+}
+
+impl Default for DynamicsSettings {
+    fn default() -> Self {
+        DynamicsSettings {
+            enabled: false,
+            gravity: 9.81,
+            damping: 0.05,
+            stiffness: 0.02,
+            iterations: 4,
+            collide_with_volumes: true,
+            collider_margin: 0.0,
+        }
+    }
+}
+
+/// GPU buffers holding the state of the simulation.
+struct SolverState {
+    positions: Buffer<[glam::Vec4]>,
+    prev_positions: Buffer<[glam::Vec4]>,
+    /// Simulated control points. Same layout as the position buffer of the scene.
+    simulated_points: Buffer<[ControlPoint]>,
+    /// Number of control points in the simulated frame.
+    point_count: usize,
+}
+
+/// Strand dynamics solver of the loaded scene.
+pub struct StrandDynamics {
+    pub settings: DynamicsSettings,
+    state: Option<SolverState>,
+    last_step: Option<Instant>,
+    /// Reset the strands to their animated shape at the next step.
+    needs_reset: bool,
+}
+
+impl StrandDynamics {
+    pub fn new() -> StrandDynamics {
+        StrandDynamics {
+            settings: DynamicsSettings::default(),
+            state: None,
+            last_step: None,
+            needs_reset: true,
+        }
+    }
+
+    /// Resets the strands to their animated shape.
+    pub fn reset(&mut self) {
+        self.needs_reset = true;
+    }
+
+    /// Releases the state of the simulation. Must be called when another scene is loaded.
+    pub fn clear(&mut self) {
+        self.state = None;
+        self.needs_reset = true;
+    }
+
+    /// (Re)allocates the state buffers if the number of control points has changed.
+    fn update_state(&mut self, device: &Device, scene: &Scene, point_count: usize) {
+        let total_point_count = scene.position_buffer.len();
+        let up_to_date = self
+            .state
+            .as_ref()
+            .is_some_and(|s| s.point_count == point_count && s.simulated_points.len() == total_point_count);
+        if !up_to_date {
+            // topology changed, start over
+            let usage = BufferUsage::STORAGE_BUFFER;
+            let positions = device.create_array_buffer(usage, MemoryLocation::GpuOnly, point_count.max(1));
+            positions.set_name("strand dynamics positions");
+            let prev_positions = device.create_array_buffer(usage, MemoryLocation::GpuOnly, point_count.max(1));
+            prev_positions.set_name("strand dynamics previous positions");
+            let simulated_points = device.create_array_buffer(usage, MemoryLocation::GpuOnly, total_point_count.max(1));
+            simulated_points.set_name("simulated control points");
+            self.state = Some(SolverState {
+                positions,
+                prev_positions,
+                simulated_points,
+                point_count,
+            });
+            self.needs_reset = true;
+        }
+    }
+
+    /// Advances the simulation of the given frame of the scene.
+    ///
+    /// Returns the simulated control points, to be used instead of the position buffer of the scene,
+    /// or `None` if dynamics are disabled.
+    pub fn step(
+        &mut self,
+        cmd: &mut CommandStream,
+        engine: &mut Engine,
+        scene: &Scene,
+        frame: usize,
+    ) -> Result<Option<DeviceAddress<[ControlPoint]>>, Error> {
+        if !self.settings.enabled {
+            self.last_step = None;
+            return Ok(None);
+        }
+
+        let pipeline = engine.create_compute_pipeline(
+            "strand_dynamics",
+            ComputePipelineDesc {
+                shader: PathBuf::from("crates/fluff/shaders/strand_dynamics.comp"),
+                defines: Default::default(),
+            },
+        )?;
+
+        let anim_frame = &scene.frames[frame];
+        let curve_range = anim_frame.curve_range;
+        let curves = &scene.curve_buffer.as_slice()[curve_range.start as usize..][..curve_range.count as usize];
+        let point_count = match (curves.first(), curves.last()) {
+            (Some(first), Some(last)) => (last.start + last.count - first.start) as usize,
+            _ => 0,
+        };
+
+        let now = Instant::now();
+        let dt = self
+            .last_step
+            .map_or(0.0, |last| (now - last).as_secs_f32())
+            .min(MAX_TIME_STEP);
+        self.last_step = Some(now);
+
+        // colliders: spheres inscribed in the bounding boxes of the volumes
+        let mut colliders: Vec<SphereCollider> = vec![];
+        if self.settings.collide_with_volumes {
+            for bounds in anim_frame.volume_bounds.iter() {
+                let center = 0.5 * (bounds.min + bounds.max);
+                colliders.push(SphereCollider {
+                    center: center.to_array(),
+                    radius: 0.5 * bounds.size().min_element() + self.settings.collider_margin,
+                });
+            }
+        }
+        let collider_count = colliders.len() as u32;
+        if colliders.is_empty() {
+            // can't create empty buffers
+            colliders.push(SphereCollider {
+                center: [0.0; 3],
+                radius: 0.0,
+            });
+        }
+        let collider_buffer = cmd.device().upload_array_buffer(BufferUsage::STORAGE_BUFFER, &colliders);
+        cmd.reference_resource(&collider_buffer);
+
+        let device = cmd.device().clone();
+        self.update_state(&device, scene, point_count);
+        let reset = self.needs_reset;
+        let state = self.state.as_ref().unwrap();
+        if curve_range.count > 0 {
+            cmd.barrier(Barrier::new().shader_storage_write());
+            let mut encoder = cmd.begin_compute();
+            encoder.bind_compute_pipeline(&pipeline);
+            encoder.push_constants(&StrandDynamicsParams {
+                control_points: scene.position_buffer.device_address(),
+                curves: scene.curve_buffer.device_address(),
+                positions: state.positions.device_address(),
+                prev_positions: state.prev_positions.device_address(),
+                simulated_points: state.simulated_points.device_address(),
+                colliders: collider_buffer.device_address(),
+                base_curve_index: curve_range.start,
+                curve_count: curve_range.count,
+                collider_count,
+                iterations: self.settings.iterations,
+                gravity: vec3(0.0, -self.settings.gravity, 0.0),
+                dt,
+                damping: self.settings.damping,
+                stiffness: self.settings.stiffness,
+                reset: reset as u32,
+            });
+            encoder.dispatch(curve_range.count.div_ceil(STRAND_DYNAMICS_WORKGROUP_SIZE), 1, 1);
+            encoder.finish();
+            cmd.barrier(Barrier::new().shader_storage_read());
+        }
+        self.needs_reset = false;
+        Ok(Some(state.simulated_points.device_address()))
+    }
+
+    /// Settings UI.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let s = &mut self.settings;
+        ui.checkbox(&mut s.enabled, "Enable strand dynamics")
+            .on_hover_text("Simulate the strands of the loaded groom (preview only, the scene is not modified)");
+        ui.add_enabled_ui(s.enabled, |ui| {
+            ui.add(egui::Slider::new(&mut s.gravity, 0.0..=50.0).text("Gravity"));
+            ui.add(egui::Slider::new(&mut s.damping, 0.0..=1.0).text("Damping"));
+            ui.add(egui::Slider::new(&mut s.stiffness, 0.0..=1.0).logarithmic(true).text("Stiffness"))
+                .on_hover_text("How strongly the strands are pulled back to their animated shape");
+            ui.add(egui::Slider::new(&mut s.iterations, 1..=16).text("Iterations"));
+            ui.checkbox(&mut s.collide_with_volumes, "Collide with volumes");
+            ui.add_enabled(
+                s.collide_with_volumes,
+                egui::Slider::new(&mut s.collider_margin, 0.0..=1.0).text("Collider Margin"),
+            );
+            if ui.button("Reset").clicked() {
+                self.needs_reset = true;
+            }
+        });
+    }
+}
@@ -0,0 +1,78 @@
+//! Eco mode: reduced redraw rate and quality while the window is in the background.
+//!
+//! When the window loses focus, redraws are throttled and the expensive passes are skipped (temporal
+//! average, stylization, onion skins), with coarser ribbon tessellation. Full quality is restored as soon
+//! as the window regains focus.
+use std::time::{Duration, Instant};
+
+/// Factor applied to the ribbon tessellation tolerance in eco mode.
+pub const ECO_RIBBON_TOLERANCE_SCALE: f32 = 4.0;
+
+pub struct EcoMode {
+    pub enabled: bool,
+    /// Maximum redraw rate while the window is unfocused.
+    pub unfocused_fps: f32,
+    focused: bool,
+    last_redraw: Instant,
+    /// Frames rendered since `rate_window_start`, for the measured frame rate.
+    frame_count: u32,
+    rate_window_start: Instant,
+    measured_fps: f32,
+}
+
+impl Default for EcoMode {
+    fn default() -> Self {
+        let now = Instant::now();
+        EcoMode {
+            enabled: true,
+            unfocused_fps: 5.0,
+            focused: true,
+            last_redraw: now,
+            frame_count: 0,
+            rate_window_start: now,
+            measured_fps: 0.0,
+        }
+    }
+}
+
+impl EcoMode {
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    /// Whether eco mode is currently in effect.
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.focused
+    }
+
+    /// Returns the time of the next redraw, or `None` if the window should be redrawn right away.
+    pub fn next_redraw(&self) -> Option<Instant> {
+        if !self.is_active() || self.unfocused_fps <= 0.0 {
+            return None;
+        }
+        let next = self.last_redraw + Duration::from_secs_f32(1.0 / self.unfocused_fps);
+        (next > Instant::now()).then_some(next)
+    }
+
+    /// Must be called once per rendered frame.
+    pub fn frame_rendered(&mut self) {
+        let now = Instant::now();
+        self.last_redraw = now;
+        self.frame_count += 1;
+        let elapsed = now - self.rate_window_start;
+        if elapsed >= Duration::from_secs(1) {
+            self.measured_fps = self.frame_count as f32 / elapsed.as_secs_f32();
+            self.frame_count = 0;
+            self.rate_window_start = now;
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Eco mode when unfocused")
+            .on_hover_text("Throttle redraws and skip expensive passes while the window is in the background");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.add(egui::Slider::new(&mut self.unfocused_fps, 1.0..=30.0).text("Background FPS"));
+        });
+        ui.label(format!("Frame rate: {:.1} fps", self.measured_fps));
+    }
+}
@@ -3,6 +3,11 @@ use std::{collections::HashMap, mem, path::Path, slice};
 use egui::{epaint::Primitive, ClippedPrimitive, ImageData};
 use graal::{prelude::*, util::CommandStreamExt, vk::{AttachmentLoadOp, AttachmentStoreOp, ImageAspectFlags, Offset3D}, ColorAttachment, ImageAccess, ImageCopyView, RenderPassInfo, Size3D, Vertex, Barrier};
 
+use crate::{
+    gpu_memory::{image_byte_size, MemoryCategory, MemoryTag},
+    util::CommandStreamUploadExt,
+};
+
 #[derive(Copy, Clone, Vertex)]
 #[repr(C)]
 struct EguiVertex {
@@ -23,6 +28,7 @@ struct Texture {
     image: Image,
     view: ImageView,
     sampler: Sampler,
+    _memory: MemoryTag,
 }
 
 pub struct Renderer {
@@ -115,7 +121,13 @@ impl Renderer {
                     ..Default::default()
                 });
 
-                Texture { image, view, sampler }
+                let memory = MemoryTag::new(MemoryCategory::Ui, image_byte_size(format, width, height, 1));
+                Texture {
+                    image,
+                    view,
+                    sampler,
+                    _memory: memory,
+                }
             });
 
             let (x, y) = if let Some([x, y]) = tex.pos { (x as i32, y as i32) } else { (0, 0) };
@@ -163,8 +175,8 @@ impl Renderer {
 
         for (_, mesh) in meshes.iter() {
             let vertex_data: &[EguiVertex] = unsafe { slice::from_raw_parts(mesh.vertices.as_ptr().cast(), mesh.vertices.len()) };
-            let vertex_buffer = cmd.device().upload_array_buffer(BufferUsage::VERTEX_BUFFER, vertex_data);
-            let index_buffer = cmd.device().upload_array_buffer(BufferUsage::INDEX_BUFFER, &mesh.indices);
+            let vertex_buffer = cmd.upload_slice(BufferUsage::VERTEX_BUFFER, MemoryLocation::CpuToGpu, vertex_data);
+            let index_buffer = cmd.upload_slice(BufferUsage::INDEX_BUFFER, MemoryLocation::CpuToGpu, &mesh.indices);
             vertex_buffer.set_name("egui vertex buffer");
             index_buffer.set_name("egui index buffer");
             mesh_vertex_buffers.push(vertex_buffer);
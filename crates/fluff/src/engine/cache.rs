@@ -0,0 +1,96 @@
+//! Pipeline cache entries and dependency tracking.
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::engine::Error;
+
+/// Hashes the parameters that affect the compiled code of a pipeline.
+pub(super) fn pipeline_key(shaders: &[&Path], global_defines: &BTreeMap<String, String>, defines: &BTreeMap<String, String>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    shaders.hash(&mut hasher);
+    global_defines.hash(&mut hasher);
+    defines.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn content_hash(path: &Path) -> Option<u64> {
+    let content = std::fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// A source file that a pipeline depends on.
+struct Dependency {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// Hash of the contents. `None` if the file couldn't be read.
+    hash: Option<u64>,
+}
+
+impl Dependency {
+    fn new(path: PathBuf) -> Dependency {
+        Dependency {
+            modified: modified_time(&path),
+            hash: content_hash(&path),
+            path,
+        }
+    }
+
+    /// Returns whether the file has changed since the pipeline was built.
+    ///
+    /// The contents are only re-hashed if the modification time has changed, and files that were
+    /// only touched don't invalidate the pipeline.
+    fn is_stale(&mut self) -> bool {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        content_hash(&self.path) != self.hash
+    }
+}
+
+/// Cached result of a pipeline creation.
+pub(super) struct CachedPipeline<T> {
+    pub(super) result: Result<T, Error>,
+    key: u64,
+    /// Shader files and all files they include (transitively).
+    dependencies: Vec<Dependency>,
+}
+
+impl<T: Clone> CachedPipeline<T> {
+    pub(super) fn new(result: Result<T, Error>, key: u64, dependencies: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut paths: Vec<PathBuf> = dependencies.into_iter().collect();
+        paths.sort();
+        paths.dedup();
+        CachedPipeline {
+            result,
+            key,
+            dependencies: paths.into_iter().map(Dependency::new).collect(),
+        }
+    }
+
+    /// Returns whether the cached pipeline can be reused for a request with the specified key.
+    ///
+    /// Also returns false if a dependency has changed since the pipeline was built.
+    pub(super) fn is_up_to_date(&mut self, key: u64) -> bool {
+        if self.key != key {
+            return false;
+        }
+        // check all dependencies so that their timestamps are updated
+        let mut stale = false;
+        for dep in self.dependencies.iter_mut() {
+            stale |= dep.is_stale();
+        }
+        !stale
+    }
+}
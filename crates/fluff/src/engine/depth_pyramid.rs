@@ -0,0 +1,166 @@
+//! Hierarchical-Z: a pyramid of view-space depth for occlusion culling.
+//!
+//! Level 0 is the view-space depth of each pixel of the depth buffer (see `Engine::resolve_depth`), and
+//! each following level is half the size of the previous one, rounded up, and holds the farthest depth
+//! of the 2x2 block of texels it covers. An object whose nearest depth is farther than the pyramid over
+//! its screen-space bounds, at a level where the bounds span at most 2x2 texels, is occluded.
+//!
+//! The levels are separate images so that each can be bound as a storage image.
+use glam::uvec2;
+use graal::{prelude::*, Barrier, Buffer, ImageView};
+
+use crate::{
+    engine::{Engine, Error},
+    gpu_memory::{image_byte_size, MemoryCategory, MemoryTag},
+    shaders::{shared::ResampleParams, types::ImageHandle},
+};
+
+/// Format of the levels of the pyramid.
+const FORMAT: Format = Format::R32_SFLOAT;
+
+/// Pyramid of view-space depth, built by `Engine::build_depth_pyramid`.
+pub struct DepthPyramid {
+    levels: Vec<Image>,
+    views: Vec<ImageView>,
+    memory: MemoryTag,
+}
+
+impl DepthPyramid {
+    fn new() -> DepthPyramid {
+        DepthPyramid {
+            levels: vec![],
+            views: vec![],
+            memory: MemoryTag::new(MemoryCategory::RenderTargets, 0),
+        }
+    }
+
+    /// Reallocates the levels if the size of the depth buffer changed.
+    fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        if self.width() == width && self.height() == height {
+            return;
+        }
+        self.levels.clear();
+        self.views.clear();
+        let (mut w, mut h) = (width, height);
+        loop {
+            let image = device.create_image(&ImageCreateInfo {
+                memory_location: MemoryLocation::GpuOnly,
+                type_: ImageType::Image2D,
+                usage: ImageUsage::STORAGE | ImageUsage::SAMPLED,
+                format: FORMAT,
+                width: w,
+                height: h,
+                depth: 1,
+                mip_levels: 1,
+                array_layers: 1,
+                samples: 1,
+            });
+            image.set_name(&format!("depth pyramid level {}", self.levels.len()));
+            self.views.push(image.create_top_level_view());
+            self.levels.push(image);
+            if w == 1 && h == 1 {
+                break;
+            }
+            w = w.div_ceil(2);
+            h = h.div_ceil(2);
+        }
+        self.memory.set_bytes(byte_size(width, height));
+    }
+
+    /// Width of level 0, same as the depth buffer.
+    pub fn width(&self) -> u32 {
+        self.levels.first().map_or(0, |image| image.width())
+    }
+
+    /// Height of level 0, same as the depth buffer.
+    pub fn height(&self) -> u32 {
+        self.levels.first().map_or(0, |image| image.height())
+    }
+
+    pub fn level_count(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    /// Uploads the handles of the levels to a buffer, indexed by level, for culling shaders.
+    ///
+    /// Shaders reading the pyramid must be preceded by a `shader_read_image` barrier on the levels they
+    /// read (see `barrier`).
+    pub fn upload_level_handles(&self, cmd: &mut CommandStream) -> Buffer<[ImageHandle]> {
+        let handles: Vec<_> = self.views.iter().map(|view| view.device_image_handle()).collect();
+        for view in self.views.iter() {
+            cmd.reference_resource(view);
+        }
+        let buffer = cmd.device().upload_array_buffer(BufferUsage::STORAGE_BUFFER, &handles);
+        cmd.reference_resource(&buffer);
+        buffer
+    }
+
+    /// Adds read barriers on all levels of the pyramid.
+    pub fn barrier(&self, barrier: Barrier) -> Barrier {
+        self.levels.iter().fold(barrier, |barrier, image| barrier.shader_read_image(image))
+    }
+}
+
+/// Returns the size in bytes of the levels of a pyramid for a depth buffer of the given size.
+fn byte_size(width: u32, height: u32) -> u64 {
+    let mut bytes = 0;
+    let (mut w, mut h) = (width, height);
+    loop {
+        bytes += image_byte_size(FORMAT, w, h, 1);
+        if w <= 1 && h <= 1 {
+            return bytes;
+        }
+        w = w.div_ceil(2);
+        h = h.div_ceil(2);
+    }
+}
+
+impl Engine {
+    /// Builds the depth pyramid from the depth buffer.
+    ///
+    /// `depth` should contain the depth of the occluders, e.g. after a depth pre-pass. The pyramid is kept
+    /// by the engine until the next call, and can be retrieved with `depth_pyramid`.
+    pub fn build_depth_pyramid(
+        &mut self,
+        cmd: &mut CommandStream,
+        depth: &Image,
+        near_plane: f32,
+        far_plane: f32,
+    ) -> Result<&DepthPyramid, Error> {
+        let pipeline = self.stock_pipeline("hiz_downsample")?;
+        self.use_transient("depth pyramid", "depth pyramid levels", byte_size(depth.width(), depth.height()))?;
+        let device = cmd.device().clone();
+        let mut pyramid = self.depth_pyramid.take().unwrap_or_else(DepthPyramid::new);
+        pyramid.resize(&device, depth.width(), depth.height());
+
+        let level0 = pyramid.levels[0].clone();
+        self.depth_pyramid = Some(pyramid);
+        self.resolve_depth(cmd, depth, &level0, near_plane, far_plane)?;
+        let pyramid = self.depth_pyramid.as_ref().unwrap();
+        for level in 1..pyramid.levels.len() {
+            let (input, output) = (&pyramid.levels[level - 1], &pyramid.levels[level]);
+            let (input_view, output_view) = (&pyramid.views[level - 1], &pyramid.views[level]);
+            cmd.reference_resource(input_view);
+            cmd.reference_resource(output_view);
+            cmd.barrier(Barrier::new().shader_read_image(input).shader_write_image(output));
+            let params = ResampleParams {
+                input_size: uvec2(input.width(), input.height()),
+                output_size: uvec2(output.width(), output.height()),
+                input_image: input_view.device_image_handle(),
+                output_image: output_view.device_image_handle(),
+            };
+            super::passes::dispatch(cmd, &pipeline, &params, output.width(), output.height());
+        }
+        Ok(pyramid)
+    }
+
+    /// Returns the depth pyramid built by the last call to `build_depth_pyramid`.
+    pub fn depth_pyramid(&self) -> Option<&DepthPyramid> {
+        self.depth_pyramid.as_ref()
+    }
+
+    /// Frees the depth pyramid, when it's not built anymore.
+    pub fn release_depth_pyramid(&mut self) {
+        self.depth_pyramid = None;
+    }
+}
@@ -25,10 +25,21 @@ use slotmap::SlotMap;
 use spirv_reflect::types::{ReflectDescriptorType, ReflectTypeFlags};
 use tracing::{debug, error, warn};
 
+use crate::engine::cache::{pipeline_key, CachedPipeline};
+use crate::engine::passes::PassScratch;
 use crate::engine::shader::{CompilationInfo, compile_shader_stage};
+use crate::gpu_memory::format_bytes;
+
+pub use depth_pyramid::DepthPyramid;
+pub use passes::StockPass;
+pub use transient::{PassMemory, TransientMemory};
 
 //mod bindless;
+mod cache;
+mod depth_pyramid;
+mod passes;
 mod shader;
+mod transient;
 //mod uniform_block;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -62,6 +73,77 @@ pub enum Error {
     InvalidFieldType(String),
     #[error("Vulkan error: {0}")]
     VulkanError(Rc<graal::Error>),
+    #[error("Shader reflection error: {0}")]
+    ShaderReflection(String),
+    #[error("{}", format_compile_error(.path, .diagnostics))]
+    ShaderCompile {
+        path: PathBuf,
+        diagnostics: Rc<[ShaderDiagnostic]>,
+    },
+    #[error("failed to create pipeline `{pipeline}` ({stage}): {error}")]
+    Pipeline {
+        pipeline: String,
+        /// Shader stage being compiled, or "link" if the error happened when creating the pipeline object.
+        stage: &'static str,
+        error: Rc<Error>,
+    },
+    #[error(
+        "pass `{pass}` is over the transient budget of {}: `{resource}` needs {} on top of {} used in this frame",
+        format_bytes(*.budget),
+        format_bytes(*.bytes),
+        format_bytes(*.frame_bytes)
+    )]
+    TransientBudgetExceeded {
+        pass: String,
+        resource: String,
+        bytes: u64,
+        /// Bytes used by the previous passes of the frame.
+        frame_bytes: u64,
+        budget: u64,
+    },
+}
+
+impl Error {
+    /// Returns the shader compiler diagnostics associated with this error, if any.
+    pub fn diagnostics(&self) -> &[ShaderDiagnostic] {
+        match self {
+            Error::ShaderCompile { diagnostics, .. } => diagnostics,
+            Error::Pipeline { error, .. } => error.diagnostics(),
+            _ => &[],
+        }
+    }
+}
+
+/// A message from the shader compiler, mapped back to the source file (which may be an included file).
+#[derive(Clone, Debug)]
+pub struct ShaderDiagnostic {
+    pub file: String,
+    pub line: Option<u32>,
+    pub is_error: bool,
+    pub message: String,
+    /// Lines of the source file around `line`, with their (1-based) line numbers.
+    ///
+    /// Read when the diagnostic is created, so that it matches the source that was compiled.
+    pub excerpt: Vec<(u32, String)>,
+}
+
+impl std::fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = if self.is_error { "error" } else { "warning" };
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}: {}", self.file, line, severity, self.message),
+            None => write!(f, "{}: {}: {}", self.file, severity, self.message),
+        }
+    }
+}
+
+fn format_compile_error(path: &Path, diagnostics: &[ShaderDiagnostic]) -> String {
+    let mut s = format!("failed to compile `{}`", path.display());
+    for d in diagnostics.iter().filter(|d| d.is_error) {
+        s.push_str("\n  ");
+        s.push_str(&d.to_string());
+    }
+    s
 }
 
 /*
@@ -362,6 +444,47 @@ impl AccessTracker {
 }*/
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// What to do with the previous contents of an attachment at the start of a pass.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LoadHint<T> {
+    /// Preserve the previous contents of the attachment.
+    Load,
+    /// Clear the whole attachment with the specified value.
+    Clear(T),
+}
+
+impl<T: Copy> LoadHint<T> {
+    /// The clear value to pass to graal attachments, which derive the load op from its presence.
+    pub fn attachment_clear_value(&self) -> Option<T> {
+        match self {
+            LoadHint::Load => None,
+            LoadHint::Clear(value) => Some(*value),
+        }
+    }
+}
+
+/// Builds a color attachment from a load hint.
+pub fn color_attachment(image_view: &ImageView, load: LoadHint<[f64; 4]>) -> graal::ColorAttachment {
+    graal::ColorAttachment {
+        image_view,
+        clear_value: load.attachment_clear_value(),
+    }
+}
+
+/// Builds a depth-stencil attachment from load hints for the depth and stencil aspects.
+pub fn depth_stencil_attachment(
+    image_view: &ImageView,
+    depth: LoadHint<f64>,
+    stencil: LoadHint<u32>,
+) -> graal::DepthStencilAttachment {
+    graal::DepthStencilAttachment {
+        image_view,
+        depth_clear_value: depth.attachment_clear_value(),
+        stencil_clear_value: stencil.attachment_clear_value(),
+    }
+}
+
 /*pub struct ColorAttachmentDesc {
     pub image: ImageHandle,
     pub clear_value: Option<[f64; 4]>,
@@ -738,6 +861,16 @@ pub struct ComputePipelineDesc {
     pub defines: BTreeMap<String, String>,
 }
 
+fn pipeline_error(pipeline: &str, stage: &'static str, error: Error) -> Error {
+    let error = Error::Pipeline {
+        pipeline: pipeline.to_string(),
+        stage,
+        error: Rc::new(error),
+    };
+    error!("{error}");
+    error
+}
+
 /// Rendering engine instance.
 pub struct Engine {
     device: Device,
@@ -745,9 +878,15 @@ pub struct Engine {
     global_defs: BTreeMap<String, String>,
     //bindless_layout: BindlessLayout,
     /// Cached mesh render pipelines compilation results
-    mesh_render_pipelines: BTreeMap<String, Result<GraphicsPipeline, Error>>,
+    mesh_render_pipelines: BTreeMap<String, CachedPipeline<GraphicsPipeline>>,
     /// Cached compute pipelines compilation results
-    compute_pipelines: BTreeMap<String, Result<ComputePipeline, Error>>,
+    compute_pipelines: BTreeMap<String, CachedPipeline<ComputePipeline>>,
+    /// Scratch resources of the stock passes.
+    scratch: PassScratch,
+    /// Last depth pyramid built with `build_depth_pyramid`.
+    depth_pyramid: Option<DepthPyramid>,
+    /// Transient resources used by the passes in the current frame.
+    transient: TransientMemory,
 }
 
 impl Engine {
@@ -757,14 +896,16 @@ impl Engine {
             global_defs: Default::default(),
             mesh_render_pipelines: Default::default(),
             compute_pipelines: Default::default(),
+            scratch: Default::default(),
+            depth_pyramid: None,
+            transient: Default::default(),
         }
     }
 
     pub fn set_global_defines(&mut self, defines: BTreeMap<String, String>) {
         self.global_defs = defines;
         // recompile all shaders
-        self.mesh_render_pipelines.clear();
-        self.compute_pipelines.clear();
+        self.clear_pipeline_cache();
     }
 
     /*pub fn submit_graph(&mut self, graph: RenderGraph, cmd: &mut CommandStream) {
@@ -909,29 +1050,46 @@ impl Engine {
         cmd.flush(&[], &[]).unwrap()
     }*/
 
+    /// Returns the errors of the pipelines that failed to build.
+    ///
+    /// Failed pipelines are cached like successful ones: they are retried when one of their source files
+    /// changes, or after `set_global_defines` or `clear_pipeline_cache`.
+    pub fn pipeline_errors(&self) -> Vec<Error> {
+        let compute = self.compute_pipelines.values().filter_map(|p| p.result.as_ref().err());
+        let mesh = self.mesh_render_pipelines.values().filter_map(|p| p.result.as_ref().err());
+        compute.chain(mesh).cloned().collect()
+    }
+
+    /// Forgets all cached pipelines, so that they are rebuilt on next use.
+    pub fn clear_pipeline_cache(&mut self) {
+        self.mesh_render_pipelines.clear();
+        self.compute_pipelines.clear();
+    }
+
     pub fn define_global(&mut self, define: &str, value: impl ToString) {
         self.global_defs.insert(define.to_string(), value.to_string());
     }
 
     pub fn create_compute_pipeline(&mut self, name: &str, desc: ComputePipelineDesc) -> Result<ComputePipeline, Error> {
-        if let Some(pipeline) = self.compute_pipelines.get(name) {
-            return pipeline.clone();
+        let key = pipeline_key(&[desc.shader.as_path()], &self.global_defs, &desc.defines);
+        if let Some(cached) = self.compute_pipelines.get_mut(name) {
+            if cached.is_up_to_date(key) {
+                return cached.result.clone();
+            }
+            debug!("rebuilding compute pipeline `{name}`");
         }
 
-        let file_path = &desc.shader;
-        let gdefs = &self.global_defs;
-        let defs = &desc.defines;
         let mut ci = CompilationInfo::default();
+        let result = self.build_compute_pipeline(name, &desc, &mut ci);
+        let dependencies = ci.includes.into_iter().chain([desc.shader.clone()]);
+        self.compute_pipelines
+            .insert(name.to_string(), CachedPipeline::new(result.clone(), key, dependencies));
+        result
+    }
 
-        let compute_spv = match compile_shader_stage(&file_path, &gdefs, &defs, ShaderKind::Compute, &mut ci) {
-            Ok(spv) => spv,
-            Err(err) => {
-                error!("failed to compile compute shader: {err}");
-                let result = Err(err.into());
-                self.compute_pipelines.insert(name.to_string(), result.clone());
-                return result;
-            }
-        };
+    fn build_compute_pipeline(&self, name: &str, desc: &ComputePipelineDesc, ci: &mut CompilationInfo) -> Result<ComputePipeline, Error> {
+        let compute_spv = compile_shader_stage(&desc.shader, &self.global_defs, &desc.defines, ShaderKind::Compute, ci)
+            .map_err(|err| pipeline_error(name, "compute", err))?;
 
         let cpci = ComputePipelineCreateInfo {
             set_layouts: &[],
@@ -942,56 +1100,51 @@ impl Engine {
             },
         };
 
-        match self.device.create_compute_pipeline(cpci) {
-            Ok(pipeline) => {
-                self.compute_pipelines.insert(name.to_string(), Ok(pipeline.clone()));
-                Ok(pipeline)
-            }
-            Err(err) => {
-                panic!("update_pipelines: failed to create compute pipeline: {:?}", err);
-            }
-        }
+        self.device
+            .create_compute_pipeline(cpci)
+            .map_err(|err| pipeline_error(name, "link", Error::VulkanError(Rc::new(err))))
     }
 
     pub fn create_mesh_render_pipeline(&mut self, name: &str, desc: MeshRenderPipelineDesc) -> Result<GraphicsPipeline, Error> {
-        if let Some(pipeline) = self.mesh_render_pipelines.get(name) {
-            return pipeline.clone();
+        let key = pipeline_key(
+            &[desc.task_shader.as_path(), desc.mesh_shader.as_path(), desc.fragment_shader.as_path()],
+            &self.global_defs,
+            &desc.defines,
+        );
+        if let Some(cached) = self.mesh_render_pipelines.get_mut(name) {
+            if cached.is_up_to_date(key) {
+                return cached.result.clone();
+            }
+            debug!("rebuilding mesh render pipeline `{name}`");
         }
 
-        let task_file_path = &desc.task_shader;
-        let mesh_file_path = &desc.mesh_shader;
-        let frag_file_path = &desc.fragment_shader;
+        let mut ci = CompilationInfo::default();
+        let result = self.build_mesh_render_pipeline(name, &desc, &mut ci);
+        let dependencies = ci.includes.into_iter().chain([
+            desc.task_shader.clone(),
+            desc.mesh_shader.clone(),
+            desc.fragment_shader.clone(),
+        ]);
+        self.mesh_render_pipelines
+            .insert(name.to_string(), CachedPipeline::new(result.clone(), key, dependencies));
+        result
+    }
+
+    fn build_mesh_render_pipeline(
+        &self,
+        name: &str,
+        desc: &MeshRenderPipelineDesc,
+        ci: &mut CompilationInfo,
+    ) -> Result<GraphicsPipeline, Error> {
         let gdefs = &self.global_defs;
         let defs = &desc.defines;
-        let mut ci = CompilationInfo::default();
 
-        let task_spv = match compile_shader_stage(&task_file_path, &gdefs, &defs, ShaderKind::Task, &mut ci) {
-            Ok(spv) => spv,
-            Err(err) => {
-                error!("failed to compile task shader: {err}");
-                let result = Err(err.into());
-                self.mesh_render_pipelines.insert(name.to_string(), result.clone());
-                return result;
-            }
-        };
-        let mesh_spv = match compile_shader_stage(&mesh_file_path, &gdefs, &defs, ShaderKind::Mesh, &mut ci) {
-            Ok(spv) => spv,
-            Err(err) => {
-                error!("failed to compile mesh shader: {err}");
-                let result = Err(err.into());
-                self.mesh_render_pipelines.insert(name.to_string(), result.clone());
-                return result;
-            }
-        };
-        let fragment_spv = match compile_shader_stage(&frag_file_path, &gdefs, &defs, ShaderKind::Fragment, &mut ci) {
-            Ok(spv) => spv,
-            Err(err) => {
-                error!("failed to compile fragment shader: {err}");
-                let result = Err(err.into());
-                self.mesh_render_pipelines.insert(name.to_string(), result.clone());
-                return result;
-            }
-        };
+        let task_spv = compile_shader_stage(&desc.task_shader, gdefs, defs, ShaderKind::Task, ci)
+            .map_err(|err| pipeline_error(name, "task", err))?;
+        let mesh_spv = compile_shader_stage(&desc.mesh_shader, gdefs, defs, ShaderKind::Mesh, ci)
+            .map_err(|err| pipeline_error(name, "mesh", err))?;
+        let fragment_spv = compile_shader_stage(&desc.fragment_shader, gdefs, defs, ShaderKind::Fragment, ci)
+            .map_err(|err| pipeline_error(name, "fragment", err))?;
 
         let gpci = GraphicsPipelineCreateInfo {
             set_layouts: &[],
@@ -1020,14 +1173,8 @@ impl Engine {
             },
         };
 
-        match self.device.create_graphics_pipeline(gpci) {
-            Ok(pipeline) => {
-                self.mesh_render_pipelines.insert(name.to_string(), Ok(pipeline.clone()));
-                Ok(pipeline)
-            }
-            Err(err) => {
-                panic!("update_pipelines: failed to create mesh render pipeline: {:?}", err);
-            }
-        }
+        self.device
+            .create_graphics_pipeline(gpci)
+            .map_err(|err| pipeline_error(name, "link", Error::VulkanError(Rc::new(err))))
     }
 }
@@ -0,0 +1,266 @@
+//! Library of stock compute passes: separable gaussian blur, 2x downsampling, image copy with format
+//! conversion, and depth resolve.
+//!
+//! The passes can be looked up by name with `StockPass::from_name`, so that plug-ins and scripts
+//! compose them without shipping their own shaders. Their pipelines are built and cached by the engine
+//! like the others, under a `stock_` prefix, and their scratch resources are kept between frames.
+use glam::uvec2;
+use graal::{prelude::*, Barrier, Buffer, ComputePipeline, ImageCopyBuffer, ImageCopyView, ImageDataLayout};
+
+use crate::{
+    engine::{ComputePipelineDesc, Engine, Error},
+    gpu_memory::{image_byte_size, MemoryCategory, MemoryTag},
+    shaders::shared::{BlurParams, ResampleParams, ResolveDepthParams, STOCK_PASS_WORKGROUP_SIZE},
+};
+
+/// Standard deviation of the blur when not specified in the pass name, in pixels.
+const DEFAULT_BLUR_SIGMA: f32 = 2.0;
+
+/// A stock pass that reads an image and writes another one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StockPass {
+    /// Separable gaussian blur. `sigma` is the standard deviation of the kernel, in pixels.
+    Blur { sigma: f32 },
+    /// Halves the size of the image with a 2x2 box filter. The output should be half the size of the input,
+    /// rounded up.
+    Downsample,
+    /// Copies the image, converting it to the format of the output.
+    Copy,
+}
+
+impl StockPass {
+    /// Names of the passes accepted by `from_name`.
+    pub const NAMES: &'static [&'static str] = &["blur", "downsample", "copy"];
+
+    /// Looks up a pass by name.
+    ///
+    /// The standard deviation of the blur can be given after a colon (e.g. `blur:4`).
+    pub fn from_name(name: &str) -> Option<StockPass> {
+        let (name, arg) = match name.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (name, None),
+        };
+        match (name, arg) {
+            ("blur", None) => Some(StockPass::Blur {
+                sigma: DEFAULT_BLUR_SIGMA,
+            }),
+            ("blur", Some(sigma)) => {
+                let sigma: f32 = sigma.parse().ok()?;
+                (sigma > 0.0).then_some(StockPass::Blur { sigma })
+            }
+            ("downsample", None) => Some(StockPass::Downsample),
+            ("copy", None) => Some(StockPass::Copy),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StockPass::Blur { .. } => "blur",
+            StockPass::Downsample => "downsample",
+            StockPass::Copy => "copy",
+        }
+    }
+}
+
+/// Scratch resources of the stock passes, reallocated when the image size changes.
+pub(super) struct PassScratch {
+    /// Result of the horizontal blur, input of the vertical one.
+    blur_image: Option<Image>,
+    /// Copy of the depth buffer readable from compute shaders.
+    depth_copy: Option<Buffer<[f32]>>,
+    blur_image_memory: MemoryTag,
+    depth_copy_memory: MemoryTag,
+}
+
+impl Default for PassScratch {
+    fn default() -> Self {
+        PassScratch {
+            blur_image: None,
+            depth_copy: None,
+            blur_image_memory: MemoryTag::new(MemoryCategory::RenderTargets, 0),
+            depth_copy_memory: MemoryTag::new(MemoryCategory::RenderTargets, 0),
+        }
+    }
+}
+
+impl PassScratch {
+    fn blur_image(&mut self, device: &Device, like: &Image) -> Image {
+        if let Some(ref image) = self.blur_image {
+            if image.width() == like.width() && image.height() == like.height() && image.format() == like.format() {
+                return image.clone();
+            }
+        }
+        let image = device.create_image(&ImageCreateInfo {
+            memory_location: MemoryLocation::GpuOnly,
+            type_: ImageType::Image2D,
+            usage: ImageUsage::STORAGE,
+            format: like.format(),
+            width: like.width(),
+            height: like.height(),
+            depth: 1,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: 1,
+        });
+        image.set_name("stock blur image");
+        self.blur_image_memory
+            .set_bytes(image_byte_size(like.format(), like.width(), like.height(), 1));
+        self.blur_image = Some(image.clone());
+        image
+    }
+
+    fn depth_copy(&mut self, device: &Device, width: u32, height: u32) -> Buffer<[f32]> {
+        let len = width as usize * height as usize;
+        if let Some(ref buffer) = self.depth_copy {
+            if buffer.len() == len {
+                return buffer.clone();
+            }
+        }
+        let buffer = device.create_array_buffer::<f32>(
+            BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+            len,
+        );
+        buffer.set_name("stock depth copy");
+        self.depth_copy_memory.set_bytes((len * size_of::<f32>()) as u64);
+        self.depth_copy = Some(buffer.clone());
+        buffer
+    }
+}
+
+/// Records a dispatch covering `width` x `height` pixels.
+pub(super) fn dispatch<T: Copy>(cmd: &mut CommandStream, pipeline: &ComputePipeline, params: &T, width: u32, height: u32) {
+    let mut encoder = cmd.begin_compute();
+    encoder.bind_compute_pipeline(pipeline);
+    encoder.push_constants(params);
+    encoder.dispatch(
+        width.div_ceil(STOCK_PASS_WORKGROUP_SIZE),
+        height.div_ceil(STOCK_PASS_WORKGROUP_SIZE),
+        1,
+    );
+    encoder.finish();
+}
+
+impl Engine {
+    pub(super) fn stock_pipeline(&mut self, shader: &str) -> Result<ComputePipeline, Error> {
+        self.create_compute_pipeline(
+            &format!("stock_{shader}"),
+            ComputePipelineDesc {
+                shader: format!("crates/fluff/shaders/{shader}.comp").into(),
+                defines: Default::default(),
+            },
+        )
+    }
+
+    /// Runs a stock pass from `input` to `output`.
+    ///
+    /// Both images must have the `STORAGE` usage, and be distinct.
+    pub fn run_pass(&mut self, cmd: &mut CommandStream, pass: StockPass, input: &Image, output: &Image) -> Result<(), Error> {
+        let input_view = input.create_top_level_view();
+        let output_view = output.create_top_level_view();
+        cmd.reference_resource(&input_view);
+        cmd.reference_resource(&output_view);
+        let resample = ResampleParams {
+            input_size: uvec2(input.width(), input.height()),
+            output_size: uvec2(output.width(), output.height()),
+            input_image: input_view.device_image_handle(),
+            output_image: output_view.device_image_handle(),
+        };
+
+        match pass {
+            StockPass::Blur { sigma } => {
+                let pipeline = self.stock_pipeline("blur")?;
+                let bytes = image_byte_size(input.format(), input.width(), input.height(), 1);
+                self.use_transient(pass.name(), "stock blur image", bytes)?;
+                let device = cmd.device().clone();
+                let temp = self.scratch.blur_image(&device, input);
+                let temp_view = temp.create_top_level_view();
+                cmd.reference_resource(&temp_view);
+                let (width, height) = (input.width(), input.height());
+                let params = BlurParams {
+                    image_size: uvec2(width, height),
+                    input_image: input_view.device_image_handle(),
+                    output_image: temp_view.device_image_handle(),
+                    horizontal: 1,
+                    // the kernel is truncated at 3 sigmas
+                    radius: (3.0 * sigma).ceil() as u32,
+                    sigma,
+                };
+                cmd.barrier(Barrier::new().shader_read_image(input).shader_write_image(&temp));
+                dispatch(cmd, &pipeline, &params, width, height);
+                cmd.barrier(Barrier::new().shader_read_image(&temp).shader_write_image(output));
+                let params = BlurParams {
+                    input_image: temp_view.device_image_handle(),
+                    output_image: output_view.device_image_handle(),
+                    horizontal: 0,
+                    ..params
+                };
+                dispatch(cmd, &pipeline, &params, width, height);
+            }
+            StockPass::Downsample | StockPass::Copy => {
+                let shader = if pass == StockPass::Copy { "copy_image" } else { "downsample" };
+                let pipeline = self.stock_pipeline(shader)?;
+                cmd.barrier(Barrier::new().shader_read_image(input).shader_write_image(output));
+                dispatch(cmd, &pipeline, &resample, output.width(), output.height());
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the stock pass with the specified name (see `StockPass::from_name`).
+    pub fn run_named_pass(&mut self, cmd: &mut CommandStream, name: &str, input: &Image, output: &Image) -> Result<(), Error> {
+        let pass = StockPass::from_name(name).ok_or_else(|| Error::ResourceNotFound(format!("stock pass `{name}`")))?;
+        self.run_pass(cmd, pass, input, output)
+    }
+
+    /// Writes the view-space depth of each pixel of `depth` to the red channel of `output`.
+    ///
+    /// `output` must have the same size as `depth`, a floating-point format and the `STORAGE` usage.
+    pub fn resolve_depth(
+        &mut self,
+        cmd: &mut CommandStream,
+        depth: &Image,
+        output: &Image,
+        near_plane: f32,
+        far_plane: f32,
+    ) -> Result<(), Error> {
+        let pipeline = self.stock_pipeline("resolve_depth")?;
+        let (width, height) = (depth.width(), depth.height());
+        let bytes = width as u64 * height as u64 * size_of::<f32>() as u64;
+        self.use_transient("resolve_depth", "stock depth copy", bytes)?;
+        let device = cmd.device().clone();
+        let depth_copy = self.scratch.depth_copy(&device, width, height);
+        let output_view = output.create_top_level_view();
+        cmd.reference_resource(&output_view);
+        cmd.reference_resource(&depth_copy);
+
+        cmd.copy_image_to_buffer(
+            ImageCopyView {
+                image: depth,
+                mip_level: 0,
+                origin: vk::Offset3D { x: 0, y: 0, z: 0 },
+                aspect: vk::ImageAspectFlags::DEPTH,
+            },
+            ImageCopyBuffer {
+                buffer: &depth_copy.untyped,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    row_length: Some(width),
+                    image_height: Some(height),
+                },
+            },
+            vk::Extent3D { width, height, depth: 1 },
+        );
+        cmd.barrier(Barrier::new().shader_storage_read().shader_write_image(output));
+        let params = ResolveDepthParams {
+            image_size: uvec2(width, height),
+            depth: depth_copy.device_address(),
+            output_image: output_view.device_image_handle(),
+            near_plane,
+            far_plane,
+        };
+        dispatch(cmd, &pipeline, &params, width, height);
+        Ok(())
+    }
+}
@@ -1,11 +1,11 @@
-use crate::engine::Error;
+use crate::engine::{Error, ShaderDiagnostic};
 use graal::{
     get_shader_compiler, shaderc,
     shaderc::{EnvVersion, ShaderKind, SpirvVersion, TargetEnv},
     BufferAccess, ImageAccess,
 };
 use spirv_reflect::types::ReflectTypeFlags;
-use std::{collections::BTreeMap, path::Path};
+use std::{cell::RefCell, collections::BTreeMap, path::{Path, PathBuf}};
 use std::rc::Rc;
 use graal::shaderc::OptimizationLevel;
 use tracing::{error, warn};
@@ -15,6 +15,55 @@ pub(super) struct CompilationInfo {
     pub(super) used_images: BTreeMap<String, ImageAccess>,
     pub(super) used_buffers: BTreeMap<String, BufferAccess>,
     pub(super) push_cst_size: usize,
+    /// Files included by the compiled shaders (transitively).
+    pub(super) includes: Vec<PathBuf>,
+}
+
+/// Number of lines shown before and after the offending line in diagnostic excerpts.
+const EXCERPT_CONTEXT_LINES: u32 = 2;
+
+/// Reads the lines around `line` (1-based) in the specified file.
+fn read_excerpt(file: &str, line: u32) -> Vec<(u32, String)> {
+    let Ok(source) = std::fs::read_to_string(file) else {
+        return vec![];
+    };
+    let first = line.saturating_sub(EXCERPT_CONTEXT_LINES).max(1);
+    source
+        .lines()
+        .zip(1..)
+        .skip(first as usize - 1)
+        .take_while(|(_, n)| *n <= line + EXCERPT_CONTEXT_LINES)
+        .map(|(text, n)| (n, text.to_string()))
+        .collect()
+}
+
+/// Parses the messages of the shader compiler.
+///
+/// Lines have the form `<file>:<line>: error: <message>`. The file is the one that contains the error,
+/// which may be an included file. File names may contain colons (drive letters).
+fn parse_compiler_log(log: &str) -> Vec<ShaderDiagnostic> {
+    let mut diagnostics = vec![];
+    for line in log.lines() {
+        let (location, is_error, message) = if let Some((location, message)) = line.split_once(": error: ") {
+            (location, true, message)
+        } else if let Some((location, message)) = line.split_once(": warning: ") {
+            (location, false, message)
+        } else {
+            continue;
+        };
+        let (file, line) = match location.rsplit_once(':') {
+            Some((file, line)) if line.parse::<u32>().is_ok() => (file, line.parse().ok()),
+            _ => (location, None),
+        };
+        diagnostics.push(ShaderDiagnostic {
+            file: file.to_string(),
+            line,
+            is_error,
+            message: message.trim().to_string(),
+            excerpt: line.map(|line| read_excerpt(file, line)).unwrap_or_default(),
+        });
+    }
+    diagnostics
 }
 
 pub(super) fn compile_shader_stage(
@@ -38,6 +87,18 @@ pub(super) fn compile_shader_stage(
         }
     };
 
+    // WGSL sources go through naga, which has no preprocessor
+    let is_wgsl = file_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("wgsl"));
+    if is_wgsl {
+        if !defines.is_empty() {
+            warn!("`{display_path}`: WGSL shaders have no preprocessor, defines are ignored");
+        }
+        let spirv = compile_wgsl(file_path, &source_content, shader_kind)?;
+        return reflect_stage(file_path, shader_kind, bytemuck::cast_slice(&spirv), info);
+    }
+
     // determine include path
     // this is the current directory if the shader is embedded, otherwise it's the parent
     // directory of the shader file
@@ -59,9 +120,13 @@ pub(super) fn compile_shader_stage(
     for (key, value) in defines.iter() {
         options.add_macro_definition(key, Some(value));
     }
+    let includes = Rc::new(RefCell::new(Vec::new()));
+    let includes_clone = includes.clone();
     options.set_include_callback(move |requested_source, _type, _requesting_source, _include_depth| {
         let mut path = base_include_path.clone();
         path.push(requested_source);
+        // record the dependency even if it can't be read, so that creating the file triggers a rebuild
+        includes_clone.borrow_mut().push(path.clone());
         let content = match std::fs::read_to_string(&path) {
             Ok(content) => content,
             Err(e) => return Err(e.to_string()),
@@ -101,8 +166,17 @@ pub(super) fn compile_shader_stage(
     }
 
     let compiler = get_shader_compiler();
-    let compilation_artifact = match compiler.compile_into_spirv(&source_content, shader_kind, &display_path, "main", Some(&options)) {
+    let compilation_result = compiler.compile_into_spirv(&source_content, shader_kind, &display_path, "main", Some(&options));
+    info.includes.extend(includes.borrow().iter().cloned());
+    let compilation_artifact = match compilation_result {
         Ok(artifact) => artifact,
+        Err(shaderc::Error::CompilationError(_, log)) => {
+            error!("failed to compile shader `{display_path}`:\n{log}");
+            return Err(Error::ShaderCompile {
+                path: file_path.to_path_buf(),
+                diagnostics: parse_compiler_log(&log).into(),
+            });
+        }
         Err(err) => {
             error!("failed to compile shader `{display_path}`: {err}");
             return Err(Rc::new(err).into());
@@ -116,6 +190,18 @@ pub(super) fn compile_shader_stage(
         }
     }
 
+    reflect_stage(file_path, shader_kind, compilation_artifact.as_binary_u8(), info)
+}
+
+/// Dumps the SPIR-V of a compiled stage next to the source, and reflects the size of its push constants.
+fn reflect_stage(
+    file_path: &Path,
+    shader_kind: ShaderKind,
+    spirv: &[u8],
+    info: &mut CompilationInfo,
+) -> Result<Vec<u32>, Error> {
+    let display_path = file_path.display().to_string();
+
     // dump compilation artifact to disk
     let stage_ext = match shader_kind {
         ShaderKind::Vertex => "vert",
@@ -129,11 +215,14 @@ pub(super) fn compile_shader_stage(
         _ => "unknown",
     };
     let dump_path = file_path.with_extension(format!("{stage_ext}.spv"));
-    std::fs::write(&dump_path, &compilation_artifact.as_binary_u8()).unwrap();
+    if let Err(err) = std::fs::write(&dump_path, spirv) {
+        warn!("could not write `{}`: {err}", dump_path.display());
+    }
 
 
     // remap resource bindings
-    let module = spirv_reflect::create_shader_module(compilation_artifact.as_binary_u8()).unwrap();
+    let module = spirv_reflect::create_shader_module(spirv)
+        .map_err(|err| Error::ShaderReflection(format!("`{display_path}`: {err}")))?;
     /*let descriptor_bindings = module.enumerate_descriptor_bindings(Some("main")).unwrap();
     for refl in descriptor_bindings.iter() {
         let ty = refl.descriptor_type;
@@ -202,7 +291,9 @@ pub(super) fn compile_shader_stage(
     }*/
 
     // reflect push constants
-    let push_constants = module.enumerate_push_constant_blocks(Some("main")).unwrap();
+    let push_constants = module
+        .enumerate_push_constant_blocks(Some("main"))
+        .map_err(|err| Error::ShaderReflection(format!("`{display_path}`: {err}")))?;
     if push_constants.len() > 1 {
         warn!("`{display_path}`: multiple push constant blocks found; only the first one will be used");
     }
@@ -281,3 +372,93 @@ pub(super) fn compile_shader_stage(
 
     Ok(module.get_code())
 }
+
+/// Converts a naga diagnostic location to a `ShaderDiagnostic`.
+#[cfg(feature = "wgsl")]
+fn wgsl_diagnostic(file_path: &Path, location: Option<naga::SourceLocation>, message: String) -> ShaderDiagnostic {
+    let file = file_path.display().to_string();
+    let line = location.map(|location| location.line_number);
+    ShaderDiagnostic {
+        excerpt: line.map(|line| read_excerpt(&file, line)).unwrap_or_default(),
+        file,
+        line,
+        is_error: true,
+        message,
+    }
+}
+
+/// Compiles a WGSL source to SPIR-V with naga.
+///
+/// The source may contain entry points for several stages; the one for `shader_kind` is selected (the
+/// one named `main` if there are several) and renamed to `main`, the entry point used by pipelines.
+#[cfg(feature = "wgsl")]
+fn compile_wgsl(file_path: &Path, source: &str, shader_kind: ShaderKind) -> Result<Vec<u32>, Error> {
+    use naga::back::spv;
+
+    let display_path = file_path.display().to_string();
+    let compile_error = |diagnostic: ShaderDiagnostic| {
+        error!("failed to compile shader `{display_path}`: {}", diagnostic.message);
+        Error::ShaderCompile {
+            path: file_path.to_path_buf(),
+            diagnostics: vec![diagnostic].into(),
+        }
+    };
+
+    let stage = match shader_kind {
+        ShaderKind::Vertex => naga::ShaderStage::Vertex,
+        ShaderKind::Fragment => naga::ShaderStage::Fragment,
+        ShaderKind::Compute => naga::ShaderStage::Compute,
+        _ => {
+            return Err(Error::UnsupportedFeature(format!(
+                "`{display_path}`: {shader_kind:?} shaders can't be written in WGSL"
+            )))
+        }
+    };
+
+    let mut module = naga::front::wgsl::parse_str(source)
+        .map_err(|err| compile_error(wgsl_diagnostic(file_path, err.location(source), err.message().to_string())))?;
+
+    // select the entry point of the stage
+    let candidates: Vec<usize> = (0..module.entry_points.len())
+        .filter(|&i| module.entry_points[i].stage == stage)
+        .collect();
+    let entry_point = match candidates[..] {
+        [i] => i,
+        _ => candidates
+            .iter()
+            .copied()
+            .find(|&i| module.entry_points[i].name == "main")
+            .ok_or_else(|| {
+                compile_error(wgsl_diagnostic(
+                    file_path,
+                    None,
+                    format!("expected a single {stage:?} entry point, or one named `main`"),
+                ))
+            })?,
+    };
+    module.entry_points[entry_point].name = "main".to_string();
+
+    let module_info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(&module)
+        .map_err(|err| compile_error(wgsl_diagnostic(file_path, err.location(source), err.emit_to_string(source))))?;
+
+    let options = spv::Options {
+        lang_version: (1, 5),
+        flags: spv::WriterFlags::DEBUG | spv::WriterFlags::LABEL_VARYINGS,
+        ..Default::default()
+    };
+    let pipeline_options = spv::PipelineOptions {
+        shader_stage: stage,
+        entry_point: "main".to_string(),
+    };
+    spv::write_vec(&module, &module_info, &options, Some(&pipeline_options))
+        .map_err(|err| compile_error(wgsl_diagnostic(file_path, None, err.to_string())))
+}
+
+#[cfg(not(feature = "wgsl"))]
+fn compile_wgsl(file_path: &Path, _source: &str, _shader_kind: ShaderKind) -> Result<Vec<u32>, Error> {
+    Err(Error::UnsupportedFeature(format!(
+        "`{}`: WGSL shaders require the `wgsl` feature",
+        file_path.display()
+    )))
+}
@@ -0,0 +1,143 @@
+//! Accounting of the transient resources used by each pass during a frame.
+//!
+//! Passes declare the scratch images and buffers they use with `Engine::use_transient`, before
+//! allocating them. The engine sums them by pass and by frame, keeps the high-water marks, and fails
+//! the pass if the frame total goes over the transient budget, so that a pass that suddenly needs
+//! much more memory is noticed instead of silently increasing VRAM use.
+use tracing::error;
+
+use crate::engine::{Engine, Error};
+
+/// Transient memory used by a pass.
+#[derive(Clone, Debug)]
+pub struct PassMemory {
+    pub pass: String,
+    /// Bytes used in the current frame.
+    pub bytes: u64,
+    /// Largest number of bytes used in a frame.
+    pub peak_bytes: u64,
+}
+
+/// Transient memory used by the passes of the engine.
+#[derive(Default)]
+pub struct TransientMemory {
+    /// Maximum number of bytes of transient resources in a frame, if any.
+    budget: Option<u64>,
+    /// Passes that used transient resources, in the order of their first use.
+    passes: Vec<PassMemory>,
+    frame_bytes: u64,
+    /// Largest number of bytes used in a frame.
+    peak_frame_bytes: u64,
+    /// Error of the first pass that went over the budget in this frame.
+    exceeded: Option<Error>,
+    /// Whether the budget was exceeded in the previous frame, to report the error only once.
+    was_exceeded: bool,
+}
+
+impl TransientMemory {
+    pub fn budget(&self) -> Option<u64> {
+        self.budget
+    }
+
+    pub fn passes(&self) -> &[PassMemory] {
+        &self.passes
+    }
+
+    /// Bytes used in the current frame.
+    pub fn frame_bytes(&self) -> u64 {
+        self.frame_bytes
+    }
+
+    /// High-water mark of the bytes used in a frame.
+    pub fn peak_frame_bytes(&self) -> u64 {
+        self.peak_frame_bytes
+    }
+
+    /// Returns the error of the first pass that went over the budget in the current frame.
+    pub fn exceeded(&self) -> Option<&Error> {
+        self.exceeded.as_ref()
+    }
+
+    /// Resets the high-water marks.
+    pub fn reset_peaks(&mut self) {
+        self.peak_frame_bytes = self.frame_bytes;
+        for pass in self.passes.iter_mut() {
+            pass.peak_bytes = pass.bytes;
+        }
+    }
+
+    fn begin_frame(&mut self) {
+        self.frame_bytes = 0;
+        for pass in self.passes.iter_mut() {
+            pass.bytes = 0;
+        }
+        self.was_exceeded = self.exceeded.take().is_some();
+    }
+
+    fn add(&mut self, pass: &str, resource: &str, bytes: u64) -> Result<(), Error> {
+        if let Some(budget) = self.budget {
+            if self.frame_bytes + bytes > budget {
+                let err = Error::TransientBudgetExceeded {
+                    pass: pass.to_string(),
+                    resource: resource.to_string(),
+                    bytes,
+                    frame_bytes: self.frame_bytes,
+                    budget,
+                };
+                if self.exceeded.is_none() {
+                    if !self.was_exceeded {
+                        error!("{err}");
+                    }
+                    self.exceeded = Some(err.clone());
+                }
+                return Err(err);
+            }
+        }
+
+        let index = match self.passes.iter().position(|p| p.pass == pass) {
+            Some(index) => index,
+            None => {
+                self.passes.push(PassMemory {
+                    pass: pass.to_string(),
+                    bytes: 0,
+                    peak_bytes: 0,
+                });
+                self.passes.len() - 1
+            }
+        };
+        let entry = &mut self.passes[index];
+        entry.bytes += bytes;
+        entry.peak_bytes = entry.peak_bytes.max(entry.bytes);
+        self.frame_bytes += bytes;
+        self.peak_frame_bytes = self.peak_frame_bytes.max(self.frame_bytes);
+        Ok(())
+    }
+}
+
+impl Engine {
+    /// Starts accounting the transient resources of a new frame.
+    pub fn begin_frame(&mut self) {
+        self.transient.begin_frame();
+    }
+
+    /// Sets the maximum number of bytes of transient resources used in a frame. `None` disables the check.
+    pub fn set_transient_budget(&mut self, budget: Option<u64>) {
+        self.transient.budget = budget;
+    }
+
+    pub fn transient_memory(&self) -> &TransientMemory {
+        &self.transient
+    }
+
+    pub fn transient_memory_mut(&mut self) -> &mut TransientMemory {
+        &mut self.transient
+    }
+
+    /// Declares a transient resource of `bytes` bytes used by `pass` in the current frame.
+    ///
+    /// Call this before allocating the resource: it fails with `Error::TransientBudgetExceeded` if the
+    /// resource doesn't fit in the budget, in which case the pass shouldn't run.
+    pub fn use_transient(&mut self, pass: &str, resource: &str, bytes: u64) -> Result<(), Error> {
+        self.transient.add(pass, resource, bytes)
+    }
+}
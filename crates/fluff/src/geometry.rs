@@ -0,0 +1,214 @@
+//! Display of the meshes (e.g. scalp meshes) and point clouds of the scene.
+//!
+//! Useful to check that grooms are aligned with the surface they're emitted from.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use glam::Vec3;
+use graal::{prelude::*, vk, DeviceAddress, ImageView, RenderPassInfo};
+
+use crate::{
+    engine::{color_attachment, depth_stencil_attachment, Engine, Error, LoadHint, MeshRenderPipelineDesc},
+    overlay::OverlayRenderer,
+    scene::{Scene, SceneObjectKind},
+    shaders::shared::{DrawGeometryPushConstants, SceneParams, SUBGROUP_SIZE},
+};
+
+/// Maximum number of normals drawn in the overlay.
+const MAX_DISPLAYED_NORMALS: usize = 20000;
+
+/// Display settings of scene objects.
+pub struct GeometryDisplay {
+    /// Names of the objects that are not displayed.
+    pub hidden: BTreeSet<String>,
+    /// Color meshes by their normals.
+    pub color_by_normals: bool,
+    /// Draw vertex normals as lines.
+    pub show_normals: bool,
+    /// Length of the normal lines, in scene units.
+    pub normal_length: f32,
+    /// Radius of point sprites, in scene units if `size_attenuation` is set, otherwise in pixels.
+    pub point_size: f32,
+    pub size_attenuation: bool,
+    /// Draw the bounding boxes of the curves and of the visible objects.
+    pub show_bounds: bool,
+}
+
+impl Default for GeometryDisplay {
+    fn default() -> Self {
+        GeometryDisplay {
+            hidden: BTreeSet::new(),
+            color_by_normals: false,
+            show_normals: false,
+            normal_length: 0.05,
+            point_size: 2.0,
+            size_attenuation: false,
+            show_bounds: false,
+        }
+    }
+}
+
+impl GeometryDisplay {
+    fn create_pipeline(engine: &mut Engine, name: &str, points: bool) -> Result<GraphicsPipeline, Error> {
+        let mut defines = BTreeMap::new();
+        if points {
+            defines.insert("POINTS".to_string(), "1".to_string());
+        }
+        engine.create_mesh_render_pipeline(
+            name,
+            MeshRenderPipelineDesc {
+                task_shader: PathBuf::from("crates/fluff/shaders/geometry.glsl"),
+                mesh_shader: PathBuf::from("crates/fluff/shaders/geometry.glsl"),
+                fragment_shader: PathBuf::from("crates/fluff/shaders/geometry.glsl"),
+                defines,
+                color_targets: vec![ColorTargetState {
+                    format: Format::R16G16B16A16_SFLOAT,
+                    ..Default::default()
+                }],
+                rasterization_state: Default::default(),
+                depth_stencil_state: Some(DepthStencilState {
+                    format: Format::D32_SFLOAT,
+                    depth_write_enable: true,
+                    depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                    stencil_state: StencilState::default(),
+                }),
+                multisample_state: Default::default(),
+            },
+        )
+    }
+
+    /// Draws the visible meshes and point clouds of the given frame.
+    pub fn render(
+        &self,
+        cmd: &mut CommandStream,
+        engine: &mut Engine,
+        scene: &Scene,
+        frame: usize,
+        scene_params: DeviceAddress<SceneParams>,
+        color_target: &ImageView,
+        depth_target: &ImageView,
+    ) -> Result<(), Error> {
+        let objects: Vec<_> = scene.frames[frame]
+            .objects
+            .iter()
+            .filter(|object| !self.hidden.contains(&object.name) && object.count > 0)
+            .collect();
+        if objects.is_empty() {
+            return Ok(());
+        }
+
+        let mesh_pipeline = Self::create_pipeline(engine, "draw_scene_meshes", false)?;
+        let point_pipeline = Self::create_pipeline(engine, "draw_scene_points", true)?;
+
+        let mut encoder = cmd.begin_rendering(RenderPassInfo {
+            color_attachments: &[color_attachment(color_target, LoadHint::Load)],
+            depth_stencil_attachment: Some(depth_stencil_attachment(depth_target, LoadHint::Load, LoadHint::Load)),
+        });
+        for object in objects {
+            encoder.bind_graphics_pipeline(match object.kind {
+                SceneObjectKind::Mesh => &mesh_pipeline,
+                SceneObjectKind::Points => &point_pipeline,
+            });
+            encoder.push_constants(&DrawGeometryPushConstants {
+                mesh_vertices: scene.mesh_vertex_buffer.device_address(),
+                points: scene.point_buffer.device_address(),
+                scene_params,
+                base_index: object.start,
+                count: object.count,
+                point_size: self.point_size,
+                size_attenuation: self.size_attenuation as u32,
+                color_by_normals: self.color_by_normals as u32,
+            });
+            encoder.draw_mesh_tasks(object.count.div_ceil(SUBGROUP_SIZE), 1, 1);
+        }
+        encoder.finish();
+        Ok(())
+    }
+
+    /// Draws the vertex normals of the visible meshes in the overlay.
+    pub fn draw_normals(&self, overlay: &mut OverlayRenderer, scene: &Scene, frame: usize) {
+        if !self.show_normals {
+            return;
+        }
+        let vertices = scene.mesh_vertex_buffer.as_slice();
+        let mut remaining = MAX_DISPLAYED_NORMALS;
+        for object in scene.frames[frame].objects.iter() {
+            if object.kind != SceneObjectKind::Mesh || self.hidden.contains(&object.name) {
+                continue;
+            }
+            let start = object.start as usize;
+            let end = start + 3 * object.count as usize;
+            // vertices are duplicated for each triangle: skip shared positions with identical normals
+            let mut drawn = BTreeSet::new();
+            for v in vertices[start..end].iter() {
+                if remaining == 0 {
+                    return;
+                }
+                let key = (v.pos.map(f32::to_bits), v.normal.map(f32::to_bits));
+                if !drawn.insert(key) {
+                    continue;
+                }
+                let p = Vec3::from(v.pos);
+                let n = Vec3::from(v.normal);
+                let tip = p + n * self.normal_length;
+                overlay.line(p.as_dvec3(), tip.as_dvec3(), [80, 160, 255, 255], [80, 160, 255, 0]);
+                remaining -= 1;
+            }
+        }
+    }
+
+    /// Draws the bounding boxes of the curves and of the visible objects in the overlay.
+    pub fn draw_bounds(&self, overlay: &mut OverlayRenderer, scene: &Scene, frame: usize) {
+        if !self.show_bounds {
+            return;
+        }
+        let frame = &scene.frames[frame];
+        if let Some(ref bounds) = frame.curve_bounds {
+            overlay.aabb(bounds, [120, 220, 120, 255]);
+        }
+        for object in frame.objects.iter() {
+            if !self.hidden.contains(&object.name) {
+                overlay.aabb(&object.bounds, [255, 200, 80, 255]);
+            }
+        }
+    }
+
+    /// Per-object visibility toggles and display settings.
+    pub fn ui(&mut self, ui: &mut egui::Ui, scene: Option<&Scene>, frame: usize) {
+        if let Some(scene) = scene {
+            let objects = &scene.frames[frame].objects;
+            if objects.is_empty() {
+                ui.label("No meshes or point clouds in this frame");
+            }
+            for object in objects.iter() {
+                let mut visible = !self.hidden.contains(&object.name);
+                let label = match object.kind {
+                    SceneObjectKind::Mesh => format!("{} ({} triangles)", object.name, object.count),
+                    SceneObjectKind::Points => format!("{} ({} points)", object.name, object.count),
+                };
+                if ui.checkbox(&mut visible, label).changed() {
+                    if visible {
+                        self.hidden.remove(&object.name);
+                    } else {
+                        self.hidden.insert(object.name.clone());
+                    }
+                }
+            }
+        }
+        ui.checkbox(&mut self.color_by_normals, "Color meshes by normals");
+        ui.checkbox(&mut self.show_bounds, "Show bounding boxes")
+            .on_hover_text("Curves in green, meshes and point clouds in orange");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show_normals, "Show normals");
+            ui.add_enabled(
+                self.show_normals,
+                egui::Slider::new(&mut self.normal_length, 0.001..=1.0).logarithmic(true).text("Length"),
+            );
+        });
+        ui.add(egui::Slider::new(&mut self.point_size, 0.001..=20.0).logarithmic(true).text("Point Size"));
+        ui.checkbox(&mut self.size_attenuation, "Point size attenuation")
+            .on_hover_text("Point size is in scene units instead of pixels");
+    }
+}
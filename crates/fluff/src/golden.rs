@@ -0,0 +1,332 @@
+//! `fluff golden`: golden-image regression tests of the render pipelines.
+//!
+//! Usage: `fluff golden [--update] [--out <dir>] [--case <name>] [<cases-dir>]`
+//!
+//! Each case in `<cases-dir>/cases.json` (default `data/golden`) renders a small scene headlessly, with
+//! the default settings and no plug-ins, optionally runs a chain of stock passes on the result, and
+//! compares it with `<cases-dir>/<name>.exr`. Images are compared in OkLab: a pixel differs if its
+//! distance (×100) exceeds the tolerance of the case, and the case fails if more than
+//! `max_bad_fraction` of the pixels differ. The rendered image and a diff image of failed cases are
+//! written to the output directory (default `target/golden`).
+//!
+//! `--update` replaces the golden images with the rendered ones. Review the changes before committing them.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use graal::{prelude::*, ImageCopyBuffer, ImageCopyView, ImageDataLayout};
+use image::{DynamicImage, Rgb, RgbImage, Rgba32FImage};
+
+use crate::app::App;
+
+/// A golden image test case, in `cases.json`.
+#[derive(Clone, Debug, serde::Deserialize)]
+struct GoldenCase {
+    name: String,
+    /// Lua script setting up the scene (see `script`), relative to the cases directory.
+    #[serde(default)]
+    script: Option<PathBuf>,
+    #[serde(default = "default_size")]
+    width: u32,
+    #[serde(default = "default_size")]
+    height: u32,
+    /// Curve rendering mode (`bin`, `oit`, `oit2` or `ribbons`).
+    #[serde(default = "default_render_mode")]
+    render_mode: String,
+    /// Number of frames rendered before the capture, so that temporal effects settle.
+    #[serde(default = "default_frames")]
+    frames: u32,
+    /// Stock passes applied to the rendered frame, in order (see `StockPass::from_name`).
+    #[serde(default)]
+    passes: Vec<String>,
+    /// Maximum OkLab distance (×100) between the pixels of the rendered and golden images.
+    #[serde(default = "default_tolerance")]
+    tolerance: f32,
+    /// Fraction of pixels allowed to exceed the tolerance.
+    #[serde(default = "default_max_bad_fraction")]
+    max_bad_fraction: f32,
+}
+
+fn default_size() -> u32 {
+    256
+}
+
+fn default_render_mode() -> String {
+    "bin".to_string()
+}
+
+fn default_frames() -> u32 {
+    4
+}
+
+fn default_tolerance() -> f32 {
+    2.0
+}
+
+fn default_max_bad_fraction() -> f32 {
+    0.001
+}
+
+struct GoldenOptions {
+    cases_dir: PathBuf,
+    out_dir: PathBuf,
+    update: bool,
+    /// Only run the cases with these names.
+    filter: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<GoldenOptions, String> {
+    let mut options = GoldenOptions {
+        cases_dir: PathBuf::from("data/golden"),
+        out_dir: PathBuf::from("target/golden"),
+        update: false,
+        filter: vec![],
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--update" => options.update = true,
+            "--out" => {
+                let path = args.next().ok_or("missing value for --out")?;
+                options.out_dir = PathBuf::from(path);
+            }
+            "--case" => {
+                let name = args.next().ok_or("missing value for --case")?;
+                options.filter.push(name.clone());
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+            _ => options.cases_dir = PathBuf::from(arg),
+        }
+    }
+    Ok(options)
+}
+
+/// Result of the comparison of a rendered image with its golden image.
+struct Comparison {
+    /// Largest OkLab distance (×100) between two pixels.
+    max_distance: f32,
+    /// Number of pixels whose distance exceeds the tolerance.
+    bad_pixels: usize,
+    /// Diff image: differing pixels in red over the darkened golden image.
+    diff: RgbImage,
+}
+
+fn oklab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(|c| c.max(0.0));
+    let l = (0.4122215 * r + 0.5363325 * g + 0.05144599 * b).cbrt();
+    let m = (0.2119035 * r + 0.6806995 * g + 0.107397 * b).cbrt();
+    let s = (0.08830246 * r + 0.2817188 * g + 0.6299787 * b).cbrt();
+    [
+        0.2104543 * l + 0.7936178 * m - 0.004072047 * s,
+        1.977998 * l - 2.428592 * m + 0.4505937 * s,
+        0.02590404 * l + 0.7827718 * m - 0.8086758 * s,
+    ]
+}
+
+/// Compares two images of the same size in OkLab. Alpha is ignored.
+fn compare(actual: &Rgba32FImage, golden: &Rgba32FImage, tolerance: f32) -> Comparison {
+    let mut max_distance = 0.0f32;
+    let mut bad_pixels = 0;
+    let mut diff = RgbImage::new(golden.width(), golden.height());
+    for ((a, g), d) in actual.pixels().zip(golden.pixels()).zip(diff.pixels_mut()) {
+        let la = oklab([a[0], a[1], a[2]]);
+        let lg = oklab([g[0], g[1], g[2]]);
+        let distance = 100.0 * ((la[0] - lg[0]).powi(2) + (la[1] - lg[1]).powi(2) + (la[2] - lg[2]).powi(2)).sqrt();
+        max_distance = max_distance.max(distance);
+        let background = (lg[0].clamp(0.0, 1.0) * 80.0) as u8;
+        *d = if distance > tolerance {
+            bad_pixels += 1;
+            let intensity = (distance / (4.0 * tolerance)).clamp(0.25, 1.0);
+            Rgb([(255.0 * intensity) as u8, 0, 0])
+        } else {
+            Rgb([background; 3])
+        };
+    }
+    Comparison {
+        max_distance,
+        bad_pixels,
+        diff,
+    }
+}
+
+/// Copies an `R32G32B32A32_SFLOAT` image to host memory, waiting for the GPU.
+fn read_back(device: &Device, cmd: &mut CommandStream, image: &Image) -> Result<Rgba32FImage, String> {
+    let (width, height) = (image.width(), image.height());
+    let len = width as usize * height as usize * 4;
+    let buffer = device.create_array_buffer::<f32>(BufferUsage::TRANSFER_DST, MemoryLocation::CpuToGpu, len);
+    buffer.set_name("golden readback");
+    cmd.copy_image_to_buffer(
+        ImageCopyView {
+            image,
+            mip_level: 0,
+            origin: vk::Offset3D { x: 0, y: 0, z: 0 },
+            aspect: vk::ImageAspectFlags::COLOR,
+        },
+        ImageCopyBuffer {
+            buffer: &buffer.untyped,
+            layout: ImageDataLayout {
+                offset: 0,
+                row_length: Some(width),
+                image_height: Some(height),
+            },
+        },
+        vk::Extent3D { width, height, depth: 1 },
+    );
+    cmd.flush(&[], &[]).map_err(|err| format!("failed to submit commands: {err}"))?;
+    // SAFETY: no other thread uses the device
+    unsafe {
+        device
+            .raw()
+            .device_wait_idle()
+            .map_err(|err| format!("failed to wait for the device: {err}"))?;
+    }
+    // SAFETY: the buffer is host-visible and the GPU is done with it
+    let data = unsafe { std::slice::from_raw_parts(buffer.as_mut_ptr() as *const f32, len) }.to_vec();
+    Ok(Rgba32FImage::from_raw(width, height, data).expect("invalid readback size"))
+}
+
+/// Renders a case, and returns the final image.
+fn render_case(device: &Device, cmd: &mut CommandStream, cases_dir: &Path, case: &GoldenCase) -> Result<Rgba32FImage, String> {
+    let (width, height) = (case.width, case.height);
+    let mut app = App::new_headless(device, width, height, Format::R16G16B16A16_SFLOAT);
+    app.resize(device, width, height);
+    if !app.set_render_mode(&case.render_mode) {
+        return Err(format!("unknown render mode `{}`", case.render_mode));
+    }
+    if let Some(ref script) = case.script {
+        app.run_script_file(&cases_dir.join(script));
+    }
+
+    let target = device.create_image(&ImageCreateInfo {
+        memory_location: MemoryLocation::GpuOnly,
+        type_: ImageType::Image2D,
+        usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST,
+        format: Format::R32G32B32A32_SFLOAT,
+        width,
+        height,
+        depth: 1,
+        mip_levels: 1,
+        array_layers: 1,
+        samples: 1,
+    });
+    target.set_name("golden target");
+    for _ in 0..case.frames.max(1) {
+        app.render(cmd, &target);
+    }
+    let errors = app.pipeline_errors();
+    if let Some(err) = errors.first() {
+        return Err(format!("{} pipeline(s) failed to build: {err}", errors.len()));
+    }
+    let output = app
+        .run_stock_passes(cmd, target, &case.passes)
+        .map_err(|err| format!("stock passes failed: {err}"))?;
+    read_back(device, cmd, &output)
+}
+
+/// Runs a case. Returns whether it passed.
+fn run_case(device: &Device, cmd: &mut CommandStream, options: &GoldenOptions, case: &GoldenCase) -> Result<bool, String> {
+    let actual = render_case(device, cmd, &options.cases_dir, case)?;
+    let golden_path = options.cases_dir.join(format!("{}.exr", case.name));
+    if options.update {
+        DynamicImage::ImageRgba32F(actual)
+            .save(&golden_path)
+            .map_err(|err| format!("could not write `{}`: {err}", golden_path.display()))?;
+        println!("{}: updated `{}`", case.name, golden_path.display());
+        return Ok(true);
+    }
+
+    let golden = image::open(&golden_path)
+        .map_err(|err| format!("could not read `{}` ({err}); run with --update to create it", golden_path.display()))?
+        .into_rgba32f();
+    let save_actual = || {
+        let path = options.out_dir.join(format!("{}.actual.exr", case.name));
+        DynamicImage::ImageRgba32F(actual.clone())
+            .save(&path)
+            .map_err(|err| format!("could not write `{}`: {err}", path.display()))
+    };
+    if golden.dimensions() != actual.dimensions() {
+        save_actual()?;
+        println!(
+            "{}: FAILED, size {:?} differs from the golden image ({:?})",
+            case.name,
+            actual.dimensions(),
+            golden.dimensions()
+        );
+        return Ok(false);
+    }
+
+    let comparison = compare(&actual, &golden, case.tolerance);
+    let pixel_count = (actual.width() * actual.height()) as usize;
+    let bad_fraction = comparison.bad_pixels as f32 / pixel_count.max(1) as f32;
+    if bad_fraction <= case.max_bad_fraction {
+        println!("{}: ok (max distance {:.2})", case.name, comparison.max_distance);
+        return Ok(true);
+    }
+    save_actual()?;
+    let diff_path = options.out_dir.join(format!("{}.diff.png", case.name));
+    comparison
+        .diff
+        .save(&diff_path)
+        .map_err(|err| format!("could not write `{}`: {err}", diff_path.display()))?;
+    println!(
+        "{}: FAILED, {} pixels ({:.2}%) differ, max distance {:.2}; see `{}`",
+        case.name,
+        comparison.bad_pixels,
+        bad_fraction * 100.0,
+        comparison.max_distance,
+        diff_path.display()
+    );
+    Ok(false)
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            eprintln!("Usage: fluff golden [--update] [--out <dir>] [--case <name>] [<cases-dir>]");
+            return 2;
+        }
+    };
+
+    let cases_path = options.cases_dir.join("cases.json");
+    let cases: Vec<GoldenCase> = match fs::read_to_string(&cases_path)
+        .map_err(|err| err.to_string())
+        .and_then(|json| serde_json::from_str(&json).map_err(|err| err.to_string()))
+    {
+        Ok(cases) => cases,
+        Err(err) => {
+            eprintln!("Error: could not load `{}`: {err}", cases_path.display());
+            return 1;
+        }
+    };
+    if let Err(err) = fs::create_dir_all(&options.out_dir) {
+        eprintln!("Error: could not create `{}`: {err}", options.out_dir.display());
+        return 1;
+    }
+
+    let (device, mut cmd) = unsafe { graal::create_device_and_command_stream(None).expect("failed to create device") };
+    let mut failed = vec![];
+    for case in cases.iter() {
+        if !options.filter.is_empty() && !options.filter.contains(&case.name) {
+            continue;
+        }
+        match run_case(&device, &mut cmd, &options, case) {
+            Ok(true) => {}
+            Ok(false) => failed.push(case.name.clone()),
+            Err(err) => {
+                println!("{}: ERROR, {err}", case.name);
+                failed.push(case.name.clone());
+            }
+        }
+        device.cleanup();
+    }
+
+    if failed.is_empty() {
+        0
+    } else {
+        println!("{} case(s) failed: {}", failed.len(), failed.join(", "));
+        1
+    }
+}
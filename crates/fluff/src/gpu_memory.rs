@@ -0,0 +1,278 @@
+//! Accounting of GPU memory by subsystem.
+//!
+//! Resources are tagged with a `MemoryCategory` when they are created, by holding a `MemoryTag` next
+//! to them. The tag adds the byte size of the resource to the totals of its category, and removes it
+//! when dropped. Sizes are computed from the resource descriptions, so they don't include the
+//! alignment and padding added by the allocator.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use egui::{Color32, Ui};
+use graal::vk;
+
+use crate::engine::TransientMemory;
+use crate::stats::{history_graph, HISTORY_LEN};
+
+/// What a GPU resource is used for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryCategory {
+    /// Control points, curves and strokes of the animation.
+    Curves,
+    /// Meshes and point clouds.
+    Geometry,
+    /// Brush textures and lookup tables.
+    Textures,
+    /// Frame-sized images and buffers, reallocated when the window is resized.
+    RenderTargets,
+    /// Textures of the user interface.
+    Ui,
+    Other,
+}
+
+impl MemoryCategory {
+    pub const ALL: [MemoryCategory; 6] = [
+        MemoryCategory::Curves,
+        MemoryCategory::Geometry,
+        MemoryCategory::Textures,
+        MemoryCategory::RenderTargets,
+        MemoryCategory::Ui,
+        MemoryCategory::Other,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MemoryCategory::Curves => "Curve buffers",
+            MemoryCategory::Geometry => "Geometry",
+            MemoryCategory::Textures => "Textures",
+            MemoryCategory::RenderTargets => "Render targets",
+            MemoryCategory::Ui => "UI",
+            MemoryCategory::Other => "Other",
+        }
+    }
+
+    fn color(&self) -> Color32 {
+        match self {
+            MemoryCategory::Curves => Color32::from_rgb(110, 160, 210),
+            MemoryCategory::Geometry => Color32::from_rgb(120, 200, 120),
+            MemoryCategory::Textures => Color32::from_rgb(230, 160, 80),
+            MemoryCategory::RenderTargets => Color32::from_rgb(210, 110, 160),
+            MemoryCategory::Ui => Color32::from_rgb(180, 180, 110),
+            MemoryCategory::Other => Color32::GRAY,
+        }
+    }
+}
+
+struct CategoryUsage {
+    bytes: AtomicU64,
+    /// Number of non-empty tags.
+    count: AtomicUsize,
+}
+
+impl CategoryUsage {
+    const fn new() -> CategoryUsage {
+        CategoryUsage {
+            bytes: AtomicU64::new(0),
+            count: AtomicUsize::new(0),
+        }
+    }
+}
+
+// Scenes are built on background threads, so the totals are atomics.
+static USAGE: [CategoryUsage; MemoryCategory::ALL.len()] = [const { CategoryUsage::new() }; MemoryCategory::ALL.len()];
+
+/// Accounts for the memory of a resource in the totals of a category, until dropped.
+pub struct MemoryTag {
+    category: MemoryCategory,
+    bytes: u64,
+}
+
+impl MemoryTag {
+    pub fn new(category: MemoryCategory, bytes: u64) -> MemoryTag {
+        let mut tag = MemoryTag { category, bytes: 0 };
+        tag.set_bytes(bytes);
+        tag
+    }
+
+    /// Changes the size of the tagged resource (e.g. after it was reallocated).
+    pub fn set_bytes(&mut self, bytes: u64) {
+        let usage = &USAGE[self.category as usize];
+        usage.bytes.fetch_add(bytes, Ordering::Relaxed);
+        usage.bytes.fetch_sub(self.bytes, Ordering::Relaxed);
+        match (self.bytes, bytes) {
+            (0, b) if b > 0 => {
+                usage.count.fetch_add(1, Ordering::Relaxed);
+            }
+            (b, 0) if b > 0 => {
+                usage.count.fetch_sub(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+        self.bytes = bytes;
+    }
+
+    /// Moves the resource to another category.
+    pub fn set_category(&mut self, category: MemoryCategory) {
+        let bytes = self.bytes;
+        self.set_bytes(0);
+        self.category = category;
+        self.set_bytes(bytes);
+    }
+}
+
+impl Drop for MemoryTag {
+    fn drop(&mut self) {
+        self.set_bytes(0);
+    }
+}
+
+/// Returns the size in bytes of a texel of the given format.
+fn texel_size(format: vk::Format) -> u64 {
+    match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SRGB => 1,
+        vk::Format::R16_SFLOAT => 2,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::R32_SFLOAT
+        | vk::Format::R32_UINT
+        | vk::Format::D32_SFLOAT => 4,
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32_SFLOAT => 8,
+        vk::Format::R32G32B32A32_SFLOAT => 16,
+        _ => 4,
+    }
+}
+
+/// Returns the size in bytes of a 2D image and its mip levels.
+pub fn image_byte_size(format: vk::Format, width: u32, height: u32, mip_levels: u32) -> u64 {
+    let texel_size = texel_size(format);
+    (0..mip_levels.max(1))
+        .map(|level| (width >> level).max(1) as u64 * (height >> level).max(1) as u64 * texel_size)
+        .sum()
+}
+
+/// Memory used by a category.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MemoryUsage {
+    pub bytes: u64,
+    /// Number of resources.
+    pub count: usize,
+}
+
+/// Returns the memory used by each category, in the order of `MemoryCategory::ALL`.
+pub fn usage() -> [MemoryUsage; MemoryCategory::ALL.len()] {
+    MemoryCategory::ALL.map(|category| {
+        let usage = &USAGE[category as usize];
+        MemoryUsage {
+            bytes: usage.bytes.load(Ordering::Relaxed),
+            count: usage.count.load(Ordering::Relaxed),
+        }
+    })
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    if bytes as f64 >= 1024.0 * MIB {
+        format!("{:.2} GiB", bytes as f64 / (1024.0 * MIB))
+    } else {
+        format!("{:.1} MiB", bytes as f64 / MIB)
+    }
+}
+
+/// Diagnostics panel showing the GPU memory used by each category.
+#[derive(Default)]
+pub struct MemoryPanel {
+    history: VecDeque<[MemoryUsage; MemoryCategory::ALL.len()]>,
+}
+
+impl MemoryPanel {
+    /// Samples the current usage. Called once per frame.
+    pub fn update(&mut self) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(usage());
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        let Some(last) = self.history.back().copied() else {
+            ui.label("Waiting for data...");
+            return;
+        };
+        let total: u64 = last.iter().map(|u| u.bytes).sum();
+
+        egui::Grid::new("gpu_memory").num_columns(3).striped(true).show(ui, |ui| {
+            for (category, usage) in MemoryCategory::ALL.iter().zip(last.iter()) {
+                ui.colored_label(category.color(), category.label());
+                ui.label(format!("{} resources", usage.count));
+                let fraction = usage.bytes as f32 / total.max(1) as f32;
+                ui.add(
+                    egui::ProgressBar::new(fraction)
+                        .desired_width(160.0)
+                        .fill(category.color())
+                        .text(format_bytes(usage.bytes)),
+                );
+                ui.end_row();
+            }
+        });
+        ui.separator();
+        ui.label(format!("Total: {}", format_bytes(total)));
+        ui.label("Sizes are computed from the resource descriptions and exclude allocator overhead.")
+            .on_hover_text("Per-frame upload buffers are not tracked");
+
+        for (i, category) in MemoryCategory::ALL.iter().enumerate() {
+            if last[i].bytes == 0 {
+                continue;
+            }
+            ui.label(format!("{} (MiB)", category.label()));
+            history_graph(
+                ui,
+                self.history.iter().map(|usage| usage[i].bytes as f32 / (1024.0 * 1024.0)),
+                category.color(),
+            );
+        }
+    }
+    /// Shows the transient resources used by each pass in the last frame, and their high-water marks.
+    ///
+    /// Returns whether the transient budget (`budget_mib`) was changed.
+    pub fn transient_ui(&mut self, ui: &mut Ui, transient: &mut TransientMemory, budget_mib: &mut Option<u64>) -> bool {
+        ui.strong("Transient resources");
+        egui::Grid::new("transient_memory").num_columns(3).striped(true).show(ui, |ui| {
+            ui.label("Pass");
+            ui.label("Frame");
+            ui.label("Peak");
+            ui.end_row();
+            for pass in transient.passes() {
+                ui.label(&pass.pass);
+                ui.label(format_bytes(pass.bytes));
+                ui.label(format_bytes(pass.peak_bytes));
+                ui.end_row();
+            }
+            ui.strong("Total");
+            ui.strong(format_bytes(transient.frame_bytes()));
+            ui.strong(format_bytes(transient.peak_frame_bytes()));
+            ui.end_row();
+        });
+        if ui.button("Reset peaks").clicked() {
+            transient.reset_peaks();
+        }
+
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            let mut enabled = budget_mib.is_some();
+            if ui.checkbox(&mut enabled, "Budget").changed() {
+                // start from the high-water mark, rounded up to the next MiB
+                *budget_mib = enabled.then(|| transient.peak_frame_bytes().div_ceil(1 << 20).max(1));
+                changed = true;
+            }
+            if let Some(mib) = budget_mib.as_mut() {
+                changed |= ui.add(egui::DragValue::new(mib).clamp_range(1..=65536).suffix(" MiB")).changed();
+            }
+        });
+        if let Some(err) = transient.exceeded() {
+            ui.colored_label(Color32::from_rgb(230, 80, 80), err.to_string());
+        }
+        changed
+    }
+}
@@ -0,0 +1,307 @@
+//! `fluff import`: batch conversion of geometry files to fluff scene files.
+//!
+//! Usage: `fluff import [-o <output>] [--lods <count>] [--units <mm|cm|m>] [--up-axis <y|z>] <inputs...>`
+//!
+//! Each input file becomes one frame of the output scene, in the order given on the command line.
+//! USD files contribute one frame per time code. `--units` and `--up-axis` give the conventions of the
+//! inputs, which are converted to the ones of the scene (meters, Y-up).
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use houdinio::Geo;
+
+use crate::{
+    curve_id::curve_ids,
+    import_transform::{ImportTransform, Unit, UpAxis},
+    scene::{SceneFile, SceneFileCurve, SceneFileFrame},
+    usd,
+};
+
+/// Point attributes that are carried over to the scene file.
+const SUPPORTED_POINT_ATTRIBUTES: &[&str] = &["P", "Cd"];
+
+/// Coarsest LODs have at least this number of curves.
+const MIN_LOD_CURVE_COUNT: usize = 64;
+
+struct ImportOptions {
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    max_lods: usize,
+    transform: ImportTransform,
+}
+
+fn parse_args(args: &[String]) -> Result<ImportOptions, String> {
+    let mut options = ImportOptions {
+        inputs: vec![],
+        output: None,
+        max_lods: 4,
+        transform: ImportTransform::default(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                let path = args.next().ok_or("missing value for --output")?;
+                options.output = Some(PathBuf::from(path));
+            }
+            "--lods" => {
+                let count = args.next().ok_or("missing value for --lods")?;
+                options.max_lods = count.parse().map_err(|_| format!("invalid LOD count: {count}"))?;
+            }
+            "--units" => {
+                let unit = args.next().ok_or("missing value for --units")?;
+                options.transform.unit = Unit::from_label(unit).ok_or_else(|| format!("invalid unit: {unit}"))?;
+            }
+            "--up-axis" => {
+                let axis = args.next().ok_or("missing value for --up-axis")?;
+                options.transform.up_axis =
+                    UpAxis::from_label(axis).ok_or_else(|| format!("invalid up axis: {axis}"))?;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+            _ => options.inputs.push(PathBuf::from(arg)),
+        }
+    }
+    if options.inputs.is_empty() {
+        return Err("no input files".to_string());
+    }
+    Ok(options)
+}
+
+/// Import statistics, printed at the end.
+#[derive(Default)]
+struct ImportReport {
+    frames: usize,
+    curves: usize,
+    failed_inputs: Vec<(PathBuf, String)>,
+    /// Attributes present in the inputs but not carried over to the scene file.
+    dropped_attributes: BTreeSet<String>,
+    /// Curves skipped because their control point count isn't `3n+1`.
+    invalid_curves: usize,
+    /// Volume primitives, which scene files can't represent.
+    skipped_volumes: usize,
+    /// Polygons (e.g. scalp meshes), which scene files can't represent.
+    skipped_polygons: usize,
+    /// Parser warnings (e.g. skipped primitives).
+    warnings: Vec<String>,
+}
+
+/// Returns the order in which curves should be stored so that each LOD is a prefix.
+///
+/// Uses a bit-reversal permutation: curves that are close in the input (usually close in space)
+/// end up far apart, so that prefixes are evenly spread over the whole set.
+fn lod_order(count: usize) -> Vec<usize> {
+    if count <= 1 {
+        return (0..count).collect();
+    }
+    let bits = usize::BITS - (count - 1).leading_zeros();
+    (0..1usize << bits)
+        .map(|i| i.reverse_bits() >> (usize::BITS - bits))
+        .filter(|&i| i < count)
+        .collect()
+}
+
+/// Converts the curves of a frame. `previous_ids` holds the curve IDs of the previous frame, and is
+/// updated with the IDs of this one.
+fn convert_geo(
+    geo: &Geo,
+    max_lods: usize,
+    previous_ids: &mut Option<Vec<u32>>,
+    report: &mut ImportReport,
+) -> SceneFileFrame {
+    for attr in geo.point_attributes.iter() {
+        if !SUPPORTED_POINT_ATTRIBUTES.contains(&attr.name.as_str()) {
+            report.dropped_attributes.insert(format!("point:{}", attr.name));
+        }
+    }
+    for attr in geo.primitive_attributes.iter() {
+        // curve IDs are carried over
+        if attr.name != "id" {
+            report.dropped_attributes.insert(format!("primitive:{}", attr.name));
+        }
+    }
+    if let Some(cd) = geo.find_point_attribute("Cd") {
+        if cd.size != 3 || cd.as_f32_slice().is_none() {
+            report.dropped_attributes.insert("point:Cd (expected 3 x fpreal32)".to_string());
+        }
+    }
+    let has_color = geo.color().is_some();
+    let ids = curve_ids(geo, previous_ids.as_deref());
+    let mut curve_index = 0;
+
+    let mut curves = vec![];
+    for prim in geo.primitives.iter() {
+        match prim {
+            houdinio::Primitive::BezierRun(run) => {
+                for curve in run.iter() {
+                    let id = ids[curve_index];
+                    curve_index += 1;
+                    let n = curve.vertices.len();
+                    if n < 4 || (n - 1) % 3 != 0 {
+                        report.invalid_curves += 1;
+                        continue;
+                    }
+                    let points = curve.vertices.iter().map(|&v| geo.vertex_position(v)).collect();
+                    let colors = if has_color {
+                        curve.vertices.iter().map(|&v| geo.vertex_color(v)).collect()
+                    } else {
+                        None
+                    };
+                    curves.push(SceneFileCurve {
+                        points,
+                        colors,
+                        id: Some(id),
+                    });
+                }
+            }
+            houdinio::Primitive::PolygonRun(run) => {
+                report.skipped_polygons += run.count;
+            }
+            houdinio::Primitive::Volume(_) => {
+                report.skipped_volumes += 1;
+            }
+        }
+    }
+    *previous_ids = Some(ids);
+
+    // reorder curves for LODs
+    let order = lod_order(curves.len());
+    let mut curves: Vec<_> = curves.into_iter().map(Some).collect();
+    let curves: Vec<_> = order.iter().map(|&i| curves[i].take().unwrap()).collect();
+
+    let mut lod_curve_counts = vec![curves.len() as u32];
+    let mut count = curves.len() / 2;
+    while lod_curve_counts.len() < max_lods && count >= MIN_LOD_CURVE_COUNT {
+        lod_curve_counts.push(count as u32);
+        count /= 2;
+    }
+
+    report.curves += curves.len();
+    SceneFileFrame { curves, lod_curve_counts }
+}
+
+fn import_file(
+    path: &Path,
+    max_lods: usize,
+    transform: &ImportTransform,
+    previous_ids: &mut Option<Vec<u32>>,
+    report: &mut ImportReport,
+) -> Result<Vec<SceneFileFrame>, String> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        _ if houdinio::is_geo_file(path) => {
+            let options = houdinio::ParseOptions {
+                lenient: true,
+                validation: houdinio::Validation::Repair,
+                recenter: false,
+            };
+            let (mut geo, warnings) = Geo::load_with_options(path, &options).map_err(|err| err.to_string())?;
+            for warning in warnings {
+                report.warnings.push(format!("{}: {}", path.display(), warning));
+            }
+            transform.apply(&mut geo);
+            Ok(vec![convert_geo(&geo, max_lods, previous_ids, report)])
+        }
+        Some("usda") => {
+            let (mut frames, warnings) = usd::load_usd(path).map_err(|err| err.to_string())?;
+            for warning in warnings {
+                report.warnings.push(format!("{}: {}", path.display(), warning));
+            }
+            Ok(frames
+                .iter_mut()
+                .map(|geo| {
+                    transform.apply(geo);
+                    convert_geo(geo, max_lods, previous_ids, report)
+                })
+                .collect())
+        }
+        Some("abc") => Err("alembic files are not supported yet".to_string()),
+        _ => Err("unknown file type".to_string()),
+    }
+}
+
+/// Entry point of `fluff import`. Returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            eprintln!("Usage: fluff import [-o <output>] [--lods <count>] [--units <mm|cm|m>] [--up-axis <y|z>] <inputs...>");
+            return 2;
+        }
+    };
+
+    let mut report = ImportReport::default();
+    let mut scene = SceneFile {
+        version: SceneFile::VERSION,
+        frames: vec![],
+    };
+
+    let mut previous_ids = None;
+    for input in options.inputs.iter() {
+        eprint!("Importing: `{}`...", input.display());
+        match import_file(input, options.max_lods, &options.transform, &mut previous_ids, &mut report) {
+            Ok(frames) => {
+                report.frames += frames.len();
+                scene.frames.extend(frames);
+                eprintln!("OK");
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                report.failed_inputs.push((input.clone(), err));
+            }
+        }
+    }
+    let output = options
+        .output
+        .unwrap_or_else(|| options.inputs[0].with_extension("fluff.json"));
+    // don't overwrite the output with an empty scene
+    if report.failed_inputs.len() == options.inputs.len() {
+        eprintln!("Error: no input could be imported, `{}` not written", output.display());
+        return 1;
+    }
+    let json = match serde_json::to_string(&scene) {
+        Ok(json) => json,
+        Err(err) => {
+            eprintln!("Error: failed to serialize scene: {err}");
+            return 1;
+        }
+    };
+    if let Err(err) = fs::write(&output, json) {
+        eprintln!("Error: could not write `{}`: {err}", output.display());
+        return 1;
+    }
+
+    println!("Wrote `{}`", output.display());
+    println!("  {} frames, {} curves", report.frames, report.curves);
+    if report.invalid_curves > 0 {
+        println!("  {} curves skipped (control point count is not 3n+1)", report.invalid_curves);
+    }
+    if report.skipped_volumes > 0 {
+        println!("  {} volume primitives skipped (not supported in scene files)", report.skipped_volumes);
+    }
+    if report.skipped_polygons > 0 {
+        println!("  {} polygons skipped (not supported in scene files)", report.skipped_polygons);
+    }
+    if !report.dropped_attributes.is_empty() {
+        println!("  dropped attributes:");
+        for attr in report.dropped_attributes.iter() {
+            println!("    {attr}");
+        }
+    }
+    if !report.warnings.is_empty() {
+        println!("  warnings:");
+        for warning in report.warnings.iter() {
+            println!("    {warning}");
+        }
+    }
+    if !report.failed_inputs.is_empty() {
+        println!("  failed inputs:");
+        for (path, err) in report.failed_inputs.iter() {
+            println!("    {}: {err}", path.display());
+        }
+        return 1;
+    }
+    0
+}
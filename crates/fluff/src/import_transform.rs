@@ -0,0 +1,165 @@
+//! Unit and up-axis conversion applied to imported geometry.
+//!
+//! Scenes are Y-up, in meters. Assets exported with other conventions (e.g. centimeters and Z-up)
+//! would otherwise arrive rotated or 100x too big. The conversion is chosen per asset in the import
+//! dialog and saved with the app settings, so that reloading the asset applies it again.
+use glam::{Mat3, Vec3};
+use houdinio::{AttributeStorage, Geo};
+
+/// Length unit of an imported asset.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Unit {
+    Millimeters,
+    Centimeters,
+    #[default]
+    Meters,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 3] = [Unit::Millimeters, Unit::Centimeters, Unit::Meters];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Unit::Millimeters => "mm",
+            Unit::Centimeters => "cm",
+            Unit::Meters => "m",
+        }
+    }
+
+    fn meters(&self) -> f32 {
+        match self {
+            Unit::Millimeters => 0.001,
+            Unit::Centimeters => 0.01,
+            Unit::Meters => 1.0,
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Unit> {
+        Unit::ALL.into_iter().find(|unit| unit.label() == label)
+    }
+}
+
+/// Up axis of an imported asset.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    Z,
+}
+
+impl UpAxis {
+    pub fn label(&self) -> &'static str {
+        match self {
+            UpAxis::Y => "Y-up",
+            UpAxis::Z => "Z-up",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<UpAxis> {
+        match label.to_ascii_lowercase().as_str() {
+            "y" | "y-up" => Some(UpAxis::Y),
+            "z" | "z-up" => Some(UpAxis::Z),
+            _ => None,
+        }
+    }
+}
+
+/// Conversion from the conventions of an asset to the ones of the scene.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ImportTransform {
+    pub unit: Unit,
+    pub up_axis: UpAxis,
+}
+
+impl ImportTransform {
+    pub fn is_identity(&self) -> bool {
+        *self == ImportTransform::default()
+    }
+
+    /// Rotation part of the conversion.
+    fn rotation(&self) -> Mat3 {
+        match self.up_axis {
+            UpAxis::Y => Mat3::IDENTITY,
+            // rotation of -90° around X: +Z becomes +Y, +Y becomes -Z
+            UpAxis::Z => Mat3::from_cols(Vec3::X, Vec3::NEG_Z, Vec3::Y),
+        }
+    }
+
+    /// Returns the matrix mapping asset positions to scene positions.
+    pub fn matrix(&self) -> Mat3 {
+        self.rotation() * self.unit.meters()
+    }
+
+    /// Converts the positions (`P`) and normals (`N`) of the geometry in place.
+    pub fn apply(&self, geo: &mut Geo) {
+        if self.is_identity() {
+            return;
+        }
+        let matrix = self.matrix();
+        let rotation = self.rotation();
+        for attr in geo.point_attributes.iter_mut() {
+            let m = match attr.name.as_str() {
+                "P" => matrix,
+                "N" => rotation,
+                _ => continue,
+            };
+            if attr.size != 3 {
+                continue;
+            }
+            if let AttributeStorage::FpReal32(ref mut data) = attr.storage {
+                for v in data.chunks_exact_mut(3) {
+                    let p = m * Vec3::from_slice(v);
+                    v.copy_from_slice(&p.to_array());
+                }
+            }
+        }
+    }
+
+    /// Returns a short description of the conversion (e.g. `×0.01, Z-up → Y-up`).
+    pub fn describe(&self) -> String {
+        let mut parts = vec![];
+        if self.unit != Unit::Meters {
+            parts.push(format!("×{}", self.unit.meters()));
+        }
+        if self.up_axis != UpAxis::Y {
+            parts.push(format!("{} → Y-up", self.up_axis.label()));
+        }
+        if parts.is_empty() {
+            "none".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+
+    /// Edits the conversion. Returns whether it was changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = *self;
+        egui::ComboBox::from_label("Units")
+            .selected_text(self.unit.label())
+            .show_ui(ui, |ui| {
+                for unit in Unit::ALL {
+                    ui.selectable_value(&mut self.unit, unit, unit.label());
+                }
+            });
+        egui::ComboBox::from_label("Up axis")
+            .selected_text(self.up_axis.label())
+            .show_ui(ui, |ui| {
+                for axis in [UpAxis::Y, UpAxis::Z] {
+                    ui.selectable_value(&mut self.up_axis, axis, axis.label());
+                }
+            });
+
+        // preview: where the axes of the asset end up in the scene
+        let matrix = self.matrix();
+        ui.label(format!("Conversion: {}", self.describe()));
+        egui::Grid::new("import_transform_preview").striped(true).show(ui, |ui| {
+            for (name, axis) in [("X", Vec3::X), ("Y", Vec3::Y), ("Z", Vec3::Z)] {
+                let v = matrix * axis;
+                ui.label(format!("asset +{name}"));
+                ui.label(format!("→ ({:.3}, {:.3}, {:.3})", v.x, v.y, v.z));
+                ui.end_row();
+            }
+        });
+        *self != before
+    }
+}
@@ -0,0 +1,210 @@
+//! Background jobs.
+//!
+//! Long-running tasks (imports, exports, ...) run on a small pool of worker threads. Jobs report
+//! their progress and can be cancelled from the UI. Their results are collected on the main thread
+//! by polling the `JobHandle` once per frame.
+use std::{
+    any::Any,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+};
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// State of a job shared between the worker thread, the handle and the job list.
+struct JobState {
+    name: String,
+    /// Progress in [0,1], as the bits of a f32.
+    progress: AtomicU32,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+}
+
+/// Passed to the job function to report progress and check for cancellation.
+pub struct JobContext {
+    state: Arc<JobState>,
+}
+
+impl JobContext {
+    /// Sets the progress of the job, between 0 and 1.
+    pub fn set_progress(&self, progress: f32) {
+        self.state.progress.store(progress.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Whether the job has been cancelled. Jobs should check this regularly and return early.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Status of a job, returned by `JobHandle::poll`.
+pub enum JobStatus<T> {
+    Running,
+    Done(T),
+    Cancelled,
+    /// The job panicked.
+    Failed(String),
+}
+
+/// Handle to a job started with `JobSystem::spawn`.
+///
+/// Dropping the handle cancels the job.
+pub struct JobHandle<T> {
+    state: Arc<JobState>,
+    result: mpsc::Receiver<Result<T, String>>,
+}
+
+impl<T> JobHandle<T> {
+    /// Requests cancellation of the job.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the result of the job if it has finished.
+    ///
+    /// The result is only returned once: after that, the status is `Failed`.
+    pub fn poll(&mut self) -> JobStatus<T> {
+        match self.result.try_recv() {
+            Err(mpsc::TryRecvError::Empty) => JobStatus::Running,
+            Err(mpsc::TryRecvError::Disconnected) => JobStatus::Failed("job result already taken".to_string()),
+            Ok(_) if self.state.cancelled.load(Ordering::Relaxed) => JobStatus::Cancelled,
+            Ok(Ok(result)) => JobStatus::Done(result),
+            Ok(Err(message)) => JobStatus::Failed(message),
+        }
+    }
+}
+
+impl<T> Drop for JobHandle<T> {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "job panicked".to_string()
+    }
+}
+
+/// Pool of worker threads running background jobs.
+pub struct JobSystem {
+    sender: Option<mpsc::Sender<Task>>,
+    workers: Vec<thread::JoinHandle<()>>,
+    /// Jobs that are queued or running, for display in the UI.
+    jobs: Vec<Arc<JobState>>,
+}
+
+impl JobSystem {
+    pub fn new(thread_count: usize) -> JobSystem {
+        let (sender, receiver) = mpsc::channel::<Task>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..thread_count.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("fluff job worker {i}"))
+                    .spawn(move || loop {
+                        let task = receiver.lock().unwrap().recv();
+                        match task {
+                            Ok(task) => task(),
+                            // the job system was dropped
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("failed to spawn job worker thread")
+            })
+            .collect();
+        JobSystem {
+            sender: Some(sender),
+            workers,
+            jobs: vec![],
+        }
+    }
+
+    /// Starts a job on a worker thread.
+    pub fn spawn<T, F>(&mut self, name: impl Into<String>, f: F) -> JobHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&JobContext) -> T + Send + 'static,
+    {
+        let state = Arc::new(JobState {
+            name: name.into(),
+            progress: AtomicU32::new(0.0f32.to_bits()),
+            cancelled: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+        });
+        let (result_sender, result) = mpsc::channel();
+        let ctx = JobContext { state: state.clone() };
+        let task: Task = Box::new(move || {
+            let result = if ctx.is_cancelled() {
+                // cancelled before it started
+                Err("cancelled".to_string())
+            } else {
+                catch_unwind(AssertUnwindSafe(|| f(&ctx))).map_err(panic_message)
+            };
+            ctx.state.finished.store(true, Ordering::Release);
+            // the handle may have been dropped
+            let _ = result_sender.send(result);
+        });
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send(task)
+            .expect("job worker threads have exited");
+        self.jobs.push(state.clone());
+        JobHandle { state, result }
+    }
+
+    /// Whether there are queued or running jobs.
+    pub fn is_busy(&self) -> bool {
+        self.jobs.iter().any(|job| !job.finished.load(Ordering::Acquire))
+    }
+
+    /// Forgets the finished jobs. Call once per frame, whether or not the job list is shown.
+    pub fn poll(&mut self) {
+        self.jobs.retain(|job| !job.finished.load(Ordering::Acquire));
+    }
+
+    /// Lists the running jobs, with their progress and a button to cancel them.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.jobs.is_empty() {
+            ui.label("No background jobs");
+            return;
+        }
+        for job in self.jobs.iter() {
+            ui.horizontal(|ui| {
+                let cancelled = job.cancelled.load(Ordering::Relaxed);
+                let progress = f32::from_bits(job.progress.load(Ordering::Relaxed));
+                ui.add(
+                    egui::ProgressBar::new(progress)
+                        .desired_width(160.0)
+                        .text(if cancelled { "Cancelling..." } else { job.name.as_str() }),
+                );
+                if ui.add_enabled(!cancelled, egui::Button::new("Cancel")).clicked() {
+                    job.cancelled.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+    }
+}
+
+impl Drop for JobSystem {
+    fn drop(&mut self) {
+        for job in self.jobs.iter() {
+            job.cancelled.store(true, Ordering::Relaxed);
+        }
+        // closing the channel stops the workers once they're done with their current job
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Keyframed parameters.
+use std::collections::BTreeMap;
+
+/// A key on a keyframe curve.
+///
+/// Tangent handles are expressed relative to the key, in (frames, value) units.
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Keyframe {
+    pub frame: f64,
+    pub value: f64,
+    pub in_tangent: [f64; 2],
+    pub out_tangent: [f64; 2],
+}
+
+impl Keyframe {
+    /// Creates a key with flat tangents.
+    pub fn new(frame: f64, value: f64) -> Keyframe {
+        Keyframe {
+            frame,
+            value,
+            in_tangent: [-1.0, 0.0],
+            out_tangent: [1.0, 0.0],
+        }
+    }
+}
+
+/// A curve of bézier-interpolated keys. Keys are sorted by frame.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct KeyframeCurve {
+    pub keys: Vec<Keyframe>,
+}
+
+/// Evaluates a 1D cubic bézier.
+fn bezier(p0: f64, p1: f64, p2: f64, p3: f64, t: f64) -> f64 {
+    let u = 1.0 - t;
+    u * u * u * p0 + 3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t * p3
+}
+
+impl KeyframeCurve {
+    /// Evaluates the curve at the given frame.
+    ///
+    /// Returns `None` if there are no keys. The curve is held constant before the first key and
+    /// after the last key.
+    pub fn evaluate(&self, frame: f64) -> Option<f64> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if frame <= first.frame {
+            return Some(first.value);
+        }
+        if frame >= last.frame {
+            return Some(last.value);
+        }
+
+        let i = self.keys.partition_point(|k| k.frame <= frame);
+        let k0 = &self.keys[i - 1];
+        let k1 = &self.keys[i];
+        let span = k1.frame - k0.frame;
+
+        // Clamp the handles to the segment so that the curve is a function of the frame.
+        let x0 = k0.frame;
+        let x1 = k0.frame + k0.out_tangent[0].clamp(0.0, span);
+        let x2 = k1.frame + k1.in_tangent[0].clamp(-span, 0.0);
+        let x3 = k1.frame;
+        let y0 = k0.value;
+        let y1 = k0.value + k0.out_tangent[1];
+        let y2 = k1.value + k1.in_tangent[1];
+        let y3 = k1.value;
+
+        // x(t) is monotonic, find t by bisection
+        let mut lo = 0.0;
+        let mut hi = 1.0;
+        let mut t = (frame - x0) / span;
+        for _ in 0..32 {
+            let x = bezier(x0, x1, x2, x3, t);
+            if (x - frame).abs() < 1e-6 {
+                break;
+            }
+            if x < frame {
+                lo = t;
+            } else {
+                hi = t;
+            }
+            t = 0.5 * (lo + hi);
+        }
+
+        Some(bezier(y0, y1, y2, y3, t))
+    }
+
+    /// Inserts a key at the given frame, or replaces the value of the key already there.
+    ///
+    /// Returns the index of the key.
+    pub fn set_key(&mut self, frame: f64, value: f64) -> usize {
+        if let Some(i) = self.keys.iter().position(|k| (k.frame - frame).abs() < 1e-6) {
+            self.keys[i].value = value;
+            return i;
+        }
+        let i = self.keys.partition_point(|k| k.frame < frame);
+        self.keys.insert(i, Keyframe::new(frame, value));
+        i
+    }
+
+    /// Removes the key at the given frame, if there's one.
+    pub fn remove_key_at(&mut self, frame: f64) {
+        self.keys.retain(|k| (k.frame - frame).abs() >= 1e-6);
+    }
+
+    /// Restores the ordering of keys after they have been moved.
+    pub fn sort(&mut self) {
+        self.keys.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+    }
+}
+
+/// Keyframe curves of animated parameters, by parameter name.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct AnimatedParams {
+    pub curves: BTreeMap<String, KeyframeCurve>,
+}
+
+impl AnimatedParams {
+    /// Evaluates all animated parameters at the given frame.
+    pub fn evaluate(&self, frame: f64) -> impl Iterator<Item = (&str, f64)> + '_ {
+        self.curves
+            .iter()
+            .filter_map(move |(name, curve)| Some((name.as_str(), curve.evaluate(frame)?)))
+    }
+
+    pub fn is_animated(&self, name: &str) -> bool {
+        self.curves.get(name).is_some_and(|c| !c.keys.is_empty())
+    }
+}
@@ -0,0 +1,113 @@
+//! Live link: reloads the scene when a DCC rewrites the files of a watched export directory.
+//!
+//! The directory is polled for changes of the modification time or the size of the files. Exporters
+//! write files over some time, so a changed file is only reported once it has stayed the same for a
+//! whole poll interval.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Interval between two scans of the directory.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct LiveLink {
+    /// Watched directory.
+    pub directory: Option<PathBuf>,
+    pub enabled: bool,
+    last_poll: Option<Instant>,
+    /// Modification time and size of the files at the last scan. `None` until the directory is scanned.
+    files: Option<BTreeMap<PathBuf, (SystemTime, u64)>>,
+    /// Files that changed, waiting to be written completely.
+    changed: BTreeSet<PathBuf>,
+}
+
+impl LiveLink {
+    pub fn new(directory: Option<PathBuf>) -> LiveLink {
+        LiveLink {
+            directory,
+            enabled: false,
+            last_poll: None,
+            files: None,
+            changed: BTreeSet::new(),
+        }
+    }
+
+    /// Forgets the state of the directory, so that the next scan doesn't report existing files as changed.
+    fn reset(&mut self) {
+        self.files = None;
+        self.changed.clear();
+    }
+
+    /// Scans the directory if the poll interval has elapsed. Returns the files that were written since
+    /// the previous scans, and haven't changed since the last one.
+    ///
+    /// `is_asset` tells whether a file can be loaded.
+    pub fn poll(&mut self, is_asset: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+        let Some(ref directory) = self.directory else { return vec![] };
+        if !self.enabled || self.last_poll.is_some_and(|last| last.elapsed() < POLL_INTERVAL) {
+            return vec![];
+        }
+        self.last_poll = Some(Instant::now());
+
+        let Ok(entries) = fs::read_dir(directory) else { return vec![] };
+        let mut files = BTreeMap::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !is_asset(&path) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else { continue };
+            if let Ok(modified) = metadata.modified() {
+                files.insert(path, (modified, metadata.len()));
+            }
+        }
+
+        let mut written = vec![];
+        if let Some(ref previous) = self.files {
+            for (path, state) in files.iter() {
+                let unchanged = previous.get(path) == Some(state);
+                if !unchanged {
+                    self.changed.insert(path.clone());
+                } else if self.changed.remove(path) {
+                    written.push(path.clone());
+                }
+            }
+            // deleted files
+            self.changed.retain(|path| files.contains_key(path));
+        }
+        self.files = Some(files);
+        written
+    }
+
+    /// Shows the live link settings. Returns whether the directory changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut directory_changed = false;
+        ui.horizontal(|ui| {
+            let label = self
+                .directory
+                .as_ref()
+                .map_or("<no directory>".to_string(), |dir| dir.display().to_string());
+            ui.label(label);
+            if ui.button("Choose...").clicked() {
+                if let Some(directory) = rfd::FileDialog::new().pick_folder() {
+                    self.directory = Some(directory);
+                    self.reset();
+                    directory_changed = true;
+                }
+            }
+        });
+        ui.add_enabled_ui(self.directory.is_some(), |ui| {
+            if ui
+                .checkbox(&mut self.enabled, "Reload on export")
+                .on_hover_text("Reload the scene when its files are rewritten in this directory")
+                .changed()
+            {
+                self.reset();
+            }
+        });
+        directory_changed
+    }
+}
@@ -3,13 +3,14 @@ use egui_winit::create_window;
 use glam::{dvec2, DVec2};
 use std::{
     fs,
+    path::Path,
     time::{Duration, Instant},
 };
 
 use graal::vk;
 use winit::{
     event::{Event, MouseScrollDelta, WindowEvent},
-    event_loop::EventLoop,
+    event_loop::{ControlFlow, EventLoop},
     raw_window_handle::HasRawWindowHandle,
 };
 
@@ -17,16 +18,43 @@ use crate::app::App;
 
 mod aabb;
 mod app;
+mod asset_browser;
+mod audio;
+mod brush;
 mod camera_control;
+mod color;
+mod curve_id;
+mod determinism;
 mod egui_backend;
 mod overlay;
 mod engine;
+mod import;
+mod import_transform;
+mod keyframe;
+mod live_link;
 mod util;
 mod shaders;
 mod point_painter;
+mod plugin;
+mod review_session;
 mod ui;
 mod scene;
+mod script;
+mod selection;
+mod dynamics;
+mod dynamic_resolution;
+mod eco_mode;
+mod geometry;
+mod golden;
+mod gpu_memory;
+mod jobs;
+mod onion_skin;
+mod stats;
+mod stylize;
+mod svg_export;
 mod tool;
+mod usd;
+mod workspace;
 
 fn setup_custom_fonts(ctx: &egui::Context) {
     let mut fonts = egui::FontDefinitions::default();
@@ -58,6 +86,15 @@ fn setup_custom_fonts(ctx: &egui::Context) {
 fn main() {
     tracing_subscriber::fmt::init();
 
+    // subcommands
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("import") {
+        std::process::exit(import::run(&args[2..]));
+    }
+    if args.get(1).map(String::as_str) == Some("golden") {
+        std::process::exit(golden::run(&args[2..]));
+    }
+
     // Create the event loop and the main window
     let event_loop = EventLoop::new().expect("failed to create event loop");
     let egui_ctx = egui::Context::default();
@@ -74,6 +111,14 @@ fn main() {
     let (mut width, mut height) = window.inner_size().into();
     let mut app = App::new(&device, width, height, surface_format.format);
 
+    // `--script <file>`: run a Lua script once the app is initialized
+    if let Some(pos) = args.iter().position(|arg| arg == "--script") {
+        match args.get(pos + 1) {
+            Some(path) => app.run_script_file(Path::new(path)),
+            None => eprintln!("Error: missing value for --script"),
+        }
+    }
+
     // imgui stuff
     //let mut imgui = imgui::Context::create();
     //let mut platform = WinitPlatform::init(&mut imgui); // step 1
@@ -142,6 +187,9 @@ fn main() {
                         WindowEvent::Touch(touch) => {
                             app.touch_event(touch);
                         }
+                        WindowEvent::Focused(focused) => {
+                            app.set_focused(*focused);
+                        }
                         WindowEvent::CloseRequested => {
                             println!("The close button was pressed; stopping");
                             app.on_exit();
@@ -188,7 +236,14 @@ fn main() {
                 }
                 Event::AboutToWait => {
                     //platform.prepare_frame(imgui.io_mut(), &window).expect("Failed to prepare frame");
-                    window.request_redraw();
+                    // in eco mode, wait until the next throttled redraw
+                    match app.next_redraw() {
+                        Some(deadline) => event_loop.set_control_flow(ControlFlow::WaitUntil(deadline)),
+                        None => {
+                            event_loop.set_control_flow(ControlFlow::Wait);
+                            window.request_redraw();
+                        }
+                    }
                 }
                 event => {
                     //platform.handle_event(imgui.io_mut(), &window, &event);
@@ -0,0 +1,91 @@
+//! Onion skinning: ghosted display of the frames around the current one, for animation review.
+use egui::Color32;
+use glam::Vec4;
+
+/// A frame drawn as a ghost.
+pub struct GhostFrame {
+    pub frame: usize,
+    /// Color blended over the curve colors, with the blend amount in alpha.
+    pub tint: Vec4,
+    pub opacity: f32,
+}
+
+/// Onion skinning settings.
+pub struct OnionSkin {
+    pub enabled: bool,
+    /// Number of previous frames drawn.
+    pub frames_before: usize,
+    /// Number of next frames drawn.
+    pub frames_after: usize,
+    /// Opacity of the nearest ghost frames. Farther frames fade out linearly.
+    pub opacity: f32,
+    /// How much the ghost frames are tinted (0: original colors, 1: tint color only).
+    pub tint_amount: f32,
+    pub before_color: Color32,
+    pub after_color: Color32,
+}
+
+impl Default for OnionSkin {
+    fn default() -> Self {
+        OnionSkin {
+            enabled: false,
+            frames_before: 2,
+            frames_after: 2,
+            opacity: 0.4,
+            tint_amount: 0.6,
+            before_color: Color32::from_rgb(255, 90, 70),
+            after_color: Color32::from_rgb(80, 200, 110),
+        }
+    }
+}
+
+impl OnionSkin {
+    /// Returns the frames to draw as ghosts, farthest first, so that the frames closest to the current
+    /// one are drawn on top.
+    pub fn ghost_frames(&self, current_frame: usize, frame_count: usize) -> Vec<GhostFrame> {
+        if !self.enabled {
+            return vec![];
+        }
+        let tint = |color: Color32| {
+            let [r, g, b, _] = color.to_normalized_gamma_f32();
+            Vec4::new(r, g, b, self.tint_amount)
+        };
+        let fade = |distance: usize, count: usize| self.opacity * (1.0 - (distance - 1) as f32 / count as f32);
+
+        let mut ghosts = vec![];
+        for distance in (1..=self.frames_before.max(self.frames_after)).rev() {
+            if distance <= self.frames_before && distance <= current_frame {
+                ghosts.push(GhostFrame {
+                    frame: current_frame - distance,
+                    tint: tint(self.before_color),
+                    opacity: fade(distance, self.frames_before),
+                });
+            }
+            if distance <= self.frames_after && current_frame + distance < frame_count {
+                ghosts.push(GhostFrame {
+                    frame: current_frame + distance,
+                    tint: tint(self.after_color),
+                    opacity: fade(distance, self.frames_after),
+                });
+            }
+        }
+        ghosts
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Show adjacent frames")
+            .on_hover_text("Only the GPU Ribbons render mode draws onion skins");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.frames_before).clamp_range(0..=10));
+                ui.label("before");
+                ui.color_edit_button_srgba(&mut self.before_color);
+                ui.add(egui::DragValue::new(&mut self.frames_after).clamp_range(0..=10));
+                ui.label("after");
+                ui.color_edit_button_srgba(&mut self.after_color);
+            });
+            ui.add(egui::Slider::new(&mut self.opacity, 0.0..=1.0).text("Opacity"));
+            ui.add(egui::Slider::new(&mut self.tint_amount, 0.0..=1.0).text("Tint"));
+        });
+    }
+}
@@ -5,6 +5,7 @@ use glam::{DVec2, DVec3, Mat4, vec3, Vec3};
 use graal::{ColorAttachment, DepthStencilAttachment, prelude::*, RenderPassInfo};
 use graal::vk::{AttachmentLoadOp, AttachmentStoreOp};
 
+use crate::aabb::AABB;
 use crate::camera_control::Camera;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -335,6 +336,26 @@ impl OverlayRenderer {
         });
     }
 
+    /// Draws the edges of a bounding box.
+    pub fn aabb(&mut self, bounds: &AABB, color: [u8; 4]) {
+        let (min, max) = (bounds.min.as_dvec3(), bounds.max.as_dvec3());
+        let corner = |i: usize| {
+            DVec3::new(
+                if i & 1 != 0 { max.x } else { min.x },
+                if i & 2 != 0 { max.y } else { min.y },
+                if i & 4 != 0 { max.z } else { min.z },
+            )
+        };
+        // edges connect corners that differ by one bit
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color, color);
+                }
+            }
+        }
+    }
+
     pub fn screen_line(&mut self, camera: &Camera, a: DVec2, b: DVec2, a_color: [u8; 4], b_color: [u8; 4]) {
         // TODO: dedicated screen-space line shader
         let a = camera.screen_to_world(a.extend(0.0));
@@ -0,0 +1,232 @@
+//! Plug-in API: custom render passes, geometry importers and UI panels.
+//!
+//! Plug-ins implement `Plugin` and add their extensions to a `PluginRegistry` at startup.
+//! They are either compiled in (see `builtin_plugins`) or loaded from dynamic libraries found in
+//! the `plugins` directory next to the executable.
+//!
+//! # Dynamic libraries
+//!
+//! A plug-in library exports a `FLUFF_PLUGIN` static of type `PluginDeclaration`:
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub static FLUFF_PLUGIN: PluginDeclaration = PluginDeclaration {
+//!     api_version: PLUGIN_API_VERSION,
+//!     fluff_version: FLUFF_VERSION,
+//!     create: create_plugin,
+//! };
+//!
+//! fn create_plugin() -> Box<dyn Plugin> { Box::new(MyPlugin) }
+//! ```
+//!
+//! Extensions are passed as Rust trait objects, so libraries must be built with the same compiler
+//! and against the same version of fluff. Libraries declaring another API or fluff version are
+//! rejected before anything else in them is called.
+use std::path::{Path, PathBuf};
+
+use bitflags::bitflags;
+use graal::{CommandStream, Image, ImageView};
+use houdinio::Geo;
+use tracing::{info, warn};
+
+use crate::{camera_control::Camera, engine::Engine};
+
+/// Version of the plug-in API. Bumped on every breaking change of the traits in this module.
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// Version of fluff the plug-in API types come from.
+pub const FLUFF_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Name of the symbol exported by plug-in libraries.
+const PLUGIN_DECLARATION_SYMBOL: &[u8] = b"FLUFF_PLUGIN\0";
+
+bitflags! {
+    /// Kinds of extensions provided by a plug-in.
+    #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+    pub struct Capabilities: u32 {
+        const RENDER_PASSES = 1 << 0;
+        const IMPORTERS = 1 << 1;
+        const PANELS = 1 << 2;
+    }
+}
+
+/// Resources available to custom render passes.
+pub struct RenderPassContext<'a> {
+    pub camera: Camera,
+    /// Image containing the rendered frame, before the overlay is drawn.
+    pub color_target: &'a Image,
+    pub color_target_view: &'a ImageView,
+    pub depth_target: &'a ImageView,
+    pub width: u32,
+    pub height: u32,
+    /// Index of the current frame of the animation.
+    pub frame: usize,
+}
+
+/// A render pass that runs after the strokes are rendered, and before the overlay is drawn.
+pub trait RenderPass {
+    fn name(&self) -> &str;
+
+    /// Records the commands of the pass.
+    ///
+    /// Pipelines should be created with `engine`, which caches them and reports build errors in the UI.
+    /// When occlusion culling is enabled, the depth pyramid of the meshes is available with
+    /// `Engine::depth_pyramid`.
+    fn record(&mut self, cmd: &mut CommandStream, engine: &mut Engine, ctx: &RenderPassContext);
+}
+
+/// Converts geometry files into Houdini geometry, which is then loaded like `.geo` files.
+pub trait GeometryImporter {
+    /// Name of the file format, shown in file dialogs.
+    fn format_name(&self) -> &str;
+
+    /// Handled file extensions, without the leading dot.
+    fn extensions(&self) -> &[&str];
+
+    fn import(&self, path: &Path) -> anyhow::Result<Geo>;
+}
+
+/// A panel shown as a window in the UI.
+pub trait Panel {
+    fn title(&self) -> &str;
+
+    fn ui(&mut self, ui: &mut egui::Ui);
+}
+
+pub trait Plugin {
+    fn name(&self) -> &str;
+
+    /// Kinds of extensions that the plug-in registers.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Registers the extensions of the plug-in.
+    fn register(&self, registry: &mut PluginRegistry);
+}
+
+/// Exported by plug-in libraries under the name `FLUFF_PLUGIN`.
+#[repr(C)]
+pub struct PluginDeclaration {
+    pub api_version: u32,
+    pub fluff_version: &'static str,
+    pub create: fn() -> Box<dyn Plugin>,
+}
+
+/// Information about a loaded plug-in.
+#[derive(Clone, Debug)]
+pub struct PluginInfo {
+    pub name: String,
+    pub capabilities: Capabilities,
+    /// Library the plug-in was loaded from, `None` for built-in plug-ins.
+    pub library: Option<PathBuf>,
+}
+
+/// Extensions registered by all plug-ins.
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: Vec<PluginInfo>,
+    pub(crate) render_passes: Vec<Box<dyn RenderPass>>,
+    pub(crate) importers: Vec<Box<dyn GeometryImporter>>,
+    pub(crate) panels: Vec<Box<dyn Panel>>,
+    /// Loaded libraries. They must outlive the extensions they provide: keep this field last so that
+    /// it's dropped last.
+    libraries: Vec<libloading::Library>,
+}
+
+impl PluginRegistry {
+    pub fn add_render_pass(&mut self, pass: impl RenderPass + 'static) {
+        self.render_passes.push(Box::new(pass));
+    }
+
+    pub fn add_importer(&mut self, importer: impl GeometryImporter + 'static) {
+        self.importers.push(Box::new(importer));
+    }
+
+    pub fn add_panel(&mut self, panel: impl Panel + 'static) {
+        self.panels.push(Box::new(panel));
+    }
+
+    /// Returns the loaded plug-ins.
+    pub fn plugins(&self) -> &[PluginInfo] {
+        &self.plugins
+    }
+
+    /// Returns the importer that handles the specified file, if any.
+    pub fn importer_for(&self, path: &Path) -> Option<&dyn GeometryImporter> {
+        let ext = path.extension()?.to_str()?;
+        self.importers
+            .iter()
+            .find(|importer| importer.extensions().iter().any(|e| e.eq_ignore_ascii_case(ext)))
+            .map(|importer| &**importer)
+    }
+
+    fn register(&mut self, plugin: &dyn Plugin, library: Option<PathBuf>) {
+        info!("registering plug-in `{}` ({:?})", plugin.name(), plugin.capabilities());
+        plugin.register(self);
+        self.plugins.push(PluginInfo {
+            name: plugin.name().to_string(),
+            capabilities: plugin.capabilities(),
+            library,
+        });
+    }
+
+    /// Loads a plug-in library.
+    ///
+    /// # Safety
+    ///
+    /// Loading a library runs its initialization code, and the plug-in declaration can't be verified
+    /// beyond its version fields.
+    unsafe fn load_library(&mut self, path: &Path) -> anyhow::Result<()> {
+        let library = libloading::Library::new(path)?;
+        let decl: &PluginDeclaration = &**library.get::<*const PluginDeclaration>(PLUGIN_DECLARATION_SYMBOL)?;
+        if decl.api_version != PLUGIN_API_VERSION || decl.fluff_version != FLUFF_VERSION {
+            anyhow::bail!(
+                "incompatible plug-in: built for API version {} (fluff {}), expected {} (fluff {})",
+                decl.api_version,
+                decl.fluff_version,
+                PLUGIN_API_VERSION,
+                FLUFF_VERSION
+            );
+        }
+        let plugin = (decl.create)();
+        self.register(&*plugin, Some(path.to_path_buf()));
+        drop(plugin);
+        self.libraries.push(library);
+        Ok(())
+    }
+
+    /// Registers the built-in plug-ins and the plug-in libraries found in `dir`.
+    pub fn load(dir: &Path) -> PluginRegistry {
+        let mut registry = PluginRegistry::default();
+        for plugin in builtin_plugins() {
+            registry.register(&*plugin, None);
+        }
+
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return registry;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().and_then(|e| e.to_str()) == Some(std::env::consts::DLL_EXTENSION))
+            .collect();
+        paths.sort();
+        for path in paths {
+            if let Err(err) = unsafe { registry.load_library(&path) } {
+                warn!("failed to load plug-in `{}`: {err}", path.display());
+            }
+        }
+        registry
+    }
+}
+
+/// Directory searched for plug-in libraries.
+pub fn plugin_directory() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("plugins")))
+        .unwrap_or_else(|| PathBuf::from("plugins"))
+}
+
+/// Plug-ins compiled into the application.
+fn builtin_plugins() -> Vec<Box<dyn Plugin>> {
+    vec![]
+}
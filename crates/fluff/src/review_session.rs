@@ -0,0 +1,888 @@
+//! Review sessions: camera, timeline and annotations shared between instances over the network.
+//!
+//! One instance hosts the session over TCP, others join it. Camera moves and timeline changes of the
+//! presenter are mirrored by the other participants (the viewers). Anyone can draw annotations, which
+//! are shown to everyone. The host relays messages between participants and decides who presents: it
+//! starts as the presenter, and grants or denies the requests for control of the participants.
+//!
+//! The host only listens on the loopback interface unless remote participants are allowed. Participants
+//! must present the session token generated by the host, which the host shares with them out of band.
+//!
+//! Messages are JSON objects, one per line. They are written by a thread per connection, so that a slow
+//! participant doesn't stall the frame loop.
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use glam::{dvec2, DVec2, DVec3};
+use tracing::{info, warn};
+
+use crate::{
+    camera_control::{Camera, CameraControl},
+    overlay::OverlayRenderer,
+};
+
+pub const DEFAULT_PORT: u16 = 7717;
+/// Minimum interval between two camera updates sent by the presenter.
+const CAMERA_SEND_INTERVAL: Duration = Duration::from_millis(33);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Participants that don't accept data for this long are disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+/// Messages waiting to be written to a connection. Participants that fall further behind are disconnected.
+const SEND_QUEUE_LEN: usize = 256;
+/// Maximum length of a message, in bytes. Connections sending longer lines are closed.
+const MAX_MESSAGE_LEN: usize = 4 << 20;
+/// Interval at which the host checks for new connections.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Participant ID of the host.
+const HOST_ID: u32 = 0;
+
+/// An annotation stroke.
+///
+/// Points are in normalized viewport coordinates (0 to 1 on both axes), so that strokes map to
+/// viewports of different sizes.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Annotation {
+    pub points: Vec<[f32; 2]>,
+    pub color: [u8; 4],
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+enum Message {
+    /// Sent by a participant after connecting.
+    Hello { name: String, token: String },
+    /// Sent by the host in response to `Hello` when the token is wrong. The host closes the connection.
+    Rejected { reason: String },
+    /// Sent by the host in response to `Hello`.
+    Welcome {
+        peer_id: u32,
+        presenter: u32,
+        annotations: Vec<Annotation>,
+    },
+    /// Sent by the host when participants join or leave.
+    Participants { participants: Vec<(u32, String)> },
+    /// Sent by the host when the presenter changes.
+    Presenter { peer_id: u32 },
+    /// Asks the host to become the presenter.
+    RequestControl,
+    /// Sent by the host when it denies a request for control.
+    ControlDenied,
+    Camera {
+        eye: [f64; 3],
+        center: [f64; 3],
+        up: [f64; 3],
+        fov_y: f64,
+    },
+    Timeline { frame: usize, playing: bool },
+    Annotation(Annotation),
+    ClearAnnotations,
+}
+
+/// Received by the main thread from the network threads.
+enum NetEvent {
+    Connected(u32, TcpStream),
+    Message(u32, Message),
+    Disconnected(u32),
+}
+
+/// Changes from the presenter to apply to the local state.
+pub enum SessionUpdate {
+    Camera {
+        eye: DVec3,
+        center: DVec3,
+        up: DVec3,
+        fov_y: f64,
+    },
+    Timeline { frame: usize, playing: bool },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct CameraState {
+    eye: DVec3,
+    center: DVec3,
+    up: DVec3,
+    fov_y: f64,
+}
+
+impl CameraState {
+    fn of(camera_control: &CameraControl) -> CameraState {
+        let (eye, center, up) = camera_control.look_at();
+        CameraState {
+            eye,
+            center,
+            up,
+            fov_y: camera_control.fov_y_radians(),
+        }
+    }
+}
+
+/// A connection to another participant.
+///
+/// Messages are queued and written by a separate thread.
+struct Connection {
+    stream: TcpStream,
+    queue: mpsc::SyncSender<Vec<u8>>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Connection> {
+        let (queue, lines) = mpsc::sync_channel::<Vec<u8>>(SEND_QUEUE_LEN);
+        let mut writer = stream.try_clone()?;
+        thread::spawn(move || {
+            // ends when the connection is dropped, after writing the queued messages
+            for line in lines {
+                if writer.write_all(&line).is_err() {
+                    break;
+                }
+            }
+            // also stops the reader thread, which reports the disconnection
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+        Ok(Connection { stream, queue })
+    }
+
+    /// Queues a message. Returns false if the connection failed or the participant is too far behind.
+    fn send(&self, message: &Message) -> bool {
+        let Ok(mut line) = serde_json::to_vec(message) else { return false };
+        line.push(b'\n');
+        self.queue.try_send(line).is_ok()
+    }
+
+    fn shutdown(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Reads messages from `stream` until it's closed, and forwards them to the main thread.
+fn spawn_reader(peer_id: u32, stream: TcpStream, events: mpsc::Sender<NetEvent>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut line = vec![];
+        loop {
+            line.clear();
+            match (&mut reader).take(MAX_MESSAGE_LEN as u64 + 1).read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            if line.last() != Some(&b'\n') {
+                if line.len() > MAX_MESSAGE_LEN {
+                    warn!("review session: message from participant {peer_id} is too long, closing the connection");
+                    let _ = reader.get_ref().shutdown(Shutdown::Both);
+                }
+                break;
+            }
+            match serde_json::from_slice(&line) {
+                Ok(message) => {
+                    if events.send(NetEvent::Message(peer_id, message)).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => warn!("invalid review session message: {err}"),
+            }
+        }
+        let _ = events.send(NetEvent::Disconnected(peer_id));
+    });
+}
+
+struct Peer {
+    name: String,
+    connection: Connection,
+    /// Whether the participant sent the session token. Other participants don't see it until then.
+    authenticated: bool,
+}
+
+enum Link {
+    Host {
+        peers: BTreeMap<u32, Peer>,
+        /// Token that participants must send in `Hello`.
+        token: String,
+        /// Participants asking for control, in the order of their requests.
+        control_requests: Vec<u32>,
+        /// Set to stop accepting connections.
+        closed: Arc<AtomicBool>,
+    },
+    Client {
+        host: Connection,
+    },
+}
+
+/// A connection to a review session, as the host or as a participant.
+struct Session {
+    link: Link,
+    events: mpsc::Receiver<NetEvent>,
+    peer_id: u32,
+    presenter: u32,
+    participants: Vec<(u32, String)>,
+    /// Participants whose connection failed, disconnected on the next poll.
+    failed: Vec<u32>,
+    /// Why the host closed the connection, if it did.
+    end_reason: Option<String>,
+}
+
+impl Session {
+    /// Hosts a session. Only local connections are accepted unless `allow_remote` is set.
+    fn host(name: &str, port: u16, allow_remote: bool) -> io::Result<Session> {
+        let address = if allow_remote { "0.0.0.0" } else { "127.0.0.1" };
+        let listener = TcpListener::bind((address, port))?;
+        listener.set_nonblocking(true)?;
+        let closed = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+
+        let closed_flag = closed.clone();
+        thread::spawn(move || {
+            let mut next_id = HOST_ID + 1;
+            while !closed_flag.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, addr)) => {
+                        info!("review session: {addr} connected");
+                        let setup = stream
+                            .set_nonblocking(false)
+                            .and_then(|_| stream.set_nodelay(true))
+                            .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+                            .and_then(|_| stream.try_clone());
+                        let Ok(reader) = setup else { continue };
+                        let id = next_id;
+                        next_id += 1;
+                        if tx.send(NetEvent::Connected(id, stream)).is_err() {
+                            return;
+                        }
+                        spawn_reader(id, reader, tx.clone());
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL_INTERVAL),
+                    Err(err) => {
+                        warn!("review session: accept failed: {err}");
+                        thread::sleep(ACCEPT_POLL_INTERVAL);
+                    }
+                }
+            }
+        });
+
+        Ok(Session {
+            link: Link::Host {
+                peers: BTreeMap::new(),
+                token: format!("{:016x}", rand::random::<u64>()),
+                control_requests: vec![],
+                closed,
+            },
+            events: rx,
+            peer_id: HOST_ID,
+            presenter: HOST_ID,
+            participants: vec![(HOST_ID, name.to_string())],
+            failed: vec![],
+            end_reason: None,
+        })
+    }
+
+    fn join(name: &str, address: &str, token: &str) -> io::Result<Session> {
+        let addr = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "could not resolve address"))?;
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let (tx, rx) = mpsc::channel();
+        spawn_reader(HOST_ID, stream.try_clone()?, tx);
+        let host = Connection::new(stream)?;
+        host.send(&Message::Hello {
+            name: name.to_string(),
+            token: token.trim().to_string(),
+        });
+        Ok(Session {
+            link: Link::Client { host },
+            events: rx,
+            // assigned by the host in `Welcome`
+            peer_id: u32::MAX,
+            presenter: HOST_ID,
+            participants: vec![],
+            failed: vec![],
+            end_reason: None,
+        })
+    }
+
+    fn is_host(&self) -> bool {
+        matches!(self.link, Link::Host { .. })
+    }
+
+    fn is_presenter(&self) -> bool {
+        self.presenter == self.peer_id
+    }
+
+    /// Sends a message to all authenticated participants (host) or to the host (participants), except `except`.
+    fn send(&mut self, message: &Message, except: Option<u32>) {
+        match self.link {
+            Link::Host { ref peers, .. } => {
+                for (&id, peer) in peers.iter() {
+                    if Some(id) != except && peer.authenticated && !peer.connection.send(message) {
+                        self.failed.push(id);
+                    }
+                }
+            }
+            Link::Client { ref host } => {
+                if !host.send(message) {
+                    self.failed.push(HOST_ID);
+                }
+            }
+        }
+    }
+
+    fn send_to(&mut self, peer_id: u32, message: &Message) {
+        if let Link::Host { ref peers, .. } = self.link {
+            if let Some(peer) = peers.get(&peer_id) {
+                if !peer.connection.send(message) {
+                    self.failed.push(peer_id);
+                }
+            }
+        }
+    }
+
+    fn update_participants(&mut self) {
+        let Link::Host { ref peers, .. } = self.link else { return };
+        let host_name = self.participants.first().map(|(_, name)| name.clone()).unwrap_or_default();
+        let mut participants = vec![(HOST_ID, host_name)];
+        participants.extend(
+            peers
+                .iter()
+                .filter(|(_, peer)| peer.authenticated)
+                .map(|(&id, peer)| (id, peer.name.clone())),
+        );
+        self.participants = participants.clone();
+        self.send(&Message::Participants { participants }, None);
+    }
+
+    fn set_presenter(&mut self, peer_id: u32) {
+        self.presenter = peer_id;
+        if let Link::Host {
+            ref mut control_requests, ..
+        } = self.link
+        {
+            control_requests.retain(|&id| id != peer_id);
+        }
+        self.send(&Message::Presenter { peer_id }, None);
+    }
+
+    /// Participants waiting for the host to grant or deny them control.
+    fn control_requests(&self) -> &[u32] {
+        match self.link {
+            Link::Host {
+                ref control_requests, ..
+            } => control_requests,
+            Link::Client { .. } => &[],
+        }
+    }
+
+    /// Denies a request for control (host only).
+    fn deny_control(&mut self, peer_id: u32) {
+        if let Link::Host {
+            ref mut control_requests, ..
+        } = self.link
+        {
+            control_requests.retain(|&id| id != peer_id);
+        }
+        self.send_to(peer_id, &Message::ControlDenied);
+    }
+
+    /// Becomes the presenter (host), or asks the host for control (participants).
+    fn take_control(&mut self) {
+        if self.is_host() {
+            self.set_presenter(HOST_ID);
+        } else {
+            self.send(&Message::RequestControl, None);
+        }
+    }
+
+    /// Handles a disconnection. Returns false if the connection to the host was lost.
+    fn disconnected(&mut self, peer_id: u32) -> bool {
+        let Link::Host {
+            ref mut peers,
+            ref mut control_requests,
+            ..
+        } = self.link
+        else {
+            return false;
+        };
+        control_requests.retain(|&id| id != peer_id);
+        let Some(peer) = peers.remove(&peer_id) else {
+            return true;
+        };
+        peer.connection.shutdown();
+        if !peer.authenticated {
+            return true;
+        }
+        info!("review session: participant {peer_id} left");
+        if self.presenter == peer_id {
+            self.set_presenter(HOST_ID);
+        }
+        self.update_participants();
+        true
+    }
+
+    /// Processes the messages received since the last call, relaying them to the other participants
+    /// if hosting. Returns the messages to apply locally, or `None` if the session has ended.
+    fn poll(&mut self, annotations: &[Annotation]) -> Option<Vec<Message>> {
+        let mut received = vec![];
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                NetEvent::Connected(peer_id, stream) => {
+                    if let Link::Host { ref mut peers, .. } = self.link {
+                        match Connection::new(stream) {
+                            Ok(connection) => {
+                                peers.insert(
+                                    peer_id,
+                                    Peer {
+                                        name: format!("Participant {peer_id}"),
+                                        connection,
+                                        authenticated: false,
+                                    },
+                                );
+                            }
+                            Err(err) => warn!("review session: could not set up the connection: {err}"),
+                        }
+                    }
+                }
+                NetEvent::Disconnected(peer_id) => {
+                    if !self.disconnected(peer_id) {
+                        return None;
+                    }
+                }
+                NetEvent::Message(from, message) => {
+                    if self.is_host() {
+                        self.handle_as_host(from, message, annotations, &mut received);
+                    } else {
+                        self.handle_as_client(message, &mut received);
+                    }
+                }
+            }
+        }
+        for peer_id in std::mem::take(&mut self.failed) {
+            if !self.disconnected(peer_id) {
+                return None;
+            }
+        }
+        if self.end_reason.is_some() {
+            return None;
+        }
+        Some(received)
+    }
+
+    fn handle_as_host(&mut self, from: u32, message: Message, annotations: &[Annotation], received: &mut Vec<Message>) {
+        let Link::Host {
+            ref mut peers,
+            ref token,
+            ref mut control_requests,
+            ..
+        } = self.link
+        else {
+            return;
+        };
+        let Some(peer) = peers.get_mut(&from) else { return };
+        if !peer.authenticated {
+            // nothing but the token is accepted from participants that haven't sent it
+            match message {
+                Message::Hello {
+                    ref name,
+                    token: ref peer_token,
+                } if peer_token == token => {
+                    peer.name = name.clone();
+                    peer.authenticated = true;
+                }
+                _ => {
+                    warn!("review session: participant {from} sent a wrong session token");
+                    peer.connection.send(&Message::Rejected {
+                        reason: "Wrong session token".to_string(),
+                    });
+                    // the connection is closed once the message is written
+                    peers.remove(&from);
+                    return;
+                }
+            }
+        }
+
+        match message {
+            Message::Hello { .. } => {
+                let welcome = Message::Welcome {
+                    peer_id: from,
+                    presenter: self.presenter,
+                    annotations: annotations.to_vec(),
+                };
+                self.send_to(from, &welcome);
+                self.update_participants();
+            }
+            Message::RequestControl => {
+                if from != self.presenter && !control_requests.contains(&from) {
+                    control_requests.push(from);
+                }
+            }
+            Message::Camera { .. } | Message::Timeline { .. } => {
+                if from == self.presenter {
+                    self.send(&message, Some(from));
+                    received.push(message);
+                }
+            }
+            Message::Annotation(_) | Message::ClearAnnotations => {
+                self.send(&message, Some(from));
+                received.push(message);
+            }
+            // only sent by the host
+            Message::Welcome { .. }
+            | Message::Rejected { .. }
+            | Message::Participants { .. }
+            | Message::Presenter { .. }
+            | Message::ControlDenied => {}
+        }
+    }
+
+    fn handle_as_client(&mut self, message: Message, received: &mut Vec<Message>) {
+        match message {
+            Message::Welcome { peer_id, presenter, .. } => {
+                self.peer_id = peer_id;
+                self.presenter = presenter;
+                received.push(message);
+            }
+            Message::Rejected { reason } => self.end_reason = Some(reason),
+            Message::Participants { participants } => self.participants = participants,
+            Message::Presenter { peer_id } => self.presenter = peer_id,
+            Message::Camera { .. }
+            | Message::Timeline { .. }
+            | Message::Annotation(_)
+            | Message::ClearAnnotations
+            | Message::ControlDenied => received.push(message),
+            Message::Hello { .. } | Message::RequestControl => {}
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // closing the streams stops the reader threads
+        match self.link {
+            Link::Host { ref peers, ref closed, .. } => {
+                closed.store(true, Ordering::Relaxed);
+                for peer in peers.values() {
+                    peer.connection.shutdown();
+                }
+            }
+            Link::Client { ref host } => host.shutdown(),
+        }
+    }
+}
+
+/// Review session state and annotations.
+pub struct ReviewSession {
+    session: Option<Session>,
+    name: String,
+    /// Address of the host to join.
+    address: String,
+    /// Token of the session to join, given by the host.
+    token: String,
+    port: u16,
+    /// Whether to accept connections from other machines when hosting.
+    allow_remote: bool,
+    error: Option<String>,
+    /// Whether the camera and timeline follow the presenter.
+    follow_presenter: bool,
+    /// Whether left-dragging in the viewport draws annotations instead of rotating the camera.
+    annotating: bool,
+    annotation_color: [u8; 4],
+    annotations: Vec<Annotation>,
+    current_stroke: Option<Annotation>,
+    /// Last camera state sent, and when.
+    last_camera_sent: Option<(CameraState, Instant)>,
+    last_timeline_sent: Option<(usize, bool)>,
+}
+
+impl Default for ReviewSession {
+    fn default() -> Self {
+        ReviewSession {
+            session: None,
+            name: std::env::var("USERNAME")
+                .or_else(|_| std::env::var("USER"))
+                .unwrap_or_else(|_| "Reviewer".to_string()),
+            address: format!("localhost:{DEFAULT_PORT}"),
+            token: String::new(),
+            port: DEFAULT_PORT,
+            allow_remote: false,
+            error: None,
+            follow_presenter: true,
+            annotating: false,
+            annotation_color: [255, 64, 64, 255],
+            annotations: vec![],
+            current_stroke: None,
+            last_camera_sent: None,
+            last_timeline_sent: None,
+        }
+    }
+}
+
+impl ReviewSession {
+    pub fn is_connected(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// Whether the local camera and timeline are shared with the other participants.
+    fn is_presenter(&self) -> bool {
+        self.session.as_ref().is_some_and(|s| s.is_presenter())
+    }
+
+    fn start(&mut self, session: io::Result<Session>) {
+        match session {
+            Ok(session) => {
+                self.session = Some(session);
+                self.error = None;
+                self.last_camera_sent = None;
+                self.last_timeline_sent = None;
+            }
+            Err(err) => self.error = Some(err.to_string()),
+        }
+    }
+
+    pub fn leave(&mut self) {
+        self.session = None;
+    }
+
+    /// Processes incoming messages. Returns the changes from the presenter to apply locally.
+    pub fn poll(&mut self) -> Vec<SessionUpdate> {
+        let Some(ref mut session) = self.session else {
+            return vec![];
+        };
+        let was_presenter = session.is_presenter();
+        let Some(received) = session.poll(&self.annotations) else {
+            let reason = session.end_reason.take();
+            self.session = None;
+            self.error = Some(reason.unwrap_or_else(|| "Disconnected from the host".to_string()));
+            return vec![];
+        };
+        if session.is_presenter() && !was_presenter {
+            // send the full state to the viewers
+            self.last_camera_sent = None;
+            self.last_timeline_sent = None;
+        }
+        let follow = self.follow_presenter && !session.is_presenter();
+
+        let mut updates = vec![];
+        for message in received {
+            match message {
+                Message::Welcome { annotations, .. } => self.annotations = annotations,
+                Message::Annotation(annotation) => self.annotations.push(annotation),
+                Message::ClearAnnotations => self.annotations.clear(),
+                Message::ControlDenied => self.error = Some("The host denied the request for control".to_string()),
+                Message::Camera { eye, center, up, fov_y } if follow => updates.push(SessionUpdate::Camera {
+                    eye: DVec3::from(eye),
+                    center: DVec3::from(center),
+                    up: DVec3::from(up),
+                    fov_y,
+                }),
+                Message::Timeline { frame, playing } if follow => {
+                    updates.push(SessionUpdate::Timeline { frame, playing })
+                }
+                _ => {}
+            }
+        }
+        updates
+    }
+
+    /// Sends the camera to the viewers if it has changed and this instance is the presenter.
+    pub fn share_camera(&mut self, camera_control: &CameraControl) {
+        if !self.is_presenter() {
+            return;
+        }
+        let state = CameraState::of(camera_control);
+        if let Some((last, time)) = self.last_camera_sent {
+            if last == state || time.elapsed() < CAMERA_SEND_INTERVAL {
+                return;
+            }
+        }
+        self.last_camera_sent = Some((state, Instant::now()));
+        if let Some(ref mut session) = self.session {
+            session.send(
+                &Message::Camera {
+                    eye: state.eye.to_array(),
+                    center: state.center.to_array(),
+                    up: state.up.to_array(),
+                    fov_y: state.fov_y,
+                },
+                None,
+            );
+        }
+    }
+
+    /// Sends the timeline state to the viewers if it has changed and this instance is the presenter.
+    pub fn share_timeline(&mut self, frame: usize, playing: bool) {
+        if !self.is_presenter() || self.last_timeline_sent == Some((frame, playing)) {
+            return;
+        }
+        self.last_timeline_sent = Some((frame, playing));
+        if let Some(ref mut session) = self.session {
+            session.send(&Message::Timeline { frame, playing }, None);
+        }
+    }
+
+    /// Whether pointer input in the viewport draws annotations.
+    pub fn is_annotating(&self) -> bool {
+        self.annotating
+    }
+
+    /// Starts an annotation stroke at the specified position, in physical pixels.
+    pub fn begin_stroke(&mut self, pos: DVec2, viewport_size: DVec2) {
+        let p = pos / viewport_size;
+        self.current_stroke = Some(Annotation {
+            points: vec![[p.x as f32, p.y as f32]],
+            color: self.annotation_color,
+        });
+    }
+
+    pub fn extend_stroke(&mut self, pos: DVec2, viewport_size: DVec2) {
+        let Some(ref mut stroke) = self.current_stroke else {
+            return;
+        };
+        let last = stroke.points.last().copied().unwrap_or_default();
+        // skip points closer than a pixel to the previous one
+        if (dvec2(last[0] as f64, last[1] as f64) * viewport_size).distance(pos) >= 1.0 {
+            let p = pos / viewport_size;
+            stroke.points.push([p.x as f32, p.y as f32]);
+        }
+    }
+
+    /// Ends the current annotation stroke, and sends it to the other participants.
+    pub fn end_stroke(&mut self) {
+        let Some(stroke) = self.current_stroke.take() else {
+            return;
+        };
+        if stroke.points.len() < 2 {
+            return;
+        }
+        if let Some(ref mut session) = self.session {
+            session.send(&Message::Annotation(stroke.clone()), None);
+        }
+        self.annotations.push(stroke);
+    }
+
+    pub fn clear_annotations(&mut self) {
+        self.annotations.clear();
+        if let Some(ref mut session) = self.session {
+            session.send(&Message::ClearAnnotations, None);
+        }
+    }
+
+    /// Draws the annotations over the viewport.
+    pub fn draw(&self, overlay: &mut OverlayRenderer, camera: &Camera) {
+        let size = camera.screen_size;
+        for stroke in self.annotations.iter().chain(self.current_stroke.iter()) {
+            let points: Vec<_> = stroke
+                .points
+                .iter()
+                .map(|p| dvec2(p[0] as f64, p[1] as f64) * size)
+                .collect();
+            overlay.screen_polyline(camera, &points, stroke.color);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.session.is_none() {
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut self.name);
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.port).prefix("Port "));
+                ui.checkbox(&mut self.allow_remote, "Allow remote participants")
+                    .on_hover_text("Accept connections from other machines, not only from this one");
+                if ui.button("Host").clicked() {
+                    self.start(Session::host(&self.name, self.port, self.allow_remote));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.address);
+                ui.add(egui::TextEdit::singleline(&mut self.token).hint_text("Session token").desired_width(120.0));
+                if ui.button("Join").clicked() {
+                    self.start(Session::join(&self.name, &self.address, &self.token));
+                }
+            });
+        }
+
+        let mut leave = false;
+        if let Some(ref mut session) = self.session {
+            let role = if session.is_presenter() { "presenter" } else { "viewer" };
+            if let Link::Host { ref token, .. } = session.link {
+                ui.label(format!("Hosting on port {} ({role})", self.port));
+                ui.horizontal(|ui| {
+                    ui.label(format!("Session token: {token}"));
+                    if ui.button("Copy").clicked() {
+                        ui.output_mut(|o| o.copied_text = token.clone());
+                    }
+                });
+            } else {
+                ui.label(format!("Connected to {} ({role})", self.address));
+            }
+            ui.label("Participants:");
+            for (id, name) in session.participants.iter() {
+                let mut text = name.clone();
+                if *id == session.presenter {
+                    text.push_str(" (presenting)");
+                }
+                if *id == session.peer_id {
+                    text.push_str(" (you)");
+                }
+                ui.label(text);
+            }
+            let mut granted = None;
+            let mut denied = None;
+            for &id in session.control_requests() {
+                let name = session.participants.iter().find(|(pid, _)| *pid == id).map_or("?", |(_, name)| name);
+                ui.horizontal(|ui| {
+                    ui.label(format!("{name} asks for control"));
+                    if ui.button("Grant").clicked() {
+                        granted = Some(id);
+                    }
+                    if ui.button("Deny").clicked() {
+                        denied = Some(id);
+                    }
+                });
+            }
+            if let Some(id) = granted {
+                session.set_presenter(id);
+            }
+            if let Some(id) = denied {
+                session.deny_control(id);
+            }
+            ui.horizontal(|ui| {
+                let label = if session.is_host() { "Take control" } else { "Request control" };
+                if !session.is_presenter() && ui.button(label).clicked() {
+                    session.take_control();
+                }
+                if ui.button("Leave").clicked() {
+                    leave = true;
+                }
+            });
+            ui.add_enabled(
+                !session.is_presenter(),
+                egui::Checkbox::new(&mut self.follow_presenter, "Follow presenter"),
+            );
+        }
+        if leave {
+            self.leave();
+        }
+        if let Some(ref error) = self.error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.annotating, "Annotate")
+                .on_hover_text("Draw annotations with the left mouse button");
+            ui.color_edit_button_srgba_unmultiplied(&mut self.annotation_color);
+            if ui.button("Clear").clicked() {
+                self.clear_annotations();
+            }
+        });
+    }
+}
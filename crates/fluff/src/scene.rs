@@ -1,10 +1,15 @@
 //! Stuff related to strokes.
-use glam::{DVec4, vec2, Vec3};
+use glam::{DVec4, vec2, vec3, Vec3, Vec4};
 use graal::{BufferUsage, Device, MemoryLocation};
 use houdinio::Geo;
+use tracing::warn;
+use crate::aabb::AABB;
+use crate::brush::BrushSettings;
+use crate::curve_id::{curve_ids, curve_seed};
+use crate::gpu_memory::MemoryCategory;
 use crate::util::{AppendBuffer, lagrange_interpolate_4};
 use crate::overlay::CubicBezierSegment;
-use crate::shaders::shared::{ControlPoint, CurveDesc, Stroke, StrokeVertex};
+use crate::shaders::shared::{ControlPoint, CurveDesc, MeshVertex, Stroke, StrokeVertex};
 
 /// Represents a range of curves in the curve buffer.
 #[derive(Copy, Clone, Debug)]
@@ -14,6 +19,29 @@ pub struct CurveRange {
     pub count: u32,
 }
 
+/// Kind of a scene object.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SceneObjectKind {
+    /// Triangle mesh (e.g. a scalp mesh), in the mesh vertex buffer.
+    Mesh,
+    /// Point cloud, in the point buffer.
+    Points,
+}
+
+/// A mesh or point cloud in an animation frame.
+#[derive(Clone, Debug)]
+pub struct SceneObject {
+    /// Name of the object. Identifies the object across frames.
+    pub name: String,
+    pub kind: SceneObjectKind,
+    /// Index of the first vertex (meshes) or point (point clouds).
+    pub start: u32,
+    /// Number of triangles (meshes) or points (point clouds).
+    pub count: u32,
+    /// Bounding box of the vertices or points.
+    pub bounds: AABB,
+}
+
 /// Information about a single animation frame.
 #[derive(Debug)]
 pub struct AnimationFrame {
@@ -23,8 +51,39 @@ pub struct AnimationFrame {
     pub curve_range: CurveRange,
     /// Curve segments
     pub curve_segments: Vec<CubicBezierSegment>,
+    /// Bounding boxes of the volume primitives in the frame.
+    pub volume_bounds: Vec<AABB>,
     pub stroke_offset: u32,
     pub stroke_count: u32,
+    /// Meshes and point clouds.
+    pub objects: Vec<SceneObject>,
+    /// Bounding box of the control points of the curves. `None` if there are no curves.
+    pub curve_bounds: Option<AABB>,
+}
+
+impl AnimationFrame {
+    /// Returns the bounding box of the curves and objects of the frame.
+    pub fn bounds(&self) -> Option<AABB> {
+        self.objects
+            .iter()
+            .map(|object| object.bounds)
+            .chain(self.curve_bounds)
+            .reduce(|a, b| a.union(&b))
+    }
+}
+
+/// A stroke drawn in the viewport with the brush engine (see `brush`).
+///
+/// The stroke is kept as cubic Bézier curves, so that it can be tessellated again when edited.
+#[derive(Clone, Debug)]
+pub struct DrawnStroke {
+    /// Index of the tessellated stroke in the stroke buffer.
+    pub stroke_index: u32,
+    /// Control points, as a sequence of cubic bézier segments sharing their end points. `w` is the pen pressure.
+    pub control_points: Vec<Vec4>,
+    /// Brush settings the stroke was tessellated with.
+    pub brush: BrushSettings,
+    pub color: [u8; 4],
 }
 
 /// Scene data.
@@ -38,8 +97,172 @@ pub struct Scene {
     pub curve_buffer: AppendBuffer<CurveDesc>,
     pub stroke_vertex_buffer: AppendBuffer<StrokeVertex>,
     pub stroke_buffer: AppendBuffer<Stroke>,
+    /// Vertices of the meshes, three per triangle.
+    pub mesh_vertex_buffer: AppendBuffer<MeshVertex>,
+    /// Points of the point clouds.
+    pub point_buffer: AppendBuffer<ControlPoint>,
+    /// Strokes drawn in the viewport.
+    pub drawn_strokes: Vec<DrawnStroke>,
+}
+
+impl Scene {
+    /// Returns the strokes of the given frame.
+    pub fn frame_strokes(&self, frame: usize) -> &[Stroke] {
+        let frame = &self.frames[frame];
+        let start = frame.stroke_offset as usize;
+        &self.stroke_buffer.as_slice()[start..start + frame.stroke_count as usize]
+    }
+
+    /// Returns the vertices of a stroke.
+    pub fn stroke_vertices(&self, stroke: &Stroke) -> &[StrokeVertex] {
+        let start = stroke.base_vertex as usize;
+        &self.stroke_vertex_buffer.as_slice()[start..start + stroke.vertex_count as usize]
+    }
+
+    /// Returns the vertices of a stroke for modification.
+    pub fn stroke_vertices_mut(&mut self, stroke: &Stroke) -> &mut [StrokeVertex] {
+        let start = stroke.base_vertex as usize;
+        &mut self.stroke_vertex_buffer.as_mut_slice()[start..start + stroke.vertex_count as usize]
+    }
+
+    /// Adds a stroke at the end of a frame. Returns the index of the stroke in the stroke buffer.
+    ///
+    /// Strokes are stored contiguously by frame, so only the last frame can receive new strokes: returns
+    /// `None` for other frames.
+    pub fn push_stroke(&mut self, frame: usize, vertices: &[StrokeVertex], mut stroke: Stroke) -> Option<u32> {
+        if frame + 1 != self.frames.len() {
+            return None;
+        }
+        stroke.base_vertex = self.stroke_vertex_buffer.len() as u32;
+        stroke.vertex_count = vertices.len() as u32;
+        for v in vertices {
+            self.stroke_vertex_buffer.push(*v);
+        }
+        let index = self.stroke_buffer.len() as u32;
+        self.stroke_buffer.push(stroke);
+        self.frames[frame].stroke_count += 1;
+        Some(index)
+    }
+
+    /// Replaces the vertices of a stroke.
+    ///
+    /// The new vertices are appended to the vertex buffer; the old ones stay unused until the scene is reloaded.
+    pub fn replace_stroke_vertices(&mut self, stroke_index: u32, vertices: &[StrokeVertex], arc_length: f32) {
+        let base_vertex = self.stroke_vertex_buffer.len() as u32;
+        for v in vertices {
+            self.stroke_vertex_buffer.push(*v);
+        }
+        let stroke = &mut self.stroke_buffer.as_mut_slice()[stroke_index as usize];
+        stroke.base_vertex = base_vertex;
+        stroke.vertex_count = vertices.len() as u32;
+        stroke.arc_length = arc_length;
+    }
+
+    /// Adds the strokes drawn on another scene (e.g. a previous version of the same file) to the same
+    /// frames of this scene. Strokes drawn on frames that this scene doesn't have are dropped.
+    ///
+    /// Call this before the scene is rendered: the stroke buffer is rewritten in place.
+    pub fn copy_drawn_strokes(&mut self, from: &Scene) {
+        let mut by_frame: Vec<Vec<&DrawnStroke>> = vec![vec![]; self.frames.len()];
+        let mut dropped = 0;
+        for drawn in from.drawn_strokes.iter() {
+            let frame = from.frames.iter().position(|frame| {
+                (frame.stroke_offset..frame.stroke_offset + frame.stroke_count).contains(&drawn.stroke_index)
+            });
+            match frame.and_then(|frame| by_frame.get_mut(frame)) {
+                Some(strokes) => strokes.push(drawn),
+                None => dropped += 1,
+            }
+        }
+        if dropped > 0 {
+            warn!("{dropped} drawn strokes dropped: their frames are not in the new scene");
+        }
+        if by_frame.iter().all(Vec::is_empty) {
+            return;
+        }
+
+        // strokes are stored contiguously by frame: rebuild the stroke buffer with the drawn strokes
+        // after the strokes of their frame
+        let strokes = self.stroke_buffer.as_slice().to_vec();
+        self.stroke_buffer.truncate(0);
+        for (frame_index, drawn_strokes) in by_frame.into_iter().enumerate() {
+            let start = self.frames[frame_index].stroke_offset as usize;
+            let end = start + self.frames[frame_index].stroke_count as usize;
+            self.frames[frame_index].stroke_offset = self.stroke_buffer.len() as u32;
+            for stroke in strokes[start..end].iter() {
+                self.stroke_buffer.push(*stroke);
+            }
+            for drawn in drawn_strokes {
+                let stroke = from.stroke_buffer.as_slice()[drawn.stroke_index as usize];
+                // drawn strokes are identified by their index
+                let stroke_index = self.stroke_buffer.len() as u32;
+                let base_vertex = self.stroke_vertex_buffer.len() as u32;
+                for v in from.stroke_vertices(&stroke) {
+                    self.stroke_vertex_buffer.push(*v);
+                }
+                self.stroke_buffer.push(Stroke {
+                    base_vertex,
+                    curve_id: stroke_index,
+                    seed: curve_seed(stroke_index),
+                    ..stroke
+                });
+                self.frames[frame_index].stroke_count += 1;
+                self.drawn_strokes.push(DrawnStroke {
+                    stroke_index,
+                    ..drawn.clone()
+                });
+            }
+        }
+    }
+
+    /// Returns the center (average of the vertices) of each stroke in the given frame.
+    pub fn stroke_centers(&self, frame: usize) -> Vec<Vec3> {
+        self.frame_strokes(frame)
+            .iter()
+            .map(|stroke| {
+                let vertices = self.stroke_vertices(stroke);
+                let sum: Vec3 = vertices.iter().map(|v| Vec3::from(v.pos)).sum();
+                sum / vertices.len().max(1) as f32
+            })
+            .collect()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A curve in a scene file.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SceneFileCurve {
+    /// Control points of the curve, as a sequence of cubic bézier segments sharing their end points.
+    pub points: Vec<[f32; 3]>,
+    /// Per-control point colors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colors: Option<Vec<[f32; 3]>>,
+    /// Stable ID of the curve, from the `id` primitive attribute or generated on import.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+}
+
+/// A frame in a scene file.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct SceneFileFrame {
+    pub curves: Vec<SceneFileCurve>,
+    /// Number of curves in each level of detail, from the finest to the coarsest.
+    ///
+    /// Curves are ordered so that each LOD is a prefix of `curves`.
+    pub lod_curve_counts: Vec<u32>,
 }
 
+/// Serialized scene, as produced by `fluff import`.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct SceneFile {
+    pub version: u32,
+    pub frames: Vec<SceneFileFrame>,
+}
+
+impl SceneFile {
+    pub const VERSION: u32 = 1;
+}
 
 /// Converts Bézier curve data from `.geo` files to a format that can be uploaded to the GPU.
 ///
@@ -65,6 +288,7 @@ pub fn load_stroke_animation_data(device: &Device, geo_files: &[Geo]) -> Scene {
                         curve_count += v.iter().map(|v| v.len() / 3).sum::<usize>();
                     }
                 },
+                houdinio::Primitive::PolygonRun(_) | houdinio::Primitive::Volume(_) => {}
             }
         }
     }
@@ -73,13 +297,23 @@ pub fn load_stroke_animation_data(device: &Device, geo_files: &[Geo]) -> Scene {
 
     let mut position_buffer = AppendBuffer::with_capacity(device, BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu, point_count);
     position_buffer.set_name("control point buffer");
+    position_buffer.set_memory_category(MemoryCategory::Curves);
     let mut curve_buffer = AppendBuffer::with_capacity(device, BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu, curve_count);
     curve_buffer.set_name("curve buffer");
+    curve_buffer.set_memory_category(MemoryCategory::Curves);
 
     let mut stroke_vertex_buffer = AppendBuffer::new(device, BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu);
     stroke_vertex_buffer.set_name("stroke vertex buffer");
+    stroke_vertex_buffer.set_memory_category(MemoryCategory::Curves);
     let mut stroke_buffer = AppendBuffer::new(device, BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu);
     stroke_buffer.set_name("stroke buffer");
+    stroke_buffer.set_memory_category(MemoryCategory::Curves);
+    let mut mesh_vertex_buffer = AppendBuffer::new(device, BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu);
+    mesh_vertex_buffer.set_name("mesh vertex buffer");
+    mesh_vertex_buffer.set_memory_category(MemoryCategory::Geometry);
+    let mut point_buffer = AppendBuffer::new(device, BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu);
+    point_buffer.set_name("point cloud buffer");
+    point_buffer.set_memory_category(MemoryCategory::Geometry);
 
     let mut frames = vec![];
 
@@ -93,15 +327,22 @@ pub fn load_stroke_animation_data(device: &Device, geo_files: &[Geo]) -> Scene {
         let mut point_ptr = 0;
         let curve_data: *mut CurveDesc = curve_buffer.as_mut_ptr();
         let mut curve_ptr = 0;
+        let mut previous_ids: Option<Vec<u32>> = None;
 
         for f in geo_files.iter() {
             let offset = curve_ptr;
+            let point_offset = point_ptr;
+            let ids = curve_ids(f, previous_ids.as_deref());
+            let mut curve_index = 0;
 
             let mut curve_segments = vec![];
+            let mut volume_bounds = vec![];
             for prim in f.primitives.iter() {
                 match prim {
                     houdinio::Primitive::BezierRun(run) => {
                         for curve in run.iter() {
+                            let curve_id = ids[curve_index];
+                            curve_index += 1;
                             let start = point_ptr;
                             for &vertex_index in curve.vertices.iter() {
                                 let pos = f.vertex_position(vertex_index);
@@ -130,21 +371,34 @@ pub fn load_stroke_animation_data(device: &Device, geo_files: &[Geo]) -> Scene {
                                     opacity_profile: opacity_profile.to_array(),
                                     param_range: vec2(i as f32 / num_segments_f, (i + 1) as f32 / num_segments_f),
                                     brush_index: 0,
+                                    curve_id,
+                                    seed: curve_seed(curve_id),
                                     //_dummy: [0; 3],
                                 };
                                 curve_ptr += 1;
                             }
                         }
                     }
+                    houdinio::Primitive::Volume(volume) => {
+                        let (min, max) = volume.bounds(f);
+                        volume_bounds.push(AABB {
+                            min: min.into(),
+                            max: max.into(),
+                        });
+                    }
+                    houdinio::Primitive::PolygonRun(_) => {}
                 }
             }
 
             // flatten curves to polylines
             let stroke_offset = stroke_buffer.len() as u32;
+            curve_index = 0;
             for prim in f.primitives.iter() {
                 match prim {
                     houdinio::Primitive::BezierRun(run) => {
                         for curve in run.iter() {
+                            let curve_id = ids[curve_index];
+                            curve_index += 1;
                             let mut vertices = vec![];
                             let mut color = [1.0, 1.0, 1.0];
                             let base_vertex = stroke_vertex_buffer.len() as u32;
@@ -186,12 +440,18 @@ pub fn load_stroke_animation_data(device: &Device, geo_files: &[Geo]) -> Scene {
                                 vertex_count: vertices.len() as u32,
                                 brush: 0,
                                 arc_length: s,
+                                curve_id,
+                                seed: curve_seed(curve_id),
                             });
                         }
                     }
+                    houdinio::Primitive::PolygonRun(_) | houdinio::Primitive::Volume(_) => {}
                 }
             }
 
+            let objects = load_scene_objects(f, &mut mesh_vertex_buffer, &mut point_buffer);
+            let curve_bounds = AABB::from_points((point_offset..point_ptr).map(|i| Vec3::from((*point_data.offset(i)).pos)));
+
             frames.push(AnimationFrame {
                 time: 0.0, // TODO
                 curve_range: CurveRange {
@@ -199,9 +459,13 @@ pub fn load_stroke_animation_data(device: &Device, geo_files: &[Geo]) -> Scene {
                     count: curve_ptr as u32 - offset as u32,
                 },
                 curve_segments,
+                volume_bounds,
                 stroke_offset,
                 stroke_count: stroke_buffer.len() as u32 - stroke_offset,
+                objects,
+                curve_bounds,
             });
+            previous_ids = Some(ids);
         }
         position_buffer.set_len(point_count);
         curve_buffer.set_len(curve_count);
@@ -216,5 +480,100 @@ pub fn load_stroke_animation_data(device: &Device, geo_files: &[Geo]) -> Scene {
         curve_buffer,
         stroke_vertex_buffer,
         stroke_buffer,
+        mesh_vertex_buffer,
+        point_buffer,
+        drawn_strokes: vec![],
     }
 }
+
+/// Triangulates the polygons of a geometry file, and collects the points that are not part of any primitive
+/// as a point cloud.
+///
+/// Each polygon run becomes a mesh object. Normals come from the `N` point attribute if present,
+/// otherwise polygons are flat-shaded.
+fn load_scene_objects(
+    geo: &Geo,
+    mesh_vertices: &mut AppendBuffer<MeshVertex>,
+    points: &mut AppendBuffer<ControlPoint>,
+) -> Vec<SceneObject> {
+    const DEFAULT_MESH_COLOR: [f32; 3] = [0.7, 0.7, 0.7];
+    const DEFAULT_POINT_COLOR: [f32; 3] = [1.0, 0.8, 0.2];
+
+    let mut objects = vec![];
+    let normals = geo.normals();
+    let mut mesh_index = 0;
+    for prim in geo.primitives.iter() {
+        let houdinio::Primitive::PolygonRun(run) = prim else { continue };
+        let start = mesh_vertices.len() as u32;
+        for (vertices, closed) in run.iter() {
+            if !closed || vertices.len() < 3 {
+                continue;
+            }
+            let positions: Vec<Vec3> = vertices.iter().map(|&v| Vec3::from(geo.vertex_position(v))).collect();
+            // Newell's method, robust to non-planar polygons
+            let mut face_normal = Vec3::ZERO;
+            for (i, p) in positions.iter().enumerate() {
+                let q = positions[(i + 1) % positions.len()];
+                face_normal += vec3((p.y - q.y) * (p.z + q.z), (p.z - q.z) * (p.x + q.x), (p.x - q.x) * (p.y + q.y));
+            }
+            let face_normal = face_normal.normalize_or_zero();
+            let vertex = |i: usize| {
+                let point = geo.topology[vertices[i] as usize] as usize;
+                MeshVertex {
+                    pos: positions[i].to_array(),
+                    normal: normals.map_or(face_normal.to_array(), |n| n[point]),
+                    color: geo.vertex_color(vertices[i]).unwrap_or(DEFAULT_MESH_COLOR),
+                }
+            };
+            // triangle fan
+            for i in 1..vertices.len() - 1 {
+                mesh_vertices.push(vertex(0));
+                mesh_vertices.push(vertex(i));
+                mesh_vertices.push(vertex(i + 1));
+            }
+        }
+        let count = (mesh_vertices.len() as u32 - start) / 3;
+        if count > 0 {
+            let vertices = &mesh_vertices.as_slice()[start as usize..];
+            objects.push(SceneObject {
+                name: format!("mesh{mesh_index}"),
+                kind: SceneObjectKind::Mesh,
+                start,
+                count,
+                bounds: AABB::from_points(vertices.iter().map(|v| Vec3::from(v.pos))).unwrap_or_default(),
+            });
+        }
+        mesh_index += 1;
+    }
+
+    // points not referenced by any vertex
+    let mut referenced = vec![false; geo.point_count];
+    for &point in geo.topology.iter() {
+        if let Some(r) = referenced.get_mut(point as usize) {
+            *r = true;
+        }
+    }
+    let start = points.len() as u32;
+    let colors = geo.color();
+    for (i, pos) in geo.positions().iter().enumerate() {
+        if !referenced[i] {
+            points.push(ControlPoint {
+                pos: *pos,
+                color: colors.map_or(DEFAULT_POINT_COLOR, |c| c[i]),
+            });
+        }
+    }
+    let count = points.len() as u32 - start;
+    if count > 0 {
+        let cloud = &points.as_slice()[start as usize..];
+        objects.push(SceneObject {
+            name: "points".to_string(),
+            kind: SceneObjectKind::Points,
+            start,
+            count,
+            bounds: AABB::from_points(cloud.iter().map(|p| Vec3::from(p.pos))).unwrap_or_default(),
+        });
+    }
+
+    objects
+}
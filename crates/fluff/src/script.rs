@@ -0,0 +1,222 @@
+//! Lua scripting, for batch tweaks and reproducible look-dev setups.
+//!
+//! Scripts access the application through the `fluff` table:
+//!
+//! | Function                         | Description                                                 |
+//! |----------------------------------|-------------------------------------------------------------|
+//! | `fluff.params()`                 | Names of the animatable parameters                          |
+//! | `fluff.get(name)`                | Value of a parameter                                        |
+//! | `fluff.set(name, value)`         | Sets the value of a parameter                               |
+//! | `fluff.frame()`                  | Current frame (0-based)                                     |
+//! | `fluff.set_frame(frame)`         | Moves the timeline to a frame                               |
+//! | `fluff.frame_count()`            | Number of frames of the loaded animation                    |
+//! | `fluff.play(playing)`            | Starts or stops playback                                    |
+//! | `fluff.load(path)`               | Loads a geometry file (or file sequence)                    |
+//! | `fluff.stroke_count()`           | Number of strokes in the current frame                      |
+//! | `fluff.selection()`              | Indices of the selected strokes                             |
+//! | `fluff.select(strokes, op)`      | Selects strokes; `op` is "replace" (default), "add" or "subtract" |
+//! | `fluff.define(name, value)`      | Sets a global shader define                                 |
+//! | `fluff.export(output, inputs)`   | Converts geometry files to a scene file (like `fluff import`) |
+//!
+//! Relative paths given to `fluff.load` and `fluff.export` by a script file are resolved against the
+//! directory of the script. Output of `print` goes to the console window.
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
+
+use mlua::{Lua, Variadic};
+
+use crate::selection::SelectionOp;
+
+/// Application state exposed to scripts.
+pub trait ScriptHost {
+    fn param_names(&self) -> Vec<String>;
+    fn param(&self, name: &str) -> Option<f64>;
+    fn set_param(&mut self, name: &str, value: f64);
+    fn frame(&self) -> usize;
+    fn set_frame(&mut self, frame: usize);
+    fn frame_count(&self) -> usize;
+    fn set_playing(&mut self, playing: bool);
+    fn load_geometry(&mut self, path: &Path);
+    fn stroke_count(&self) -> usize;
+    fn selection(&self) -> Vec<u32>;
+    fn select(&mut self, op: SelectionOp, strokes: Vec<u32>);
+    fn define(&mut self, name: &str, value: &str);
+}
+
+/// A line of console output.
+#[derive(Clone, Debug)]
+pub enum ConsoleLine {
+    /// Command entered in the console.
+    Input(String),
+    Output(String),
+    Error(String),
+}
+
+/// Lua interpreter. Global variables persist between scripts.
+pub struct ScriptEngine {
+    lua: Lua,
+    output: RefCell<Vec<ConsoleLine>>,
+}
+
+fn selection_op(op: Option<String>) -> mlua::Result<SelectionOp> {
+    match op.as_deref() {
+        None | Some("replace") => Ok(SelectionOp::Replace),
+        Some("add") => Ok(SelectionOp::Add),
+        Some("subtract") => Ok(SelectionOp::Subtract),
+        Some(other) => Err(mlua::Error::RuntimeError(format!("invalid selection operation: `{other}`"))),
+    }
+}
+
+impl ScriptEngine {
+    pub fn new() -> ScriptEngine {
+        ScriptEngine {
+            lua: Lua::new(),
+            output: RefCell::new(vec![]),
+        }
+    }
+
+    /// Returns the console output.
+    pub fn output(&self) -> std::cell::Ref<'_, Vec<ConsoleLine>> {
+        self.output.borrow()
+    }
+
+    pub fn clear_output(&self) {
+        self.output.borrow_mut().clear();
+    }
+
+    fn print(&self, line: ConsoleLine) {
+        match line {
+            ConsoleLine::Error(ref msg) => eprintln!("{msg}"),
+            ConsoleLine::Output(ref msg) => println!("{msg}"),
+            ConsoleLine::Input(_) => {}
+        }
+        self.output.borrow_mut().push(line);
+    }
+
+    /// Runs a command entered in the console.
+    pub fn run_command(&self, command: &str, host: &mut dyn ScriptHost) {
+        self.output.borrow_mut().push(ConsoleLine::Input(command.to_string()));
+        self.run(command, "console", None, host);
+    }
+
+    /// Runs a script file.
+    pub fn run_file(&self, path: &Path, host: &mut dyn ScriptHost) {
+        match std::fs::read_to_string(path) {
+            Ok(source) => self.run(&source, &path.display().to_string(), path.parent(), host),
+            Err(err) => self.print(ConsoleLine::Error(format!("could not read `{}`: {err}", path.display()))),
+        }
+    }
+
+    /// Runs a script, resolving relative paths against `dir`. Errors are reported in the console.
+    fn run(&self, source: &str, name: &str, dir: Option<&Path>, host: &mut dyn ScriptHost) {
+        if let Err(err) = self.run_inner(source, name, dir, host) {
+            self.print(ConsoleLine::Error(err.to_string()));
+        }
+    }
+
+    fn run_inner(&self, source: &str, name: &str, dir: Option<&Path>, host: &mut dyn ScriptHost) -> mlua::Result<()> {
+        let lua = &self.lua;
+        let resolve = |path: String| match dir {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        };
+        let host = RefCell::new(host);
+        lua.scope(|scope| {
+            let fluff = lua.create_table()?;
+            fluff.set("params", scope.create_function(|_, ()| Ok(host.borrow().param_names()))?)?;
+            fluff.set("get", scope.create_function(|_, name: String| Ok(host.borrow().param(&name)))?)?;
+            fluff.set(
+                "set",
+                scope.create_function(|_, (name, value): (String, f64)| {
+                    if host.borrow().param(&name).is_none() {
+                        return Err(mlua::Error::RuntimeError(format!("unknown parameter: `{name}`")));
+                    }
+                    host.borrow_mut().set_param(&name, value);
+                    Ok(())
+                })?,
+            )?;
+            fluff.set("frame", scope.create_function(|_, ()| Ok(host.borrow().frame()))?)?;
+            fluff.set(
+                "set_frame",
+                scope.create_function(|_, frame: usize| {
+                    host.borrow_mut().set_frame(frame);
+                    Ok(())
+                })?,
+            )?;
+            fluff.set("frame_count", scope.create_function(|_, ()| Ok(host.borrow().frame_count()))?)?;
+            fluff.set(
+                "play",
+                scope.create_function(|_, playing: Option<bool>| {
+                    host.borrow_mut().set_playing(playing.unwrap_or(true));
+                    Ok(())
+                })?,
+            )?;
+            fluff.set(
+                "load",
+                scope.create_function(|_, path: String| {
+                    host.borrow_mut().load_geometry(&resolve(path));
+                    Ok(())
+                })?,
+            )?;
+            fluff.set("stroke_count", scope.create_function(|_, ()| Ok(host.borrow().stroke_count()))?)?;
+            fluff.set("selection", scope.create_function(|_, ()| Ok(host.borrow().selection()))?)?;
+            fluff.set(
+                "select",
+                scope.create_function(|_, (strokes, op): (Vec<u32>, Option<String>)| {
+                    host.borrow_mut().select(selection_op(op)?, strokes);
+                    Ok(())
+                })?,
+            )?;
+            fluff.set(
+                "define",
+                scope.create_function(|_, (name, value): (String, mlua::Value)| {
+                    let value = match value {
+                        mlua::Value::String(s) => s.to_str()?.to_string(),
+                        mlua::Value::Integer(i) => i.to_string(),
+                        mlua::Value::Number(n) => n.to_string(),
+                        mlua::Value::Boolean(b) => (b as u32).to_string(),
+                        mlua::Value::Nil => String::new(),
+                        other => {
+                            return Err(mlua::Error::RuntimeError(format!(
+                                "invalid define value type: {}",
+                                other.type_name()
+                            )))
+                        }
+                    };
+                    host.borrow_mut().define(&name, &value);
+                    Ok(())
+                })?,
+            )?;
+            fluff.set(
+                "export",
+                scope.create_function(|_, (output, inputs): (String, Vec<String>)| {
+                    let path_arg = |path: String| resolve(path).to_string_lossy().into_owned();
+                    let mut args = vec!["-o".to_string(), path_arg(output)];
+                    args.extend(inputs.into_iter().map(path_arg));
+                    Ok(crate::import::run(&args) == 0)
+                })?,
+            )?;
+            lua.globals().set("fluff", fluff)?;
+
+            lua.globals().set(
+                "print",
+                scope.create_function(|lua, args: Variadic<mlua::Value>| {
+                    let tostring: mlua::Function = lua.globals().get("tostring")?;
+                    let mut line = String::new();
+                    for (i, arg) in args.into_iter().enumerate() {
+                        if i > 0 {
+                            line.push('\t');
+                        }
+                        line.push_str(&tostring.call::<_, String>(arg)?);
+                    }
+                    self.print(ConsoleLine::Output(line));
+                    Ok(())
+                })?,
+            )?;
+
+            lua.load(source).set_name(name).exec()
+        })
+    }
+}
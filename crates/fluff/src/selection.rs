@@ -0,0 +1,299 @@
+//! Stroke selection: marquee & lasso selection, named selection sets and soft selection.
+//!
+//! Strokes under a selection gesture are found in the stroke ID image written by the stroke pass, which is
+//! read back from the GPU a few frames after the gesture ends.
+use std::collections::{BTreeMap, BTreeSet};
+
+use glam::{DVec2, Vec3};
+use graal::{
+    util::DeviceExt, vk, Buffer, BufferUsage, CommandStream, Device, Image, ImageCopyBuffer, ImageCopyView,
+    ImageDataLayout, MemoryLocation,
+};
+
+/// Number of frames to wait before reading back the stroke IDs. Must be larger than the number of frames in flight.
+const READBACK_LATENCY: usize = 4;
+
+/// How a selection gesture combines with the current selection.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SelectionOp {
+    Replace,
+    Add,
+    Subtract,
+}
+
+/// Screen-space region of a selection gesture.
+#[derive(Clone, Debug)]
+pub enum SelectionShape {
+    /// Rectangle between two corners.
+    Marquee(DVec2, DVec2),
+    /// Closed polygon.
+    Lasso(Vec<DVec2>),
+}
+
+impl SelectionShape {
+    /// Whether the given screen-space point is inside the region.
+    pub fn contains(&self, p: DVec2) -> bool {
+        match self {
+            SelectionShape::Marquee(a, b) => {
+                let (min, max) = (a.min(*b), a.max(*b));
+                p.cmpge(min).all() && p.cmple(max).all()
+            }
+            SelectionShape::Lasso(points) => {
+                // even-odd rule
+                let mut inside = false;
+                for (i, a) in points.iter().enumerate() {
+                    let b = points[(i + 1) % points.len()];
+                    if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    /// Returns the outline of the region, for display.
+    pub fn outline(&self) -> Vec<DVec2> {
+        match self {
+            SelectionShape::Marquee(a, b) => vec![*a, DVec2::new(b.x, a.y), *b, DVec2::new(a.x, b.y), *a],
+            SelectionShape::Lasso(points) => points.iter().copied().chain(points.first().copied()).collect(),
+        }
+    }
+}
+
+/// Soft-selection falloff profile.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Falloff {
+    Linear,
+    Smooth,
+    Constant,
+}
+
+impl Falloff {
+    pub const ALL: [Falloff; 3] = [Falloff::Linear, Falloff::Smooth, Falloff::Constant];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Falloff::Linear => "Linear",
+            Falloff::Smooth => "Smooth",
+            Falloff::Constant => "Constant",
+        }
+    }
+
+    /// Evaluates the falloff at normalized distance `t` (0 at the selection, 1 at the radius).
+    pub fn eval(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Falloff::Linear => 1.0 - t,
+            Falloff::Smooth => {
+                let s = 1.0 - t;
+                s * s * (3.0 - 2.0 * s)
+            }
+            Falloff::Constant => {
+                if t < 1.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Selected strokes of the current frame, plus named selection sets.
+///
+/// Strokes are identified by their index in the frame, so that selections carry over between the frames
+/// of animations with a consistent curve order.
+pub struct Selection {
+    pub selected: BTreeSet<u32>,
+    /// Named selection sets.
+    pub sets: BTreeMap<String, BTreeSet<u32>>,
+    /// Soft-selection radius in world units. Zero disables soft selection.
+    pub soft_radius: f32,
+    pub falloff: Falloff,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Selection {
+            selected: Default::default(),
+            sets: Default::default(),
+            soft_radius: 0.0,
+            falloff: Falloff::Smooth,
+        }
+    }
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        self.selected.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.selected.clear();
+    }
+
+    /// Applies a selection operation with the given strokes.
+    pub fn apply(&mut self, op: SelectionOp, strokes: impl IntoIterator<Item = u32>) {
+        match op {
+            SelectionOp::Replace => {
+                self.selected = strokes.into_iter().collect();
+            }
+            SelectionOp::Add => self.selected.extend(strokes),
+            SelectionOp::Subtract => {
+                for stroke in strokes {
+                    self.selected.remove(&stroke);
+                }
+            }
+        }
+    }
+
+    /// Saves the current selection as a named set, replacing any existing set with the same name.
+    pub fn save_set(&mut self, name: &str) {
+        self.sets.insert(name.to_string(), self.selected.clone());
+    }
+
+    /// Selects the strokes of a named set.
+    pub fn select_set(&mut self, name: &str, op: SelectionOp) {
+        if let Some(set) = self.sets.get(name).cloned() {
+            self.apply(op, set);
+        }
+    }
+
+    /// Computes the selection weight of each stroke.
+    ///
+    /// `centers` are the world-space centers of the strokes of the frame. Selected strokes have
+    /// weight 1; with soft selection, other strokes get a weight decreasing with the distance to
+    /// the nearest selected stroke.
+    pub fn weights(&self, centers: &[Vec3]) -> Vec<f32> {
+        let mut weights = vec![0.0; centers.len()];
+        for &i in self.selected.iter() {
+            if let Some(w) = weights.get_mut(i as usize) {
+                *w = 1.0;
+            }
+        }
+        if self.soft_radius <= 0.0 || self.selected.is_empty() {
+            return weights;
+        }
+
+        let selected_centers: Vec<Vec3> = self.selected.iter().filter_map(|&i| centers.get(i as usize).copied()).collect();
+        for (center, w) in centers.iter().zip(weights.iter_mut()) {
+            if *w == 1.0 {
+                continue;
+            }
+            let dist = selected_centers
+                .iter()
+                .map(|c| c.distance(*center))
+                .fold(f32::INFINITY, f32::min);
+            *w = self.falloff.eval(dist / self.soft_radius);
+        }
+        weights
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A selection gesture waiting for the stroke IDs under its region to be read back from the GPU.
+pub struct PendingSelection {
+    pub op: SelectionOp,
+    shape: SelectionShape,
+    /// Animation frame whose strokes were rendered in the stroke ID image.
+    pub frame: usize,
+    /// Scale from screen coordinates to pixels of the stroke ID image.
+    scale: f64,
+    /// Region of the stroke ID image copied to the buffer.
+    origin: [u32; 2],
+    size: [u32; 2],
+    buffer: Buffer<[u32]>,
+    frames_left: usize,
+}
+
+impl PendingSelection {
+    /// Copies the region of the stroke ID image (`R32_UINT`, 0 where there's no stroke, otherwise the index of
+    /// the stroke in the frame plus one) under the gesture to host memory.
+    ///
+    /// Returns `None` if the region doesn't overlap the image.
+    pub fn new(
+        device: &Device,
+        cmd: &mut CommandStream,
+        stroke_ids: &Image,
+        op: SelectionOp,
+        shape: SelectionShape,
+        frame: usize,
+        scale: f64,
+    ) -> Option<PendingSelection> {
+        let outline = shape.outline();
+        let min = outline.iter().fold(DVec2::INFINITY, |min, p| min.min(*p)) * scale;
+        let max = outline.iter().fold(DVec2::NEG_INFINITY, |max, p| max.max(*p)) * scale;
+        let min = min.floor().max(DVec2::ZERO);
+        let max = max.ceil().min(DVec2::new(stroke_ids.width() as f64, stroke_ids.height() as f64));
+        if !(max - min).cmpgt(DVec2::ZERO).all() {
+            return None;
+        }
+        let origin = min.as_uvec2().to_array();
+        let size = (max - min).as_uvec2().to_array();
+
+        let len = size[0] as usize * size[1] as usize;
+        let buffer = device.create_array_buffer::<u32>(BufferUsage::TRANSFER_DST, MemoryLocation::CpuToGpu, len);
+        buffer.set_name("selection readback");
+        cmd.copy_image_to_buffer(
+            ImageCopyView {
+                image: stroke_ids,
+                mip_level: 0,
+                origin: vk::Offset3D {
+                    x: origin[0] as i32,
+                    y: origin[1] as i32,
+                    z: 0,
+                },
+                aspect: vk::ImageAspectFlags::COLOR,
+            },
+            ImageCopyBuffer {
+                buffer: &buffer.untyped,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    row_length: Some(size[0]),
+                    image_height: Some(size[1]),
+                },
+            },
+            vk::Extent3D {
+                width: size[0],
+                height: size[1],
+                depth: 1,
+            },
+        );
+        Some(PendingSelection {
+            op,
+            shape,
+            frame,
+            scale,
+            origin,
+            size,
+            buffer,
+            frames_left: READBACK_LATENCY,
+        })
+    }
+
+    /// Call once per frame. Returns the strokes inside the region once the GPU has finished the copy.
+    pub fn poll(&mut self) -> Option<Vec<u32>> {
+        if self.frames_left > 0 {
+            self.frames_left -= 1;
+            return None;
+        }
+        let [width, height] = self.size;
+        // SAFETY: the buffer is host-visible, and the GPU has finished the frame that wrote to it
+        // since there are less than READBACK_LATENCY frames in flight.
+        let ids = unsafe { std::slice::from_raw_parts(self.buffer.as_mut_ptr(), width as usize * height as usize) };
+        let mut strokes = BTreeSet::new();
+        for y in 0..height {
+            for x in 0..width {
+                let id = ids[(y * width + x) as usize];
+                // test the center of the pixel, in screen coordinates
+                let p = (DVec2::new((self.origin[0] + x) as f64, (self.origin[1] + y) as f64) + 0.5) / self.scale;
+                if id != 0 && self.shape.contains(p) {
+                    strokes.insert(id - 1);
+                }
+            }
+        }
+        Some(strokes.into_iter().collect())
+    }
+}
@@ -30,6 +30,8 @@ pub struct SceneParams {
     pub viewport_size: UVec2,
     pub cursor_pos: Vec2,
     pub time: f32,
+    /// Random seed of the frame (see `determinism`).
+    pub seed: u32,
 }
 
 /// 3D bezier control point.
@@ -58,6 +60,10 @@ pub struct CurveDesc {
     /// parameter range
     pub param_range: Vec2,
     pub brush_index: u32,
+    /// Stable ID of the curve that the range belongs to.
+    pub curve_id: u32,
+    /// Random value in `[0, 1)` derived from the curve ID.
+    pub seed: f32,
 }
 
 /// Stroke vertex.
@@ -80,6 +86,10 @@ pub struct Stroke {
     pub vertex_count: u32,
     pub brush: u8,
     pub arc_length: f32,
+    /// Stable ID of the source curve.
+    pub curve_id: u32,
+    /// Random value in `[0, 1)` derived from the curve ID.
+    pub seed: f32,
 }
 
 
@@ -207,5 +217,220 @@ pub struct DrawStrokesPushConstants {
     pub brush: u32,
 }
 
+/// Culling statistics, accumulated by the GPU ribbons pipeline.
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct CullingStats {
+    /// Curve segments tested for visibility.
+    pub segments_tested: u32,
+    /// Segments outside the view frustum.
+    pub frustum_culled: u32,
+    /// Segments in the view frustum but beyond the culling distance.
+    pub distance_culled: u32,
+    /// Segments hidden behind the occluders of the depth pre-pass.
+    pub occlusion_culled: u32,
+    /// Line segments produced by the tessellation of visible curve segments.
+    pub segments_rasterized: u32,
+    /// Fragments shaded.
+    pub fragments: u32,
+}
+
+/// Push constants of the GPU ribbon tessellation pipeline (`ribbons.glsl`).
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DrawRibbonsPushConstants {
+    pub control_points: DeviceAddress<[ControlPoint]>,
+    pub curves: DeviceAddress<[CurveDesc]>,
+    pub scene_params: DeviceAddress<SceneParams>,
+    /// Base index into the curve buffer.
+    pub base_curve_index: u32,
+    pub curve_count: u32,
+    /// Ribbon width in pixels.
+    pub width: f32,
+    pub filter_width: f32,
+    /// Maximum distance in pixels between the tessellated ribbon and the curve.
+    pub tolerance: f32,
+    /// Segments farther than this distance from the camera are culled. Disabled if zero.
+    pub cull_distance: f32,
+    /// If non-zero, culling statistics are accumulated in `stats`.
+    pub collect_stats: u32,
+    pub stats: DeviceAddress<[CullingStats]>,
+    /// Color blended over the curve colors, with the blend amount in alpha (onion skinning).
+    pub tint: Vec4,
+    /// Opacity multiplier.
+    pub opacity: f32,
+    /// Levels of the depth pyramid of the occluders (see `DepthPyramid`), for occlusion culling.
+    pub depth_pyramid: DeviceAddress<[ImageHandle]>,
+    /// Number of levels of the depth pyramid. Occlusion culling is disabled if zero.
+    pub depth_pyramid_levels: u32,
+}
+
+/// Sphere collider of the strand dynamics solver.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct SphereCollider {
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+/// Push constants of the strand dynamics solver (`strand_dynamics.comp`).
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct StrandDynamicsParams {
+    /// Animated control points. The solver pulls the simulated strands towards them.
+    pub control_points: DeviceAddress<[ControlPoint]>,
+    pub curves: DeviceAddress<[CurveDesc]>,
+    /// Simulated positions of the control points of the frame, relative to the first control point of the frame.
+    pub positions: DeviceAddress<[Vec4]>,
+    /// Positions at the previous step.
+    pub prev_positions: DeviceAddress<[Vec4]>,
+    /// Simulated control points, at the same indices as in `control_points`.
+    pub simulated_points: DeviceAddress<[ControlPoint]>,
+    pub colliders: DeviceAddress<[SphereCollider]>,
+    /// Base index into the curve buffer.
+    pub base_curve_index: u32,
+    pub curve_count: u32,
+    pub collider_count: u32,
+    /// Number of constraint iterations.
+    pub iterations: u32,
+    pub gravity: Vec3,
+    /// Time step in seconds.
+    pub dt: f32,
+    /// Fraction of the velocity lost at each step.
+    pub damping: f32,
+    /// How strongly the strands are pulled back to their animated shape (0: free, 1: rigid).
+    pub stiffness: f32,
+    /// If non-zero, the strands are reset to their animated shape.
+    pub reset: u32,
+}
+
+pub const STRAND_DYNAMICS_WORKGROUP_SIZE: u32 = 64;
+
+/// Vertex of the meshes of the scene (e.g. scalp meshes).
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct MeshVertex {
+    pub pos: [f32; 3],
+    pub normal: [f32; 3],
+    pub color: [f32; 3],
+}
+
+/// Push constants of the mesh and point cloud pipelines (`geometry.glsl`).
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DrawGeometryPushConstants {
+    /// Mesh vertices, three per triangle.
+    pub mesh_vertices: DeviceAddress<[MeshVertex]>,
+    pub points: DeviceAddress<[ControlPoint]>,
+    pub scene_params: DeviceAddress<SceneParams>,
+    /// Index of the first vertex (meshes) or point (point clouds).
+    pub base_index: u32,
+    /// Number of triangles or points.
+    pub count: u32,
+    /// Radius of the point sprites, in scene units if `size_attenuation` is set, otherwise in pixels.
+    pub point_size: f32,
+    pub size_attenuation: u32,
+    /// If non-zero, meshes are colored by their normals instead of shaded.
+    pub color_by_normals: u32,
+}
 
+/// Push constants of the display transform pass (`display_transform.comp`).
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DisplayTransformParams {
+    pub viewport_size: UVec2,
+    /// Frame in the working space.
+    pub input_image: ImageHandle,
+    pub output_image: ImageHandle,
+    /// Conversion from the working space to linear Rec.709.
+    pub working_to_rec709: Mat3,
+    /// Exposure multiplier, applied in the working space.
+    pub exposure: f32,
+    /// View transform: 0 = raw, 1 = sRGB transfer function, 2 = 3D LUT.
+    pub view: u32,
+    /// Entries of the 3D LUT, red varying fastest.
+    pub lut: DeviceAddress<[Vec3]>,
+    /// Number of LUT entries along each axis.
+    pub lut_size: u32,
+    /// Input range of the LUT.
+    pub lut_domain_min: Vec3,
+    pub lut_domain_max: Vec3,
+}
+
+pub const DISPLAY_TRANSFORM_WORKGROUP_SIZE: u32 = 16;
+
+/// Push constants of the stylization pass (`stylize.comp`).
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct StylizeParams {
+    pub viewport_size: UVec2,
+    pub input_image: ImageHandle,
+    pub output_image: ImageHandle,
+    /// Copy of the depth buffer, one value per pixel, row by row.
+    pub depth: DeviceAddress<[f32]>,
+    /// Clip planes of the camera, to linearize depth values.
+    pub near_plane: f32,
+    pub far_plane: f32,
+    /// Outline color, with the opacity in alpha (0 disables outlines).
+    pub outline_color: Vec4,
+    /// Outline width in pixels.
+    pub outline_width: f32,
+    /// Relative change in view depth above which an outline is drawn.
+    pub depth_threshold: f32,
+    /// Color difference above which an outline is drawn.
+    pub color_threshold: f32,
+    /// Strength of the paper grain (0 disables the grain).
+    pub paper_strength: f32,
+    /// Size of the grain cells, in pixels.
+    pub paper_scale: f32,
+    /// Period of the grain pattern, in grain cells.
+    pub paper_period: u32,
+    /// Offset of the grain pattern, in pixels.
+    pub paper_offset: Vec2,
+    /// Random seed of the grain pattern.
+    pub seed: u32,
+}
+
+pub const STYLIZE_WORKGROUP_SIZE: u32 = 16;
+
+/// Push constants of the stock blur pass (`blur.comp`), one direction of a separable gaussian blur.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct BlurParams {
+    pub image_size: UVec2,
+    pub input_image: ImageHandle,
+    pub output_image: ImageHandle,
+    /// Non-zero to blur horizontally, zero to blur vertically.
+    pub horizontal: u32,
+    /// Number of taps on each side of the center tap.
+    pub radius: u32,
+    /// Standard deviation of the kernel, in pixels.
+    pub sigma: f32,
+}
+
+/// Push constants of the stock copy and downsampling passes (`copy_image.comp`, `downsample.comp`), and of
+/// the depth pyramid builder (`hiz_downsample.comp`).
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct ResampleParams {
+    pub input_size: UVec2,
+    pub output_size: UVec2,
+    pub input_image: ImageHandle,
+    pub output_image: ImageHandle,
+}
+
+/// Push constants of the stock depth resolve pass (`resolve_depth.comp`).
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct ResolveDepthParams {
+    pub image_size: UVec2,
+    /// Copy of the depth buffer, one value per pixel, row by row.
+    pub depth: DeviceAddress<[f32]>,
+    /// Receives the view-space depth in the red channel.
+    pub output_image: ImageHandle,
+    /// Clip planes of the camera, to linearize depth values.
+    pub near_plane: f32,
+    pub far_plane: f32,
+}
 
+pub const STOCK_PASS_WORKGROUP_SIZE: u32 = 16;
@@ -0,0 +1,151 @@
+//! Culling statistics gathered from the GPU pipelines.
+//!
+//! Counters are accumulated on the GPU in host-visible buffers, and read back a few frames later
+//! once the GPU is done with them.
+use std::collections::VecDeque;
+
+use egui::{Color32, Pos2, Sense, Stroke, Ui};
+use graal::{util::DeviceExt, Buffer, BufferUsage, Device, DeviceAddress, MemoryLocation};
+
+use crate::shaders::shared::CullingStats;
+
+/// Number of readback buffers. Must be larger than the number of frames in flight.
+const READBACK_LATENCY: usize = 4;
+
+/// Number of frames kept in the history.
+pub(crate) const HISTORY_LEN: usize = 240;
+
+/// Culling statistics of a frame.
+#[derive(Copy, Clone, Default)]
+pub struct FrameCullingStats {
+    pub counters: CullingStats,
+    /// Number of pixels of the viewport.
+    pub pixel_count: u32,
+}
+
+impl FrameCullingStats {
+    /// Segments that passed all culling tests.
+    pub fn segments_passed(&self) -> u32 {
+        let c = &self.counters;
+        c.segments_tested
+            .saturating_sub(c.frustum_culled + c.distance_culled + c.occlusion_culled)
+    }
+
+    /// Average number of fragments per pixel.
+    pub fn overdraw(&self) -> f32 {
+        self.counters.fragments as f32 / self.pixel_count.max(1) as f32
+    }
+}
+
+struct Readback {
+    buffer: Buffer<[CullingStats]>,
+    /// Viewport size of the frame that wrote to the buffer. `None` if the buffer is not in use.
+    pixel_count: Option<u32>,
+}
+
+/// Collects culling statistics over the last frames.
+pub struct CullingStatsCollector {
+    /// Whether the pipelines should gather statistics. Counting fragments has a noticeable cost.
+    pub enabled: bool,
+    readbacks: Vec<Readback>,
+    next: usize,
+    history: VecDeque<FrameCullingStats>,
+}
+
+impl CullingStatsCollector {
+    pub fn new(device: &Device) -> CullingStatsCollector {
+        let readbacks = (0..READBACK_LATENCY)
+            .map(|_| {
+                let buffer = device.create_array_buffer(BufferUsage::STORAGE_BUFFER, MemoryLocation::CpuToGpu, 1);
+                buffer.set_name("culling stats");
+                Readback { buffer, pixel_count: None }
+            })
+            .collect();
+        CullingStatsCollector {
+            enabled: false,
+            readbacks,
+            next: 0,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+
+    /// Returns the buffer that the pipelines of the current frame should write their counters to.
+    ///
+    /// Reads back the counters of the frame that last used this buffer, and resets them.
+    pub fn begin_frame(&mut self, width: u32, height: u32) -> DeviceAddress<[CullingStats]> {
+        let readback = &mut self.readbacks[self.next];
+        self.next = (self.next + 1) % READBACK_LATENCY;
+        // SAFETY: the buffer is host-visible, and the GPU has finished the frame that last wrote to it
+        // since there are less than READBACK_LATENCY frames in flight.
+        let counters = unsafe { &mut *readback.buffer.as_mut_ptr() };
+        if let Some(pixel_count) = readback.pixel_count.take() {
+            if self.history.len() == HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.history.push_back(FrameCullingStats {
+                counters: *counters,
+                pixel_count,
+            });
+        }
+        *counters = CullingStats::default();
+        if self.enabled {
+            readback.pixel_count = Some(width * height);
+        }
+        readback.buffer.device_address()
+    }
+
+    /// Statistics panel.
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.enabled, "Collect statistics")
+            .on_hover_text("Only the GPU Ribbons render mode reports culling statistics");
+        if !self.enabled {
+            self.history.clear();
+            return;
+        }
+        let Some(last) = self.history.back().copied() else {
+            ui.label("Waiting for data...");
+            return;
+        };
+        let c = &last.counters;
+        ui.label(format!("{} segments tested, {} passed", c.segments_tested, last.segments_passed()));
+        ui.label(format!("{} frustum culled, {} distance culled", c.frustum_culled, c.distance_culled));
+        ui.label(format!("{} occlusion culled", c.occlusion_culled));
+        ui.label(format!("{} line segments rasterized", c.segments_rasterized));
+        ui.label(format!("{:.2} average overdraw", last.overdraw()));
+
+        ui.label("Segments passed");
+        history_graph(ui, self.history.iter().map(|s| s.segments_passed() as f32), Color32::from_rgb(110, 160, 210));
+        ui.label("Line segments rasterized");
+        history_graph(ui, self.history.iter().map(|s| s.counters.segments_rasterized as f32), Color32::from_rgb(120, 200, 120));
+        ui.label("Average overdraw");
+        history_graph(ui, self.history.iter().map(|s| s.overdraw()), Color32::from_rgb(230, 160, 80));
+    }
+}
+
+/// Draws a line graph of the values, scaled to the maximum value.
+pub(crate) fn history_graph(ui: &mut Ui, values: impl ExactSizeIterator<Item = f32> + Clone, color: Color32) {
+    let size = egui::vec2(ui.available_width().max(200.0), 40.0);
+    let (resp, painter) = ui.allocate_painter(size, Sense::hover());
+    let rect = resp.rect;
+    painter.rect_filled(rect, 0., Color32::from_gray(32));
+
+    let max = values.clone().fold(0.0f32, f32::max);
+    if max <= 0.0 {
+        return;
+    }
+    let dx = rect.width() / (HISTORY_LEN - 1) as f32;
+    // align the most recent value to the right edge
+    let x0 = rect.max.x - dx * (values.len() as f32 - 1.0);
+    let points: Vec<Pos2> = values
+        .enumerate()
+        .map(|(i, v)| Pos2::new(x0 + i as f32 * dx, rect.max.y - v / max * rect.height()))
+        .collect();
+    painter.add(egui::Shape::line(points, Stroke::new(1., color)));
+    painter.text(
+        rect.left_top() + egui::vec2(2.0, 2.0),
+        egui::Align2::LEFT_TOP,
+        format!("{max:.1}"),
+        egui::FontId::monospace(10.0),
+        Color32::GRAY,
+    );
+}
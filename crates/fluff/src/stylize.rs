@@ -0,0 +1,260 @@
+//! Stylization of the viewport.
+//!
+//! A post pass (`stylize.comp`) applied to the rendered frame before the overlays: outlines extracted
+//! from discontinuities in the depth buffer and in the frame colors, and a procedural paper grain.
+//!
+//! There is no object ID buffer yet, so color discontinuities stand in for ID edges, and the
+//! parameters apply to the whole frame.
+use glam::{uvec2, vec2, Vec4};
+use graal::{prelude::*, Barrier, Buffer, ImageCopyBuffer, ImageCopyView, ImageDataLayout};
+
+use crate::{
+    camera_control::Camera,
+    engine::{ComputePipelineDesc, Engine, Error},
+    gpu_memory::{image_byte_size, MemoryCategory, MemoryTag},
+    shaders::shared::{StylizeParams, STYLIZE_WORKGROUP_SIZE},
+};
+
+/// Stylization settings, saved with the app settings.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StylizeSettings {
+    pub enabled: bool,
+    pub outlines: bool,
+    /// Outline color (linear, unmultiplied alpha).
+    pub outline_color: [f32; 4],
+    /// Outline width in pixels.
+    pub outline_width: f32,
+    /// Relative change in view depth above which an outline is drawn.
+    pub depth_threshold: f32,
+    /// Color difference above which an outline is drawn. Zero to outline depth discontinuities only.
+    pub color_threshold: f32,
+    pub paper: bool,
+    pub paper_strength: f32,
+    /// Size of the grain cells, in pixels.
+    pub paper_scale: f32,
+    /// Number of grain cells after which the pattern repeats.
+    pub paper_period: u32,
+    /// Offset of the grain pattern, in pixels.
+    pub paper_offset: [f32; 2],
+}
+
+impl Default for StylizeSettings {
+    fn default() -> Self {
+        StylizeSettings {
+            enabled: false,
+            outlines: true,
+            outline_color: [0.02, 0.02, 0.03, 1.0],
+            outline_width: 2.0,
+            depth_threshold: 0.05,
+            color_threshold: 0.0,
+            paper: true,
+            paper_strength: 0.15,
+            paper_scale: 3.0,
+            paper_period: 64,
+            paper_offset: [0.0, 0.0],
+        }
+    }
+}
+
+/// Applies the stylization pass to the frame.
+pub struct Stylize {
+    pub settings: StylizeSettings,
+    /// Copy of the depth buffer readable from compute shaders, reallocated when the frame size changes.
+    depth_copy: Option<Buffer<[f32]>>,
+    /// Output of the pass, reallocated when the frame size changes.
+    output_image: Option<Image>,
+    memory: MemoryTag,
+}
+
+impl Stylize {
+    pub fn new(settings: StylizeSettings) -> Stylize {
+        Stylize {
+            settings,
+            depth_copy: None,
+            output_image: None,
+            memory: MemoryTag::new(MemoryCategory::RenderTargets, 0),
+        }
+    }
+
+    /// Size in bytes of the depth copy and output image for a frame of the given size.
+    fn scratch_byte_size(width: u32, height: u32) -> u64 {
+        (width as u64 * height as u64 * size_of::<f32>() as u64)
+            + image_byte_size(Format::R16G16B16A16_SFLOAT, width, height, 1)
+    }
+
+    fn depth_copy(&mut self, device: &Device, width: u32, height: u32) -> Buffer<[f32]> {
+        let len = width as usize * height as usize;
+        if let Some(ref buffer) = self.depth_copy {
+            if buffer.len() == len {
+                return buffer.clone();
+            }
+        }
+        let buffer = device.create_array_buffer::<f32>(
+            BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+            len,
+        );
+        buffer.set_name("stylize depth copy");
+        self.memory.set_bytes(Self::scratch_byte_size(width, height));
+        self.depth_copy = Some(buffer.clone());
+        buffer
+    }
+
+    fn output_image(&mut self, device: &Device, width: u32, height: u32) -> Image {
+        if let Some(ref image) = self.output_image {
+            if image.width() == width && image.height() == height {
+                return image.clone();
+            }
+        }
+        let image = device.create_image(&ImageCreateInfo {
+            memory_location: MemoryLocation::GpuOnly,
+            type_: ImageType::Image2D,
+            usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+            format: Format::R16G16B16A16_SFLOAT,
+            width,
+            height,
+            depth: 1,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: 1,
+        });
+        image.set_name("stylize_image");
+        self.memory.set_bytes(Self::scratch_byte_size(width, height));
+        self.output_image = Some(image.clone());
+        image
+    }
+
+    /// Stylizes `frame` in place. `depth` is the depth buffer the frame was rendered with, and `seed`
+    /// the seed of the paper grain, which should be the same on all frames (see `SeedSettings::base_seed`).
+    pub fn apply(
+        &mut self,
+        cmd: &mut CommandStream,
+        engine: &mut Engine,
+        camera: &Camera,
+        frame: &Image,
+        depth: &Image,
+        seed: u32,
+    ) -> Result<(), Error> {
+        let settings = &self.settings;
+        if !settings.enabled || (!settings.outlines && !settings.paper) {
+            return Ok(());
+        }
+        let pipeline = engine.create_compute_pipeline(
+            "stylize",
+            ComputePipelineDesc {
+                shader: "crates/fluff/shaders/stylize.comp".into(),
+                defines: Default::default(),
+            },
+        )?;
+
+        let (width, height) = (frame.width(), frame.height());
+        let device = cmd.device().clone();
+        let depth_copy = self.depth_copy(&device, width, height);
+        let output = self.output_image(&device, width, height);
+        let input_view = frame.create_top_level_view();
+        let output_view = output.create_top_level_view();
+        cmd.reference_resource(&input_view);
+        cmd.reference_resource(&output_view);
+        cmd.reference_resource(&depth_copy);
+
+        cmd.copy_image_to_buffer(
+            ImageCopyView {
+                image: depth,
+                mip_level: 0,
+                origin: vk::Offset3D { x: 0, y: 0, z: 0 },
+                aspect: vk::ImageAspectFlags::DEPTH,
+            },
+            ImageCopyBuffer {
+                buffer: &depth_copy.untyped,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    row_length: Some(width),
+                    image_height: Some(height),
+                },
+            },
+            vk::Extent3D { width, height, depth: 1 },
+        );
+
+        let settings = &self.settings;
+        let outline_color = if settings.outlines {
+            Vec4::from(settings.outline_color)
+        } else {
+            Vec4::ZERO
+        };
+        let paper_strength = if settings.paper { settings.paper_strength } else { 0.0 };
+
+        cmd.barrier(
+            Barrier::new()
+                .shader_storage_read()
+                .shader_read_image(frame)
+                .shader_write_image(&output),
+        );
+        let mut encoder = cmd.begin_compute();
+        encoder.bind_compute_pipeline(&pipeline);
+        encoder.push_constants(&StylizeParams {
+            viewport_size: uvec2(width, height),
+            input_image: input_view.device_image_handle(),
+            output_image: output_view.device_image_handle(),
+            depth: depth_copy.device_address(),
+            near_plane: camera.frustum.near_plane,
+            far_plane: camera.frustum.far_plane,
+            outline_color,
+            outline_width: settings.outline_width,
+            depth_threshold: settings.depth_threshold,
+            color_threshold: settings.color_threshold,
+            paper_strength,
+            paper_scale: settings.paper_scale,
+            paper_period: settings.paper_period,
+            paper_offset: vec2(settings.paper_offset[0], settings.paper_offset[1]),
+            seed,
+        });
+        encoder.dispatch(
+            width.div_ceil(STYLIZE_WORKGROUP_SIZE),
+            height.div_ceil(STYLIZE_WORKGROUP_SIZE),
+            1,
+        );
+        encoder.finish();
+        cmd.blit_full_image_top_mip_level(&output, frame);
+        Ok(())
+    }
+
+    /// Stylization settings panel. Returns true if the settings changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = self.settings.clone();
+        let s = &mut self.settings;
+        ui.checkbox(&mut s.enabled, "Enable stylization");
+        ui.add_enabled_ui(s.enabled, |ui| {
+            ui.separator();
+            ui.checkbox(&mut s.outlines, "Outlines");
+            ui.add_enabled_ui(s.outlines, |ui| {
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgba_unmultiplied(&mut s.outline_color);
+                    ui.label("Color");
+                });
+                ui.add(egui::Slider::new(&mut s.outline_width, 1.0..=8.0).text("Width (px)"));
+                ui.add(
+                    egui::Slider::new(&mut s.depth_threshold, 0.001..=1.0)
+                        .logarithmic(true)
+                        .text("Depth threshold"),
+                )
+                .on_hover_text("Relative change in view depth between neighboring pixels");
+                ui.add(egui::Slider::new(&mut s.color_threshold, 0.0..=1.0).text("Color threshold"))
+                    .on_hover_text("Also outline color discontinuities (0 to disable)");
+            });
+
+            ui.separator();
+            ui.checkbox(&mut s.paper, "Paper grain");
+            ui.add_enabled_ui(s.paper, |ui| {
+                ui.add(egui::Slider::new(&mut s.paper_strength, 0.0..=1.0).text("Strength"));
+                ui.add(egui::Slider::new(&mut s.paper_scale, 1.0..=32.0).text("Grain size (px)"));
+                ui.add(egui::Slider::new(&mut s.paper_period, 1..=512).text("Tile period (cells)"));
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut s.paper_offset[0]).speed(1.0));
+                    ui.add(egui::DragValue::new(&mut s.paper_offset[1]).speed(1.0));
+                    ui.label("Offset (px)");
+                });
+            });
+        });
+        self.settings != before
+    }
+}
@@ -0,0 +1,283 @@
+//! SVG export of strokes, projected with the viewport camera.
+//!
+//! Each stroke becomes a path, in screen coordinates (the SVG has the size of the viewport). Strokes are
+//! either stroked paths with the average width of the stroke, or, with `variable_width`, filled outlines
+//! following the width of each vertex. The paths are grouped in layers (by brush or by color); top-level
+//! groups are imported as layers by Illustrator, and the `inkscape:` attributes do the same for Inkscape.
+//!
+//! Parts of strokes behind the camera are cut out. Colors are converted from the linear working space to
+//! sRGB, without the exposure and view transform of the viewport.
+use std::{collections::BTreeSet, fmt::Write, fs, path::Path};
+
+use glam::{DVec2, Vec3};
+
+use crate::{
+    camera_control::Camera,
+    scene::Scene,
+    shaders::shared::{Stroke, StrokeVertex},
+};
+
+/// Vertices closer than this to the previous one (in pixels) are dropped.
+const MIN_VERTEX_DISTANCE: f64 = 0.25;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SvgScope {
+    /// The selected strokes of the current frame.
+    Selection,
+    /// All strokes of the current frame.
+    Frame,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SvgLayers {
+    Single,
+    /// One layer per brush.
+    ByBrush,
+    /// One layer per stroke color.
+    ByColor,
+}
+
+impl SvgLayers {
+    fn label(&self) -> &'static str {
+        match self {
+            SvgLayers::Single => "Single layer",
+            SvgLayers::ByBrush => "By brush",
+            SvgLayers::ByColor => "By color",
+        }
+    }
+}
+
+/// SVG export settings.
+#[derive(Copy, Clone)]
+pub struct SvgExport {
+    pub scope: SvgScope,
+    pub layers: SvgLayers,
+    /// Export strokes as filled outlines following the width of the vertices.
+    pub variable_width: bool,
+    /// Width in pixels of a vertex of full width.
+    pub stroke_width: f32,
+}
+
+impl Default for SvgExport {
+    fn default() -> Self {
+        SvgExport {
+            scope: SvgScope::Frame,
+            layers: SvgLayers::ByBrush,
+            variable_width: false,
+            stroke_width: 4.0,
+        }
+    }
+}
+
+/// A stroke projected on the screen.
+struct ProjectedStroke {
+    /// Runs of vertices in front of the camera, with their width in pixels.
+    runs: Vec<Vec<(DVec2, f64)>>,
+    color: [u8; 3],
+    opacity: f32,
+    layer: String,
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let s = if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (s * 255.0).round() as u8
+}
+
+/// Returns the screen position of a point, or `None` if it's behind the camera.
+fn project(camera: &Camera, pos: Vec3) -> Option<DVec2> {
+    let clip = camera.view_projection() * pos.extend(1.0);
+    if clip.w <= 0.0 {
+        return None;
+    }
+    let ndc = (clip.truncate() / clip.w).as_dvec3();
+    Some(DVec2::new(
+        0.5 * (ndc.x + 1.0) * camera.screen_size.x,
+        0.5 * (1.0 - ndc.y) * camera.screen_size.y,
+    ))
+}
+
+impl SvgExport {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.scope, SvgScope::Frame, "Current frame");
+            ui.radio_value(&mut self.scope, SvgScope::Selection, "Selected strokes");
+        });
+        egui::ComboBox::from_label("Layers")
+            .selected_text(self.layers.label())
+            .show_ui(ui, |ui| {
+                for layers in [SvgLayers::Single, SvgLayers::ByBrush, SvgLayers::ByColor] {
+                    ui.selectable_value(&mut self.layers, layers, layers.label());
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.stroke_width, 0.1..=40.0).text("Stroke width (px)"));
+        ui.checkbox(&mut self.variable_width, "Variable width")
+            .on_hover_text("Export strokes as filled outlines following the pressure, instead of stroked paths");
+    }
+
+    fn project_stroke(
+        &self,
+        camera: &Camera,
+        stroke: &Stroke,
+        vertices: &[StrokeVertex],
+        brush_names: &[String],
+    ) -> ProjectedStroke {
+        let mut runs = vec![];
+        let mut run: Vec<(DVec2, f64)> = vec![];
+        for v in vertices {
+            let Some(p) = project(camera, Vec3::from(v.pos)) else {
+                if run.len() > 1 {
+                    runs.push(run);
+                }
+                run = vec![];
+                continue;
+            };
+            if run.last().is_some_and(|&(last, _)| last.distance(p) < MIN_VERTEX_DISTANCE) {
+                continue;
+            }
+            run.push((p, v.width as f64 / 255.0 * self.stroke_width as f64));
+        }
+        if run.len() > 1 {
+            runs.push(run);
+        }
+
+        let first = vertices.first().map_or([255; 4], |v| v.color);
+        let color = [0, 1, 2].map(|i| linear_to_srgb(first[i] as f32 / 255.0));
+        let opacity = vertices.iter().map(|v| v.opacity as f32).sum::<f32>() / (255.0 * vertices.len().max(1) as f32)
+            * (first[3] as f32 / 255.0);
+        let layer = match self.layers {
+            SvgLayers::Single => "strokes".to_string(),
+            SvgLayers::ByBrush => brush_names
+                .get(stroke.brush as usize)
+                .map_or_else(|| format!("brush {}", stroke.brush), |name| name.clone()),
+            SvgLayers::ByColor => format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2]),
+        };
+        ProjectedStroke {
+            runs,
+            color,
+            opacity,
+            layer,
+        }
+    }
+
+    /// Copies the strokes of a frame to export, with their vertices, so that the export can run in a
+    /// background job.
+    ///
+    /// `selection` holds the indices of the selected strokes in the frame, used with `SvgScope::Selection`.
+    pub fn collect_strokes(
+        &self,
+        scene: &Scene,
+        frame: usize,
+        selection: &BTreeSet<u32>,
+    ) -> Vec<(Stroke, Vec<StrokeVertex>)> {
+        scene
+            .frame_strokes(frame)
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.scope == SvgScope::Frame || selection.contains(&(*i as u32)))
+            .map(|(_, stroke)| (*stroke, scene.stroke_vertices(stroke).to_vec()))
+            .collect()
+    }
+
+    /// Writes the SVG document for strokes returned by `collect_strokes`.
+    ///
+    /// `brush_names` are the names of the brush textures, for layer names.
+    pub fn to_svg(&self, strokes: &[(Stroke, Vec<StrokeVertex>)], camera: &Camera, brush_names: &[String]) -> String {
+        let strokes: Vec<ProjectedStroke> = strokes
+            .iter()
+            .map(|(stroke, vertices)| self.project_stroke(camera, stroke, vertices, brush_names))
+            .filter(|stroke| !stroke.runs.is_empty())
+            .collect();
+
+        // layers in order of first appearance, so that the paint order is kept within layers
+        let mut layers: Vec<&str> = vec![];
+        for stroke in strokes.iter() {
+            if !layers.contains(&stroke.layer.as_str()) {
+                layers.push(&stroke.layer);
+            }
+        }
+
+        let (width, height) = (camera.screen_size.x, camera.screen_size.y);
+        let mut svg = String::new();
+        // writing to a String can't fail
+        let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        );
+        for (i, layer) in layers.iter().enumerate() {
+            let name = escape_xml(layer);
+            let _ = writeln!(svg, r#"  <g id="layer{i}" inkscape:label="{name}" inkscape:groupmode="layer">"#);
+            for stroke in strokes.iter().filter(|s| s.layer == *layer) {
+                for run in stroke.runs.iter() {
+                    self.write_path(&mut svg, stroke, run);
+                }
+            }
+            let _ = writeln!(svg, "  </g>");
+        }
+        let _ = writeln!(svg, "</svg>");
+        svg
+    }
+
+    fn write_path(&self, svg: &mut String, stroke: &ProjectedStroke, run: &[(DVec2, f64)]) {
+        let [r, g, b] = stroke.color;
+        let opacity = stroke.opacity;
+        if self.variable_width {
+            let outline = outline(run);
+            let _ = write!(svg, r#"    <path d=""#);
+            write_points(svg, &outline);
+            let _ = writeln!(svg, r#" Z" fill="rgb({r},{g},{b})" fill-opacity="{opacity:.3}" stroke="none"/>"#);
+        } else {
+            let width = run.iter().map(|(_, w)| w).sum::<f64>() / run.len() as f64;
+            let points: Vec<DVec2> = run.iter().map(|(p, _)| *p).collect();
+            let _ = write!(svg, r#"    <path d=""#);
+            write_points(svg, &points);
+            let _ = writeln!(
+                svg,
+                r#"" fill="none" stroke="rgb({r},{g},{b})" stroke-opacity="{opacity:.3}" stroke-width="{width:.2}" stroke-linecap="round" stroke-linejoin="round"/>"#
+            );
+        }
+    }
+
+    /// Writes the SVG document for strokes returned by `collect_strokes` to a file.
+    pub fn export(
+        &self,
+        path: &Path,
+        strokes: &[(Stroke, Vec<StrokeVertex>)],
+        camera: &Camera,
+        brush_names: &[String],
+    ) -> anyhow::Result<()> {
+        fs::write(path, self.to_svg(strokes, camera, brush_names))?;
+        Ok(())
+    }
+}
+
+fn write_points(svg: &mut String, points: &[DVec2]) {
+    for (i, p) in points.iter().enumerate() {
+        let _ = write!(svg, "{}{:.2},{:.2}", if i == 0 { "M" } else { " L" }, p.x, p.y);
+    }
+}
+
+/// Returns the outline of a polyline with a width at each point: the left side, then the right side
+/// in reverse.
+fn outline(run: &[(DVec2, f64)]) -> Vec<DVec2> {
+    let normal = |i: usize| {
+        let prev = run[i.saturating_sub(1)].0;
+        let next = run[(i + 1).min(run.len() - 1)].0;
+        (next - prev).normalize_or_zero().perp()
+    };
+    let left = (0..run.len()).map(|i| run[i].0 + normal(i) * run[i].1 * 0.5);
+    let right = (0..run.len()).rev().map(|i| run[i].0 - normal(i) * run[i].1 * 0.5);
+    left.chain(right).collect()
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,105 @@
+use egui::{emath::RectTransform, pos2, Color32, Pos2, Rect, Response, Sense, Stroke, Ui, Vec2};
+
+use crate::keyframe::KeyframeCurve;
+
+/// Displays an editor for a keyframe curve.
+///
+/// Keys can be dragged around, as well as their tangent handles. The view is fitted to the
+/// keys and to `frame_range`. `current_frame` is shown as a vertical line.
+pub fn keyframe_curve_editor(ui: &mut Ui, curve: &mut KeyframeCurve, frame_range: (f64, f64), current_frame: f64) -> Response {
+    let size = egui::vec2(ui.available_width().max(200.0), 200.);
+    let handle_radius = 4.;
+
+    let (mut resp, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+    let rect = resp.rect;
+
+    // fit the view to the keys and their handles
+    let (mut min_frame, mut max_frame) = frame_range;
+    let mut min_value = f64::INFINITY;
+    let mut max_value = f64::NEG_INFINITY;
+    for k in curve.keys.iter() {
+        min_frame = min_frame.min(k.frame);
+        max_frame = max_frame.max(k.frame);
+        min_value = min_value.min(k.value).min(k.value + k.in_tangent[1]).min(k.value + k.out_tangent[1]);
+        max_value = max_value.max(k.value).max(k.value + k.in_tangent[1]).max(k.value + k.out_tangent[1]);
+    }
+    if !min_value.is_finite() {
+        min_value = 0.0;
+        max_value = 1.0;
+    }
+    if max_value - min_value < 1e-3 {
+        min_value -= 0.5;
+        max_value += 0.5;
+    }
+    if max_frame - min_frame < 1.0 {
+        max_frame = min_frame + 1.0;
+    }
+    let pad = 0.1 * (max_value - min_value);
+    min_value -= pad;
+    max_value += pad;
+
+    let to_area = RectTransform::from_to(
+        Rect::from_min_max(pos2(min_frame as f32, max_value as f32), pos2(max_frame as f32, min_value as f32)),
+        rect.shrink(handle_radius),
+    );
+    let to_curve = to_area.inverse();
+
+    painter.rect_filled(rect, 0., Color32::from_gray(32));
+
+    // current frame
+    let x = to_area.transform_pos(pos2(current_frame as f32, 0.0)).x;
+    painter.line_segment([Pos2::new(x, rect.min.y), Pos2::new(x, rect.max.y)], Stroke::new(1., Color32::from_rgb(255, 128, 0)));
+
+    // curve
+    let mut prev = None;
+    for i in 0..=rect.width() as u32 {
+        let x = rect.min.x + i as f32;
+        let frame = to_curve.transform_pos(pos2(x, 0.0)).x as f64;
+        let Some(v) = curve.evaluate(frame) else { break };
+        let pos = to_area.transform_pos(pos2(frame as f32, v as f32));
+        if let Some(prev) = prev {
+            painter.line_segment([prev, pos], Stroke::new(1., Color32::GRAY));
+        }
+        prev = Some(pos);
+    }
+
+    // keys & tangent handles
+    let mut moved = false;
+    for (i, key) in curve.keys.iter_mut().enumerate() {
+        let mut center = to_area.transform_pos(pos2(key.frame as f32, key.value as f32));
+        let handle_size = Vec2::splat(2. * handle_radius);
+
+        for (j, tangent) in [&mut key.in_tangent, &mut key.out_tangent].into_iter().enumerate() {
+            let mut handle_pos = to_area.transform_pos(pos2((key.frame + tangent[0]) as f32, (key.value + tangent[1]) as f32));
+            let handle_resp = ui.interact(Rect::from_center_size(handle_pos, handle_size), resp.id.with((i, j)), Sense::drag());
+            if handle_resp.dragged() {
+                resp.mark_changed();
+                handle_pos += handle_resp.drag_delta();
+                let p = to_curve.transform_pos(handle_pos);
+                tangent[0] = p.x as f64 - key.frame;
+                tangent[1] = p.y as f64 - key.value;
+                // in tangents point backwards, out tangents forward
+                tangent[0] = if j == 0 { tangent[0].min(0.0) } else { tangent[0].max(0.0) };
+            }
+            painter.line_segment([center, handle_pos], Stroke::new(1., Color32::from_gray(128)));
+            painter.circle_filled(handle_pos, handle_radius * 0.5, Color32::from_gray(160));
+        }
+
+        let key_resp = ui.interact(Rect::from_center_size(center, handle_size), resp.id.with(i), Sense::drag());
+        if key_resp.dragged() {
+            resp.mark_changed();
+            center += key_resp.drag_delta();
+            let p = to_curve.transform_pos(center);
+            key.frame = (p.x as f64).round();
+            key.value = p.y as f64;
+            moved = true;
+        }
+        painter.circle(center, handle_radius, Color32::TRANSPARENT, Stroke::new(1., Color32::WHITE));
+    }
+
+    if moved {
+        curve.sort();
+    }
+
+    resp
+}
@@ -2,10 +2,14 @@ mod highlight;
 mod curve;
 mod popup_button;
 mod icon_button;
+mod keyframes;
+mod waveform;
 
 pub use curve::*;
 pub use popup_button::*;
 pub use icon_button::*;
+pub use keyframes::*;
+pub use waveform::*;
 
 use egui::{Align, Align2, Area, Color32, Direction, FontId, Frame, InnerResponse, Key, Layout, Order, Pos2, Rect, Response, RichText, Sense, Stroke, TextEdit, TextFormat, TextStyle, Ui, Vec2, WidgetText};
 use std::{fmt::Debug, hash::Hash};
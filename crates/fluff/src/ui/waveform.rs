@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use egui::{Color32, Pos2, Sense, Stroke, Ui};
+
+use crate::audio::AudioTrack;
+
+/// Draws the waveform of an audio track under the timeline.
+///
+/// `duration` is the length of the timeline. Returns the timeline position under the pointer
+/// if the user clicked or dragged on the waveform.
+pub fn timeline_waveform(ui: &mut Ui, track: &AudioTrack, duration: Duration, current: Duration) -> Option<Duration> {
+    let size = egui::vec2(ui.available_width().max(200.0), 40.0);
+    let (resp, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+    let rect = resp.rect;
+    let duration = duration.max(Duration::from_millis(1));
+
+    painter.rect_filled(rect, 0., Color32::from_gray(32));
+
+    let width = rect.width().max(1.0) as u32;
+    let seconds_per_px = duration.as_secs_f64() / width as f64;
+    let stroke = Stroke::new(1., Color32::from_rgb(110, 160, 210));
+    for x in 0..width {
+        let from = Duration::from_secs_f64(x as f64 * seconds_per_px);
+        let to = Duration::from_secs_f64((x + 1) as f64 * seconds_per_px);
+        let h = 0.5 * rect.height() * track.peak(from, to);
+        let px = rect.min.x + x as f32 + 0.5;
+        painter.line_segment([Pos2::new(px, rect.center().y - h), Pos2::new(px, rect.center().y + h)], stroke);
+    }
+
+    // playhead
+    let x = rect.min.x + (current.as_secs_f64() / duration.as_secs_f64()) as f32 * rect.width();
+    painter.line_segment([Pos2::new(x, rect.min.y), Pos2::new(x, rect.max.y)], Stroke::new(1., Color32::from_rgb(255, 128, 0)));
+
+    if resp.clicked() || resp.dragged() {
+        let pos = resp.interact_pointer_pos()?;
+        let t = ((pos.x - rect.min.x) / rect.width()).clamp(0.0, 1.0) as f64;
+        return Some(Duration::from_secs_f64(t * duration.as_secs_f64()));
+    }
+    None
+}
@@ -0,0 +1,1118 @@
+//! Minimal USD reader: imports `BasisCurves` and `Mesh` prims from text layers (`.usda`).
+//!
+//! Only the subset needed to bring curves and meshes into fluff is supported: the prim hierarchy,
+//! transform ops, and default values and time samples of attributes. Composition arcs (references,
+//! payloads, sublayers, variants) are ignored, with a warning. Binary crate files (`.usdc`, and `.usd`
+//! files, which are usually in the crate format) are not supported; they can be converted to text with
+//! `usdcat -o file.usda file.usdc`.
+//!
+//! Curves are converted to cubic bezier runs and meshes to polygon runs, so that the result goes
+//! through the same path as Houdini geometry. Animated layers are sampled at each integer time code
+//! of their time range, producing one `Geo` per frame.
+use std::{borrow::Cow, collections::BTreeSet, fs, path::Path};
+
+use anyhow::{anyhow, bail};
+use glam::{dvec3, DMat4, DQuat, DVec3};
+use houdinio::{Attribute, AttributeStorage, BezierBasis, BezierRun, Geo, PolygonRun, PrimVar, Primitive, TypeInfo};
+
+/// File extensions handled by this module.
+pub const USD_EXTENSIONS: &[&str] = &["usda"];
+
+/// Color of points when the prim has no `displayColor` (same as the default color of the scene).
+const DEFAULT_COLOR: DVec3 = dvec3(0.1, 0.8, 0.1);
+
+/// Upper bound on the number of frames sampled from a layer, in case of a bogus time range.
+const MAX_FRAMES: usize = 100_000;
+
+/// Returns whether the file has a USD extension.
+pub fn is_usd_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| USD_EXTENSIONS.contains(&ext))
+}
+
+/// Loads a USD file. Returns one `Geo` per frame, and warnings about unsupported features.
+pub fn load_usd(path: &Path) -> anyhow::Result<(Vec<Geo>, Vec<String>)> {
+    let data = fs::read(path)?;
+    if data.starts_with(b"PXR-USDC") {
+        bail!("binary USD files are not supported, convert to text first with `usdcat -o <file>.usda <file>`");
+    }
+    if !data.starts_with(b"#usda") {
+        bail!("not a USD text file (missing `#usda` header)");
+    }
+    let layer = Parser::new(&data).parse_layer()?;
+    Ok(layer.to_geo())
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Lexer
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// Keywords, type names and property names (which may contain `:` and `.`).
+    Ident(String),
+    Number(f64),
+    String(String),
+    /// `<...>`
+    Path(String),
+    /// `@...@`
+    Asset(String),
+    Punct(u8),
+    Eof,
+}
+
+struct Lexer<'a> {
+    src: &'a [u8],
+    pos: usize,
+    line: usize,
+    peeked: Option<Token>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(src: &'a [u8]) -> Lexer<'a> {
+        Lexer {
+            src,
+            pos: 0,
+            line: 1,
+            peeked: None,
+        }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> anyhow::Error {
+        anyhow!("line {}: {}", self.line, message)
+    }
+
+    fn byte(&self, offset: usize) -> u8 {
+        self.src.get(self.pos + offset).copied().unwrap_or(0)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.src.len() {
+            match self.src[self.pos] {
+                b'\n' => {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                b' ' | b'\t' | b'\r' => self.pos += 1,
+                // comments (and the `#usda` header)
+                b'#' => {
+                    while self.pos < self.src.len() && self.src[self.pos] != b'\n' {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Consumes bytes up to (and including) the delimiter, and returns the bytes before it.
+    fn take_until(&mut self, delimiter: &[u8]) -> anyhow::Result<String> {
+        let start = self.pos;
+        while !self.src[self.pos..].starts_with(delimiter) {
+            if self.pos >= self.src.len() {
+                return Err(self.error("unterminated literal"));
+            }
+            if self.src[self.pos] == b'\n' {
+                self.line += 1;
+            }
+            self.pos += 1;
+        }
+        let text = String::from_utf8_lossy(&self.src[start..self.pos]).into_owned();
+        self.pos += delimiter.len();
+        Ok(text)
+    }
+
+    fn lex_string(&mut self) -> anyhow::Result<String> {
+        let quote = self.byte(0);
+        if self.byte(1) == quote && self.byte(2) == quote {
+            self.pos += 3;
+            return self.take_until(&[quote; 3]);
+        }
+        self.pos += 1;
+        let mut bytes = vec![];
+        loop {
+            let b = self.byte(0);
+            self.pos += 1;
+            match b {
+                0 | b'\n' => return Err(self.error("unterminated string")),
+                b'\\' => {
+                    let escaped = self.byte(0);
+                    self.pos += 1;
+                    bytes.push(match escaped {
+                        b'n' => b'\n',
+                        b't' => b'\t',
+                        other => other,
+                    });
+                }
+                b if b == quote => break,
+                b => bytes.push(b),
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn lex_number(&mut self) -> anyhow::Result<f64> {
+        let start = self.pos;
+        self.pos += 1;
+        while matches!(self.byte(0), b'0'..=b'9' | b'.' | b'e' | b'E')
+            || (matches!(self.byte(0), b'-' | b'+') && matches!(self.src[self.pos - 1], b'e' | b'E'))
+        {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+        text.parse().map_err(|_| self.error(format!("invalid number `{text}`")))
+    }
+
+    fn lex_ident(&mut self) -> String {
+        let start = self.pos;
+        while matches!(self.byte(0), b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b':' | b'.') {
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.src[start..self.pos]).into_owned()
+    }
+
+    fn lex(&mut self) -> anyhow::Result<Token> {
+        self.skip_whitespace();
+        let token = match self.byte(0) {
+            0 if self.pos >= self.src.len() => Token::Eof,
+            b'"' | b'\'' => Token::String(self.lex_string()?),
+            b'<' => {
+                self.pos += 1;
+                Token::Path(self.take_until(b">")?)
+            }
+            b'@' => {
+                self.pos += 1;
+                Token::Asset(self.take_until(b"@")?)
+            }
+            b'0'..=b'9' => Token::Number(self.lex_number()?),
+            b'-' | b'+' | b'.' if matches!(self.byte(1), b'0'..=b'9' | b'.') => Token::Number(self.lex_number()?),
+            b'-' if self.src[self.pos..].starts_with(b"-inf") => {
+                self.pos += 4;
+                Token::Number(f64::NEG_INFINITY)
+            }
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => Token::Ident(self.lex_ident()),
+            b @ (b'(' | b')' | b'[' | b']' | b'{' | b'}' | b'=' | b',' | b';' | b':') => {
+                self.pos += 1;
+                Token::Punct(b)
+            }
+            b => return Err(self.error(format!("unexpected character `{}`", b as char))),
+        };
+        Ok(token)
+    }
+
+    fn peek(&mut self) -> anyhow::Result<&Token> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lex()?);
+        }
+        Ok(self.peeked.as_ref().unwrap())
+    }
+
+    fn next(&mut self) -> anyhow::Result<Token> {
+        match self.peeked.take() {
+            Some(token) => Ok(token),
+            None => self.lex(),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Layer contents
+
+/// Attribute or metadata value.
+///
+/// Only the shapes of values that the importer needs are distinguished: numeric values (scalars,
+/// tuples, matrices and arrays of those) are flattened into a list of numbers.
+#[derive(Clone, Debug)]
+enum Value {
+    /// `None` (blocked value).
+    None,
+    Numbers(Vec<f64>),
+    /// Strings and tokens.
+    String(String),
+    Strings(Vec<String>),
+    /// Paths, assets, dictionaries and arrays of mixed values.
+    Other,
+}
+
+impl Value {
+    fn as_numbers(&self) -> Option<&[f64]> {
+        match self {
+            Value::Numbers(numbers) => Some(numbers),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_strings(&self) -> Option<&[String]> {
+        match self {
+            Value::Strings(strings) => Some(strings),
+            // empty arrays are parsed as numbers
+            Value::Numbers(numbers) if numbers.is_empty() => Some(&[]),
+            _ => None,
+        }
+    }
+}
+
+struct UsdAttribute {
+    name: String,
+    default: Option<Value>,
+    /// Time samples, sorted by time.
+    time_samples: Vec<(f64, Value)>,
+    /// Value of the `interpolation` metadata, for primvars.
+    interpolation: Option<String>,
+}
+
+impl UsdAttribute {
+    /// Evaluates the attribute at the specified time code, or at the default time if `None`.
+    ///
+    /// Numeric values are interpolated linearly between samples, if they have the same number of elements.
+    /// Other values are held.
+    fn eval(&self, time: Option<f64>) -> Option<Cow<'_, Value>> {
+        let value = match (time, self.time_samples.as_slice()) {
+            (_, []) => Cow::Borrowed(self.default.as_ref()?),
+            (None, [(_, first), ..]) => Cow::Borrowed(self.default.as_ref().unwrap_or(first)),
+            (Some(time), samples) => {
+                let i = samples.partition_point(|(t, _)| *t <= time);
+                if i == 0 {
+                    Cow::Borrowed(&samples[0].1)
+                } else if i == samples.len() || samples[i - 1].0 == time {
+                    Cow::Borrowed(&samples[i - 1].1)
+                } else {
+                    let (t0, v0) = &samples[i - 1];
+                    let (t1, v1) = &samples[i];
+                    match (v0, v1) {
+                        (Value::Numbers(a), Value::Numbers(b)) if a.len() == b.len() => {
+                            let s = (time - t0) / (t1 - t0);
+                            Cow::Owned(Value::Numbers(a.iter().zip(b).map(|(a, b)| a + (b - a) * s).collect()))
+                        }
+                        _ => Cow::Borrowed(v0),
+                    }
+                }
+            }
+        };
+        match *value {
+            Value::None => None,
+            _ => Some(value),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Specifier {
+    Def,
+    Over,
+    Class,
+}
+
+struct Prim {
+    specifier: Specifier,
+    type_name: String,
+    name: String,
+    attributes: Vec<UsdAttribute>,
+    children: Vec<Prim>,
+}
+
+impl Prim {
+    fn attribute(&self, name: &str) -> Option<&UsdAttribute> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    fn numbers(&self, name: &str, time: Option<f64>) -> Option<Cow<'_, [f64]>> {
+        match self.attribute(name)?.eval(time)? {
+            Cow::Borrowed(value) => value.as_numbers().map(Cow::Borrowed),
+            Cow::Owned(Value::Numbers(numbers)) => Some(Cow::Owned(numbers)),
+            Cow::Owned(_) => None,
+        }
+    }
+
+    fn vectors(&self, name: &str, time: Option<f64>) -> Option<Vec<DVec3>> {
+        let numbers = self.numbers(name, time)?;
+        Some(numbers.chunks_exact(3).map(|v| dvec3(v[0], v[1], v[2])).collect())
+    }
+
+    fn counts(&self, name: &str, time: Option<f64>) -> Option<Vec<usize>> {
+        let numbers = self.numbers(name, time)?;
+        Some(numbers.iter().map(|&n| n.max(0.0) as usize).collect())
+    }
+
+    fn token(&self, name: &str, time: Option<f64>) -> Option<String> {
+        self.attribute(name)?.eval(time)?.as_str().map(str::to_string)
+    }
+
+    fn tokens(&self, name: &str, time: Option<f64>) -> Option<Vec<String>> {
+        self.attribute(name)?.eval(time)?.as_strings().map(<[String]>::to_vec)
+    }
+}
+
+struct Layer {
+    prims: Vec<Prim>,
+    start_time_code: Option<f64>,
+    end_time_code: Option<f64>,
+    /// Warnings emitted while parsing (e.g. unsupported composition arcs).
+    warnings: BTreeSet<String>,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Parser
+
+const COMPOSITION_ARCS: &[&str] = &["references", "payload", "inherits", "specializes", "subLayers", "variantSets"];
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    warnings: BTreeSet<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a [u8]) -> Parser<'a> {
+        Parser {
+            lexer: Lexer::new(src),
+            warnings: BTreeSet::new(),
+        }
+    }
+
+    fn eat(&mut self, punct: u8) -> anyhow::Result<bool> {
+        if *self.lexer.peek()? == Token::Punct(punct) {
+            self.lexer.next()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn expect(&mut self, punct: u8) -> anyhow::Result<()> {
+        let token = self.lexer.next()?;
+        if token != Token::Punct(punct) {
+            return Err(self.lexer.error(format!("expected `{}`, found {:?}", punct as char, token)));
+        }
+        Ok(())
+    }
+
+    fn expect_ident(&mut self) -> anyhow::Result<String> {
+        match self.lexer.next()? {
+            Token::Ident(ident) => Ok(ident),
+            token => Err(self.lexer.error(format!("expected identifier, found {:?}", token))),
+        }
+    }
+
+    fn peek_ident(&mut self) -> anyhow::Result<Option<&str>> {
+        match self.lexer.peek()? {
+            Token::Ident(ident) => Ok(Some(ident)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Skips tokens up to the bracket matching an already consumed opening bracket.
+    fn skip_group(&mut self) -> anyhow::Result<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.lexer.next()? {
+                Token::Punct(b'(' | b'[' | b'{') => depth += 1,
+                Token::Punct(b')' | b']' | b'}') => depth -= 1,
+                Token::Eof => return Err(self.lexer.error("unexpected end of file")),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_layer(mut self) -> anyhow::Result<Layer> {
+        let mut layer = Layer {
+            prims: vec![],
+            start_time_code: None,
+            end_time_code: None,
+            warnings: BTreeSet::new(),
+        };
+        if *self.lexer.peek()? == Token::Punct(b'(') {
+            for (key, value) in self.parse_metadata()? {
+                let number = value.as_numbers().and_then(|n| n.first().copied());
+                match key.as_str() {
+                    "startTimeCode" => layer.start_time_code = number,
+                    "endTimeCode" => layer.end_time_code = number,
+                    _ => {}
+                }
+            }
+        }
+        while *self.lexer.peek()? != Token::Eof {
+            layer.prims.push(self.parse_prim()?);
+        }
+        layer.warnings = self.warnings;
+        Ok(layer)
+    }
+
+    /// Parses a metadata block: `( key = value ... )`.
+    fn parse_metadata(&mut self) -> anyhow::Result<Vec<(String, Value)>> {
+        self.expect(b'(')?;
+        let mut entries = vec![];
+        loop {
+            match self.lexer.next()? {
+                Token::Punct(b')') => break,
+                Token::Punct(b';') => {}
+                // a string alone is the `doc` metadata
+                Token::String(_) => {}
+                Token::Ident(mut key) => {
+                    // list ops (`prepend references = ...`) and typed dictionary entries (`string foo = ...`)
+                    if let Some(ident) = self.peek_ident()? {
+                        key = ident.to_string();
+                        self.lexer.next()?;
+                    }
+                    if COMPOSITION_ARCS.contains(&key.as_str()) {
+                        self.warnings
+                            .insert(format!("composition arcs (`{key}`) are not supported and were ignored"));
+                    }
+                    let value = if self.eat(b'=')? { self.parse_value()? } else { Value::None };
+                    entries.push((key, value));
+                }
+                token => return Err(self.lexer.error(format!("unexpected {:?} in metadata", token))),
+            }
+        }
+        Ok(entries)
+    }
+
+    fn parse_value(&mut self) -> anyhow::Result<Value> {
+        let value = match self.lexer.next()? {
+            Token::Number(x) => Value::Numbers(vec![x]),
+            Token::String(s) => Value::String(s),
+            Token::Ident(ident) => match ident.as_str() {
+                "None" => Value::None,
+                "inf" => Value::Numbers(vec![f64::INFINITY]),
+                "nan" => Value::Numbers(vec![f64::NAN]),
+                _ => Value::String(ident),
+            },
+            Token::Path(_) => Value::Other,
+            Token::Asset(_) => {
+                // references: `@asset@</prim> (offset = ...)`
+                if matches!(self.lexer.peek()?, Token::Path(_)) {
+                    self.lexer.next()?;
+                }
+                if self.eat(b'(')? {
+                    self.skip_group()?;
+                }
+                Value::Other
+            }
+            Token::Punct(b'(') => self.parse_sequence(b')')?,
+            Token::Punct(b'[') => self.parse_sequence(b']')?,
+            Token::Punct(b'{') => {
+                self.skip_group()?;
+                Value::Other
+            }
+            token => return Err(self.lexer.error(format!("expected value, found {:?}", token))),
+        };
+        Ok(value)
+    }
+
+    /// Parses the elements of a tuple or array, after the opening bracket.
+    fn parse_sequence(&mut self, close: u8) -> anyhow::Result<Value> {
+        // numbers are accumulated directly, since point arrays can be large
+        let mut numbers = vec![];
+        let mut strings = vec![];
+        let mut other = false;
+        loop {
+            if self.eat(close)? {
+                break;
+            }
+            if let Token::Number(x) = *self.lexer.peek()? {
+                self.lexer.next()?;
+                numbers.push(x);
+            } else {
+                match self.parse_value()? {
+                    Value::Numbers(n) => numbers.extend(n),
+                    Value::String(s) => strings.push(s),
+                    _ => other = true,
+                }
+            }
+            if !self.eat(b',')? {
+                self.expect(close)?;
+                break;
+            }
+        }
+        if other || (!numbers.is_empty() && !strings.is_empty()) {
+            Ok(Value::Other)
+        } else if !strings.is_empty() {
+            Ok(Value::Strings(strings))
+        } else {
+            Ok(Value::Numbers(numbers))
+        }
+    }
+
+    /// Parses `{ time: value, ... }`.
+    fn parse_time_samples(&mut self) -> anyhow::Result<Vec<(f64, Value)>> {
+        self.expect(b'{')?;
+        let mut samples = vec![];
+        loop {
+            if self.eat(b'}')? {
+                break;
+            }
+            let time = match self.lexer.next()? {
+                Token::Number(time) => time,
+                token => return Err(self.lexer.error(format!("expected time code, found {:?}", token))),
+            };
+            self.expect(b':')?;
+            samples.push((time, self.parse_value()?));
+            if !self.eat(b',')? {
+                self.expect(b'}')?;
+                break;
+            }
+        }
+        samples.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Ok(samples)
+    }
+
+    /// Parses `def|over|class [Type] "name" [( metadata )] { ... }`.
+    fn parse_prim(&mut self) -> anyhow::Result<Prim> {
+        let specifier = match self.expect_ident()?.as_str() {
+            "def" => Specifier::Def,
+            "over" => Specifier::Over,
+            "class" => Specifier::Class,
+            other => return Err(self.lexer.error(format!("expected prim, found `{other}`"))),
+        };
+        let type_name = match self.peek_ident()? {
+            Some(_) => self.expect_ident()?,
+            None => String::new(),
+        };
+        let name = match self.lexer.next()? {
+            Token::String(name) => name,
+            token => return Err(self.lexer.error(format!("expected prim name, found {:?}", token))),
+        };
+        if *self.lexer.peek()? == Token::Punct(b'(') {
+            self.parse_metadata()?;
+        }
+        self.expect(b'{')?;
+
+        let mut prim = Prim {
+            specifier,
+            type_name,
+            name,
+            attributes: vec![],
+            children: vec![],
+        };
+        loop {
+            if self.eat(b'}')? {
+                break;
+            }
+            match self.peek_ident()? {
+                Some("def" | "over" | "class") => {
+                    let child = self.parse_prim()?;
+                    prim.children.push(child);
+                }
+                Some("variantSet") => {
+                    self.lexer.next()?;
+                    self.lexer.next()?; // name
+                    self.expect(b'=')?;
+                    self.expect(b'{')?;
+                    self.skip_group()?;
+                    self.warnings.insert("variant sets are not supported and were ignored".to_string());
+                }
+                Some(_) => self.parse_property(&mut prim)?,
+                None => {
+                    let token = self.lexer.next()?;
+                    return Err(self.lexer.error(format!("unexpected {:?} in prim", token)));
+                }
+            }
+        }
+        Ok(prim)
+    }
+
+    /// Parses an attribute or relationship declaration:
+    /// `[custom] [uniform] type[] name[.timeSamples] [= value] [( metadata )]`
+    fn parse_property(&mut self, prim: &mut Prim) -> anyhow::Result<()> {
+        let mut ident = self.expect_ident()?;
+        // list ops, only used on relationships and metadata-like properties here
+        if matches!(ident.as_str(), "prepend" | "append" | "add" | "delete" | "reorder") {
+            ident = self.expect_ident()?;
+        }
+        if ident == "custom" {
+            ident = self.expect_ident()?;
+        }
+        if matches!(ident.as_str(), "uniform" | "varying" | "config") {
+            ident = self.expect_ident()?;
+        }
+
+        let is_relationship = ident == "rel";
+        let name = if is_relationship {
+            self.expect_ident()?
+        } else if matches!(ident.as_str(), "nameChildren" | "propertyOrder") {
+            ident
+        } else {
+            // `ident` is the type name
+            if self.eat(b'[')? {
+                self.expect(b']')?;
+            }
+            self.expect_ident()?
+        };
+
+        let (name, field) = match name.rsplit_once('.') {
+            Some((name, field @ ("timeSamples" | "connect" | "spline"))) => (name.to_string(), Some(field)),
+            _ => (name, None),
+        };
+
+        let mut default = None;
+        let mut time_samples = vec![];
+        if self.eat(b'=')? {
+            match field {
+                Some("timeSamples") => time_samples = self.parse_time_samples()?,
+                Some(_) => {
+                    self.parse_value()?;
+                }
+                None => default = Some(self.parse_value()?),
+            }
+        }
+        let mut interpolation = None;
+        if *self.lexer.peek()? == Token::Punct(b'(') {
+            for (key, value) in self.parse_metadata()? {
+                if key == "interpolation" {
+                    interpolation = value.as_str().map(str::to_string);
+                }
+            }
+        }
+        if is_relationship {
+            return Ok(());
+        }
+
+        // the default value and the time samples can be declared separately
+        match prim.attributes.iter_mut().find(|a| a.name == name) {
+            Some(attribute) => {
+                attribute.default = default.or(attribute.default.take());
+                if !time_samples.is_empty() {
+                    attribute.time_samples = time_samples;
+                }
+                attribute.interpolation = interpolation.or(attribute.interpolation.take());
+            }
+            None => prim.attributes.push(UsdAttribute {
+                name,
+                default,
+                time_samples,
+                interpolation,
+            }),
+        }
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Conversion
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum CurveBasis {
+    Linear,
+    Bezier,
+    BSpline,
+    CatmullRom,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum CurveWrap {
+    NonPeriodic,
+    Periodic,
+    Pinned,
+}
+
+/// Converts the control points of a curve to the control points of a piecewise cubic bezier curve
+/// (3n+1 points).
+///
+/// Since the conversion only uses affine combinations of the control points, it's also used on colors.
+fn to_cubic_bezier(points: &[DVec3], basis: CurveBasis, wrap: CurveWrap) -> Vec<DVec3> {
+    let n = points.len();
+    let mut out = vec![];
+    if n < 2 {
+        return out;
+    }
+    let periodic = wrap == CurveWrap::Periodic;
+    match basis {
+        CurveBasis::Linear => {
+            let mut points = points.to_vec();
+            if periodic {
+                points.push(points[0]);
+            }
+            out.push(points[0]);
+            for s in points.windows(2) {
+                out.push(s[0].lerp(s[1], 1.0 / 3.0));
+                out.push(s[0].lerp(s[1], 2.0 / 3.0));
+                out.push(s[1]);
+            }
+        }
+        CurveBasis::Bezier => {
+            out = points.to_vec();
+            if periodic {
+                out.push(points[0]);
+            }
+            out.truncate((out.len() - 1) / 3 * 3 + 1);
+            if out.len() < 4 {
+                out.clear();
+            }
+        }
+        CurveBasis::BSpline | CurveBasis::CatmullRom => {
+            let mut points = points.to_vec();
+            match wrap {
+                CurveWrap::NonPeriodic => {}
+                CurveWrap::Periodic => points.extend_from_within(..3.min(n)),
+                CurveWrap::Pinned => {
+                    // phantom points so that the curve goes through the end points
+                    points.insert(0, 2.0 * points[0] - points[1]);
+                    points.push(2.0 * points[n] - points[n - 1]);
+                }
+            }
+            for s in points.windows(4) {
+                let [b0, b1, b2, b3] = match basis {
+                    CurveBasis::BSpline => [
+                        (s[0] + 4.0 * s[1] + s[2]) / 6.0,
+                        (2.0 * s[1] + s[2]) / 3.0,
+                        (s[1] + 2.0 * s[2]) / 3.0,
+                        (s[1] + 4.0 * s[2] + s[3]) / 6.0,
+                    ],
+                    _ => [s[1], s[1] + (s[2] - s[0]) / 6.0, s[2] - (s[3] - s[1]) / 6.0, s[2]],
+                };
+                if out.is_empty() {
+                    out.push(b0);
+                }
+                out.extend([b1, b2, b3]);
+            }
+        }
+    }
+    out
+}
+
+/// Builds a `Geo` from the prims of a frame.
+#[derive(Default)]
+struct GeoBuilder {
+    positions: Vec<f32>,
+    colors: Vec<f32>,
+    has_colors: bool,
+    topology: Vec<u32>,
+    primitive_count: usize,
+    primitives: Vec<Primitive>,
+}
+
+impl GeoBuilder {
+    fn add_point(&mut self, position: DVec3, color: Option<DVec3>) -> u32 {
+        let index = (self.positions.len() / 3) as u32;
+        self.positions.extend(position.as_vec3().to_array());
+        self.colors.extend(color.unwrap_or(DEFAULT_COLOR).as_vec3().to_array());
+        self.has_colors |= color.is_some();
+        index
+    }
+
+    fn add_vertex(&mut self, point: u32) -> i32 {
+        self.topology.push(point);
+        (self.topology.len() - 1) as i32
+    }
+
+    fn finish(self) -> Geo {
+        fn float3_attribute(name: &str, data: Vec<f32>, type_info: TypeInfo) -> Attribute {
+            Attribute {
+                name: name.into(),
+                size: 3,
+                storage: AttributeStorage::FpReal32(data),
+                scope: "public".into(),
+                type_info,
+                options: vec![],
+                defaults: vec![0.0],
+            }
+        }
+
+        let point_count = self.positions.len() / 3;
+        let mut point_attributes = vec![float3_attribute("P", self.positions, TypeInfo::Point)];
+        if self.has_colors {
+            point_attributes.push(float3_attribute("Cd", self.colors, TypeInfo::Color));
+        }
+        Geo {
+            point_count,
+            vertex_count: self.topology.len(),
+            primitive_count: self.primitive_count,
+            topology: self.topology,
+            point_attributes,
+            primitive_attributes: vec![],
+            primitives: self.primitives,
+            ..Default::default()
+        }
+    }
+}
+
+/// Prim types that describe geometry that can't be imported.
+const UNSUPPORTED_GPRIMS: &[&str] = &[
+    "Points",
+    "NurbsCurves",
+    "HermiteCurves",
+    "NurbsPatch",
+    "PointInstancer",
+    "Cube",
+    "Sphere",
+    "Cylinder",
+    "Cone",
+    "Capsule",
+    "Plane",
+];
+
+fn rotation(axis: char, degrees: f64) -> DMat4 {
+    let angle = degrees.to_radians();
+    match axis {
+        'X' => DMat4::from_rotation_x(angle),
+        'Y' => DMat4::from_rotation_y(angle),
+        _ => DMat4::from_rotation_z(angle),
+    }
+}
+
+/// Evaluates the local transform of a prim from its `xformOpOrder`.
+///
+/// Returns the transform and whether it resets the parent transform (`!resetXformStack!`).
+fn local_transform(prim: &Prim, time: Option<f64>, warnings: &mut BTreeSet<String>) -> (DMat4, bool) {
+    let mut transform = DMat4::IDENTITY;
+    let mut reset = false;
+    for op in prim.tokens("xformOpOrder", time).unwrap_or_default() {
+        if op == "!resetXformStack!" {
+            transform = DMat4::IDENTITY;
+            reset = true;
+            continue;
+        }
+        let (name, invert) = match op.strip_prefix("!invert!") {
+            Some(name) => (name, true),
+            None => (op.as_str(), false),
+        };
+        let Some(v) = prim.numbers(name, time) else {
+            warnings.insert(format!("missing value for transform op `{name}`"));
+            continue;
+        };
+        let kind = name.strip_prefix("xformOp:").unwrap_or(name).split(':').next().unwrap();
+        let m = match (kind, v.len()) {
+            ("translate", 3) => DMat4::from_translation(dvec3(v[0], v[1], v[2])),
+            ("scale", 3) => DMat4::from_scale(dvec3(v[0], v[1], v[2])),
+            ("rotateX" | "rotateY" | "rotateZ", 1) => rotation(kind.chars().last().unwrap(), v[0]),
+            ("rotateXYZ" | "rotateXZY" | "rotateYXZ" | "rotateYZX" | "rotateZXY" | "rotateZYX", 3) => {
+                // the first axis in the name is applied first
+                kind[6..].chars().fold(DMat4::IDENTITY, |m, axis| {
+                    let angle = v[(axis as u8 - b'X') as usize];
+                    rotation(axis, angle) * m
+                })
+            }
+            // quaternions are stored as (real, i, j, k)
+            ("orient", 4) => DMat4::from_quat(DQuat::from_xyzw(v[1], v[2], v[3], v[0]).normalize()),
+            // USD matrices are row-major and transform row vectors, so the rows are glam's columns
+            ("transform", 16) => DMat4::from_cols_slice(&v[..]),
+            _ => {
+                warnings.insert(format!("unsupported transform op `{name}`"));
+                continue;
+            }
+        };
+        transform *= if invert { m.inverse() } else { m };
+    }
+    (transform, reset)
+}
+
+/// Returns the color of each point of a prim, from its `displayColor` primvar.
+///
+/// `uniform_counts` is the number of points of each element (curve), for colors with `uniform` interpolation.
+fn point_colors(
+    prim: &Prim,
+    time: Option<f64>,
+    point_count: usize,
+    uniform_counts: Option<&[usize]>,
+    warnings: &mut BTreeSet<String>,
+) -> Option<Vec<DVec3>> {
+    let attribute = prim.attribute("primvars:displayColor")?;
+    let colors = prim.vectors("primvars:displayColor", time)?;
+    let first = *colors.first()?;
+    let interpolation = attribute.interpolation.as_deref().unwrap_or("constant");
+    match (interpolation, uniform_counts) {
+        ("vertex" | "varying", _) if colors.len() == point_count => Some(colors),
+        ("uniform", Some(counts)) if colors.len() == counts.len() => {
+            let mut point_colors = Vec::with_capacity(point_count);
+            for (&count, color) in counts.iter().zip(colors) {
+                point_colors.resize(point_colors.len() + count, color);
+            }
+            Some(point_colors)
+        }
+        ("constant", _) => Some(vec![first; point_count]),
+        _ => {
+            warnings.insert(format!(
+                "`{interpolation}` interpolation of `displayColor` is not supported on `{}` prims, using a constant color",
+                prim.type_name
+            ));
+            Some(vec![first; point_count])
+        }
+    }
+}
+
+fn add_basis_curves(
+    geo: &mut GeoBuilder,
+    prim: &Prim,
+    path: &str,
+    transform: &DMat4,
+    time: Option<f64>,
+    warnings: &mut BTreeSet<String>,
+) {
+    let Some(points) = prim.vectors("points", time) else { return };
+    let counts = prim.counts("curveVertexCounts", time).unwrap_or_default();
+    let colors = point_colors(prim, time, points.len(), Some(&counts), warnings);
+
+    let curve_type = prim.token("type", time).unwrap_or_else(|| "cubic".to_string());
+    let basis = match (curve_type.as_str(), prim.token("basis", time).as_deref()) {
+        ("linear", _) => CurveBasis::Linear,
+        (_, Some("bspline")) => CurveBasis::BSpline,
+        (_, Some("catmullRom")) => CurveBasis::CatmullRom,
+        (_, None | Some("bezier")) => CurveBasis::Bezier,
+        (_, Some(other)) => {
+            warnings.insert(format!("unsupported curve basis `{other}`, using bezier"));
+            CurveBasis::Bezier
+        }
+    };
+    let wrap = match prim.token("wrap", time).as_deref() {
+        Some("periodic") => CurveWrap::Periodic,
+        Some("pinned") => CurveWrap::Pinned,
+        _ => CurveWrap::NonPeriodic,
+    };
+
+    let mut curves = vec![];
+    let mut start = 0;
+    for count in counts {
+        if start + count > points.len() {
+            warnings.insert(format!("{path}: `curveVertexCounts` doesn't match the number of points"));
+            break;
+        }
+        let range = start..start + count;
+        start += count;
+        let bezier_points = to_cubic_bezier(&points[range.clone()], basis, wrap);
+        if bezier_points.is_empty() {
+            continue;
+        }
+        let bezier_colors = colors.as_ref().map(|colors| to_cubic_bezier(&colors[range], basis, wrap));
+        let vertices = bezier_points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let color = bezier_colors.as_ref().map(|colors| colors[i]);
+                let point = geo.add_point(transform.transform_point3(*p), color);
+                geo.add_vertex(point)
+            })
+            .collect();
+        curves.push(vertices);
+    }
+    if !curves.is_empty() {
+        geo.primitive_count += curves.len();
+        geo.primitives.push(Primitive::BezierRun(BezierRun {
+            count: curves.len(),
+            vertices: PrimVar::Varying(curves),
+            closed: PrimVar::Uniform(false),
+            basis: PrimVar::Uniform(BezierBasis {
+                order: 4,
+                knots: vec![],
+            }),
+        }));
+    }
+}
+
+fn add_mesh(
+    geo: &mut GeoBuilder,
+    prim: &Prim,
+    path: &str,
+    transform: &DMat4,
+    time: Option<f64>,
+    warnings: &mut BTreeSet<String>,
+) {
+    let Some(points) = prim.vectors("points", time) else { return };
+    let counts = prim.counts("faceVertexCounts", time).unwrap_or_default();
+    let indices = prim.counts("faceVertexIndices", time).unwrap_or_default();
+    let colors = point_colors(prim, time, points.len(), None, warnings);
+
+    let base = (geo.positions.len() / 3) as u32;
+    for (i, p) in points.iter().enumerate() {
+        geo.add_point(transform.transform_point3(*p), colors.as_ref().map(|colors| colors[i]));
+    }
+    let mut faces = vec![];
+    let mut start = 0;
+    for count in counts {
+        let Some(face) = indices.get(start..start + count) else {
+            warnings.insert(format!("{path}: `faceVertexCounts` doesn't match `faceVertexIndices`"));
+            break;
+        };
+        start += count;
+        if face.iter().any(|&index| index >= points.len()) {
+            warnings.insert(format!("{path}: face vertex index out of bounds"));
+            continue;
+        }
+        faces.push(face.iter().map(|&index| geo.add_vertex(base + index as u32)).collect());
+    }
+    if !faces.is_empty() {
+        geo.primitive_count += faces.len();
+        geo.primitives.push(Primitive::PolygonRun(PolygonRun {
+            count: faces.len(),
+            vertices: PrimVar::Varying(faces),
+            closed: PrimVar::Uniform(true),
+        }));
+    }
+}
+
+fn add_prim(
+    geo: &mut GeoBuilder,
+    prim: &Prim,
+    parent_path: &str,
+    parent_transform: &DMat4,
+    time: Option<f64>,
+    warnings: &mut BTreeSet<String>,
+) {
+    // `over`s have nothing to apply to without composition, and classes aren't rendered
+    if prim.specifier != Specifier::Def || prim.token("visibility", time).as_deref() == Some("invisible") {
+        return;
+    }
+    let path = format!("{parent_path}/{}", prim.name);
+    let (local, reset) = local_transform(prim, time, warnings);
+    let transform = if reset { local } else { *parent_transform * local };
+    match prim.type_name.as_str() {
+        "BasisCurves" => add_basis_curves(geo, prim, &path, &transform, time, warnings),
+        "Mesh" => add_mesh(geo, prim, &path, &transform, time, warnings),
+        other if UNSUPPORTED_GPRIMS.contains(&other) => {
+            warnings.insert(format!("`{other}` prims are not supported and were skipped"));
+        }
+        _ => {}
+    }
+    for child in prim.children.iter() {
+        add_prim(geo, child, &path, &transform, time, warnings);
+    }
+}
+
+impl Layer {
+    /// Returns the time codes at which the layer is sampled, one per frame.
+    ///
+    /// `None` stands for the default time, for layers without animation.
+    fn frame_times(&self) -> Vec<Option<f64>> {
+        fn sample_times(prim: &Prim, times: &mut Vec<f64>) {
+            for attribute in prim.attributes.iter() {
+                times.extend(attribute.time_samples.iter().map(|(t, _)| *t));
+            }
+            for child in prim.children.iter() {
+                sample_times(child, times);
+            }
+        }
+
+        let mut times = vec![];
+        for prim in self.prims.iter() {
+            sample_times(prim, &mut times);
+        }
+        let first = times.iter().copied().fold(f64::INFINITY, f64::min);
+        let last = times.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let start = self.start_time_code.unwrap_or(first);
+        let end = self.end_time_code.unwrap_or(last);
+        if times.is_empty() || !(start.is_finite() && end.is_finite()) || end <= start {
+            return vec![None];
+        }
+        let count = ((end - start).floor() as usize + 1).min(MAX_FRAMES);
+        (0..count).map(|i| Some(start + i as f64)).collect()
+    }
+
+    fn to_geo(&self) -> (Vec<Geo>, Vec<String>) {
+        let mut warnings = self.warnings.clone();
+        let mut frames = vec![];
+        for time in self.frame_times() {
+            let mut geo = GeoBuilder::default();
+            for prim in self.prims.iter() {
+                add_prim(&mut geo, prim, "", &DMat4::IDENTITY, time, &mut warnings);
+            }
+            frames.push(geo.finish());
+        }
+        (frames, warnings.into_iter().collect())
+    }
+}
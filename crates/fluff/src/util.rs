@@ -6,8 +6,10 @@ use graal::{BufferUsage, CommandStream, Format, Image, ImageCopyBuffer, ImageCop
 use graal::util::CommandStreamExt;
 
 pub mod gpu_append_buffer;
+pub mod gpu_upload;
 
 pub use gpu_append_buffer::AppendBuffer;
+pub use gpu_upload::CommandStreamUploadExt;
 
 /// Given a path of the form `foo####.ext`, with `####` being a frame number, returns a list of all files in the same directory
 /// that follow the same pattern, sorted by frame number.
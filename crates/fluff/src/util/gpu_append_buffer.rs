@@ -3,6 +3,9 @@ use graal::{Buffer, BufferUsage, CommandStream, Device, DeviceAddress, MemoryLoc
 use graal::util::DeviceExt;
 use tracing::trace;
 
+use super::CommandStreamUploadExt;
+use crate::gpu_memory::{MemoryCategory, MemoryTag};
+
 /// A resizable, append-only GPU buffer. Like `Vec<T>` but stored on GPU device memory.
 ///
 /// If the buffer is host-visible, elements can be added directly to the buffer.
@@ -12,6 +15,7 @@ pub struct AppendBuffer<T> {
     buffer: Buffer<[T]>,
     len: usize,
     staging: Vec<T>,
+    memory: MemoryTag,
 }
 
 impl<T: Copy> AppendBuffer<T> {
@@ -27,13 +31,20 @@ impl<T: Copy> AppendBuffer<T> {
             usage |= BufferUsage::TRANSFER_DST;
         }
         let buffer = device.create_array_buffer(usage, memory_location, capacity);
+        let memory = MemoryTag::new(MemoryCategory::Other, (capacity * size_of::<T>()) as u64);
         Self {
             buffer,
             len: 0,
             staging: vec![],
+            memory,
         }
     }
 
+    /// Sets the category under which the memory of the buffer is reported (see `gpu_memory`).
+    pub fn set_memory_category(&mut self, category: MemoryCategory) {
+        self.memory.set_category(category);
+    }
+
     /// Returns the pointer to the buffer data in host memory.
     ///
     /// # Panics
@@ -43,6 +54,28 @@ impl<T: Copy> AppendBuffer<T> {
         self.buffer.as_mut_ptr()
     }
 
+    /// Returns the elements of the buffer in host memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is not host-visible.
+    pub fn as_slice(&self) -> &[T] {
+        assert!(self.host_visible());
+        unsafe { std::slice::from_raw_parts(self.buffer.as_mut_ptr(), self.len) }
+    }
+
+    /// Returns the elements of the buffer in host memory, for modification.
+    ///
+    /// The caller is responsible for making sure that the GPU is not reading the data at the same time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is not host-visible.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        assert!(self.host_visible());
+        unsafe { std::slice::from_raw_parts_mut(self.buffer.as_mut_ptr(), self.len) }
+    }
+
     pub unsafe fn set_len(&mut self, len: usize) {
         assert!(self.host_visible());
         assert!(len <= self.buffer.len());
@@ -91,6 +124,7 @@ impl<T: Copy> AppendBuffer<T> {
                 .device()
                 .create_array_buffer(self.buffer.usage(), memory_location, new_capacity);
             cmd.copy_buffer(&self.buffer.untyped, 0, &new_buffer.untyped, 0, (self.len * size_of::<T>()) as u64);
+            self.memory.set_bytes((new_capacity * size_of::<T>()) as u64);
             self.buffer = new_buffer;
         }
     }
@@ -113,6 +147,7 @@ impl<T: Copy> AppendBuffer<T> {
             unsafe {
                 ptr::copy_nonoverlapping(self.buffer.as_mut_ptr(), new_buffer.as_mut_ptr(), self.len);
             }
+            self.memory.set_bytes((new_capacity * size_of::<T>()) as u64);
             self.buffer = new_buffer;
         }
     }
@@ -162,23 +197,7 @@ impl<T: Copy> AppendBuffer<T> {
         }
 
         self.reserve_gpu(cmd, n);
-        // allocate staging buffer & copy pending elements
-        let staging_buf = self
-            .buffer
-            .device()
-            .create_array_buffer::<T>(BufferUsage::TRANSFER_SRC, MemoryLocation::CpuToGpu, n);
-        unsafe {
-            ptr::copy_nonoverlapping(self.staging.as_ptr(), staging_buf.as_mut_ptr(), n);
-        }
-        // copy from staging to main buffer
-        let elem_size = size_of::<T>() as u64;
-        cmd.copy_buffer(
-            &staging_buf.untyped,
-            0,
-            &self.buffer.untyped,
-            self.len as u64 * elem_size,
-            n as u64 * elem_size,
-        );
+        cmd.update_buffer(&self.buffer, self.len, &self.staging);
         self.staging.clear();
     }
 
@@ -0,0 +1,62 @@
+use std::ptr;
+
+use graal::{Buffer, BufferUsage, CommandStream, MemoryLocation};
+
+/// Helpers to upload data to GPU buffers.
+///
+/// Host-visible buffers are written directly. Other buffers are written through a temporary staging
+/// buffer, copied on the device timeline.
+pub trait CommandStreamUploadExt {
+    /// Creates a buffer in the given memory location, initialized with the contents of `data`.
+    ///
+    /// `TRANSFER_DST` is added to `usage` if the buffer is not host-visible.
+    fn upload_slice<T: Copy>(&mut self, usage: BufferUsage, memory_location: MemoryLocation, data: &[T]) -> Buffer<[T]>;
+
+    /// Writes `data` to `buffer`, starting at element `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data doesn't fit in the buffer.
+    fn update_buffer<T: Copy>(&mut self, buffer: &Buffer<[T]>, offset: usize, data: &[T]);
+}
+
+impl CommandStreamUploadExt for CommandStream {
+    fn upload_slice<T: Copy>(&mut self, mut usage: BufferUsage, memory_location: MemoryLocation, data: &[T]) -> Buffer<[T]> {
+        if memory_location != MemoryLocation::CpuToGpu {
+            usage |= BufferUsage::TRANSFER_DST;
+        }
+        // zero-sized buffers are invalid
+        let buffer = self.device().create_array_buffer::<T>(usage, memory_location, data.len().max(1));
+        self.update_buffer(&buffer, 0, data);
+        buffer
+    }
+
+    fn update_buffer<T: Copy>(&mut self, buffer: &Buffer<[T]>, offset: usize, data: &[T]) {
+        assert!(offset + data.len() <= buffer.len(), "data doesn't fit in the buffer");
+        if data.is_empty() {
+            return;
+        }
+        if buffer.memory_location() == MemoryLocation::CpuToGpu {
+            // the buffer is mapped in memory, write directly
+            unsafe {
+                ptr::copy_nonoverlapping(data.as_ptr(), buffer.as_mut_ptr().add(offset), data.len());
+            }
+            return;
+        }
+
+        let staging = self
+            .device()
+            .create_array_buffer::<T>(BufferUsage::TRANSFER_SRC, MemoryLocation::CpuToGpu, data.len());
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), staging.as_mut_ptr(), data.len());
+        }
+        let elem_size = size_of::<T>() as u64;
+        self.copy_buffer(
+            &staging.untyped,
+            0,
+            &buffer.untyped,
+            offset as u64 * elem_size,
+            data.len() as u64 * elem_size,
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Workspace layouts: named arrangements of the panels of the application, for switching between tasks.
+//!
+//! A layout records which panels are shown and where. Panels are the floating windows of the UI,
+//! identified by their title. Switching to a layout shows and hides panels, and moves the panels that
+//! have a recorded position. Layouts are saved with the application settings; the built-in ones only
+//! set the visibility until they are updated from an arrangement.
+use std::collections::BTreeMap;
+
+use egui::{Context, Key, KeyboardShortcut, Modifiers, Pos2, Rect};
+
+/// Titles of the panels managed by workspace layouts.
+pub const PANELS: &[&str] = &[
+    "Assets",
+    "Color Management",
+    "Stylization",
+    "Review Session",
+    "Culling",
+    "GPU Memory",
+    "Console",
+    "Keyframes",
+    "Selection",
+    "Settings",
+];
+
+const LAYOUT_SHORTCUT_KEYS: [Key; 9] = [
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+];
+
+/// State of a panel in a layout.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PanelLayout {
+    pub visible: bool,
+    /// Position of the top-left corner of the panel. `None` leaves the panel where it is.
+    #[serde(default)]
+    pub pos: Option<[f32; 2]>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct WorkspaceLayout {
+    pub name: String,
+    /// Layout of each panel, by title. Panels missing from the layout are hidden.
+    pub panels: BTreeMap<String, PanelLayout>,
+}
+
+impl WorkspaceLayout {
+    fn with_panels(name: &str, panels: &[&str]) -> WorkspaceLayout {
+        WorkspaceLayout {
+            name: name.to_string(),
+            panels: panels
+                .iter()
+                .map(|title| (title.to_string(), PanelLayout { visible: true, pos: None }))
+                .collect(),
+        }
+    }
+
+    fn is_visible(&self, panel: &str) -> bool {
+        self.panels.get(panel).is_some_and(|layout| layout.visible)
+    }
+}
+
+/// Built-in layouts, used when the settings have none.
+pub fn default_layouts() -> Vec<WorkspaceLayout> {
+    vec![
+        WorkspaceLayout::with_panels("Modeling", &["Assets", "Selection", "Keyframes", "Settings"]),
+        WorkspaceLayout::with_panels("Shading", &["Color Management", "Stylization", "Settings"]),
+        WorkspaceLayout::with_panels("Review", &["Review Session", "Keyframes", "Console"]),
+    ]
+}
+
+/// Current arrangement of the panels, and the saved layouts.
+pub struct Workspace {
+    pub layouts: Vec<WorkspaceLayout>,
+    /// Index of the last applied layout.
+    current: Option<usize>,
+    /// Panels that are shown. All panels are shown until a layout is applied.
+    visible: BTreeMap<&'static str, bool>,
+    /// Last known rectangle of each panel.
+    rects: BTreeMap<&'static str, Rect>,
+    /// Positions to move panels to, on the next frame they are shown.
+    pending_pos: BTreeMap<&'static str, Pos2>,
+    new_layout_name: String,
+}
+
+impl Workspace {
+    pub fn new(layouts: Vec<WorkspaceLayout>) -> Workspace {
+        Workspace {
+            layouts,
+            current: None,
+            visible: PANELS.iter().map(|&title| (title, true)).collect(),
+            rects: Default::default(),
+            pending_pos: Default::default(),
+            new_layout_name: String::new(),
+        }
+    }
+
+    pub fn is_visible(&self, panel: &str) -> bool {
+        self.visible.get(panel).copied().unwrap_or(true)
+    }
+
+    /// Returns the window of a panel, moved to the position of the last applied layout if needed.
+    ///
+    /// `open` should be initialized with `is_visible`, and passed to `panel_shown` with the response of
+    /// the window after it's shown.
+    pub fn window<'open>(&mut self, panel: &'static str, open: &'open mut bool) -> egui::Window<'open> {
+        let window = egui::Window::new(panel).open(open);
+        match self.pending_pos.remove(panel) {
+            Some(pos) => window.current_pos(pos),
+            None => window,
+        }
+    }
+
+    /// Records whether the panel was closed, and where it was shown, for `capture`.
+    pub fn panel_shown<R>(&mut self, panel: &'static str, open: bool, response: Option<egui::InnerResponse<R>>) {
+        self.visible.insert(panel, open);
+        if let Some(response) = response {
+            self.rects.insert(panel, response.response.rect);
+        }
+    }
+
+    /// Shows, hides and moves panels according to a layout.
+    pub fn apply(&mut self, index: usize) {
+        let Some(layout) = self.layouts.get(index) else { return };
+        for &panel in PANELS {
+            self.visible.insert(panel, layout.is_visible(panel));
+            if let Some([x, y]) = layout.panels.get(panel).and_then(|layout| layout.pos) {
+                self.pending_pos.insert(panel, Pos2::new(x, y));
+            }
+        }
+        self.current = Some(index);
+    }
+
+    /// Returns a layout with the current arrangement of the panels.
+    fn capture(&self, name: String) -> WorkspaceLayout {
+        let panels = PANELS
+            .iter()
+            .map(|&panel| {
+                let layout = PanelLayout {
+                    visible: self.is_visible(panel),
+                    pos: self.rects.get(panel).map(|rect| rect.min.into()),
+                };
+                (panel.to_string(), layout)
+            })
+            .collect();
+        WorkspaceLayout { name, panels }
+    }
+
+    /// Switches layouts with Ctrl+1 to Ctrl+9.
+    pub fn handle_shortcuts(&mut self, ctx: &Context) {
+        for (i, key) in LAYOUT_SHORTCUT_KEYS.iter().enumerate().take(self.layouts.len()) {
+            let shortcut = KeyboardShortcut::new(Modifiers::CTRL, *key);
+            if ctx.input_mut(|input| input.consume_shortcut(&shortcut)) {
+                self.apply(i);
+            }
+        }
+    }
+
+    /// Menu to switch, update and create layouts, and show or hide panels. Returns whether the saved
+    /// layouts changed.
+    pub fn menu_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        for i in 0..self.layouts.len() {
+            let mut button = egui::Button::new(&self.layouts[i].name).selected(self.current == Some(i));
+            if let Some(key) = LAYOUT_SHORTCUT_KEYS.get(i) {
+                button = button.shortcut_text(ui.ctx().format_shortcut(&KeyboardShortcut::new(Modifiers::CTRL, *key)));
+            }
+            if ui.add(button).clicked() {
+                self.apply(i);
+                ui.close_menu();
+            }
+        }
+        ui.separator();
+
+        if let Some(current) = self.current {
+            let name = self.layouts[current].name.clone();
+            if ui
+                .button(format!("Update \"{name}\""))
+                .on_hover_text("Save the current arrangement of the panels in this layout")
+                .clicked()
+            {
+                self.layouts[current] = self.capture(name.clone());
+                changed = true;
+                ui.close_menu();
+            }
+            if ui.button(format!("Delete \"{name}\"")).clicked() {
+                self.layouts.remove(current);
+                self.current = None;
+                changed = true;
+                ui.close_menu();
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.new_layout_name);
+            if ui
+                .add_enabled(!self.new_layout_name.is_empty(), egui::Button::new("Save as new"))
+                .clicked()
+            {
+                let name = std::mem::take(&mut self.new_layout_name);
+                let layout = self.capture(name);
+                self.layouts.push(layout);
+                self.current = Some(self.layouts.len() - 1);
+                changed = true;
+            }
+        });
+        if ui.button("Restore built-in layouts").clicked() {
+            self.layouts = default_layouts();
+            self.current = None;
+            changed = true;
+        }
+
+        ui.separator();
+        ui.menu_button("Panels", |ui| {
+            for &panel in PANELS {
+                let mut visible = self.is_visible(panel);
+                if ui.checkbox(&mut visible, panel).changed() {
+                    self.visible.insert(panel, visible);
+                }
+            }
+        });
+        changed
+    }
+}
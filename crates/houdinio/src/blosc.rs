@@ -0,0 +1,329 @@
+//! Decompression of Blosc containers (`.sc` files).
+//!
+//! Houdini compresses `.sc` files with Blosc: the file is a sequence of Blosc chunks, each starting with
+//! a 16-byte header giving its compressed and uncompressed sizes. Chunks are split in blocks compressed
+//! independently, optionally after shuffling the bytes (or bits) of the values by significance.
+//! Blocks compressed with BloscLZ, LZ4, zlib and zstd are supported.
+use std::io::Read;
+
+use crate::Error;
+
+const HEADER_LEN: usize = 16;
+
+// Header flags.
+const FLAG_SHUFFLE: u8 = 0x01;
+const FLAG_MEMCPYED: u8 = 0x02;
+const FLAG_BITSHUFFLE: u8 = 0x04;
+const FLAG_DONT_SPLIT: u8 = 0x10;
+
+// Compressor codes (upper 3 bits of the flags).
+const CODEC_BLOSCLZ: u8 = 0;
+const CODEC_LZ4: u8 = 1;
+const CODEC_ZLIB: u8 = 3;
+const CODEC_ZSTD: u8 = 4;
+
+/// Blocks of values of up to this size are compressed as one stream per byte of the values.
+const MAX_SPLITS: usize = 16;
+/// Blocks are only split if each stream has at least this many bytes.
+const MIN_BUFFER_SIZE: usize = 128;
+/// Larger blocks are rejected. Blosc uses blocks of a few hundred KiB at most.
+const MAX_BLOCK_SIZE: usize = 64 << 20;
+
+/// Returns the uncompressed contents of a Blosc container.
+pub(crate) fn decompress(mut data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut out = vec![];
+    while !data.is_empty() {
+        let chunk_len = decompress_chunk(data, &mut out)?;
+        data = &data[chunk_len..];
+    }
+    Ok(out)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+/// Decompresses the chunk at the start of `data`, appending its contents to `out`.
+///
+/// Returns the size of the compressed chunk.
+fn decompress_chunk(data: &[u8], out: &mut Vec<u8>) -> Result<usize, Error> {
+    let header = data.get(..HEADER_LEN).ok_or(Error::EarlyEof)?;
+    let version = header[0];
+    let flags = header[2];
+    let typesize = header[3] as usize;
+    let nbytes = read_u32(header, 4).unwrap();
+    let blocksize = read_u32(header, 8).unwrap();
+    let cbytes = read_u32(header, 12).unwrap();
+    if version == 0 || cbytes < HEADER_LEN {
+        return Err(Error::Malformed);
+    }
+    let chunk = data.get(..cbytes).ok_or(Error::EarlyEof)?;
+
+    if flags & FLAG_MEMCPYED != 0 {
+        let contents = chunk.get(HEADER_LEN..HEADER_LEN + nbytes).ok_or(Error::Malformed)?;
+        out.extend_from_slice(contents);
+        return Ok(cbytes);
+    }
+    if nbytes == 0 {
+        return Ok(cbytes);
+    }
+    if typesize == 0 || blocksize == 0 || blocksize > MAX_BLOCK_SIZE {
+        return Err(Error::Malformed);
+    }
+
+    let block_count = nbytes.div_ceil(blocksize);
+    let leftover = nbytes % blocksize;
+    for i in 0..block_count {
+        let is_leftover = i == block_count - 1 && leftover != 0;
+        let size = if is_leftover { leftover } else { blocksize };
+        let start = read_u32(chunk, HEADER_LEN + 4 * i).ok_or(Error::Malformed)?;
+        let src = chunk.get(start..).ok_or(Error::Malformed)?;
+        let block = decompress_block(version, flags, typesize, size, is_leftover, src)?;
+        out.extend_from_slice(&block);
+    }
+    Ok(cbytes)
+}
+
+/// Decompresses a block of `size` bytes.
+///
+/// `is_leftover` is set for the last block of a chunk when it's smaller than the others.
+fn decompress_block(
+    version: u8,
+    flags: u8,
+    typesize: usize,
+    size: usize,
+    is_leftover: bool,
+    mut src: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let codec = flags >> 5;
+    let split = flags & FLAG_DONT_SPLIT == 0
+        && typesize <= MAX_SPLITS
+        && size / typesize >= MIN_BUFFER_SIZE
+        && !is_leftover;
+    let split_count = if split { typesize } else { 1 };
+    let split_size = size / split_count;
+    if split_size * split_count != size {
+        return Err(Error::Malformed);
+    }
+
+    let mut block = Vec::with_capacity(size);
+    for _ in 0..split_count {
+        let len = read_u32(src, 0).ok_or(Error::Malformed)?;
+        let stream = src.get(4..4 + len).ok_or(Error::Malformed)?;
+        let start = block.len();
+        if len == split_size {
+            // stored uncompressed
+            block.extend_from_slice(stream);
+        } else {
+            decompress_stream(codec, stream, split_size, &mut block)?;
+        }
+        if block.len() - start != split_size {
+            return Err(Error::Malformed);
+        }
+        src = &src[4 + len..];
+    }
+
+    if flags & FLAG_SHUFFLE != 0 && typesize > 1 {
+        Ok(unshuffle(typesize, &block))
+    } else if flags & FLAG_BITSHUFFLE != 0 && size >= typesize {
+        Ok(bitunshuffle(version, typesize, &block))
+    } else {
+        Ok(block)
+    }
+}
+
+/// Decompresses a stream of at most `max_len` bytes, appending it to `out`.
+fn decompress_stream(codec: u8, src: &[u8], max_len: usize, out: &mut Vec<u8>) -> Result<(), Error> {
+    match codec {
+        CODEC_BLOSCLZ => blosclz_decompress(src, max_len, out).ok_or(Error::Malformed),
+        CODEC_LZ4 => lz4_decompress(src, max_len, out).ok_or(Error::Malformed),
+        CODEC_ZLIB => {
+            let decoder = flate2::read::ZlibDecoder::new(src);
+            decoder
+                .take(max_len as u64)
+                .read_to_end(out)
+                .map_err(|_| Error::Malformed)?;
+            Ok(())
+        }
+        CODEC_ZSTD => {
+            let data = zstd::bulk::decompress(src, max_len).map_err(|_| Error::Malformed)?;
+            out.extend_from_slice(&data);
+            Ok(())
+        }
+        // snappy, or unknown
+        _ => Err(Error::Unsupported),
+    }
+}
+
+/// Decompresses a BloscLZ stream (a variant of FastLZ).
+fn blosclz_decompress(src: &[u8], max_len: usize, out: &mut Vec<u8>) -> Option<()> {
+    const MAX_DISTANCE: usize = 8191;
+    let base = out.len();
+    let limit = base + max_len;
+    // the first byte is always a literal run; its upper bits hold the compression level
+    let mut ctrl = (*src.first()? & 31) as usize;
+    let mut ip = 1;
+    loop {
+        if ctrl >= 32 {
+            // match
+            let mut len = (ctrl >> 5) - 1;
+            let ofs = (ctrl & 31) << 8;
+            if len == 6 {
+                loop {
+                    let code = *src.get(ip)?;
+                    ip += 1;
+                    len += code as usize;
+                    if code != 255 {
+                        break;
+                    }
+                }
+            }
+            let code = *src.get(ip)? as usize;
+            ip += 1;
+            len += 3;
+            let mut distance = ofs + code;
+            if code == 255 && ofs == 31 << 8 {
+                // 16-bit distance
+                distance = ((*src.get(ip)? as usize) << 8 | *src.get(ip + 1)? as usize) + MAX_DISTANCE;
+                ip += 2;
+            }
+            let op = out.len();
+            if op + len > limit || distance + 1 > op - base {
+                return None;
+            }
+            // the match may overlap the output
+            let start = op - distance - 1;
+            for i in 0..len {
+                out.push(out[start + i]);
+            }
+        } else {
+            // literal run
+            let len = ctrl + 1;
+            if out.len() + len > limit {
+                return None;
+            }
+            out.extend_from_slice(src.get(ip..ip + len)?);
+            ip += len;
+        }
+        if ip >= src.len() {
+            return Some(());
+        }
+        ctrl = src[ip] as usize;
+        ip += 1;
+    }
+}
+
+/// Reads a length continued in the following bytes (LZ4 encoding), adding it to `len`.
+fn lz4_length(src: &[u8], ip: &mut usize, mut len: usize) -> Option<usize> {
+    loop {
+        let b = *src.get(*ip)?;
+        *ip += 1;
+        len += b as usize;
+        if b != 255 {
+            return Some(len);
+        }
+    }
+}
+
+/// Decompresses an LZ4 block.
+fn lz4_decompress(src: &[u8], max_len: usize, out: &mut Vec<u8>) -> Option<()> {
+    let base = out.len();
+    let limit = base + max_len;
+    let mut ip = 0;
+    loop {
+        let token = *src.get(ip)?;
+        ip += 1;
+        let mut literals = (token >> 4) as usize;
+        if literals == 15 {
+            literals = lz4_length(src, &mut ip, literals)?;
+        }
+        if out.len() + literals > limit {
+            return None;
+        }
+        out.extend_from_slice(src.get(ip..ip + literals)?);
+        ip += literals;
+        // the last sequence has no match
+        if ip == src.len() {
+            return Some(());
+        }
+
+        let offset = u16::from_le_bytes([*src.get(ip)?, *src.get(ip + 1)?]) as usize;
+        ip += 2;
+        let mut len = (token & 15) as usize;
+        if len == 15 {
+            len = lz4_length(src, &mut ip, len)?;
+        }
+        len += 4;
+        let op = out.len();
+        if offset == 0 || offset > op - base || op + len > limit {
+            return None;
+        }
+        let start = op - offset;
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+/// Reverses the byte shuffle: `src` holds the first byte of every value, then the second byte, etc.
+fn unshuffle(typesize: usize, src: &[u8]) -> Vec<u8> {
+    let count = src.len() / typesize;
+    // trailing bytes that don't make a whole value are stored as is
+    let mut dst = src.to_vec();
+    for i in 0..count {
+        for j in 0..typesize {
+            dst[i * typesize + j] = src[j * count + i];
+        }
+    }
+    dst
+}
+
+/// Transposes an 8x8 bit matrix.
+fn transpose_bits_8x8(mut x: u64) -> u64 {
+    let t = (x ^ (x >> 7)) & 0x00AA_00AA_00AA_00AA;
+    x = x ^ t ^ (t << 7);
+    let t = (x ^ (x >> 14)) & 0x0000_CCCC_0000_CCCC;
+    x = x ^ t ^ (t << 14);
+    let t = (x ^ (x >> 28)) & 0x0000_0000_F0F0_F0F0;
+    x ^ t ^ (t << 28)
+}
+
+/// Reverses the bit shuffle: `src` holds the first bit of every value, then the second bit, etc.
+///
+/// Only whole groups of 8 values are shuffled. Blocks written by the first format versions are stored as
+/// is when their value count isn't a multiple of 8.
+fn bitunshuffle(version: u8, typesize: usize, src: &[u8]) -> Vec<u8> {
+    let size = src.len() / typesize;
+    let mut dst = src.to_vec();
+    let count = match version {
+        2 if !size.is_multiple_of(8) => return dst,
+        _ => size - size % 8,
+    };
+    if count == 0 {
+        return dst;
+    }
+
+    // gather the bit rows of each byte of the values
+    let row_len = count / 8;
+    let mut rows = vec![0; count * typesize];
+    for j in 0..typesize {
+        for i in 0..row_len {
+            for k in 0..8 {
+                rows[i * 8 * typesize + j * 8 + k] = src[(j * 8 + k) * row_len + i];
+            }
+        }
+    }
+    // transpose the bits of each group of 8 values
+    let len = count * typesize;
+    for j in (0..8 * typesize).step_by(8) {
+        for i in (0..len).step_by(8 * typesize) {
+            let mut x = transpose_bits_8x8(u64::from_le_bytes(rows[i + j..i + j + 8].try_into().unwrap()));
+            for k in 0..8 {
+                dst[i + j / 8 + k * typesize] = x as u8;
+                x >>= 8;
+            }
+        }
+    }
+    dst
+}
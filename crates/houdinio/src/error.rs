@@ -1,5 +1,66 @@
+use std::fmt;
 use thiserror::Error;
 
+/// Section of the file that was being parsed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Section {
+    Header,
+    Topology,
+    Attributes,
+    Primitives,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Section::Header => "header",
+            Section::Topology => "topology",
+            Section::Attributes => "attributes",
+            Section::Primitives => "primitives",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Details about a parse error.
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    /// Section of the file being parsed.
+    pub section: Section,
+    /// Path to the offending value in the JSON document, in JSON pointer syntax (e.g. `/primitives/3/0/runtype`).
+    ///
+    /// Keys of key-value arrays (`["key", value, ...]`) are treated like object keys.
+    pub path: String,
+    /// The offending token, if any.
+    pub token: Option<String>,
+    /// What went wrong.
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (in {}, at `{}`", self.message, self.section, self.path)?;
+        if let Some(ref token) = self.token {
+            write!(f, ", found {}", token)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A non-fatal issue encountered while parsing in lenient mode.
+#[derive(Clone, Debug)]
+pub struct Warning {
+    pub section: Section,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (in {}, at `{}`)", self.message, self.section, self.path)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("malformed .geo file")]
@@ -8,6 +69,8 @@ pub enum Error {
     Unsupported,
     #[error("early EOF")]
     EarlyEof,
+    #[error("parse error: {0}")]
+    Parse(Box<ParseError>),
     #[error("I/O error")]
     Io(#[from] std::io::Error),
 }
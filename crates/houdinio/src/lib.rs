@@ -1,13 +1,23 @@
 //! Houdini geometry (.geo) file parser.
 //!
-//! For now it only supports JSON-based `.geo` files. Binary files (`.bgeo`) are not supported.
+//! Reads JSON (`.geo`) and binary (`.bgeo`) geometry files. Files compressed with gzip (`.geo.gz`)
+//! or Blosc (`.geo.sc`) are decompressed transparently.
 
+mod blosc;
 mod error;
 mod parser;
+mod validate;
 
-pub use error::Error;
+pub use error::{Error, ParseError, Section, Warning};
+pub use validate::Validation;
 use smol_str::SmolStr;
-use std::{fs, path::Path, slice};
+use std::{
+    borrow::Cow,
+    fs,
+    io::Read,
+    path::Path,
+    slice,
+};
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
@@ -27,6 +37,54 @@ pub enum AttributeStorage {
     Int64(Vec<i64>),
 }
 
+/// How the values of an attribute should be interpreted (the `type` option of the attribute).
+///
+/// This tells how the attribute is affected by transforms: points are translated, vectors are rotated
+/// and scaled, normals are transformed by the inverse transpose, and the other kinds (e.g. colors)
+/// are left as is.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TypeInfo {
+    /// No type information, the values aren't transformed.
+    #[default]
+    None,
+    Point,
+    /// Homogeneous point (4 components).
+    HPoint,
+    Vector,
+    Normal,
+    Color,
+    Quaternion,
+    TextureCoord,
+    Matrix,
+    /// Type information not known to this parser.
+    Other(SmolStr),
+}
+
+impl TypeInfo {
+    pub fn from_name(name: &str) -> TypeInfo {
+        match name {
+            "" | "none" => TypeInfo::None,
+            "point" => TypeInfo::Point,
+            "hpoint" => TypeInfo::HPoint,
+            "vector" => TypeInfo::Vector,
+            "normal" => TypeInfo::Normal,
+            "color" => TypeInfo::Color,
+            "quaternion" => TypeInfo::Quaternion,
+            "texturecoord" => TypeInfo::TextureCoord,
+            "matrix" => TypeInfo::Matrix,
+            other => TypeInfo::Other(other.into()),
+        }
+    }
+
+    /// Whether values of this type are affected by transforms.
+    pub fn is_transformed(&self) -> bool {
+        matches!(
+            self,
+            TypeInfo::Point | TypeInfo::HPoint | TypeInfo::Vector | TypeInfo::Normal | TypeInfo::Quaternion | TypeInfo::Matrix
+        )
+    }
+}
+
 /// Geometry attribute.
 #[derive(Clone, Debug)]
 pub struct Attribute {
@@ -36,9 +94,29 @@ pub struct Attribute {
     pub size: usize,
     /// Storage.
     pub storage: AttributeStorage,
+    /// Visibility of the attribute (`public` or `private`).
+    pub scope: SmolStr,
+    /// Interpretation of the values.
+    pub type_info: TypeInfo,
+    /// Other options of the attribute (e.g. qualifiers set by exporters), with their values as strings.
+    ///
+    /// Options whose value is an array or a map are not preserved.
+    pub options: Vec<(SmolStr, String)>,
+    /// Default value of a tuple. May have fewer elements than `size`, in which case the last value is repeated.
+    pub defaults: Vec<f64>,
 }
 
 impl Attribute {
+    /// Returns the value of the specified option.
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the default value of the specified tuple element.
+    pub fn default_value(&self, index: usize) -> f64 {
+        self.defaults.get(index).or(self.defaults.last()).copied().unwrap_or(0.0)
+    }
+
     pub fn as_f32_slice(&self) -> Option<&[f32]> {
         match &self.storage {
             AttributeStorage::FpReal32(data) => Some(data),
@@ -62,6 +140,8 @@ pub struct Topology {
 #[derive(Clone, Debug)]
 pub enum Primitive {
     BezierRun(BezierRun),
+    PolygonRun(PolygonRun),
+    Volume(Volume),
 }
 
 /// The contents of a houdini geometry file.
@@ -74,6 +154,14 @@ pub struct Geo {
     pub point_attributes: Vec<Attribute>,
     pub primitive_attributes: Vec<Attribute>,
     pub primitives: Vec<Primitive>,
+    /// Positions in double precision, if the position attribute is stored as `fpreal64` in the file.
+    ///
+    /// The position attribute then holds the positions converted to single precision.
+    pub positions_f64: Option<Vec<[f64; 3]>>,
+    /// Offset of the single-precision positions: the position of a point is `origin + P`.
+    ///
+    /// Zero unless the geometry was re-centered (see `Geo::recenter`).
+    pub origin: [f64; 3],
 }
 
 impl Geo {
@@ -84,7 +172,7 @@ impl Geo {
         self.point_attributes.iter().find(|a| a.name == name)
     }
 
-    /// Returns the contents of the position attribute (`P`).
+    /// Returns the contents of the position attribute (`P`), relative to `origin`.
     pub fn positions(&self) -> &[[f32; 3]] {
         // The first attribute is always the position attribute.
         // The fact that this is an f32 attribute is ensured by the loader.
@@ -95,6 +183,47 @@ impl Geo {
         unsafe { slice::from_raw_parts(data.as_ptr().cast(), new_len) }
     }
 
+    /// Returns the positions in double precision, with the `origin` offset applied.
+    ///
+    /// They are converted from the position attribute if the file doesn't store them as `fpreal64`.
+    pub fn positions_f64(&self) -> Cow<'_, [[f64; 3]]> {
+        match self.positions_f64 {
+            Some(ref positions) => Cow::Borrowed(positions),
+            None => Cow::Owned(
+                self.positions()
+                    .iter()
+                    .map(|p| [0, 1, 2].map(|i| self.origin[i] + p[i] as f64))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Moves the origin to the center of the bounding box of the points, and converts the positions
+    /// to single precision relative to it. Returns the new origin.
+    ///
+    /// This keeps the precision of the single-precision positions of geometry far away from the origin
+    /// of the world, provided that the file stores positions as `fpreal64`.
+    pub fn recenter(&mut self) -> [f64; 3] {
+        let positions = self.positions_f64().into_owned();
+        if positions.is_empty() {
+            return self.origin;
+        }
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for p in positions.iter() {
+            min = [0, 1, 2].map(|i| min[i].min(p[i]));
+            max = [0, 1, 2].map(|i| max[i].max(p[i]));
+        }
+        let origin = [0, 1, 2].map(|i| 0.5 * (min[i] + max[i]));
+        let data = positions
+            .iter()
+            .flat_map(|p| [0, 1, 2].map(|i| (p[i] - origin[i]) as f32))
+            .collect();
+        self.point_attributes[0].storage = AttributeStorage::FpReal32(data);
+        self.origin = origin;
+        origin
+    }
+
     /// Returns the contents of the color attribute (`Cd`).
     pub fn color(&self) -> Option<&[[f32; 3]]> {
         let data = self.find_point_attribute("Cd")?.as_f32_slice()?;
@@ -102,6 +231,17 @@ impl Geo {
         Some(unsafe { slice::from_raw_parts(data.as_ptr().cast(), new_len) })
     }
 
+    /// Returns the contents of the normal attribute (`N`).
+    pub fn normals(&self) -> Option<&[[f32; 3]]> {
+        let attr = self.find_point_attribute("N")?;
+        if attr.size != 3 {
+            return None;
+        }
+        let data = attr.as_f32_slice()?;
+        let new_len = data.len() / 3;
+        Some(unsafe { slice::from_raw_parts(data.as_ptr().cast(), new_len) })
+    }
+
     /// Returns the position of of the given vertex.
     pub fn vertex_position(&self, vertex_index: i32) -> [f32; 3] {
         // The vertex is an index into the topology array, which gives us the index into the point attribute.
@@ -214,16 +354,194 @@ impl Default for BezierRun {
     }
 }
 
+/// A run of polygons.
+#[derive(Clone, Debug)]
+pub struct PolygonRun {
+    /// Number of polygons in the run.
+    pub count: usize,
+    /// Vertices of the polygons.
+    ///
+    /// They are indices into the `topology` vector.
+    pub vertices: PrimVar<Vec<i32>>,
+    /// Whether the polygon is closed. Open polygons are polylines.
+    pub closed: PrimVar<bool>,
+}
+
+impl PolygonRun {
+    /// Returns the vertices and the closed flag of each polygon in the run.
+    ///
+    /// Polygons missing from a truncated varying list have no vertices and are open.
+    pub fn iter(&self) -> impl Iterator<Item = (&[i32], bool)> + '_ {
+        (0..self.count).map(move |i| {
+            let vertices = match &self.vertices {
+                PrimVar::Uniform(vertices) => vertices.as_slice(),
+                PrimVar::Varying(vertices) => vertices.get(i).map_or(&[][..], Vec::as_slice),
+            };
+            let closed = match &self.closed {
+                PrimVar::Uniform(closed) => *closed,
+                PrimVar::Varying(closed) => closed.get(i).copied().unwrap_or(false),
+            };
+            (vertices, closed)
+        })
+    }
+}
+
+impl Default for PolygonRun {
+    fn default() -> Self {
+        PolygonRun {
+            count: 0,
+            vertices: PrimVar::Varying(vec![]),
+            // polygons are closed unless specified otherwise
+            closed: PrimVar::Uniform(true),
+        }
+    }
+}
+
+/// Voxel data of a volume primitive.
+#[derive(Clone, Debug)]
+pub enum VolumeData {
+    /// Dense voxel values, with X varying fastest, then Y, then Z.
+    Dense(Vec<f32>),
+    /// Reference to a grid in an external OpenVDB file.
+    ///
+    /// Either field may be missing if the file doesn't specify it.
+    VdbFile { path: Option<String>, grid: Option<String> },
+    /// A VDB grid stored in the file itself. The grid data is not decoded.
+    Vdb,
+}
+
+/// A volume primitive (native Houdini volume or VDB).
+#[derive(Clone, Debug)]
+pub struct Volume {
+    /// Vertex at the center of the volume (index into the `topology` vector).
+    pub vertex: i32,
+    /// Row-major 3x3 matrix mapping the `[-1,1]^3` cube to the volume extents, around the center point.
+    ///
+    /// As in Houdini, it is applied to row vectors (`p = u * M`).
+    pub transform: [f32; 9],
+    /// Number of voxels along each axis. Zero for VDB references, whose resolution isn't known without loading the grid.
+    pub resolution: [u32; 3],
+    pub data: VolumeData,
+}
+
+impl Volume {
+    /// Returns the bounding box (min, max) of the volume in object space.
+    pub fn bounds(&self, geo: &Geo) -> ([f32; 3], [f32; 3]) {
+        let center = geo.vertex_position(self.vertex);
+        let m = &self.transform;
+        let mut min = center;
+        let mut max = center;
+        for i in 0..3 {
+            // half-extent along axis `i` of the transformed unit cube
+            let extent = m[i].abs() + m[3 + i].abs() + m[6 + i].abs();
+            min[i] -= extent;
+            max[i] += extent;
+        }
+        (min, max)
+    }
+
+    /// Returns the value of the voxel at the specified coordinates, if the volume is dense.
+    ///
+    /// Returns `None` if the coordinates are outside the volume.
+    pub fn voxel(&self, x: u32, y: u32, z: u32) -> Option<f32> {
+        let VolumeData::Dense(ref voxels) = self.data else { return None };
+        let [rx, ry, rz] = self.resolution;
+        if x >= rx || y >= ry || z >= rz {
+            return None;
+        }
+        let (x, y, z, rx, ry) = (x as usize, y as usize, z as usize, rx as usize, ry as usize);
+        voxels.get(x + rx * (y + ry * z)).copied()
+    }
+}
+
+/// Options for loading geometry files.
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    /// Skip unsupported primitive types instead of failing the whole load.
+    ///
+    /// Skipped primitives are reported as warnings.
+    pub lenient: bool,
+    /// Topology checks after parsing.
+    pub validation: Validation,
+    /// Re-center the geometry on the center of its bounding box (see `Geo::recenter`).
+    pub recenter: bool,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Returns whether the file name has the extension of a geometry file (JSON or binary), compressed or not.
+pub fn is_geo_file(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+        [".geo", ".geo.gz", ".geo.sc", ".bgeo", ".bgeo.gz", ".bgeo.sc"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+    })
+}
+
+/// Reads a geometry file, decompressing it if it starts with the magic bytes of gzip or zstd, or if it
+/// has the `.sc` extension of Blosc containers.
+fn read_geo_file(path: &Path) -> Result<Vec<u8>, Error> {
+    let data = fs::read(path)?;
+    let mut decompressed = vec![];
+    if data.starts_with(GZIP_MAGIC) {
+        flate2::read::MultiGzDecoder::new(&data[..]).read_to_end(&mut decompressed)?;
+    } else if data.starts_with(ZSTD_MAGIC) {
+        zstd::stream::read::Decoder::new(&data[..])?.read_to_end(&mut decompressed)?;
+    } else if path.extension().is_some_and(|ext| ext == "sc") {
+        decompressed = blosc::decompress(&data)?;
+    } else {
+        decompressed = data;
+    }
+    Ok(decompressed)
+}
+
+/// Parses the contents of a JSON or binary geometry file.
+fn parse_geo_data(data: &[u8], options: &ParseOptions) -> Result<(Geo, Vec<Warning>), Error> {
+    if parser::is_binary(data) {
+        parser::parse_binary(data, options)
+    } else {
+        let text = std::str::from_utf8(data).map_err(|_| Error::Malformed)?;
+        parser::parse_json(text, options)
+    }
+}
+
 impl Geo {
+    /// Loads a geometry file, JSON or binary, compressed or not.
+    ///
+    /// The format is detected from the contents of the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Geo, Error> {
+        let (geo, _) = Geo::load_with_options(path, &ParseOptions::default())?;
+        Ok(geo)
+    }
+
+    /// Loads a geometry file, JSON or binary, with the specified options.
+    ///
+    /// Returns the geometry along with the warnings emitted during parsing.
+    pub fn load_with_options<P: AsRef<Path>>(path: P, options: &ParseOptions) -> Result<(Geo, Vec<Warning>), Error> {
+        let data = read_geo_file(path.as_ref())?;
+        parse_geo_data(&data, options)
+    }
+
+    /// Loads a JSON geometry file, compressed or not.
     pub fn load_json<P: AsRef<Path>>(path: P) -> Result<Geo, Error> {
-        let data = fs::read_to_string(path)?;
-        parser::parse_json(&data)
+        let (geo, _) = Geo::load_json_with_options(path, &ParseOptions::default())?;
+        Ok(geo)
+    }
+
+    /// Loads a JSON geometry file with the specified options.
+    ///
+    /// Returns the geometry along with the warnings emitted during parsing.
+    pub fn load_json_with_options<P: AsRef<Path>>(path: P, options: &ParseOptions) -> Result<(Geo, Vec<Warning>), Error> {
+        let data = read_geo_file(path.as_ref())?;
+        let text = String::from_utf8(data).map_err(|_| Error::Malformed)?;
+        parser::parse_json(&text, options)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::Geo;
+    use crate::{blosc, parser, Error, Geo, ParseOptions, Primitive, Section, TypeInfo, Validation, VolumeData};
 
     #[test]
     fn compiles() {
@@ -231,4 +549,392 @@ mod test {
         let geo = Geo::load_json(path).unwrap();
         eprintln!("{:#?}", geo);
     }
+
+    fn test_geo(primitives: &str) -> String {
+        format!(
+            r#"[
+                "pointcount", 4, "vertexcount", 4, "primitivecount", 2,
+                "topology", ["pointref", ["indices", [0, 1, 2, 3]]],
+                "attributes", ["pointattributes", [
+                    [["name", "P"], ["size", 3, "storage", "fpreal32", "values", ["size", 3, "storage", "fpreal32", "tuples", [[0,0,0],[1,0,0],[1,1,0],[0,1,0]]]]]
+                ]],
+                "primitives", {primitives}
+            ]"#
+        )
+    }
+
+    const BEZIER_RUN: &str = r#"[["type", "run", "runtype", "BezierCurve", "varyingfields", ["vertex"], "uniformfields", {"closed": false}], [[[0, 1, 2, 3]]]]"#;
+    const SPHERE: &str = r#"[["type", "Sphere"], ["vertex", [0], "transform", [1, 0, 0, 0, 1, 0, 0, 0, 1]]]"#;
+
+    #[test]
+    fn error_path() {
+        let data = test_geo("[[\"oops\"]]");
+        let Err(Error::Parse(err)) = parser::parse_json(&data, &ParseOptions::default()) else {
+            panic!("expected a parse error")
+        };
+        assert_eq!(err.section, Section::Primitives);
+        assert_eq!(err.path, "/primitives/0/0");
+        assert_eq!(err.token.as_deref(), Some("string \"oops\""));
+    }
+
+    #[test]
+    fn lenient_unknown_primitive() {
+        let data = test_geo(&format!("[{SPHERE}, {BEZIER_RUN}]"));
+        assert!(parser::parse_json(&data, &ParseOptions::default()).is_err());
+        let (geo, warnings) = parser::parse_json(&data, &ParseOptions {
+            lenient: true,
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(geo.primitives.len(), 1);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].path, "/primitives/0");
+    }
+
+    #[test]
+    fn volumes() {
+        let volume = r#"[["type", "Volume"], ["vertex", [1], "transform", [2, 0, 0, 0, 1, 0, 0, 0, 1], "res", [20, 1, 1],
+            "voxels", ["tiledarray", ["version", 2, "tiles", [["compression", 1, "value", 0.5], ["compression", 0, "data", [1, 2, 3, 4]]]]]]]"#;
+        let vdb_ref = r#"[["type", "PackedDisk"], ["vertex", [0], "parameters", {"filename": "smoke.vdb", "primname": "density"}]]"#;
+        let data = test_geo(&format!("[{volume}, {vdb_ref}]"));
+        let (geo, _) = parser::parse_json(&data, &ParseOptions::default()).unwrap();
+        assert_eq!(geo.primitives.len(), 2);
+
+        let Primitive::Volume(ref v) = geo.primitives[0] else { panic!("expected a volume") };
+        assert_eq!(v.resolution, [20, 1, 1]);
+        assert_eq!(v.voxel(15, 0, 0), Some(0.5));
+        assert_eq!(v.voxel(17, 0, 0), Some(2.0));
+        assert_eq!(v.voxel(0, 1, 0), None);
+        assert_eq!(v.voxel(20, 0, 0), None);
+        assert_eq!(v.bounds(&geo), ([-1.0, -1.0, -1.0], [3.0, 1.0, 1.0]));
+
+        let Primitive::Volume(ref v) = geo.primitives[1] else { panic!("expected a volume") };
+        let VolumeData::VdbFile { ref path, ref grid } = v.data else { panic!("expected a VDB reference") };
+        assert_eq!(path.as_deref(), Some("smoke.vdb"));
+        assert_eq!(grid.as_deref(), Some("density"));
+
+        // the voxel count overflows u32
+        let huge = r#"[["type", "Volume"], ["vertex", [0], "res", [65536, 65536, 65536], "voxels", ["tiledarray", ["tiles", []]]]]"#;
+        let data = test_geo(&format!("[{huge}]"));
+        assert!(parser::parse_json(&data, &ParseOptions::default()).is_err());
+    }
+
+    #[test]
+    fn polygons() {
+        let poly_run = r#"[["type", "run", "runtype", "Poly", "varyingfields", ["vertex"], "uniformfields", {"closed": true}],
+            [[[0, 1, 2]], [[0, 2, 3]]]]"#;
+        let data = test_geo(&format!("[{poly_run}, {BEZIER_RUN}]"));
+        let (geo, _) = parser::parse_json(&data, &ParseOptions::default()).unwrap();
+        assert_eq!(geo.primitives.len(), 2);
+
+        let Primitive::PolygonRun(ref run) = geo.primitives[0] else { panic!("expected a polygon run") };
+        let polygons: Vec<_> = run.iter().collect();
+        assert_eq!(polygons, [(&[0, 1, 2][..], true), (&[0, 2, 3][..], true)]);
+    }
+
+    #[test]
+    fn validation() {
+        // second curve has too few vertices, third references a missing vertex
+        let bezier_run = r#"[["type", "run", "runtype", "BezierCurve", "varyingfields", ["vertex"],
+            "uniformfields", {"closed": false, "basis": ["type", "Bezier", "order", 4]}],
+            [[[0, 1, 2, 3]], [[0, 1]], [[0, 1, 2, 7]]]]"#;
+        let data = test_geo(&format!("[{bezier_run}]"));
+        assert!(parser::parse_json(&data, &ParseOptions::default()).is_ok());
+
+        let strict = ParseOptions {
+            validation: Validation::Strict,
+            ..Default::default()
+        };
+        let Err(Error::Parse(err)) = parser::parse_json(&data, &strict) else {
+            panic!("expected a validation error")
+        };
+        assert_eq!(err.path, "/primitives/0");
+
+        let repair = ParseOptions {
+            validation: Validation::Repair,
+            ..Default::default()
+        };
+        let (geo, warnings) = parser::parse_json(&data, &repair).unwrap();
+        assert_eq!(warnings.len(), 2);
+        let Primitive::BezierRun(ref run) = geo.primitives[0] else { panic!("expected a bezier run") };
+        assert_eq!(run.count, 1);
+        assert_eq!(run.iter().next().unwrap().vertices, &[0, 1, 2, 3]);
+        assert_eq!(geo.primitive_count, 1);
+        assert!(geo.validate().is_empty());
+    }
+
+    #[test]
+    fn double_positions() {
+        let data = r#"[
+            "pointcount", 2, "vertexcount", 0, "primitivecount", 0,
+            "attributes", ["pointattributes", [
+                [["name", "P"], ["size", 3, "storage", "fpreal64", "values", ["size", 3, "storage", "fpreal64",
+                    "tuples", [[10000000.125, 0, -2], [10000000.375, 1, -4]]]]]
+            ]],
+            "primitives", []
+        ]"#;
+        let (geo, _) = parser::parse_json(data, &ParseOptions::default()).unwrap();
+        assert_eq!(geo.positions_f64()[0], [10000000.125, 0.0, -2.0]);
+        // single precision can't represent the fractional part
+        assert_eq!(geo.positions()[0][0], 10000000.0);
+
+        let options = ParseOptions {
+            recenter: true,
+            ..Default::default()
+        };
+        let (geo, _) = parser::parse_json(data, &options).unwrap();
+        assert_eq!(geo.origin, [10000000.25, 0.5, -3.0]);
+        assert_eq!(geo.positions(), &[[-0.125, -0.5, 1.0], [0.125, 0.5, -1.0]]);
+        assert_eq!(geo.positions_f64()[1], [10000000.375, 1.0, -4.0]);
+    }
+
+    #[test]
+    fn paged_attributes() {
+        let attribute = |raw: &str| {
+            format!(
+                r#"[
+                "pointcount", 3, "vertexcount", 0, "primitivecount", 0,
+                "attributes", ["pointattributes", [
+                    [["name", "P"], ["size", 3, "storage", "fpreal32", "values", ["size", 3, "storage", "fpreal32",
+                        "packing", [2, 1], "pagesize", 2, "constantpageflags", [[false, true], [true, false]],
+                        "rawpagedata", [{raw}]]]]
+                ]],
+                "primitives", []
+            ]"#
+            )
+        };
+        let (geo, _) = parser::parse_json(&attribute("1, 2, 3, 4, 5, 6, 7, 8"), &ParseOptions::default()).unwrap();
+        assert_eq!(geo.positions(), &[[1.0, 2.0, 5.0], [3.0, 4.0, 5.0], [6.0, 7.0, 8.0]]);
+
+        // one value short
+        let result = parser::parse_json(&attribute("1, 2, 3, 4, 5, 6, 7"), &ParseOptions::default());
+        assert!(matches!(result, Err(Error::Parse(_))));
+    }
+
+    /// Returns a path in the temporary directory that doesn't collide with other test runs.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("houdinio_{}_{name}", std::process::id()))
+    }
+
+    /// Builds a Blosc chunk with the specified flags, holding a single block.
+    fn blosc_chunk(flags: u8, typesize: u8, nbytes: usize, block: &[u8]) -> Vec<u8> {
+        let memcpyed = flags & 0x02 != 0;
+        let cbytes = 16 + if memcpyed { block.len() } else { 4 + block.len() };
+        let mut chunk = vec![2, 1, flags, typesize];
+        chunk.extend((nbytes as u32).to_le_bytes());
+        chunk.extend((nbytes as u32).to_le_bytes());
+        chunk.extend((cbytes as u32).to_le_bytes());
+        if !memcpyed {
+            // offset of the only block
+            chunk.extend(20u32.to_le_bytes());
+        }
+        chunk.extend(block);
+        chunk
+    }
+
+    /// A block made of a single compressed stream.
+    fn blosc_stream(stream: &[u8]) -> Vec<u8> {
+        let mut block = (stream.len() as u32).to_le_bytes().to_vec();
+        block.extend(stream);
+        block
+    }
+
+    #[test]
+    fn blosc() {
+        use std::io::Write;
+
+        // "abc", then a match of 9 bytes at distance 3, then "!"
+        let expected = b"abcabcabcabc!";
+        let blosclz = blosc_stream(&[0x02, b'a', b'b', b'c', 0xe0, 0x00, 0x02, 0x00, b'!']);
+        let lz4 = blosc_stream(&[0x35, b'a', b'b', b'c', 0x03, 0x00, 0x10, b'!']);
+        let mut zlib = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        zlib.write_all(expected).unwrap();
+        let zlib = blosc_stream(&zlib.finish().unwrap());
+        let zstd = blosc_stream(&zstd::encode_all(&expected[..], 0).unwrap());
+        for (codec, block) in [(0, blosclz), (1, lz4), (3, zlib), (4, zstd)] {
+            let chunk = blosc_chunk(codec << 5, 1, expected.len(), &block);
+            assert_eq!(blosc::decompress(&chunk).unwrap(), expected, "codec {codec}");
+        }
+
+        // byte shuffle of 4-byte values, stored uncompressed
+        let shuffled = [1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let chunk = blosc_chunk(0x01, 4, 16, &blosc_stream(&shuffled));
+        let values: Vec<u8> = [1u32, 2, 3, 4].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(blosc::decompress(&chunk).unwrap(), values);
+
+        // bit shuffle: the first bit of each value, then the second, etc.
+        let chunk = blosc_chunk(0x04, 1, 8, &blosc_stream(&[0x01; 8]));
+        assert_eq!(blosc::decompress(&chunk).unwrap(), [0xff, 0, 0, 0, 0, 0, 0, 0]);
+
+        // several chunks, one stored as is
+        let mut data = blosc_chunk(0x02, 1, 3, b"abc");
+        let lz4 = blosc_stream(&[0x35, b'a', b'b', b'c', 0x03, 0x00, 0x10, b'!']);
+        data.extend(blosc_chunk(1 << 5, 1, expected.len(), &lz4));
+        assert_eq!(blosc::decompress(&data).unwrap(), b"abcabcabcabcabc!");
+
+        // match before the start of the block
+        let chunk = blosc_chunk(0x20, 1, 8, &blosc_stream(&[0x00, b'a', 0x20, 0x09]));
+        assert!(blosc::decompress(&chunk).is_err());
+        // truncated
+        assert!(blosc::decompress(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn compressed() {
+        use std::io::Write;
+
+        let data = test_geo(&format!("[{BEZIER_RUN}]"));
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(data.as_bytes()).unwrap();
+        let mut blosc = blosc_chunk(0x02, 1, 16, &data.as_bytes()[..16]);
+        let rest = &data.as_bytes()[16..];
+        let zstd_block = blosc_stream(&zstd::encode_all(rest, 0).unwrap());
+        blosc.extend(blosc_chunk(4 << 5, 1, rest.len(), &zstd_block));
+        let files = [
+            ("compressed.geo.gz", gz.finish().unwrap()),
+            ("compressed_zstd.geo.sc", zstd::encode_all(data.as_bytes(), 0).unwrap()),
+            ("compressed_blosc.geo.sc", blosc),
+        ];
+        for (name, contents) in files {
+            let path = temp_path(name);
+            std::fs::write(&path, contents).unwrap();
+            assert!(crate::is_geo_file(&path));
+            let geo = Geo::load_json(&path).unwrap();
+            assert_eq!(geo.primitives.len(), 1);
+            assert_eq!(geo.positions().len(), 4);
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    /// Encodes a JSON document in the binary format, like Houdini does: strings are defined as tokens
+    /// on first use, and arrays of numbers are written as uniform arrays.
+    fn to_binary(json: &str) -> Vec<u8> {
+        use serde_json::Value;
+
+        fn length(out: &mut Vec<u8>, n: usize) {
+            if n < 0xf1 {
+                out.push(n as u8);
+            } else {
+                out.push(0xf4);
+                out.extend((n as u32).to_le_bytes());
+            }
+        }
+
+        fn string(out: &mut Vec<u8>, tokens: &mut Vec<String>, s: &str) {
+            let id = tokens.iter().position(|t| t == s).unwrap_or_else(|| {
+                tokens.push(s.to_string());
+                out.push(0x2b);
+                length(out, tokens.len() - 1);
+                length(out, s.len());
+                out.extend(s.as_bytes());
+                tokens.len() - 1
+            });
+            out.push(0x26);
+            length(out, id);
+        }
+
+        fn write(out: &mut Vec<u8>, tokens: &mut Vec<String>, value: &Value) {
+            match value {
+                Value::Null => out.push(0x00),
+                Value::Bool(b) => out.push(if *b { 0x31 } else { 0x30 }),
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => {
+                        out.push(0x13);
+                        out.extend((i as i32).to_le_bytes());
+                    }
+                    None => {
+                        out.push(0x1a);
+                        out.extend(n.as_f64().unwrap().to_le_bytes());
+                    }
+                },
+                Value::String(s) => string(out, tokens, s),
+                Value::Array(a) if !a.is_empty() && a.iter().all(Value::is_i64) => {
+                    out.extend([0x40, 0x13]);
+                    length(out, a.len());
+                    for v in a {
+                        out.extend((v.as_i64().unwrap() as i32).to_le_bytes());
+                    }
+                }
+                Value::Array(a) if !a.is_empty() && a.iter().all(Value::is_number) => {
+                    out.extend([0x40, 0x19]);
+                    length(out, a.len());
+                    for v in a {
+                        out.extend((v.as_f64().unwrap() as f32).to_le_bytes());
+                    }
+                }
+                Value::Array(a) => {
+                    out.push(b'[');
+                    for v in a {
+                        write(out, tokens, v);
+                    }
+                    out.push(b']');
+                }
+                Value::Object(m) => {
+                    out.push(b'{');
+                    for (k, v) in m {
+                        string(out, tokens, k);
+                        write(out, tokens, v);
+                    }
+                    out.push(b'}');
+                }
+            }
+        }
+
+        let mut out = vec![0x7f];
+        out.extend(0x624a534e_u32.to_le_bytes());
+        write(&mut out, &mut vec![], &serde_json::from_str(json).unwrap());
+        out
+    }
+
+    #[test]
+    fn binary() {
+        let volume = r#"[["type", "Volume"], ["vertex", [1], "transform", [2, 0, 0, 0, 1, 0, 0, 0, 1], "res", [2, 1, 1], "voxels", [0.5, 0.25]]]"#;
+        let json = test_geo(&format!("[{BEZIER_RUN}, {volume}]"));
+        let data = to_binary(&json);
+        assert!(parser::is_binary(&data));
+        let options = ParseOptions {
+            validation: Validation::Repair,
+            ..Default::default()
+        };
+        let (geo, _) = parser::parse_binary(&data, &options).unwrap();
+        let (expected, _) = parser::parse_json(&json, &options).unwrap();
+        assert_eq!(geo.topology, expected.topology);
+        assert_eq!(geo.positions(), expected.positions());
+        assert_eq!(geo.primitives.len(), 2);
+        let Primitive::BezierRun(ref run) = geo.primitives[0] else { panic!("expected a bezier run") };
+        assert_eq!(run.iter().next().unwrap().vertices, &[0, 1, 2, 3]);
+        let Primitive::Volume(ref volume) = geo.primitives[1] else { panic!("expected a volume") };
+        assert_eq!(volume.voxel(1, 0, 0), Some(0.25));
+
+        // truncated files are errors
+        assert!(parser::parse_binary(&data[..data.len() / 2], &ParseOptions::default()).is_err());
+        assert!(crate::is_geo_file(std::path::Path::new("cache.0001.bgeo.sc")));
+    }
+
+    #[test]
+    fn attribute_metadata() {
+        let data = r#"[
+            "pointcount", 2, "vertexcount", 0, "primitivecount", 0,
+            "attributes", ["pointattributes", [
+                [["scope", "public", "type", "numeric", "name", "P", "options", {"type": {"type": "string", "value": "point"}}],
+                 ["size", 3, "storage", "fpreal32", "values", ["size", 3, "storage", "fpreal32", "tuples", [[0,0,0],[1,0,0]]]]],
+                [["scope", "public", "type", "numeric", "name", "Cd", "options", {
+                    "type": {"type": "string", "value": "color"},
+                    "export": {"type": "bool", "value": true},
+                    "ranges": {"type": "array", "value": [0, 1]}}],
+                 ["size", 3, "storage", "fpreal32", "defaults", ["size", 1, "storage", "fpreal64", "values", [1]],
+                  "values", ["size", 3, "storage", "fpreal32", "tuples", [[1,0,0],[0,1,0]]]]]
+            ]],
+            "primitives", []
+        ]"#;
+        let (geo, _) = parser::parse_json(data, &ParseOptions::default()).unwrap();
+        assert_eq!(geo.point_attributes[0].type_info, TypeInfo::Point);
+        let color = geo.find_point_attribute("Cd").unwrap();
+        assert_eq!(color.type_info, TypeInfo::Color);
+        assert!(!color.type_info.is_transformed());
+        assert_eq!(color.scope, "public");
+        assert_eq!(color.option("export"), Some("true"));
+        assert_eq!(color.option("ranges"), None);
+        assert_eq!(color.default_value(2), 1.0);
+        assert_eq!(geo.color(), Some(&[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]][..]));
+    }
 }
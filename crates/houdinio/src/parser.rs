@@ -1,9 +1,14 @@
 mod binary;
 mod json;
 
-use crate::{Attribute, AttributeStorage, BezierBasis, BezierRun, Error, Error::Malformed, Geo, PrimVar, Primitive, StorageKind};
-use json::ParserImpl;
+use crate::{
+    error::{ParseError, Section}, Attribute, AttributeStorage, BezierBasis, BezierRun, Error, Geo, ParseOptions, PolygonRun, PrimVar, Primitive, StorageKind, TypeInfo, Validation, Volume,
+    VolumeData, Warning,
+};
+use binary::BinaryInput;
+use json::{Input, ParseContext, ParserImpl};
 use smol_str::SmolStr;
+use std::rc::Rc;
 
 #[derive(PartialEq, Debug)]
 #[allow(dead_code)]
@@ -16,6 +21,21 @@ enum Event {
     EndArray,
     BeginMap,
     EndMap,
+    Null,
+    /// Invalid JSON, with the error message.
+    Invalid(String),
+}
+
+/// Kind of the next value in the input.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Peek {
+    /// Beginning of an array or a map.
+    Begin,
+    /// End of an array or a map.
+    End,
+    /// A scalar value.
+    Value,
+    Eof,
 }
 
 impl Event {
@@ -46,13 +66,13 @@ impl Event {
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
 impl StorageKind {
-    fn parse(s: &str) -> Result<StorageKind, Error> {
+    fn parse(p: &ParserImpl, s: &str) -> Result<StorageKind, Error> {
         match s {
             "fpreal32" => Ok(StorageKind::FpReal32),
             "fpreal64" => Ok(StorageKind::FpReal64),
             "int32" => Ok(StorageKind::Int32),
             "int64" => Ok(StorageKind::Int64),
-            _ => Err(Error::Malformed),
+            _ => Err(p.error(format!("unsupported storage kind `{s}`"), None)),
         }
     }
 }
@@ -69,7 +89,7 @@ impl AttributeStorage {
 
     fn read_element(&mut self, p: &mut ParserImpl) -> Result<(), Error> {
         //eprintln!("read_element");
-        match p.next().ok_or(Error::EarlyEof)? {
+        match p.next().ok_or_else(|| p.early_eof())? {
             Event::Float(f) => match self {
                 AttributeStorage::FpReal32(v) => v.push(f as f32),
                 AttributeStorage::FpReal64(v) => v.push(f as f64),
@@ -82,8 +102,8 @@ impl AttributeStorage {
                 AttributeStorage::Int32(v) => v.push(i as i32),
                 AttributeStorage::Int64(v) => v.push(i),
             },
-            _ => {
-                return Err(Malformed);
+            e => {
+                return Err(p.unexpected("a number", &e));
             }
         }
         Ok(())
@@ -125,26 +145,152 @@ macro_rules! read_array {
     };
 }
 
+/// Expands paged attribute values (`rawpagedata`) to a tuple of `size` values per element.
+///
+/// Elements are grouped in pages of `page_size` elements. The components of the tuples are split in
+/// subvectors (`packing`), and each page holds the values of each subvector in turn: a single tuple if
+/// the page is constant for that subvector (`constant[subvector][page]`), otherwise a tuple per element.
+fn unpack_pages<T: Copy + Default>(
+    raw: &[T],
+    size: usize,
+    packing: &[usize],
+    page_size: usize,
+    constant: &[Vec<bool>],
+    count: usize,
+) -> Option<Vec<T>> {
+    // constant pages store a single tuple, so the data can't expand more than this
+    let len = count.checked_mul(size).filter(|&len| len <= raw.len().saturating_mul(page_size))?;
+    let mut values = vec![T::default(); len];
+    let mut src = 0;
+    for page in 0..count.div_ceil(page_size) {
+        let elements = page * page_size..count.min((page + 1) * page_size);
+        let mut offset = 0;
+        for (i, &n) in packing.iter().enumerate() {
+            let is_constant = constant.get(i).and_then(|flags| flags.get(page)).copied().unwrap_or(false);
+            for e in elements.clone() {
+                let tuple = raw.get(src..src + n)?;
+                values[e * size + offset..][..n].copy_from_slice(tuple);
+                if !is_constant {
+                    src += n;
+                }
+            }
+            if is_constant {
+                src += n;
+            }
+            offset += n;
+        }
+    }
+    (src == raw.len()).then_some(values)
+}
+
+/// Reads `rawpagedata`, and expands it to a tuple per element (see `unpack_pages`).
+fn read_paged_values(
+    p: &mut ParserImpl,
+    storage_kind: StorageKind,
+    size: usize,
+    packing: &[usize],
+    page_size: usize,
+    constant: &[Vec<bool>],
+    count: usize,
+) -> Result<AttributeStorage, Error> {
+    let packing = if packing.is_empty() { &[size][..] } else { packing };
+    if page_size == 0 || packing.iter().sum::<usize>() != size {
+        let message = format!("invalid paging (page size {page_size}, packing {packing:?}, size {size})");
+        return Err(p.error(message, None));
+    }
+    let mut raw = AttributeStorage::new(storage_kind);
+    read_array!(p => raw.read_element(p)?);
+    let values = match raw {
+        AttributeStorage::FpReal32(v) => {
+            unpack_pages(&v, size, packing, page_size, constant, count).map(AttributeStorage::FpReal32)
+        }
+        AttributeStorage::FpReal64(v) => {
+            unpack_pages(&v, size, packing, page_size, constant, count).map(AttributeStorage::FpReal64)
+        }
+        AttributeStorage::Int32(v) => unpack_pages(&v, size, packing, page_size, constant, count).map(AttributeStorage::Int32),
+        AttributeStorage::Int64(v) => unpack_pages(&v, size, packing, page_size, constant, count).map(AttributeStorage::Int64),
+    };
+    values.ok_or_else(|| p.error(format!("paged data doesn't match {count} elements of size {size}"), None))
+}
+
+/// Reads the constant page flags of a subvector, stored as booleans or integers.
+fn read_page_flags(p: &mut ParserImpl) -> Result<Vec<bool>, Error> {
+    let mut flags = vec![];
+    p.read_array(|p| {
+        while let Some(e) = p.next() {
+            flags.push(match e {
+                Event::Boolean(b) => b,
+                e => e.as_integer().ok_or_else(|| p.unexpected("a page flag", &e))? != 0,
+            });
+        }
+        Ok(())
+    })?;
+    Ok(flags)
+}
+
 fn read_topology(p: &mut ParserImpl, geo: &mut Geo) -> Result<(), Error> {
     p.read_array(|p| match p.str()?.as_str() {
         "pointref" => p.read_array(|p| match p.str()?.as_str() {
             "indices" => p.read_array(|p| {
                 while let Some(e) = p.next() {
-                    geo.topology.push(e.as_integer().ok_or(Malformed)? as u32);
+                    geo.topology.push(e.as_integer().ok_or_else(|| p.unexpected("a point index", &e))? as u32);
                 }
                 Ok(())
             }),
-            _ => Err(Malformed),
+            key => Err(p.error(format!("unexpected key `{key}`, expected `indices`"), None)),
         }),
-        _ => Err(Malformed),
+        key => Err(p.error(format!("unexpected key `{key}`, expected `pointref`"), None)),
+    })
+}
+
+/// Reads the options of an attribute (`{"name": {"type": ..., "value": ...}, ...}`).
+fn read_attribute_options(p: &mut ParserImpl, type_info: &mut TypeInfo, options: &mut Vec<(SmolStr, String)>) -> Result<(), Error> {
+    p.read_map(|p, name| {
+        let mut value = None;
+        read_map! {p,
+            "value" => {
+                value = match p.scalar() {
+                    Some(Event::String(s)) => Some(s),
+                    Some(Event::Float(f)) => Some(f.to_string()),
+                    Some(Event::Integer(i)) => Some(i.to_string()),
+                    Some(Event::Boolean(b)) => Some(b.to_string()),
+                    _ => None,
+                };
+            }
+        }
+        match (name, value) {
+            ("type", Some(value)) => *type_info = TypeInfo::from_name(&value),
+            (name, Some(value)) => options.push((name.into(), value)),
+            _ => {}
+        }
+        Ok(())
     })
 }
 
-fn read_point_attribute(p: &mut ParserImpl) -> Result<Attribute, Error> {
+/// Reads the default value of an attribute (`["size", 1, "storage", "fpreal64", "values", [0]]`).
+fn read_attribute_defaults(p: &mut ParserImpl) -> Result<Vec<f64>, Error> {
+    let mut defaults = vec![];
+    read_kvarray! {p,
+        "values" => {
+            read_array!(p => defaults.push(p.float()?));
+        }
+    }
+    Ok(defaults)
+}
+
+/// Reads an attribute of `count` elements.
+fn read_point_attribute(p: &mut ParserImpl, count: usize) -> Result<Attribute, Error> {
     let mut name = SmolStr::default();
+    let mut scope = SmolStr::new_inline("public");
+    let mut type_info = TypeInfo::None;
+    let mut options = vec![];
+    let mut defaults = vec![];
     let mut storage = None;
     let mut size = 0;
     let mut storage_kind = StorageKind::Int32;
+    let mut packing = vec![];
+    let mut page_size = 0;
+    let mut constant_pages = vec![];
 
     //eprintln!("read_point_attribute metadata");
 
@@ -154,17 +300,27 @@ fn read_point_attribute(p: &mut ParserImpl) -> Result<Attribute, Error> {
         "name" => {
             name = p.str()?.into();
         }
+        "scope" => {
+            scope = p.str()?.into();
+        }
+        "options" => {
+            read_attribute_options(p, &mut type_info, &mut options)?;
+        }
     }
 
     //eprintln!("read_point_attribute data");
     read_kvarray! {p,
+        "defaults" => {
+            defaults = read_attribute_defaults(p)?;
+        }
         "values" => {
             read_kvarray!(p,
                 "size" => {
                     size = p.integer()? as usize;
                 }
                 "storage" => {
-                    storage_kind = StorageKind::parse(&p.str()?)?;
+                    let kind = p.str()?;
+                    storage_kind = StorageKind::parse(p, &kind)?;
                 }
                 "arrays" => {
                     storage = Some(AttributeStorage::new(storage_kind));
@@ -183,6 +339,26 @@ fn read_point_attribute(p: &mut ParserImpl) -> Result<Attribute, Error> {
                         }
                     }
                 }
+                "packing" => {
+                    packing = p.read_int32_array()?.into_iter().map(|n| n.max(0) as usize).collect();
+                }
+                "pagesize" => {
+                    page_size = p.integer()?.max(0) as usize;
+                }
+                "constantpageflags" => {
+                    read_array!(p => constant_pages.push(read_page_flags(p)?));
+                }
+                "rawpagedata" => {
+                    storage = Some(read_paged_values(
+                        p,
+                        storage_kind,
+                        size,
+                        &packing,
+                        page_size,
+                        &constant_pages,
+                        count,
+                    )?);
+                }
             );
         }
     }
@@ -190,13 +366,199 @@ fn read_point_attribute(p: &mut ParserImpl) -> Result<Attribute, Error> {
     p.end_array()?;
 
     let Some(storage) = storage else {
-        return Err(Error::Malformed);
+        return Err(p.error(format!("attribute `{name}` has no values"), None));
     };
-    Ok(Attribute { name, size, storage })
+    Ok(Attribute {
+        name,
+        size,
+        storage,
+        scope,
+        type_info,
+        options,
+        defaults,
+    })
 }
 
 enum PrimType {
     Run,
+    /// Native Houdini volume.
+    Volume,
+    /// VDB primitive, with the grid embedded in the file.
+    Vdb,
+    /// Packed primitive referencing a file on disk.
+    PackedDisk,
+}
+
+/// Size of the tiles of a Houdini volume, along each axis.
+const VOLUME_TILE_SIZE: u32 = 16;
+
+/// Maximum number of voxels of a dense volume (4 GiB of voxel data).
+const MAX_VOXEL_COUNT: usize = 1 << 30;
+
+/// Returns the number of voxels of a volume, or an error if the resolution is too large.
+fn voxel_count(p: &ParserImpl, resolution: [u32; 3]) -> Result<usize, Error> {
+    let [rx, ry, rz] = resolution;
+    (rx as usize)
+        .checked_mul(ry as usize)
+        .and_then(|n| n.checked_mul(rz as usize))
+        .filter(|&n| n <= MAX_VOXEL_COUNT)
+        .ok_or_else(|| p.error(format!("volume resolution {rx}x{ry}x{rz} is too large"), None))
+}
+
+/// Reads voxel data in the tiled format (`["tiledarray", [...]]`, the leading string already consumed).
+///
+/// Tiles are 16x16x16 (smaller at the upper edges), ordered X-fastest. Each tile holds either a
+/// single constant value or all of its voxels, X-fastest.
+fn read_tiled_voxels(p: &mut ParserImpl, resolution: [u32; 3], voxels: &mut Vec<f32>) -> Result<(), Error> {
+    let [rx, ry, rz] = resolution;
+    let count = voxel_count(p, resolution)?;
+    voxels.clear();
+    voxels.resize(count, 0.0);
+    let tile_counts = resolution.map(|r| r.div_ceil(VOLUME_TILE_SIZE));
+    let tile_count = tile_counts[0] * tile_counts[1] * tile_counts[2];
+    let mut tile_index = 0;
+
+    read_kvarray! {p,
+        "tiles" => {
+            read_array! {p =>
+                {
+                    if tile_index >= tile_count {
+                        return Err(p.error(format!("too many tiles for a {rx}x{ry}x{rz} volume"), None));
+                    }
+                    let mut compression = 0;
+                    let mut data = vec![];
+                    read_kvarray! {p,
+                        "compression" => {
+                            compression = p.integer()?;
+                        }
+                        "value" => {
+                            data = vec![p.float()? as f32];
+                        }
+                        "data" => {
+                            data = p.read_fp32_array()?;
+                        }
+                    }
+
+                    let tx = tile_index % tile_counts[0];
+                    let ty = (tile_index / tile_counts[0]) % tile_counts[1];
+                    let tz = tile_index / (tile_counts[0] * tile_counts[1]);
+                    let origin = [tx * VOLUME_TILE_SIZE, ty * VOLUME_TILE_SIZE, tz * VOLUME_TILE_SIZE];
+                    let size = [0, 1, 2].map(|i| VOLUME_TILE_SIZE.min(resolution[i] - origin[i]));
+                    let voxel_count = (size[0] * size[1] * size[2]) as usize;
+
+                    if data.len() == 1 || data.len() == voxel_count {
+                        let mut i = 0;
+                        for z in 0..size[2] {
+                            for y in 0..size[1] {
+                                for x in 0..size[0] {
+                                    let dst = (origin[0] + x) + rx * ((origin[1] + y) + ry * (origin[2] + z));
+                                    voxels[dst as usize] = data[i.min(data.len() - 1)];
+                                    i += 1;
+                                }
+                            }
+                        }
+                    } else if p.lenient() {
+                        p.warn(format!("volume tile {tile_index} uses an unsupported compression ({compression}), filled with zeros"));
+                    } else {
+                        return Err(p.error(format!("volume tile {tile_index} uses an unsupported compression ({compression})"), None));
+                    }
+                    tile_index += 1;
+                }
+            }
+        }
+    }
+
+    if tile_index != tile_count {
+        return Err(p.error(format!("expected {tile_count} tiles for a {rx}x{ry}x{rz} volume, got {tile_index}"), None));
+    }
+    Ok(())
+}
+
+/// Reads the `voxels` field of a native volume: either a flat array of values, or a tiled array.
+fn read_voxels(p: &mut ParserImpl, resolution: [u32; 3]) -> Result<Vec<f32>, Error> {
+    let mut voxels = vec![];
+    p.read_array(|p| {
+        let Some(first) = p.next() else { return Ok(()) };
+        match first {
+            Event::String(s) if s == "tiledarray" => read_tiled_voxels(p, resolution, &mut voxels),
+            e => {
+                voxels.push(e.as_float().ok_or_else(|| p.unexpected("a voxel value or `tiledarray`", &e))? as f32);
+                while let Some(e) = p.next() {
+                    voxels.push(e.as_float().ok_or_else(|| p.unexpected("a voxel value", &e))? as f32);
+                }
+                Ok(())
+            }
+        }
+    })?;
+    let [rx, ry, rz] = resolution;
+    let count = voxel_count(p, resolution)?;
+    if voxels.len() != count {
+        return Err(p.error(format!("expected {count} voxels for a {rx}x{ry}x{rz} volume, got {}", voxels.len()), None));
+    }
+    Ok(voxels)
+}
+
+/// Reads the data of a volume-like primitive.
+///
+/// Returns `None` for packed primitives that don't reference a VDB file.
+fn read_volume(p: &mut ParserImpl, prim_type: &PrimType) -> Result<Option<Volume>, Error> {
+    let mut vertex = None;
+    let mut transform = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+    let mut resolution = [0; 3];
+    let mut voxels = None;
+    let mut path = None;
+    let mut grid = None;
+
+    read_kvarray! {p,
+        "vertex" => {
+            vertex = p.read_int32_array()?.first().copied();
+        }
+        "transform" => {
+            let m = p.read_fp32_array()?;
+            transform = m.try_into().map_err(|m: Vec<f32>| p.error(format!("expected a 3x3 transform, got {} values", m.len()), None))?;
+        }
+        "res" => {
+            let res = p.read_int32_array()?;
+            let &[x, y, z] = res.as_slice() else {
+                return Err(p.error(format!("expected 3 resolution values, got {}", res.len()), None));
+            };
+            resolution = [x, y, z].map(|r| r.max(0) as u32);
+        }
+        "voxels" => {
+            voxels = Some(read_voxels(p, resolution)?);
+        }
+        "parameters" => {
+            read_map! {p,
+                "filename" => {
+                    path = Some(p.str()?);
+                }
+                "primname" | "gridname" => {
+                    grid = Some(p.str()?);
+                }
+            }
+        }
+    }
+
+    let data = match prim_type {
+        PrimType::Volume => VolumeData::Dense(voxels.unwrap_or_default()),
+        PrimType::Vdb => VolumeData::Vdb,
+        PrimType::PackedDisk => {
+            if !path.as_deref().is_some_and(|path| path.ends_with(".vdb")) {
+                return Ok(None);
+            }
+            VolumeData::VdbFile { path, grid }
+        }
+        PrimType::Run => unreachable!(),
+    };
+    let Some(vertex) = vertex else {
+        return Err(p.error("volume primitive has no vertex", None));
+    };
+    Ok(Some(Volume {
+        vertex,
+        transform,
+        resolution,
+        data,
+    }))
 }
 
 fn read_bezier_basis(p: &mut ParserImpl) -> Result<BezierBasis, Error> {
@@ -219,6 +581,7 @@ fn read_bezier_basis(p: &mut ParserImpl) -> Result<BezierBasis, Error> {
 
 enum PrimitiveRun {
     BezierRun(BezierRun),
+    PolygonRun(PolygonRun),
 }
 
 impl PrimitiveRun {
@@ -235,6 +598,14 @@ impl PrimitiveRun {
                     r.basis = PrimVar::Uniform(read_bezier_basis(p)?);
                 }
             },
+            PrimitiveRun::PolygonRun(r) => read_map! {p,
+                "vertex" => {
+                    r.vertices = PrimVar::Uniform(p.read_int32_array()?);
+                }
+                "closed" => {
+                    r.closed = PrimVar::Uniform(p.boolean()?);
+                }
+            },
         }
         Ok(())
     }
@@ -282,6 +653,40 @@ impl PrimitiveRun {
                     r.basis = PrimVar::Varying(basis);
                 }
             }
+            PrimitiveRun::PolygonRun(r) => {
+                let mut vertices = vec![];
+                let mut closed = vec![];
+
+                read_array! {p =>
+                    // array of primitives
+                    {
+                        r.count += 1;
+                        read_array!{p =>
+                            // array of fields in the primitive
+                            for f in fields {
+                                match f.as_str() {
+                                    "vertex" => {
+                                        vertices.push(p.read_int32_array()?);
+                                    }
+                                    "closed" => {
+                                        closed.push(p.boolean()?);
+                                    }
+                                    _ => {
+                                        p.skip();
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if !vertices.is_empty() {
+                    r.vertices = PrimVar::Varying(vertices);
+                }
+                if !closed.is_empty() {
+                    r.closed = PrimVar::Varying(closed);
+                }
+            }
         }
         Ok(())
     }
@@ -294,16 +699,24 @@ fn read_primitives(p: &mut ParserImpl, geo: &mut Geo) -> Result<(), Error> {
             let mut varying_fields = Vec::new();
             let mut primitive_run = None;
 
+            let mut type_name = String::new();
+
             read_kvarray! {p,
                 "type" => {
-                    match p.str()?.as_str() {
+                    type_name = p.str()?;
+                    match type_name.as_str() {
                         "run" => prim_type = Some(PrimType::Run),
+                        "Volume" => prim_type = Some(PrimType::Volume),
+                        "VDB" => prim_type = Some(PrimType::Vdb),
+                        "PackedDisk" => prim_type = Some(PrimType::PackedDisk),
                         _ => {}
                     }
                 }
                 "runtype" => {
-                    match p.str()?.as_str() {
+                    type_name = p.str()?;
+                    match type_name.as_str() {
                         "BezierCurve" => primitive_run = Some(PrimitiveRun::BezierRun(BezierRun::default())),
+                        "Poly" => primitive_run = Some(PrimitiveRun::PolygonRun(PolygonRun::default())),
                         _ => {}
                     }
                 }
@@ -311,21 +724,42 @@ fn read_primitives(p: &mut ParserImpl, geo: &mut Geo) -> Result<(), Error> {
                     read_array!(p => varying_fields.push(p.str()?.to_string()));
                 }
                 "uniformfields" => {
-                    let primitive_run = primitive_run.as_mut().ok_or(Malformed)?;
-                    primitive_run.read_uniform_fields(p)?;
+                    if let Some(primitive_run) = primitive_run.as_mut() {
+                        primitive_run.read_uniform_fields(p)?;
+                    } else {
+                        p.skip();
+                    }
                 }
             }
 
-            {
-                let primitive_run = primitive_run.as_mut().ok_or(Malformed)?;
-                primitive_run.read_varying_fields(&varying_fields, p)?;
+            if let Some(prim_type @ (PrimType::Volume | PrimType::Vdb | PrimType::PackedDisk)) = prim_type.as_ref() {
+                match read_volume(p, prim_type)? {
+                    Some(volume) => geo.primitives.push(Primitive::Volume(volume)),
+                    None if p.lenient() => p.warn("unsupported packed primitive (not a VDB file reference), skipped"),
+                    None => return Err(p.error("unsupported packed primitive (not a VDB file reference)", None)),
+                }
+                return Ok(());
             }
 
+            let Some(primitive_run) = primitive_run.as_mut() else {
+                let message = format!("unsupported primitive type `{type_name}`");
+                if p.lenient() {
+                    p.warn(format!("{message}, skipped"));
+                    // skip the primitive data
+                    p.skip();
+                    return Ok(());
+                }
+                return Err(p.error(message, None));
+            };
+            primitive_run.read_varying_fields(&varying_fields, p)?;
+
             match primitive_run {
-                Some(PrimitiveRun::BezierRun(r)) => {
-                    geo.primitives.push(Primitive::BezierRun(r));
+                PrimitiveRun::BezierRun(r) => {
+                    geo.primitives.push(Primitive::BezierRun(std::mem::take(r)));
+                }
+                PrimitiveRun::PolygonRun(r) => {
+                    geo.primitives.push(Primitive::PolygonRun(std::mem::take(r)));
                 }
-                _ => {}
             }
 
             Ok(())
@@ -338,12 +772,12 @@ fn read_attributes(p: &mut ParserImpl, geo: &mut Geo) -> Result<(), Error> {
     read_kvarray! {p,
         "pointattributes" => {
             read_array!(p => {
-                geo.point_attributes.push(read_point_attribute(p)?);
+                geo.point_attributes.push(read_point_attribute(p, geo.point_count)?);
             })
         }
         "primitiveattributes" => {
             read_array!(p => {
-                geo.primitive_attributes.push(read_point_attribute(p)?);
+                geo.primitive_attributes.push(read_point_attribute(p, geo.primitive_count)?);
             })
         }
     }
@@ -356,30 +790,100 @@ fn read_file(p: &mut ParserImpl) -> Result<Geo, Error> {
         "pointcount" => { geo.point_count = p.integer()? as usize}
         "vertexcount" => {geo.vertex_count = p.integer()? as usize}
         "primitivecount" =>{ geo.primitive_count = p.integer()? as usize}
-        "topology" => {read_topology(p, &mut geo)?}
-        "attributes" => {read_attributes(p, &mut geo)?}
-        "primitives" => {read_primitives(p, &mut geo)?}
+        "topology" => {
+            p.section = Section::Topology;
+            read_topology(p, &mut geo)?
+        }
+        "attributes" => {
+            p.section = Section::Attributes;
+            read_attributes(p, &mut geo)?
+        }
+        "primitives" => {
+            p.section = Section::Primitives;
+            read_primitives(p, &mut geo)?
+        }
     }
 
     // Sanity checks for the position attribute.
-    // TODO make this errors instead of panics
-    assert!(
-        geo.point_attributes.len() > 0,
-        "the geometry should contain at least one point attribute"
-    );
-    let positions = &geo.point_attributes[0];
-    assert_eq!(positions.name, "P", "the first point attribute should be the point position");
-    assert_eq!(positions.size, 3, "the position attribute should have 3 components");
-    assert!(positions.as_f32_slice().is_some(), "the position attribute should be fpreal32");
-    let positions_fp32 = positions.as_f32_slice().unwrap();
-    assert!(positions_fp32.len() % 3 == 0, "the number of positions should be a multiple of 3");
-    assert_eq!(positions_fp32.len(), geo.point_count * 3);
+    p.section = Section::Attributes;
+    let Some(positions) = geo.point_attributes.first() else {
+        return Err(p.error("the geometry should contain at least one point attribute", None));
+    };
+    if positions.name != "P" {
+        return Err(p.error(
+            format!("the first point attribute should be the point position `P`, got `{}`", positions.name),
+            None,
+        ));
+    }
+    if positions.size != 3 {
+        return Err(p.error(format!("the position attribute should have 3 components, got {}", positions.size), None));
+    }
+    // Double-precision positions are kept aside, and the position attribute is converted to fpreal32.
+    let positions = &mut geo.point_attributes[0];
+    match positions.storage {
+        AttributeStorage::FpReal32(_) => {}
+        AttributeStorage::FpReal64(ref data) => {
+            let positions_fp32 = data.iter().map(|&x| x as f32).collect();
+            geo.positions_f64 = Some(data.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect());
+            positions.storage = AttributeStorage::FpReal32(positions_fp32);
+        }
+        _ => return Err(p.error("the position attribute should be fpreal32 or fpreal64", None)),
+    }
+    let positions_len = geo.point_attributes[0].as_f32_slice().map_or(0, |data| data.len());
+    if positions_len != geo.point_count * 3 {
+        return Err(p.error(
+            format!(
+                "expected {} positions (from `pointcount`), got {}",
+                geo.point_count,
+                positions_len as f64 / 3.0
+            ),
+            None,
+        ));
+    }
 
     Ok(geo)
 }
 
-pub(crate) fn parse_json(str: &str) -> Result<Geo, Error> {
-    let mut parser = ParserImpl::new(str);
-    let geo = read_file(&mut parser)?;
-    Ok(geo)
+pub(crate) fn parse_json(str: &str, options: &ParseOptions) -> Result<(Geo, Vec<Warning>), Error> {
+    parse(Input::Json(str), options)
+}
+
+/// Returns whether the data starts like a binary geometry file.
+pub(crate) fn is_binary(data: &[u8]) -> bool {
+    data.first() == Some(&binary::JID_MAGIC)
+}
+
+pub(crate) fn parse_binary(data: &[u8], options: &ParseOptions) -> Result<(Geo, Vec<Warning>), Error> {
+    let input = BinaryInput::new(data).ok_or(Error::Malformed)?;
+    parse(Input::Binary(input), options)
+}
+
+fn parse(input: Input, options: &ParseOptions) -> Result<(Geo, Vec<Warning>), Error> {
+    let ctx = Rc::new(ParseContext {
+        lenient: options.lenient,
+        warnings: Default::default(),
+        tokens: Default::default(),
+    });
+    let mut parser = ParserImpl::new(input, ctx.clone());
+    let mut geo = read_file(&mut parser)?;
+    drop(parser);
+    let mut warnings = ctx.warnings.take();
+    if options.recenter {
+        geo.recenter();
+    }
+    match options.validation {
+        Validation::None => {}
+        Validation::Strict => {
+            if let Some(problem) = geo.validate().into_iter().next() {
+                return Err(Error::Parse(Box::new(ParseError {
+                    section: problem.section,
+                    path: problem.path,
+                    token: None,
+                    message: problem.message,
+                })));
+            }
+        }
+        Validation::Repair => warnings.extend(geo.repair()),
+    }
+    Ok((geo, warnings))
 }
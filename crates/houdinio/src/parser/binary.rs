@@ -0,0 +1,246 @@
+//! Binary geometry format (`.bgeo`).
+//!
+//! Binary files are Houdini's binary encoding of JSON: the same document as a `.geo` file, written as
+//! a stream of tagged values. Strings are usually interned (defined once as tokens, then referenced
+//! by id), and arrays of numbers are written as uniform arrays, without a tag for each element.
+use crate::parser::{json::ParseContext, Event, Peek};
+
+// Value tags (`UT_JID` in the HDK).
+const JID_NULL: u8 = 0x00;
+const JID_MAP_BEGIN: u8 = b'{';
+const JID_MAP_END: u8 = b'}';
+const JID_ARRAY_BEGIN: u8 = b'[';
+const JID_ARRAY_END: u8 = b']';
+const JID_BOOL: u8 = 0x10;
+const JID_INT8: u8 = 0x11;
+const JID_INT16: u8 = 0x12;
+const JID_INT32: u8 = 0x13;
+const JID_INT64: u8 = 0x14;
+const JID_REAL16: u8 = 0x18;
+const JID_REAL32: u8 = 0x19;
+const JID_REAL64: u8 = 0x1a;
+const JID_UINT8: u8 = 0x21;
+const JID_UINT16: u8 = 0x22;
+const JID_STRING: u8 = 0x27;
+const JID_FALSE: u8 = 0x30;
+const JID_TRUE: u8 = 0x31;
+const JID_TOKEN_DEF: u8 = 0x2b;
+const JID_TOKEN_REF: u8 = 0x26;
+const JID_TOKEN_UNDEF: u8 = 0x2d;
+const JID_UNIFORM_ARRAY: u8 = 0x40;
+const JID_KEY_SEPARATOR: u8 = b':';
+const JID_VALUE_SEPARATOR: u8 = b',';
+pub(crate) const JID_MAGIC: u8 = 0x7f;
+
+/// Magic number following `JID_MAGIC`, in the byte order of the file.
+const MAGIC: u32 = 0x624a534e;
+
+/// Uniform array being read: elements have no tag.
+#[derive(Copy, Clone, Debug)]
+struct UniformArray {
+    /// Tag of the elements.
+    jid: u8,
+    remaining: u64,
+    /// Current word of a bool array (bools are packed in 32-bit words), and the index of the next bit.
+    bits: u32,
+    bit: u32,
+}
+
+/// Binary input, positioned on the next value.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct BinaryInput<'a> {
+    data: &'a [u8],
+    /// Whether the file was written in big-endian byte order.
+    big_endian: bool,
+    uniform: Option<UniformArray>,
+}
+
+impl<'a> BinaryInput<'a> {
+    /// Checks the header of the file and returns the input positioned on the root value.
+    ///
+    /// Returns `None` if the data doesn't start with the binary JSON magic number.
+    pub(crate) fn new(data: &'a [u8]) -> Option<BinaryInput<'a>> {
+        let (&JID_MAGIC, rest) = data.split_first()? else { return None };
+        let magic: [u8; 4] = rest.get(..4)?.try_into().unwrap();
+        let big_endian = if u32::from_le_bytes(magic) == MAGIC {
+            false
+        } else if u32::from_be_bytes(magic) == MAGIC {
+            true
+        } else {
+            return None;
+        };
+        Some(BinaryInput {
+            data: &rest[4..],
+            big_endian,
+            uniform: None,
+        })
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes: [u8; N] = self.data.get(..N)?.try_into().unwrap();
+        self.data = &self.data[N..];
+        Some(if self.big_endian {
+            let mut bytes = bytes;
+            bytes.reverse();
+            bytes
+        } else {
+            bytes
+        })
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|[b]| b)
+    }
+
+    /// Reads a length or a token id: one byte below 0xf1, otherwise a byte giving the size of the
+    /// following integer.
+    fn length(&mut self) -> Option<u64> {
+        match self.u8()? {
+            n if n < 0xf1 => Some(n as u64),
+            0xf2 => self.bytes().map(|b| u16::from_le_bytes(b) as u64),
+            0xf4 => self.bytes().map(|b| u32::from_le_bytes(b) as u64),
+            0xf8 => self.bytes().map(u64::from_le_bytes),
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = usize::try_from(self.length()?).ok()?;
+        let bytes = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    /// Reads the value of a scalar with the specified tag.
+    fn scalar(&mut self, jid: u8, ctx: &ParseContext) -> Option<Event> {
+        let event = match jid {
+            JID_NULL => Event::Null,
+            JID_FALSE => Event::Boolean(false),
+            JID_TRUE => Event::Boolean(true),
+            JID_BOOL => Event::Boolean(self.u8()? != 0),
+            JID_INT8 => Event::Integer(i8::from_le_bytes(self.bytes()?) as i64),
+            JID_INT16 => Event::Integer(i16::from_le_bytes(self.bytes()?) as i64),
+            JID_INT32 => Event::Integer(i32::from_le_bytes(self.bytes()?) as i64),
+            JID_INT64 => Event::Integer(i64::from_le_bytes(self.bytes()?)),
+            JID_UINT8 => Event::Integer(self.u8()? as i64),
+            JID_UINT16 => Event::Integer(u16::from_le_bytes(self.bytes()?) as i64),
+            JID_REAL16 => Event::Float(half_to_f32(u16::from_le_bytes(self.bytes()?)) as f64),
+            JID_REAL32 => Event::Float(f32::from_le_bytes(self.bytes()?) as f64),
+            JID_REAL64 => Event::Float(f64::from_le_bytes(self.bytes()?)),
+            JID_STRING => Event::String(self.string()?),
+            JID_TOKEN_REF => {
+                let id = self.length()?;
+                match ctx.tokens.borrow().get(&id) {
+                    Some(s) => Event::String(s.clone()),
+                    None => Event::Invalid(format!("undefined string token {id}")),
+                }
+            }
+            // definitions that `peek` couldn't read
+            JID_TOKEN_DEF | JID_TOKEN_UNDEF => return None,
+            jid => Event::Invalid(format!("unknown value tag 0x{jid:02x}")),
+        };
+        Some(event)
+    }
+
+    /// Reads the next element of the uniform array being read.
+    fn uniform_element(&mut self, ctx: &ParseContext) -> Option<Event> {
+        let mut uniform = self.uniform?;
+        uniform.remaining -= 1;
+        let event = if uniform.jid == JID_BOOL {
+            if uniform.bit == 0 {
+                uniform.bits = u32::from_le_bytes(self.bytes()?);
+            }
+            let value = uniform.bits & (1 << uniform.bit) != 0;
+            uniform.bit = (uniform.bit + 1) % 32;
+            Event::Boolean(value)
+        } else {
+            self.scalar(uniform.jid, ctx)?
+        };
+        self.uniform = Some(uniform);
+        Some(event)
+    }
+
+    /// Returns the kind of the next value.
+    ///
+    /// String token definitions before the value are read and added to the context.
+    pub(crate) fn peek(&mut self, ctx: &ParseContext) -> Peek {
+        if let Some(ref uniform) = self.uniform {
+            return if uniform.remaining == 0 { Peek::End } else { Peek::Value };
+        }
+        loop {
+            match self.data.first() {
+                None => return Peek::Eof,
+                Some(&(JID_KEY_SEPARATOR | JID_VALUE_SEPARATOR)) => self.data = &self.data[1..],
+                Some(&JID_TOKEN_DEF) => {
+                    let mut input = *self;
+                    input.data = &input.data[1..];
+                    let Some((id, s)) = input.length().zip(input.string()) else {
+                        // truncated definition, reported by `read`
+                        return Peek::Value;
+                    };
+                    ctx.tokens.borrow_mut().insert(id, s);
+                    *self = input;
+                }
+                Some(&JID_TOKEN_UNDEF) => {
+                    let mut input = *self;
+                    input.data = &input.data[1..];
+                    let Some(id) = input.length() else { return Peek::Value };
+                    ctx.tokens.borrow_mut().remove(&id);
+                    *self = input;
+                }
+                Some(&(JID_ARRAY_BEGIN | JID_MAP_BEGIN | JID_UNIFORM_ARRAY)) => return Peek::Begin,
+                Some(&(JID_ARRAY_END | JID_MAP_END)) => return Peek::End,
+                Some(_) => return Peek::Value,
+            }
+        }
+    }
+
+    /// Reads the next value, after `peek` returned something else than `Peek::Eof`.
+    pub(crate) fn read(&mut self, ctx: &ParseContext) -> Event {
+        let event = if let Some(uniform) = self.uniform {
+            if uniform.remaining == 0 {
+                self.uniform = None;
+                Some(Event::EndArray)
+            } else {
+                self.uniform_element(ctx)
+            }
+        } else {
+            match self.u8() {
+                Some(JID_ARRAY_BEGIN) => Some(Event::BeginArray),
+                Some(JID_MAP_BEGIN) => Some(Event::BeginMap),
+                Some(JID_ARRAY_END) => Some(Event::EndArray),
+                Some(JID_MAP_END) => Some(Event::EndMap),
+                Some(JID_UNIFORM_ARRAY) => self.u8().zip(self.length()).map(|(jid, len)| {
+                    self.uniform = Some(UniformArray {
+                        jid,
+                        remaining: len,
+                        bits: 0,
+                        bit: 0,
+                    });
+                    Event::BeginArray
+                }),
+                Some(jid) => self.scalar(jid, ctx),
+                None => None,
+            }
+        };
+        event.unwrap_or_else(|| {
+            // can't resume after truncated data
+            self.data = &[];
+            self.uniform = None;
+            Event::Invalid("unexpected end of binary data".to_string())
+        })
+    }
+}
+
+/// Converts a half-precision float to single precision.
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 0x1f) as i32;
+    let mantissa = (h & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
@@ -1,5 +1,12 @@
 //! JSON geometry format
-use crate::{error::Error, parser::Event};
+//!
+//! The parser reads the JSON document as a stream of events. Binary files encode the same document,
+//! and are read by the same parser with a different input (see `binary`).
+use crate::{
+    error::{Error, ParseError, Section, Warning},
+    parser::{binary::BinaryInput, Event, Peek},
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum ParserState {
@@ -7,79 +14,207 @@ enum ParserState {
     Map,
 }
 
+/// State shared between a parser and its subparsers.
+#[derive(Default)]
+pub(crate) struct ParseContext {
+    /// Skip unsupported elements with a warning instead of failing.
+    pub(crate) lenient: bool,
+    pub(crate) warnings: RefCell<Vec<Warning>>,
+    /// Strings defined as tokens in binary files, by id.
+    pub(crate) tokens: RefCell<HashMap<u64, String>>,
+}
+
+/// Remaining input of a parser.
+#[derive(Copy, Clone)]
+pub(crate) enum Input<'a> {
+    Json(&'a str),
+    Binary(BinaryInput<'a>),
+}
+
+impl<'a> Input<'a> {
+    /// Skips separators and returns the kind of the next value.
+    fn peek(&mut self, ctx: &ParseContext) -> Peek {
+        match self {
+            Input::Json(data) => {
+                *data = data.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',' || c == ':');
+                match data.chars().next() {
+                    Some('[' | '{') => Peek::Begin,
+                    Some(']' | '}') => Peek::End,
+                    Some(_) => Peek::Value,
+                    None => Peek::Eof,
+                }
+            }
+            Input::Binary(input) => input.peek(ctx),
+        }
+    }
+
+    /// Reads the next value, after `peek` returned something else than `Peek::Eof`.
+    fn read(&mut self, ctx: &ParseContext) -> Event {
+        match self {
+            Input::Json(data) => read_json_value(data),
+            Input::Binary(input) => input.read(ctx),
+        }
+    }
+}
+
+/// Reads the next JSON token (a bracket or a scalar value), without leading whitespace or separators.
+fn read_json_value(data: &mut &str) -> Event {
+    let event = match data.chars().next() {
+        Some('[') => Event::BeginArray,
+        Some('{') => Event::BeginMap,
+        Some(']') => Event::EndArray,
+        Some('}') => Event::EndMap,
+        _ => {
+            let mut des = serde_json::Deserializer::from_str(data).into_iter();
+            let event = match des.next() {
+                Some(Ok(serde_json::Value::String(value))) => Event::String(value),
+                Some(Ok(serde_json::Value::Number(value))) => match value.as_f64() {
+                    Some(value) => Event::Float(value),
+                    None => Event::Invalid(format!("invalid number `{value}`")),
+                },
+                Some(Ok(serde_json::Value::Bool(value))) => Event::Boolean(value),
+                Some(Ok(serde_json::Value::Null)) => Event::Null,
+                Some(Ok(_)) => Event::Invalid("unexpected value".to_string()),
+                Some(Err(err)) => {
+                    // can't resume after a syntax error
+                    *data = "";
+                    return Event::Invalid(err.to_string());
+                }
+                None => Event::Invalid("unexpected value".to_string()),
+            };
+            *data = &data[des.byte_offset()..];
+            return event;
+        }
+    };
+    *data = &data[1..];
+    event
+}
+
 pub(crate) struct ParserImpl<'a> {
-    data: &'a str,
+    input: Input<'a>,
     state: Vec<ParserState>,
     depth: usize,
+    ctx: Rc<ParseContext>,
+    /// Path of the array or map being parsed.
+    path: String,
+    /// Number of values read so far in the array or map.
+    count: usize,
+    /// Current key, in key-value arrays and maps.
+    key: Option<String>,
+    /// Section of the file being parsed, for diagnostics.
+    pub(crate) section: Section,
+}
+
+/// Formats a token for diagnostics.
+fn describe_token(event: &Event) -> String {
+    match event {
+        Event::Integer(i) => format!("integer `{i}`"),
+        Event::Float(f) => format!("number `{f}`"),
+        Event::String(s) if s.chars().count() > 40 => format!("string \"{}...\"", s.chars().take(40).collect::<String>()),
+        Event::String(s) => format!("string \"{s}\""),
+        Event::Boolean(b) => format!("boolean `{b}`"),
+        Event::Null => "`null`".to_string(),
+        Event::BeginArray => "`[`".to_string(),
+        Event::EndArray => "`]`".to_string(),
+        Event::BeginMap => "`{`".to_string(),
+        Event::EndMap => "`}`".to_string(),
+        Event::Invalid(err) => format!("invalid JSON ({err})"),
+    }
 }
 
 impl<'a> ParserImpl<'a> {
-    pub(crate) fn new(data: &'a str) -> Self {
+    pub(crate) fn new(input: Input<'a>, ctx: Rc<ParseContext>) -> Self {
         Self {
-            data,
+            input,
             state: Vec::new(),
             depth: 0,
+            ctx,
+            path: String::new(),
+            count: 0,
+            key: None,
+            section: Section::Header,
+        }
+    }
+
+    /// Creates a parser for the contents of the array or map that was just opened.
+    fn subparser(&self) -> ParserImpl<'a> {
+        ParserImpl {
+            input: self.input,
+            state: Vec::new(),
+            depth: self.depth + 1,
+            ctx: self.ctx.clone(),
+            // the root array of the document has an empty path
+            path: if self.depth == 0 { String::new() } else { self.current_path() },
+            count: 0,
+            key: None,
+            section: self.section,
         }
     }
 
-    fn skip_ws(&mut self) {
-        self.data = self.data.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    /// Returns the path to the last value read.
+    pub(crate) fn current_path(&self) -> String {
+        match self.key {
+            Some(ref key) => format!("{}/{}", self.path, key),
+            None if self.count > 0 => format!("{}/{}", self.path, self.count - 1),
+            None => self.path.clone(),
+        }
+    }
+
+    /// Returns an error pointing to the last value read.
+    pub(crate) fn error(&self, message: impl Into<String>, token: Option<&Event>) -> Error {
+        Error::Parse(Box::new(ParseError {
+            section: self.section,
+            path: self.current_path(),
+            token: token.map(describe_token),
+            message: message.into(),
+        }))
+    }
+
+    /// Returns an error for an unexpected token.
+    pub(crate) fn unexpected(&self, expected: &str, token: &Event) -> Error {
+        self.error(format!("expected {expected}"), Some(token))
+    }
+
+    pub(crate) fn early_eof(&self) -> Error {
+        self.error("unexpected end of input", None)
+    }
+
+    pub(crate) fn lenient(&self) -> bool {
+        self.ctx.lenient
+    }
+
+    /// Records a warning about the array or map being parsed.
+    pub(crate) fn warn(&self, message: impl Into<String>) {
+        self.ctx.warnings.borrow_mut().push(Warning {
+            section: self.section,
+            path: self.path.clone(),
+            message: message.into(),
+        });
     }
 
     pub(crate) fn next(&mut self) -> Option<Event> {
-        self.skip_ws();
-        let n = match self.data.chars().next() {
-            Some('[') => {
-                self.data = &self.data[1..];
-                self.state.push(ParserState::Array);
-                Some(Event::BeginArray)
-            }
-            Some('{') => {
-                self.data = &self.data[1..];
-                self.state.push(ParserState::Map);
-                Some(Event::BeginMap)
-            }
-            Some(']') => {
-                self.state.pop()?;
-                self.data = &self.data[1..];
-                Some(Event::EndArray)
+        let top_level = self.state.is_empty();
+        let n = match self.input.peek(&self.ctx) {
+            Peek::Begin => {
+                let event = self.input.read(&self.ctx);
+                self.state.push(if event == Event::BeginMap {
+                    ParserState::Map
+                } else {
+                    ParserState::Array
+                });
+                Some(event)
             }
-            Some('}') => {
+            Peek::End => {
+                // end of the array or map of this parser: left to the parent parser
                 self.state.pop()?;
-                self.data = &self.data[1..];
-                Some(Event::EndMap)
-            }
-            Some(',') => {
-                // TODO maybe do some basic syntax checking here,
-                // but in general we assume that the input is well-formed.
-                self.data = &self.data[1..];
-                return self.next();
+                Some(self.input.read(&self.ctx))
             }
-            Some(':') => {
-                // TODO same as above
-                self.data = &self.data[1..];
-                return self.next();
-            }
-            Some(_) => {
-                let mut des = serde_json::Deserializer::from_str(self.data).into_iter();
-                let event = match des.next() {
-                    Some(Ok(serde_json::Value::String(value))) => Event::String(value),
-                    Some(Ok(serde_json::Value::Number(value))) => {
-                        Event::Float(value.as_f64().expect("invalid number in json repr"))
-                    }
-                    Some(Ok(serde_json::Value::Bool(value))) => Event::Boolean(value),
-                    Some(Ok(serde_json::Value::Null)) => {
-                        panic!("null");
-                    }
-                    _ => {
-                        panic!("unexpected json value");
-                    }
-                };
-                self.data = &self.data[des.byte_offset()..];
-                Some(event)
-            }
-            None => None,
+            Peek::Value => Some(self.input.read(&self.ctx)),
+            Peek::Eof => None,
         };
+        if top_level && !matches!(n, None | Some(Event::EndArray | Event::EndMap)) {
+            self.count += 1;
+        }
         //eprintln!("next: {:?}", n);
         n
     }
@@ -131,65 +266,83 @@ impl<'a> ParserImpl<'a> {
         }
     }*/
 
+    /// Reads the next value if it's a string, number, boolean or null.
+    ///
+    /// Arrays and maps are skipped, and `None` is returned.
+    pub(crate) fn scalar(&mut self) -> Option<Event> {
+        if self.input.peek(&self.ctx) == Peek::Begin {
+            self.skip();
+            return None;
+        }
+        self.next()
+    }
+
     /// Reads a string from the input.
     pub(crate) fn str(&mut self) -> Result<String, Error> {
-        match self.next().ok_or(Error::EarlyEof)? {
+        match self.next().ok_or_else(|| self.early_eof())? {
             Event::String(s) => Ok(s),
-            _ => Err(Error::Malformed),
+            e => Err(self.unexpected("a string", &e)),
         }
     }
 
     /// Expects the beginning of an array.
     pub(crate) fn begin_array(&mut self) -> Result<(), Error> {
-        match self.next().ok_or(Error::EarlyEof)? {
+        match self.next().ok_or_else(|| self.early_eof())? {
             Event::BeginArray => Ok(()),
-            _ => Err(Error::Malformed),
+            e => Err(self.unexpected("an array", &e)),
         }
     }
 
     /// Expects the end of an array.
     pub(crate) fn end_array(&mut self) -> Result<(), Error> {
-        match self.next().ok_or(Error::EarlyEof)? {
+        match self.next().ok_or_else(|| self.early_eof())? {
             Event::EndArray => Ok(()),
-            _ => Err(Error::Malformed),
+            e => Err(self.unexpected("the end of the array", &e)),
         }
     }
 
     fn begin_map(&mut self) -> Result<(), Error> {
-        match self.next().ok_or(Error::EarlyEof)? {
+        match self.next().ok_or_else(|| self.early_eof())? {
             Event::BeginMap => Ok(()),
-            _ => Err(Error::Malformed),
+            e => Err(self.unexpected("a map", &e)),
         }
     }
 
     fn end_map(&mut self) -> Result<(), Error> {
-        match self.next().ok_or(Error::EarlyEof)? {
+        match self.next().ok_or_else(|| self.early_eof())? {
             Event::EndMap => Ok(()),
-            _ => Err(Error::Malformed),
+            e => Err(self.unexpected("the end of the map", &e)),
         }
     }
 
     pub(crate) fn eof(&mut self) -> bool {
-        self.skip_ws();
-        match self.data.chars().next() {
-            Some(']' | '}') if self.state.is_empty() => true,
-            None => true,
+        match self.input.peek(&self.ctx) {
+            Peek::End => self.state.is_empty(),
+            Peek::Eof => true,
             _ => false,
         }
     }
 
     pub(crate) fn integer(&mut self) -> Result<i64, Error> {
-        match self.next().ok_or(Error::EarlyEof)? {
+        match self.next().ok_or_else(|| self.early_eof())? {
             Event::Float(f) => Ok(f as i64),
             Event::Integer(i) => Ok(i),
-            _ => Err(Error::Malformed),
+            e => Err(self.unexpected("an integer", &e)),
+        }
+    }
+
+    pub(crate) fn float(&mut self) -> Result<f64, Error> {
+        match self.next().ok_or_else(|| self.early_eof())? {
+            Event::Float(f) => Ok(f),
+            Event::Integer(i) => Ok(i as f64),
+            e => Err(self.unexpected("a number", &e)),
         }
     }
 
     pub(crate) fn boolean(&mut self) -> Result<bool, Error> {
-        match self.next().ok_or(Error::EarlyEof)? {
+        match self.next().ok_or_else(|| self.early_eof())? {
             Event::Boolean(b) => Ok(b),
-            _ => Err(Error::Malformed),
+            e => Err(self.unexpected("a boolean", &e)),
         }
     }
 
@@ -197,7 +350,7 @@ impl<'a> ParserImpl<'a> {
         let mut v = Vec::new();
         self.read_array(|p| {
             while let Some(e) = p.next() {
-                v.push(e.as_integer().ok_or(Error::Malformed)? as i32);
+                v.push(e.as_integer().ok_or_else(|| p.unexpected("an integer", &e))? as i32);
             }
             Ok(())
         })?;
@@ -208,7 +361,7 @@ impl<'a> ParserImpl<'a> {
         let mut v = Vec::new();
         self.read_array(|p| {
             while let Some(e) = p.next() {
-                v.push(e.as_float().ok_or(Error::Malformed)? as f32);
+                v.push(e.as_float().ok_or_else(|| p.unexpected("a number", &e))? as f32);
             }
             Ok(())
         })?;
@@ -221,13 +374,9 @@ impl<'a> ParserImpl<'a> {
     {
         //eprintln!("{}array", "  ".repeat(self.depth));
         self.begin_array()?;
-        let mut subparser = ParserImpl {
-            data: self.data,
-            state: Vec::new(),
-            depth: self.depth + 1,
-        };
+        let mut subparser = self.subparser();
         f(&mut subparser)?;
-        self.data = subparser.data;
+        self.input = subparser.input;
         self.end_array()?;
         Ok(())
     }
@@ -238,17 +387,14 @@ impl<'a> ParserImpl<'a> {
     {
         //eprintln!("{}kvarray", "  ".repeat(self.depth));
         self.begin_array()?;
-        let mut subparser = ParserImpl {
-            data: self.data,
-            state: Vec::new(),
-            depth: self.depth + 1,
-        };
+        let mut subparser = self.subparser();
         while let Some(e) = subparser.next() {
-            let key = e.as_str().ok_or(Error::Malformed)?;
+            let key = e.as_str().ok_or_else(|| subparser.unexpected("a key", &e))?;
             //eprintln!("{}key: {}", "  ".repeat(subparser.depth), key);
+            subparser.key = Some(key.to_string());
             f(&mut subparser, key)?;
         }
-        self.data = subparser.data;
+        self.input = subparser.input;
         self.end_array()?;
         Ok(())
     }
@@ -259,17 +405,14 @@ impl<'a> ParserImpl<'a> {
     {
         //eprintln!("{}map", "  ".repeat(self.depth));
         self.begin_map()?;
-        let mut subparser = ParserImpl {
-            data: self.data,
-            state: Vec::new(),
-            depth: self.depth + 1,
-        };
+        let mut subparser = self.subparser();
         while let Some(e) = subparser.next() {
-            let key = e.as_str().ok_or(Error::Malformed)?;
+            let key = e.as_str().ok_or_else(|| subparser.unexpected("a key", &e))?;
             //eprintln!("{}key: {}", "  ".repeat(subparser.depth), key);
+            subparser.key = Some(key.to_string());
             f(&mut subparser, key)?;
         }
-        self.data = subparser.data;
+        self.input = subparser.input;
         self.end_map()?;
         Ok(())
     }
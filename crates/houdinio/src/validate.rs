@@ -0,0 +1,191 @@
+//! Topology validation.
+//!
+//! Checks that the primitives only reference existing vertices and points, and that they have
+//! enough vertices to be drawn. Invalid primitives can be dropped so that the geometry can be used
+//! without bounds checks.
+use crate::{BezierBasis, Geo, PrimVar, Primitive, Section, Warning};
+
+/// Basis order assumed for bezier runs that don't specify one (cubic).
+const DEFAULT_BEZIER_ORDER: u32 = 4;
+
+/// What to do after parsing if the topology is invalid.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Validation {
+    /// Don't check the topology.
+    #[default]
+    None,
+    /// Fail with an error describing the first problem.
+    Strict,
+    /// Drop the invalid primitives, and report them as warnings.
+    Repair,
+}
+
+/// Returns the value of a primitive variable for the element at `index`.
+fn get<T>(var: &PrimVar<T>, index: usize) -> Option<&T> {
+    match var {
+        PrimVar::Uniform(value) => Some(value),
+        PrimVar::Varying(values) => values.get(index),
+    }
+}
+
+/// Removes the values of the elements that are not kept.
+fn retain<T>(var: &mut PrimVar<T>, keep: &[bool]) {
+    if let PrimVar::Varying(values) = var {
+        let mut i = 0;
+        values.retain(|_| {
+            i += 1;
+            keep.get(i - 1).copied().unwrap_or(false)
+        });
+    }
+}
+
+fn warning(primitive: usize, message: String) -> Warning {
+    Warning {
+        section: Section::Primitives,
+        path: format!("/primitives/{primitive}"),
+        message,
+    }
+}
+
+impl Geo {
+    /// Checks that the vertices of a primitive are valid, returns a description of the problem otherwise.
+    fn check_vertices(&self, vertices: &[i32]) -> Result<(), String> {
+        for &v in vertices {
+            let Some(&point) = usize::try_from(v).ok().and_then(|v| self.topology.get(v)) else {
+                return Err(format!("vertex {v} out of range ({} vertices)", self.topology.len()));
+            };
+            if point as usize >= self.point_count {
+                return Err(format!("vertex {v} references point {point}, out of range ({} points)", self.point_count));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks each element of each primitive, returning one flag per element (`true` if valid),
+    /// and the problems found.
+    fn check_primitives(&self) -> (Vec<Vec<bool>>, Vec<Warning>) {
+        let mut warnings = vec![];
+        let mut valid = vec![];
+        for (prim_index, prim) in self.primitives.iter().enumerate() {
+            let mut check = |element: usize, result: Result<(), String>| match result {
+                Ok(()) => true,
+                Err(message) => {
+                    warnings.push(warning(prim_index, format!("primitive {element}: {message}")));
+                    false
+                }
+            };
+            let flags = match prim {
+                Primitive::BezierRun(run) => (0..run.count)
+                    .map(|i| {
+                        let result = (|| {
+                            let vertices = get(&run.vertices, i).ok_or("missing vertices")?;
+                            if let PrimVar::Varying(ref closed) = run.closed {
+                                // an empty array means that the flag wasn't specified
+                                if !closed.is_empty() && closed.len() <= i {
+                                    return Err("missing closed flag".to_string());
+                                }
+                            }
+                            let order = match run.basis {
+                                // no basis specified
+                                PrimVar::Varying(ref b) if b.is_empty() => DEFAULT_BEZIER_ORDER,
+                                _ => get(&run.basis, i).ok_or("missing basis")?.order,
+                            };
+                            self.check_vertices(vertices)?;
+                            if vertices.len() < order.max(2) as usize {
+                                return Err(format!("degenerate curve ({} vertices, order {order})", vertices.len()));
+                            }
+                            Ok(())
+                        })();
+                        check(i, result)
+                    })
+                    .collect(),
+                Primitive::PolygonRun(run) => (0..run.count)
+                    .map(|i| {
+                        let result = (|| {
+                            let vertices = get(&run.vertices, i).ok_or("missing vertices")?;
+                            let closed = *get(&run.closed, i).ok_or("missing closed flag")?;
+                            self.check_vertices(vertices)?;
+                            let min_vertices = if closed { 3 } else { 2 };
+                            if vertices.len() < min_vertices {
+                                return Err(format!("degenerate polygon ({} vertices)", vertices.len()));
+                            }
+                            Ok(())
+                        })();
+                        check(i, result)
+                    })
+                    .collect(),
+                Primitive::Volume(volume) => vec![check(0, self.check_vertices(&[volume.vertex]))],
+            };
+            valid.push(flags);
+        }
+        (valid, warnings)
+    }
+
+    /// Checks the topology, and returns the problems found.
+    ///
+    /// Reports vertices and points out of range, and curves or polygons with too few vertices.
+    pub fn validate(&self) -> Vec<Warning> {
+        let mut warnings = vec![];
+        if let Some((i, point)) = self
+            .topology
+            .iter()
+            .enumerate()
+            .find(|(_, &point)| point as usize >= self.point_count)
+        {
+            warnings.push(Warning {
+                section: Section::Topology,
+                path: format!("/topology/pointref/indices/{i}"),
+                message: format!("point {point} out of range ({} points)", self.point_count),
+            });
+        }
+        warnings.extend(self.check_primitives().1);
+        warnings
+    }
+
+    /// Removes the invalid primitives, and returns the problems found.
+    ///
+    /// After this, all primitives can be iterated over and their vertices and points indexed without
+    /// bounds checks failing.
+    pub fn repair(&mut self) -> Vec<Warning> {
+        let (valid, warnings) = self.check_primitives();
+        let mut valid = valid.into_iter();
+        self.primitives.retain_mut(|prim| {
+            let keep = valid.next().unwrap();
+            match prim {
+                Primitive::BezierRun(run) => {
+                    if matches!(run.basis, PrimVar::Varying(ref b) if b.is_empty()) {
+                        run.basis = PrimVar::Uniform(BezierBasis {
+                            order: DEFAULT_BEZIER_ORDER,
+                            knots: vec![],
+                        });
+                    }
+                    if matches!(run.closed, PrimVar::Varying(ref c) if c.is_empty()) {
+                        run.closed = PrimVar::Uniform(false);
+                    }
+                    retain(&mut run.vertices, &keep);
+                    retain(&mut run.closed, &keep);
+                    retain(&mut run.basis, &keep);
+                    run.count = keep.iter().filter(|k| **k).count();
+                    run.count > 0
+                }
+                Primitive::PolygonRun(run) => {
+                    retain(&mut run.vertices, &keep);
+                    retain(&mut run.closed, &keep);
+                    run.count = keep.iter().filter(|k| **k).count();
+                    run.count > 0
+                }
+                Primitive::Volume(_) => keep[0],
+            }
+        });
+        self.primitive_count = self
+            .primitives
+            .iter()
+            .map(|prim| match prim {
+                Primitive::BezierRun(run) => run.count,
+                Primitive::PolygonRun(run) => run.count,
+                Primitive::Volume(_) => 1,
+            })
+            .sum();
+        warnings
+    }
+}
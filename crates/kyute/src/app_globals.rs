@@ -1,15 +1,26 @@
 use crate::backend::ApplicationBackend;
-use std::cell::RefCell;
+use crate::preferences::Preferences;
+use crate::reactive::Property;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use std::time::Duration;
+use tokio::sync::watch;
 
 //==================================================================================================
 
+/// Double-click time used in headless mode.
+const DEFAULT_DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
+
 /// Application globals.
 ///
 /// Stuff that would be too complicated/impractical/ugly to carry and pass around as parameters.
 pub struct AppGlobals {
-    pub(crate) backend: ApplicationBackend,
+    /// Platform backend, `None` in headless mode.
+    backend: Option<ApplicationBackend>,
+    /// Accessibility preferences.
+    preferences: Property<Preferences>,
+    /// Preferences of the system when they were last read.
+    system_preferences: Cell<Preferences>,
 }
 
 thread_local! {
@@ -21,12 +32,46 @@ impl AppGlobals {
     pub fn new() -> Rc<AppGlobals> {
         // TODO: make sure that we're not making multiple applications
         let backend = ApplicationBackend::new();
-        let app = Rc::new(AppGlobals { backend });
+        let preferences = backend.preferences();
+        Self::install(AppGlobals {
+            backend: Some(backend),
+            preferences: Property::new(preferences),
+            system_preferences: Cell::new(preferences),
+        })
+    }
+
+    /// Creates application globals without a platform backend.
+    ///
+    /// In this mode, no windows or compositor layers can be created, but element trees can still be
+    /// laid out and painted offscreen (see `testing::render_to_image`).
+    pub fn new_headless() -> Rc<AppGlobals> {
+        Self::install(AppGlobals {
+            backend: None,
+            preferences: Property::new(Preferences::default()),
+            system_preferences: Cell::new(Preferences::default()),
+        })
+    }
 
+    fn install(app: AppGlobals) -> Rc<AppGlobals> {
+        let app = Rc::new(app);
         APP_GLOBALS.with(|g| g.replace(Some(app.clone())));
         app
     }
 
+    /// Returns the platform backend.
+    ///
+    /// # Panics
+    ///
+    /// In headless mode.
+    pub(crate) fn backend(&self) -> &ApplicationBackend {
+        self.backend.as_ref().expect("this operation is not supported in headless mode")
+    }
+
+    /// Returns whether the application runs without a platform backend.
+    pub fn is_headless(&self) -> bool {
+        self.backend.is_none()
+    }
+
     pub fn try_get() -> Option<Rc<AppGlobals>> {
         APP_GLOBALS.with(|g| Some(g.borrow().as_ref()?.clone()))
     }
@@ -36,7 +81,37 @@ impl AppGlobals {
     }
 
     pub fn double_click_time(&self) -> Duration {
-        self.backend.double_click_time()
+        match self.backend {
+            Some(ref backend) => backend.double_click_time(),
+            // typical platform default
+            None => DEFAULT_DOUBLE_CLICK_TIME,
+        }
+    }
+
+    /// Returns the accessibility preferences (see `preferences`).
+    pub fn preferences(&self) -> Preferences {
+        self.preferences.get()
+    }
+
+    pub(crate) fn preferences_stream(&self) -> watch::Receiver<Preferences> {
+        self.preferences.stream()
+    }
+
+    pub(crate) fn set_preferences(&self, preferences: Preferences) {
+        self.preferences.modify(|value| {
+            let modified = *value != preferences;
+            *value = preferences;
+            modified
+        });
+    }
+
+    /// Reads the preferences of the system again, and updates the preferences if they changed.
+    pub(crate) fn refresh_preferences(&self) {
+        let Some(ref backend) = self.backend else { return };
+        let preferences = backend.preferences();
+        if self.system_preferences.replace(preferences) != preferences {
+            self.set_preferences(preferences);
+        }
     }
 
     pub fn teardown() {
@@ -16,7 +16,7 @@ use std::sync::{OnceLock};
 use std::task::{Poll, Waker};
 use std::time::{Duration, Instant};
 use tracy_client::set_thread_name;
-use winit::event::{Event, StartCause};
+use winit::event::{Event, MouseScrollDelta, StartCause, TouchPhase, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy, EventLoopWindowTarget};
 use winit::window::WindowId;
 
@@ -30,7 +30,10 @@ pub enum ExtEvent {
 static EVENT_LOOP_PROXY: OnceLock<EventLoopProxy<ExtEvent>> = OnceLock::new();
 
 pub fn wake_event_loop() {
-    EVENT_LOOP_PROXY.get().unwrap().send_event(ExtEvent::UpdateUi).unwrap()
+    // there's no event loop in headless mode
+    if let Some(proxy) = EVENT_LOOP_PROXY.get() {
+        proxy.send_event(ExtEvent::UpdateUi).unwrap()
+    }
 }
 
 scoped_thread_local!(static EVENT_LOOP_WINDOW_TARGET: EventLoopWindowTarget<ExtEvent>);
@@ -48,6 +51,8 @@ struct Timer {
 struct AppState {
     windows: RefCell<HashMap<WindowId, Weak<dyn WindowHandler>>>,
     spawner: LocalSpawner,
+    /// Spawner for low-priority tasks (see `spawn_background`).
+    background_spawner: LocalSpawner,
     timers: RefCell<SmallVec<Timer, 4>>,
 }
 
@@ -67,6 +72,24 @@ pub fn spawn(fut: impl Future<Output=()> + 'static) -> AbortHandle {
     })
 }
 
+/// Spawns a low-priority task on the main-thread executor.
+///
+/// Background tasks are only polled once all pending input events have been dispatched, so that
+/// they don't delay input handling when the event queue is busy (e.g. while dragging).
+/// Use this for tasks reacting to data changes rather than to user input.
+pub fn spawn_background(fut: impl Future<Output=()> + 'static) -> AbortHandle {
+    APP_STATE.with(|state| {
+        let (fut, abort_handle) = abortable(fut);
+        state
+            .background_spawner
+            .spawn_local(async {
+                let _ = fut.await; // ignore aborts
+            })
+            .expect("failed to spawn task");
+        abort_handle
+    })
+}
+
 pub trait WindowHandlerObjectSafe {
     fn event_future<'a>(&'a self, event: &'a winit::event::WindowEvent) -> LocalBoxFuture<'a, ()>;
 }
@@ -131,6 +154,96 @@ pub async fn wait_for(duration: Duration) {
     wait_until(deadline).await;
 }
 
+/// Wakes the tasks waiting on expired timers.
+fn wake_expired_timers(state: &AppState) {
+    let timers = &mut *state.timers.borrow_mut();
+    timers.sort_by_key(|t| t.deadline);
+    let now = Instant::now();
+    while let Some(timer) = timers.first() {
+        if timer.deadline <= now {
+            let timer = timers.remove(0);
+            timer.waker.wake();
+        } else {
+            break;
+        }
+    }
+}
+
+/// A continuous input event (pointer move or scroll) that is held back until the event queue
+/// is drained, so that it can be merged with the following events of the same kind.
+struct CoalescedEvent {
+    window_id: WindowId,
+    event: WindowEvent,
+}
+
+fn is_continuous_event(event: &WindowEvent) -> bool {
+    matches!(event, WindowEvent::CursorMoved { .. } | WindowEvent::MouseWheel { .. })
+}
+
+/// Merges `next` into `pending`. Returns `next` back if the events can't be merged.
+///
+/// Pointer moves are replaced by the latest one. Scroll deltas are accumulated, but not across
+/// changes of touch phase.
+fn coalesce(pending: &mut WindowEvent, next: WindowEvent) -> Result<(), WindowEvent> {
+    match (&mut *pending, &next) {
+        (
+            WindowEvent::CursorMoved { device_id, .. },
+            WindowEvent::CursorMoved {
+                device_id: next_device_id,
+                ..
+            },
+        ) if device_id == next_device_id => {}
+        (
+            WindowEvent::MouseWheel {
+                device_id,
+                delta,
+                phase: TouchPhase::Moved,
+            },
+            WindowEvent::MouseWheel {
+                device_id: next_device_id,
+                delta: next_delta,
+                phase: TouchPhase::Moved,
+            },
+        ) if device_id == next_device_id => {
+            match (delta, next_delta) {
+                (MouseScrollDelta::LineDelta(x, y), MouseScrollDelta::LineDelta(dx, dy)) => {
+                    *x += dx;
+                    *y += dy;
+                }
+                (MouseScrollDelta::PixelDelta(pos), MouseScrollDelta::PixelDelta(d)) => {
+                    pos.x += d.x;
+                    pos.y += d.y;
+                }
+                _ => return Err(next),
+            }
+            return Ok(());
+        }
+        _ => return Err(next),
+    }
+    // latest pointer position wins
+    *pending = next;
+    Ok(())
+}
+
+fn dispatch_window_event(state: &AppState, local_pool: &mut LocalPool, window_id: WindowId, event: &WindowEvent) {
+    // eprintln!("[{:?}] [{:?}]", window_id, event);
+    // Don't hold a borrow of `state.windows` across the handler since
+    // the handler may create new windows.
+    if matches!(event, WindowEvent::Focused(true) | WindowEvent::ThemeChanged(_)) {
+        // accessibility settings may have changed in the meantime
+        AppGlobals::get().refresh_preferences();
+    }
+    let handler = state.windows.borrow().get(&window_id).cloned();
+    if let Some(handler) = handler {
+        if let Some(handler) = handler.upgrade() {
+            local_pool.run_until(handler.event_future(event));
+        } else {
+            // remove the window if the handler has been dropped
+            state.windows.borrow_mut().remove(&window_id);
+        }
+    }
+}
+
 pub fn run(root_future: impl Future<Output=()> + 'static) -> Result<(), anyhow::Error> {
     set_thread_name!("UI thread");
     let event_loop: EventLoop<ExtEvent> = EventLoopBuilder::with_user_event()
@@ -147,9 +260,12 @@ pub fn run(root_future: impl Future<Output=()> + 'static) -> Result<(), anyhow::
     let _event_loop_start_time = Instant::now();
 
     let mut local_pool = LocalPool::new();
+    let mut background_pool = LocalPool::new();
+    let mut pending_event: Option<CoalescedEvent> = None;
     let app_state = AppState {
         windows: RefCell::new(HashMap::new()),
         spawner: local_pool.spawner(),
+        background_spawner: background_pool.spawner(),
         timers: RefCell::new(Default::default()),
     };
 
@@ -173,17 +289,7 @@ pub fn run(root_future: impl Future<Output=()> + 'static) -> Result<(), anyhow::
                                 StartCause::ResumeTimeReached { .. }
                                 | StartCause::WaitCancelled { .. }
                                 | StartCause::Poll => {
-                                    // wake all expired timers
-                                    let timers = &mut *state.timers.borrow_mut();
-                                    let now = Instant::now();
-                                    while let Some(timer) = timers.first() {
-                                        if timer.deadline <= now {
-                                            let timer = timers.remove(0);
-                                            timer.waker.wake();
-                                        } else {
-                                            break;
-                                        }
-                                    }
+                                    wake_expired_timers(state);
                                 }
                                 StartCause::Init => {}
                             }
@@ -192,18 +298,40 @@ pub fn run(root_future: impl Future<Output=()> + 'static) -> Result<(), anyhow::
                             window_id,
                             event: window_event,
                         } => {
-                            // eprintln!("[{:?}] [{:?}]", window_id, window_event);
-                            // Don't hold a borrow of `state.windows` across the handler since
-                            // the handler may create new windows.
-                            let handler = state.windows.borrow().get(&window_id).cloned();
-                            if let Some(handler) = handler {
-                                if let Some(handler) = handler.upgrade() {
-                                    local_pool.run_until(handler.event_future(&window_event));
-                                } else {
-                                    // remove the window if the handler has been dropped
-                                    state.windows.borrow_mut().remove(&window_id);
+                            if is_continuous_event(&window_event) {
+                                let next = match pending_event.take() {
+                                    Some(mut pending) if pending.window_id == window_id => {
+                                        match coalesce(&mut pending.event, window_event) {
+                                            Ok(()) => Some(pending),
+                                            Err(window_event) => {
+                                                dispatch_window_event(state, &mut local_pool, pending.window_id, &pending.event);
+                                                Some(CoalescedEvent { window_id, event: window_event })
+                                            }
+                                        }
+                                    }
+                                    other => {
+                                        if let Some(pending) = other {
+                                            dispatch_window_event(state, &mut local_pool, pending.window_id, &pending.event);
+                                        }
+                                        Some(CoalescedEvent { window_id, event: window_event })
+                                    }
+                                };
+                                pending_event = next;
+                            } else {
+                                // deliver held-back events first to preserve ordering
+                                if let Some(pending) = pending_event.take() {
+                                    dispatch_window_event(state, &mut local_pool, pending.window_id, &pending.event);
                                 }
+                                dispatch_window_event(state, &mut local_pool, window_id, &window_event);
+                            }
+                        }
+                        Event::AboutToWait => {
+                            // the event queue is drained
+                            if let Some(pending) = pending_event.take() {
+                                dispatch_window_event(state, &mut local_pool, pending.window_id, &pending.event);
                             }
+                            // input has been processed, now run low-priority tasks
+                            background_pool.run_until_stalled();
                         }
                         _ => {}
                     };
@@ -229,3 +357,52 @@ pub fn run(root_future: impl Future<Output=()> + 'static) -> Result<(), anyhow::
     AppGlobals::teardown();
     result
 }
+
+/// Runs a future to completion without an event loop or a platform backend, and returns its output.
+///
+/// This is meant for tests and tools running on machines without a display (e.g. CI): windows can't
+/// be created, but element trees can be laid out and painted offscreen with `testing::render_to_image`.
+/// Tasks spawned with `spawn` or `spawn_background`, and timers, work as usual.
+///
+/// # Panics
+///
+/// If the future is blocked on something else than a timer (it would never complete).
+pub fn run_headless<T: 'static>(root_future: impl Future<Output=T> + 'static) -> T {
+    AppGlobals::new_headless();
+
+    let mut local_pool = LocalPool::new();
+    let mut background_pool = LocalPool::new();
+    let app_state = AppState {
+        windows: RefCell::new(HashMap::new()),
+        spawner: local_pool.spawner(),
+        background_spawner: background_pool.spawner(),
+        timers: RefCell::new(Default::default()),
+    };
+
+    let output = Rc::new(RefCell::new(None));
+    let result = APP_STATE.set(&app_state, || {
+        let output_slot = output.clone();
+        spawn(async move {
+            let value = root_future.await;
+            output_slot.replace(Some(value));
+        });
+        loop {
+            local_pool.run_until_stalled();
+            background_pool.run_until_stalled();
+            local_pool.run_until_stalled();
+            if let Some(value) = output.take() {
+                break value;
+            }
+            // all tasks are stalled: wait for the next timer
+            let next_deadline = app_state.timers.borrow().iter().map(|t| t.deadline).min();
+            let Some(deadline) = next_deadline else {
+                panic!("run_headless: the root future is blocked on something else than a timer");
+            };
+            std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            wake_expired_timers(&app_state);
+        }
+    });
+
+    AppGlobals::teardown();
+    result
+}
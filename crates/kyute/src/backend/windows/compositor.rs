@@ -1,8 +1,10 @@
 //! Windows compositor implementation details
 
 use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::ffi::c_void;
 use std::rc::Rc;
+use std::time::Duration;
 
 use raw_window_handle::RawWindowHandle;
 use skia_safe as sk;
@@ -10,6 +12,7 @@ use skia_safe::gpu::d3d::TextureResourceInfo;
 use skia_safe::gpu::{DirectContext, FlushInfo, Protected};
 use skia_safe::surface::BackendSurfaceAccess;
 use skia_safe::{ColorSpace, SurfaceProps};
+use tracing::warn;
 use tracy_client::span;
 use windows::core::{Interface, Owned};
 use windows::Win32::Foundation::{HANDLE, HWND};
@@ -18,24 +21,76 @@ use windows::Win32::Graphics::DirectComposition::{
     IDCompositionDesktopDevice, IDCompositionTarget, IDCompositionVisual3,
 };
 use windows::Win32::Graphics::Dxgi::Common::{
-    DXGI_ALPHA_MODE_IGNORE, DXGI_FORMAT, DXGI_FORMAT_R16G16B16A16_FLOAT, DXGI_SAMPLE_DESC,
+    DXGI_ALPHA_MODE_IGNORE, DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709, DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+    DXGI_COLOR_SPACE_TYPE, DXGI_FORMAT, DXGI_FORMAT_R10G10B10A2_UNORM, DXGI_FORMAT_R16G16B16A16_FLOAT,
+    DXGI_SAMPLE_DESC,
 };
 use windows::Win32::Graphics::Dxgi::{
-    IDXGISwapChain3, DXGI_PRESENT, DXGI_SCALING_STRETCH, DXGI_SWAP_CHAIN_DESC1,
-    DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT, DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
+    IDXGIOutput6, IDXGISwapChain3, IDXGISwapChain4, DXGI_FRAME_STATISTICS, DXGI_HDR_METADATA_HDR10,
+    DXGI_HDR_METADATA_TYPE_HDR10, DXGI_PRESENT, DXGI_SCALING_STRETCH, DXGI_SWAP_CHAIN_COLOR_SPACE_SUPPORT_FLAG_PRESENT,
+    DXGI_SWAP_CHAIN_DESC1, DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT, DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
     DXGI_USAGE_RENDER_TARGET_OUTPUT,
 };
+use windows::Win32::Graphics::Gdi::{MonitorFromWindow, MONITOR_DEFAULTTONEAREST};
+use windows::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
 use windows::Win32::System::Threading::WaitForSingleObject;
 
 use crate::backend::windows::BackendInner;
 use crate::backend::ApplicationBackend;
-use crate::compositor::ColorType;
+use crate::compositor::{ColorType, DisplayInfo, HdrMetadata, PresentFeedback, SurfaceColorSpace};
 use crate::Size;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
 const SWAP_CHAIN_BUFFER_COUNT: u32 = 2;
 
+/// Number of presents for which the submission time is remembered, to measure present latency.
+const PRESENT_HISTORY_LEN: usize = 16;
+
+fn qpc_now() -> i64 {
+    let mut t = 0;
+    unsafe {
+        // SAFETY: FFI, never fails on Windows XP and later
+        let _ = QueryPerformanceCounter(&mut t);
+    }
+    t
+}
+
+fn qpc_to_duration(ticks: i64) -> Duration {
+    let mut freq = 0;
+    unsafe {
+        // SAFETY: same as above
+        let _ = QueryPerformanceFrequency(&mut freq);
+    }
+    Duration::from_secs_f64(ticks.max(0) as f64 / freq.max(1) as f64)
+}
+
+/// Swap chain buffer format used for the specified color space.
+fn swap_chain_format(color_space: SurfaceColorSpace) -> DXGI_FORMAT {
+    match color_space {
+        SurfaceColorSpace::ScRgb => DXGI_FORMAT_R16G16B16A16_FLOAT,
+        SurfaceColorSpace::Hdr10 => DXGI_FORMAT_R10G10B10A2_UNORM,
+    }
+}
+
+fn dxgi_color_space(color_space: SurfaceColorSpace) -> DXGI_COLOR_SPACE_TYPE {
+    match color_space {
+        SurfaceColorSpace::ScRgb => DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709,
+        SurfaceColorSpace::Hdr10 => DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+    }
+}
+
+/// Skia color type and color space of surfaces drawing to swap chain buffers in the specified color space.
+fn skia_color_info(color_space: SurfaceColorSpace) -> (sk::ColorType, ColorSpace) {
+    match color_space {
+        SurfaceColorSpace::ScRgb => (sk::ColorType::RGBAF16, ColorSpace::new_srgb_linear()),
+        SurfaceColorSpace::Hdr10 => (
+            sk::ColorType::RGBA1010102,
+            ColorSpace::new_rgb(&sk::named_transfer_fn::PQ, &sk::named_gamut::REC2020),
+        ),
+    }
+}
+
 /// Windows drawable surface backend.
 pub(crate) struct DrawableSurface {
     composition_device: IDCompositionDesktopDevice,
@@ -90,7 +145,14 @@ pub struct Layer {
     visual: IDCompositionVisual3,
     size: Cell<Size>,
     swap_chain: Option<SwapChain>,
+    color_space: Cell<SurfaceColorSpace>,
     window_target: RefCell<Option<IDCompositionTarget>>,
+    /// Window the layer is bound to.
+    hwnd: Cell<Option<HWND>>,
+    /// Present count and QPC time of the last presents.
+    present_history: RefCell<VecDeque<(u32, i64)>>,
+    /// Frame statistics retrieved after the previous present.
+    last_frame_statistics: Cell<Option<DXGI_FRAME_STATISTICS>>,
 }
 
 impl Drop for Layer {
@@ -116,6 +178,11 @@ impl Layer {
 
         self.size.set(size);
 
+        self.resize_buffers(width, height, self.color_space.get());
+    }
+
+    /// Reallocates the swap chain buffers with the specified size and color space.
+    fn resize_buffers(&self, width: u32, height: u32, color_space: SurfaceColorSpace) {
         if let Some(ref swap_chain) = self.swap_chain {
             // Wait for the GPU to finish using the previous swap chain buffers.
             self.app.wait_for_gpu();
@@ -129,7 +196,7 @@ impl Layer {
                     SWAP_CHAIN_BUFFER_COUNT,
                     width,
                     height,
-                    DXGI_FORMAT_R16G16B16A16_FLOAT,
+                    swap_chain_format(color_space),
                     DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT,
                 ) {
                     Ok(_) => {}
@@ -142,6 +209,92 @@ impl Layer {
         }
     }
 
+    pub(crate) fn color_space(&self) -> SurfaceColorSpace {
+        self.color_space.get()
+    }
+
+    /// Changes the color space of the swap chain, see `compositor::Layer::set_color_space`.
+    pub(crate) fn set_color_space(&self, color_space: SurfaceColorSpace) -> bool {
+        if self.color_space.get() == color_space {
+            return true;
+        }
+        let swap_chain = self.swap_chain.as_ref().expect("layer should be a surface layer");
+        let size = self.size.get();
+        // the buffers must be in the right format before the color space can be checked
+        self.resize_buffers(size.width as u32, size.height as u32, color_space);
+        unsafe {
+            // SAFETY: FFI
+            let supported = swap_chain
+                .inner
+                .CheckColorSpaceSupport(dxgi_color_space(color_space))
+                .is_ok_and(|flags| flags & DXGI_SWAP_CHAIN_COLOR_SPACE_SUPPORT_FLAG_PRESENT.0 as u32 != 0);
+            if !supported || swap_chain.inner.SetColorSpace1(dxgi_color_space(color_space)).is_err() {
+                warn!("swap chain color space {color_space:?} not supported");
+                self.resize_buffers(size.width as u32, size.height as u32, self.color_space.get());
+                return false;
+            }
+        }
+        self.color_space.set(color_space);
+        true
+    }
+
+    /// Sets the HDR10 metadata of the swap chain.
+    pub(crate) fn set_hdr_metadata(&self, metadata: &HdrMetadata) {
+        let swap_chain = self.swap_chain.as_ref().expect("layer should be a surface layer");
+        // chromaticity coordinates are in units of 0.00002
+        let xy = |x: f32, y: f32| [(x * 50000.0) as u16, (y * 50000.0) as u16];
+        let hdr10 = DXGI_HDR_METADATA_HDR10 {
+            // Rec.2020 primaries, D65 white point
+            RedPrimary: xy(0.708, 0.292),
+            GreenPrimary: xy(0.170, 0.797),
+            BluePrimary: xy(0.131, 0.046),
+            WhitePoint: xy(0.3127, 0.3290),
+            MaxMasteringLuminance: metadata.max_mastering_luminance as u32,
+            // in units of 0.0001 nits
+            MinMasteringLuminance: (metadata.min_mastering_luminance * 10000.0) as u32,
+            MaxContentLightLevel: metadata.max_content_light_level as u16,
+            MaxFrameAverageLightLevel: metadata.max_frame_average_light_level as u16,
+        };
+        unsafe {
+            // SAFETY: FFI, `hdr10` outlives the call
+            let result = swap_chain.inner.cast::<IDXGISwapChain4>().and_then(|swap_chain| {
+                swap_chain.SetHDRMetaData(
+                    DXGI_HDR_METADATA_TYPE_HDR10,
+                    size_of::<DXGI_HDR_METADATA_HDR10>() as u32,
+                    Some(&hdr10 as *const _ as *const c_void),
+                )
+            });
+            if let Err(err) = result {
+                warn!("failed to set HDR metadata: {err}");
+            }
+        }
+    }
+
+    /// Returns the capabilities of the display showing the window the layer is bound to.
+    pub(crate) fn display_info(&self) -> Option<DisplayInfo> {
+        let hwnd = self.hwnd.get()?;
+        unsafe {
+            // SAFETY: FFI
+            let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+            let mut i = 0;
+            while let Ok(output) = self.app.adapter.EnumOutputs(i) {
+                i += 1;
+                let Ok(desc) = output.cast::<IDXGIOutput6>().and_then(|output| output.GetDesc1()) else {
+                    continue;
+                };
+                if desc.Monitor == monitor {
+                    return Some(DisplayInfo {
+                        hdr_enabled: desc.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+                        min_luminance: desc.MinLuminance,
+                        max_luminance: desc.MaxLuminance,
+                        max_full_frame_luminance: desc.MaxFullFrameLuminance,
+                    });
+                }
+            }
+        }
+        None
+    }
+
     /// Waits for the specified surface to be ready for presentation.
     ///
     /// TODO explain
@@ -157,6 +310,56 @@ impl Layer {
         }
     }
 
+    /// Returns presentation statistics, see `compositor::Layer::presentation_feedback`.
+    pub(crate) fn presentation_feedback(&self) -> PresentFeedback {
+        let swap_chain = self.swap_chain.as_ref().expect("layer should be a surface layer");
+        let now = qpc_now();
+        let mut stats = DXGI_FRAME_STATISTICS::default();
+        unsafe {
+            // SAFETY: FFI
+            if let Ok(count) = swap_chain.inner.GetLastPresentCount() {
+                let mut history = self.present_history.borrow_mut();
+                if history.len() == PRESENT_HISTORY_LEN {
+                    history.pop_front();
+                }
+                history.push_back((count, now));
+            }
+            // This fails if no frame has been displayed yet, or if the statistics are
+            // discontinuous (e.g. after a mode change).
+            if swap_chain.inner.GetFrameStatistics(&mut stats).is_err() {
+                self.last_frame_statistics.set(None);
+                return PresentFeedback::default();
+            }
+        }
+
+        let history = self.present_history.borrow();
+        let submitted = |present_count: u32| {
+            history
+                .iter()
+                .find(|(count, _)| *count == present_count)
+                .map(|(_, time)| *time)
+        };
+        let latency = submitted(stats.PresentCount).map(|time| qpc_to_duration(stats.SyncQPCTime - time));
+
+        let mut missed_vsyncs = 0;
+        if let Some(prev) = self.last_frame_statistics.replace(Some(stats)) {
+            let refreshes = stats.SyncRefreshCount.wrapping_sub(prev.SyncRefreshCount);
+            let displayed = stats.PresentRefreshCount.wrapping_sub(prev.PresentRefreshCount);
+            if stats.PresentCount != prev.PresentCount && refreshes > 0 {
+                let refresh_period = (stats.SyncQPCTime - prev.SyncQPCTime) / refreshes as i64;
+                // the frame could have been displayed at the vblank that followed the previous frame
+                // if it was submitted before that
+                if let Some(time) = submitted(stats.PresentCount) {
+                    if time <= prev.SyncQPCTime + refresh_period {
+                        missed_vsyncs = displayed.saturating_sub(1);
+                    }
+                }
+            }
+        }
+
+        PresentFeedback { missed_vsyncs, latency }
+    }
+
     /// Creates a skia drawing context for the specified surface layer.
     pub(crate) fn acquire_drawing_surface(&self) -> DrawableSurface {
         let swap_chain = self.swap_chain.as_ref().expect("layer should be a surface layer");
@@ -169,13 +372,15 @@ impl Layer {
                 .GetBuffer::<ID3D12Resource>(index)
                 .expect("failed to retrieve swap chain buffer");
 
+            let color_space = self.color_space.get();
+            let (color_type, sk_color_space) = skia_color_info(color_space);
             let surface = self.app.create_surface_for_texture(
                 swap_chain_buffer,
-                DXGI_FORMAT_R16G16B16A16_FLOAT,
+                swap_chain_format(color_space),
                 self.size.get(),
                 sk::gpu::SurfaceOrigin::TopLeft,
-                sk::ColorType::RGBAF16,
-                sk::ColorSpace::new_srgb_linear(),
+                color_type,
+                sk_color_space,
                 Some(sk::SurfaceProps::new(
                     sk::SurfacePropsFlags::default(),
                     sk::PixelGeometry::RGBH,
@@ -202,11 +407,13 @@ impl Layer {
             RawWindowHandle::Win32(w) => w,
             _ => panic!("expected a Win32 window handle"),
         };
+        let hwnd = HWND(win32_handle.hwnd.get() as *mut c_void);
         let window_target = self
             .app
             .composition_device
-            .CreateTargetForHwnd(HWND(win32_handle.hwnd.get() as *mut c_void), false)
+            .CreateTargetForHwnd(hwnd, false)
             .expect("CreateTargetForHwnd failed");
+        self.hwnd.set(Some(hwnd));
         window_target.SetRoot(&self.visual).expect("SetRoot failed");
         self.window_target.replace(Some(window_target));
     }
@@ -280,7 +487,7 @@ impl ApplicationBackend {
             let swap_chain_desc = DXGI_SWAP_CHAIN_DESC1 {
                 Width: width,
                 Height: height,
-                Format: DXGI_FORMAT_R16G16B16A16_FLOAT,
+                Format: swap_chain_format(SurfaceColorSpace::ScRgb),
                 Stereo: false.into(),
                 SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
                 BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
@@ -302,6 +509,9 @@ impl ApplicationBackend {
 
             // SAFETY: FFI
             swap_chain.SetMaximumFrameLatency(1).unwrap();
+            swap_chain
+                .SetColorSpace1(dxgi_color_space(SurfaceColorSpace::ScRgb))
+                .expect("SetColorSpace1 failed");
             let frame_latency_waitable = swap_chain.GetFrameLatencyWaitableObject();
 
             let swap_chain = SwapChain {
@@ -321,7 +531,11 @@ impl ApplicationBackend {
                 visual: visual.cast().unwrap(),
                 size: Cell::new(size),
                 swap_chain: Some(swap_chain),
+                color_space: Cell::new(SurfaceColorSpace::ScRgb),
                 window_target: RefCell::new(None),
+                hwnd: Cell::new(None),
+                present_history: RefCell::new(VecDeque::with_capacity(PRESENT_HISTORY_LEN)),
+                last_frame_statistics: Cell::new(None),
             }
         }
     }
@@ -0,0 +1,112 @@
+//! File open/save dialogs (IFileOpenDialog / IFileSaveDialog).
+use std::path::PathBuf;
+
+use windows::core::{Interface, HSTRING, PCWSTR};
+use windows::Win32::Foundation::{ERROR_CANCELLED, HWND};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED,
+};
+use windows::Win32::UI::Shell::Common::COMDLG_FILTERSPEC;
+use windows::Win32::UI::Shell::{
+    FileOpenDialog, FileSaveDialog, IFileDialog, IFileOpenDialog, IFileSaveDialog, IShellItem, FOS_ALLOWMULTISELECT,
+    SHCreateItemFromParsingName, FOS_OVERWRITEPROMPT, FOS_PICKFOLDERS, SIGDN_FILESYSPATH,
+};
+
+use crate::dialog::{FileDialogKind, FileDialogOptions};
+
+unsafe fn item_path(item: &IShellItem) -> windows::core::Result<PathBuf> {
+    let name = item.GetDisplayName(SIGDN_FILESYSPATH)?;
+    let path = String::from_utf16_lossy(name.as_wide());
+    CoTaskMemFree(Some(name.0 as *const _));
+    Ok(PathBuf::from(path))
+}
+
+unsafe fn show(options: &FileDialogOptions, owner: Option<isize>) -> windows::core::Result<Vec<PathBuf>> {
+    let dialog: IFileDialog = match options.kind {
+        FileDialogKind::Save => CoCreateInstance::<_, IFileSaveDialog>(&FileSaveDialog, None, CLSCTX_INPROC_SERVER)?.into(),
+        _ => CoCreateInstance::<_, IFileOpenDialog>(&FileOpenDialog, None, CLSCTX_INPROC_SERVER)?.into(),
+    };
+
+    let mut flags = dialog.GetOptions()?;
+    match options.kind {
+        FileDialogKind::Open if options.multiple => flags |= FOS_ALLOWMULTISELECT,
+        FileDialogKind::Save => flags |= FOS_OVERWRITEPROMPT,
+        FileDialogKind::PickFolder => flags |= FOS_PICKFOLDERS,
+        _ => {}
+    }
+    dialog.SetOptions(flags)?;
+
+    if !options.title.is_empty() {
+        dialog.SetTitle(&HSTRING::from(options.title.as_str()))?;
+    }
+    if let Some(ref directory) = options.directory {
+        // a missing directory isn't an error: the dialog opens in the last used directory instead
+        if let Ok(folder) = SHCreateItemFromParsingName::<_, _, IShellItem>(&HSTRING::from(directory.as_path()), None) {
+            dialog.SetFolder(&folder)?;
+        }
+    }
+    if let Some(ref file_name) = options.file_name {
+        dialog.SetFileName(&HSTRING::from(file_name.as_str()))?;
+    }
+
+    // The filter strings must outlive the call to `SetFileTypes`.
+    let filters: Vec<(HSTRING, HSTRING)> = options
+        .filters
+        .iter()
+        .map(|f| {
+            let spec = f.extensions.iter().map(|ext| format!("*.{ext}")).collect::<Vec<_>>().join(";");
+            (HSTRING::from(f.name.as_str()), HSTRING::from(spec))
+        })
+        .collect();
+    if !filters.is_empty() {
+        let specs: Vec<COMDLG_FILTERSPEC> = filters
+            .iter()
+            .map(|(name, spec)| COMDLG_FILTERSPEC {
+                pszName: PCWSTR(name.as_ptr()),
+                pszSpec: PCWSTR(spec.as_ptr()),
+            })
+            .collect();
+        dialog.SetFileTypes(&specs)?;
+        if let Some(ext) = options.filters[0].extensions.first() {
+            dialog.SetDefaultExtension(&HSTRING::from(ext.as_str()))?;
+        }
+    }
+
+    let owner = owner.map(|hwnd| HWND(hwnd as *mut _)).unwrap_or_default();
+    if let Err(err) = dialog.Show(owner) {
+        return if err.code() == ERROR_CANCELLED.to_hresult() {
+            Ok(vec![])
+        } else {
+            Err(err)
+        };
+    }
+
+    let mut paths = vec![];
+    if options.kind == FileDialogKind::Open && options.multiple {
+        let results = dialog.cast::<IFileOpenDialog>()?.GetResults()?;
+        for i in 0..results.GetCount()? {
+            paths.push(item_path(&results.GetItemAt(i)?)?);
+        }
+    } else {
+        paths.push(item_path(&dialog.GetResult()?)?);
+    }
+    Ok(paths)
+}
+
+/// Shows a file dialog and blocks until it is closed.
+///
+/// This runs a modal message loop: call it from a separate thread so as not to block the event loop.
+/// `owner` is the HWND of the owner window. Returns an empty list if the user cancelled the dialog.
+pub(crate) fn show_file_dialog(options: &FileDialogOptions, owner: Option<isize>) -> Vec<PathBuf> {
+    unsafe {
+        let initialized = CoInitializeEx(None, COINIT_APARTMENTTHREADED).is_ok();
+        let result = show(options, owner);
+        if initialized {
+            CoUninitialize();
+        }
+        result.unwrap_or_else(|err| {
+            tracing::error!("file dialog failed: {err}");
+            vec![]
+        })
+    }
+}
@@ -22,10 +22,25 @@ use windows::Win32::Graphics::Dxgi::{
 use windows::Win32::System::Com::{COINIT_APARTMENTTHREADED, CoInitializeEx};
 use windows::Win32::System::Threading::{CreateEventW, WaitForSingleObject};
 use windows::Win32::UI::Input::KeyboardAndMouse::GetDoubleClickTime;
+use windows::Win32::Foundation::BOOL;
+use windows::Win32::Graphics::Gdi::{
+    GetSysColor, COLOR_GRAYTEXT, COLOR_HIGHLIGHT, COLOR_HIGHLIGHTTEXT, COLOR_WINDOW, COLOR_WINDOWTEXT, SYS_COLOR_INDEX,
+};
+use windows::Win32::UI::Accessibility::{HCF_HIGHCONTRASTON, HIGHCONTRASTW};
+use windows::Win32::UI::WindowsAndMessaging::{
+    SystemParametersInfoW, SPI_GETCLIENTAREAANIMATION, SPI_GETHIGHCONTRAST, SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+};
+
+use crate::preferences::{HighContrastColors, Preferences};
+use crate::Color;
 
 pub(crate) use compositor::{DrawableSurface, Layer};
+pub(crate) use file_dialog::show_file_dialog;
+pub(crate) use pointer::{pen_info, pointer_type, PenInfo};
 
 mod compositor;
+mod file_dialog;
+mod pointer;
 
 /////////////////////////////////////////////////////////////////////////////
 // COM wrappers
@@ -298,4 +313,57 @@ impl ApplicationBackend {
             Duration::from_millis(ms as u64)
         }
     }
+
+    /// Reads the accessibility preferences of the system.
+    pub(crate) fn preferences(&self) -> Preferences {
+        let no_update = SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0);
+
+        // "Show animations in Windows"
+        let mut animations = BOOL(1);
+        // SAFETY: SPI_GETCLIENTAREAANIMATION writes a BOOL
+        let reduced_motion = unsafe {
+            SystemParametersInfoW(
+                SPI_GETCLIENTAREAANIMATION,
+                0,
+                Some(&mut animations as *mut BOOL as *mut _),
+                no_update,
+            )
+        }
+        .is_ok()
+            && !animations.as_bool();
+
+        let mut high_contrast = HIGHCONTRASTW {
+            cbSize: size_of::<HIGHCONTRASTW>() as u32,
+            ..Default::default()
+        };
+        // SAFETY: SPI_GETHIGHCONTRAST writes a HIGHCONTRASTW of the size given in `cbSize`
+        let high_contrast_on = unsafe {
+            SystemParametersInfoW(
+                SPI_GETHIGHCONTRAST,
+                high_contrast.cbSize,
+                Some(&mut high_contrast as *mut HIGHCONTRASTW as *mut _),
+                no_update,
+            )
+        }
+        .is_ok()
+            && high_contrast.dwFlags.contains(HCF_HIGHCONTRASTON);
+
+        Preferences {
+            reduced_motion,
+            high_contrast: high_contrast_on.then(|| HighContrastColors {
+                text: system_color(COLOR_WINDOWTEXT),
+                background: system_color(COLOR_WINDOW),
+                highlight: system_color(COLOR_HIGHLIGHT),
+                highlight_text: system_color(COLOR_HIGHLIGHTTEXT),
+                disabled_text: system_color(COLOR_GRAYTEXT),
+            }),
+        }
+    }
+}
+
+/// Returns a color of the system theme.
+fn system_color(index: SYS_COLOR_INDEX) -> Color {
+    // 0x00BBGGRR
+    let color = unsafe { GetSysColor(index) };
+    Color::from_rgb_u8(color as u8, (color >> 8) as u8, (color >> 16) as u8)
 }
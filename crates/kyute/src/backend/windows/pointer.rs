@@ -0,0 +1,65 @@
+//! Pen information of pointer messages (GetPointerPenInfo).
+//!
+//! winit reports pens and touch contacts as touch events, with only the position and the pressure.
+//! The rest (pointer type, tilt, rotation, barrel button, eraser) is queried from the pointer ID. The
+//! query returns the state as of the pointer message being processed, so it must be made while the
+//! touch event is dispatched.
+use kurbo::Vec2;
+use windows::Win32::UI::Input::Pointer::{GetPointerPenInfo, GetPointerType, POINTER_PEN_INFO};
+use windows::Win32::UI::WindowsAndMessaging::{
+    PEN_FLAG_BARREL, PEN_FLAG_ERASER, PEN_FLAG_INVERTED, PEN_MASK_PRESSURE, PEN_MASK_ROTATION, PEN_MASK_TILT_X,
+    PEN_MASK_TILT_Y, POINTER_INPUT_TYPE, PT_MOUSE, PT_PEN, PT_TOUCH, PT_TOUCHPAD,
+};
+
+use crate::event::PointerType;
+
+/// Maximum pressure reported by pens.
+const MAX_PEN_PRESSURE: f32 = 1024.0;
+
+/// State of a pen.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct PenInfo {
+    /// Normalized pressure, or `None` if the pen doesn't report pressure.
+    pub(crate) pressure: Option<f32>,
+    /// Tilt in degrees.
+    pub(crate) tilt: Vec2,
+    /// Rotation in degrees.
+    pub(crate) twist: f32,
+    /// Whether the barrel button is pressed.
+    pub(crate) barrel: bool,
+    /// Whether the pen is inverted or the eraser button is pressed.
+    pub(crate) eraser: bool,
+}
+
+/// Returns the type of the pointer with the specified ID (the ID of winit touch events).
+pub(crate) fn pointer_type(pointer_id: u64) -> PointerType {
+    let mut ty = POINTER_INPUT_TYPE::default();
+    // SAFETY: `ty` is a valid pointer
+    if unsafe { GetPointerType(pointer_id as u32, &mut ty) }.is_err() {
+        return PointerType::Other;
+    }
+    match ty {
+        PT_PEN => PointerType::Pen,
+        PT_TOUCH => PointerType::Touch,
+        PT_MOUSE | PT_TOUCHPAD => PointerType::Mouse,
+        _ => PointerType::Other,
+    }
+}
+
+/// Returns the state of the pen with the specified pointer ID, or `None` if the pointer isn't a pen.
+pub(crate) fn pen_info(pointer_id: u64) -> Option<PenInfo> {
+    let mut info = POINTER_PEN_INFO::default();
+    // SAFETY: `info` is a valid pointer
+    unsafe { GetPointerPenInfo(pointer_id as u32, &mut info) }.ok()?;
+    let has = |mask: u32| info.penMask & mask != 0;
+    Some(PenInfo {
+        pressure: has(PEN_MASK_PRESSURE).then(|| (info.pressure as f32 / MAX_PEN_PRESSURE).clamp(0.0, 1.0)),
+        tilt: Vec2::new(
+            if has(PEN_MASK_TILT_X) { info.tiltX as f64 } else { 0.0 },
+            if has(PEN_MASK_TILT_Y) { info.tiltY as f64 } else { 0.0 },
+        ),
+        twist: if has(PEN_MASK_ROTATION) { info.rotation as f32 } else { 0.0 },
+        barrel: info.penFlags & PEN_FLAG_BARREL != 0,
+        eraser: info.penFlags & (PEN_FLAG_INVERTED | PEN_FLAG_ERASER) != 0,
+    })
+}
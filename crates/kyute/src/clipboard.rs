@@ -0,0 +1,43 @@
+//! Access to the system clipboard.
+//!
+//! Only text is supported. Clipboard errors are logged and otherwise ignored: a failed copy
+//! leaves the clipboard unchanged, and a failed paste behaves as if the clipboard were empty.
+use std::cell::RefCell;
+
+use copypasta::{ClipboardContext, ClipboardProvider};
+use tracing::warn;
+
+thread_local! {
+    static CLIPBOARD: RefCell<Option<ClipboardContext>> = RefCell::new(match ClipboardContext::new() {
+        Ok(ctx) => Some(ctx),
+        Err(err) => {
+            warn!("clipboard unavailable: {err}");
+            None
+        }
+    });
+}
+
+/// Returns the text in the clipboard, or `None` if the clipboard is empty or doesn't contain text.
+pub fn get_text() -> Option<String> {
+    CLIPBOARD.with(|clipboard| {
+        let mut clipboard = clipboard.borrow_mut();
+        let text = clipboard.as_mut()?.get_contents().ok()?;
+        (!text.is_empty()).then_some(text)
+    })
+}
+
+/// Replaces the contents of the clipboard with the specified text.
+pub fn set_text(text: &str) {
+    CLIPBOARD.with(|clipboard| {
+        if let Some(clipboard) = clipboard.borrow_mut().as_mut() {
+            if let Err(err) = clipboard.set_contents(text.to_string()) {
+                warn!("failed to copy to the clipboard: {err}");
+            }
+        }
+    })
+}
+
+/// Returns whether the clipboard contains text that can be pasted.
+pub fn has_text() -> bool {
+    get_text().is_some()
+}
@@ -1,4 +1,6 @@
 //! System compositor interface
+use std::time::Duration;
+
 use raw_window_handle::RawWindowHandle;
 use skia_safe as sk;
 
@@ -7,15 +9,34 @@ use crate::app_globals::AppGlobals;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
+enum SurfaceKind {
+    /// Surface of a compositor layer, presented when dropped.
+    Backend(backend::DrawableSurface),
+    /// CPU surface, not presented anywhere.
+    Raster(sk::Surface),
+}
+
 /// A drawable surface
 pub struct DrawableSurface {
-    backend: backend::DrawableSurface,
+    kind: SurfaceKind,
 }
 
 impl DrawableSurface {
+    /// Creates a CPU-backed drawable surface of the specified size in pixels, for offscreen painting.
+    pub fn raster(width: i32, height: i32) -> DrawableSurface {
+        let surface =
+            sk::surfaces::raster_n32_premul((width.max(1), height.max(1))).expect("failed to create raster surface");
+        DrawableSurface {
+            kind: SurfaceKind::Raster(surface),
+        }
+    }
+
     /// Returns the underlying skia surface.
     pub fn surface(&self) -> sk::Surface {
-        self.backend.surface()
+        match self.kind {
+            SurfaceKind::Backend(ref backend) => backend.surface(),
+            SurfaceKind::Raster(ref surface) => surface.clone(),
+        }
     }
 }
 
@@ -71,6 +92,63 @@ impl ColorType {
     }
 }
 
+/// Color space of the contents of a surface layer.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum SurfaceColorSpace {
+    /// Linear values with Rec.709 primaries, in half floats (scRGB).
+    ///
+    /// 1.0 is the SDR reference white. On HDR displays, values above 1.0 are brighter than the
+    /// reference white.
+    #[default]
+    ScRgb,
+    /// ST 2084 (PQ) transfer function with Rec.2020 primaries, in 10 bits per component (HDR10).
+    ///
+    /// Should be combined with `HdrMetadata` describing the content.
+    Hdr10,
+}
+
+/// Static metadata describing the contents of an HDR10 surface, so that the display can adapt them
+/// to its capabilities.
+///
+/// Luminance values are in nits (cd/m²).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HdrMetadata {
+    /// Maximum luminance of the display on which the content was mastered.
+    pub max_mastering_luminance: f32,
+    /// Minimum luminance of the display on which the content was mastered.
+    pub min_mastering_luminance: f32,
+    /// Maximum luminance of any pixel of the content (MaxCLL).
+    pub max_content_light_level: f32,
+    /// Maximum average luminance of a frame of the content (MaxFALL).
+    pub max_frame_average_light_level: f32,
+}
+
+/// Luminance capabilities of the display showing a surface layer.
+///
+/// Luminance values are in nits (cd/m²).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DisplayInfo {
+    /// Whether HDR output is enabled on the display.
+    pub hdr_enabled: bool,
+    pub min_luminance: f32,
+    /// Maximum luminance of a small area of the display.
+    pub max_luminance: f32,
+    /// Maximum luminance when the whole display is lit.
+    pub max_full_frame_luminance: f32,
+}
+
+/// Presentation feedback for a surface layer, returned by `Layer::presentation_feedback`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PresentFeedback {
+    /// Number of vertical blanks missed by the last frame that reached the screen.
+    ///
+    /// A frame misses a vblank when it was presented in time for it, but only displayed later.
+    pub missed_vsyncs: u32,
+    /// Time between presentation and display of the last frame that reached the screen,
+    /// if known.
+    pub latency: Option<Duration>,
+}
+
 /// Handle to a compositor layer.
 pub struct Layer(backend::Layer);
 
@@ -82,6 +160,13 @@ impl Layer {
         self.0.wait_for_presentation();
     }
 
+    /// Returns presentation statistics of the surface.
+    ///
+    /// Should be called once after each frame is presented (i.e. after the `DrawableSurface` is dropped).
+    pub fn presentation_feedback(&self) -> PresentFeedback {
+        self.0.presentation_feedback()
+    }
+
     /// Creates a skia drawing context to paint on the specified surface layer.
     ///
     /// Only one drawing context can be active at a time.
@@ -96,7 +181,7 @@ impl Layer {
         // is not very ergonomic (methods like `size()` would be inaccessible, even though
         // it's perfectly OK to call while a DrawableSurface is active).
         DrawableSurface {
-            backend: self.0.acquire_drawing_surface(),
+            kind: SurfaceKind::Backend(self.0.acquire_drawing_surface()),
         }
     }
 
@@ -105,6 +190,32 @@ impl Layer {
         self.0.set_surface_size(size);
     }
 
+    /// Returns the color space of the contents of the surface layer.
+    pub fn color_space(&self) -> SurfaceColorSpace {
+        self.0.color_space()
+    }
+
+    /// Changes the color space of the contents of the surface layer.
+    ///
+    /// Returns false if the color space is not supported by the display, in which case the color
+    /// space is left unchanged. Drawable surfaces acquired after this call are in the new color space,
+    /// and skia converts the colors drawn on them.
+    pub fn set_color_space(&self, color_space: SurfaceColorSpace) -> bool {
+        self.0.set_color_space(color_space)
+    }
+
+    /// Sets the HDR metadata of the surface contents. Only used by HDR10 surfaces.
+    pub fn set_hdr_metadata(&self, metadata: &HdrMetadata) {
+        self.0.set_hdr_metadata(metadata)
+    }
+
+    /// Returns the luminance capabilities of the display showing the layer.
+    ///
+    /// Returns `None` if the layer is not bound to a window, or if the information is not available.
+    pub fn display_info(&self) -> Option<DisplayInfo> {
+        self.0.display_info()
+    }
+
     /// Binds a layer to a native window.
     pub unsafe fn bind_to_window(&self, window: RawWindowHandle) {
         self.0.bind_to_window(window)
@@ -119,7 +230,7 @@ impl Layer {
     /// * size Size of the surface in pixels
     /// * format Pixel format
     pub fn new_surface(size: Size, format: ColorType) -> Layer {
-        Layer(AppGlobals::get().backend.create_surface_layer(size, format))
+        Layer(AppGlobals::get().backend().create_surface_layer(size, format))
     }
 }
 
@@ -0,0 +1,311 @@
+//! Modal dialogs, message boxes, and file dialogs.
+//!
+//! Dialogs are separate windows shown over a parent window. While a dialog is open, pointer and
+//! keyboard input to the parent is blocked (see `Window::set_modal_dialog`).
+//!
+//! # Example
+//!
+//! ```ignore
+//! let result = message_box(&main_window, MessageKind::Warning, "Unsaved changes", "Save before closing?", DialogButtons::YesNoCancel).await;
+//! if result == DialogResult::Yes { ... }
+//! ```
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use futures_util::future::select_all;
+use futures_util::FutureExt;
+use kurbo::Size;
+use raw_window_handle::RawWindowHandle;
+use scopeguard::defer;
+use tokio::select;
+
+use crate::layout::flex::Axis;
+use crate::layout::{PaddingBottom, PaddingLeft, PaddingRight, PaddingTop};
+use crate::text::TextStyle;
+use crate::theme::{self, palette};
+use crate::widgets::button::button;
+use crate::widgets::frame::{Frame, FrameLayout, FrameStyle};
+use crate::widgets::text::Text;
+use crate::{text, Color, Element, Window, WindowOptions};
+
+/// The button that closed a dialog.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DialogResult {
+    Ok,
+    Cancel,
+    Yes,
+    No,
+}
+
+impl DialogResult {
+    fn label(&self) -> &'static str {
+        match self {
+            DialogResult::Ok => "OK",
+            DialogResult::Cancel => "Cancel",
+            DialogResult::Yes => "Yes",
+            DialogResult::No => "No",
+        }
+    }
+}
+
+/// Standard sets of dialog buttons.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DialogButtons {
+    Ok,
+    OkCancel,
+    YesNo,
+    YesNoCancel,
+}
+
+impl DialogButtons {
+    fn results(&self) -> &'static [DialogResult] {
+        match self {
+            DialogButtons::Ok => &[DialogResult::Ok],
+            DialogButtons::OkCancel => &[DialogResult::Ok, DialogResult::Cancel],
+            DialogButtons::YesNo => &[DialogResult::Yes, DialogResult::No],
+            DialogButtons::YesNoCancel => &[DialogResult::Yes, DialogResult::No, DialogResult::Cancel],
+        }
+    }
+
+    /// Result returned when the dialog is closed without clicking a button.
+    fn dismiss_result(&self) -> DialogResult {
+        match self {
+            DialogButtons::Ok => DialogResult::Ok,
+            DialogButtons::YesNo => DialogResult::No,
+            DialogButtons::OkCancel | DialogButtons::YesNoCancel => DialogResult::Cancel,
+        }
+    }
+}
+
+/// Severity of a message box, which determines its icon.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MessageKind {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl MessageKind {
+    pub(crate) fn icon(&self) -> (&'static str, Color) {
+        match self {
+            MessageKind::Info => ("ℹ", palette::LIGHT_BLUE_400),
+            MessageKind::Success => ("✔", palette::GREEN_500),
+            MessageKind::Warning => ("⚠", palette::AMBER_500),
+            MessageKind::Error => ("⛔", palette::RED_500),
+        }
+    }
+}
+
+fn dialog_frame(direction: Axis) -> Rc<Frame> {
+    Frame::new(FrameStyle {
+        layout: FrameLayout::Flex { direction },
+        ..Default::default()
+    })
+}
+
+/// Shows a modal dialog with the specified content and buttons over `parent`, and waits for it to be closed.
+///
+/// Returns the button that was clicked. Closing the dialog window is equivalent to clicking "Cancel", or
+/// "No" if there's no "Cancel" button.
+pub async fn show_dialog(parent: &Window, title: &str, content: &Element, buttons: DialogButtons) -> DialogResult {
+    let root = dialog_frame(Axis::Vertical);
+    PaddingLeft.set(&root, 16.0.into());
+    PaddingRight.set(&root, 16.0.into());
+    PaddingTop.set(&root, 16.0.into());
+    PaddingBottom.set(&root, 12.0.into());
+    root.add_child(content);
+
+    let button_row = dialog_frame(Axis::Horizontal);
+    PaddingTop.set(&button_row, 16.0.into());
+    let results = buttons.results();
+    let button_frames: Vec<_> = results
+        .iter()
+        .map(|result| {
+            let b = button(result.label());
+            b.set_tab_focusable(true);
+            button_row.add_child(&b);
+            b
+        })
+        .collect();
+    root.add_child(&button_row);
+
+    // the dialog is laid out at the UI scale of its parent
+    let ui_scale = parent.ui_scale();
+    let size = Size::new(400.0, 160.0) * ui_scale;
+    let options = WindowOptions {
+        title,
+        size,
+        parent: Some(parent.raw_window_handle()),
+        position: Some(parent.centered_position(size)),
+        background: theme::current().content_background_color,
+        ui_scale,
+        ..Default::default()
+    };
+    let dialog = Window::new(&options, &root);
+    parent.set_modal_dialog(&dialog);
+    // unblock the parent even if this future is dropped before the dialog is closed
+    defer! { parent.clear_modal_dialog(); }
+    dialog.set_focus(Some(&**button_frames[0])).await;
+
+    let clicked = select_all(button_frames.iter().map(|b| b.clicked().boxed_local()));
+    select! {
+        (_, index, _) = clicked => results[index],
+        _ = dialog.close_requested() => buttons.dismiss_result(),
+    }
+}
+
+/// Shows a standard message box.
+pub async fn message_box(
+    parent: &Window,
+    kind: MessageKind,
+    title: &str,
+    message: &str,
+    buttons: DialogButtons,
+) -> DialogResult {
+    let theme = &theme::current();
+    let (icon, icon_color) = kind.icon();
+    let text_style = TextStyle::new()
+        .font_size(theme.font_size as f32)
+        .font_family(theme.font_family)
+        .color(theme.text_color);
+
+    let content = dialog_frame(Axis::Horizontal);
+    let icon_style = text_style.clone().font_size(24.0).color(icon_color);
+    let icon = Text::new(text!( style(icon_style) "{icon}" ));
+    PaddingRight.set(&icon, 12.0.into());
+    content.add_child(&icon);
+    content.add_child(&Text::new(text!( style(text_style) "{message}" )));
+
+    show_dialog(parent, title, &content, buttons).await
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// File dialogs
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FileDialogKind {
+    Open,
+    Save,
+    PickFolder,
+}
+
+/// A named group of file extensions shown in file dialogs (e.g. "Images": `["png", "jpg"]`).
+#[derive(Clone, Debug)]
+pub struct FileFilter {
+    pub name: String,
+    /// Extensions without the leading dot.
+    pub extensions: Vec<String>,
+}
+
+impl FileFilter {
+    pub fn new(name: impl Into<String>, extensions: &[&str]) -> FileFilter {
+        FileFilter {
+            name: name.into(),
+            extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FileDialogOptions {
+    pub kind: FileDialogKind,
+    pub title: String,
+    pub filters: Vec<FileFilter>,
+    /// Directory shown when the dialog opens. Defaults to the last directory used by the application.
+    pub directory: Option<PathBuf>,
+    /// Initial file name (save dialogs).
+    pub file_name: Option<String>,
+    /// Allow selecting multiple files (open dialogs).
+    pub multiple: bool,
+}
+
+impl FileDialogOptions {
+    pub fn new(kind: FileDialogKind) -> FileDialogOptions {
+        FileDialogOptions {
+            kind,
+            title: String::new(),
+            filters: vec![],
+            directory: None,
+            file_name: None,
+            multiple: false,
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn filter(mut self, name: impl Into<String>, extensions: &[&str]) -> Self {
+        self.filters.push(FileFilter::new(name, extensions));
+        self
+    }
+
+    pub fn directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    pub fn file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    pub fn multiple(mut self, multiple: bool) -> Self {
+        self.multiple = multiple;
+        self
+    }
+}
+
+/// Shows a platform file dialog and returns the selected paths (empty if the dialog was cancelled).
+///
+/// The dialog runs on a separate thread so that the event loop (and other windows) keep running;
+/// input to `parent` is blocked by the platform while it is open.
+pub async fn show_file_dialog(parent: Option<&Window>, options: FileDialogOptions) -> Vec<PathBuf> {
+    let owner = parent.and_then(|w| match w.raw_window_handle() {
+        RawWindowHandle::Win32(h) => Some(h.hwnd.get()),
+        _ => None,
+    });
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        let paths = crate::backend::show_file_dialog(&options, owner);
+        let _ = tx.send(paths);
+    });
+    rx.await.unwrap_or_default()
+}
+
+/// Shows a file open dialog, in `directory` if specified.
+pub async fn open_file(
+    parent: &Window,
+    title: &str,
+    filters: &[FileFilter],
+    directory: Option<&Path>,
+) -> Option<PathBuf> {
+    let mut options = FileDialogOptions::new(FileDialogKind::Open).title(title);
+    options.filters = filters.to_vec();
+    options.directory = directory.map(Path::to_path_buf);
+    show_file_dialog(Some(parent), options).await.into_iter().next()
+}
+
+/// Shows a file save dialog, in `directory` if specified.
+pub async fn save_file(
+    parent: &Window,
+    title: &str,
+    filters: &[FileFilter],
+    directory: Option<&Path>,
+    file_name: &str,
+) -> Option<PathBuf> {
+    let mut options = FileDialogOptions::new(FileDialogKind::Save).title(title).file_name(file_name);
+    options.filters = filters.to_vec();
+    options.directory = directory.map(Path::to_path_buf);
+    show_file_dialog(Some(parent), options).await.into_iter().next()
+}
+
+/// Shows a directory picker, in `directory` if specified.
+pub async fn pick_folder(parent: &Window, title: &str, directory: Option<&Path>) -> Option<PathBuf> {
+    let mut options = FileDialogOptions::new(FileDialogKind::PickFolder).title(title);
+    options.directory = directory.map(Path::to_path_buf);
+    show_file_dialog(Some(parent), options).await.into_iter().next()
+}
@@ -1,7 +1,7 @@
 //! Drawing-related wrappers and helpers for use with skia.
 // re-export kurbo types
-use kurbo::{Affine, Point, Rect, Vec2};
-pub use kurbo::{RoundedRect, RoundedRectRadii, Shape};
+use kurbo::{Affine, PathEl, Point, Rect, Vec2};
+pub use kurbo::{BezPath, RoundedRect, RoundedRectRadii, Shape};
 use skia_safe as sk;
 
 pub use border::BorderStyle;
@@ -220,6 +220,34 @@ fn radii_to_skia(radii: &RoundedRectRadii) -> [sk::Vector; 4] {
     ]
 }
 
+impl ToSkia for BezPath {
+    type Target = sk::Path;
+
+    fn to_skia(&self) -> Self::Target {
+        let mut path = sk::Path::new();
+        for el in self.elements() {
+            match *el {
+                PathEl::MoveTo(p) => {
+                    path.move_to(p.to_skia());
+                }
+                PathEl::LineTo(p) => {
+                    path.line_to(p.to_skia());
+                }
+                PathEl::QuadTo(p1, p2) => {
+                    path.quad_to(p1.to_skia(), p2.to_skia());
+                }
+                PathEl::CurveTo(p1, p2, p3) => {
+                    path.cubic_to(p1.to_skia(), p2.to_skia(), p3.to_skia());
+                }
+                PathEl::ClosePath => {
+                    path.close();
+                }
+            }
+        }
+        path
+    }
+}
+
 impl ToSkia for RoundedRect {
     type Target = skia_safe::RRect;
 
@@ -2,6 +2,7 @@ use std::any::{Any, TypeId};
 use std::cell::{Cell, Ref, RefCell, UnsafeCell};
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
+use std::future::Future;
 use std::marker::PhantomPinned;
 use std::mem;
 use std::ops::Deref;
@@ -10,13 +11,17 @@ use std::rc::{Rc, Weak};
 
 use crate::compositor::DrawableSurface;
 use bitflags::bitflags;
+use futures::future::AbortHandle;
 use futures_util::future::LocalBoxFuture;
 use futures_util::FutureExt;
-use kurbo::{Affine, Point, Size, Vec2};
+use kurbo::{Affine, BezPath, Point, Rect, RoundedRect, Shape, Size, Vec2};
 use tracing::warn;
 
-use crate::event::Event;
+use crate::application::spawn;
+use crate::event::{Event, TaskId, TaskResult};
 use crate::layout::{LayoutInput, LayoutOutput};
+use crate::paint_cache::{PaintCache, PaintCaching};
+use crate::perf::count_paint_cache_hit;
 use crate::window::WeakWindow;
 use crate::PaintCtx;
 
@@ -25,6 +30,9 @@ bitflags! {
     pub struct ChangeFlags: u32 {
         const PAINT = 0b0001;
         const LAYOUT = 0b0010;
+        /// A descendant needs to be laid out again, but not this element (the change is absorbed
+        /// by a relayout boundary).
+        const CHILD_LAYOUT = 0b0100;
         const NONE = 0b0000;
     }
 }
@@ -60,6 +68,37 @@ pub trait AttachedProperty: Any {
     }
 }
 
+/// Clip shape of an element, in the local coordinate space of the element.
+///
+/// The clip applies to the painting of the element and its descendants, and points outside of it
+/// don't hit the element or its descendants.
+#[derive(Clone, Debug)]
+pub enum Clip {
+    Rect(Rect),
+    RoundedRect(RoundedRect),
+    Path(BezPath),
+}
+
+impl Clip {
+    /// Returns whether the specified point (in local coordinates) is inside the clip shape.
+    pub fn contains(&self, point: Point) -> bool {
+        match self {
+            Clip::Rect(rect) => rect.contains(point),
+            Clip::RoundedRect(rrect) => rrect.contains(point),
+            Clip::Path(path) => path.contains(point),
+        }
+    }
+
+    /// Returns the bounding box of the clip shape.
+    pub fn bounding_box(&self) -> Rect {
+        match self {
+            Clip::Rect(rect) => *rect,
+            Clip::RoundedRect(rrect) => rrect.rect(),
+            Clip::Path(path) => path.bounding_box(),
+        }
+    }
+}
+
 /// Wrapper over Rc<dyn Visual> that has PartialEq impl.
 #[derive(Clone)]
 #[repr(transparent)]
@@ -256,10 +295,17 @@ pub struct Element {
     pub(crate) window: RefCell<WeakWindow>,
     /// Layout: transform from local to parent coordinates.
     transform: Cell<Affine>,
+    /// Transform applied in local coordinates before the layout transform (see `set_render_transform`).
+    render_transform: Cell<Affine>,
+    /// Clip shape, in local coordinates.
+    clip: RefCell<Option<Clip>>,
     /// Layout: geometry (size and baseline) of this element.
     geometry: Cell<Size>,
-    /// TODO unused
     change_flags: Cell<ChangeFlags>,
+    /// Whether this element is a relayout boundary (see `set_relayout_boundary`).
+    relayout_boundary: Cell<bool>,
+    /// Size passed to the last call to `do_layout`, and its result.
+    last_layout: Cell<Option<(Size, LayoutOutput)>>,
     // List of child elements.
     //children: RefCell<Vec<AnyVisual>>,
     /// Name of the element.
@@ -268,6 +314,16 @@ pub struct Element {
     focusable: Cell<bool>,
     /// Map of attached properties.
     attached_properties: UnsafeCell<BTreeMap<TypeId, Box<dyn Any>>>,
+    /// Tasks spawned with `spawn_local` that haven't completed yet.
+    tasks: RefCell<Vec<(TaskId, AbortHandle)>>,
+    /// Recorded paint output of this element and its descendants.
+    paint_cache: PaintCache,
+}
+
+impl Drop for Element {
+    fn drop(&mut self) {
+        self.abort_tasks();
+    }
 }
 
 impl Element {
@@ -284,11 +340,17 @@ impl Element {
             window: Default::default(),
             parent: Default::default(),
             transform: Cell::new(Affine::default()),
+            render_transform: Cell::new(Affine::default()),
+            clip: RefCell::new(None),
             geometry: Cell::new(Size::default()),
             change_flags: Cell::new(ChangeFlags::LAYOUT | ChangeFlags::PAINT),
+            relayout_boundary: Cell::new(false),
+            last_layout: Cell::new(None),
             name: RefCell::new(format!("{:p}", weak_this.as_ptr())),
             focusable: Cell::new(false),
             attached_properties: Default::default(),
+            tasks: Default::default(),
+            paint_cache: Default::default(),
         }
     }
 
@@ -457,6 +519,19 @@ impl Element {
         self.window.borrow().set_pointer_capture(self);
     }
 
+    /// Enables or disables IME composition in the parent window.
+    ///
+    /// Text input elements should enable it when they gain focus, and disable it when they lose it.
+    pub fn set_ime_allowed(&self, allowed: bool) {
+        self.window.borrow().set_ime_allowed(allowed);
+    }
+
+    /// Sets the area of the text being composed (usually the caret rectangle), in local coordinates.
+    pub fn set_ime_cursor_area(&self, rect: Rect) {
+        let rect = self.window_transform().transform_rect_bbox(rect);
+        self.window.borrow().set_ime_cursor_area(rect);
+    }
+
     /*pub fn children(&self) -> Ref<[AnyVisual]> {
         Ref::map(self.children.borrow(), |v| v.as_slice())
     }*/
@@ -475,8 +550,11 @@ impl Element {
     }
 
     /// Removes all child visuals.
+    ///
+    /// Tasks spawned by the removed elements and their descendants are aborted.
     pub fn clear_children(&self) {
         for c in self.children().iter() {
+            c.abort_tasks_recursive();
             // TODO: don't do that if there's only one reference remaining
             // detach from window
             c.window.replace(WeakWindow::default());
@@ -492,9 +570,9 @@ impl Element {
 
     /// Returns the transform of this visual relative to its parent.
     ///
-    /// Shorthand for `self.element().transform.get()`.
+    /// This is the layout transform combined with the render transform of the element.
     pub fn transform(&self) -> Affine {
-        self.transform.get()
+        self.transform.get() * self.render_transform.get()
     }
 
     /// This should be called by `Visual::layout()` so this doesn't set the layout dirty flag.
@@ -507,6 +585,37 @@ impl Element {
         self.set_transform(Affine::translate(offset));
     }
 
+    /// Returns the render transform of this element.
+    pub fn render_transform(&self) -> Affine {
+        self.render_transform.get()
+    }
+
+    /// Sets a transform applied to this element and its descendants, in addition to the transform
+    /// set by the layout.
+    ///
+    /// The transform is applied in the local coordinate space of the element (use `Affine::rotate_about`
+    /// or `Affine::scale_about` to rotate or scale around a point of the element). It affects painting,
+    /// hit-testing and the positions of pointer events, but not layout: the parent still lays out the
+    /// element as if it wasn't transformed.
+    pub fn set_render_transform(&self, transform: Affine) {
+        self.render_transform.set(transform);
+        self.mark_needs_repaint();
+    }
+
+    /// Returns the clip shape of this element.
+    pub fn clip(&self) -> Option<Clip> {
+        self.clip.borrow().clone()
+    }
+
+    /// Sets the clip shape of this element, in local coordinates.
+    ///
+    /// Painting of this element and its descendants is restricted to the shape, and points outside
+    /// of it are not delivered to this element or its descendants.
+    pub fn set_clip(&self, clip: Option<Clip>) {
+        self.clip.replace(clip);
+        self.mark_needs_repaint();
+    }
+
     /// Returns the transform from this visual's coordinate space to the coordinate space of the parent window.
     ///
     /// This walks up the parent chain and multiplies the transforms, so consider reusing the result instead
@@ -552,10 +661,16 @@ impl Element {
     }*/
 
     fn set_dirty_flags(&self, flags: ChangeFlags) {
-        let flags = self.change_flags.get() | flags;
-        self.change_flags.set(flags);
+        self.change_flags.set(self.change_flags.get() | flags);
         if let Some(parent) = self.parent() {
-            parent.set_dirty_flags(flags);
+            let mut parent_flags = flags;
+            if self.relayout_boundary.get() && flags.intersects(ChangeFlags::LAYOUT | ChangeFlags::CHILD_LAYOUT) {
+                // the size of a relayout boundary doesn't depend on its contents, so the parent
+                // doesn't need to be laid out again
+                parent_flags.remove(ChangeFlags::LAYOUT);
+                parent_flags.insert(ChangeFlags::CHILD_LAYOUT);
+            }
+            parent.set_dirty_flags(parent_flags);
         }
         if flags.contains(ChangeFlags::PAINT) {
             // TODO: maybe don't call repaint for every widget in the hierarchy. winit should coalesce repaint requests, but still
@@ -571,22 +686,127 @@ impl Element {
         self.set_dirty_flags(ChangeFlags::LAYOUT | ChangeFlags::PAINT);
     }
 
+    /// Marks this element and all of its descendants as needing to be laid out and painted again.
+    ///
+    /// Unlike `mark_needs_relayout`, this also invalidates the cached layout and paint output of
+    /// the descendants. Use this when something that all elements depend on changes, like the theme.
+    pub fn mark_subtree_needs_relayout(&self) {
+        fn mark_rec(element: &Element) {
+            element
+                .change_flags
+                .set(element.change_flags.get() | ChangeFlags::LAYOUT | ChangeFlags::PAINT);
+            for child in element.children().iter() {
+                mark_rec(child);
+            }
+        }
+        mark_rec(self);
+        // propagate to the ancestors and request a repaint
+        self.mark_needs_relayout();
+    }
+
     pub(crate) fn mark_layout_done(&self) {
-        self.change_flags.set(self.change_flags.get() & !ChangeFlags::LAYOUT);
+        self.change_flags
+            .set(self.change_flags.get() & !(ChangeFlags::LAYOUT | ChangeFlags::CHILD_LAYOUT));
+    }
+
+    /// Makes this element a relayout boundary.
+    ///
+    /// Changes inside a relayout boundary don't cause its ancestors to be laid out again:
+    /// only the boundary and the affected elements inside it are. Use this for elements whose size
+    /// only depends on the size given by their parent (e.g. fixed-size panels, viewports, scroll views),
+    /// not on their contents.
+    pub fn set_relayout_boundary(&self, boundary: bool) {
+        self.relayout_boundary.set(boundary);
     }
 
     pub(crate) fn mark_paint_done(&self) {
         self.change_flags.set(self.change_flags.get() & !ChangeFlags::PAINT);
     }
 
+    /// Returns whether this element, or one of its descendants, needs to be laid out again.
     pub fn needs_relayout(&self) -> bool {
-        self.change_flags.get().contains(ChangeFlags::LAYOUT)
+        self.change_flags.get().intersects(ChangeFlags::LAYOUT | ChangeFlags::CHILD_LAYOUT)
     }
 
     pub fn needs_repaint(&self) -> bool {
         self.change_flags.get().contains(ChangeFlags::PAINT)
     }
 
+    /// Hints that the paint output of this element and its descendants should be cached.
+    ///
+    /// Equivalent to `set_paint_caching(PaintCaching::Always)`. Use this on complex subtrees that
+    /// rarely change, like parameter panels, when the automatic heuristics don't pick them up.
+    pub fn cache_paint(&self) {
+        self.set_paint_caching(PaintCaching::Always);
+    }
+
+    /// Sets whether the paint output of this element and its descendants is cached.
+    ///
+    /// The default is `PaintCaching::Auto`.
+    pub fn set_paint_caching(&self, caching: PaintCaching) {
+        self.paint_cache.mode.set(caching);
+        if caching == PaintCaching::Never {
+            self.paint_cache.clear();
+        }
+    }
+
+    /// Spawns a task on the main-thread executor whose lifetime is tied to this element.
+    ///
+    /// The task is aborted when the element is dropped, or removed with `clear_children`.
+    /// Its output is sent back to the element in an `Event::TaskCompleted` event, which is
+    /// never delivered to an element that has been dropped.
+    ///
+    /// Use this instead of `application::spawn` for loads and other work started by an element
+    /// whose result is only useful to the element.
+    pub fn spawn_local<T: 'static>(&self, fut: impl Future<Output = T> + 'static) -> TaskId {
+        thread_local! {
+            static NEXT_TASK_ID: Cell<u64> = Cell::new(0);
+        }
+        let id = TaskId(NEXT_TASK_ID.with(|next| next.replace(next.get() + 1)));
+        let weak_this = self.weak_this.clone();
+        let handle = spawn(async move {
+            let value = fut.await;
+            let Some(this) = weak_this.upgrade() else { return };
+            this.tasks.borrow_mut().retain(|(task, _)| *task != id);
+            let mut event = Event::TaskCompleted(TaskResult {
+                task: id,
+                value: Rc::new(value),
+            });
+            this.send_event(&mut event).await;
+        });
+        self.tasks.borrow_mut().push((id, handle));
+        id
+    }
+
+    /// Aborts a task spawned with `spawn_local`. Its result won't be delivered.
+    pub fn abort_task(&self, task: TaskId) {
+        self.tasks.borrow_mut().retain(|(id, handle)| {
+            if *id == task {
+                handle.abort();
+            }
+            *id != task
+        });
+    }
+
+    /// Aborts all the tasks spawned with `spawn_local` that haven't completed yet.
+    pub fn abort_tasks(&self) {
+        for (_, handle) in self.tasks.take() {
+            handle.abort();
+        }
+    }
+
+    /// Returns whether tasks spawned with `spawn_local` are still running.
+    pub fn has_pending_tasks(&self) -> bool {
+        !self.tasks.borrow().is_empty()
+    }
+
+    fn abort_tasks_recursive(&self) {
+        self.abort_tasks();
+        for child in self.children().iter() {
+            child.abort_tasks_recursive();
+        }
+    }
+
     /// Sets the value of an attached property.
     ///
     /// This replaces the value if the property is already set.
@@ -746,9 +966,30 @@ impl dyn ElementMethods + '_ {
 
 
     pub fn do_layout(&self, size: Size) -> LayoutOutput {
+        let flags = self.change_flags.get();
+        if let Some((last_size, last_output)) = self.last_layout.get() {
+            if last_size == size && !flags.contains(ChangeFlags::LAYOUT) {
+                // The layout of this element hasn't changed, but some descendants may need
+                // to be laid out again: do so in place, with the same size as before.
+                if flags.contains(ChangeFlags::CHILD_LAYOUT) {
+                    for child in self.children().iter() {
+                        if child.needs_relayout() {
+                            if let Some((child_size, _)) = child.last_layout.get() {
+                                child.do_layout(child_size);
+                            }
+                        }
+                    }
+                }
+                self.mark_layout_done();
+                return last_output;
+            }
+        }
+
         let children = self.children();
         let geometry = self.layout(&*children, size);
+        crate::perf::count_layout();
         self.geometry.set(Size::new(geometry.width, geometry.height));
+        self.last_layout.set(Some((size, geometry)));
         self.mark_layout_done();
         geometry
     }
@@ -762,13 +1003,14 @@ impl dyn ElementMethods + '_ {
     pub(crate) fn do_hit_test(&self, point: Point) -> Vec<AnyVisual> {
         // Helper function to recursively hit-test the children of a visual.
         // point: point in the local coordinate space of the visual
-        // transform: accumulated transform from the local coord space of `visual` to the root coord space
-        fn hit_test_rec(
-            visual: &dyn ElementMethods,
-            point: Point,
-            transform: Affine,
-            result: &mut Vec<AnyVisual>,
-        ) -> bool {
+        fn hit_test_rec(visual: &dyn ElementMethods, point: Point, result: &mut Vec<AnyVisual>) -> bool {
+            // points outside the clip don't hit the visual or its children
+            if let Some(clip) = visual.clip.borrow().as_ref() {
+                if !clip.contains(point) {
+                    return false;
+                }
+            }
+
             let mut hit = false;
             // hit-test ourselves
             if visual.hit_test(point) {
@@ -777,9 +1019,8 @@ impl dyn ElementMethods + '_ {
             }
 
             for child in visual.children().iter() {
-                let transform = transform * child.transform();
-                let local_point = transform.inverse() * point;
-                if hit_test_rec(&**child, local_point, transform, result) {
+                let local_point = child.transform().inverse() * point;
+                if hit_test_rec(&**child, local_point, result) {
                     hit = true;
                     break;
                 }
@@ -789,29 +1030,70 @@ impl dyn ElementMethods + '_ {
         }
 
         let mut path = Vec::new();
-        hit_test_rec(self, point, self.transform(), &mut path);
+        hit_test_rec(self, point, &mut path);
         path
     }
 
-    pub fn do_paint(&self, surface: &DrawableSurface, scale_factor: f64) {
+    /// Paints this visual and its children. Returns the number of elements painted.
+    pub fn do_paint(&self, surface: &DrawableSurface, scale_factor: f64) -> usize {
         let mut paint_ctx = PaintCtx {
             scale_factor,
             window_transform: Default::default(),
             surface,
+            paint_count: 0,
+            recorders: Default::default(),
         };
 
+        /// Margin around the bounds of recorded pictures, for shadows and other effects
+        /// drawn outside the element.
+        const PICTURE_BOUNDS_MARGIN: f64 = 64.0;
+
         // Recursively paint the UI tree.
-        fn paint_rec(visual: &dyn ElementMethods, ctx: &mut PaintCtx) {
+        fn paint_subtree(visual: &dyn ElementMethods, ctx: &mut PaintCtx) {
             visual.paint(ctx);
+            ctx.paint_count += 1;
             for child in visual.children().iter() {
                 ctx.with_transform(&child.transform(), |ctx| {
-                    // TODO clipping
                     paint_rec(&**child, ctx);
                     child.mark_paint_done();
                 });
             }
         }
 
+        fn paint_contents(visual: &dyn ElementMethods, ctx: &mut PaintCtx) {
+            let cache = &visual.paint_cache;
+            let size = visual.size();
+            let changed = visual.needs_repaint();
+            if changed {
+                cache.invalidate();
+            } else if let Some(picture) = cache.get(size) {
+                ctx.draw_picture(&picture);
+                count_paint_cache_hit();
+                return;
+            }
+
+            let start = ctx.paint_count;
+            if cache.should_record() {
+                let bounds = size.to_rect().inflate(PICTURE_BOUNDS_MARGIN, PICTURE_BOUNDS_MARGIN);
+                if let Some(picture) = ctx.record_picture(bounds, |ctx| paint_subtree(visual, ctx)) {
+                    ctx.draw_picture(&picture);
+                    cache.store(size, picture);
+                }
+            } else {
+                paint_subtree(visual, ctx);
+            }
+            cache.painted(changed, ctx.paint_count - start);
+        }
+
+        fn paint_rec(visual: &dyn ElementMethods, ctx: &mut PaintCtx) {
+            if let Some(clip) = visual.clip() {
+                ctx.with_clip(&clip, |ctx| paint_contents(visual, ctx));
+            } else {
+                paint_contents(visual, ctx);
+            }
+        }
+
         paint_rec(self, &mut paint_ctx);
+        paint_ctx.paint_count
     }
 }
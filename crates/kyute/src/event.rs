@@ -1,5 +1,8 @@
 //! Events sent to elements.
+use std::any::Any;
 use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
 
 pub use keyboard_types::KeyboardEvent;
 pub use keyboard_types::Modifiers;
@@ -17,9 +20,17 @@ pub enum PointerType {
     Mouse,
     Pen,
     Stylus,
+    /// A finger on a touch screen.
+    Touch,
     Other,
 }
 
+/// Pointer ID of the mouse.
+///
+/// Each pen and each touch contact has its own ID, valid from the moment it touches the surface (or
+/// comes in range, for pens) until it's lifted.
+pub const MOUSE_POINTER_ID: u64 = 1;
+
 /// Represents a pointer button.
 // TODO why u no bitflags?
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -118,6 +129,19 @@ pub struct PointerEvent {
     pub transform: Affine,
     /// Whether the receiver has captured the pointer.
     pub request_capture: bool,
+    /// Identifies the mouse, pen, or touch contact that emitted this event (see `MOUSE_POINTER_ID`).
+    pub pointer_id: u64,
+    pub pointer_type: PointerType,
+    /// Normalized pressure, in `[0, 1]`.
+    ///
+    /// For pointers that don't report pressure (e.g. mice), 0.5 when a button is pressed and 0 otherwise.
+    pub pressure: f32,
+    /// Tilt of the pen in degrees, in `[-90, 90]`, in the X-Z and Y-Z planes. Zero for other pointers.
+    ///
+    /// Positive values tilt the pen towards the right (X) and towards the user (Y).
+    pub tilt: Vec2,
+    /// Clockwise rotation of the pen around its own axis, in degrees, in `[0, 360)`. Zero for other pointers.
+    pub twist: f32,
 }
 
 impl PointerEvent {
@@ -134,6 +158,35 @@ impl PointerEvent {
         }
     }*/
 
+    /// Creates an event for the mouse pointer.
+    pub fn mouse(
+        position: Point,
+        modifiers: Modifiers,
+        buttons: PointerButtons,
+        button: Option<PointerButton>,
+        repeat_count: u8,
+    ) -> PointerEvent {
+        PointerEvent {
+            position,
+            modifiers,
+            buttons,
+            button,
+            repeat_count,
+            transform: Default::default(),
+            request_capture: false,
+            pointer_id: MOUSE_POINTER_ID,
+            pointer_type: PointerType::Mouse,
+            pressure: if buttons.is_empty() { 0.0 } else { 0.5 },
+            tilt: Vec2::ZERO,
+            twist: 0.0,
+        }
+    }
+
+    /// Returns whether the event comes from the eraser end of a pen (or a pen in eraser mode).
+    pub fn is_eraser(&self) -> bool {
+        self.pointer_type == PointerType::Pen && self.buttons.test(PointerButton::ERASER)
+    }
+
     /// Local position
     pub fn local_position(&self) -> Point {
         self.transform.inverse() * self.position
@@ -145,6 +198,32 @@ impl PointerEvent {
     }
 }
 
+/// Mouse wheel or touchpad scroll.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct WheelEvent {
+    /// Pointer position and modifiers when the wheel was moved.
+    pub pointer: PointerEvent,
+    /// Scroll amount in pixels. Positive `y` values scroll towards the top of the content.
+    pub delta: Vec2,
+}
+
+/// Multi-touch (or touchpad) gesture: pan, pinch-zoom and rotation.
+///
+/// Sent while two or more touch contacts move on the window. The individual contacts are also sent as
+/// pointer events; elements that handle gestures should ignore pointer events of type `PointerType::Touch`
+/// while a gesture is in progress.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GestureEvent {
+    /// Center of the contacts, and modifiers.
+    pub pointer: PointerEvent,
+    /// Movement of the center since the last gesture event, in logical pixels.
+    pub translation: Vec2,
+    /// Scale factor since the last gesture event (greater than 1 when the contacts move apart).
+    pub scale: f64,
+    /// Clockwise rotation since the last gesture event, in radians.
+    pub rotation: f64,
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
 /*/// Keyboard event.
@@ -160,6 +239,58 @@ pub struct KeyboardEvent {
     pub is_composing: bool,
 }*/
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// An underlined range of the text being composed.
+///
+/// Ranges are byte offsets into `CompositionEvent::text`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompositionUnderline {
+    pub range: Range<usize>,
+    /// Thick underlines mark the clause currently being converted.
+    pub thick: bool,
+}
+
+/// In-progress IME composition (pre-edit text).
+///
+/// Text widgets should display `text` at the caret position, with the specified underlines,
+/// but not insert it into the document until `Event::CompositionCommit` is received.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompositionEvent {
+    /// The pre-edit text.
+    pub text: String,
+    /// Position of the caret in the pre-edit text (byte range), if the IME wants it displayed.
+    pub cursor: Option<Range<usize>>,
+    pub underlines: Vec<CompositionUnderline>,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Identifies a task spawned with `Element::spawn_local`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TaskId(pub(crate) u64);
+
+/// Output of a task spawned with `Element::spawn_local`, sent back to the element that spawned it.
+#[derive(Clone)]
+pub struct TaskResult {
+    /// The task that completed.
+    pub task: TaskId,
+    pub(crate) value: Rc<dyn Any>,
+}
+
+impl TaskResult {
+    /// Returns the output of the task, if it has the specified type.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl fmt::Debug for TaskResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaskResult").field("task", &self.task).finish_non_exhaustive()
+    }
+}
+
 /// Events.
 #[derive(Clone, Debug)]
 pub enum Event {
@@ -173,8 +304,20 @@ pub enum Event {
     PointerOut(PointerEvent),
     PointerEnter(PointerEvent),
     PointerLeave(PointerEvent),
+    Wheel(WheelEvent),
+    Gesture(GestureEvent),
     KeyDown(KeyboardEvent),
     KeyUp(KeyboardEvent),
+    /// An IME composition has started.
+    CompositionStart,
+    /// The pre-edit text of the current IME composition has changed.
+    CompositionUpdate(CompositionEvent),
+    /// The IME composition has ended, and the specified text should be inserted.
+    ///
+    /// The string is empty if the composition was cancelled.
+    CompositionCommit(String),
+    /// A task spawned by the element with `Element::spawn_local` has completed.
+    TaskCompleted(TaskResult),
 }
 
 impl Event {
@@ -186,7 +329,9 @@ impl Event {
             | Event::PointerOver(ref mut pe)
             | Event::PointerOut(ref mut pe)
             | Event::PointerEnter(ref mut pe)
-            | Event::PointerLeave(ref mut pe) => {
+            | Event::PointerLeave(ref mut pe)
+            | Event::Wheel(WheelEvent { pointer: ref mut pe, .. })
+            | Event::Gesture(GestureEvent { pointer: ref mut pe, .. }) => {
                 let prev = pe.transform;
                 pe.transform *= *transform;
                 Some(prev)
@@ -203,7 +348,9 @@ impl Event {
             | Event::PointerOver(ref mut pe)
             | Event::PointerOut(ref mut pe)
             | Event::PointerEnter(ref mut pe)
-            | Event::PointerLeave(ref mut pe) => {
+            | Event::PointerLeave(ref mut pe)
+            | Event::Wheel(WheelEvent { pointer: ref mut pe, .. })
+            | Event::Gesture(GestureEvent { pointer: ref mut pe, .. }) => {
                 pe.transform = *transform;
             }
             _ => {}
@@ -218,7 +365,9 @@ impl Event {
             | Event::PointerOver(ref mut pe)
             | Event::PointerOut(ref mut pe)
             | Event::PointerEnter(ref mut pe)
-            | Event::PointerLeave(ref mut pe) => pe.request_capture,
+            | Event::PointerLeave(ref mut pe)
+            | Event::Wheel(WheelEvent { pointer: ref mut pe, .. })
+            | Event::Gesture(GestureEvent { pointer: ref mut pe, .. }) => pe.request_capture,
             _ => false,
         }
     }
@@ -0,0 +1,267 @@
+//! Anchor layout: positions elements relative to the edges of their container or of their siblings.
+//!
+//! Each edge of an element can be anchored to an edge of the container or of a sibling, with an
+//! offset. An element anchored on both sides of an axis is stretched between the two anchors;
+//! otherwise it keeps its measured size on that axis.
+//!
+//! # Example
+//!
+//! ```ignore
+//! // toolbar at the top-left corner of the viewport, status label below it
+//! anchor_to_parent(&toolbar, Edge::Left, Edge::Left, 8.0);
+//! anchor_to_parent(&toolbar, Edge::Top, Edge::Top, 8.0);
+//! anchor_to(&status, Edge::Left, &toolbar, Edge::Left, 0.0);
+//! anchor_to(&status, Edge::Top, &toolbar, Edge::Bottom, 4.0);
+//! ```
+use std::rc::{Rc, Weak};
+
+use kurbo::{Rect, Size, Vec2};
+use tracing::warn;
+
+use crate::element::{AttachedProperty, Element, ElementMethods};
+use crate::layout::flex::Axis;
+use crate::layout::{LayoutInput, LayoutOutput, SizeConstraint};
+
+/// An edge or center line of an element.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    HorizontalCenter,
+    VerticalCenter,
+}
+
+impl Edge {
+    /// Returns the axis along which the edge is positioned.
+    pub fn axis(self) -> Axis {
+        match self {
+            Edge::Left | Edge::Right | Edge::HorizontalCenter => Axis::Horizontal,
+            Edge::Top | Edge::Bottom | Edge::VerticalCenter => Axis::Vertical,
+        }
+    }
+
+    /// Returns the position of the edge of the specified rectangle.
+    fn position(self, rect: &Rect) -> f64 {
+        match self {
+            Edge::Left => rect.x0,
+            Edge::Right => rect.x1,
+            Edge::Top => rect.y0,
+            Edge::Bottom => rect.y1,
+            Edge::HorizontalCenter => 0.5 * (rect.x0 + rect.x1),
+            Edge::VerticalCenter => 0.5 * (rect.y0 + rect.y1),
+        }
+    }
+}
+
+/// The element that an edge is anchored to.
+#[derive(Clone)]
+pub enum AnchorTarget {
+    /// The container.
+    Parent,
+    /// Another child of the same container.
+    Sibling(Weak<dyn ElementMethods>),
+}
+
+/// Anchors `edge` of an element to `target_edge` of `target`, offset by `offset`.
+#[derive(Clone)]
+pub struct Anchor {
+    pub edge: Edge,
+    pub target: AnchorTarget,
+    pub target_edge: Edge,
+    pub offset: f64,
+}
+
+/// Attached property holding the anchors of an element inside an anchor layout.
+#[derive(Copy, Clone, Debug)]
+pub struct Anchors;
+
+impl AttachedProperty for Anchors {
+    type Value = Vec<Anchor>;
+}
+
+fn add_anchor(element: &Element, anchor: Anchor) {
+    assert_eq!(
+        anchor.edge.axis(),
+        anchor.target_edge.axis(),
+        "cannot anchor a horizontal edge to a vertical edge"
+    );
+    let mut anchors = element.get(Anchors).unwrap_or_default();
+    // an edge can only have one anchor
+    anchors.retain(|a| a.edge != anchor.edge);
+    anchors.push(anchor);
+    element.set(Anchors, anchors);
+    if let Some(parent) = element.parent() {
+        parent.mark_needs_relayout();
+    }
+}
+
+/// Anchors `edge` of `element` to `target_edge` of its container.
+pub fn anchor_to_parent(element: &Element, edge: Edge, target_edge: Edge, offset: f64) {
+    add_anchor(
+        element,
+        Anchor {
+            edge,
+            target: AnchorTarget::Parent,
+            target_edge,
+            offset,
+        },
+    );
+}
+
+/// Anchors `edge` of `element` to `target_edge` of `sibling`.
+///
+/// `sibling` must be a child of the same container. Anchors to other elements are ignored.
+pub fn anchor_to(element: &Element, edge: Edge, sibling: &Element, target_edge: Edge, offset: f64) {
+    add_anchor(
+        element,
+        Anchor {
+            edge,
+            target: AnchorTarget::Sibling(sibling.weak()),
+            target_edge,
+            offset,
+        },
+    );
+}
+
+/// Removes all anchors of an element.
+pub fn clear_anchors(element: &Element) {
+    element.set(Anchors, vec![]);
+    if let Some(parent) = element.parent() {
+        parent.mark_needs_relayout();
+    }
+}
+
+pub struct AnchorLayoutParams {
+    /// Sizing constraint in the horizontal direction.
+    pub width_constraint: SizeConstraint,
+    /// Sizing constraint in the vertical direction.
+    pub height_constraint: SizeConstraint,
+    /// Offset applied to the children (e.g. padding of the container).
+    pub origin: Vec2,
+}
+
+/// Resolves the position of an element along one axis. Returns the start position and the size.
+fn resolve_axis(start: Option<f64>, end: Option<f64>, center: Option<f64>, measured: f64) -> (f64, f64) {
+    match (start, end, center) {
+        (Some(start), Some(end), _) => (start, (end - start).max(0.0)),
+        (Some(start), None, _) => (start, measured),
+        (None, Some(end), _) => (end - measured, measured),
+        (None, None, Some(center)) => (center - 0.5 * measured, measured),
+        (None, None, None) => (0.0, measured),
+    }
+}
+
+/// Lays out children according to their anchors.
+///
+/// Children are laid out after the siblings they are anchored to. Anchors that form a cycle are ignored.
+pub fn do_anchor_layout(p: &AnchorLayoutParams, children: &[Rc<dyn ElementMethods>]) -> LayoutOutput {
+    let container_width = p.width_constraint.available().filter(|w| w.is_finite());
+    let container_height = p.height_constraint.available().filter(|h| h.is_finite());
+    let container = Rect::new(0.0, 0.0, container_width.unwrap_or(0.0), container_height.unwrap_or(0.0));
+
+    // resolve sibling targets to indices in `children`
+    let anchors: Vec<Vec<(Anchor, Option<usize>)>> = children
+        .iter()
+        .map(|child| {
+            child
+                .get(Anchors)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|anchor| {
+                    let index = match anchor.target {
+                        AnchorTarget::Parent => None,
+                        AnchorTarget::Sibling(ref sibling) => {
+                            let sibling = sibling.upgrade()?;
+                            let Some(index) = children.iter().position(|c| c.is_same(&*sibling)) else {
+                                warn!("anchor target is not a sibling of the anchored element");
+                                return None;
+                            };
+                            Some(index)
+                        }
+                    };
+                    Some((anchor, index))
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut rects: Vec<Option<Rect>> = vec![None; children.len()];
+    let mut ignore_unresolved = false;
+    while rects.iter().any(Option::is_none) {
+        let mut progress = false;
+        for (i, child) in children.iter().enumerate() {
+            if rects[i].is_some() {
+                continue;
+            }
+            let ready = anchors[i]
+                .iter()
+                .all(|(_, index)| index.map_or(true, |index| rects[index].is_some()));
+            if !ready && !ignore_unresolved {
+                continue;
+            }
+
+            let mut edges = [[None; 3]; 2];
+            for (anchor, index) in anchors[i].iter() {
+                let target = match index {
+                    None => container,
+                    Some(index) => match rects[*index] {
+                        Some(rect) => rect,
+                        None => continue,
+                    },
+                };
+                let pos = anchor.target_edge.position(&target) + anchor.offset;
+                let (axis, slot) = match anchor.edge {
+                    Edge::Left => (0, 0),
+                    Edge::Right => (0, 1),
+                    Edge::HorizontalCenter => (0, 2),
+                    Edge::Top => (1, 0),
+                    Edge::Bottom => (1, 1),
+                    Edge::VerticalCenter => (1, 2),
+                };
+                edges[axis][slot] = Some(pos);
+            }
+
+            let [[left, right, h_center], [top, bottom, v_center]] = edges;
+            let stretch_x = left.is_some() && right.is_some();
+            let stretch_y = top.is_some() && bottom.is_some();
+            let measured = if stretch_x && stretch_y {
+                Size::ZERO
+            } else {
+                let width = if stretch_x {
+                    SizeConstraint::Available((right.unwrap() - left.unwrap()).max(0.0))
+                } else {
+                    p.width_constraint
+                };
+                let output = child.do_measure(&LayoutInput {
+                    width,
+                    height: p.height_constraint,
+                });
+                Size::new(output.width, output.height)
+            };
+            let (x, width) = resolve_axis(left, right, h_center, measured.width);
+            let (y, height) = resolve_axis(top, bottom, v_center, measured.height);
+            rects[i] = Some(Rect::new(x, y, x + width, y + height));
+            progress = true;
+        }
+        if !progress {
+            warn!("circular anchors in anchor layout");
+            ignore_unresolved = true;
+        }
+    }
+
+    let mut bounds = container;
+    for (child, rect) in children.iter().zip(rects.iter()) {
+        let rect = rect.unwrap();
+        child.do_layout(rect.size());
+        child.set_offset(p.origin + rect.origin().to_vec2());
+        bounds = bounds.union(rect);
+    }
+
+    LayoutOutput {
+        width: container_width.unwrap_or(bounds.x1.max(0.0)),
+        height: container_height.unwrap_or(bounds.y1.max(0.0)),
+        baseline: None,
+    }
+}
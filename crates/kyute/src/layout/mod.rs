@@ -10,6 +10,7 @@ use crate::element::AttachedProperty;
 use crate::layout::flex::Axis;
 use crate::ElementMethods;
 
+pub mod anchor;
 pub mod flex;
 //pub mod grid;
 
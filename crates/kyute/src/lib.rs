@@ -1,16 +1,23 @@
 mod app_globals;
 pub mod application;
 mod backend;
+pub mod clipboard;
 pub mod compositor;
+pub mod dialog;
 pub mod drawing;
 pub mod element;
 pub mod event;
 mod handler;
 pub mod layout;
+pub mod notification;
+mod paint_cache;
 mod paint_ctx;
-mod reactive;
+pub mod perf;
+pub mod preferences;
+pub mod reactive;
 //mod skia_backend;
 pub mod style;
+pub mod testing;
 pub mod text;
 pub mod theme;
 pub mod widgets;
@@ -22,6 +29,7 @@ pub use kyute_common::Color;
 pub use element::{Element, ElementMethods};
 pub use event::Event;
 pub use kurbo::{self, Point, Rect, Size};
+pub use paint_cache::PaintCaching;
 pub use paint_ctx::PaintCtx;
 pub use skia_safe;
 pub use style::Style;
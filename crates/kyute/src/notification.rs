@@ -0,0 +1,354 @@
+//! Non-modal notifications ("toasts").
+//!
+//! Toasts are small windows stacked in the bottom-right corner of a parent window. They disappear
+//! after a delay, or when clicked, which also runs their action. When more toasts are posted than
+//! can be shown at once, they wait in a queue. Recent notifications are kept in a history that can
+//! be shown in a notification center window.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let notifications = Notifications::new(&main_window);
+//! notifications.notify(Notification::new(MessageKind::Success, "Import finished").message("1200 curves"));
+//! ```
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use kurbo::{Point, Size};
+use tokio::select;
+
+use crate::application::{spawn, wait_for};
+use crate::dialog::MessageKind;
+use crate::layout::flex::Axis;
+use crate::layout::{PaddingBottom, PaddingLeft, PaddingRight, PaddingTop};
+use crate::text::{FontWeight, TextStyle};
+use crate::theme::{self, palette};
+use crate::widgets::frame::{Frame, FrameLayout, FrameStyle};
+use crate::widgets::text::Text;
+use crate::window::WeakWindow;
+use crate::{text, Window, WindowOptions};
+
+/// Maximum number of toasts visible at the same time.
+pub const MAX_VISIBLE_TOASTS: usize = 4;
+
+/// Number of notifications kept in the history.
+pub const NOTIFICATION_HISTORY_LEN: usize = 50;
+
+const TOAST_SIZE: Size = Size::new(320.0, 72.0);
+
+/// Space between toasts, and between toasts and the edges of the parent window.
+const TOAST_MARGIN: f64 = 12.0;
+
+/// A notification to post.
+pub struct Notification {
+    pub kind: MessageKind,
+    pub title: String,
+    pub message: String,
+    /// Time after which the toast is dismissed. `None` to keep it until it's clicked.
+    pub timeout: Option<Duration>,
+    /// Label of the action, and function called when the toast is clicked.
+    action: Option<(String, Box<dyn FnOnce()>)>,
+}
+
+impl Notification {
+    /// Creates a notification, with a timeout that depends on its severity.
+    pub fn new(kind: MessageKind, title: impl Into<String>) -> Notification {
+        let timeout = match kind {
+            MessageKind::Info | MessageKind::Success => Duration::from_secs(4),
+            MessageKind::Warning => Duration::from_secs(8),
+            MessageKind::Error => Duration::from_secs(15),
+        };
+        Notification {
+            kind,
+            title: title.into(),
+            message: String::new(),
+            timeout: Some(timeout),
+            action: None,
+        }
+    }
+
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the function called when the toast is clicked. `label` is shown on the toast.
+    pub fn action(mut self, label: impl Into<String>, action: impl FnOnce() + 'static) -> Self {
+        self.action = Some((label.into(), Box::new(action)));
+        self
+    }
+}
+
+/// A notification in the history.
+#[derive(Clone, Debug)]
+pub struct NotificationRecord {
+    pub kind: MessageKind,
+    pub title: String,
+    pub message: String,
+    /// Time at which the notification was posted.
+    pub time: Instant,
+}
+
+/// How a toast was closed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ToastOutcome {
+    /// The toast was clicked, and its action was run.
+    Clicked,
+    /// The timeout elapsed.
+    Expired,
+    /// The toast window was closed, or the parent window was destroyed.
+    Closed,
+}
+
+struct NotificationsInner {
+    parent: WeakWindow,
+    /// Slots in the toast stack, from the bottom. `true` if occupied.
+    slots: RefCell<[bool; MAX_VISIBLE_TOASTS]>,
+    /// Toasts waiting for a free slot, in posting order. The slot index is sent to them.
+    queue: RefCell<VecDeque<tokio::sync::oneshot::Sender<usize>>>,
+    history: RefCell<VecDeque<NotificationRecord>>,
+}
+
+impl NotificationsInner {
+    /// Waits for a free slot in the toast stack, and reserves it.
+    async fn acquire_slot(&self) -> usize {
+        let free = self.slots.borrow().iter().position(|occupied| !occupied);
+        if let (Some(slot), true) = (free, self.queue.borrow().is_empty()) {
+            self.slots.borrow_mut()[slot] = true;
+            return slot;
+        }
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.queue.borrow_mut().push_back(tx);
+        // the sender is only dropped after sending
+        rx.await.unwrap()
+    }
+
+    /// Hands the slot over to the next queued toast, or frees it.
+    fn release_slot(&self, slot: usize) {
+        while let Some(next) = self.queue.borrow_mut().pop_front() {
+            // skip toasts that were dropped while waiting
+            if next.send(slot).is_ok() {
+                return;
+            }
+        }
+        self.slots.borrow_mut()[slot] = false;
+    }
+
+    fn record(&self, notification: &Notification) {
+        let mut history = self.history.borrow_mut();
+        if history.len() == NOTIFICATION_HISTORY_LEN {
+            history.pop_back();
+        }
+        history.push_front(NotificationRecord {
+            kind: notification.kind,
+            title: notification.title.clone(),
+            message: notification.message.clone(),
+            time: Instant::now(),
+        });
+    }
+}
+
+/// Notifications shown over a window.
+#[derive(Clone)]
+pub struct Notifications(Rc<NotificationsInner>);
+
+impl Notifications {
+    pub fn new(parent: &Window) -> Notifications {
+        Notifications(Rc::new(NotificationsInner {
+            parent: parent.as_weak(),
+            slots: RefCell::new([false; MAX_VISIBLE_TOASTS]),
+            queue: RefCell::new(VecDeque::new()),
+            history: RefCell::new(VecDeque::new()),
+        }))
+    }
+
+    /// Posts a notification, without waiting for the toast to close.
+    pub fn notify(&self, notification: Notification) {
+        let this = self.clone();
+        spawn(async move {
+            this.show(notification).await;
+        });
+    }
+
+    /// Posts a notification and waits for its toast to close.
+    pub async fn show(&self, mut notification: Notification) -> ToastOutcome {
+        self.0.record(&notification);
+        let slot = self.0.acquire_slot().await;
+        let inner = self.0.clone();
+        scopeguard::defer! { inner.release_slot(slot); }
+
+        let Some(parent) = self.0.parent.upgrade() else {
+            return ToastOutcome::Closed;
+        };
+        let root = toast_contents(&notification);
+        // toasts are laid out at the UI scale of their parent
+        let ui_scale = parent.ui_scale();
+        let size = TOAST_SIZE * ui_scale;
+        let options = WindowOptions {
+            title: &notification.title,
+            size,
+            parent: Some(parent.raw_window_handle()),
+            position: Some(toast_position(&parent, size, slot)),
+            ui_scale,
+            decorations: false,
+            no_focus: true,
+            vsync: false,
+            background: theme::current().alternate_content_background_color,
+            ..Default::default()
+        };
+        let window = Window::new(&options, &root);
+        drop(parent);
+
+        let timeout = notification.timeout;
+        let expired = async move {
+            match timeout {
+                Some(timeout) => wait_for(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let outcome = select! {
+            _ = root.clicked() => ToastOutcome::Clicked,
+            _ = expired => ToastOutcome::Expired,
+            _ = window.close_requested() => ToastOutcome::Closed,
+        };
+        if outcome == ToastOutcome::Clicked {
+            if let Some((_, action)) = notification.action.take() {
+                action();
+            }
+        }
+        outcome
+    }
+
+    /// Returns the recent notifications, most recent first.
+    pub fn history(&self) -> Vec<NotificationRecord> {
+        self.0.history.borrow().iter().cloned().collect()
+    }
+
+    pub fn clear_history(&self) {
+        self.0.history.borrow_mut().clear();
+    }
+
+    /// Shows a window listing the recent notifications, and waits for it to be closed.
+    pub async fn show_notification_center(&self) {
+        let Some(parent) = self.0.parent.upgrade() else {
+            return;
+        };
+        let root = Frame::new(FrameStyle {
+            layout: FrameLayout::Flex {
+                direction: Axis::Vertical,
+            },
+            ..Default::default()
+        });
+        PaddingLeft.set(&root, 12.0.into());
+        PaddingRight.set(&root, 12.0.into());
+        PaddingTop.set(&root, 12.0.into());
+        PaddingBottom.set(&root, 12.0.into());
+
+        let theme = &theme::current();
+        let text_style = TextStyle::new()
+            .font_size(theme.font_size as f32)
+            .font_family(theme.font_family)
+            .color(theme.text_color);
+        let time_style = text_style.clone().color(palette::GREY_500);
+        let history = self.history();
+        if history.is_empty() {
+            root.add_child(&Text::new(text!( style(time_style) "No notifications" )));
+        }
+        let now = Instant::now();
+        for record in history.iter() {
+            let (icon, icon_color) = record.kind.icon();
+            let icon_style = text_style.clone().color(icon_color);
+            let title = &record.title;
+            let ago = format_elapsed(now - record.time);
+            let row = Text::new(text!( style(icon_style) "{icon} " style(text_style.clone()) "{title}" style(time_style.clone()) "  {ago}" ));
+            PaddingTop.set(&row, 6.0.into());
+            root.add_child(&row);
+            if !record.message.is_empty() {
+                let message = &record.message;
+                root.add_child(&Text::new(text!( style(time_style.clone()) "{message}" )));
+            }
+        }
+
+        let ui_scale = parent.ui_scale();
+        let size = Size::new(400.0, 480.0) * ui_scale;
+        let options = WindowOptions {
+            title: "Notifications",
+            size,
+            parent: Some(parent.raw_window_handle()),
+            position: Some(parent.centered_position(size)),
+            background: theme.content_background_color,
+            ui_scale,
+            ..Default::default()
+        };
+        let window = Window::new(&options, &root);
+        drop(parent);
+        window.close_requested().await;
+    }
+}
+
+/// Builds the contents of a toast window. Returns the root frame, which receives clicks.
+fn toast_contents(notification: &Notification) -> Rc<Frame> {
+    let theme = &theme::current();
+    let text_style = TextStyle::new()
+        .font_size(theme.font_size as f32)
+        .font_family(theme.font_family)
+        .color(theme.text_color);
+    let (icon, icon_color) = notification.kind.icon();
+
+    let root = Frame::new(FrameStyle {
+        layout: FrameLayout::Flex {
+            direction: Axis::Vertical,
+        },
+        border_left: 4.0.into(),
+        border_color: icon_color,
+        ..Default::default()
+    });
+    PaddingLeft.set(&root, 12.0.into());
+    PaddingRight.set(&root, 12.0.into());
+    PaddingTop.set(&root, 10.0.into());
+    PaddingBottom.set(&root, 10.0.into());
+
+    let title_style = text_style.clone().font_weight(FontWeight::SEMI_BOLD);
+    let icon_style = title_style.clone().color(icon_color);
+    let title = &notification.title;
+    root.add_child(&Text::new(text!( style(icon_style) "{icon} " style(title_style) "{title}" )));
+    if !notification.message.is_empty() {
+        let message = &notification.message;
+        root.add_child(&Text::new(text!( style(text_style.clone()) "{message}" )));
+    }
+    if let Some((ref label, _)) = notification.action {
+        let action_style = text_style.color(theme.accent_color);
+        let action = Text::new(text!( style(action_style) "{label}" ));
+        PaddingTop.set(&action, 4.0.into());
+        root.add_child(&action);
+    }
+    root
+}
+
+/// Returns the logical screen position of the toast in the specified slot, stacked upwards from
+/// the bottom-right corner of the parent window.
+/// Returns the position of a toast of the specified size in the specified slot, stacked from the
+/// bottom-right corner of the parent.
+fn toast_position(parent: &Window, size: Size, slot: usize) -> Point {
+    let bounds = parent.inner_bounds();
+    Point::new(
+        bounds.x1 - size.width - TOAST_MARGIN,
+        bounds.y1 - (size.height + TOAST_MARGIN) * (slot + 1) as f64,
+    )
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{} min ago", secs / 60),
+        _ => format!("{} h ago", secs / 3600),
+    }
+}
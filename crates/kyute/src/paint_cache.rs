@@ -0,0 +1,111 @@
+//! Caching of the paint output of static subtrees.
+//!
+//! The paint output of an element and its descendants can be recorded into a skia picture, and
+//! replayed in the following frames instead of painting the subtree again, as long as nothing in
+//! the subtree needs to be repainted (see `Element::mark_needs_repaint`) and its size hasn't changed.
+//!
+//! By default (`PaintCaching::Auto`), a subtree is cached once it has been painted without changes for
+//! a few frames in a row, if it's expensive enough to paint. Subtrees whose cache keeps being
+//! discarded soon after being recorded wait longer before being cached again.
+use std::cell::{Cell, RefCell};
+
+use kurbo::Size;
+use skia_safe::Picture;
+
+/// Minimum number of elements painted in a subtree for it to be cached automatically.
+const AUTO_CACHE_MIN_COST: usize = 16;
+/// Number of consecutive frames without changes after which a subtree is cached automatically.
+const AUTO_CACHE_STABLE_FRAMES: u32 = 4;
+/// A cached picture discarded after being replayed fewer times than this was not worth recording.
+const AUTO_CACHE_MIN_HITS: u32 = 8;
+/// Maximum backoff exponent: subtrees that change often wait up to `AUTO_CACHE_STABLE_FRAMES << MAX_BACKOFF` frames.
+const MAX_BACKOFF: u32 = 6;
+
+/// Whether the paint output of an element and its descendants is cached.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum PaintCaching {
+    /// Decide based on the paint cost of the subtree and on how often it changes.
+    #[default]
+    Auto,
+    /// Always cache the subtree, even if it changes often (see `Element::cache_paint`).
+    Always,
+    /// Never cache the subtree.
+    Never,
+}
+
+struct CachedPicture {
+    size: Size,
+    picture: Picture,
+    /// Number of times the picture was replayed.
+    hits: u32,
+}
+
+/// Paint cache of an element.
+#[derive(Default)]
+pub(crate) struct PaintCache {
+    pub(crate) mode: Cell<PaintCaching>,
+    picture: RefCell<Option<CachedPicture>>,
+    /// Number of consecutive frames in which the subtree was painted without changes.
+    clean_frames: Cell<u32>,
+    /// Number of elements painted in the subtree the last time it was painted.
+    cost: Cell<usize>,
+    /// Backoff exponent of the automatic caching, increased when a picture was discarded too early.
+    backoff: Cell<u32>,
+}
+
+impl PaintCache {
+    /// Returns the cached picture, if there's one for the specified size.
+    pub(crate) fn get(&self, size: Size) -> Option<Picture> {
+        let mut cached = self.picture.borrow_mut();
+        let cached = cached.as_mut().filter(|cached| cached.size == size)?;
+        cached.hits += 1;
+        self.clean_frames.set(self.clean_frames.get().saturating_add(1));
+        Some(cached.picture.clone())
+    }
+
+    /// Discards the cached picture, because the subtree changed.
+    pub(crate) fn invalidate(&self) {
+        self.clean_frames.set(0);
+        if let Some(cached) = self.picture.take() {
+            let backoff = self.backoff.get();
+            self.backoff.set(if cached.hits < AUTO_CACHE_MIN_HITS {
+                (backoff + 1).min(MAX_BACKOFF)
+            } else {
+                backoff.saturating_sub(1)
+            });
+        }
+    }
+
+    /// Returns whether the subtree should be recorded into a picture the next time it's painted.
+    pub(crate) fn should_record(&self) -> bool {
+        match self.mode.get() {
+            PaintCaching::Always => true,
+            PaintCaching::Never => false,
+            PaintCaching::Auto => {
+                self.cost.get() >= AUTO_CACHE_MIN_COST
+                    && self.clean_frames.get() >= AUTO_CACHE_STABLE_FRAMES << self.backoff.get()
+            }
+        }
+    }
+
+    /// Called after the subtree was painted (and not replayed from the cache).
+    ///
+    /// `changed` is whether the subtree needed to be repainted, and `cost` the number of elements painted.
+    pub(crate) fn painted(&self, changed: bool, cost: usize) {
+        self.cost.set(cost);
+        if changed {
+            self.clean_frames.set(0);
+        } else {
+            self.clean_frames.set(self.clean_frames.get().saturating_add(1));
+        }
+    }
+
+    pub(crate) fn store(&self, size: Size, picture: Picture) {
+        self.picture.replace(Some(CachedPicture { size, picture, hits: 0 }));
+    }
+
+    /// Discards the cached picture, without affecting the heuristics.
+    pub(crate) fn clear(&self) {
+        self.picture.replace(None);
+    }
+}
@@ -1,6 +1,11 @@
+use std::cell::RefCell;
+use std::mem;
+
 use crate::compositor::DrawableSurface;
 use crate::drawing::ToSkia;
+use crate::element::Clip;
 use kurbo::{Affine, Rect, Vec2};
+use skia_safe::{Picture, PictureRecorder};
 
 /// Paint context.
 pub struct PaintCtx<'a> {
@@ -9,6 +14,10 @@ pub struct PaintCtx<'a> {
     pub(crate) window_transform: Affine,
     /// Drawable surface.
     pub surface: &'a DrawableSurface,
+    /// Number of elements painted so far (for the performance HUD).
+    pub(crate) paint_count: usize,
+    /// Recorders of the paint caches being filled, innermost last (see `record_picture`).
+    pub(crate) recorders: RefCell<Vec<PictureRecorder>>,
     //pub(crate) debug_info: PaintDebugInfo,
 }
 
@@ -27,28 +36,46 @@ impl<'a> PaintCtx<'a> {
         let scale = self.scale_factor as skia_safe::scalar;
         let prev_transform = self.window_transform;
         self.window_transform *= *transform;
-        let mut surface = self.surface.surface();
-        surface.canvas().save();
-        surface.canvas().reset_matrix();
-        surface.canvas().scale((scale, scale));
-        surface.canvas().concat(&self.window_transform.to_skia());
-        // TODO clip
+        self.with_canvas(|canvas| {
+            canvas.save();
+            canvas.reset_matrix();
+            canvas.scale((scale, scale));
+            canvas.concat(&self.window_transform.to_skia());
+        });
         let result = f(self);
-        let mut surface = self.surface.surface();
-        surface.canvas().restore();
+        self.with_canvas(|canvas| {
+            canvas.restore();
+        });
         self.window_transform = prev_transform;
 
         result
     }
 
     pub fn with_clip_rect(&mut self, rect: Rect, f: impl FnOnce(&mut PaintCtx<'a>)) {
-        let mut surface = self.surface.surface();
-        surface.canvas().save();
-        surface
-            .canvas()
-            .clip_rect(rect.to_skia(), skia_safe::ClipOp::Intersect, false);
-        f(self);
-        surface.canvas().restore();
+        self.with_clip(&Clip::Rect(rect), f)
+    }
+
+    /// Restricts painting to the specified shape, in the current coordinate space.
+    pub fn with_clip<R>(&mut self, clip: &Clip, f: impl FnOnce(&mut PaintCtx<'a>) -> R) -> R {
+        self.with_canvas(|canvas| {
+            canvas.save();
+            match clip {
+                Clip::Rect(rect) => {
+                    canvas.clip_rect(rect.to_skia(), skia_safe::ClipOp::Intersect, false);
+                }
+                Clip::RoundedRect(rrect) => {
+                    canvas.clip_rrect(rrect.to_skia(), skia_safe::ClipOp::Intersect, true);
+                }
+                Clip::Path(path) => {
+                    canvas.clip_path(&path.to_skia(), skia_safe::ClipOp::Intersect, true);
+                }
+            }
+        });
+        let result = f(self);
+        self.with_canvas(|canvas| {
+            canvas.restore();
+        });
+        result
     }
 
     /*pub fn paint(&mut self, widget: &mut dyn Widget) {
@@ -62,12 +89,45 @@ impl<'a> PaintCtx<'a> {
         widget.paint(self)
     }*/
 
+    /// Calls `f` with the canvas to draw to.
+    ///
+    /// This is the canvas of the surface, or the recording canvas of a paint cache if the
+    /// element is being recorded.
     pub fn with_canvas<F, R>(&self, f: F) -> R
     where
         F: FnOnce(&skia_safe::Canvas) -> R,
     {
+        if let Some(recorder) = self.recorders.borrow_mut().last_mut() {
+            return f(recorder.recording_canvas().expect("paint cache recorder is not recording"));
+        }
         let mut surface = self.surface.surface();
         let result = f(surface.canvas());
         result
     }
+
+    /// Records what `f` paints into a picture, in the current coordinate space.
+    ///
+    /// `bounds` is a hint of the area painted. The picture doesn't depend on the transform of the
+    /// current element, so it can be replayed after the element has moved.
+    pub(crate) fn record_picture(&mut self, bounds: Rect, f: impl FnOnce(&mut PaintCtx<'a>)) -> Option<Picture> {
+        let mut recorder = PictureRecorder::new();
+        recorder.begin_recording(bounds.to_skia(), None);
+        self.recorders.borrow_mut().push(recorder);
+        // record in local coordinates, at scale 1: the picture is drawn with the transform of the
+        // element when it's replayed
+        let window_transform = mem::replace(&mut self.window_transform, Affine::IDENTITY);
+        let scale_factor = mem::replace(&mut self.scale_factor, 1.0);
+        f(self);
+        self.window_transform = window_transform;
+        self.scale_factor = scale_factor;
+        let mut recorder = self.recorders.borrow_mut().pop().unwrap();
+        recorder.finish_recording_as_picture(None)
+    }
+
+    /// Replays a picture recorded with `record_picture`.
+    pub(crate) fn draw_picture(&self, picture: &Picture) {
+        self.with_canvas(|canvas| {
+            canvas.draw_picture(picture, None, None);
+        });
+    }
 }
@@ -0,0 +1,217 @@
+//! Frame timing instrumentation and performance HUD.
+//!
+//! Windows record how long each frame spends in each phase (event dispatch, layout, paint, composite).
+//! The HUD overlay (see `Window::set_perf_hud_visible`) shows a rolling graph of the last frames.
+//! Windows also record presentation feedback from the compositor (missed vsyncs and present latency).
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use skia_safe::font::Edging;
+use skia_safe::{Font, Paint, Rect};
+
+use crate::window::default_typeface;
+
+/// Number of frames kept in the history.
+const HISTORY_LEN: usize = 120;
+
+/// Phases of a UI frame.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FramePhase {
+    /// Dispatching input events to elements (accumulated since the previous frame).
+    EventDispatch,
+    Layout,
+    Paint,
+    /// Flushing skia and presenting the surface to the compositor.
+    Composite,
+}
+
+impl FramePhase {
+    pub const ALL: [FramePhase; 4] = [
+        FramePhase::EventDispatch,
+        FramePhase::Layout,
+        FramePhase::Paint,
+        FramePhase::Composite,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            FramePhase::EventDispatch => "events",
+            FramePhase::Layout => "layout",
+            FramePhase::Paint => "paint",
+            FramePhase::Composite => "composite",
+        }
+    }
+
+    fn color(&self) -> skia_safe::Color {
+        match self {
+            FramePhase::EventDispatch => skia_safe::Color::from_rgb(0x29, 0xb6, 0xf6),
+            FramePhase::Layout => skia_safe::Color::from_rgb(0xff, 0xc1, 0x07),
+            FramePhase::Paint => skia_safe::Color::from_rgb(0x66, 0xbb, 0x6a),
+            FramePhase::Composite => skia_safe::Color::from_rgb(0xab, 0x47, 0xbc),
+        }
+    }
+}
+
+/// Timings of a single frame.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FrameTimings {
+    /// Time spent in each phase, indexed by `FramePhase as usize`.
+    pub phases: [Duration; 4],
+    /// Number of elements laid out during the frame.
+    pub layout_count: usize,
+    /// Number of elements painted during the frame.
+    pub paint_count: usize,
+    /// Number of subtrees replayed from their paint cache instead of being painted.
+    pub paint_cache_hits: usize,
+    /// Vertical blanks missed by the last frame that reached the screen.
+    pub missed_vsyncs: u32,
+    /// Time between presentation and display of the last frame that reached the screen, if known.
+    pub present_latency: Option<Duration>,
+}
+
+impl FrameTimings {
+    pub fn phase(&self, phase: FramePhase) -> Duration {
+        self.phases[phase as usize]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().sum()
+    }
+}
+
+thread_local! {
+    static LAYOUT_COUNT: Cell<usize> = const { Cell::new(0) };
+    static PAINT_CACHE_HITS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Called by `do_layout` for each element laid out.
+pub(crate) fn count_layout() {
+    LAYOUT_COUNT.with(|c| c.set(c.get() + 1));
+}
+
+/// Returns the number of elements laid out since the last call, and resets the counter.
+pub(crate) fn take_layout_count() -> usize {
+    LAYOUT_COUNT.with(|c| c.replace(0))
+}
+
+/// Called by `do_paint` for each subtree replayed from its paint cache.
+pub(crate) fn count_paint_cache_hit() {
+    PAINT_CACHE_HITS.with(|c| c.set(c.get() + 1));
+}
+
+/// Returns the number of paint cache hits since the last call, and resets the counter.
+pub(crate) fn take_paint_cache_hits() -> usize {
+    PAINT_CACHE_HITS.with(|c| c.replace(0))
+}
+
+/// Rolling history of frame timings.
+#[derive(Default)]
+pub struct FrameStats {
+    frames: VecDeque<FrameTimings>,
+}
+
+impl FrameStats {
+    pub fn push(&mut self, timings: FrameTimings) {
+        if self.frames.len() == HISTORY_LEN {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(timings);
+    }
+
+    /// Returns the recorded frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &FrameTimings> {
+        self.frames.iter()
+    }
+
+    pub fn last(&self) -> Option<&FrameTimings> {
+        self.frames.back()
+    }
+
+    /// Average time spent in the specified phase over the recorded frames.
+    pub fn average(&self, phase: FramePhase) -> Duration {
+        if self.frames.is_empty() {
+            return Duration::ZERO;
+        }
+        self.frames.iter().map(|f| f.phase(phase)).sum::<Duration>() / self.frames.len() as u32
+    }
+
+    /// Total number of missed vsyncs over the recorded frames.
+    pub fn missed_vsyncs(&self) -> u32 {
+        self.frames.iter().map(|f| f.missed_vsyncs).sum()
+    }
+
+    /// Average present latency over the recorded frames for which it is known.
+    pub fn average_present_latency(&self) -> Option<Duration> {
+        let latencies: Vec<Duration> = self.frames.iter().filter_map(|f| f.present_latency).collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        Some(latencies.iter().sum::<Duration>() / latencies.len() as u32)
+    }
+
+    /// Paints the HUD at the top-right corner of the canvas.
+    pub(crate) fn draw_hud(&self, canvas: &skia_safe::Canvas, window_width: f32) {
+        const WIDTH: f32 = 2.0 * HISTORY_LEN as f32;
+        const GRAPH_HEIGHT: f32 = 60.0;
+        // Frame time corresponding to the full height of the graph.
+        const GRAPH_SCALE_MS: f32 = 33.3;
+
+        let x0 = window_width - WIDTH - 8.0;
+        let y0 = 8.0;
+
+        let mut bg = Paint::default();
+        bg.set_color(skia_safe::Color::from_argb(200, 16, 16, 16));
+        canvas.draw_rect(Rect::from_xywh(x0 - 4.0, y0 - 4.0, WIDTH + 8.0, GRAPH_HEIGHT + 91.0), &bg);
+
+        // 16ms line
+        let mut line = Paint::default();
+        line.set_color(skia_safe::Color::from_argb(128, 255, 255, 255));
+        let y_16 = y0 + GRAPH_HEIGHT * (1.0 - 16.6 / GRAPH_SCALE_MS);
+        canvas.draw_line((x0, y_16), (x0 + WIDTH, y_16), &line);
+
+        // stacked bars, one per frame
+        let mut paint = Paint::default();
+        for (i, frame) in self.frames.iter().enumerate() {
+            let x = x0 + 2.0 * (HISTORY_LEN - self.frames.len() + i) as f32;
+            let mut y = y0 + GRAPH_HEIGHT;
+            for phase in FramePhase::ALL {
+                let h = frame.phase(phase).as_secs_f32() * 1000.0 / GRAPH_SCALE_MS * GRAPH_HEIGHT;
+                let h = h.min(y - y0);
+                paint.set_color(phase.color());
+                canvas.draw_rect(Rect::from_xywh(x, y - h, 2.0, h), &paint);
+                y -= h;
+            }
+        }
+
+        // legend with averages
+        let mut font = Font::from_typeface(default_typeface(), 11.0);
+        font.set_edging(Edging::AntiAlias);
+        let mut y = y0 + GRAPH_HEIGHT + 14.0;
+        for phase in FramePhase::ALL {
+            paint.set_color(phase.color());
+            let text = format!("{:<10}{:>6.2} ms", phase.name(), self.average(phase).as_secs_f64() * 1000.0);
+            canvas.draw_str(text, (x0, y), &font, &paint);
+            y += 13.0;
+        }
+        if let Some(last) = self.last() {
+            paint.set_color(skia_safe::Color::WHITE);
+            let text = format!(
+                "{:.2} ms, {} layouts, {} paints, {} cached",
+                last.total().as_secs_f64() * 1000.0,
+                last.layout_count,
+                last.paint_count,
+                last.paint_cache_hits
+            );
+            canvas.draw_str(text, (x0, y), &font, &paint);
+            y += 13.0;
+        }
+        let latency = match self.average_present_latency() {
+            Some(latency) => format!("{:.2} ms", latency.as_secs_f64() * 1000.0),
+            None => "n/a".to_string(),
+        };
+        paint.set_color(skia_safe::Color::WHITE);
+        let text = format!("{} missed vsyncs, latency {latency}", self.missed_vsyncs());
+        canvas.draw_str(text, (x0, y), &font, &paint);
+    }
+}
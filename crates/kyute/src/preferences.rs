@@ -0,0 +1,52 @@
+//! User interface preferences from the accessibility settings of the system.
+//!
+//! The preferences are read when the application starts, and read again when a window gets the focus
+//! or the system theme changes: accessibility settings are changed in another application, so changes
+//! show up when the user comes back. Widgets query the preferences with `preferences()` when they start
+//! an animation or pick colors, and can subscribe to changes with `preferences_changed()`.
+use tokio::sync::watch;
+
+use crate::{AppGlobals, Color};
+
+/// Colors of the system high-contrast theme.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HighContrastColors {
+    pub text: Color,
+    pub background: Color,
+    /// Background of selected or highlighted items.
+    pub highlight: Color,
+    /// Text of selected or highlighted items.
+    pub highlight_text: Color,
+    /// Text of disabled items.
+    pub disabled_text: Color,
+}
+
+/// Accessibility preferences.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Preferences {
+    /// Animations and transitions should be replaced by immediate changes.
+    pub reduced_motion: bool,
+    /// Colors of the system high-contrast theme, if one is active.
+    pub high_contrast: Option<HighContrastColors>,
+}
+
+/// Returns the current preferences, or the defaults if no application is running.
+pub fn preferences() -> Preferences {
+    AppGlobals::try_get().map_or_else(Preferences::default, |app| app.preferences())
+}
+
+/// Returns a stream of the preferences, that yields the new preferences when they change.
+///
+/// # Panics
+///
+/// If no application is running.
+pub fn preferences_changed() -> watch::Receiver<Preferences> {
+    AppGlobals::get().preferences_stream()
+}
+
+/// Replaces the preferences, e.g. for an in-app accessibility setting, or in tests.
+///
+/// They are replaced again by the preferences of the system the next time these change.
+pub fn set_preferences(preferences: Preferences) {
+    AppGlobals::get().set_preferences(preferences);
+}
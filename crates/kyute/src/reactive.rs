@@ -1,5 +1,51 @@
+//! Observable values.
+use std::cell::RefCell;
+
 use tokio::sync::watch;
 
+/// A notification deferred until the end of the current transaction.
+struct PendingNotification {
+    /// Address of the property, to notify each property only once.
+    property: *const (),
+    notify: Box<dyn FnOnce()>,
+}
+
+thread_local! {
+    /// Notifications deferred by the current transaction, `None` outside of a transaction.
+    static TRANSACTION: RefCell<Option<Vec<PendingNotification>>> = RefCell::new(None);
+}
+
+/// Runs `f` in a transaction.
+///
+/// Properties modified in `f` notify their subscribers once, when `f` returns, instead of once per
+/// modification. Use this when updating many properties at once (e.g. all animated parameters on a
+/// frame change) to avoid redundant relayouts.
+///
+/// Transactions can be nested: notifications are sent at the end of the outermost one.
+pub fn batch<R>(f: impl FnOnce() -> R) -> R {
+    let outermost = TRANSACTION.with(|t| {
+        let mut t = t.borrow_mut();
+        if t.is_none() {
+            *t = Some(vec![]);
+            true
+        } else {
+            false
+        }
+    });
+    if !outermost {
+        return f();
+    }
+
+    // send notifications even if `f` panics, since some properties may have been modified already
+    let _flush = scopeguard::guard((), |_| {
+        let pending = TRANSACTION.with(|t| t.borrow_mut().take()).unwrap_or_default();
+        for notification in pending {
+            (notification.notify)();
+        }
+    });
+    f()
+}
+
 /// Observable property.
 pub struct Property<T> {
     value: watch::Sender<T>,
@@ -22,8 +68,53 @@ impl<T> Property<T> {
         self.value.subscribe()
     }
 
-    pub fn modify(&self, f: impl FnOnce(&mut T) -> bool) -> bool {
-        self.value.send_if_modified(f)
+    /// Modifies the value of the property in place.
+    ///
+    /// `f` returns whether the value was modified, in which case subscribers are notified (at the end
+    /// of the current transaction if there's one, see `batch`). Returns the result of `f`.
+    pub fn modify(&self, f: impl FnOnce(&mut T) -> bool) -> bool
+    where
+        T: 'static,
+    {
+        let in_transaction = TRANSACTION.with(|t| t.borrow().is_some());
+        if !in_transaction {
+            return self.value.send_if_modified(f);
+        }
+
+        // modify without notifying, and defer the notification
+        let mut modified = false;
+        self.value.send_if_modified(|value| {
+            modified = f(value);
+            false
+        });
+        if modified {
+            let property = self as *const Self as *const ();
+            let sender = self.value.clone();
+            TRANSACTION.with(|t| {
+                let mut t = t.borrow_mut();
+                let pending = t.as_mut().unwrap();
+                if !pending.iter().any(|n| n.property == property) {
+                    pending.push(PendingNotification {
+                        property,
+                        notify: Box::new(move || sender.send_modify(|_| {})),
+                    });
+                }
+            });
+        }
+        modified
+    }
+
+    /// Applies several modifications to the value, and notifies subscribers once.
+    pub fn batch(&self, f: impl FnOnce(&mut T))
+    where
+        T: 'static,
+    {
+        batch(|| {
+            self.modify(|value| {
+                f(value);
+                true
+            })
+        });
     }
 
     pub fn borrow(&self) -> watch::Ref<T> {
@@ -0,0 +1,361 @@
+//! Support for UI regression tests.
+//!
+//! Input event streams can be recorded against a window (`Window::start_recording`), saved to a file,
+//! and replayed later with the same timing (`Window::replay`). Replayed events are dispatched to the
+//! element tree directly, bypassing the OS, so replays don't depend on the window having focus or
+//! being visible.
+//!
+//! After a replay, tests can compare the layout of the element tree (`layout_snapshot`) and the
+//! painted output (`paint_hash`) against reference snapshots (`assert_snapshot`).
+//!
+//! Element trees can also be rendered to images without a window (`render_to_image`), e.g. for
+//! screenshots or golden-image tests. Together with `application::run_headless`, this works on
+//! machines without a display. The contents of an open window can be captured with `Window::capture`.
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use keyboard_types::{Code, Key, Location};
+use kurbo::{Point, Rect, Size};
+use serde::{Deserialize, Serialize};
+use winit::event::{ElementState, Ime, KeyEvent, MouseScrollDelta, WindowEvent};
+use winit::keyboard::KeyLocation;
+
+use crate::compositor::DrawableSurface;
+use crate::element::{Element, ElementMethods};
+use crate::event::key_event_to_key_code;
+use crate::window::WHEEL_LINE_HEIGHT;
+
+/// Environment variable that, when set, makes `assert_snapshot` overwrite the reference snapshots
+/// instead of comparing against them.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "KYUTE_UPDATE_SNAPSHOTS";
+
+/// Mouse buttons in recorded events.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+}
+
+impl MouseButton {
+    fn from_winit(button: winit::event::MouseButton) -> Option<MouseButton> {
+        match button {
+            winit::event::MouseButton::Left => Some(MouseButton::Left),
+            winit::event::MouseButton::Right => Some(MouseButton::Right),
+            winit::event::MouseButton::Middle => Some(MouseButton::Middle),
+            winit::event::MouseButton::Back => Some(MouseButton::Back),
+            winit::event::MouseButton::Forward => Some(MouseButton::Forward),
+            winit::event::MouseButton::Other(_) => None,
+        }
+    }
+
+    pub(crate) fn to_winit(self) -> winit::event::MouseButton {
+        match self {
+            MouseButton::Left => winit::event::MouseButton::Left,
+            MouseButton::Right => winit::event::MouseButton::Right,
+            MouseButton::Middle => winit::event::MouseButton::Middle,
+            MouseButton::Back => winit::event::MouseButton::Back,
+            MouseButton::Forward => winit::event::MouseButton::Forward,
+        }
+    }
+}
+
+/// A recorded input event.
+///
+/// This is the subset of winit window events that affect the element tree, in a form that can be
+/// serialized and replayed (winit events can't be constructed outside winit).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    /// The pointer moved to the specified position, in window coordinates.
+    PointerMove { x: f64, y: f64 },
+    PointerButton { button: MouseButton, pressed: bool },
+    /// Scroll, in pixels.
+    Wheel { dx: f64, dy: f64 },
+    Key {
+        key: Key,
+        code: Code,
+        location: Location,
+        pressed: bool,
+        repeat: bool,
+    },
+    /// IME composition update, with the byte range of the cursor.
+    ImePreedit { text: String, cursor: Option<(usize, usize)> },
+    ImeCommit(String),
+    ImeDisabled,
+}
+
+impl InputEvent {
+    fn from_key_event(event: &KeyEvent) -> InputEvent {
+        let (key, code) = key_event_to_key_code(event);
+        InputEvent::Key {
+            key,
+            code,
+            location: match event.location {
+                KeyLocation::Standard => Location::Standard,
+                KeyLocation::Left => Location::Left,
+                KeyLocation::Right => Location::Right,
+                KeyLocation::Numpad => Location::Numpad,
+            },
+            pressed: event.state == ElementState::Pressed,
+            repeat: event.repeat,
+        }
+    }
+
+    /// Converts a winit window event. Returns `None` for events that are not recorded.
+    pub(crate) fn from_window_event(event: &WindowEvent) -> Option<InputEvent> {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => Some(InputEvent::PointerMove {
+                x: position.x,
+                y: position.y,
+            }),
+            WindowEvent::MouseInput { button, state, .. } => Some(InputEvent::PointerButton {
+                button: MouseButton::from_winit(*button)?,
+                pressed: state.is_pressed(),
+            }),
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (dx, dy) = match *delta {
+                    MouseScrollDelta::LineDelta(x, y) => (x as f64 * WHEEL_LINE_HEIGHT, y as f64 * WHEEL_LINE_HEIGHT),
+                    MouseScrollDelta::PixelDelta(pos) => (pos.x, pos.y),
+                };
+                Some(InputEvent::Wheel { dx, dy })
+            }
+            WindowEvent::KeyboardInput { event, .. } => Some(InputEvent::from_key_event(event)),
+            WindowEvent::Ime(Ime::Preedit(text, cursor)) => Some(InputEvent::ImePreedit {
+                text: text.clone(),
+                cursor: *cursor,
+            }),
+            WindowEvent::Ime(Ime::Commit(text)) => Some(InputEvent::ImeCommit(text.clone())),
+            WindowEvent::Ime(Ime::Disabled) => Some(InputEvent::ImeDisabled),
+            _ => None,
+        }
+    }
+}
+
+/// An input event with its time relative to the start of the recording.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimedInputEvent {
+    pub time: Duration,
+    pub event: InputEvent,
+}
+
+/// A stream of input events recorded against a window.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EventRecording {
+    pub events: Vec<TimedInputEvent>,
+}
+
+impl EventRecording {
+    /// Loads a recording saved with `save`.
+    pub fn load(path: impl AsRef<Path>) -> Result<EventRecording, anyhow::Error> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("invalid event recording: {}", path.display()))
+    }
+
+    /// Saves the recording to a JSON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Duration of the recording.
+    pub fn duration(&self) -> Duration {
+        self.events.last().map(|e| e.time).unwrap_or_default()
+    }
+}
+
+/// Records the input events received by a window.
+pub(crate) struct EventRecorder {
+    start: Instant,
+    recording: EventRecording,
+}
+
+impl EventRecorder {
+    pub(crate) fn new() -> EventRecorder {
+        EventRecorder {
+            start: Instant::now(),
+            recording: EventRecording::default(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: &WindowEvent) {
+        if let Some(event) = InputEvent::from_window_event(event) {
+            self.recording.events.push(TimedInputEvent {
+                time: self.start.elapsed(),
+                event,
+            });
+        }
+    }
+
+    pub(crate) fn finish(self) -> EventRecording {
+        self.recording
+    }
+}
+
+/// Returns the name of an element for snapshots.
+///
+/// Elements without an explicit name are named after their address, which isn't stable across runs.
+fn snapshot_name(element: &dyn ElementMethods) -> Option<String> {
+    let name = element.name();
+    (!name.starts_with("0x")).then_some(name)
+}
+
+/// Returns a textual description of the geometry of an element tree, as of the last layout.
+///
+/// There's one line per element, with the index path of the element, its name if it has one, and its
+/// bounds in window coordinates, e.g. `0.2 ok_button [10.0 20.0 90.0 44.0]`.
+pub fn layout_snapshot(root: &Element) -> String {
+    fn rec(element: &dyn ElementMethods, path: &str, out: &mut String) {
+        let bounds = element
+            .window_transform()
+            .transform_rect_bbox(Rect::from_origin_size(Point::ORIGIN, element.size()));
+        let _ = write!(out, "{path}");
+        if let Some(name) = snapshot_name(element) {
+            let _ = write!(out, " {name}");
+        }
+        let _ = writeln!(out, " [{:.1} {:.1} {:.1} {:.1}]", bounds.x0, bounds.y0, bounds.x1, bounds.y1);
+        for (i, child) in element.children().iter().enumerate() {
+            rec(&**child, &format!("{path}.{i}"), out);
+        }
+    }
+
+    let mut out = String::new();
+    rec(&*root.rc(), "0", &mut out);
+    out
+}
+
+/// Pixels of an element tree painted offscreen (see `render_to_image`), or of a window (see `Window::capture`).
+#[derive(Clone, Debug)]
+pub struct RenderedImage {
+    pub width: u32,
+    pub height: u32,
+    /// RGBA8 pixels, with premultiplied alpha, row by row.
+    pub pixels: Vec<u8>,
+}
+
+impl RenderedImage {
+    /// Returns a hash of the pixels.
+    ///
+    /// The hash is stable across runs, but the pixels themselves depend on the fonts installed and on
+    /// the skia version, so painted-output snapshots should be recorded on the same machine configuration
+    /// as the one running the tests (e.g. the CI image).
+    pub fn hash(&self) -> u64 {
+        // FNV-1a
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for &byte in self.pixels.iter() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
+    }
+
+    fn image_info(&self) -> skia_safe::ImageInfo {
+        skia_safe::ImageInfo::new(
+            (self.width as i32, self.height as i32),
+            skia_safe::ColorType::RGBA8888,
+            skia_safe::AlphaType::Premul,
+            None,
+        )
+    }
+
+    /// Encodes the image to PNG.
+    pub fn encode_png(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let info = self.image_info();
+        let data = skia_safe::Data::new_copy(&self.pixels);
+        let image = skia_safe::images::raster_from_data(&info, data, info.min_row_bytes())
+            .context("invalid image dimensions")?;
+        let png = image
+            .encode(None, skia_safe::EncodedImageFormat::PNG, None)
+            .context("failed to encode image")?;
+        Ok(png.as_bytes().to_vec())
+    }
+
+    /// Saves the image to a PNG file.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
+        let path = path.as_ref();
+        fs::write(path, self.encode_png()?).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Lays out an element tree with the specified logical size, and paints it on an offscreen CPU surface.
+///
+/// This doesn't need a window or a GPU, and works in headless mode (see `application::run_headless`).
+/// The image is `size * scale_factor` pixels, with a transparent background.
+pub fn render_to_image(root: &Element, size: Size, scale_factor: f64) -> RenderedImage {
+    let root = root.rc();
+    root.do_layout(size);
+    let width = (size.width * scale_factor).ceil().max(1.0) as u32;
+    let height = (size.height * scale_factor).ceil().max(1.0) as u32;
+    let surface = DrawableSurface::raster(width as i32, height as i32);
+    surface.surface().canvas().clear(skia_safe::Color::TRANSPARENT);
+    root.do_paint(&surface, scale_factor);
+    // read back in a fixed format, the native format of raster surfaces depends on the platform
+    read_surface(&mut surface.surface(), None).expect("failed to read back the painted surface")
+}
+
+/// Reads back the pixels of a skia surface as RGBA8.
+///
+/// If `color_space` is specified, the pixels are converted from the color space of the surface;
+/// otherwise they are copied as is.
+pub(crate) fn read_surface(
+    surface: &mut skia_safe::Surface,
+    color_space: Option<skia_safe::ColorSpace>,
+) -> Option<RenderedImage> {
+    let (width, height) = (surface.width().max(0) as u32, surface.height().max(0) as u32);
+    let mut image = RenderedImage {
+        width,
+        height,
+        pixels: vec![0; width as usize * height as usize * 4],
+    };
+    let info = image.image_info().with_color_space(color_space);
+    surface
+        .read_pixels(&info, &mut image.pixels, info.min_row_bytes(), (0, 0))
+        .then_some(image)
+}
+
+/// Paints an element tree offscreen, and returns a hash of the pixels (see `RenderedImage::hash`).
+pub fn paint_hash(root: &Element, size: Size, scale_factor: f64) -> u64 {
+    render_to_image(root, size, scale_factor).hash()
+}
+
+/// Compares `actual` with the reference snapshot stored at `path`.
+///
+/// If the reference doesn't exist, or if the `KYUTE_UPDATE_SNAPSHOTS` environment variable is set,
+/// the reference is (over)written instead.
+///
+/// # Panics
+///
+/// If the snapshot doesn't match the reference.
+pub fn assert_snapshot(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+    if std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some() || !path.exists() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("failed to create the snapshot directory");
+        }
+        fs::write(path, actual).expect("failed to write the snapshot");
+        return;
+    }
+
+    let expected = fs::read_to_string(path).expect("failed to read the snapshot");
+    if expected == actual {
+        return;
+    }
+    let mismatch = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(e, a)| e != a)
+        .unwrap_or(expected.lines().count().min(actual.lines().count()));
+    panic!(
+        "snapshot mismatch: {} (line {})\n  expected: {}\n  actual:   {}\n(set {UPDATE_SNAPSHOTS_ENV}=1 to update)",
+        path.display(),
+        mismatch + 1,
+        expected.lines().nth(mismatch).unwrap_or("<end>"),
+        actual.lines().nth(mismatch).unwrap_or("<end>"),
+    );
+}
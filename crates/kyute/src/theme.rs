@@ -1,3 +1,9 @@
+use std::rc::Weak;
+
+use futures::future::AbortHandle;
+
+use crate::application::spawn;
+use crate::preferences::{preferences, preferences_changed, HighContrastColors};
 use crate::Color;
 
 pub mod palette {
@@ -281,6 +287,10 @@ pub struct Theme {
     pub content_background_color: Color,
     pub alternate_content_background_color: Color,
     pub accent_color: Color,
+    /// Text drawn over `accent_color` (e.g. selected items).
+    pub highlight_text_color: Color,
+    /// Text of disabled items.
+    pub disabled_text_color: Color,
 }
 
 pub const DARK_THEME: Theme = Theme {
@@ -293,6 +303,8 @@ pub const DARK_THEME: Theme = Theme {
     content_background_color: Color::from_hex("#212121"),
     alternate_content_background_color: Color::from_hex("#424242"),
     accent_color: Color::from_hex("#e91e63"),
+    highlight_text_color: Color::from_hex("#ffffff"),
+    disabled_text_color: Color::from_hex("#6e6e6e"),
 };
 
 pub const LIGHT_THEME: Theme = Theme {
@@ -305,4 +317,52 @@ pub const LIGHT_THEME: Theme = Theme {
     content_background_color: Color::from_hex("#212121"),
     alternate_content_background_color: Color::from_hex("#424242"),
     accent_color: Color::from_hex("#e91e63"),
+    highlight_text_color: Color::from_hex("#ffffff"),
+    disabled_text_color: Color::from_hex("#a0a0a0"),
 };
+
+impl Theme {
+    /// Theme with the colors of the system high-contrast theme.
+    pub fn high_contrast(colors: &HighContrastColors) -> Theme {
+        Theme {
+            text_color: colors.text,
+            window_background_color: colors.background,
+            text_background_color: colors.background,
+            content_background_color: colors.background,
+            alternate_content_background_color: colors.background,
+            accent_color: colors.highlight,
+            highlight_text_color: colors.highlight_text,
+            disabled_text_color: colors.disabled_text,
+            ..DARK_THEME
+        }
+    }
+}
+
+/// Returns the theme to use according to the accessibility preferences: the high-contrast theme if
+/// the system has one active, otherwise the dark theme.
+pub fn current() -> Theme {
+    match preferences().high_contrast {
+        Some(ref colors) => Theme::high_contrast(colors),
+        None => DARK_THEME,
+    }
+}
+
+/// Calls `f` with `target` each time the theme returned by `current` changes, until `target` is dropped.
+///
+/// Elements that resolve theme colors when they are built use this to build them again. Returns the
+/// handle of the task watching the preferences, to abort when `target` is dropped.
+pub fn on_theme_changed<T: 'static>(target: Weak<T>, f: impl Fn(&T) + 'static) -> AbortHandle {
+    let mut stream = preferences_changed();
+    let mut high_contrast = stream.borrow().high_contrast;
+    spawn(async move {
+        while stream.changed().await.is_ok() {
+            // the theme only depends on the high-contrast colors
+            let new_high_contrast = stream.borrow().high_contrast;
+            if std::mem::replace(&mut high_contrast, new_high_contrast) == new_high_contrast {
+                continue;
+            }
+            let Some(target) = target.upgrade() else { break };
+            f(&target);
+        }
+    })
+}
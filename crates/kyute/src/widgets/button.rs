@@ -6,7 +6,7 @@ use smallvec::smallvec;
 use crate::{Color, text};
 use crate::drawing::BoxShadow;
 use crate::text::TextStyle;
-use crate::theme::DARK_THEME;
+use crate::theme;
 use crate::widgets::frame::{Frame, FrameStyle, FrameStyleOverride, InteractState};
 use crate::widgets::text::Text;
 
@@ -38,7 +38,7 @@ fn button_style() -> FrameStyle {
             },
                 FrameStyleOverride {
                 state: InteractState::FOCUSED,
-                border_color: Some(DARK_THEME.accent_color),
+                border_color: Some(theme::current().accent_color),
                 ..Default::default()
                 },
                 FrameStyleOverride {
@@ -54,7 +54,7 @@ fn button_style() -> FrameStyle {
 
 pub fn button(label: impl Into<String>) -> Rc<Frame> {
     let label = label.into();
-    let theme = &DARK_THEME;
+    let theme = &theme::current();
     let text_style = TextStyle::new()
         .font_size(theme.font_size as f32)
         .font_family(theme.font_family)
@@ -0,0 +1,438 @@
+//! Infinite pannable and zoomable canvas.
+//!
+//! Children are positioned in canvas coordinates with the `CanvasPosition` attached property, and
+//! displayed through the view transform of the canvas (a translation and a uniform scale).
+//! This is the base for editors like node graphs or curve editors.
+use std::cell::{Cell, RefCell};
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use futures::future::AbortHandle;
+use kurbo::{Affine, Point, Rect, Size, Vec2};
+
+use crate::application::{spawn, wait_for};
+use crate::element::{AttachedProperty, Clip, Element, ElementMethods};
+use crate::event::{Event, PointerButton};
+use crate::handler::Handler;
+use crate::layout::{LayoutInput, LayoutOutput, SizeConstraint};
+use crate::preferences::preferences;
+use crate::window::WHEEL_LINE_HEIGHT;
+
+/// Zoom factor applied for each line (notch) of mouse wheel scroll.
+const WHEEL_ZOOM_STEP: f64 = 1.1;
+/// Interval between two steps of the inertia animation.
+const INERTIA_STEP: Duration = Duration::from_millis(16);
+/// Fraction of the panning velocity kept after one second of inertia.
+const INERTIA_FRICTION: f64 = 0.02;
+/// Inertia stops below this velocity, in pixels per second.
+const INERTIA_MIN_VELOCITY: f64 = 20.0;
+/// Panning velocity is ignored if the pointer was still for longer than this before release.
+const INERTIA_RELEASE_DELAY: Duration = Duration::from_millis(80);
+
+/// Position of an element in the coordinate space of its parent `Canvas`.
+pub struct CanvasPosition;
+
+impl AttachedProperty for CanvasPosition {
+    type Value = Point;
+}
+
+/// View of a canvas: maps canvas coordinates to view (element) coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CanvasView {
+    /// Position of the canvas origin in the view.
+    pub offset: Vec2,
+    /// Scale factor from canvas to view coordinates.
+    pub zoom: f64,
+}
+
+impl Default for CanvasView {
+    fn default() -> Self {
+        CanvasView {
+            offset: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl CanvasView {
+    /// Returns the transform from canvas coordinates to view coordinates.
+    pub fn transform(&self) -> Affine {
+        Affine::translate(self.offset) * Affine::scale(self.zoom)
+    }
+
+    pub fn view_to_canvas(&self, point: Point) -> Point {
+        ((point.to_vec2() - self.offset) / self.zoom).to_point()
+    }
+
+    pub fn canvas_to_view(&self, point: Point) -> Point {
+        (point.to_vec2() * self.zoom + self.offset).to_point()
+    }
+}
+
+/// Pointer drag state for panning.
+#[derive(Copy, Clone, Debug)]
+struct PanGesture {
+    last_position: Point,
+    last_time: Instant,
+    /// Smoothed panning velocity, in view pixels per second.
+    velocity: Vec2,
+}
+
+/// An infinite canvas that can be panned and zoomed.
+///
+/// Dragging the background with the left button, or anywhere with the middle button, pans the view,
+/// and the mouse wheel zooms around the pointer. Releasing a drag while moving keeps the view moving
+/// for a short time.
+pub struct Canvas {
+    element: Element,
+    weak_this: RefCell<Weak<Canvas>>,
+    view: Cell<CanvasView>,
+    min_zoom: Cell<f64>,
+    max_zoom: Cell<f64>,
+    /// Zoom levels at which the level of detail changes, in ascending order.
+    lod_thresholds: RefCell<Vec<f64>>,
+    lod: Cell<usize>,
+    view_changed: Handler<CanvasView>,
+    lod_changed: Handler<usize>,
+    gesture: Cell<Option<PanGesture>>,
+    inertia: Cell<bool>,
+    inertia_task: RefCell<Option<AbortHandle>>,
+}
+
+impl Deref for Canvas {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl Canvas {
+    pub fn new() -> Rc<Canvas> {
+        let canvas = Element::new_derived(|element| Canvas {
+            element,
+            weak_this: RefCell::new(Weak::new()),
+            view: Cell::new(CanvasView::default()),
+            min_zoom: Cell::new(0.05),
+            max_zoom: Cell::new(20.0),
+            lod_thresholds: RefCell::new(vec![]),
+            lod: Cell::new(0),
+            view_changed: Handler::new(),
+            lod_changed: Handler::new(),
+            gesture: Cell::new(None),
+            inertia: Cell::new(true),
+            inertia_task: RefCell::new(None),
+        });
+        canvas.weak_this.replace(Rc::downgrade(&canvas));
+        canvas
+    }
+
+    /// Emitted when the view is panned or zoomed.
+    pub async fn view_changed(&self) -> CanvasView {
+        self.view_changed.wait().await
+    }
+
+    /// Emitted when the zoom level crosses one of the level-of-detail thresholds, with the new level.
+    pub async fn lod_changed(&self) -> usize {
+        self.lod_changed.wait().await
+    }
+
+    /// Adds an element to the canvas at the specified position, in canvas coordinates.
+    pub fn add_item(&self, item: &Element, position: Point) {
+        item.set(CanvasPosition, position);
+        self.add_child(item);
+    }
+
+    /// Moves an element of the canvas to the specified position, in canvas coordinates.
+    pub fn set_item_position(&self, item: &Element, position: Point) {
+        item.set(CanvasPosition, position);
+        self.mark_needs_relayout();
+    }
+
+    pub fn view(&self) -> CanvasView {
+        self.view.get()
+    }
+
+    /// Sets the view. The zoom is clamped to the zoom range.
+    pub fn set_view(&self, view: CanvasView) {
+        if let Some(lod) = self.update_view(view) {
+            // can't await here, emit from a task
+            let this_weak = self.weak_this.borrow().clone();
+            spawn(async move {
+                if let Some(this) = this_weak.upgrade() {
+                    this.lod_changed.emit(lod).await;
+                }
+            });
+        }
+    }
+
+    pub fn set_zoom_range(&self, min_zoom: f64, max_zoom: f64) {
+        assert!(0.0 < min_zoom && min_zoom <= max_zoom);
+        self.min_zoom.set(min_zoom);
+        self.max_zoom.set(max_zoom);
+        self.set_view(self.view.get());
+    }
+
+    /// Sets the zoom levels at which the level of detail changes.
+    ///
+    /// The level of detail is the number of thresholds below the current zoom level: with thresholds
+    /// `[0.25, 1.0]`, it is 0 below 0.25, 1 between 0.25 and 1.0, and 2 above 1.0.
+    pub fn set_lod_thresholds(&self, thresholds: Vec<f64>) {
+        debug_assert!(thresholds.windows(2).all(|w| w[0] <= w[1]), "thresholds must be sorted");
+        self.lod_thresholds.replace(thresholds);
+        self.set_view(self.view.get());
+    }
+
+    /// Returns the current level of detail (see `set_lod_thresholds`).
+    pub fn lod(&self) -> usize {
+        self.lod.get()
+    }
+
+    /// Enables or disables inertia when a pan gesture is released.
+    pub fn set_inertia(&self, inertia: bool) {
+        self.inertia.set(inertia);
+    }
+
+    /// Converts a point from view (local element) coordinates to canvas coordinates.
+    pub fn view_to_canvas(&self, point: Point) -> Point {
+        self.view.get().view_to_canvas(point)
+    }
+
+    /// Converts a point from canvas coordinates to view (local element) coordinates.
+    pub fn canvas_to_view(&self, point: Point) -> Point {
+        self.view.get().canvas_to_view(point)
+    }
+
+    /// Returns the area of the canvas that is visible, in canvas coordinates.
+    pub fn visible_rect(&self) -> Rect {
+        let size = self.size();
+        Rect::from_points(
+            self.view_to_canvas(Point::ZERO),
+            self.view_to_canvas(Point::new(size.width, size.height)),
+        )
+    }
+
+    /// Pans the view by the specified amount, in view coordinates.
+    pub fn pan_by(&self, delta: Vec2) {
+        let view = self.view.get();
+        self.set_view(CanvasView {
+            offset: view.offset + delta,
+            ..view
+        });
+    }
+
+    /// Zooms around the specified point in view coordinates, which stays fixed.
+    pub fn zoom_about(&self, zoom: f64, anchor: Point) {
+        self.set_view(self.zoomed_view(zoom, anchor));
+    }
+
+    /// Adjusts the view so that the specified rectangle (in canvas coordinates) fits in the view,
+    /// with the specified margin in view pixels.
+    pub fn fit_rect(&self, rect: Rect, margin: f64) {
+        let size = self.size();
+        let available = Size::new((size.width - 2.0 * margin).max(1.0), (size.height - 2.0 * margin).max(1.0));
+        let zoom = (available.width / rect.width().max(1e-6)).min(available.height / rect.height().max(1e-6));
+        let zoom = zoom.clamp(self.min_zoom.get(), self.max_zoom.get());
+        let offset = size.to_rect().center().to_vec2() - rect.center().to_vec2() * zoom;
+        self.set_view(CanvasView { offset, zoom });
+    }
+
+    fn zoomed_view(&self, zoom: f64, anchor: Point) -> CanvasView {
+        let view = self.view.get();
+        let zoom = zoom.clamp(self.min_zoom.get(), self.max_zoom.get());
+        let canvas_anchor = view.view_to_canvas(anchor);
+        CanvasView {
+            offset: anchor.to_vec2() - canvas_anchor.to_vec2() * zoom,
+            zoom,
+        }
+    }
+
+    /// Applies the view. Returns the new level of detail if it has changed.
+    fn update_view(&self, mut view: CanvasView) -> Option<usize> {
+        view.zoom = view.zoom.clamp(self.min_zoom.get(), self.max_zoom.get());
+        if view != self.view.get() {
+            self.view.set(view);
+            self.mark_needs_relayout();
+        }
+
+        let lod = self.lod_thresholds.borrow().iter().filter(|&&t| view.zoom >= t).count();
+        if lod != self.lod.replace(lod) {
+            Some(lod)
+        } else {
+            None
+        }
+    }
+
+    /// Applies the view from an event handler, and emits the change notifications.
+    async fn update_view_and_notify(&self, view: CanvasView) {
+        let lod = self.update_view(view);
+        if let Some(lod) = lod {
+            self.lod_changed.emit(lod).await;
+        }
+        self.view_changed.emit(self.view.get()).await;
+    }
+
+    /// Returns whether there's an item of the canvas under the specified point, in view coordinates.
+    fn item_at(&self, point: Point) -> bool {
+        self.children()
+            .iter()
+            .any(|child| child.hit_test(child.transform().inverse() * point))
+    }
+
+    fn stop_inertia(&self) {
+        if let Some(task) = self.inertia_task.take() {
+            task.abort();
+        }
+    }
+
+    /// Keeps panning the view with a decaying velocity.
+    fn start_inertia(&self, velocity: Vec2) {
+        self.stop_inertia();
+        if !self.inertia.get() || preferences().reduced_motion || velocity.hypot() < INERTIA_MIN_VELOCITY {
+            return;
+        }
+        let this_weak = self.weak_this.borrow().clone();
+        let task = spawn(async move {
+            let mut velocity = velocity;
+            let mut last = Instant::now();
+            loop {
+                wait_for(INERTIA_STEP).await;
+                let Some(this) = this_weak.upgrade() else { break };
+                let now = Instant::now();
+                let dt = (now - last).as_secs_f64();
+                last = now;
+                let view = this.view.get();
+                this.update_view_and_notify(CanvasView {
+                    offset: view.offset + velocity * dt,
+                    ..view
+                })
+                .await;
+                velocity *= INERTIA_FRICTION.powf(dt);
+                if velocity.hypot() < INERTIA_MIN_VELOCITY {
+                    break;
+                }
+            }
+        });
+        self.inertia_task.replace(Some(task));
+    }
+}
+
+impl ElementMethods for Canvas {
+    fn element(&self) -> &Element {
+        &self.element
+    }
+
+    fn measure(&self, _children: &[Rc<dyn ElementMethods>], layout_input: &LayoutInput) -> LayoutOutput {
+        // the canvas takes all the available space
+        LayoutOutput {
+            width: layout_input.width.available().filter(|w| w.is_finite()).unwrap_or(0.0),
+            height: layout_input.height.available().filter(|h| h.is_finite()).unwrap_or(0.0),
+            baseline: None,
+        }
+    }
+
+    fn layout(&self, children: &[Rc<dyn ElementMethods>], size: Size) -> LayoutOutput {
+        let view_transform = self.view.get().transform();
+        for child in children {
+            // children get their ideal size
+            let ideal = child.do_measure(&LayoutInput {
+                width: SizeConstraint::Unspecified,
+                height: SizeConstraint::Unspecified,
+            });
+            child.do_layout(Size::new(ideal.width, ideal.height));
+            let position = child.get(CanvasPosition).unwrap_or_default();
+            child.set_transform(view_transform * Affine::translate(position.to_vec2()));
+        }
+
+        let bounds = Clip::Rect(size.to_rect());
+        if !matches!(self.clip(), Some(Clip::Rect(rect)) if rect == size.to_rect()) {
+            self.set_clip(Some(bounds));
+        }
+
+        LayoutOutput {
+            width: size.width,
+            height: size.height,
+            baseline: None,
+        }
+    }
+
+    async fn event(&self, event: &mut Event)
+    where
+        Self: Sized,
+    {
+        match event {
+            Event::PointerDown(event) => {
+                // the left button only pans when pressed on the background
+                let pan = match event.button {
+                    Some(PointerButton::MIDDLE) => true,
+                    Some(PointerButton::LEFT) => !self.item_at(event.local_position()),
+                    _ => false,
+                };
+                if pan {
+                    self.stop_inertia();
+                    self.gesture.set(Some(PanGesture {
+                        last_position: event.local_position(),
+                        last_time: Instant::now(),
+                        velocity: Vec2::ZERO,
+                    }));
+                    self.set_pointer_capture();
+                }
+            }
+            Event::PointerMove(event) => {
+                if let Some(mut gesture) = self.gesture.get() {
+                    let pos = event.local_position();
+                    let delta = pos - gesture.last_position;
+                    let now = Instant::now();
+                    let dt = (now - gesture.last_time).as_secs_f64();
+                    if dt > 0.0 {
+                        // smooth the velocity over the last few events
+                        gesture.velocity = gesture.velocity * 0.5 + delta / dt * 0.5;
+                    }
+                    gesture.last_position = pos;
+                    gesture.last_time = now;
+                    self.gesture.set(Some(gesture));
+                    let view = self.view.get();
+                    self.update_view_and_notify(CanvasView {
+                        offset: view.offset + delta,
+                        ..view
+                    })
+                    .await;
+                }
+            }
+            Event::PointerUp(_) => {
+                if let Some(gesture) = self.gesture.take() {
+                    if gesture.last_time.elapsed() < INERTIA_RELEASE_DELAY {
+                        self.start_inertia(gesture.velocity);
+                    }
+                }
+            }
+            Event::Wheel(wheel) => {
+                self.stop_inertia();
+                let steps = wheel.delta.y / WHEEL_LINE_HEIGHT;
+                let zoom = self.view.get().zoom * WHEEL_ZOOM_STEP.powf(steps);
+                let view = self.zoomed_view(zoom, wheel.pointer.local_position());
+                self.update_view_and_notify(view).await;
+            }
+            Event::Gesture(gesture) => {
+                // two-finger pan and pinch-zoom, replacing a single-finger pan
+                self.stop_inertia();
+                self.gesture.set(None);
+                let view = self.view.get();
+                let panned = CanvasView {
+                    offset: view.offset + gesture.translation,
+                    ..view
+                };
+                let zoom = (view.zoom * gesture.scale).clamp(self.min_zoom.get(), self.max_zoom.get());
+                let anchor = gesture.pointer.local_position();
+                let canvas_anchor = panned.view_to_canvas(anchor);
+                self.update_view_and_notify(CanvasView {
+                    offset: anchor.to_vec2() - canvas_anchor.to_vec2() * zoom,
+                    zoom,
+                })
+                .await;
+            }
+            _ => {}
+        }
+    }
+}
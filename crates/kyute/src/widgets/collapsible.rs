@@ -0,0 +1,471 @@
+//! Collapsible sections and accordions.
+//!
+//! A `Collapsible` is a header with a title, above content that is shown or hidden by clicking on
+//! the header. Whether the section is expanded is stored in a `Property<bool>`, which can be shared
+//! with the owner (e.g. to persist the state of parameter panels). Changes are animated.
+//!
+//! An `Accordion` stacks collapsible sections vertically, optionally keeping at most one of them expanded.
+use std::cell::{Cell, RefCell};
+use std::f64::consts::FRAC_PI_2;
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use futures::future::AbortHandle;
+use kurbo::{Affine, BezPath, Point, Rect, Size, Vec2};
+
+use crate::application::{spawn, wait_for};
+use crate::drawing::{Paint, ToSkia};
+use crate::element::{Clip, Element, ElementMethods};
+use crate::event::Event;
+use crate::layout::{LayoutInput, LayoutOutput, SizeConstraint};
+use crate::preferences::preferences;
+use crate::reactive::Property;
+use crate::text::{FontWeight, TextStyle};
+use crate::theme;
+use crate::widgets::text::Text;
+use crate::{text, Color, PaintCtx};
+
+const HEADER_HEIGHT: f64 = 24.0;
+const HEADER_PADDING: f64 = 6.0;
+const DISCLOSURE_SIZE: f64 = 8.0;
+/// Duration of the expand and collapse animations.
+const ANIMATION_DURATION: Duration = Duration::from_millis(150);
+/// Interval between two steps of the expand and collapse animations.
+const ANIMATION_STEP: Duration = Duration::from_millis(16);
+
+/// A section with a header that shows or hides its content when clicked.
+pub struct Collapsible {
+    element: Element,
+    weak_this: RefCell<Weak<Collapsible>>,
+    expanded: Rc<Property<bool>>,
+    title_text: String,
+    title: RefCell<Rc<dyn ElementMethods>>,
+    content: RefCell<Option<Rc<dyn ElementMethods>>>,
+    /// Whether expanding and collapsing is animated.
+    animated: Cell<bool>,
+    /// Fraction of the content height that is shown, between 0 and 1.
+    openness: Cell<f64>,
+    /// Height of the content, updated on measure.
+    content_height: Cell<f64>,
+    header_hovered: Cell<bool>,
+    animation_task: RefCell<Option<AbortHandle>>,
+    /// Task starting the animation when `expanded` changes.
+    watch_task: RefCell<Option<AbortHandle>>,
+    /// Task building the title again when the theme changes.
+    theme_task: RefCell<Option<AbortHandle>>,
+}
+
+impl Deref for Collapsible {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl Drop for Collapsible {
+    fn drop(&mut self) {
+        if let Some(task) = self.animation_task.take() {
+            task.abort();
+        }
+        if let Some(task) = self.watch_task.take() {
+            task.abort();
+        }
+        if let Some(task) = self.theme_task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Returns the title element of a section, styled with the current theme.
+fn title_element(title: &str) -> Rc<dyn ElementMethods> {
+    let theme = &theme::current();
+    let title_style = TextStyle::new()
+        .font_size(theme.font_size as f32)
+        .font_family(theme.font_family)
+        .font_weight(FontWeight::SEMI_BOLD)
+        .color(theme.text_color);
+    Text::new(text!( style(title_style) "{title}" ))
+}
+
+impl Collapsible {
+    /// Creates a collapsible section with the specified title, expanded according to `expanded`.
+    pub fn new(title: impl Into<String>, expanded: Rc<Property<bool>>) -> Rc<Collapsible> {
+        let title = title.into();
+        let initial = if expanded.get() { 1.0 } else { 0.0 };
+        let collapsible = Element::new_derived(|element| Collapsible {
+            element,
+            weak_this: RefCell::new(Weak::new()),
+            expanded: expanded.clone(),
+            title: RefCell::new(title_element(&title)),
+            title_text: title,
+            content: RefCell::new(None),
+            animated: Cell::new(true),
+            openness: Cell::new(initial),
+            content_height: Cell::new(0.0),
+            header_hovered: Cell::new(false),
+            animation_task: RefCell::new(None),
+            watch_task: RefCell::new(None),
+            theme_task: RefCell::new(None),
+        });
+        collapsible.weak_this.replace(Rc::downgrade(&collapsible));
+        collapsible.add_child(&collapsible.title.borrow());
+
+        let this_weak = Rc::downgrade(&collapsible);
+        let mut stream = expanded.stream();
+        let task = spawn(async move {
+            while stream.changed().await.is_ok() {
+                let Some(this) = this_weak.upgrade() else { break };
+                this.start_animation();
+            }
+        });
+        collapsible.watch_task.replace(Some(task));
+        let task = theme::on_theme_changed(Rc::downgrade(&collapsible), Collapsible::update_theme);
+        collapsible.theme_task.replace(Some(task));
+        collapsible
+    }
+
+    /// Builds the title again with the current theme.
+    fn update_theme(&self) {
+        let title = title_element(&self.title_text);
+        let old = self.title.replace(title.clone());
+        old.insert_after(&title);
+        old.detach();
+    }
+
+    /// Returns the property holding whether the section is expanded.
+    pub fn expanded(&self) -> &Rc<Property<bool>> {
+        &self.expanded
+    }
+
+    pub fn is_expanded(&self) -> bool {
+        self.expanded.get()
+    }
+
+    pub fn set_expanded(&self, expanded: bool) {
+        self.expanded
+            .modify(|value| std::mem::replace(value, expanded) != expanded);
+    }
+
+    /// Sets whether expanding and collapsing is animated. Enabled by default; animations are skipped
+    /// anyway when the reduced motion preference is set.
+    pub fn set_animated(&self, animated: bool) {
+        self.animated.set(animated);
+    }
+
+    /// Sets the element shown when the section is expanded.
+    pub fn set_content(&self, content: Rc<dyn ElementMethods>) {
+        if let Some(old) = self.content.replace(Some(content.clone())) {
+            old.detach();
+        }
+        self.add_child(&content);
+    }
+
+    fn stop_animation(&self) {
+        if let Some(task) = self.animation_task.take() {
+            task.abort();
+        }
+    }
+
+    /// Animates `openness` towards the current value of `expanded`.
+    fn start_animation(&self) {
+        self.stop_animation();
+        let target = if self.expanded.get() { 1.0 } else { 0.0 };
+        if !self.animated.get() || preferences().reduced_motion {
+            self.openness.set(target);
+            self.mark_needs_relayout();
+            return;
+        }
+        let this_weak = self.weak_this.borrow().clone();
+        let task = spawn(async move {
+            let mut last = Instant::now();
+            loop {
+                wait_for(ANIMATION_STEP).await;
+                let Some(this) = this_weak.upgrade() else { break };
+                let now = Instant::now();
+                let step = (now - last).as_secs_f64() / ANIMATION_DURATION.as_secs_f64();
+                last = now;
+                let openness = this.openness.get();
+                let openness = if target > openness {
+                    (openness + step).min(target)
+                } else {
+                    (openness - step).max(target)
+                };
+                this.openness.set(openness);
+                this.mark_needs_relayout();
+                if openness == target {
+                    break;
+                }
+            }
+        });
+        self.animation_task.replace(Some(task));
+    }
+
+    /// Returns the height of the section for the current animation state.
+    fn height(&self) -> f64 {
+        // ease in-out
+        let t = self.openness.get();
+        let t = t * t * (3.0 - 2.0 * t);
+        HEADER_HEIGHT + t * self.content_height.get()
+    }
+}
+
+impl ElementMethods for Collapsible {
+    fn element(&self) -> &Element {
+        &self.element
+    }
+
+    fn measure(&self, _children: &[Rc<dyn ElementMethods>], layout_input: &LayoutInput) -> LayoutOutput {
+        let title_width = self
+            .title
+            .borrow()
+            .do_measure(&LayoutInput {
+                width: SizeConstraint::MAX,
+                height: SizeConstraint::MAX,
+            })
+            .width;
+        let mut width = title_width + 2.0 * HEADER_PADDING + DISCLOSURE_SIZE;
+        let content_width = layout_input.width.available().filter(|w| w.is_finite());
+        if let Some(ref content) = *self.content.borrow() {
+            let output = content.do_measure(&LayoutInput {
+                width: layout_input.width,
+                height: SizeConstraint::MAX,
+            });
+            width = width.max(output.width);
+            self.content_height.set(output.height);
+        } else {
+            self.content_height.set(0.0);
+        }
+        LayoutOutput {
+            width: content_width.unwrap_or(width),
+            height: self.height(),
+            baseline: None,
+        }
+    }
+
+    fn layout(&self, _children: &[Rc<dyn ElementMethods>], size: Size) -> LayoutOutput {
+        let title_x = 2.0 * HEADER_PADDING + DISCLOSURE_SIZE;
+        let title = self.title.borrow();
+        let output = title.do_layout(Size::new(
+            (size.width - title_x - HEADER_PADDING).max(0.0),
+            HEADER_HEIGHT,
+        ));
+        title.set_offset(Vec2::new(title_x, (HEADER_HEIGHT - output.height) / 2.0));
+
+        if let Some(ref content) = *self.content.borrow() {
+            let output = content.do_measure(&LayoutInput {
+                width: size.width.into(),
+                height: SizeConstraint::MAX,
+            });
+            self.content_height.set(output.height);
+            content.do_layout(Size::new(size.width, output.height));
+            content.set_offset(Vec2::new(0.0, HEADER_HEIGHT));
+        }
+
+        // hide the part of the content that is collapsed
+        let height = self.height();
+        self.set_clip(Some(Clip::Rect(Rect::new(0.0, 0.0, size.width, height))));
+        LayoutOutput {
+            width: size.width,
+            height,
+            baseline: None,
+        }
+    }
+
+    fn paint(&self, ctx: &mut PaintCtx) {
+        let theme = &theme::current();
+        let width = self.size().width;
+        let header_rect = Rect::new(0.0, 0.0, width, HEADER_HEIGHT);
+        let header_color = if self.header_hovered.get() {
+            Color::from_rgba_u8(255, 255, 255, 16)
+        } else {
+            Color::from_rgba_u8(255, 255, 255, 8)
+        };
+
+        ctx.with_canvas(|canvas| {
+            canvas.draw_rect(
+                header_rect.to_skia(),
+                &Paint::from(header_color).to_sk_paint(header_rect),
+            );
+
+            // disclosure triangle, pointing right when collapsed and down when expanded
+            let h = 0.5 * DISCLOSURE_SIZE;
+            let mut triangle = BezPath::new();
+            triangle.move_to(Point::new(-0.5 * h, -h));
+            triangle.line_to(Point::new(0.75 * h, 0.0));
+            triangle.line_to(Point::new(-0.5 * h, h));
+            triangle.close_path();
+            let center = Point::new(HEADER_PADDING + h, 0.5 * HEADER_HEIGHT);
+            let triangle =
+                Affine::translate(center.to_vec2()) * Affine::rotate(self.openness.get() * FRAC_PI_2) * triangle;
+            let mut paint = Paint::from(theme.text_color).to_sk_paint(header_rect);
+            paint.set_anti_alias(true);
+            canvas.draw_path(&triangle.to_skia(), &paint);
+        });
+    }
+
+    async fn event(&self, event: &mut Event)
+    where
+        Self: Sized,
+    {
+        match event {
+            Event::PointerDown(event) => {
+                if event.local_position().y < HEADER_HEIGHT {
+                    self.set_expanded(!self.is_expanded());
+                }
+            }
+            Event::PointerMove(event) => {
+                let hovered = event.local_position().y < HEADER_HEIGHT;
+                if hovered != self.header_hovered.replace(hovered) {
+                    self.mark_needs_repaint();
+                }
+            }
+            Event::PointerLeave(_) => {
+                if self.header_hovered.replace(false) {
+                    self.mark_needs_repaint();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Collapsible sections stacked vertically.
+pub struct Accordion {
+    element: Element,
+    weak_this: RefCell<Weak<Accordion>>,
+    sections: RefCell<Vec<Rc<Collapsible>>>,
+    /// Whether expanding a section collapses the others.
+    exclusive: Cell<bool>,
+    /// Tasks watching the `expanded` property of each section.
+    watch_tasks: RefCell<Vec<AbortHandle>>,
+}
+
+impl Deref for Accordion {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl Drop for Accordion {
+    fn drop(&mut self) {
+        for task in self.watch_tasks.take() {
+            task.abort();
+        }
+    }
+}
+
+impl Accordion {
+    pub fn new() -> Rc<Accordion> {
+        let accordion = Element::new_derived(|element| Accordion {
+            element,
+            weak_this: RefCell::new(Weak::new()),
+            sections: RefCell::new(vec![]),
+            exclusive: Cell::new(false),
+            watch_tasks: RefCell::new(vec![]),
+        });
+        accordion.weak_this.replace(Rc::downgrade(&accordion));
+        accordion
+    }
+
+    /// Sets whether at most one section can be expanded at a time.
+    ///
+    /// When enabled, all sections but the first expanded one are collapsed.
+    pub fn set_exclusive(&self, exclusive: bool) {
+        self.exclusive.set(exclusive);
+        if exclusive {
+            let first = self.sections.borrow().iter().position(|s| s.is_expanded());
+            if let Some(first) = first {
+                self.collapse_others(first);
+            }
+        }
+    }
+
+    /// Appends a section.
+    pub fn add_section(&self, section: &Rc<Collapsible>) {
+        self.add_child(section);
+        self.sections.borrow_mut().push(section.clone());
+        if self.exclusive.get() && section.is_expanded() {
+            self.collapse_others(self.sections.borrow().len() - 1);
+        }
+
+        let this_weak = self.weak_this.borrow().clone();
+        let section_weak = Rc::downgrade(section);
+        let mut stream = section.expanded().stream();
+        let task = spawn(async move {
+            while stream.changed().await.is_ok() {
+                let Some(this) = this_weak.upgrade() else { break };
+                let Some(section) = section_weak.upgrade() else { break };
+                if !this.exclusive.get() || !section.is_expanded() {
+                    continue;
+                }
+                let index = this.sections.borrow().iter().position(|s| Rc::ptr_eq(s, &section));
+                if let Some(index) = index {
+                    this.collapse_others(index);
+                }
+            }
+        });
+        self.watch_tasks.borrow_mut().push(task);
+    }
+
+    pub fn sections(&self) -> Vec<Rc<Collapsible>> {
+        self.sections.borrow().clone()
+    }
+
+    fn collapse_others(&self, index: usize) {
+        for (i, section) in self.sections.borrow().iter().enumerate() {
+            if i != index {
+                section.set_expanded(false);
+            }
+        }
+    }
+}
+
+impl ElementMethods for Accordion {
+    fn element(&self) -> &Element {
+        &self.element
+    }
+
+    fn measure(&self, _children: &[Rc<dyn ElementMethods>], layout_input: &LayoutInput) -> LayoutOutput {
+        let mut width: f64 = 0.0;
+        let mut height = 0.0;
+        for section in self.sections.borrow().iter() {
+            let output = section.do_measure(&LayoutInput {
+                width: layout_input.width,
+                height: SizeConstraint::MAX,
+            });
+            width = width.max(output.width);
+            height += output.height;
+        }
+        LayoutOutput {
+            width: layout_input
+                .width
+                .available()
+                .filter(|w| w.is_finite())
+                .unwrap_or(width),
+            height,
+            baseline: None,
+        }
+    }
+
+    fn layout(&self, _children: &[Rc<dyn ElementMethods>], size: Size) -> LayoutOutput {
+        let mut y = 0.0;
+        for section in self.sections.borrow().iter() {
+            let output = section.do_measure(&LayoutInput {
+                width: size.width.into(),
+                height: SizeConstraint::MAX,
+            });
+            section.do_layout(Size::new(size.width, output.height));
+            section.set_offset(Vec2::new(0.0, y));
+            y += output.height;
+        }
+        LayoutOutput {
+            width: size.width,
+            height: y,
+            baseline: None,
+        }
+    }
+}
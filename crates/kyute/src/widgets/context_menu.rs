@@ -0,0 +1,215 @@
+//! Context menus, and the standard editing commands of text elements.
+//!
+//! Context menus are shown in popup windows (see `Window::set_popup`). They close when an item is
+//! clicked, or when the popup loses focus (e.g. the user clicked elsewhere).
+use std::rc::Rc;
+
+use futures_util::future::select_all;
+use futures_util::FutureExt;
+use keyboard_types::{Key, KeyboardEvent};
+use kurbo::{Point, Size};
+use smallvec::smallvec;
+use tokio::select;
+
+use crate::layout::flex::Axis;
+use crate::layout::{PaddingBottom, PaddingLeft, PaddingRight, PaddingTop};
+use crate::text::TextStyle;
+use crate::theme;
+use crate::widgets::frame::{Frame, FrameLayout, FrameStyle, FrameStyleOverride, InteractState};
+use crate::widgets::text::Text;
+use crate::{text, Color, Window, WindowOptions};
+
+const MENU_WIDTH: f64 = 200.0;
+const ITEM_HEIGHT: f64 = 24.0;
+const MENU_PADDING: f64 = 4.0;
+
+/// An item of a context menu.
+#[derive(Clone, Debug)]
+pub struct MenuItem {
+    pub label: String,
+    /// Keyboard shortcut displayed next to the label (e.g. `Ctrl+C`). Informative only.
+    pub shortcut: Option<String>,
+    /// Disabled items are grayed out and can't be clicked.
+    pub enabled: bool,
+}
+
+impl MenuItem {
+    pub fn new(label: impl Into<String>) -> MenuItem {
+        MenuItem {
+            label: label.into(),
+            shortcut: None,
+            enabled: true,
+        }
+    }
+
+    pub fn shortcut(mut self, shortcut: impl Into<String>) -> MenuItem {
+        self.shortcut = Some(shortcut.into());
+        self
+    }
+
+    pub fn enabled(mut self, enabled: bool) -> MenuItem {
+        self.enabled = enabled;
+        self
+    }
+}
+
+fn menu_item_style(enabled: bool) -> FrameStyle {
+    FrameStyle {
+        layout: FrameLayout::Flex {
+            direction: Axis::Horizontal,
+        },
+        border_radius: 4.0.into(),
+        overrides: if enabled {
+            smallvec![FrameStyleOverride {
+                state: InteractState::HOVERED,
+                background_color: Some(theme::current().alternate_content_background_color),
+                ..Default::default()
+            }]
+        } else {
+            smallvec![]
+        },
+        ..Default::default()
+    }
+}
+
+/// Shows a context menu at the specified position in `parent`, and waits for it to be closed.
+///
+/// `position` is in logical window coordinates (e.g. `PointerEvent::position`). Returns the index of
+/// the item that was clicked, or `None` if the menu was dismissed.
+pub async fn show_context_menu(parent: &Window, position: Point, items: &[MenuItem]) -> Option<usize> {
+    let theme = &theme::current();
+    let text_style = TextStyle::new()
+        .font_size(theme.font_size as f32)
+        .font_family(theme.font_family)
+        .color(theme.text_color);
+    let disabled_style = text_style.clone().color(theme.disabled_text_color);
+
+    let root = Frame::new(FrameStyle {
+        layout: FrameLayout::Flex {
+            direction: Axis::Vertical,
+        },
+        border_color: Color::from_hex("4c3e0a"),
+        background_color: theme.content_background_color,
+        ..Default::default()
+    });
+    PaddingLeft.set(&root, MENU_PADDING.into());
+    PaddingRight.set(&root, MENU_PADDING.into());
+    PaddingTop.set(&root, MENU_PADDING.into());
+    PaddingBottom.set(&root, MENU_PADDING.into());
+
+    let mut rows: Vec<(usize, Rc<Frame>)> = vec![];
+    for (index, item) in items.iter().enumerate() {
+        let style = if item.enabled { &text_style } else { &disabled_style };
+        let row = Frame::new(menu_item_style(item.enabled));
+        PaddingLeft.set(&row, 8.0.into());
+        PaddingRight.set(&row, 8.0.into());
+        PaddingTop.set(&row, 4.0.into());
+        PaddingBottom.set(&row, 4.0.into());
+        let label = &item.label;
+        row.add_child(&Text::new(text!( style(style) "{label}" )));
+        if let Some(ref shortcut) = item.shortcut {
+            let shortcut_text = Text::new(text!( style(disabled_style) "{shortcut}" ));
+            PaddingLeft.set(&shortcut_text, 24.0.into());
+            row.add_child(&shortcut_text);
+        }
+        root.add_child(&row);
+        if item.enabled {
+            rows.push((index, row));
+        }
+    }
+
+    // the popup is positioned in screen coordinates, which don't include the UI scale factor
+    let origin = parent.inner_bounds().origin();
+    let ui_scale = parent.ui_scale();
+    let size = Size::new(MENU_WIDTH, items.len() as f64 * ITEM_HEIGHT + 2.0 * MENU_PADDING) * ui_scale;
+    let options = WindowOptions {
+        title: "",
+        size,
+        parent: Some(parent.raw_window_handle()),
+        decorations: false,
+        position: Some(origin + position.to_vec2() * ui_scale),
+        background: theme.content_background_color,
+        ui_scale,
+        ..Default::default()
+    };
+    let popup = Window::new(&options, &root);
+    parent.set_popup(&popup);
+
+    let clicked = async {
+        if rows.is_empty() {
+            std::future::pending().await
+        } else {
+            let (_, i, _) = select_all(rows.iter().map(|(_, row)| row.clicked().boxed_local())).await;
+            rows[i].0
+        }
+    };
+    let dismissed = async {
+        loop {
+            if !popup.focus_changed().await {
+                break;
+            }
+        }
+    };
+    select! {
+        index = clicked => Some(index),
+        _ = dismissed => None,
+        _ = popup.close_requested() => None,
+    }
+}
+
+/// Standard editing commands of text elements.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum EditCommand {
+    Cut,
+    Copy,
+    Paste,
+    SelectAll,
+}
+
+impl EditCommand {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EditCommand::Cut => "Cut",
+            EditCommand::Copy => "Copy",
+            EditCommand::Paste => "Paste",
+            EditCommand::SelectAll => "Select All",
+        }
+    }
+
+    pub fn shortcut(&self) -> &'static str {
+        match self {
+            EditCommand::Cut => "Ctrl+X",
+            EditCommand::Copy => "Ctrl+C",
+            EditCommand::Paste => "Ctrl+V",
+            EditCommand::SelectAll => "Ctrl+A",
+        }
+    }
+
+    /// Returns the command triggered by a key press, if it's one of the standard shortcuts.
+    pub fn from_key_event(event: &KeyboardEvent) -> Option<EditCommand> {
+        let modifiers = event.modifiers;
+        if !modifiers.ctrl() || modifiers.alt() || modifiers.shift() {
+            return None;
+        }
+        let Key::Character(ref s) = event.key else { return None };
+        match s.to_lowercase().as_str() {
+            "x" => Some(EditCommand::Cut),
+            "c" => Some(EditCommand::Copy),
+            "v" => Some(EditCommand::Paste),
+            "a" => Some(EditCommand::SelectAll),
+            _ => None,
+        }
+    }
+}
+
+/// Shows a context menu with the specified editing commands, and returns the one that was chosen.
+///
+/// Each command is given with whether it's currently enabled (e.g. "Copy" is disabled when the selection is empty).
+pub async fn show_edit_menu(parent: &Window, position: Point, commands: &[(EditCommand, bool)]) -> Option<EditCommand> {
+    let items: Vec<_> = commands
+        .iter()
+        .map(|(command, enabled)| MenuItem::new(command.label()).shortcut(command.shortcut()).enabled(*enabled))
+        .collect();
+    let index = show_context_menu(parent, position, &items).await?;
+    Some(commands[index].0)
+}
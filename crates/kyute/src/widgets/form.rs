@@ -0,0 +1,241 @@
+//! Form layout: rows of labels and fields.
+//!
+//! Labels are in a column shared by all rows, sized to the widest label, and each label is aligned
+//! on the first baseline of its field. When the form is too narrow for both columns, labels are
+//! placed above their fields instead.
+use std::cell::{Cell, RefCell};
+use std::ops::Deref;
+use std::rc::Rc;
+
+use futures::future::AbortHandle;
+use kurbo::{Size, Vec2};
+
+use crate::element::{Element, ElementMethods};
+use crate::layout::{LayoutInput, LayoutOutput, SizeConstraint};
+use crate::text::TextStyle;
+use crate::widgets::text::Text;
+use crate::{text, theme};
+
+/// Space between the label column and the field column.
+const COLUMN_GAP: f64 = 8.0;
+/// Space between two rows.
+const ROW_GAP: f64 = 4.0;
+/// Space between a label and its field when the label is placed above.
+const WRAPPED_LABEL_GAP: f64 = 2.0;
+
+struct FormRow {
+    label_text: String,
+    label: Rc<Text>,
+    field: Rc<dyn ElementMethods>,
+}
+
+/// Position and size of the label and the field of a row.
+struct RowGeometry {
+    label_offset: Vec2,
+    label_size: Size,
+    field_offset: Vec2,
+    field_size: Size,
+}
+
+/// Rows of labels and fields, with labels in a shared column and aligned on the baseline of their fields.
+pub struct Form {
+    element: Element,
+    rows: RefCell<Vec<FormRow>>,
+    /// Maximum width of the label column. Longer labels wrap.
+    max_label_width: Cell<f64>,
+    /// Minimum width of the field column, below which labels are placed above their fields.
+    min_field_width: Cell<f64>,
+    /// Task building the labels again when the theme changes.
+    theme_task: RefCell<Option<AbortHandle>>,
+}
+
+impl Deref for Form {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl Drop for Form {
+    fn drop(&mut self) {
+        if let Some(task) = self.theme_task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Returns the label element of a row, styled with the current theme.
+fn label_element(label: &str) -> Rc<Text> {
+    let theme = &theme::current();
+    let label_style = TextStyle::new()
+        .font_size(theme.font_size as f32)
+        .font_family(theme.font_family)
+        .color(theme.text_color);
+    Text::new(text!( style(label_style) "{label}" ))
+}
+
+impl Form {
+    pub fn new() -> Rc<Form> {
+        let form = Element::new_derived(|element| Form {
+            element,
+            rows: RefCell::new(vec![]),
+            max_label_width: Cell::new(200.0),
+            min_field_width: Cell::new(120.0),
+            theme_task: RefCell::new(None),
+        });
+        let task = theme::on_theme_changed(Rc::downgrade(&form), Form::update_theme);
+        form.theme_task.replace(Some(task));
+        form
+    }
+
+    /// Appends a row with the specified label and field.
+    pub fn add_row(&self, label: impl Into<String>, field: Rc<dyn ElementMethods>) {
+        let label_text = label.into();
+        let label = label_element(&label_text);
+        self.add_child(&label);
+        self.add_child(&field);
+        self.rows.borrow_mut().push(FormRow {
+            label_text,
+            label,
+            field,
+        });
+        self.mark_needs_relayout();
+    }
+
+    /// Builds the labels again with the current theme.
+    fn update_theme(&self) {
+        for row in self.rows.borrow_mut().iter_mut() {
+            let label = label_element(&row.label_text);
+            row.label.insert_after(&label);
+            row.label.detach();
+            row.label = label;
+        }
+    }
+
+    /// Sets the maximum width of the label column. Labels longer than that wrap. 200 by default.
+    pub fn set_max_label_width(&self, width: f64) {
+        self.max_label_width.set(width);
+        self.mark_needs_relayout();
+    }
+
+    /// Sets the width below which the field column would be too narrow, and labels are placed above
+    /// their fields. 120 by default.
+    pub fn set_min_field_width(&self, width: f64) {
+        self.min_field_width.set(width);
+        self.mark_needs_relayout();
+    }
+
+    /// Computes the geometry of the rows for the specified width (or the natural width of the form if
+    /// unspecified), and returns the size and baseline of the form.
+    fn arrange(&self, width: Option<f64>) -> (LayoutOutput, Vec<RowGeometry>) {
+        let rows = self.rows.borrow();
+        let max_content = LayoutInput {
+            width: SizeConstraint::MAX,
+            height: SizeConstraint::MAX,
+        };
+
+        let label_width = rows
+            .iter()
+            .map(|row| row.label.do_measure(&max_content).width)
+            .fold(0.0, f64::max)
+            .min(self.max_label_width.get());
+        let field_width = match width {
+            Some(width) => width - label_width - COLUMN_GAP,
+            None => rows
+                .iter()
+                .map(|row| row.field.do_measure(&max_content).width)
+                .fold(0.0, f64::max),
+        };
+        let wrapped = field_width < self.min_field_width.get();
+        let width = width.unwrap_or(label_width + COLUMN_GAP + field_width);
+
+        let mut geometry = Vec::with_capacity(rows.len());
+        let mut baseline = None;
+        let mut y = 0.0;
+        for (i, row) in rows.iter().enumerate() {
+            if i > 0 {
+                y += ROW_GAP;
+            }
+            let (label_column, field_column) = if wrapped {
+                (width, width)
+            } else {
+                (label_width, field_width)
+            };
+            let label = row.label.do_measure(&LayoutInput {
+                width: label_column.into(),
+                height: SizeConstraint::MAX,
+            });
+            let field = row.field.do_measure(&LayoutInput {
+                width: field_column.into(),
+                height: SizeConstraint::MAX,
+            });
+            let label_size = Size::new(label.width.min(label_column), label.height);
+            let field_size = Size::new(field.width.min(field_column), field.height);
+            let label_baseline = label.baseline.unwrap_or(label.height);
+
+            let row_geometry = if wrapped {
+                let field_y = y + label.height + WRAPPED_LABEL_GAP;
+                RowGeometry {
+                    label_offset: Vec2::new(0.0, y),
+                    label_size,
+                    field_offset: Vec2::new(0.0, field_y),
+                    field_size,
+                }
+            } else {
+                // align the label on the first baseline of the field, or center it on fields without text
+                let (label_y, field_y) = match field.baseline {
+                    Some(field_baseline) => {
+                        let row_baseline = label_baseline.max(field_baseline);
+                        (row_baseline - label_baseline, row_baseline - field_baseline)
+                    }
+                    None => {
+                        let row_height = label.height.max(field.height);
+                        (0.5 * (row_height - label.height), 0.5 * (row_height - field.height))
+                    }
+                };
+                RowGeometry {
+                    label_offset: Vec2::new(0.0, y + label_y),
+                    label_size,
+                    field_offset: Vec2::new(label_width + COLUMN_GAP, y + field_y),
+                    field_size,
+                }
+            };
+
+            if i == 0 {
+                baseline = Some(row_geometry.label_offset.y + label_baseline);
+            }
+            y = (row_geometry.label_offset.y + label.height).max(row_geometry.field_offset.y + field.height);
+            geometry.push(row_geometry);
+        }
+
+        let output = LayoutOutput {
+            width,
+            height: y,
+            baseline,
+        };
+        (output, geometry)
+    }
+}
+
+impl ElementMethods for Form {
+    fn element(&self) -> &Element {
+        &self.element
+    }
+
+    fn measure(&self, _children: &[Rc<dyn ElementMethods>], layout_input: &LayoutInput) -> LayoutOutput {
+        let width = layout_input.width.available().filter(|w| w.is_finite());
+        self.arrange(width).0
+    }
+
+    fn layout(&self, _children: &[Rc<dyn ElementMethods>], size: Size) -> LayoutOutput {
+        let (output, geometry) = self.arrange(Some(size.width));
+        for (row, geometry) in self.rows.borrow().iter().zip(geometry) {
+            row.label.do_layout(geometry.label_size);
+            row.label.set_offset(geometry.label_offset);
+            row.field.do_layout(geometry.field_size);
+            row.field.set_offset(geometry.field_offset);
+        }
+        output
+    }
+}
@@ -4,7 +4,7 @@ use std::ops::Deref;
 use std::rc::Rc;
 
 use bitflags::bitflags;
-use kurbo::{Insets, RoundedRect, Size};
+use kurbo::{Insets, RoundedRect, Size, Vec2};
 use smallvec::SmallVec;
 use tracing::{trace, trace_span};
 
@@ -12,6 +12,7 @@ use crate::drawing::{BoxShadow, Paint, ToSkia};
 use crate::element::{Element, ElementMethods};
 use crate::event::Event;
 use crate::handler::Handler;
+use crate::layout::anchor::{do_anchor_layout, AnchorLayoutParams};
 use crate::layout::flex::{do_flex_layout, Axis, CrossAxisAlignment, FlexLayoutParams, MainAxisAlignment};
 use crate::layout::{
     FlexSize, LayoutInput, LayoutOutput, LengthOrPercentage, PaddingBottom, PaddingLeft, PaddingRight, PaddingTop,
@@ -74,6 +75,8 @@ pub struct FrameStyleOverride {
 #[derive(Clone)]
 pub enum FrameLayout {
     Flex { direction: Axis }, // TODO grid
+    /// Children are positioned with anchors (see `layout::anchor`).
+    Anchored,
 }
 
 impl Default for FrameLayout {
@@ -311,22 +314,31 @@ impl Frame {
             .height
             .resolve_length(self.get(PaddingBottom).unwrap_or_default());
 
-        let direction = match s.layout {
-            FrameLayout::Flex { direction, .. } => direction,
-        };
-
         // layout children
         // TODO other layouts
-        let flex_params = FlexLayoutParams {
-            axis: direction,
-            width_constraint: layout_input.width.deflate(padding_left + padding_right),
-            height_constraint: layout_input.height.deflate(padding_top + padding_bottom),
-            gap: FlexSize::NULL,
-            initial_gap: FlexSize::NULL,
-            final_gap: FlexSize::NULL,
+        let width_constraint = layout_input.width.deflate(padding_left + padding_right);
+        let height_constraint = layout_input.height.deflate(padding_top + padding_bottom);
+        let mut layout_output = match s.layout {
+            FrameLayout::Flex { direction, .. } => {
+                let flex_params = FlexLayoutParams {
+                    axis: direction,
+                    width_constraint,
+                    height_constraint,
+                    gap: FlexSize::NULL,
+                    initial_gap: FlexSize::NULL,
+                    final_gap: FlexSize::NULL,
+                };
+                do_flex_layout(&flex_params, children)
+            }
+            FrameLayout::Anchored => {
+                let anchor_params = AnchorLayoutParams {
+                    width_constraint,
+                    height_constraint,
+                    origin: Vec2::new(padding_left, padding_top),
+                };
+                do_anchor_layout(&anchor_params, children)
+            }
         };
-
-        let mut layout_output = do_flex_layout(&flex_params, children);
         layout_output.width += padding_left + padding_right;
         layout_output.height += padding_top + padding_bottom;
         layout_output.baseline.as_mut().map(|b| *b += padding_top);
@@ -0,0 +1,444 @@
+//! Rich text from a constrained subset of markdown, for tooltips, error messages and release notes.
+//!
+//! Supported syntax:
+//! - headings (`#`, `##`, `###`)
+//! - bullet lists (lines starting with `- ` or `* `)
+//! - paragraphs, separated by blank lines; consecutive lines are joined
+//! - `**bold**`, `*italic*` or `_italic_`, `` `code` `` and `[links](target)`
+//! - `\` escapes the next character
+//!
+//! Anything else is shown as plain text. Each block is laid out as a separate paragraph of text.
+//! Clicking a link emits its target with `link_activated`; opening it is up to the owner.
+use std::cell::RefCell;
+use std::ops::{Deref, Range};
+use std::rc::Rc;
+
+use kurbo::{Point, Size, Vec2};
+
+use crate::element::{Element, ElementMethods};
+use crate::event::{Event, PointerButton};
+use crate::handler::Handler;
+use crate::layout::{LayoutInput, LayoutOutput, SizeConstraint};
+use crate::text::{FontStyle, FontWeight, TextRun, TextStyle};
+use crate::theme;
+use crate::widgets::text::Text;
+
+/// Space between two blocks.
+const BLOCK_GAP: f64 = 6.0;
+/// Indentation of the text of list items, after the bullet.
+const LIST_INDENT: f64 = 16.0;
+/// Font sizes of headings, relative to the font size of the theme.
+const HEADING_SCALE: [f64; 3] = [1.5, 1.25, 1.1];
+const CODE_FONT_FAMILY: &str = "Consolas";
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct SpanStyle {
+    bold: bool,
+    italic: bool,
+    code: bool,
+}
+
+/// Run of text with the same style.
+#[derive(Clone, Debug, PartialEq)]
+struct Span {
+    text: String,
+    style: SpanStyle,
+    /// Target of the link that the span is part of.
+    link: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum BlockKind {
+    Paragraph,
+    /// Heading, with its level starting from 1.
+    Heading(u8),
+    ListItem,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Block {
+    kind: BlockKind,
+    spans: Vec<Span>,
+}
+
+/// Splits the source into blocks, and parses their inline formatting.
+fn parse(source: &str) -> Vec<Block> {
+    let mut blocks = vec![];
+    // kind and source text of the block being accumulated
+    let mut current: Option<(BlockKind, String)> = None;
+
+    fn flush(blocks: &mut Vec<Block>, current: &mut Option<(BlockKind, String)>) {
+        if let Some((kind, text)) = current.take() {
+            blocks.push(Block {
+                kind,
+                spans: parse_inline(&text),
+            });
+        }
+    }
+
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            flush(&mut blocks, &mut current);
+            continue;
+        }
+
+        let heading_level = trimmed.bytes().take_while(|&b| b == b'#').count();
+        if (1..=HEADING_SCALE.len()).contains(&heading_level) && trimmed[heading_level..].starts_with(' ') {
+            flush(&mut blocks, &mut current);
+            let text = trimmed[heading_level..].trim().to_string();
+            current = Some((BlockKind::Heading(heading_level as u8), text));
+            flush(&mut blocks, &mut current);
+        } else if let Some(item) = trimmed.strip_prefix("- ").or_else(|| trimmed.strip_prefix("* ")) {
+            flush(&mut blocks, &mut current);
+            current = Some((BlockKind::ListItem, item.trim().to_string()));
+        } else {
+            // continuation of a paragraph or of a list item
+            match current {
+                Some((_, ref mut text)) => {
+                    text.push(' ');
+                    text.push_str(trimmed);
+                }
+                None => current = Some((BlockKind::Paragraph, trimmed.to_string())),
+            }
+        }
+    }
+    flush(&mut blocks, &mut current);
+    blocks
+}
+
+/// Parses the inline formatting of a block.
+///
+/// Unterminated markers are shown as is.
+fn parse_inline(text: &str) -> Vec<Span> {
+    let mut spans: Vec<Span> = vec![];
+    let mut style = SpanStyle::default();
+    let mut link: Option<String> = None;
+
+    fn push(spans: &mut Vec<Span>, text: &str, style: SpanStyle, link: &Option<String>) {
+        if text.is_empty() {
+            return;
+        }
+        match spans.last_mut() {
+            Some(last) if last.style == style && last.link == *link => last.text.push_str(text),
+            _ => spans.push(Span {
+                text: text.to_string(),
+                style,
+                link: link.clone(),
+            }),
+        }
+    }
+
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        let prev = text[..text.len() - rest.len()].chars().next_back();
+        match c {
+            '\\' if !after.is_empty() => {
+                let escaped = after.chars().next().unwrap();
+                push(&mut spans, &after[..escaped.len_utf8()], style, &link);
+                rest = &after[escaped.len_utf8()..];
+            }
+            // code spans aren't formatted inside
+            '`' if !style.code => match after.find('`') {
+                Some(end) => {
+                    let code = SpanStyle { code: true, ..style };
+                    push(&mut spans, &after[..end], code, &link);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    push(&mut spans, "`", style, &link);
+                    rest = after;
+                }
+            },
+            '*' if after.starts_with('*') && (style.bold || after[1..].contains("**")) => {
+                style.bold = !style.bold;
+                rest = &after[1..];
+            }
+            '*' | '_' if toggles_italic(c, prev, after, style.italic) => {
+                style.italic = !style.italic;
+                rest = after;
+            }
+            '[' if link.is_none() => match parse_link(after) {
+                Some((label, target, remaining)) => {
+                    link = Some(target.to_string());
+                    for span in parse_inline(label) {
+                        let span_style = SpanStyle {
+                            bold: style.bold || span.style.bold,
+                            italic: style.italic || span.style.italic,
+                            code: span.style.code,
+                        };
+                        push(&mut spans, &span.text, span_style, &link);
+                    }
+                    link = None;
+                    rest = remaining;
+                }
+                None => {
+                    push(&mut spans, "[", style, &link);
+                    rest = after;
+                }
+            },
+            _ => {
+                // copy up to the next character that may be a marker
+                let end = rest
+                    .find(|c: char| matches!(c, '\\' | '`' | '*' | '_' | '['))
+                    .filter(|&end| end > 0)
+                    .unwrap_or(c.len_utf8());
+                push(&mut spans, &rest[..end], style, &link);
+                rest = &rest[end..];
+            }
+        }
+    }
+    spans
+}
+
+/// Returns whether a `*` or `_` marker starts or ends italic text.
+///
+/// Markers must be followed by text to start italics, and underscores inside words (e.g. in
+/// identifiers of error messages) are left as is.
+fn toggles_italic(marker: char, prev: Option<char>, after: &str, italic: bool) -> bool {
+    let next = after.chars().next();
+    if italic {
+        marker != '_' || !next.is_some_and(char::is_alphanumeric)
+    } else {
+        let intraword = marker == '_' && prev.is_some_and(char::is_alphanumeric);
+        let starts_text = next.is_some_and(|next| !next.is_whitespace() && next != marker);
+        starts_text && !intraword && after.contains(marker)
+    }
+}
+
+/// Parses the rest of a link after the opening bracket: `label](target)`.
+///
+/// Returns the label, the target and the text after the link.
+fn parse_link(text: &str) -> Option<(&str, &str, &str)> {
+    let label_end = text.find("](")?;
+    let target_start = label_end + 2;
+    let target_end = target_start + text[target_start..].find(')')?;
+    Some((
+        &text[..label_end],
+        text[target_start..target_end].trim(),
+        &text[target_end + 1..],
+    ))
+}
+
+/// Laid out block.
+struct BlockElement {
+    kind: BlockKind,
+    text: Rc<Text>,
+    bullet: Option<Rc<Text>>,
+    /// Byte ranges of the links in the text, with their targets.
+    links: Vec<(Range<usize>, String)>,
+}
+
+/// Rich text from a markdown subset.
+pub struct Markdown {
+    element: Element,
+    blocks: RefCell<Vec<BlockElement>>,
+    /// Emitted with the target of a link when it is clicked.
+    pub link_activated: Handler<String>,
+}
+
+impl Deref for Markdown {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl Markdown {
+    pub fn new(source: &str) -> Rc<Markdown> {
+        let markdown = Element::new_derived(|element| Markdown {
+            element,
+            blocks: RefCell::new(vec![]),
+            link_activated: Default::default(),
+        });
+        markdown.set_source(source);
+        markdown
+    }
+
+    /// Replaces the contents with the specified markdown source.
+    pub fn set_source(&self, source: &str) {
+        for block in self.blocks.take() {
+            block.text.detach();
+            if let Some(bullet) = block.bullet {
+                bullet.detach();
+            }
+        }
+
+        let theme = &theme::current();
+        let base_style = TextStyle::new()
+            .font_size(theme.font_size as f32)
+            .font_family(theme.font_family)
+            .color(theme.text_color);
+
+        let mut blocks = vec![];
+        for block in parse(source) {
+            let block_style = match block.kind {
+                BlockKind::Heading(level) => base_style
+                    .clone()
+                    .font_size((theme.font_size * HEADING_SCALE[level as usize - 1]) as f32)
+                    .font_weight(FontWeight::SEMI_BOLD),
+                BlockKind::Paragraph | BlockKind::ListItem => base_style.clone(),
+            };
+
+            let mut styles = Vec::with_capacity(block.spans.len());
+            let mut links = vec![];
+            let mut pos = 0;
+            for span in block.spans.iter() {
+                let mut style = block_style.clone();
+                if span.style.bold {
+                    style.font_weight = FontWeight::BOLD;
+                }
+                if span.style.italic {
+                    style.font_style = FontStyle::Italic;
+                }
+                if span.style.code {
+                    style.font_family = CODE_FONT_FAMILY.into();
+                }
+                if let Some(ref target) = span.link {
+                    style.color = theme.accent_color;
+                    links.push((pos..pos + span.text.len(), target.clone()));
+                }
+                pos += span.text.len();
+                styles.push(style);
+            }
+            let runs: Vec<TextRun> = block
+                .spans
+                .iter()
+                .zip(styles.iter())
+                .map(|(span, style)| TextRun { str: &span.text, style })
+                .collect();
+
+            let text = Text::new(&runs);
+            self.add_child(&text);
+            let bullet = if block.kind == BlockKind::ListItem {
+                let bullet = Text::new(&[TextRun {
+                    str: "•",
+                    style: &base_style,
+                }]);
+                self.add_child(&bullet);
+                Some(bullet)
+            } else {
+                None
+            };
+            blocks.push(BlockElement {
+                kind: block.kind,
+                text,
+                bullet,
+                links,
+            });
+        }
+        self.blocks.replace(blocks);
+        self.mark_needs_relayout();
+    }
+
+    /// Waits for a link to be clicked, and returns its target.
+    pub async fn link_activated(&self) -> String {
+        self.link_activated.wait().await
+    }
+
+    /// Returns the target of the link under the specified point, in local coordinates.
+    fn link_at(&self, point: Point) -> Option<String> {
+        let blocks = self.blocks.borrow();
+        let (block, local) = blocks.iter().find_map(|block| {
+            let local = block.text.transform().inverse() * point;
+            block.text.size().to_rect().contains(local).then_some((block, local))
+        })?;
+        let pos = block.text.text_position_for_point(local);
+        block
+            .links
+            .iter()
+            .find(|(range, _)| range.contains(&pos))
+            .map(|(_, target)| target.clone())
+    }
+
+    /// Measures the blocks for the specified width, and lays them out if `layout` is true.
+    fn arrange(&self, width: Option<f64>, layout: bool) -> LayoutOutput {
+        let blocks = self.blocks.borrow();
+        let mut output_width: f64 = 0.0;
+        let mut baseline = None;
+        let mut y = 0.0;
+        for (i, block) in blocks.iter().enumerate() {
+            if i > 0 {
+                y += BLOCK_GAP;
+            }
+            let indent = if block.kind == BlockKind::ListItem {
+                LIST_INDENT
+            } else {
+                0.0
+            };
+            let text_width = width.map(|w| (w - indent).max(0.0));
+            let text = block.text.do_measure(&LayoutInput {
+                width: text_width.map_or(SizeConstraint::MAX, SizeConstraint::from),
+                height: SizeConstraint::MAX,
+            });
+            if layout {
+                block
+                    .text
+                    .do_layout(Size::new(text_width.unwrap_or(text.width), text.height));
+                block.text.set_offset(Vec2::new(indent, y));
+                if let Some(ref bullet) = block.bullet {
+                    // align the bullet on the first line of the item
+                    let bullet_size = bullet.do_measure(&LayoutInput {
+                        width: SizeConstraint::MAX,
+                        height: SizeConstraint::MAX,
+                    });
+                    let bullet_baseline = bullet_size.baseline.unwrap_or(bullet_size.height);
+                    let text_baseline = text.baseline.unwrap_or(bullet_baseline);
+                    bullet.do_layout(Size::new(bullet_size.width, bullet_size.height));
+                    bullet.set_offset(Vec2::new(0.0, y + text_baseline - bullet_baseline));
+                }
+            }
+            if i == 0 {
+                baseline = text.baseline.map(|b| y + b);
+            }
+            output_width = output_width.max(indent + text.width);
+            y += text.height;
+        }
+        LayoutOutput {
+            width: width.unwrap_or(output_width),
+            height: y,
+            baseline,
+        }
+    }
+}
+
+impl ElementMethods for Markdown {
+    fn element(&self) -> &Element {
+        &self.element
+    }
+
+    fn measure(&self, _children: &[Rc<dyn ElementMethods>], layout_input: &LayoutInput) -> LayoutOutput {
+        let width = layout_input.width.available().filter(|w| w.is_finite());
+        let output = self.arrange(width, false);
+        // don't take all the available width if the text is narrower
+        let natural = self.arrange(None, false);
+        LayoutOutput {
+            width: output.width.min(natural.width),
+            ..output
+        }
+    }
+
+    fn layout(&self, _children: &[Rc<dyn ElementMethods>], size: Size) -> LayoutOutput {
+        self.arrange(Some(size.width), true)
+    }
+
+    fn hit_test(&self, point: Point) -> bool {
+        self.size().to_rect().contains(point)
+    }
+
+    async fn event(&self, event: &mut Event)
+    where
+        Self: Sized,
+    {
+        match event {
+            Event::PointerUp(event) if event.button == Some(PointerButton::LEFT) => {
+                if let Some(target) = self.link_at(event.local_position()) {
+                    self.link_activated.emit(target).await;
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -2,4 +2,11 @@ pub mod text;
 pub mod button;
 //mod interact;
 pub mod frame;
-pub mod text_edit;
\ No newline at end of file
+pub mod text_edit;
+pub mod table;
+pub mod canvas;
+pub mod tabs;
+pub mod collapsible;
+pub mod context_menu;
+pub mod form;
+pub mod markdown;
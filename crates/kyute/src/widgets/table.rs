@@ -0,0 +1,705 @@
+//! Data tables with resizable, reorderable and sortable columns.
+//!
+//! Rows are virtualized: cell elements are only created (by the `TableModel`) for the rows that
+//! are visible. The table scrolls by whole rows so that no cell is partially visible.
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+use std::ops::{Deref, Range};
+use std::rc::{Rc, Weak};
+
+use keyboard_types::Modifiers;
+use kurbo::{Rect, Size, Vec2};
+
+use crate::application::spawn;
+use crate::drawing::{Paint, ToSkia};
+use crate::element::{Element, ElementMethods};
+use crate::event::Event;
+use crate::handler::Handler;
+use crate::layout::{LayoutInput, LayoutOutput};
+use crate::text::TextStyle;
+use crate::theme;
+use crate::widgets::text::Text;
+use crate::{text, Color, PaintCtx};
+
+const HEADER_HEIGHT: f64 = 24.0;
+const DEFAULT_ROW_HEIGHT: f64 = 22.0;
+const CELL_PADDING: f64 = 4.0;
+/// Width of the area around column separators that starts a column resize.
+const RESIZE_HANDLE_WIDTH: f64 = 4.0;
+/// Distance the pointer must move on a column header before the column is dragged.
+const DRAG_THRESHOLD: f64 = 4.0;
+const SORT_INDICATOR_SIZE: f64 = 8.0;
+
+/// Sort order of a table column.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+impl SortOrder {
+    pub fn reversed(self) -> SortOrder {
+        match self {
+            SortOrder::Ascending => SortOrder::Descending,
+            SortOrder::Descending => SortOrder::Ascending,
+        }
+    }
+}
+
+/// Provides the rows of a `Table`.
+pub trait TableModel {
+    fn row_count(&self) -> usize;
+
+    /// Creates the element displayed in the specified cell.
+    ///
+    /// Only called for visible rows; cells are dropped when their row is scrolled out of view.
+    fn cell(&self, row: usize, column: usize) -> Rc<dyn ElementMethods>;
+
+    /// Sorts the rows by the specified column.
+    ///
+    /// Called when the user clicks the header of a sortable column. The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn sort(&self, column: usize, order: SortOrder) {}
+}
+
+/// Describes a column of a `Table`.
+#[derive(Clone, Debug)]
+pub struct TableColumn {
+    pub title: String,
+    pub width: f64,
+    /// Minimum width when resized by the user.
+    pub min_width: f64,
+    /// Whether clicking the header sorts the table by this column.
+    pub sortable: bool,
+}
+
+impl TableColumn {
+    pub fn new(title: impl Into<String>, width: f64) -> TableColumn {
+        TableColumn {
+            title: title.into(),
+            width,
+            min_width: 24.0,
+            sortable: false,
+        }
+    }
+
+    pub fn min_width(mut self, min_width: f64) -> Self {
+        self.min_width = min_width;
+        self
+    }
+
+    pub fn sortable(mut self, sortable: bool) -> Self {
+        self.sortable = sortable;
+        self
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Gesture {
+    /// Resizing the column at the specified display position.
+    Resize { column: usize, start_x: f64, start_width: f64 },
+    /// Pointer pressed on the header of the column at the specified display position.
+    ///
+    /// Becomes a column drag once the pointer has moved past `DRAG_THRESHOLD`, otherwise sorts
+    /// the column on release.
+    Header { column: usize, start_x: f64, dragging: bool },
+}
+
+/// A table with column headers and per-cell element content.
+pub struct Table {
+    element: Element,
+    weak_this: RefCell<Weak<Table>>,
+    selection_changed: Handler<Vec<usize>>,
+    sort_changed: Handler<(usize, SortOrder)>,
+    model: RefCell<Option<Rc<dyn TableModel>>>,
+    columns: RefCell<Vec<TableColumn>>,
+    /// Model column index of each displayed column, in display order.
+    column_order: RefCell<Vec<usize>>,
+    header_style: RefCell<TextStyle<'static>>,
+    /// Header labels, in model column order.
+    headers: RefCell<Vec<Rc<dyn ElementMethods>>>,
+    row_height: Cell<f64>,
+    /// Index of the first visible row.
+    first_row: Cell<usize>,
+    /// Number of rows that fit in the table, updated on layout.
+    visible_row_count: Cell<usize>,
+    /// Rows that have cell elements.
+    realized_rows: RefCell<Range<usize>>,
+    /// Cells of the realized rows, in model column order.
+    cells: RefCell<Vec<Vec<Rc<dyn ElementMethods>>>>,
+    selection: RefCell<BTreeSet<usize>>,
+    /// Row from which shift-click extends the selection.
+    selection_anchor: Cell<Option<usize>>,
+    sort: Cell<Option<(usize, SortOrder)>>,
+    gesture: Cell<Option<Gesture>>,
+    update_scheduled: Cell<bool>,
+}
+
+impl Deref for Table {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl Table {
+    pub fn new() -> Rc<Table> {
+        let theme = &theme::current();
+        let table = Element::new_derived(|element| Table {
+            element,
+            weak_this: RefCell::new(Weak::new()),
+            selection_changed: Handler::new(),
+            sort_changed: Handler::new(),
+            model: RefCell::new(None),
+            columns: RefCell::new(vec![]),
+            column_order: RefCell::new(vec![]),
+            header_style: RefCell::new(
+                TextStyle::new()
+                    .font_size(theme.font_size as f32)
+                    .font_family(theme.font_family)
+                    .color(theme.text_color),
+            ),
+            headers: RefCell::new(vec![]),
+            row_height: Cell::new(DEFAULT_ROW_HEIGHT),
+            first_row: Cell::new(0),
+            visible_row_count: Cell::new(0),
+            realized_rows: RefCell::new(0..0),
+            cells: RefCell::new(vec![]),
+            selection: RefCell::new(BTreeSet::new()),
+            selection_anchor: Cell::new(None),
+            sort: Cell::new(None),
+            gesture: Cell::new(None),
+            update_scheduled: Cell::new(false),
+        });
+        table.weak_this.replace(Rc::downgrade(&table));
+        table
+    }
+
+    /// Emitted when the selection changes as a result of user interaction.
+    pub async fn selection_changed(&self) -> Vec<usize> {
+        self.selection_changed.wait().await
+    }
+
+    /// Emitted when the user sorts the table by clicking on a column header.
+    pub async fn sort_changed(&self) -> (usize, SortOrder) {
+        self.sort_changed.wait().await
+    }
+
+    /// Sets the columns of the table. This resets the column order.
+    pub fn set_columns(&self, columns: Vec<TableColumn>) {
+        self.column_order.replace((0..columns.len()).collect());
+        self.columns.replace(columns);
+        self.sort.set(None);
+        self.create_headers();
+        self.reset_rows();
+    }
+
+    fn create_headers(&self) {
+        let header_style = self.header_style.borrow().clone();
+        let headers = self
+            .columns
+            .borrow()
+            .iter()
+            .map(|column| -> Rc<dyn ElementMethods> {
+                let title = &column.title;
+                Text::new(text!( style(header_style.clone()) "{title}" ))
+            })
+            .collect();
+        self.headers.replace(headers);
+    }
+
+    /// Returns the columns, with their current widths.
+    pub fn columns(&self) -> Vec<TableColumn> {
+        self.columns.borrow().clone()
+    }
+
+    /// Returns the model column index of each displayed column, in display order.
+    pub fn column_order(&self) -> Vec<usize> {
+        self.column_order.borrow().clone()
+    }
+
+    /// Sets the display order of the columns.
+    ///
+    /// `order` must be a permutation of the model column indices.
+    pub fn set_column_order(&self, order: Vec<usize>) {
+        assert_eq!(order.len(), self.columns.borrow().len());
+        self.column_order.replace(order);
+        self.rebuild_children();
+    }
+
+    pub fn set_header_style(&self, style: TextStyle<'static>) {
+        self.header_style.replace(style);
+        self.create_headers();
+        self.rebuild_children();
+    }
+
+    pub fn set_row_height(&self, height: f64) {
+        self.row_height.set(height);
+        self.mark_needs_relayout();
+    }
+
+    /// Sets the model providing the rows. This clears the selection.
+    pub fn set_model(&self, model: Rc<dyn TableModel>) {
+        self.model.replace(Some(model));
+        self.selection.borrow_mut().clear();
+        self.selection_anchor.set(None);
+        self.first_row.set(0);
+        self.reset_rows();
+    }
+
+    /// Recreates all visible cells. Call this when the contents of the model have changed.
+    pub fn reset_rows(&self) {
+        self.cells.borrow_mut().clear();
+        self.realized_rows.replace(0..0);
+        self.update_rows();
+    }
+
+    fn row_count(&self) -> usize {
+        self.model.borrow().as_ref().map(|m| m.row_count()).unwrap_or(0)
+    }
+
+    /// Returns the selected rows, in ascending order.
+    pub fn selection(&self) -> Vec<usize> {
+        self.selection.borrow().iter().copied().collect()
+    }
+
+    pub fn set_selection(&self, rows: impl IntoIterator<Item = usize>) {
+        self.selection.replace(rows.into_iter().collect());
+        self.mark_needs_repaint();
+    }
+
+    /// Scrolls the table so that the specified row is visible.
+    pub fn scroll_to_row(&self, row: usize) {
+        let first = self.first_row.get();
+        let visible = self.visible_row_count.get().max(1);
+        if row < first {
+            self.set_first_row(row);
+        } else if row >= first + visible {
+            self.set_first_row(row + 1 - visible);
+        }
+    }
+
+    fn set_first_row(&self, row: usize) {
+        let max_first = self.row_count().saturating_sub(self.visible_row_count.get());
+        let row = row.min(max_first);
+        if row != self.first_row.get() {
+            self.first_row.set(row);
+            self.update_rows();
+        }
+    }
+
+    fn visible_rows(&self) -> Range<usize> {
+        let first = self.first_row.get();
+        first..(first + self.visible_row_count.get()).min(self.row_count())
+    }
+
+    /// Creates the cells of the visible rows, reusing the cells of rows that were already visible.
+    fn update_rows(&self) {
+        let visible = self.visible_rows();
+        let realized = self.realized_rows.borrow().clone();
+        if visible == realized {
+            return;
+        }
+
+        let model = self.model.borrow().clone();
+        let column_count = self.columns.borrow().len();
+        let mut old_cells = std::mem::take(&mut *self.cells.borrow_mut());
+        let cells = visible
+            .clone()
+            .map(|row| {
+                if realized.contains(&row) {
+                    std::mem::take(&mut old_cells[row - realized.start])
+                } else if let Some(ref model) = model {
+                    (0..column_count).map(|column| model.cell(row, column)).collect()
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+        self.cells.replace(cells);
+        self.realized_rows.replace(visible);
+        self.rebuild_children();
+    }
+
+    /// Re-adds the header labels and the cells of realized rows as children.
+    fn rebuild_children(&self) {
+        let children: Vec<_> = self.element.children().iter().cloned().collect();
+        for child in children {
+            child.detach();
+        }
+        let order = self.column_order.borrow();
+        for &column in order.iter() {
+            if let Some(header) = self.headers.borrow().get(column) {
+                self.add_child(header);
+            }
+        }
+        for row in self.cells.borrow().iter() {
+            for &column in order.iter() {
+                if let Some(cell) = row.get(column) {
+                    self.add_child(cell);
+                }
+            }
+        }
+        self.mark_needs_relayout();
+    }
+
+    /// Schedules an update of the realized rows after the current layout pass.
+    fn schedule_update(&self) {
+        if self.update_scheduled.replace(true) {
+            return;
+        }
+        let this_weak = self.weak_this.borrow().clone();
+        spawn(async move {
+            if let Some(this) = this_weak.upgrade() {
+                this.update_scheduled.set(false);
+                this.update_rows();
+            }
+        });
+    }
+
+    /// Returns the horizontal extent of each displayed column.
+    fn column_extents(&self) -> Vec<(f64, f64)> {
+        let columns = self.columns.borrow();
+        let mut x = 0.0;
+        self.column_order
+            .borrow()
+            .iter()
+            .map(|&column| {
+                let width = columns[column].width;
+                let extent = (x, x + width);
+                x += width;
+                extent
+            })
+            .collect()
+    }
+
+    /// Returns the display position of the column under `x`.
+    fn column_at(&self, x: f64) -> Option<usize> {
+        self.column_extents().iter().position(|&(x0, x1)| x >= x0 && x < x1)
+    }
+
+    /// Returns the display position of the column whose right edge is near `x`.
+    fn resize_handle_at(&self, x: f64) -> Option<usize> {
+        self.column_extents()
+            .iter()
+            .position(|&(_, x1)| (x - x1).abs() <= RESIZE_HANDLE_WIDTH)
+    }
+
+    fn row_at(&self, y: f64) -> Option<usize> {
+        if y < HEADER_HEIGHT {
+            return None;
+        }
+        let row = self.first_row.get() + ((y - HEADER_HEIGHT) / self.row_height.get()) as usize;
+        if row < self.visible_rows().end {
+            Some(row)
+        } else {
+            None
+        }
+    }
+
+    fn update_selection(&self, row: usize, modifiers: Modifiers) {
+        let mut selection = self.selection.borrow_mut();
+        if modifiers.contains(Modifiers::SHIFT) {
+            let anchor = self.selection_anchor.get().unwrap_or(row);
+            if !modifiers.contains(Modifiers::CONTROL) {
+                selection.clear();
+            }
+            selection.extend(anchor.min(row)..=anchor.max(row));
+        } else if modifiers.contains(Modifiers::CONTROL) {
+            if !selection.remove(&row) {
+                selection.insert(row);
+            }
+            self.selection_anchor.set(Some(row));
+        } else {
+            selection.clear();
+            selection.insert(row);
+            self.selection_anchor.set(Some(row));
+        }
+        self.mark_needs_repaint();
+    }
+
+    /// Sorts the table by the column at the specified display position.
+    async fn sort_by_column(&self, display_column: usize) {
+        let column = self.column_order.borrow()[display_column];
+        if !self.columns.borrow()[column].sortable {
+            return;
+        }
+        let order = match self.sort.get() {
+            Some((c, order)) if c == column => order.reversed(),
+            _ => SortOrder::Ascending,
+        };
+        self.sort.set(Some((column, order)));
+        let model = self.model.borrow().clone();
+        if let Some(model) = model {
+            model.sort(column, order);
+        }
+        // row indices refer to the unsorted rows
+        self.selection.borrow_mut().clear();
+        self.selection_anchor.set(None);
+        self.reset_rows();
+        self.sort_changed.emit((column, order)).await;
+    }
+
+    /// Moves the dragged column to the display position under `x`.
+    fn drag_column(&self, column: usize, x: f64) -> usize {
+        let extents = self.column_extents();
+        let target = match extents.iter().position(|&(x0, x1)| x >= x0 && x < x1) {
+            Some(target) => target,
+            None if x < 0.0 => 0,
+            None => extents.len().saturating_sub(1),
+        };
+        if target != column {
+            let mut order = self.column_order.borrow_mut();
+            let moved = order.remove(column);
+            order.insert(target, moved);
+            drop(order);
+            self.rebuild_children();
+        }
+        target
+    }
+}
+
+impl ElementMethods for Table {
+    fn element(&self) -> &Element {
+        &self.element
+    }
+
+    fn measure(&self, _children: &[Rc<dyn ElementMethods>], layout_input: &LayoutInput) -> LayoutOutput {
+        let content_width: f64 = self.columns.borrow().iter().map(|c| c.width).sum();
+        let content_height = HEADER_HEIGHT + self.row_count() as f64 * self.row_height.get();
+        let width = match layout_input.width.available() {
+            Some(width) if width.is_finite() => width,
+            _ => content_width,
+        };
+        let height = match layout_input.height.available() {
+            Some(height) if height.is_finite() => height,
+            _ => content_height,
+        };
+        LayoutOutput {
+            width,
+            height,
+            baseline: None,
+        }
+    }
+
+    fn layout(&self, _children: &[Rc<dyn ElementMethods>], size: Size) -> LayoutOutput {
+        let row_height = self.row_height.get();
+        // only rows that are fully visible are shown
+        let visible_row_count = ((size.height - HEADER_HEIGHT).max(0.0) / row_height).floor() as usize;
+        self.visible_row_count.set(visible_row_count);
+        let max_first = self.row_count().saturating_sub(visible_row_count);
+        if self.first_row.get() > max_first {
+            self.first_row.set(max_first);
+        }
+        if self.visible_rows() != *self.realized_rows.borrow() {
+            // children can't be added during layout
+            self.schedule_update();
+        }
+
+        let extents = self.column_extents();
+        let order = self.column_order.borrow();
+        let sort_column = self.sort.get().map(|(column, _)| column);
+
+        let headers = self.headers.borrow();
+        for (&column, &(x0, x1)) in order.iter().zip(extents.iter()) {
+            let mut width = x1 - x0 - 2.0 * CELL_PADDING;
+            if sort_column == Some(column) {
+                width -= SORT_INDICATOR_SIZE + CELL_PADDING;
+            }
+            let header = &headers[column];
+            let output = header.do_layout(Size::new(width.max(0.0), HEADER_HEIGHT));
+            header.set_offset(Vec2::new(x0 + CELL_PADDING, (HEADER_HEIGHT - output.height) / 2.0));
+        }
+
+        let visible = self.visible_rows();
+        let realized = self.realized_rows.borrow();
+        for (i, row_cells) in self.cells.borrow().iter().enumerate() {
+            let row = realized.start + i;
+            if !visible.contains(&row) {
+                // no longer visible, but not removed yet: move it out of view until the next update
+                for cell in row_cells {
+                    cell.do_layout(Size::ZERO);
+                    cell.set_offset(Vec2::new(0.0, -1.0e6));
+                }
+                continue;
+            }
+            let y = HEADER_HEIGHT + (row - visible.start) as f64 * row_height;
+            for (&column, &(x0, x1)) in order.iter().zip(extents.iter()) {
+                let Some(cell) = row_cells.get(column) else { continue };
+                let output = cell.do_layout(Size::new((x1 - x0 - 2.0 * CELL_PADDING).max(0.0), row_height));
+                cell.set_offset(Vec2::new(x0 + CELL_PADDING, y + (row_height - output.height) / 2.0));
+            }
+        }
+
+        LayoutOutput {
+            width: size.width,
+            height: size.height,
+            baseline: None,
+        }
+    }
+
+    fn paint(&self, ctx: &mut PaintCtx) {
+        let theme = &theme::current();
+        let size = self.size();
+        let bounds = size.to_rect();
+        let row_height = self.row_height.get();
+        let extents = self.column_extents();
+        let content_right = extents.last().map(|&(_, x1)| x1).unwrap_or(0.0).min(size.width);
+        let visible = self.visible_rows();
+        let selection = self.selection.borrow();
+
+        ctx.with_canvas(|canvas| {
+            let fill = |canvas: &skia_safe::Canvas, rect: Rect, color: Color| {
+                canvas.draw_rect(rect.to_skia(), &Paint::from(color).to_sk_paint(rect));
+            };
+
+            fill(canvas, bounds, theme.content_background_color);
+
+            // rows
+            for row in visible.clone() {
+                let y = HEADER_HEIGHT + (row - visible.start) as f64 * row_height;
+                let rect = Rect::new(0.0, y, size.width, y + row_height);
+                if selection.contains(&row) {
+                    fill(canvas, rect, theme.accent_color.with_alpha(0.35));
+                } else if row % 2 == 1 {
+                    fill(canvas, rect, Color::from_rgba_u8(255, 255, 255, 8));
+                }
+            }
+
+            // header
+            let header_rect = Rect::new(0.0, 0.0, size.width, HEADER_HEIGHT);
+            fill(canvas, header_rect, theme.alternate_content_background_color);
+
+            // column separators
+            let separator_color = Color::from_rgba_u8(255, 255, 255, 24);
+            for &(_, x1) in extents.iter() {
+                fill(canvas, Rect::new(x1 - 1.0, 0.0, x1, size.height), separator_color);
+            }
+            fill(
+                canvas,
+                Rect::new(0.0, HEADER_HEIGHT - 1.0, content_right, HEADER_HEIGHT),
+                separator_color,
+            );
+
+            // sort indicator
+            if let Some((column, order)) = self.sort.get() {
+                if let Some(display) = self.column_order.borrow().iter().position(|&c| c == column) {
+                    let (_, x1) = extents[display];
+                    let cx = x1 - CELL_PADDING - 0.5 * SORT_INDICATOR_SIZE;
+                    let cy = 0.5 * HEADER_HEIGHT;
+                    let h = 0.5 * SORT_INDICATOR_SIZE;
+                    let (tip, base) = match order {
+                        SortOrder::Ascending => (cy - 0.5 * h, cy + 0.5 * h),
+                        SortOrder::Descending => (cy + 0.5 * h, cy - 0.5 * h),
+                    };
+                    let mut path = skia_safe::Path::new();
+                    path.move_to((cx as f32, tip as f32));
+                    path.line_to(((cx + h) as f32, base as f32));
+                    path.line_to(((cx - h) as f32, base as f32));
+                    path.close();
+                    let mut paint = Paint::from(theme.text_color).to_sk_paint(header_rect);
+                    paint.set_anti_alias(true);
+                    canvas.draw_path(&path, &paint);
+                }
+            }
+
+            // dragged column
+            if let Some(Gesture::Header {
+                column, dragging: true, ..
+            }) = self.gesture.get()
+            {
+                let (x0, x1) = extents[column];
+                fill(
+                    canvas,
+                    Rect::new(x0, 0.0, x1, size.height),
+                    theme.accent_color.with_alpha(0.15),
+                );
+                fill(canvas, Rect::new(x0, 0.0, x0 + 2.0, size.height), theme.accent_color);
+            }
+        });
+    }
+
+    async fn event(&self, event: &mut Event)
+    where
+        Self: Sized,
+    {
+        match event {
+            Event::PointerDown(event) => {
+                let pos = event.local_position();
+                if pos.y < HEADER_HEIGHT {
+                    if let Some(column) = self.resize_handle_at(pos.x) {
+                        let start_width = self.columns.borrow()[self.column_order.borrow()[column]].width;
+                        self.gesture.set(Some(Gesture::Resize {
+                            column,
+                            start_x: pos.x,
+                            start_width,
+                        }));
+                    } else if let Some(column) = self.column_at(pos.x) {
+                        self.gesture.set(Some(Gesture::Header {
+                            column,
+                            start_x: pos.x,
+                            dragging: false,
+                        }));
+                    }
+                    self.set_pointer_capture();
+                } else if let Some(row) = self.row_at(pos.y) {
+                    self.update_selection(row, event.modifiers);
+                    self.selection_changed.emit(self.selection()).await;
+                }
+            }
+            Event::PointerMove(event) => {
+                let pos = event.local_position();
+                match self.gesture.get() {
+                    Some(Gesture::Resize {
+                        column,
+                        start_x,
+                        start_width,
+                    }) => {
+                        let model_column = self.column_order.borrow()[column];
+                        let mut columns = self.columns.borrow_mut();
+                        let c = &mut columns[model_column];
+                        c.width = (start_width + pos.x - start_x).max(c.min_width);
+                        drop(columns);
+                        self.mark_needs_relayout();
+                    }
+                    Some(Gesture::Header {
+                        column,
+                        start_x,
+                        dragging,
+                    }) => {
+                        if dragging || (pos.x - start_x).abs() > DRAG_THRESHOLD {
+                            let column = self.drag_column(column, pos.x);
+                            self.gesture.set(Some(Gesture::Header {
+                                column,
+                                start_x,
+                                dragging: true,
+                            }));
+                            self.mark_needs_repaint();
+                        }
+                    }
+                    None => {}
+                }
+            }
+            Event::PointerUp(_) => {
+                if let Some(Gesture::Header {
+                    column, dragging: false, ..
+                }) = self.gesture.take()
+                {
+                    self.sort_by_column(column).await;
+                }
+                self.mark_needs_repaint();
+            }
+            Event::Wheel(wheel) => {
+                let rows = (wheel.delta.y.abs() / self.row_height.get()).ceil() as usize;
+                let first = self.first_row.get();
+                if wheel.delta.y > 0.0 {
+                    self.set_first_row(first.saturating_sub(rows));
+                } else {
+                    self.set_first_row(first + rows);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,474 @@
+//! Tab bars with closable and reorderable tabs.
+//!
+//! The tabs and the current tab are stored in a `TabsModel`, held in a `Property` shared between the
+//! tab bar and its owner: the tab bar updates the model on user interaction (selection, reordering,
+//! closing), and the owner can observe it, or modify it to add or remove tabs.
+use std::cell::{Cell, RefCell};
+use std::ops::Deref;
+use std::rc::Rc;
+
+use futures::future::AbortHandle;
+use kurbo::{Point, Rect, Size, Vec2};
+
+use crate::application::spawn;
+use crate::drawing::{Paint, ToSkia};
+use crate::element::{Element, ElementMethods};
+use crate::event::{Event, PointerButton};
+use crate::handler::Handler;
+use crate::layout::{LayoutInput, LayoutOutput, SizeConstraint};
+use crate::reactive::Property;
+use crate::text::TextStyle;
+use crate::theme;
+use crate::widgets::text::Text;
+use crate::{text, Color, PaintCtx};
+
+const TAB_BAR_HEIGHT: f64 = 26.0;
+const TAB_PADDING: f64 = 10.0;
+const TAB_MIN_WIDTH: f64 = 48.0;
+const CLOSE_BUTTON_SIZE: f64 = 14.0;
+/// Distance the pointer must move on a tab before the tab is dragged.
+const DRAG_THRESHOLD: f64 = 4.0;
+
+/// A tab in a `TabsModel`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tab {
+    /// Identifies the tab for the owner of the model (e.g. the panel shown in the tab).
+    pub id: u64,
+    pub title: String,
+    /// Whether the tab has a close button.
+    pub closable: bool,
+}
+
+impl Tab {
+    pub fn new(id: u64, title: impl Into<String>) -> Tab {
+        Tab {
+            id,
+            title: title.into(),
+            closable: true,
+        }
+    }
+
+    pub fn closable(mut self, closable: bool) -> Self {
+        self.closable = closable;
+        self
+    }
+}
+
+/// State of a tab bar: the tabs, in display order, and the current tab.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TabsModel {
+    pub tabs: Vec<Tab>,
+    /// Index of the current tab. Always `Some` if there are tabs.
+    pub current: Option<usize>,
+}
+
+impl TabsModel {
+    /// Appends a tab. It becomes the current tab if there was none.
+    pub fn push(&mut self, tab: Tab) -> usize {
+        self.tabs.push(tab);
+        let index = self.tabs.len() - 1;
+        if self.current.is_none() {
+            self.current = Some(index);
+        }
+        index
+    }
+
+    /// Removes the tab at the specified index.
+    ///
+    /// If it was the current tab, the next one (or the previous one, if it was the last) becomes current.
+    pub fn remove(&mut self, index: usize) -> Tab {
+        let tab = self.tabs.remove(index);
+        self.current = match self.current {
+            _ if self.tabs.is_empty() => None,
+            Some(current) if current > index => Some(current - 1),
+            Some(current) if current == index => Some(index.min(self.tabs.len() - 1)),
+            current => current,
+        };
+        tab
+    }
+
+    /// Moves the tab at index `from` to index `to`. The current tab follows.
+    pub fn move_tab(&mut self, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+        let tab = self.tabs.remove(from);
+        self.tabs.insert(to, tab);
+        self.current = self.current.map(|current| {
+            if current == from {
+                to
+            } else if from < current && current <= to {
+                current - 1
+            } else if to <= current && current < from {
+                current + 1
+            } else {
+                current
+            }
+        });
+    }
+
+    pub fn current_tab(&self) -> Option<&Tab> {
+        self.current.and_then(|current| self.tabs.get(current))
+    }
+
+    /// Returns the index of the tab with the specified ID.
+    pub fn position(&self, id: u64) -> Option<usize> {
+        self.tabs.iter().position(|tab| tab.id == id)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Gesture {
+    /// Pointer pressed on the tab at the specified index.
+    ///
+    /// Becomes a tab drag once the pointer has moved past `DRAG_THRESHOLD`.
+    Press { index: usize, start_x: f64, dragging: bool },
+    /// Pointer pressed on the close button of the tab at the specified index.
+    Close { index: usize },
+}
+
+/// A row of tabs.
+pub struct TabBar {
+    element: Element,
+    model: Rc<Property<TabsModel>>,
+    tab_closed: Handler<Tab>,
+    title_style: RefCell<TextStyle<'static>>,
+    /// Title labels, in tab order.
+    titles: RefCell<Vec<Rc<dyn ElementMethods>>>,
+    /// Horizontal extent of each tab, updated on layout.
+    extents: RefCell<Vec<(f64, f64)>>,
+    /// Tab whose close button is under the pointer.
+    hovered_close: Cell<Option<usize>>,
+    gesture: Cell<Option<Gesture>>,
+    /// Task rebuilding the titles when the model changes.
+    watch_task: RefCell<Option<AbortHandle>>,
+}
+
+impl Deref for TabBar {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl Drop for TabBar {
+    fn drop(&mut self) {
+        if let Some(task) = self.watch_task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl TabBar {
+    pub fn new(model: Rc<Property<TabsModel>>) -> Rc<TabBar> {
+        let theme = &theme::current();
+        let tab_bar = Element::new_derived(|element| TabBar {
+            element,
+            model: model.clone(),
+            tab_closed: Handler::new(),
+            title_style: RefCell::new(
+                TextStyle::new()
+                    .font_size(theme.font_size as f32)
+                    .font_family(theme.font_family)
+                    .color(theme.text_color),
+            ),
+            titles: RefCell::new(vec![]),
+            extents: RefCell::new(vec![]),
+            hovered_close: Cell::new(None),
+            gesture: Cell::new(None),
+            watch_task: RefCell::new(None),
+        });
+        tab_bar.create_titles();
+
+        let this_weak = Rc::downgrade(&tab_bar);
+        let mut stream = model.stream();
+        let task = spawn(async move {
+            while stream.changed().await.is_ok() {
+                let Some(this) = this_weak.upgrade() else { break };
+                this.create_titles();
+            }
+        });
+        tab_bar.watch_task.replace(Some(task));
+        tab_bar
+    }
+
+    /// Returns the model of the tab bar.
+    pub fn model(&self) -> &Rc<Property<TabsModel>> {
+        &self.model
+    }
+
+    /// Emitted when the user closes a tab, after it has been removed from the model.
+    pub async fn tab_closed(&self) -> Tab {
+        self.tab_closed.wait().await
+    }
+
+    pub fn set_title_style(&self, style: TextStyle<'static>) {
+        self.title_style.replace(style);
+        self.create_titles();
+    }
+
+    /// Recreates the title labels from the model.
+    fn create_titles(&self) {
+        let title_style = self.title_style.borrow().clone();
+        let titles: Vec<Rc<dyn ElementMethods>> = self
+            .model
+            .borrow()
+            .tabs
+            .iter()
+            .map(|tab| -> Rc<dyn ElementMethods> {
+                let title = &tab.title;
+                Text::new(text!( style(title_style.clone()) "{title}" ))
+            })
+            .collect();
+        self.clear_children();
+        for title in titles.iter() {
+            self.add_child(title);
+        }
+        self.titles.replace(titles);
+        self.hovered_close.set(None);
+        self.mark_needs_relayout();
+    }
+
+    fn tab_at(&self, x: f64) -> Option<usize> {
+        self.extents.borrow().iter().position(|&(x0, x1)| x >= x0 && x < x1)
+    }
+
+    /// Returns the rectangle of the close button of the tab at the specified index.
+    fn close_button_rect(&self, index: usize) -> Option<Rect> {
+        if !self.model.borrow().tabs.get(index)?.closable {
+            return None;
+        }
+        let (_, x1) = *self.extents.borrow().get(index)?;
+        let x = x1 - TAB_PADDING - CLOSE_BUTTON_SIZE;
+        let y = 0.5 * (TAB_BAR_HEIGHT - CLOSE_BUTTON_SIZE);
+        Some(Rect::new(x, y, x + CLOSE_BUTTON_SIZE, y + CLOSE_BUTTON_SIZE))
+    }
+
+    fn close_button_at(&self, pos: Point) -> Option<usize> {
+        let index = self.tab_at(pos.x)?;
+        self.close_button_rect(index)
+            .filter(|rect| rect.inflate(2.0, 2.0).contains(pos))
+            .map(|_| index)
+    }
+
+    async fn close_tab(&self, index: usize) {
+        let mut closed = None;
+        self.model.modify(|model| {
+            if index < model.tabs.len() {
+                closed = Some(model.remove(index));
+            }
+            closed.is_some()
+        });
+        if let Some(tab) = closed {
+            self.tab_closed.emit(tab).await;
+        }
+    }
+
+    fn select_tab(&self, index: usize) {
+        self.model.modify(|model| {
+            let changed = model.current != Some(index);
+            model.current = Some(index);
+            changed
+        });
+        self.mark_needs_repaint();
+    }
+
+    /// Moves the dragged tab to the position under `x`.
+    fn drag_tab(&self, index: usize, x: f64) -> usize {
+        let extents = self.extents.borrow().clone();
+        let target = match extents.iter().position(|&(x0, x1)| x >= x0 && x < x1) {
+            Some(target) => target,
+            None if x < 0.0 => 0,
+            None => extents.len().saturating_sub(1),
+        };
+        if target != index {
+            self.model.modify(|model| {
+                model.move_tab(index, target);
+                true
+            });
+        }
+        target
+    }
+}
+
+impl ElementMethods for TabBar {
+    fn element(&self) -> &Element {
+        &self.element
+    }
+
+    fn measure(&self, _children: &[Rc<dyn ElementMethods>], layout_input: &LayoutInput) -> LayoutOutput {
+        // the tab bar takes all the available width
+        let width = match layout_input.width.available() {
+            Some(width) if width.is_finite() => width,
+            _ => self.extents.borrow().last().map(|&(_, x1)| x1).unwrap_or(0.0),
+        };
+        LayoutOutput {
+            width,
+            height: TAB_BAR_HEIGHT,
+            baseline: None,
+        }
+    }
+
+    fn layout(&self, _children: &[Rc<dyn ElementMethods>], size: Size) -> LayoutOutput {
+        let model = self.model.borrow();
+        let titles = self.titles.borrow();
+        let mut extents = Vec::with_capacity(titles.len());
+        let mut x = 0.0;
+        for (tab, title) in model.tabs.iter().zip(titles.iter()) {
+            let title_width = title
+                .do_measure(&LayoutInput {
+                    width: SizeConstraint::MAX,
+                    height: SizeConstraint::MAX,
+                })
+                .width;
+            let mut width = title_width + 2.0 * TAB_PADDING;
+            if tab.closable {
+                width += CLOSE_BUTTON_SIZE + 0.5 * TAB_PADDING;
+            }
+            let width = width.max(TAB_MIN_WIDTH);
+            let output = title.do_layout(Size::new(title_width, TAB_BAR_HEIGHT));
+            title.set_offset(Vec2::new(x + TAB_PADDING, (TAB_BAR_HEIGHT - output.height) / 2.0));
+            extents.push((x, x + width));
+            x += width;
+        }
+        self.extents.replace(extents);
+
+        LayoutOutput {
+            width: size.width,
+            height: TAB_BAR_HEIGHT,
+            baseline: None,
+        }
+    }
+
+    fn paint(&self, ctx: &mut PaintCtx) {
+        let theme = &theme::current();
+        let size = self.size();
+        let bounds = size.to_rect();
+        let extents = self.extents.borrow();
+        let current = self.model.borrow().current;
+        let dragged = match self.gesture.get() {
+            Some(Gesture::Press {
+                index, dragging: true, ..
+            }) => Some(index),
+            _ => None,
+        };
+        let hovered_close = self.hovered_close.get();
+
+        ctx.with_canvas(|canvas| {
+            let fill = |canvas: &skia_safe::Canvas, rect: Rect, color: Color| {
+                canvas.draw_rect(rect.to_skia(), &Paint::from(color).to_sk_paint(rect));
+            };
+
+            fill(canvas, bounds, theme.alternate_content_background_color);
+
+            let separator_color = Color::from_rgba_u8(255, 255, 255, 24);
+            for (index, &(x0, x1)) in extents.iter().enumerate() {
+                let rect = Rect::new(x0, 0.0, x1, TAB_BAR_HEIGHT);
+                if current == Some(index) {
+                    fill(canvas, rect, theme.content_background_color);
+                    fill(canvas, Rect::new(x0, 0.0, x1, 2.0), theme.accent_color);
+                } else if dragged == Some(index) {
+                    fill(canvas, rect, theme.accent_color.with_alpha(0.15));
+                }
+                fill(
+                    canvas,
+                    Rect::new(x1 - 1.0, 4.0, x1, TAB_BAR_HEIGHT - 4.0),
+                    separator_color,
+                );
+
+                // close button: a cross, with a background when hovered
+                if let Some(button) = self.close_button_rect(index) {
+                    if hovered_close == Some(index) {
+                        let rrect = button.to_rounded_rect(3.0);
+                        let mut paint = Paint::from(Color::from_rgba_u8(255, 255, 255, 32)).to_sk_paint(button);
+                        paint.set_anti_alias(true);
+                        canvas.draw_rrect(rrect.to_skia(), &paint);
+                    }
+                    let r = button.inset(-4.0);
+                    let mut path = skia_safe::Path::new();
+                    path.move_to((r.x0 as f32, r.y0 as f32));
+                    path.line_to((r.x1 as f32, r.y1 as f32));
+                    path.move_to((r.x1 as f32, r.y0 as f32));
+                    path.line_to((r.x0 as f32, r.y1 as f32));
+                    let mut paint = Paint::from(theme.text_color).to_sk_paint(button);
+                    paint.set_style(skia_safe::paint::Style::Stroke);
+                    paint.set_stroke_width(1.5);
+                    paint.set_anti_alias(true);
+                    canvas.draw_path(&path, &paint);
+                }
+            }
+        });
+    }
+
+    async fn event(&self, event: &mut Event)
+    where
+        Self: Sized,
+    {
+        match event {
+            Event::PointerDown(event) => {
+                let pos = event.local_position();
+                if let Some(index) = self.close_button_at(pos) {
+                    self.gesture.set(Some(Gesture::Close { index }));
+                } else if let Some(index) = self.tab_at(pos.x) {
+                    if event.button == Some(PointerButton::MIDDLE) {
+                        // middle-click closes the tab
+                        if self.model.borrow().tabs[index].closable {
+                            self.gesture.set(Some(Gesture::Close { index }));
+                        }
+                    } else {
+                        self.select_tab(index);
+                        self.gesture.set(Some(Gesture::Press {
+                            index,
+                            start_x: pos.x,
+                            dragging: false,
+                        }));
+                    }
+                }
+                self.set_pointer_capture();
+            }
+            Event::PointerMove(event) => {
+                let pos = event.local_position();
+                match self.gesture.get() {
+                    Some(Gesture::Press {
+                        index,
+                        start_x,
+                        dragging,
+                    }) => {
+                        if dragging || (pos.x - start_x).abs() > DRAG_THRESHOLD {
+                            let index = self.drag_tab(index, pos.x);
+                            self.gesture.set(Some(Gesture::Press {
+                                index,
+                                start_x,
+                                dragging: true,
+                            }));
+                            self.mark_needs_repaint();
+                        }
+                    }
+                    Some(Gesture::Close { .. }) => {}
+                    None => {
+                        let hovered = self.close_button_at(pos);
+                        if hovered != self.hovered_close.replace(hovered) {
+                            self.mark_needs_repaint();
+                        }
+                    }
+                }
+            }
+            Event::PointerUp(event) => {
+                if let Some(Gesture::Close { index }) = self.gesture.take() {
+                    // only close if released over the tab that was pressed
+                    if self.tab_at(event.local_position().x) == Some(index) {
+                        self.close_tab(index).await;
+                    }
+                }
+                self.mark_needs_repaint();
+            }
+            Event::PointerLeave(_) => {
+                if self.hovered_close.take().is_some() {
+                    self.mark_needs_repaint();
+                }
+            }
+            _ => {}
+        }
+    }
+}
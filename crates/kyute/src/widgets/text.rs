@@ -4,20 +4,32 @@ use std::rc::Rc;
 
 use kurbo::{Point, Size};
 use skia_safe::textlayout;
+use skia_safe::textlayout::{RectHeightStyle, RectWidthStyle};
 use tracing::{trace, trace_span};
 
-use crate::drawing::ToSkia;
+use crate::drawing::{Paint, ToSkia};
 use crate::element::{Element, ElementMethods};
-use crate::event::Event;
+use crate::event::{Event, PointerButton, TaskId};
 use crate::layout::{LayoutInput, LayoutOutput, SizeConstraint};
-use crate::PaintCtx;
-use crate::text::{TextLayout, TextRun};
+use crate::text::{Selection, TextLayout, TextRun};
+use crate::widgets::context_menu::{show_edit_menu, EditCommand};
+use crate::{clipboard, Color, PaintCtx};
 
 pub struct Text {
     element: Element,
     relayout: Cell<bool>,
     intrinsic_size: Cell<Option<Size>>,
     paragraph: RefCell<textlayout::Paragraph>,
+    /// The text without attributes, for copying.
+    plain_text: String,
+    /// Whether the user can select and copy the text.
+    selectable: Cell<bool>,
+    selection: Cell<Selection>,
+    selection_color: Cell<Color>,
+    /// Whether a selection gesture is in progress.
+    selecting: Cell<bool>,
+    /// Context menu being shown.
+    context_menu_task: Cell<Option<TaskId>>,
 }
 
 impl Deref for Text {
@@ -31,14 +43,92 @@ impl Deref for Text {
 impl Text {
     pub fn new(text: &[TextRun]) -> Rc<Text> {
         let paragraph = TextLayout::new(text).inner;
+        let plain_text = text.iter().map(|run| run.str).collect();
         Element::new_derived(|element| Text {
             element,
             relayout: Cell::new(true),
             intrinsic_size: Cell::new(None),
             paragraph: RefCell::new(paragraph),
+            plain_text,
+            selectable: Cell::new(false),
+            selection: Cell::new(Selection::empty(0)),
+            selection_color: Cell::new(Color::from_rgba_u8(0, 0, 255, 80)),
+            selecting: Cell::new(false),
+            context_menu_task: Cell::new(None),
         })
     }
 
+    /// Makes the text selectable with the pointer, and copyable with the context menu or `Ctrl+C`.
+    ///
+    /// Text is not selectable by default.
+    pub fn set_selectable(&self, selectable: bool) {
+        self.selectable.set(selectable);
+        self.set_tab_focusable(selectable);
+        if !selectable {
+            self.set_selection(Selection::empty(0));
+        }
+    }
+
+    pub fn set_selection_color(&self, color: Color) {
+        self.selection_color.set(color);
+        self.mark_needs_repaint();
+    }
+
+    /// Returns the current selection.
+    pub fn selection(&self) -> Selection {
+        self.selection.get()
+    }
+
+    pub fn set_selection(&self, selection: Selection) {
+        if self.selection.replace(selection) != selection {
+            self.mark_needs_repaint();
+        }
+    }
+
+    /// Returns the selected text.
+    pub fn selected_text(&self) -> &str {
+        &self.plain_text[self.selection.get().byte_range()]
+    }
+
+    /// Runs an editing command. Only copy and select all apply to text that isn't editable.
+    pub fn execute_command(&self, command: EditCommand) {
+        match command {
+            EditCommand::Copy => {
+                if !self.selection.get().is_empty() {
+                    clipboard::set_text(self.selected_text());
+                }
+            }
+            EditCommand::SelectAll => self.set_selection(Selection {
+                start: 0,
+                end: self.plain_text.len(),
+            }),
+            EditCommand::Cut | EditCommand::Paste => {}
+        }
+    }
+
+    pub(crate) fn text_position_for_point(&self, point: Point) -> usize {
+        self.paragraph
+            .borrow()
+            .get_glyph_position_at_coordinate(point.to_skia())
+            .position as usize
+    }
+
+    fn show_context_menu(&self, position: Point) {
+        let commands = [
+            (EditCommand::Copy, !self.selection.get().is_empty()),
+            (EditCommand::SelectAll, !self.plain_text.is_empty()),
+        ];
+        let window = self.element.window.borrow().clone();
+        if let Some(task) = self.context_menu_task.take() {
+            self.abort_task(task);
+        }
+        let task = self.spawn_local(async move {
+            let window = window.upgrade()?;
+            show_edit_menu(&window, position, &commands).await
+        });
+        self.context_menu_task.set(Some(task));
+    }
+
     fn calculate_intrinsic_size(&self) -> Size {
         // FIXME intrinsic height
         Size::new(self.paragraph.borrow().max_intrinsic_width() as f64, 16.0)
@@ -78,18 +168,71 @@ impl ElementMethods for Text {
         })
     }
 
-    fn hit_test(&self, _point: Point) -> bool {
-        false
+    fn hit_test(&self, point: Point) -> bool {
+        // non-selectable text is transparent to the pointer
+        self.selectable.get() && self.size().to_rect().contains(point)
     }
 
     fn paint(&self, ctx: &mut PaintCtx) {
         ctx.with_canvas(|canvas| {
-            self.paragraph.borrow().paint(canvas, Point::ZERO.to_skia());
+            let paragraph = self.paragraph.borrow();
+            let selection = self.selection.get();
+            if !selection.is_empty() {
+                let rects =
+                    paragraph.get_rects_for_range(selection.byte_range(), RectHeightStyle::Tight, RectWidthStyle::Tight);
+                let paint = Paint::from(self.selection_color.get()).to_sk_paint(self.size().to_rect());
+                for text_box in rects {
+                    canvas.draw_rect(text_box.rect, &paint);
+                }
+            }
+            paragraph.paint(canvas, Point::ZERO.to_skia());
         })
     }
 
-    async fn event(&self, _event: &mut Event)
+    async fn event(&self, event: &mut Event)
     where
         Self: Sized,
-    {}
+    {
+        if !self.selectable.get() {
+            return;
+        }
+        match event {
+            Event::PointerDown(event) if event.button == Some(PointerButton::RIGHT) => {
+                self.set_focus().await;
+                self.show_context_menu(event.position);
+            }
+            Event::PointerDown(event) => {
+                let pos = self.text_position_for_point(event.local_position());
+                self.set_selection(Selection::empty(pos));
+                self.selecting.set(true);
+                self.set_pointer_capture();
+                self.set_focus().await;
+            }
+            Event::PointerMove(event) if self.selecting.get() => {
+                let pos = self.text_position_for_point(event.local_position());
+                self.set_selection(Selection {
+                    start: self.selection.get().start,
+                    end: pos,
+                });
+            }
+            Event::PointerUp(_) => {
+                self.selecting.set(false);
+            }
+            Event::FocusLost => {
+                self.set_selection(Selection::empty(0));
+            }
+            Event::KeyDown(event) => {
+                if let Some(command) = EditCommand::from_key_event(event) {
+                    self.execute_command(command);
+                }
+            }
+            Event::TaskCompleted(result) if Some(result.task) == self.context_menu_task.get() => {
+                self.context_menu_task.set(None);
+                if let Some(Some(command)) = result.downcast_ref::<Option<EditCommand>>() {
+                    self.execute_command(*command);
+                }
+            }
+            _ => {}
+        }
+    }
 }
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cell::{Cell, RefCell};
 use std::ops::Deref;
 use std::rc::Rc;
@@ -11,12 +12,14 @@ use unicode_segmentation::GraphemeCursor;
 
 use crate::{Color, PaintCtx};
 use crate::application::{spawn, wait_for};
+use crate::clipboard;
 use crate::drawing::{FromSkia, Paint, ToSkia};
 use crate::element::{Element, ElementMethods};
-use crate::event::Event;
+use crate::event::{CompositionEvent, Event, PointerButton, TaskId};
 use crate::handler::Handler;
 use crate::layout::{LayoutInput, LayoutOutput, SizeConstraint};
 use crate::text::{get_font_collection, Selection, TextAlign, TextLayout, TextStyle};
+use crate::widgets::context_menu::{show_edit_menu, EditCommand};
 
 #[derive(Debug, Copy, Clone)]
 pub enum Movement {
@@ -74,9 +77,33 @@ struct TextEditState {
     align: TextAlign,
     line_clamp: Option<usize>,
     size: Size,
+    /// IME composition in progress, displayed at the start of the selection.
+    composition: Option<CompositionEvent>,
 }
 
 impl TextEditState {
+    /// Returns the displayed text, which includes the pre-edit text of the composition in progress.
+    fn display_text(&self) -> Cow<str> {
+        match self.composition {
+            Some(ref composition) => {
+                let mut text = self.text.clone();
+                text.replace_range(self.selection.byte_range(), &composition.text);
+                Cow::Owned(text)
+            }
+            None => Cow::Borrowed(&self.text),
+        }
+    }
+
+    /// Replaces the selection with the specified text, and places the cursor after it.
+    fn insert_text(&mut self, s: &str) {
+        // TODO don't do this, emit the changed text instead
+        let selection = self.selection;
+        self.text.replace_range(selection.byte_range(), s);
+        self.rebuild_paragraph();
+        self.relayout = true;
+        self.selection = Selection::empty(selection.min() + s.len());
+    }
+
     fn rebuild_paragraph(&mut self) {
         let font_collection = get_font_collection();
         let mut text_style = skia_safe::textlayout::TextStyle::new();
@@ -101,7 +128,7 @@ impl TextEditState {
         let mut builder = skia_safe::textlayout::ParagraphBuilder::new(&paragraph_style, font_collection);
         let style = self.text_style.to_skia();
         builder.push_style(&style);
-        builder.add_text(&self.text);
+        builder.add_text(&*self.display_text());
         builder.pop();
 
         self.paragraph = builder.build();
@@ -220,6 +247,42 @@ impl TextEditState {
         word
     }
 
+    fn selected_text(&self) -> &str {
+        &self.text[self.selection.byte_range()]
+    }
+
+    /// Runs an editing command. Returns whether the text changed.
+    fn execute_command(&mut self, command: EditCommand) -> bool {
+        match command {
+            EditCommand::Cut => {
+                if self.selection.is_empty() {
+                    return false;
+                }
+                clipboard::set_text(self.selected_text());
+                self.insert_text("");
+                true
+            }
+            EditCommand::Copy => {
+                if !self.selection.is_empty() {
+                    clipboard::set_text(self.selected_text());
+                }
+                false
+            }
+            EditCommand::Paste => {
+                let Some(text) = clipboard::get_text() else { return false };
+                self.insert_text(&text);
+                true
+            }
+            EditCommand::SelectAll => {
+                self.set_selection(Selection {
+                    start: 0,
+                    end: self.text.len(),
+                });
+                false
+            }
+        }
+    }
+
     fn select_line_under_cursor(&mut self) -> bool {
         let text = &self.text;
         let selection = self.selection;
@@ -314,6 +377,8 @@ pub struct TextEdit {
     gesture: Cell<Option<Gesture>>,
     blink_phase: Cell<bool>,
     blink_reset: Cell<bool>,
+    /// Context menu being shown.
+    context_menu_task: Cell<Option<TaskId>>,
 }
 
 impl TextEdit {
@@ -336,10 +401,12 @@ impl TextEdit {
                 size: Default::default(),
                 text_overflow: TextOverflow::Clip,
                 line_clamp: None,
+                composition: None,
             }),
             blink_phase: Cell::new(true),
             blink_reset: Cell::new(false),
             gesture: Cell::new(None),
+            context_menu_task: Cell::new(None),
         });
 
         text_edit.set_tab_focusable(true);
@@ -556,6 +623,36 @@ impl TextEdit {
         }
     }
 
+    /// Runs an editing command (cut, copy, paste, select all).
+    pub fn execute_command(&self, command: EditCommand) {
+        if self.state.borrow_mut().execute_command(command) {
+            self.mark_needs_relayout();
+        }
+        self.mark_needs_repaint();
+    }
+
+    /// Shows the context menu with the editing commands at the specified position (in window coordinates).
+    ///
+    /// Cut and copy are disabled when the selection is empty, and paste when the clipboard doesn't contain text.
+    fn show_context_menu(&self, position: Point) {
+        let has_selection = !self.selection().is_empty();
+        let commands = [
+            (EditCommand::Cut, has_selection),
+            (EditCommand::Copy, has_selection),
+            (EditCommand::Paste, clipboard::has_text()),
+            (EditCommand::SelectAll, !self.state.borrow().text.is_empty()),
+        ];
+        let window = self.element.window.borrow().clone();
+        if let Some(task) = self.context_menu_task.take() {
+            self.abort_task(task);
+        }
+        let task = self.spawn_local(async move {
+            let window = window.upgrade()?;
+            show_edit_menu(&window, position, &commands).await
+        });
+        self.context_menu_task.set(Some(task));
+    }
+
     /// Emitted when the selection changes as a result of user interaction.
     pub async fn selection_changed(&self) -> Selection {
         self.selection_changed.wait().await
@@ -669,26 +766,57 @@ impl ElementMethods for TextEdit {
             // paint the paragraph
             this.paragraph.paint(canvas, Point::ZERO.to_skia());
 
-            // paint the selection rectangles
-            let selection_rects = this.paragraph.get_rects_for_range(
-                this.selection.min()..this.selection.max(),
-                RectHeightStyle::Tight,
-                RectWidthStyle::Tight,
-            );
-            let selection_paint = Paint::from(this.selection_color).to_sk_paint(bounds.to_rect());
-            for text_box in selection_rects {
-                canvas.draw_rect(text_box.rect, &selection_paint);
+            // caret position in the displayed text
+            let mut caret = this.selection.end;
+
+            if let Some(ref composition) = this.composition {
+                // underline the pre-edit text instead of painting the selection
+                let offset = this.selection.min();
+                let underline_paint = Paint::from(this.text_style.color).to_sk_paint(bounds.to_rect());
+                for underline in composition.underlines.iter() {
+                    let thickness = if underline.thick { 2.0 } else { 1.0 };
+                    let rects = this.paragraph.get_rects_for_range(
+                        offset + underline.range.start..offset + underline.range.end,
+                        RectHeightStyle::Tight,
+                        RectWidthStyle::Tight,
+                    );
+                    for text_box in rects {
+                        let r = text_box.rect;
+                        // leave a gap between adjacent clauses
+                        let underline_rect =
+                            skia_safe::Rect::new(r.left + 1.0, r.bottom - thickness, r.right - 1.0, r.bottom);
+                        canvas.draw_rect(underline_rect, &underline_paint);
+                    }
+                }
+                caret = offset + composition.cursor.as_ref().map_or(composition.text.len(), |c| c.end);
+            } else {
+                // paint the selection rectangles
+                let selection_rects = this.paragraph.get_rects_for_range(
+                    this.selection.min()..this.selection.max(),
+                    RectHeightStyle::Tight,
+                    RectWidthStyle::Tight,
+                );
+                let selection_paint = Paint::from(this.selection_color).to_sk_paint(bounds.to_rect());
+                for text_box in selection_rects {
+                    canvas.draw_rect(text_box.rect, &selection_paint);
+                }
             }
 
-            if self.has_focus() && self.blink_phase.get() {
-                if let Some(info) = this.paragraph.get_glyph_cluster_at(this.selection.end) {
-                    let caret_rect = Rect::from_origin_size(
+            if self.has_focus() {
+                let caret_rect = this.paragraph.get_glyph_cluster_at(caret).map(|info| {
+                    Rect::from_origin_size(
                         Point::new((info.bounds.left as f64).round(), (info.bounds.top as f64).round()),
                         Size::new(1.0, info.bounds.height() as f64),
-                    );
-                    //eprintln!("caret_rect: {:?}", caret_rect);
-                    let caret_paint = Paint::from(this.caret_color).to_sk_paint(bounds.to_rect());
-                    canvas.draw_rect(caret_rect.to_skia(), &caret_paint);
+                    )
+                });
+                if let Some(caret_rect) = caret_rect {
+                    // tell the IME where to put the candidate window
+                    self.set_ime_cursor_area(caret_rect - this.scroll_offset);
+                    if self.blink_phase.get() {
+                        //eprintln!("caret_rect: {:?}", caret_rect);
+                        let caret_paint = Paint::from(this.caret_color).to_sk_paint(bounds.to_rect());
+                        canvas.draw_rect(caret_rect.to_skia(), &caret_paint);
+                    }
                 }
             }
 
@@ -703,8 +831,17 @@ impl ElementMethods for TextEdit {
         let mut selection_changed = false;
         let mut this = self.state.borrow_mut();
         let mut set_focus = false;
+        let mut context_menu_position = None;
 
         match event {
+            Event::PointerDown(event) if event.button == Some(PointerButton::RIGHT) => {
+                // keep the selection if there's one, the menu applies to it
+                if this.selection.is_empty() {
+                    selection_changed |= this.set_cursor_at_point(event.local_position(), false);
+                }
+                set_focus = true;
+                context_menu_position = Some(event.position);
+            }
             Event::PointerDown(event) => {
                 let pos = event.local_position();
                 eprintln!("[text_edit] pointer down: {:?}", pos);
@@ -749,12 +886,56 @@ impl ElementMethods for TextEdit {
             }
             Event::FocusGained => {
                 eprintln!("focus gained");
+                self.set_ime_allowed(true);
                 self.reset_blink();
             }
             Event::FocusLost => {
                 eprintln!("focus lost");
+                self.set_ime_allowed(false);
+                if this.composition.take().is_some() {
+                    this.rebuild_paragraph();
+                    this.relayout = true;
+                    self.mark_needs_relayout();
+                }
                 selection_changed |= this.set_selection(Selection::empty(0));
             }
+            Event::CompositionStart => {
+                this.composition = Some(CompositionEvent::default());
+            }
+            Event::CompositionUpdate(composition) => {
+                this.composition = Some(composition.clone());
+                this.rebuild_paragraph();
+                this.relayout = true;
+                self.mark_needs_relayout();
+                self.reset_blink();
+            }
+            Event::CompositionCommit(text) => {
+                this.composition = None;
+                this.insert_text(text);
+                selection_changed = true;
+                self.mark_needs_relayout();
+                self.reset_blink();
+            }
+            Event::TaskCompleted(result) if Some(result.task) == self.context_menu_task.get() => {
+                self.context_menu_task.set(None);
+                if let Some(Some(command)) = result.downcast_ref::<Option<EditCommand>>() {
+                    if this.execute_command(*command) {
+                        self.mark_needs_relayout();
+                    }
+                    selection_changed = true;
+                    self.reset_blink();
+                }
+            }
+            // Key events are also sent during IME composition, ignore them
+            Event::KeyDown(event) if event.is_composing => {}
+            Event::KeyDown(event) if EditCommand::from_key_event(event).is_some() => {
+                let command = EditCommand::from_key_event(event).unwrap();
+                if this.execute_command(command) {
+                    self.mark_needs_relayout();
+                }
+                selection_changed = true;
+                self.reset_blink();
+            }
             Event::KeyDown(event) => {
                 let keep_anchor = event.modifiers.shift();
                 let word_nav = event.modifiers.ctrl();
@@ -779,14 +960,7 @@ impl ElementMethods for TextEdit {
                         self.reset_blink();
                     }
                     Key::Character(ref s) => {
-                        // TODO don't do this, emit the changed text instead
-                        let mut text = this.text.clone();
-                        let selection = this.selection;
-                        text.replace_range(selection.byte_range(), &s);
-                        this.text = text;
-                        this.rebuild_paragraph();
-                        this.relayout = true;
-                        this.selection = Selection::empty(selection.min() + s.len());
+                        this.insert_text(s);
                         selection_changed = true;
                         self.mark_needs_relayout();
                         self.reset_blink();
@@ -803,6 +977,10 @@ impl ElementMethods for TextEdit {
             self.set_focus().await;
         }
 
+        if let Some(position) = context_menu_position {
+            self.show_context_menu(position);
+        }
+
         if selection_changed {
             self.mark_needs_repaint();
             self.selection_changed.emit(self.selection()).await;
@@ -4,30 +4,39 @@
 //! It is responsible for translating window events from winit into `Events` that are dispatched to the `Visual` tree.
 use std::cell::{Cell, RefCell};
 use std::collections::BTreeSet;
+use std::f64::consts::{PI, TAU};
 use std::rc::{Rc, Weak};
 use std::sync::OnceLock;
 use std::thread::sleep;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use keyboard_types::{Key, KeyboardEvent};
-use kurbo::{Affine, Point, Size};
+use kurbo::{Affine, Point, Rect, Size, Vec2};
 use raw_window_handle::{HasWindowHandle, RawWindowHandle};
 use skia_safe::{Font, FontMgr, FontStyle, Typeface};
 use skia_safe::font::Edging;
+use tracing::warn;
 use winit::dpi::PhysicalSize;
-use winit::event::{DeviceId, ElementState, KeyEvent, MouseButton, WindowEvent};
+use winit::event::{
+    DeviceId, ElementState, Ime, KeyEvent, MouseButton, MouseScrollDelta, Touch, TouchPhase, WindowEvent,
+};
 use winit::keyboard::KeyLocation;
 use winit::platform::windows::WindowBuilderExtWindows;
 
-use crate::{application, Color};
+use crate::{application, backend, theme, Color};
 use crate::app_globals::AppGlobals;
 use crate::application::{WindowHandler, with_event_loop_window_target};
-use crate::compositor::{ColorType, Layer};
+use crate::compositor::{ColorType, DisplayInfo, HdrMetadata, Layer, SurfaceColorSpace};
 use crate::drawing::ToSkia;
 use crate::element::{AnyVisual, Element, ElementMethods, WeakNullableElemPtr};
-use crate::event::{Event, key_event_to_key_code, PointerButton, PointerButtons, PointerEvent};
+use crate::event::{
+    CompositionEvent, CompositionUnderline, Event, GestureEvent, key_event_to_key_code, PointerButton, PointerButtons,
+    PointerEvent, PointerType, WheelEvent,
+};
 use crate::handler::Handler;
+use crate::perf::{FramePhase, FrameStats, FrameTimings};
 use crate::layout::{LayoutInput, RequestedAxis, SizeConstraint};
+use crate::testing::{read_surface, EventRecorder, EventRecording, InputEvent, RenderedImage};
 
 fn draw_crosshair(canvas: &skia_safe::Canvas, pos: Point) {
     let mut paint = skia_safe::Paint::default();
@@ -58,6 +67,15 @@ fn draw_text_blob(canvas: &skia_safe::Canvas, str: &str, size: Size) {
     canvas.draw_text_blob(text_blob, (0.0, size.height as f32 - 16.0), &paint);
 }
 
+/// Scroll distance in pixels of one line (notch) of a mouse wheel.
+pub(crate) const WHEEL_LINE_HEIGHT: f64 = 20.0;
+
+/// Range of the UI scale factor (see `Window::set_ui_scale`).
+pub const MIN_UI_SCALE: f64 = 0.5;
+pub const MAX_UI_SCALE: f64 = 3.0;
+/// UI scale factor applied for each line (notch) of mouse wheel scroll with ctrl held.
+const UI_SCALE_WHEEL_STEP: f64 = 1.1;
+
 static DEFAULT_TYPEFACE: OnceLock<Typeface> = OnceLock::new();
 
 pub fn default_typeface() -> Typeface {
@@ -74,13 +92,35 @@ pub fn default_typeface() -> Typeface {
 /// Stores information about the last click (for double-click handling)
 #[derive(Clone, Debug)]
 struct LastClick {
-    device_id: DeviceId,
+    /// Device of the click, `None` for replayed clicks.
+    device_id: Option<DeviceId>,
     button: PointerButton,
     position: Point,
     time: Instant,
     repeat_count: u32,
 }
 
+/// A pen or touch contact on the window.
+#[derive(Copy, Clone, Debug)]
+struct Contact {
+    pointer_id: u64,
+    pointer_type: PointerType,
+    /// Position in logical coordinates.
+    position: Point,
+    /// Button reported for the contact (`PointerButton::ERASER` for erasers, `LEFT` otherwise).
+    button: PointerButton,
+}
+
+/// Returns the center, average distance to the center, and angle of the first two of the specified touch
+/// contacts. Used to compute gestures.
+fn contacts_geometry(contacts: &[Contact]) -> (Point, f64, f64) {
+    let n = contacts.len() as f64;
+    let center = (contacts.iter().map(|c| c.position.to_vec2()).fold(Vec2::ZERO, |a, b| a + b) / n).to_point();
+    let spread = contacts.iter().map(|c| (c.position - center).hypot()).sum::<f64>() / n;
+    let angle = (contacts[1].position - contacts[0].position).atan2();
+    (center, spread, angle)
+}
+
 #[derive(Default)]
 struct InputState {
     /// Modifier state. Tracked here because winit doesn't want to give it to us in events.
@@ -88,6 +128,10 @@ struct InputState {
     /// Pointer button state.
     pointer_buttons: PointerButtons,
     last_click: Option<LastClick>,
+    /// Whether an IME composition is in progress.
+    composing: bool,
+    /// Pens and touch contacts currently touching the window.
+    contacts: Vec<Contact>,
     // Result of the previous hit-test
     last_innermost_hit: Option<AnyVisual>,
     last_hits: BTreeSet<AnyVisual>,
@@ -112,6 +156,24 @@ pub(crate) struct WindowInner {
     focus: WeakNullableElemPtr,
     background: Cell<Color>,
     active_popup: RefCell<Option<Weak<WindowInner>>>,
+    /// Modal dialog shown over this window. Input to this window is blocked while it is open.
+    modal_dialog: RefCell<Option<Weak<WindowInner>>>,
+    /// Time spent dispatching events since the last frame.
+    event_dispatch_time: Cell<Duration>,
+    frame_stats: RefCell<FrameStats>,
+    perf_hud_visible: Cell<bool>,
+    /// Whether redraws are paced by the compositor clock (see `WindowOptions::vsync`).
+    vsync: Cell<bool>,
+    /// UI scale factor, applied on top of the system DPI scale factor.
+    ui_scale: Cell<f64>,
+    /// Whether ctrl+wheel changes the UI scale factor.
+    ctrl_wheel_zoom: bool,
+    ui_scale_changed: Handler<f64>,
+    /// Records input events, for UI tests (see `Window::start_recording`).
+    recorder: RefCell<Option<EventRecorder>>,
+    /// Set by `Window::capture` to read back the next frame.
+    capture_requested: Cell<bool>,
+    captured_frame: RefCell<Option<RenderedImage>>,
     // DEBUGGING
     last_kb_event: RefCell<Option<KeyboardEvent>>,
 }
@@ -265,15 +327,7 @@ impl WindowInner {
             self.pointer_capture.replace(None);
         }
 
-        let p = PointerEvent {
-            position: hit_position,
-            modifiers: input_state.modifiers,
-            buttons: input_state.pointer_buttons,
-            button: None,
-            repeat_count: 0,
-            transform: Default::default(),
-            request_capture: false,
-        };
+        let p = PointerEvent::mouse(hit_position, input_state.modifiers, input_state.pointer_buttons, None, 0);
 
         // convert hits to set
         let hits_set = BTreeSet::from_iter(hits);
@@ -311,7 +365,12 @@ impl WindowInner {
     }
 
     /// Converts a winit mouse event to an Event, and update internal state.
-    fn convert_mouse_input(&self, device_id: DeviceId, button: MouseButton, state: ElementState) -> Option<Event> {
+    fn convert_mouse_input(
+        &self,
+        device_id: Option<DeviceId>,
+        button: MouseButton,
+        state: ElementState,
+    ) -> Option<Event> {
         let mut input_state = self.input_state.borrow_mut();
         let button = match button {
             MouseButton::Left => PointerButton::LEFT,
@@ -365,15 +424,13 @@ impl WindowInner {
                 1
             }
         };
-        let pe = PointerEvent {
-            position: self.cursor_pos.get(),
-            modifiers: input_state.modifiers,
-            buttons: input_state.pointer_buttons,
-            button: Some(button),
-            repeat_count: repeat_count as u8,
-            transform: Default::default(),
-            request_capture: false,
-        };
+        let pe = PointerEvent::mouse(
+            self.cursor_pos.get(),
+            input_state.modifiers,
+            input_state.pointer_buttons,
+            Some(button),
+            repeat_count as u8,
+        );
 
         let event = if state.is_pressed() {
             Event::PointerDown(pe)
@@ -385,10 +442,28 @@ impl WindowInner {
     }
 
     fn convert_keyboard_input(&self, key_event: &KeyEvent) -> Event {
-        let input = &mut *self.input_state.borrow_mut();
         let (key, code) = key_event_to_key_code(&key_event);
+        let location = match key_event.location {
+            KeyLocation::Standard => keyboard_types::Location::Standard,
+            KeyLocation::Left => keyboard_types::Location::Left,
+            KeyLocation::Right => keyboard_types::Location::Right,
+            KeyLocation::Numpad => keyboard_types::Location::Numpad,
+        };
+        self.convert_key(key, code, location, key_event.state, key_event.repeat)
+    }
+
+    /// Creates a keyboard event, and updates the modifier state.
+    fn convert_key(
+        &self,
+        key: Key,
+        code: keyboard_types::Code,
+        location: keyboard_types::Location,
+        state: ElementState,
+        repeat: bool,
+    ) -> Event {
+        let input = &mut *self.input_state.borrow_mut();
         // update modifiers
-        match (&key, key_event.state) {
+        match (&key, state) {
             (Key::Shift, ElementState::Pressed) => input.modifiers.insert(keyboard_types::Modifiers::SHIFT),
             (Key::Shift, ElementState::Released) => input.modifiers.remove(keyboard_types::Modifiers::SHIFT),
             (Key::Control, ElementState::Pressed) => input.modifiers.insert(keyboard_types::Modifiers::CONTROL),
@@ -401,21 +476,16 @@ impl WindowInner {
         }
 
         let ke = KeyboardEvent {
-            state: match key_event.state {
+            state: match state {
                 ElementState::Pressed => keyboard_types::KeyState::Down,
                 ElementState::Released => keyboard_types::KeyState::Up,
             },
             key,
             code,
-            location: match key_event.location {
-                KeyLocation::Standard => keyboard_types::Location::Standard,
-                KeyLocation::Left => keyboard_types::Location::Left,
-                KeyLocation::Right => keyboard_types::Location::Right,
-                KeyLocation::Numpad => keyboard_types::Location::Numpad,
-            },
+            location,
             modifiers: input.modifiers,
-            repeat: key_event.repeat,
-            is_composing: false,
+            repeat,
+            is_composing: input.composing,
         };
 
         self.last_kb_event.replace(Some(ke.clone()));
@@ -427,6 +497,57 @@ impl WindowInner {
         }
     }
 
+    /// Converts a winit IME event to composition events, and updates the composition state.
+    fn convert_ime_event(&self, ime: &Ime) -> Vec<Event> {
+        let input = &mut *self.input_state.borrow_mut();
+        let mut events = vec![];
+        match ime {
+            Ime::Enabled => {}
+            Ime::Preedit(text, cursor) => {
+                if !input.composing && !text.is_empty() {
+                    input.composing = true;
+                    events.push(Event::CompositionStart);
+                }
+                if input.composing {
+                    // winit doesn't give us the clause boundaries: underline the whole text, plus
+                    // a thick underline for the range reported as the cursor, which is the clause being converted.
+                    let mut underlines = vec![];
+                    if !text.is_empty() {
+                        underlines.push(CompositionUnderline {
+                            range: 0..text.len(),
+                            thick: false,
+                        });
+                    }
+                    if let Some((start, end)) = *cursor {
+                        if start != end {
+                            underlines.push(CompositionUnderline {
+                                range: start..end,
+                                thick: true,
+                            });
+                        }
+                    }
+                    events.push(Event::CompositionUpdate(CompositionEvent {
+                        text: text.clone(),
+                        cursor: cursor.map(|(start, end)| start..end),
+                        underlines,
+                    }));
+                }
+            }
+            Ime::Commit(text) => {
+                input.composing = false;
+                events.push(Event::CompositionCommit(text.clone()));
+            }
+            Ime::Disabled => {
+                if input.composing {
+                    // cancel the composition in progress
+                    input.composing = false;
+                    events.push(Event::CompositionCommit(String::new()));
+                }
+            }
+        }
+        events
+    }
+
     fn redirect_event_to_popup(&self, _popup: &WindowInner, event: &WindowEvent) -> Option<WindowEvent> {
         // strategy: translate the event so that it appears to come from the popup window,
         // then directly invoke `dispatch_winit_input_event` on the popup window
@@ -455,7 +576,7 @@ impl WindowInner {
                     redirect = true;
                 }
             }*/
-            WindowEvent::KeyboardInput { .. } => {
+            WindowEvent::KeyboardInput { .. } | WindowEvent::Ime(_) => {
                 // no translation necessary
                 redirect = true;
             }
@@ -475,8 +596,37 @@ impl WindowInner {
         self.active_popup.replace(Some(Rc::downgrade(&window.shared)));
     }
 
+    /// Returns the modal dialog currently shown over this window, if any.
+    fn modal_dialog(&self) -> Option<Rc<WindowInner>> {
+        let dialog = self.modal_dialog.borrow().as_ref().and_then(Weak::upgrade);
+        if dialog.is_none() {
+            self.modal_dialog.replace(None);
+        }
+        dialog
+    }
+
     /// Converts & dispatches a winit window event.
     async fn dispatch_winit_input_event(&self, event: &WindowEvent) {
+        // Block input while a modal dialog is open. Clicking on the window brings the dialog to the front instead.
+        if let Some(dialog) = self.modal_dialog() {
+            match event {
+                WindowEvent::MouseInput { state, .. } => {
+                    if state.is_pressed() {
+                        dialog.window.focus_window();
+                    }
+                    return;
+                }
+                WindowEvent::CursorMoved { .. }
+                | WindowEvent::MouseWheel { .. }
+                | WindowEvent::Touch(_)
+                | WindowEvent::TouchpadMagnify { .. }
+                | WindowEvent::TouchpadRotate { .. }
+                | WindowEvent::KeyboardInput { .. }
+                | WindowEvent::Ime(_) => return,
+                _ => {}
+            }
+        }
+
         // First, redirect the input event to the popup window if there is one.
         let popup = self.active_popup.borrow().clone();
         if let Some(popup) = popup {
@@ -492,31 +642,19 @@ impl WindowInner {
 
         match event {
             WindowEvent::CursorMoved { position, .. } => {
-                let pos = Point::new(position.x, position.y);
-                //eprintln!("[{:?}] CursorMoved: {:?}", self.window.id(), pos);
-                self.cursor_pos.set(pos);
-                let modifiers = self.input_state.borrow().modifiers;
-                let buttons = self.input_state.borrow().pointer_buttons;
-                self.dispatch_pointer_event(
-                    Event::PointerMove(PointerEvent {
-                        position: pos,
-                        modifiers,
-                        buttons,
-                        button: None,
-                        repeat_count: 0,
-                        transform: Default::default(),
-                        request_capture: false,
-                    }),
-                    pos,
-                )
-                    .await;
-                // force a redraw for the debug crosshair
-                self.window.request_redraw();
+                //eprintln!("[{:?}] CursorMoved: {:?}", self.window.id(), position);
+                self.pointer_moved(Point::new(position.x, position.y)).await;
             }
             WindowEvent::Touch(touch) => {
-                self.cursor_pos.set(Point::new(touch.location.x, touch.location.y));
-                // force a redraw for the debug crosshair
-                self.window.request_redraw();
+                self.touch(touch).await;
+                self.request_debug_redraw();
+            }
+            WindowEvent::TouchpadMagnify { delta, .. } => {
+                self.gesture(self.cursor_pos.get(), Vec2::ZERO, 1.0 + *delta, 0.0).await;
+            }
+            WindowEvent::TouchpadRotate { delta, .. } => {
+                // winit rotations are counterclockwise
+                self.gesture(self.cursor_pos.get(), Vec2::ZERO, 1.0, -(*delta as f64).to_radians()).await;
             }
             WindowEvent::KeyboardInput {
                 event,
@@ -524,18 +662,30 @@ impl WindowInner {
             } => {
                 let converted_event = self.convert_keyboard_input(event);
                 self.dispatch_keyboard_event(converted_event).await;
-                // for the debugging overlay
-                self.window.request_redraw();
+                self.request_debug_redraw();
+            }
+            WindowEvent::Ime(ime) => {
+                for event in self.convert_ime_event(ime) {
+                    self.dispatch_keyboard_event(event).await;
+                }
+                self.request_debug_redraw();
             }
             WindowEvent::MouseInput {
                 button,
                 state,
                 device_id,
             } => {
-                if let Some(event) = self.convert_mouse_input(*device_id, *button, *state) {
+                if let Some(event) = self.convert_mouse_input(Some(*device_id), *button, *state) {
                     self.dispatch_pointer_event(event, self.cursor_pos.get()).await;
                 }
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match *delta {
+                    MouseScrollDelta::LineDelta(x, y) => Vec2::new(x as f64, y as f64) * WHEEL_LINE_HEIGHT,
+                    MouseScrollDelta::PixelDelta(pos) => Vec2::new(pos.x, pos.y),
+                };
+                self.wheel(delta).await;
+            }
             WindowEvent::CloseRequested => {
                 self.close_requested.emit(()).await;
             }
@@ -559,8 +709,260 @@ impl WindowInner {
         }
     }
 
+    /// Returns the scale factor from logical (element) coordinates to physical pixels.
+    ///
+    /// This is the system DPI scale factor multiplied by the UI scale factor.
+    fn effective_scale_factor(&self) -> f64 {
+        self.window.scale_factor() * self.ui_scale.get()
+    }
+
+    /// Converts a position in physical window pixels to logical coordinates.
+    fn physical_to_logical(&self, pos: Point) -> Point {
+        let scale = self.effective_scale_factor();
+        Point::new(pos.x / scale, pos.y / scale)
+    }
+
+    /// Sets the UI scale factor. Returns whether it changed.
+    fn set_ui_scale(&self, scale: f64) -> bool {
+        let scale = scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+        if scale == self.ui_scale.get() {
+            return false;
+        }
+        // keep the pointer over the same physical position
+        let physical_cursor = (self.cursor_pos.get().to_vec2() * self.effective_scale_factor()).to_point();
+        self.ui_scale.set(scale);
+        self.cursor_pos.set(self.physical_to_logical(physical_cursor));
+        // text and icons are re-rasterized at the new scale when the tree is painted again
+        self.root.mark_needs_relayout();
+        true
+    }
+
+    /// Handles a pointer move to the specified position in physical window pixels.
+    async fn pointer_moved(&self, physical_pos: Point) {
+        let pos = self.physical_to_logical(physical_pos);
+        self.cursor_pos.set(pos);
+        let modifiers = self.input_state.borrow().modifiers;
+        let buttons = self.input_state.borrow().pointer_buttons;
+        self.dispatch_pointer_event(
+            Event::PointerMove(PointerEvent::mouse(pos, modifiers, buttons, None, 0)),
+            pos,
+        )
+            .await;
+        self.request_debug_redraw();
+    }
+
+    /// Handles a touch event, from a touch screen or a pen.
+    ///
+    /// Each contact is sent as a pointer event with its own pointer ID. Touch contacts moving together
+    /// are also sent as gestures.
+    async fn touch(&self, touch: &Touch) {
+        let pos = self.physical_to_logical(Point::new(touch.location.x, touch.location.y));
+        self.cursor_pos.set(pos);
+
+        // must be queried now, while the pointer message is being processed
+        let pointer_type = backend::pointer_type(touch.id);
+        let pen = if pointer_type == PointerType::Pen {
+            backend::pen_info(touch.id)
+        } else {
+            None
+        };
+
+        // update the contacts; the borrow must end before dispatching
+        let (modifiers, index, contact, touches_before, touches_after) = {
+            let input_state = &mut *self.input_state.borrow_mut();
+            let modifiers = input_state.modifiers;
+            let contacts = &mut input_state.contacts;
+            let index = contacts.iter().position(|c| c.pointer_id == touch.id);
+            let touches = |contacts: &[Contact]| -> Vec<Contact> {
+                contacts.iter().filter(|c| c.pointer_type == PointerType::Touch).copied().collect()
+            };
+            let touches_before = touches(contacts);
+            let contact = match (touch.phase, index) {
+                (TouchPhase::Started, _) | (TouchPhase::Moved, None) => {
+                    let button = if pen.is_some_and(|pen| pen.eraser) {
+                        PointerButton::ERASER
+                    } else {
+                        PointerButton::LEFT
+                    };
+                    let contact = Contact {
+                        pointer_id: touch.id,
+                        pointer_type,
+                        position: pos,
+                        button,
+                    };
+                    if touch.phase == TouchPhase::Started {
+                        contacts.retain(|c| c.pointer_id != touch.id);
+                        contacts.push(contact);
+                    }
+                    contact
+                }
+                (TouchPhase::Moved, Some(index)) => {
+                    contacts[index].position = pos;
+                    contacts[index]
+                }
+                (TouchPhase::Ended | TouchPhase::Cancelled, Some(index)) => contacts.remove(index),
+                // lifted without having touched (e.g. hovering pen leaving)
+                (TouchPhase::Ended | TouchPhase::Cancelled, None) => return,
+            };
+            let touches_after = touches(contacts);
+            (modifiers, index, contact, touches_before, touches_after)
+        };
+
+        let in_contact = touch.phase == TouchPhase::Started || (touch.phase == TouchPhase::Moved && index.is_some());
+        let mut buttons = PointerButtons::new();
+        if in_contact {
+            buttons.set(contact.button);
+        }
+        if pen.is_some_and(|pen| pen.barrel) {
+            buttons.set(PointerButton::RIGHT);
+        }
+        let pressure = if in_contact {
+            pen.and_then(|pen| pen.pressure)
+                .or(touch.force.map(|force| force.normalized() as f32))
+                .unwrap_or(0.5)
+        } else {
+            0.0
+        };
+        let pe = PointerEvent {
+            position: pos,
+            modifiers,
+            buttons,
+            button: None,
+            repeat_count: 0,
+            transform: Default::default(),
+            request_capture: false,
+            pointer_id: touch.id,
+            pointer_type,
+            pressure,
+            tilt: pen.map_or(Vec2::ZERO, |pen| pen.tilt),
+            twist: pen.map_or(0.0, |pen| pen.twist),
+        };
+        let event = match touch.phase {
+            TouchPhase::Started => Event::PointerDown(PointerEvent {
+                button: Some(contact.button),
+                repeat_count: 1,
+                ..pe
+            }),
+            TouchPhase::Moved => Event::PointerMove(pe),
+            TouchPhase::Ended | TouchPhase::Cancelled => Event::PointerUp(PointerEvent {
+                button: Some(contact.button),
+                ..pe
+            }),
+        };
+        self.dispatch_pointer_event(event, pos).await;
+
+        // two or more fingers moving: pan, pinch and rotate
+        if touch.phase == TouchPhase::Moved && touches_after.len() >= 2 && touches_before.len() == touches_after.len() {
+            let (center_before, spread_before, angle_before) = contacts_geometry(&touches_before);
+            let (center, spread, angle) = contacts_geometry(&touches_after);
+            let scale = if spread_before > 0.0 { spread / spread_before } else { 1.0 };
+            // wrap to [-pi, pi]
+            let rotation = (angle - angle_before + PI).rem_euclid(TAU) - PI;
+            self.gesture(center, center - center_before, scale, rotation).await;
+        }
+    }
+
+    /// Sends a gesture event to the element capturing the pointer, or to the element under `center`.
+    async fn gesture(&self, center: Point, translation: Vec2, scale: f64, rotation: f64) {
+        let modifiers = self.input_state.borrow().modifiers;
+        let target = self
+            .pointer_capture
+            .upgrade()
+            .or_else(|| self.root.do_hit_test(center).last().map(|hit| hit.0.clone()));
+        let Some(target) = target else { return };
+        let mut pointer = PointerEvent::mouse(center, modifiers, PointerButtons::new(), None, 0);
+        pointer.pointer_type = PointerType::Touch;
+        let mut event = Event::Gesture(GestureEvent {
+            pointer,
+            translation,
+            scale,
+            rotation,
+        });
+        self.dispatch_event(&*target, &mut event, true).await;
+    }
+
+    /// Handles a scroll at the current pointer position. `delta` is in pixels.
+    ///
+    /// With ctrl held, this changes the UI scale factor instead (if enabled in the window options).
+    async fn wheel(&self, delta: Vec2) {
+        let modifiers = self.input_state.borrow().modifiers;
+        if self.ctrl_wheel_zoom && modifiers.contains(keyboard_types::Modifiers::CONTROL) {
+            let steps = delta.y / WHEEL_LINE_HEIGHT;
+            if self.set_ui_scale(self.ui_scale.get() * UI_SCALE_WHEEL_STEP.powf(steps)) {
+                self.ui_scale_changed.emit(self.ui_scale.get()).await;
+            }
+            return;
+        }
+        let pos = self.cursor_pos.get();
+        let buttons = self.input_state.borrow().pointer_buttons;
+        self.dispatch_pointer_event(
+            Event::Wheel(WheelEvent {
+                pointer: PointerEvent::mouse(pos, modifiers, buttons, None, 0),
+                delta,
+            }),
+            pos,
+        )
+            .await;
+    }
+
+    /// Dispatches a recorded input event.
+    ///
+    /// Like OS events, replayed events are blocked by modal dialogs, but they are not redirected
+    /// to popups.
+    async fn replay_input_event(&self, event: &InputEvent) {
+        if self.modal_dialog().is_some() {
+            return;
+        }
+        match *event {
+            InputEvent::PointerMove { x, y } => self.pointer_moved(Point::new(x, y)).await,
+            InputEvent::PointerButton { button, pressed } => {
+                let state = if pressed { ElementState::Pressed } else { ElementState::Released };
+                if let Some(event) = self.convert_mouse_input(None, button.to_winit(), state) {
+                    self.dispatch_pointer_event(event, self.cursor_pos.get()).await;
+                }
+            }
+            InputEvent::Wheel { dx, dy } => self.wheel(Vec2::new(dx, dy)).await,
+            InputEvent::Key {
+                ref key,
+                code,
+                location,
+                pressed,
+                repeat,
+            } => {
+                let state = if pressed { ElementState::Pressed } else { ElementState::Released };
+                let event = self.convert_key(key.clone(), code, location, state, repeat);
+                self.dispatch_keyboard_event(event).await;
+            }
+            InputEvent::ImePreedit { ref text, cursor } => {
+                for event in self.convert_ime_event(&Ime::Preedit(text.clone(), cursor)) {
+                    self.dispatch_keyboard_event(event).await;
+                }
+            }
+            InputEvent::ImeCommit(ref text) => {
+                for event in self.convert_ime_event(&Ime::Commit(text.clone())) {
+                    self.dispatch_keyboard_event(event).await;
+                }
+            }
+            InputEvent::ImeDisabled => {
+                for event in self.convert_ime_event(&Ime::Disabled) {
+                    self.dispatch_keyboard_event(event).await;
+                }
+            }
+        }
+    }
+
+    /// Forces a redraw to update the debugging overlays (crosshair and last key event).
+    ///
+    /// Not done for windows that aren't paced by the compositor, since they should only redraw
+    /// when their contents change.
+    fn request_debug_redraw(&self) {
+        if self.vsync.get() {
+            self.window.request_redraw();
+        }
+    }
+
     fn do_redraw(&self) {
-        let scale_factor = self.window.scale_factor();
+        let scale_factor = self.effective_scale_factor();
         let physical_size = self.window.inner_size();
         if physical_size.width == 0 || physical_size.height == 0 {
             return;
@@ -574,10 +976,17 @@ impl WindowInner {
             //self.layer.set_surface_size(physical_size);
         }
 
+        let mut timings = FrameTimings::default();
+        timings.phases[FramePhase::EventDispatch as usize] = self.event_dispatch_time.replace(Duration::ZERO);
+
+        let layout_start = Instant::now();
         if self.root.needs_relayout() {
             let _geom = self.root.do_layout(size);
         }
+        timings.phases[FramePhase::Layout as usize] = layout_start.elapsed();
+        timings.layout_count = crate::perf::take_layout_count();
 
+        let paint_start = Instant::now();
         let surface = self.layer.acquire_drawing_surface();
 
         // FIXME: only clear and flip invalid regions
@@ -585,10 +994,20 @@ impl WindowInner {
             let mut skia_surface = surface.surface();
             skia_surface.canvas().clear(self.background.get().to_skia());
 
-            self.root.do_paint(&surface, scale_factor);
+            timings.paint_count = self.root.do_paint(&surface, scale_factor);
+            timings.paint_cache_hits = crate::perf::take_paint_cache_hits();
+
+            // Captured before the debugging overlays, so that captures don't depend on the pointer position.
+            if self.capture_requested.take() {
+                let frame = read_surface(&mut skia_surface, Some(skia_safe::ColorSpace::new_srgb()));
+                if frame.is_none() {
+                    warn!("failed to read back the window surface");
+                }
+                self.captured_frame.replace(frame);
+            }
 
             // **** DEBUGGING ****
-            draw_crosshair(skia_surface.canvas(), self.cursor_pos.get());
+            draw_crosshair(skia_surface.canvas(), (self.cursor_pos.get().to_vec2() * scale_factor).to_point());
 
             if let Some(event) = &*self.last_kb_event.borrow() {
                 draw_text_blob(
@@ -597,11 +1016,20 @@ impl WindowInner {
                     size,
                 );
             }
+
+            if self.perf_hud_visible.get() {
+                skia_surface.canvas().save();
+                skia_surface.canvas().scale((scale_factor as f32, scale_factor as f32));
+                self.frame_stats.borrow().draw_hud(skia_surface.canvas(), size.width as f32);
+                skia_surface.canvas().restore();
+            }
         }
+        timings.phases[FramePhase::Paint as usize] = paint_start.elapsed();
 
         // Nothing more to paint, release the surface.
         //
         // This flushes the skia command buffers, and presents the surface to the compositor.
+        let composite_start = Instant::now();
         drop(surface);
 
         // Windows are initially created hidden, and are only shown after the first frame is painted.
@@ -613,17 +1041,39 @@ impl WindowInner {
 
         //self.clear_change_flags(ChangeFlags::PAINT);
 
-        // Wait for the compositor to be ready to render another frame (this is to reduce latency)
-        // FIXME: this assumes that there aren't any other windows waiting to be painted!
-        self.layer.wait_for_presentation();
+        let feedback = self.layer.presentation_feedback();
+        timings.missed_vsyncs = feedback.missed_vsyncs;
+        timings.present_latency = feedback.latency;
 
-        sleep(std::time::Duration::from_millis(5));
+        // Stop before waiting for the compositor: the wait is idle time, not composition work.
+        timings.phases[FramePhase::Composite as usize] = composite_start.elapsed();
+        self.frame_stats.borrow_mut().push(timings);
+
+        if self.vsync.get() {
+            // Wait for the compositor to be ready to render another frame (this is to reduce latency)
+            // FIXME: this assumes that there aren't any other windows waiting to be painted!
+            self.layer.wait_for_presentation();
+        }
+
+        if self.vsync.get() {
+            sleep(std::time::Duration::from_millis(5));
+        }
     }
 }
 
 impl WindowHandler for WindowInner {
     async fn event(&self, event: &WindowEvent) {
-        self.dispatch_winit_input_event(event).await;
+        if let Some(recorder) = self.recorder.borrow_mut().as_mut() {
+            recorder.record(event);
+        }
+        if matches!(event, WindowEvent::RedrawRequested) {
+            self.dispatch_winit_input_event(event).await;
+        } else {
+            let start = Instant::now();
+            self.dispatch_winit_input_event(event).await;
+            self.event_dispatch_time
+                .set(self.event_dispatch_time.get() + start.elapsed());
+        }
     }
 }
 
@@ -651,6 +1101,11 @@ impl Default for WeakWindow {
 }
 
 impl WeakWindow {
+    /// Returns the window, if it still exists.
+    pub fn upgrade(&self) -> Option<Window> {
+        self.shared.upgrade().map(|shared| Window { shared })
+    }
+
     pub fn request_repaint(&self) {
         if let Some(shared) = self.shared.upgrade() {
             shared.window.request_redraw();
@@ -669,6 +1124,27 @@ impl WeakWindow {
         }
     }
 
+    /// Enables or disables IME input for this window.
+    pub fn set_ime_allowed(&self, allowed: bool) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.window.set_ime_allowed(allowed);
+        }
+    }
+
+    /// Sets the area of the text being composed, in logical window coordinates.
+    ///
+    /// The IME uses it to place the candidate window.
+    pub fn set_ime_cursor_area(&self, rect: Rect) {
+        if let Some(shared) = self.shared.upgrade() {
+            // winit logical coordinates don't include the UI scale factor
+            let rect = rect.scale_from_origin(shared.ui_scale.get());
+            shared.window.set_ime_cursor_area(
+                winit::dpi::LogicalPosition::new(rect.x0, rect.y0),
+                winit::dpi::LogicalSize::new(rect.width(), rect.height()),
+            );
+        }
+    }
+
     /// Returns a reference to the currently focused element.
     pub fn is_focused(&self, element: &Element) -> bool {
         self.shared
@@ -687,6 +1163,17 @@ pub struct WindowOptions<'a> {
     pub background: Color,
     pub position: Option<Point>,
     pub no_focus: bool,
+    /// Whether redraws of the window are paced by the compositor clock.
+    ///
+    /// Set this to false for background tool windows: they then redraw only when their contents
+    /// change, and don't block the event loop waiting for the compositor.
+    pub vsync: bool,
+    /// Initial UI scale factor, applied on top of the system DPI scale factor (see `Window::set_ui_scale`).
+    pub ui_scale: f64,
+    /// Whether the user can change the UI scale factor with ctrl+mouse wheel.
+    pub ctrl_wheel_zoom: bool,
+    /// Color space of the window contents (see `Window::set_color_space`).
+    pub color_space: SurfaceColorSpace,
 }
 
 impl<'a> Default for WindowOptions<'a> {
@@ -700,6 +1187,10 @@ impl<'a> Default for WindowOptions<'a> {
             background: Color::from_hex("#151515"),
             position: None,
             no_focus: false,
+            vsync: true,
+            ui_scale: 1.0,
+            ctrl_wheel_zoom: true,
+            color_space: SurfaceColorSpace::ScRgb,
         }
     }
 }
@@ -730,6 +1221,9 @@ impl Window {
         let phy_size = window.inner_size();
         let phy_size = Size::new(phy_size.width as f64, phy_size.height as f64);
         let layer = Layer::new_surface(phy_size, ColorType::RGBAF16);
+        if !layer.set_color_space(options.color_space) {
+            warn!("{:?} output not supported, falling back to scRGB", options.color_space);
+        }
 
         let raw_window_handle = window
             .window_handle()
@@ -762,6 +1256,17 @@ impl Window {
             focus: Default::default(),
             background: Cell::new(options.background),
             active_popup: RefCell::new(None),
+            modal_dialog: RefCell::new(None),
+            event_dispatch_time: Cell::new(Duration::ZERO),
+            frame_stats: Default::default(),
+            perf_hud_visible: Cell::new(false),
+            vsync: Cell::new(options.vsync),
+            ui_scale: Cell::new(options.ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE)),
+            ctrl_wheel_zoom: options.ctrl_wheel_zoom,
+            ui_scale_changed: Handler::new(),
+            recorder: RefCell::new(None),
+            capture_requested: Cell::new(false),
+            captured_frame: RefCell::new(None),
             last_kb_event: RefCell::new(None),
         });
 
@@ -774,6 +1279,12 @@ impl Window {
         let weak = Rc::downgrade(&shared);
         root.set_parent_window(WeakWindow { shared: weak });
 
+        // Elements resolve theme colors when they paint: lay out and repaint every element with the
+        // new theme, bypassing the layout and paint caches. The task ends with the window.
+        let _ = theme::on_theme_changed(Rc::downgrade(&shared), |window| {
+            window.root.mark_subtree_needs_relayout();
+        });
+
         Window { shared }
     }
 
@@ -791,6 +1302,45 @@ impl Window {
         self.shared.set_popup(window);
     }
 
+    /// Shows `dialog` as a modal dialog over this window.
+    ///
+    /// Pointer and keyboard input to this window is blocked until the dialog window is dropped
+    /// or `clear_modal_dialog` is called.
+    pub fn set_modal_dialog(&self, dialog: &Window) {
+        self.shared.modal_dialog.replace(Some(Rc::downgrade(&dialog.shared)));
+    }
+
+    /// Unblocks input after a call to `set_modal_dialog`.
+    pub fn clear_modal_dialog(&self) {
+        self.shared.modal_dialog.replace(None);
+    }
+
+    /// Returns whether a modal dialog is open over this window.
+    pub fn has_modal_dialog(&self) -> bool {
+        self.shared.modal_dialog().is_some()
+    }
+
+    /// Returns the bounds of the client area of the window, in logical screen coordinates.
+    pub fn inner_bounds(&self) -> Rect {
+        let window = &self.shared.window;
+        let scale_factor = window.scale_factor();
+        let origin = window
+            .inner_position()
+            .unwrap_or_default()
+            .to_logical::<f64>(scale_factor);
+        let inner_size = window.inner_size().to_logical::<f64>(scale_factor);
+        Rect::from_origin_size((origin.x, origin.y), (inner_size.width, inner_size.height))
+    }
+
+    /// Returns the logical position that centers a window of the given size over this window.
+    pub fn centered_position(&self, size: Size) -> Point {
+        let bounds = self.inner_bounds();
+        Point::new(
+            bounds.x0 + (bounds.width() - size.width) / 2.0,
+            bounds.y0 + (bounds.height() - size.height) / 2.0,
+        )
+    }
+
     pub fn raw_window_handle(&self) -> RawWindowHandle {
         self.shared.window.window_handle().unwrap().as_raw()
     }
@@ -810,6 +1360,118 @@ impl Window {
         self.shared.focus_changed.wait().await
     }
 
+    /// Shows or hides the performance HUD, which displays frame timings over the window contents.
+    pub fn set_perf_hud_visible(&self, visible: bool) {
+        self.shared.perf_hud_visible.set(visible);
+        self.shared.window.request_redraw();
+    }
+
+    /// Enables or disables pacing of the redraws of this window by the compositor clock.
+    ///
+    /// See `WindowOptions::vsync`.
+    pub fn set_vsync(&self, vsync: bool) {
+        self.shared.vsync.set(vsync);
+    }
+
+    /// Calls the specified closure with the frame timing and presentation history of this window.
+    pub fn with_frame_stats<R>(&self, f: impl FnOnce(&FrameStats) -> R) -> R {
+        f(&self.shared.frame_stats.borrow())
+    }
+
+    /// Starts recording the input events received by this window.
+    ///
+    /// Any recording in progress is discarded.
+    pub fn start_recording(&self) {
+        self.shared.recorder.replace(Some(EventRecorder::new()));
+    }
+
+    /// Stops recording input events, and returns the recorded events.
+    ///
+    /// Returns an empty recording if `start_recording` wasn't called.
+    pub fn stop_recording(&self) -> EventRecording {
+        self.shared
+            .recorder
+            .take()
+            .map(EventRecorder::finish)
+            .unwrap_or_default()
+    }
+
+    /// Replays recorded input events, with the same timing as when they were recorded.
+    ///
+    /// Resolves when the last event has been dispatched.
+    pub async fn replay(&self, recording: &EventRecording) {
+        let start = Instant::now();
+        for event in recording.events.iter() {
+            application::wait_until(start + event.time).await;
+            self.shared.replay_input_event(&event.event).await;
+        }
+    }
+
+    /// Redraws the window, and returns the contents of the frame.
+    ///
+    /// The frame is read back from the GPU after the element tree is painted, before the debugging
+    /// overlays (the performance HUD is not included). Pixels are converted to sRGB from the color space
+    /// of the window; HDR values are clipped. The image is in physical pixels.
+    ///
+    /// Returns `None` if the window is minimized or the readback failed.
+    pub fn capture(&self) -> Option<RenderedImage> {
+        self.shared.capture_requested.set(true);
+        self.shared.do_redraw();
+        self.shared.capture_requested.set(false);
+        self.shared.captured_frame.take()
+    }
+
+    /// Returns the UI scale factor.
+    pub fn ui_scale(&self) -> f64 {
+        self.shared.ui_scale.get()
+    }
+
+    /// Sets the UI scale factor, applied on top of the system DPI scale factor.
+    ///
+    /// This scales the whole element tree: layout happens in the scaled coordinate space, and text
+    /// and icons are rasterized at the final resolution. The value is clamped to
+    /// `MIN_UI_SCALE..=MAX_UI_SCALE`.
+    pub fn set_ui_scale(&self, scale: f64) {
+        self.shared.set_ui_scale(scale);
+    }
+
+    /// Returns the color space of the window contents.
+    pub fn color_space(&self) -> SurfaceColorSpace {
+        self.shared.layer.color_space()
+    }
+
+    /// Changes the color space of the window contents.
+    ///
+    /// Use `SurfaceColorSpace::ScRgb` or `SurfaceColorSpace::Hdr10` to present HDR contents on HDR displays
+    /// without them being tone-mapped by the system. Returns false if the display doesn't support the
+    /// color space.
+    pub fn set_color_space(&self, color_space: SurfaceColorSpace) -> bool {
+        let supported = self.shared.layer.set_color_space(color_space);
+        if supported {
+            self.shared.window.request_redraw();
+        }
+        supported
+    }
+
+    /// Sets the HDR metadata of the window contents, for HDR10 output.
+    pub fn set_hdr_metadata(&self, metadata: &HdrMetadata) {
+        self.shared.layer.set_hdr_metadata(metadata);
+    }
+
+    /// Returns the luminance capabilities of the display showing the window.
+    ///
+    /// The display can change when the window is moved, so this should be queried again when needed.
+    pub fn display_info(&self) -> Option<DisplayInfo> {
+        self.shared.layer.display_info()
+    }
+
+    /// Waits for the user to change the UI scale factor with ctrl+mouse wheel.
+    ///
+    /// Returns the new scale factor. Not triggered by `set_ui_scale`.
+    pub async fn ui_scale_changed(&self) -> f64 {
+        self.shared.ui_scale_changed.wait().await
+    }
+
     /// Hides the window.
     pub fn hide(&self) {
         self.shared.window.set_visible(false);
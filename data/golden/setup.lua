@@ -0,0 +1,3 @@
+-- Scene of the golden image tests (see `fluff golden`).
+fluff.load("curves0001.geo")
+fluff.set_frame(0)